@@ -0,0 +1,78 @@
+//! Benchmarks for the quoting and ANSI stripping hot paths.
+//!
+//! Each group compares the library function against the previous behavior of
+//! compiling the regex on every call, so the cost of the per-call compile
+//! stays visible.
+//!
+//! Run with `cargo bench --bench hot_paths`.
+
+use command_stream::ansi::AnsiUtils;
+use command_stream::quote::{needs_quoting, quote};
+use criterion::{criterion_group, criterion_main, Criterion};
+use regex::Regex;
+use std::hint::black_box;
+
+const QUOTE_INPUTS: &[&str] = &[
+    "hello",
+    "/path/to/file.txt",
+    "hello world",
+    "it's",
+    "$HOME/*.rs",
+    "key=value",
+];
+
+const ANSI_TEXT: &str = "\x1b[1m\x1b[32mCompiling\x1b[0m command-stream v0.12.1 \
+(\x1b[36m/work/rust\x1b[0m)\n";
+
+fn quote_uncached(value: &str) -> String {
+    let safe_pattern = Regex::new(r"^[a-zA-Z0-9_\-./=,+@:]+$").unwrap();
+    if safe_pattern.is_match(value) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn strip_ansi_uncached(text: &str) -> String {
+    let re = Regex::new(r"\x1b\[[0-9;]*[mGKHFJ]").unwrap();
+    re.replace_all(text, "").to_string()
+}
+
+fn bench_quote(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quote");
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            for input in QUOTE_INPUTS {
+                black_box(quote(black_box(input)));
+            }
+        })
+    });
+    group.bench_function("compile_per_call", |b| {
+        b.iter(|| {
+            for input in QUOTE_INPUTS {
+                black_box(quote_uncached(black_box(input)));
+            }
+        })
+    });
+    group.bench_function("needs_quoting", |b| {
+        b.iter(|| {
+            for input in QUOTE_INPUTS {
+                black_box(needs_quoting(black_box(input)));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_strip_ansi(c: &mut Criterion) {
+    let mut group = c.benchmark_group("strip_ansi");
+    group.bench_function("cached", |b| {
+        b.iter(|| black_box(AnsiUtils::strip_ansi(black_box(ANSI_TEXT))))
+    });
+    group.bench_function("compile_per_call", |b| {
+        b.iter(|| black_box(strip_ansi_uncached(black_box(ANSI_TEXT))))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_quote, bench_strip_ansi);
+criterion_main!(benches);
@@ -0,0 +1,128 @@
+//! Benchmarks for the runner: how a command is dispatched and how fast its
+//! output moves.
+//!
+//! - `spawn` compares the three ways a command can run: as a virtual command,
+//!   spawned directly, and through `sh -c`.
+//! - `stream` and `pipeline` measure output throughput in bytes per second.
+//! - `dispatch` times the parsing that decides how a command runs; it needs
+//!   the `bench` feature, which exposes that step.
+//!
+//! Run with `cargo bench --bench runner --features bench`.
+
+use command_stream::{OutputChunk, Pipeline, ProcessRunner, RunOptions, StreamingRunner};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::hint::black_box;
+use tokio::runtime::Runtime;
+
+const STREAM_BYTES: u64 = 8 * 1024 * 1024;
+
+fn quiet() -> RunOptions {
+    RunOptions::builder().mirror(false).build()
+}
+
+fn run(rt: &Runtime, command: &str, options: RunOptions) {
+    let result = rt
+        .block_on(ProcessRunner::new(command, options).run())
+        .expect("benchmark command failed");
+    black_box(result);
+}
+
+fn bench_spawn(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("spawn");
+    group.bench_function("virtual", |b| b.iter(|| run(&rt, "echo hello", quiet())));
+    // `printf` is not a builtin, so it is spawned directly...
+    group.bench_function("direct_exec", |b| {
+        b.iter(|| run(&rt, "printf hello", quiet()))
+    });
+    // ...unless direct execution is turned off
+    group.bench_function("shell", |b| {
+        b.iter(|| {
+            run(
+                &rt,
+                "printf hello",
+                RunOptions::builder()
+                    .mirror(false)
+                    .direct_exec(false)
+                    .build(),
+            )
+        })
+    });
+    group.finish();
+}
+
+fn bench_stream(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let command = format!("head -c {} /dev/zero", STREAM_BYTES);
+    let mut group = c.benchmark_group("stream");
+    group.throughput(Throughput::Bytes(STREAM_BYTES));
+    group.sample_size(20);
+    group.bench_function("chunks", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let mut stream = StreamingRunner::new(&command).stream();
+                let mut received = 0;
+                while let Some(chunk) = stream.next().await {
+                    if let OutputChunk::Stdout(data) = chunk {
+                        received += data.len() as u64;
+                    }
+                }
+                assert_eq!(received, STREAM_BYTES);
+            })
+        })
+    });
+    group.bench_function("captured", |b| b.iter(|| run(&rt, &command, quiet())));
+    group.finish();
+}
+
+fn bench_pipeline(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("pipeline");
+    group.throughput(Throughput::Bytes(STREAM_BYTES));
+    group.sample_size(20);
+    group.bench_function("two_stages", |b| {
+        b.iter(|| {
+            let result = rt
+                .block_on(
+                    Pipeline::new()
+                        .add(format!("head -c {} /dev/zero", STREAM_BYTES))
+                        .add("cat")
+                        .mirror_output(false)
+                        .run(),
+                )
+                .unwrap();
+            black_box(result);
+        })
+    });
+    group.finish();
+}
+
+#[cfg(feature = "bench")]
+fn bench_dispatch(c: &mut Criterion) {
+    use command_stream::bench::virtual_command;
+    use command_stream::{literal_argv, parse_shell_command};
+
+    let mut group = c.benchmark_group("dispatch");
+    group.bench_function("virtual_command", |b| {
+        b.iter(|| black_box(virtual_command(black_box("cat 'my file.txt' -n"))))
+    });
+    group.bench_function("literal_argv", |b| {
+        b.iter(|| {
+            let parsed = parse_shell_command(black_box("git log --oneline -n 20"));
+            black_box(parsed.ok().as_ref().and_then(literal_argv))
+        })
+    });
+    group.finish();
+}
+
+#[cfg(not(feature = "bench"))]
+fn bench_dispatch(_: &mut Criterion) {}
+
+criterion_group!(
+    benches,
+    bench_spawn,
+    bench_stream,
+    bench_pipeline,
+    bench_dispatch
+);
+criterion_main!(benches);
@@ -0,0 +1,235 @@
+//! Aliases: names that stand for the start of a command
+//!
+//! An alias replaces its name wherever a command name goes: the first word
+//! of each simple command, so after `&&`, `;`, `|` and `(` too, and after
+//! `!`, `time` and the keywords that start commands, like `then`:
+//!
+//! ```rust,no_run
+//! use command_stream::aliases::alias;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! alias("ll", "ls -la");
+//! // Runs `ls -la src && ls -la tests`
+//! command_stream::run("ll src && ll tests").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The `alias` and `unalias` virtual commands define and remove them as
+//! well: `alias ll='ls -la'`. As in bash, an alias isn't expanded again
+//! inside its own expansion, so `alias ls='ls -F'` works, and when its
+//! value ends in a blank the word after it is checked for an alias too, as
+//! for `alias sudo='sudo '`. Quoted words are never aliases.
+//!
+//! These aliases apply to the commands a [`ProcessRunner`] starts, before
+//! anything else looks at them, except for raw shell scripts. A
+//! [`Session`](crate::Session) has aliases of its own, which take
+//! precedence over these, and its `alias` and `unalias` change those.
+//!
+//! [`ProcessRunner`]: crate::ProcessRunner
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+
+use crate::shell_parser::{is_assignment, tokenize, TokenType};
+use crate::CommandResult;
+
+/// Words after which the next word is a command name again
+const KEYWORDS: &[&str] = &[
+    "!", "time", "if", "then", "else", "elif", "while", "until", "do", "{",
+];
+
+static ALIASES: Lazy<RwLock<HashMap<String, String>>> = Lazy::new(Default::default);
+
+/// Make `name` at the start of a command stand for `value`
+pub fn alias(name: impl Into<String>, value: impl Into<String>) {
+    with_aliases(|aliases| aliases.insert(name.into(), value.into()));
+}
+
+/// Remove the alias `name`, returning whether there was one
+pub fn unalias(name: &str) -> bool {
+    with_aliases(|aliases| aliases.remove(name).is_some())
+}
+
+/// What the alias `name` stands for
+pub fn alias_of(name: &str) -> Option<String> {
+    ALIASES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(name)
+        .cloned()
+}
+
+/// The aliases defined with [`alias`], by name
+pub fn aliases() -> BTreeMap<String, String> {
+    let aliases = ALIASES.read().unwrap_or_else(|e| e.into_inner());
+    aliases
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// Remove all the aliases defined with [`alias`]
+pub fn clear_aliases() {
+    with_aliases(HashMap::clear);
+}
+
+pub(crate) fn with_aliases<R>(f: impl FnOnce(&mut HashMap<String, String>) -> R) -> R {
+    f(&mut ALIASES.write().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// `command` with the aliases `lookup` knows expanded
+///
+/// ```
+/// use command_stream::aliases::expand_aliases;
+///
+/// let lookup = |name: &str| match name {
+///     "ll" => Some("ls -la".to_string()),
+///     "ls" => Some("ls -F".to_string()),
+///     _ => None,
+/// };
+/// assert_eq!(
+///     expand_aliases("ll src | grep ll; ! ls", lookup),
+///     "ls -F -la src | grep ll; ! ls -F"
+/// );
+/// ```
+pub fn expand_aliases(command: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    expand(command, &lookup, &mut Vec::new(), true).0
+}
+
+/// `command` with the aliases defined with [`alias`] expanded
+pub(crate) fn expand_global(command: &str) -> String {
+    let aliases = ALIASES.read().unwrap_or_else(|e| e.into_inner());
+    if aliases.is_empty() {
+        return command.to_string();
+    }
+    expand_aliases(command, |name| aliases.get(name).cloned())
+}
+
+/// `text` with the aliases in it expanded, other than the `active` ones
+/// whose expansion it is part of, starting with a command name if
+/// `command`; and whether the word after it is a command name
+fn expand(
+    text: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+    active: &mut Vec<String>,
+    mut command: bool,
+) -> (String, bool) {
+    let mut expanded = String::new();
+    let mut copied = 0;
+    // The next word is a file to redirect to, not a command
+    let mut target = false;
+    for token in tokenize(text) {
+        match &token.token_type {
+            TokenType::Eof => break,
+            TokenType::Word(_) if target => target = false,
+            TokenType::Word(word) if command => {
+                let value = lookup(word).filter(|_| !active.contains(word));
+                let Some(value) = value else {
+                    command = is_assignment(word) || KEYWORDS.contains(&word.as_str());
+                    continue;
+                };
+                expanded.push_str(&text[copied..token.span.start]);
+                active.push(word.clone());
+                let (value_expanded, after) = expand(&value, lookup, active, true);
+                active.pop();
+                expanded.push_str(&value_expanded);
+                copied = token.span.end;
+                command = after || value.ends_with([' ', '\t']);
+            }
+            TokenType::Word(_) | TokenType::RParen => command = false,
+            redirect if redirect.is_redirect() => target = !redirect.is_duplication(),
+            _ => command = true,
+        }
+    }
+    expanded.push_str(&text[copied..]);
+    (expanded, command)
+}
+
+/// Run the `alias` or `unalias` builtin, `name`, on `aliases`
+///
+/// `alias` alone lists the aliases, `alias name=value` defines one and
+/// `alias name` shows it; `unalias name` removes one and `unalias -a` all.
+pub(crate) fn builtin(
+    name: &str,
+    args: &[String],
+    aliases: &mut HashMap<String, String>,
+) -> CommandResult {
+    let mut output = String::new();
+    let mut errors = String::new();
+    match (name, args) {
+        ("unalias", []) => {
+            return CommandResult::error_with_code(
+                "unalias: usage: unalias [-a] name [name ...]\n",
+                2,
+            )
+        }
+        ("unalias", [all]) if all == "-a" => aliases.clear(),
+        ("unalias", names) => {
+            for name in names.iter().filter(|name| aliases.remove(*name).is_none()) {
+                errors.push_str(&format!("unalias: {}: not found\n", name));
+            }
+        }
+        _ if args.iter().all(|arg| arg == "-p") => {
+            for (name, value) in aliases.iter().collect::<BTreeMap<_, _>>() {
+                output.push_str(&definition(name, value));
+            }
+        }
+        (_, args) => {
+            for arg in args {
+                match arg.split_once('=') {
+                    Some((name, _)) if !is_alias_name(name) => {
+                        errors.push_str(&format!("alias: `{}': invalid alias name\n", name));
+                    }
+                    Some((name, value)) => {
+                        aliases.insert(name.to_string(), value.to_string());
+                    }
+                    None => match aliases.get(arg) {
+                        Some(value) => output.push_str(&definition(arg, value)),
+                        None => errors.push_str(&format!("alias: {}: not found\n", arg)),
+                    },
+                }
+            }
+        }
+    }
+    CommandResult {
+        code: i32::from(!errors.is_empty()),
+        stdout: output,
+        stderr: errors,
+        ..CommandResult::default()
+    }
+}
+
+/// The `alias` command defining `name` as `value`
+fn definition(name: &str, value: &str) -> String {
+    format!("alias {}='{}'\n", name, value.replace('\'', "'\\''"))
+}
+
+fn is_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains(|c: char| c.is_whitespace() || "'\"\\$`/=;&|()<>".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_follows_command_positions() {
+        let lookup = |name: &str| match name {
+            "sudo" => Some("sudo ".to_string()),
+            "ll" => Some("ls -l".to_string()),
+            "out" => Some("tee".to_string()),
+            "again" => Some("echo a; ll".to_string()),
+            _ => None,
+        };
+        let expand = |command| expand_aliases(command, lookup);
+        assert_eq!(expand("sudo ll /"), "sudo  ls -l /");
+        assert_eq!(expand("X=1 ll > ll 2>&1 ll"), "X=1 ls -l > ll 2>&1 ll");
+        assert_eq!(expand("if ll; then out; fi"), "if ls -l; then tee; fi");
+        assert_eq!(expand("again"), "echo a; ls -l");
+        assert_eq!(expand("\"ll\" ll"), "\"ll\" ll");
+    }
+}
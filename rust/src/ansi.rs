@@ -3,6 +3,13 @@
 //! This module handles stripping and processing of ANSI escape codes
 //! and control characters from text output.
 
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// ANSI escape sequences (colors, cursor movement, erase). Compiled once and
+/// shared, since stripping runs on every chunk of streamed output.
+static ANSI_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"\x1b\[[0-9;]*[mGKHFJ]").unwrap());
+
 /// ANSI control character utilities
 pub struct AnsiUtils;
 
@@ -21,8 +28,7 @@ impl AnsiUtils {
     /// assert_eq!(AnsiUtils::strip_ansi(text), "Red text");
     /// ```
     pub fn strip_ansi(text: &str) -> String {
-        let re = regex::Regex::new(r"\x1b\[[0-9;]*[mGKHFJ]").unwrap();
-        re.replace_all(text, "").to_string()
+        ANSI_PATTERN.replace_all(text, "").to_string()
     }
 
     /// Strip control characters from text, preserving newlines, carriage returns, and tabs
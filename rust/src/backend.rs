@@ -0,0 +1,269 @@
+//! Where commands run: here, over SSH, in a Docker container or in WSL
+//!
+//! A [`Backend`] turns a command into the local command that runs it
+//! there, so output streams, exit codes and timeouts work as they do for
+//! any command:
+//!
+//! ```
+//! use command_stream::backend::Backend;
+//!
+//! let backend: Backend = "ssh://deploy@web1:2222".parse().unwrap();
+//! assert_eq!(
+//!     backend.command("cd /srv && ls"),
+//!     "ssh -p 2222 deploy@web1 -- 'cd /srv && ls'"
+//! );
+//! let backend: Backend = "docker://api".parse().unwrap();
+//! assert_eq!(backend.command("ls"), "docker exec -i api sh -c ls");
+//! ```
+//!
+//! The command is run by the shell at the other end, whole, so virtual
+//! commands and aliases don't apply to it. SSH takes its credentials and
+//! settings from the usual places, `~/.ssh/config` and the agent, or from
+//! a key given with [`Backend::identity`]; Docker from `DOCKER_HOST` and
+//! its configuration.
+//!
+//! To send every command to a backend, rewrite them with a
+//! [hook](crate::hooks), as the `command-stream` CLI does for `--backend`:
+//!
+//! ```rust,no_run
+//! use command_stream::backend::Backend;
+//! use command_stream::hooks::before_exec;
+//!
+//! let backend: Backend = "wsl://Ubuntu".parse().unwrap();
+//! before_exec(move |mut spec| {
+//!     spec.command = backend.command(&spec.command);
+//!     async move { Ok(spec) }
+//! });
+//! ```
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::quote::quote_args;
+use crate::Error;
+
+/// Where commands run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Here, `local`
+    #[default]
+    Local,
+    /// On another machine, over SSH: `ssh://[user@]host[:port]`
+    Ssh {
+        user: Option<String>,
+        host: String,
+        port: Option<u16>,
+        /// Private key to log in with, instead of SSH's own choice
+        identity: Option<PathBuf>,
+    },
+    /// In a running Docker container: `docker://[user@]container`
+    Docker {
+        user: Option<String>,
+        container: String,
+    },
+    /// In the Windows Subsystem for Linux: `wsl`, for the default
+    /// distribution, or `wsl://[user@]distribution`
+    Wsl {
+        user: Option<String>,
+        distribution: Option<String>,
+    },
+}
+
+impl Backend {
+    /// Log in over SSH with the private key at `path`; other backends
+    /// ignore it
+    pub fn identity(mut self, path: impl Into<PathBuf>) -> Self {
+        if let Backend::Ssh { identity, .. } = &mut self {
+            *identity = Some(path.into());
+        }
+        self
+    }
+
+    /// The argv that runs `command` on this backend, or `None` when it
+    /// runs here as it is
+    pub fn argv(&self, command: &str) -> Option<Vec<String>> {
+        let mut argv: Vec<String> = Vec::new();
+        match self {
+            Backend::Local => return None,
+            Backend::Ssh {
+                user,
+                host,
+                port,
+                identity,
+            } => {
+                argv.push("ssh".into());
+                if let Some(port) = port {
+                    argv.extend(["-p".into(), port.to_string()]);
+                }
+                if let Some(identity) = identity {
+                    argv.extend(["-i".into(), identity.display().to_string()]);
+                }
+                argv.push(match user {
+                    Some(user) => format!("{}@{}", user, host),
+                    None => host.clone(),
+                });
+                // ssh hands its words to the remote shell as a command line,
+                // so the command goes as it is
+                argv.extend(["--".into(), command.to_string()]);
+                return Some(argv);
+            }
+            Backend::Docker { user, container } => {
+                argv.extend(["docker".into(), "exec".into(), "-i".into()]);
+                if let Some(user) = user {
+                    argv.extend(["-u".into(), user.clone()]);
+                }
+                argv.push(container.clone());
+            }
+            Backend::Wsl { user, distribution } => {
+                argv.push("wsl.exe".into());
+                if let Some(distribution) = distribution {
+                    argv.extend(["-d".into(), distribution.clone()]);
+                }
+                if let Some(user) = user {
+                    argv.extend(["-u".into(), user.clone()]);
+                }
+                argv.push("-e".into());
+            }
+        }
+        argv.extend(["sh".into(), "-c".into(), command.to_string()]);
+        Some(argv)
+    }
+
+    /// The command that runs `command` on this backend, quoted for a
+    /// POSIX shell
+    pub fn command(&self, command: &str) -> String {
+        match self.argv(command) {
+            Some(argv) => quote_args(&argv),
+            None => command.to_string(),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self, Error> {
+        let invalid = |why: &str| Error::ParseError(format!("invalid backend `{}`: {}", spec, why));
+        let (scheme, target) = match spec.split_once("://") {
+            Some((scheme, target)) => (scheme, Some(target)),
+            None => (spec, None),
+        };
+        let (user, name) = match target.map(|target| target.rsplit_once('@')) {
+            Some(Some((user, name))) => (Some(user.to_string()), name),
+            Some(None) => (None, target.unwrap_or_default()),
+            None => (None, ""),
+        };
+        match (scheme, target) {
+            ("local", None) => Ok(Backend::Local),
+            ("wsl", None) => Ok(Backend::Wsl {
+                user: None,
+                distribution: None,
+            }),
+            ("wsl", Some(_)) => Ok(Backend::Wsl {
+                user,
+                distribution: Some(name).filter(|name| !name.is_empty()).map(Into::into),
+            }),
+            (_, Some(_)) if name.is_empty() => Err(invalid("no host or container")),
+            ("ssh", Some(_)) => {
+                let (host, port) = match name.rsplit_once(':') {
+                    Some((host, port)) => {
+                        let port = port.parse().map_err(|_| invalid("bad port"))?;
+                        (host, Some(port))
+                    }
+                    None => (name, None),
+                };
+                Ok(Backend::Ssh {
+                    user,
+                    host: host.to_string(),
+                    port,
+                    identity: None,
+                })
+            }
+            ("docker", Some(_)) => Ok(Backend::Docker {
+                user,
+                container: name.to_string(),
+            }),
+            _ => Err(invalid(
+                "expected local, ssh://host, docker://container or wsl",
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    /// The backend as it is parsed, without an SSH key
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let user = |user: &Option<String>| user.as_ref().map_or(String::new(), |u| u.clone() + "@");
+        match self {
+            Backend::Local => f.write_str("local"),
+            Backend::Ssh {
+                user: login,
+                host,
+                port,
+                ..
+            } => {
+                write!(f, "ssh://{}{}", user(login), host)?;
+                match port {
+                    Some(port) => write!(f, ":{}", port),
+                    None => Ok(()),
+                }
+            }
+            Backend::Docker {
+                user: login,
+                container,
+            } => write!(f, "docker://{}{}", user(login), container),
+            Backend::Wsl {
+                user: None,
+                distribution: None,
+            } => f.write_str("wsl"),
+            Backend::Wsl {
+                user: login,
+                distribution,
+            } => write!(
+                f,
+                "wsl://{}{}",
+                user(login),
+                distribution.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backends_parse_and_print() {
+        for spec in [
+            "local",
+            "ssh://web1",
+            "ssh://deploy@web1:2222",
+            "docker://root@api",
+            "wsl",
+            "wsl://Ubuntu",
+        ] {
+            let backend: Backend = spec.parse().unwrap();
+            assert_eq!(backend.to_string(), spec);
+        }
+        for spec in ["ssh://", "ssh://web1:port", "ftp://host", "docker"] {
+            assert!(spec.parse::<Backend>().is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn test_commands_run_through_the_backend() {
+        let ssh = "ssh://web1".parse::<Backend>().unwrap().identity("id key");
+        assert_eq!(
+            ssh.argv("echo 'hi'").unwrap(),
+            ["ssh", "-i", "id key", "web1", "--", "echo 'hi'"]
+        );
+        let wsl: Backend = "wsl://me@Ubuntu".parse().unwrap();
+        assert_eq!(
+            wsl.argv("uname").unwrap(),
+            ["wsl.exe", "-d", "Ubuntu", "-u", "me", "-e", "sh", "-c", "uname"]
+        );
+        assert_eq!(Backend::Local.command("ls -l"), "ls -l");
+    }
+}
@@ -0,0 +1,207 @@
+//! Caching the results of idempotent commands
+//!
+//! [`run_cached`] runs a command once and returns the stored result on later
+//! calls while the [`CachePolicy`] says it is still fresh. It suits commands
+//! that are slow but whose output rarely changes, such as version probes or
+//! dependency listings:
+//!
+//! ```rust,no_run
+//! use command_stream::{run_cached, CachePolicy};
+//! use std::time::Duration;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let policy = CachePolicy::ttl(Duration::from_secs(60)).input("Cargo.lock");
+//! let tree = run_cached("cargo tree --depth 1", policy.clone()).await?;
+//! // Served from the cache until a minute has passed or Cargo.lock changes
+//! let again = run_cached("cargo tree --depth 1", policy).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Results are keyed by the command string, working directory, environment
+//! overrides, stdin content and shell, plus the size and modification time
+//! of each declared input file. The cache lives in memory for the lifetime
+//! of the process. Failed results are not cached unless
+//! [`CachePolicy::cache_failures`] is set.
+
+use once_cell::sync::Lazy;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{CommandResult, ProcessRunner, Result, RunOptions, StdinOption};
+
+static CACHE: Lazy<Mutex<HashMap<u64, CachedResult>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone)]
+struct CachedResult {
+    command: String,
+    result: CommandResult,
+    stored_at: Instant,
+}
+
+/// How long a cached result stays valid and what else it depends on
+#[derive(Debug, Clone, Default)]
+pub struct CachePolicy {
+    ttl: Option<Duration>,
+    inputs: Vec<PathBuf>,
+    cache_failures: bool,
+}
+
+impl CachePolicy {
+    /// Keep results for `ttl`
+    pub fn ttl(ttl: Duration) -> Self {
+        CachePolicy {
+            ttl: Some(ttl),
+            ..Default::default()
+        }
+    }
+
+    /// Keep results until the process exits or the cache is cleared
+    pub fn forever() -> Self {
+        Self::default()
+    }
+
+    /// Run the command again when the file at `path` changes
+    ///
+    /// A relative path is resolved against the command's working directory.
+    pub fn input(mut self, path: impl Into<PathBuf>) -> Self {
+        self.inputs.push(path.into());
+        self
+    }
+
+    /// Run the command again when any of `paths` changes
+    pub fn inputs<I>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<PathBuf>,
+    {
+        self.inputs.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    /// Also cache results with a non-zero exit code
+    pub fn cache_failures(mut self, enabled: bool) -> Self {
+        self.cache_failures = enabled;
+        self
+    }
+}
+
+/// Run `command` unless a fresh result is cached; see the
+/// [module docs](self)
+///
+/// Uses [`RunOptions::from_env`], like [`run`](crate::run).
+pub async fn run_cached(command: impl Into<String>, policy: CachePolicy) -> Result<CommandResult> {
+    run_cached_with(command, RunOptions::from_env(), policy).await
+}
+
+/// [`run_cached`] with custom options
+///
+/// A cached result is mirrored again when `options.mirror` is set, so the
+/// output looks the same whether or not the command ran.
+pub async fn run_cached_with(
+    command: impl Into<String>,
+    options: RunOptions,
+    policy: CachePolicy,
+) -> Result<CommandResult> {
+    let command = command.into();
+    let key = cache_key(&command, &options, &policy);
+
+    let cached = CACHE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&key)
+        .filter(|entry| policy.ttl.is_none_or(|ttl| entry.stored_at.elapsed() < ttl))
+        .map(|entry| entry.result.clone());
+    if let Some(result) = cached {
+        crate::trace::trace_lazy("Cache", || format!("Cache hit: {}", command));
+        if options.mirror {
+            let _ = std::io::stdout().write_all(result.stdout.as_bytes());
+            let _ = std::io::stderr().write_all(result.stderr.as_bytes());
+        }
+        return Ok(result);
+    }
+
+    let result = ProcessRunner::new(command.clone(), options).run().await?;
+    if result.is_success() || policy.cache_failures {
+        CACHE.lock().unwrap_or_else(|e| e.into_inner()).insert(
+            key,
+            CachedResult {
+                command,
+                result: result.clone(),
+                stored_at: Instant::now(),
+            },
+        );
+    }
+    Ok(result)
+}
+
+/// Drop every cached result
+pub fn clear_cache() {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// Drop the cached results of `command`, whatever options it ran with
+pub fn invalidate(command: &str) {
+    CACHE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .retain(|_, entry| entry.command != command);
+}
+
+/// Hash of everything the result depends on
+fn cache_key(command: &str, options: &RunOptions, policy: &CachePolicy) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    command.hash(&mut hasher);
+    options.cwd.hash(&mut hasher);
+    if let Some(env) = &options.env {
+        let mut env: Vec<_> = env.iter().collect();
+        env.sort();
+        env.hash(&mut hasher);
+    }
+    match &options.stdin {
+        StdinOption::Content(content) => content.hash(&mut hasher),
+        StdinOption::File(path) => input_state(options, path).hash(&mut hasher),
+        other => std::mem::discriminant(other).hash(&mut hasher),
+    }
+    std::mem::discriminant(&options.shell).hash(&mut hasher);
+    options.raw_shell.hash(&mut hasher);
+    for path in &policy.inputs {
+        path.hash(&mut hasher);
+        input_state(options, path).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Size and modification time of an input file, `None` if it is missing
+fn input_state(options: &RunOptions, path: &PathBuf) -> Option<(u64, Option<SystemTime>)> {
+    let path = match &options.cwd {
+        Some(cwd) => cwd.join(path),
+        None => path.clone(),
+    };
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key() {
+        let options = RunOptions::default();
+        let policy = CachePolicy::forever();
+        let key = |command: &str, options: &RunOptions| cache_key(command, options, &policy);
+
+        assert_eq!(key("ls", &options), key("ls", &options));
+        assert_ne!(key("ls", &options), key("ls -a", &options));
+        let in_tmp = RunOptions::builder().cwd("/tmp").build();
+        assert_ne!(key("ls", &options), key("ls", &in_tmp));
+        let with_env = RunOptions::builder().env("A", "1").build();
+        assert_ne!(key("ls", &options), key("ls", &with_env));
+    }
+}
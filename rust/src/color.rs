@@ -0,0 +1,143 @@
+//! Whether commands color their output
+//!
+//! Most tools decide on colors themselves, by checking whether their output
+//! is a terminal; under this library it is a pipe, so they turn colors off
+//! even when the output is mirrored to one, or, told to force them, put
+//! escape sequences in captured text. A [`ColorPolicy`] in
+//! [`RunOptions::color`](crate::RunOptions::color) settles it by setting the
+//! conventional variables for the child: `NO_COLOR`, `FORCE_COLOR` and
+//! `CLICOLOR_FORCE`.
+//!
+//! ```rust,no_run
+//! use command_stream::{ColorPolicy, ProcessRunner, RunOptions};
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! // Colorful on a terminal, plain text in CI logs
+//! let options = RunOptions::builder().color(ColorPolicy::Auto).build();
+//! ProcessRunner::new("cargo build", options).run().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::IsTerminal;
+use tokio::process::Command;
+
+use crate::{RunOptions, StreamKind};
+
+/// When commands should color their output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorPolicy {
+    /// Color when output is mirrored to a terminal, unless this process's
+    /// own `NO_COLOR`, `FORCE_COLOR` or `CLICOLOR_FORCE` says otherwise
+    Auto,
+    /// Always color, for captured output meant for a terminal later
+    Always,
+    /// Never color, so captured output is plain text
+    Never,
+}
+
+impl ColorPolicy {
+    /// Whether a command whose output is (or isn't) `mirrored` should color
+    /// it
+    pub fn enabled(self, mirrored: bool) -> bool {
+        match self {
+            ColorPolicy::Always => true,
+            ColorPolicy::Never => false,
+            ColorPolicy::Auto => {
+                env_preference().unwrap_or_else(|| mirrored && is_tty(StreamKind::Stdout))
+            }
+        }
+    }
+
+    /// The variables to set (`Some`) or remove (`None`) for such a command
+    pub fn env(self, mirrored: bool) -> Vec<(&'static str, Option<&'static str>)> {
+        if self.enabled(mirrored) {
+            vec![
+                ("NO_COLOR", None),
+                ("FORCE_COLOR", Some("1")),
+                ("CLICOLOR_FORCE", Some("1")),
+            ]
+        } else {
+            vec![
+                ("NO_COLOR", Some("1")),
+                ("FORCE_COLOR", None),
+                ("CLICOLOR_FORCE", None),
+            ]
+        }
+    }
+}
+
+/// Whether this process's `stream` is a terminal
+pub fn is_tty(stream: StreamKind) -> bool {
+    match stream {
+        StreamKind::Stdout => std::io::stdout().is_terminal(),
+        StreamKind::Stderr => std::io::stderr().is_terminal(),
+    }
+}
+
+/// Whether this process's environment asks for colors (`Some(true)`), asks
+/// for none (`Some(false)`), or leaves it open
+///
+/// A non-empty `NO_COLOR` wins; otherwise `FORCE_COLOR` or `CLICOLOR_FORCE`
+/// set to anything but `0` forces colors, and `FORCE_COLOR=0` turns them
+/// off.
+pub fn env_preference() -> Option<bool> {
+    let var = |name| std::env::var(name).ok();
+    preference(
+        var("NO_COLOR").as_deref(),
+        var("FORCE_COLOR").as_deref(),
+        var("CLICOLOR_FORCE").as_deref(),
+    )
+}
+
+fn preference(
+    no_color: Option<&str>,
+    force_color: Option<&str>,
+    clicolor_force: Option<&str>,
+) -> Option<bool> {
+    if no_color.is_some_and(|value| !value.is_empty()) {
+        return Some(false);
+    }
+    match (force_color, clicolor_force) {
+        (Some(force), _) if force != "0" => Some(true),
+        (_, Some(force)) if force != "0" => Some(true),
+        (Some(_), _) => Some(false),
+        _ => None,
+    }
+}
+
+/// Set the color variables of `cmd` as the options' policy says
+pub(crate) fn apply(cmd: &mut Command, options: &RunOptions) {
+    let Some(policy) = options.color else {
+        return;
+    };
+    for (name, value) in policy.env(options.mirror) {
+        match value {
+            Some(value) => cmd.env(name, value),
+            None => cmd.env_remove(name),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preference() {
+        assert_eq!(preference(None, None, None), None);
+        assert_eq!(preference(Some("1"), Some("1"), None), Some(false));
+        assert_eq!(preference(Some(""), Some("3"), None), Some(true));
+        assert_eq!(preference(None, None, Some("1")), Some(true));
+        assert_eq!(preference(None, Some("0"), None), Some(false));
+    }
+
+    #[test]
+    fn test_fixed_policies() {
+        assert!(ColorPolicy::Always.enabled(false));
+        assert!(!ColorPolicy::Never.enabled(true));
+        assert!(ColorPolicy::Never
+            .env(true)
+            .contains(&("NO_COLOR", Some("1"))));
+    }
+}
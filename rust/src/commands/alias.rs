@@ -0,0 +1,21 @@
+//! Virtual `alias` and `unalias` command implementations
+
+use crate::aliases::{builtin, with_aliases};
+use crate::commands::CommandContext;
+use crate::utils::CommandResult;
+
+/// Execute the alias command
+///
+/// Defines, shows or lists the global [aliases](crate::aliases):
+/// `alias ll='ls -la'` defines one, `alias ll` shows it and `alias` alone
+/// lists them all as `alias` commands.
+pub async fn alias(ctx: CommandContext) -> CommandResult {
+    with_aliases(|aliases| builtin("alias", &ctx.args, aliases))
+}
+
+/// Execute the unalias command
+///
+/// Removes the named global aliases, or all of them with `-a`.
+pub async fn unalias(ctx: CommandContext) -> CommandResult {
+    with_aliases(|aliases| builtin("unalias", &ctx.args, aliases))
+}
@@ -0,0 +1,286 @@
+//! Shared argument parsing for virtual commands
+//!
+//! Builtins describe the options they accept with an [`ArgParser`] and get
+//! back a [`ParsedArgs`] holding the options that were given and the
+//! remaining operands. Parsing follows the usual utility conventions:
+//!
+//! - short flags may be combined (`-rf`), and a short option's value may be
+//!   attached (`-n5`) or the next argument (`-n 5`)
+//! - long options are `--name`, with values as `--name=value` or
+//!   `--name value`
+//! - options and operands may be mixed (`rm file -f`)
+//! - `--` ends option parsing; everything after it is an operand
+//! - `-` on its own, and negative numbers such as `-5`, are operands
+//!
+//! ```rust
+//! use command_stream::commands::ArgParser;
+//!
+//! let args: Vec<String> = ["-rf", "build", "--", "-odd-name"]
+//!     .iter()
+//!     .map(|s| s.to_string())
+//!     .collect();
+//! let parsed = ArgParser::new("rm")
+//!     .flag("rR", "recursive")
+//!     .flag("f", "force")
+//!     .parse(&args)
+//!     .unwrap();
+//!
+//! assert!(parsed.has("recursive") && parsed.has("force"));
+//! assert_eq!(parsed.operands, ["build", "-odd-name"]);
+//! ```
+
+use crate::utils::CommandResult;
+use std::collections::HashMap;
+
+/// One option a command accepts
+#[derive(Debug, Clone)]
+struct OptionSpec {
+    shorts: &'static str,
+    long: &'static str,
+    takes_value: bool,
+}
+
+/// Description of the options a virtual command accepts
+#[derive(Debug, Clone)]
+pub struct ArgParser {
+    command: &'static str,
+    options: Vec<OptionSpec>,
+}
+
+/// Options and operands parsed from a command's arguments
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedArgs {
+    /// Number of times each flag was given, by long name
+    counts: HashMap<&'static str, usize>,
+    /// Values given to options that take one, by long name, in order
+    values: HashMap<&'static str, Vec<String>>,
+    /// Arguments that are not options, in order
+    pub operands: Vec<String>,
+}
+
+impl ArgParser {
+    /// A parser for `command` that accepts no options yet
+    ///
+    /// The command name prefixes error messages.
+    pub fn new(command: &'static str) -> Self {
+        ArgParser {
+            command,
+            options: Vec::new(),
+        }
+    }
+
+    /// Accept a flag written as any of the characters in `shorts` or as
+    /// `--long`
+    ///
+    /// `long` also names the flag in [`ParsedArgs::has`]. Pass an empty
+    /// `shorts` for a long-only flag.
+    pub fn flag(mut self, shorts: &'static str, long: &'static str) -> Self {
+        self.options.push(OptionSpec {
+            shorts,
+            long,
+            takes_value: false,
+        });
+        self
+    }
+
+    /// Accept an option that takes a value, written as any of the characters
+    /// in `shorts` or as `--long`
+    pub fn option(mut self, shorts: &'static str, long: &'static str) -> Self {
+        self.options.push(OptionSpec {
+            shorts,
+            long,
+            takes_value: true,
+        });
+        self
+    }
+
+    /// Parse `args`
+    ///
+    /// Unknown options and missing option values are reported the way the
+    /// coreutils do, as a failed [`CommandResult`] the builtin can return
+    /// directly.
+    pub fn parse(&self, args: &[String]) -> Result<ParsedArgs, CommandResult> {
+        let mut parsed = ParsedArgs::default();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            if arg == "--" {
+                parsed.operands.extend(iter.by_ref().cloned());
+                break;
+            }
+
+            if let Some(long) = arg.strip_prefix("--") {
+                let (name, inline_value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let spec = self
+                    .options
+                    .iter()
+                    .find(|spec| spec.long == name)
+                    .ok_or_else(|| self.error(format!("unrecognized option '--{}'", name)))?;
+                match (spec.takes_value, inline_value) {
+                    (true, Some(value)) => parsed.push_value(spec.long, value),
+                    (true, None) => {
+                        let value = iter.next().ok_or_else(|| {
+                            self.error(format!("option '--{}' requires an argument", name))
+                        })?;
+                        parsed.push_value(spec.long, value.clone());
+                    }
+                    (false, Some(_)) => {
+                        return Err(
+                            self.error(format!("option '--{}' doesn't allow an argument", name))
+                        )
+                    }
+                    (false, None) => parsed.increment(spec.long),
+                }
+                continue;
+            }
+
+            let shorts = match arg.strip_prefix('-') {
+                Some(shorts) if !shorts.is_empty() && !self.is_negative_number(shorts) => shorts,
+                _ => {
+                    parsed.operands.push(arg.clone());
+                    continue;
+                }
+            };
+
+            for (index, flag) in shorts.char_indices() {
+                let spec = self
+                    .options
+                    .iter()
+                    .find(|spec| spec.shorts.contains(flag))
+                    .ok_or_else(|| self.error(format!("invalid option -- '{}'", flag)))?;
+                if !spec.takes_value {
+                    parsed.increment(spec.long);
+                    continue;
+                }
+                let attached = &shorts[index + flag.len_utf8()..];
+                let value = if attached.is_empty() {
+                    iter.next().cloned().ok_or_else(|| {
+                        self.error(format!("option requires an argument -- '{}'", flag))
+                    })?
+                } else {
+                    attached.to_string()
+                };
+                parsed.push_value(spec.long, value);
+                break;
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// Whether `-<rest>` is a negative number rather than digit flags
+    fn is_negative_number(&self, rest: &str) -> bool {
+        rest.starts_with(|c: char| c.is_ascii_digit())
+            && !self
+                .options
+                .iter()
+                .any(|spec| spec.shorts.contains(|c: char| c.is_ascii_digit()))
+            && rest.parse::<f64>().is_ok()
+    }
+
+    fn error(&self, message: String) -> CommandResult {
+        CommandResult::error(format!("{}: {}\n", self.command, message))
+    }
+}
+
+impl ParsedArgs {
+    /// Whether the flag or option named `long` was given
+    pub fn has(&self, long: &str) -> bool {
+        self.counts.contains_key(long) || self.values.contains_key(long)
+    }
+
+    /// How many times the flag named `long` was given, e.g. 2 for `-vv`
+    pub fn count(&self, long: &str) -> usize {
+        self.counts.get(long).copied().unwrap_or(0)
+    }
+
+    /// The last value given to the option named `long`
+    pub fn value(&self, long: &str) -> Option<&str> {
+        self.values
+            .get(long)
+            .and_then(|values| values.last())
+            .map(String::as_str)
+    }
+
+    /// Every value given to the option named `long`, in order
+    pub fn values(&self, long: &str) -> &[String] {
+        self.values.get(long).map_or(&[], Vec::as_slice)
+    }
+
+    fn increment(&mut self, long: &'static str) {
+        *self.counts.entry(long).or_insert(0) += 1;
+    }
+
+    fn push_value(&mut self, long: &'static str, value: String) {
+        self.values.entry(long).or_default().push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn parser() -> ArgParser {
+        ArgParser::new("demo")
+            .flag("rR", "recursive")
+            .flag("f", "force")
+            .option("n", "lines")
+    }
+
+    #[test]
+    fn test_combined_and_long_flags() {
+        let parsed = parser()
+            .parse(&args(&["-Rf", "a", "--force", "b"]))
+            .unwrap();
+        assert!(parsed.has("recursive") && parsed.has("force"));
+        assert_eq!(parsed.operands, ["a", "b"]);
+    }
+
+    #[test]
+    fn test_option_values() {
+        for list in [
+            &["-n5"][..],
+            &["-n", "5"],
+            &["--lines=5"],
+            &["--lines", "5"],
+            &["-fn5"],
+        ] {
+            let parsed = parser().parse(&args(list)).unwrap();
+            assert_eq!(parsed.value("lines"), Some("5"), "{:?}", list);
+        }
+        let parsed = parser().parse(&args(&["-n1", "-n2"])).unwrap();
+        assert_eq!(parsed.values("lines"), ["1", "2"]);
+    }
+
+    #[test]
+    fn test_double_dash_and_operand_forms() {
+        let parsed = parser()
+            .parse(&args(&["-", "-3", "--", "-f", "--lines"]))
+            .unwrap();
+        assert!(!parsed.has("force"));
+        assert_eq!(parsed.operands, ["-", "-3", "-f", "--lines"]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = parser().parse(&args(&["-x"])).unwrap_err();
+        assert_eq!(err.stderr, "demo: invalid option -- 'x'\n");
+        assert_eq!(err.code, 1);
+
+        let err = parser().parse(&args(&["--nope"])).unwrap_err();
+        assert_eq!(err.stderr, "demo: unrecognized option '--nope'\n");
+
+        let err = parser().parse(&args(&["-n"])).unwrap_err();
+        assert_eq!(err.stderr, "demo: option requires an argument -- 'n'\n");
+
+        let err = parser().parse(&args(&["--force=yes"])).unwrap_err();
+        assert!(err.stderr.contains("doesn't allow an argument"));
+    }
+}
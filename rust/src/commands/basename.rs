@@ -1,6 +1,6 @@
 //! Virtual `basename` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{ArgParser, CommandContext};
 use crate::utils::{CommandResult, VirtualUtils};
 use std::path::Path;
 
@@ -8,12 +8,16 @@ use std::path::Path;
 ///
 /// Strips directory and suffix from filenames.
 pub async fn basename(ctx: CommandContext) -> CommandResult {
-    if ctx.args.is_empty() {
+    let operands = match ArgParser::new("basename").parse(&ctx.args) {
+        Ok(parsed) => parsed.operands,
+        Err(result) => return result,
+    };
+    if operands.is_empty() {
         return VirtualUtils::missing_operand_error("basename");
     }
 
-    let path = &ctx.args[0];
-    let suffix = ctx.args.get(1);
+    let path = &operands[0];
+    let suffix = operands.get(1);
 
     let base = Path::new(path)
         .file_name()
@@ -1,14 +1,30 @@
 //! Virtual `cat` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{ArgParser, CommandContext, SpecialFile, OUTPUT_CLOSED};
 use crate::utils::{trace_lazy, CommandResult, VirtualUtils};
-use std::fs;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+/// Size of each read from a file
+const CHUNK_SIZE: usize = 64 * 1024;
 
 /// Execute the cat command
 ///
-/// Concatenates and displays file contents.
+/// Concatenates and displays file contents. Files that cannot be read are
+/// reported on stderr and skipped, and the exit code is then 1. `-` and
+/// `/dev/stdin` read stdin and `/dev/null` reads nothing, on every platform.
+///
+/// Files are read in chunks; when the context has an output channel each
+/// chunk is sent as it is read, otherwise the contents are collected into the
+/// result. Once nobody reads the channel, `cat` stops with [`OUTPUT_CLOSED`],
+/// so `cat /dev/zero | head -c 1` ends. Bytes that are not valid UTF-8 are
+/// replaced with U+FFFD.
 pub async fn cat(ctx: CommandContext) -> CommandResult {
-    if ctx.args.is_empty() {
+    let files = match ArgParser::new("cat").parse(&ctx.args) {
+        Ok(parsed) => parsed.operands,
+        Err(result) => return result,
+    };
+    if files.is_empty() {
         // Read from stdin if no files specified
         if let Some(ref stdin) = ctx.stdin {
             if !stdin.is_empty() {
@@ -19,9 +35,13 @@ pub async fn cat(ctx: CommandContext) -> CommandResult {
     }
 
     let cwd = ctx.get_cwd();
-    let mut outputs = Vec::new();
+    let mut stdin = ctx.stdin.clone();
+    let mut output = String::new();
+    let mut errors = String::new();
+    let mut had_error = false;
+    let mut bytes_read = 0;
 
-    for file in &ctx.args {
+    for file in &files {
         // Check for cancellation before processing each file
         if ctx.is_cancelled() {
             trace_lazy("VirtualCommand", || {
@@ -32,13 +52,43 @@ pub async fn cat(ctx: CommandContext) -> CommandResult {
 
         trace_lazy("VirtualCommand", || format!("cat: reading file {:?}", file));
 
+        match (file.as_str(), SpecialFile::parse(file)) {
+            ("-", _) | (_, Some(SpecialFile::Stdin)) => {
+                // Stdin is read once; later reads find it at its end
+                if let Some(text) = stdin.take().filter(|text| !text.is_empty()) {
+                    bytes_read += text.len();
+                    if !ctx.write_stdout(&mut output, text).await {
+                        return CommandResult::error_with_code(errors, OUTPUT_CLOSED);
+                    }
+                }
+                continue;
+            }
+            (_, Some(SpecialFile::Null)) => continue,
+            _ => {}
+        }
+
         let resolved_path = VirtualUtils::resolve_path(file, Some(&cwd));
 
-        match fs::read_to_string(&resolved_path) {
-            Ok(content) => {
-                outputs.push(content);
+        match read_file(&ctx, &resolved_path, &mut output).await {
+            Ok(Read::Done(count)) => bytes_read += count,
+            Ok(Read::Cancelled) => {
+                trace_lazy("VirtualCommand", || {
+                    format!("cat: cancelled while reading {:?}", file)
+                });
+                return CommandResult::error_with_code(output, 130);
+            }
+            Ok(Read::Closed) => {
+                trace_lazy("VirtualCommand", || {
+                    format!("cat: output closed while reading {:?}", file)
+                });
+                return CommandResult {
+                    stderr: errors,
+                    code: OUTPUT_CLOSED,
+                    ..Default::default()
+                };
             }
             Err(e) => {
+                // Like cat(1), report the file and carry on with the rest.
                 let error_msg = if e.kind() == std::io::ErrorKind::NotFound {
                     format!("cat: {}: No such file or directory\n", file)
                 } else if e.kind() == std::io::ErrorKind::IsADirectory
@@ -49,22 +99,110 @@ pub async fn cat(ctx: CommandContext) -> CommandResult {
                 } else {
                     format!("cat: {}: {}\n", file, e)
                 };
-                return CommandResult::error(error_msg);
+                ctx.write_stderr(&mut errors, error_msg).await;
+                had_error = true;
             }
         }
     }
 
-    let output = outputs.join("");
     trace_lazy("VirtualCommand", || {
-        format!("cat: success, bytes read: {}", output.len())
+        format!("cat: done, bytes read: {}", bytes_read)
     });
 
-    CommandResult::success(output)
+    CommandResult {
+        stdout: output,
+        stderr: errors,
+        code: if had_error { 1 } else { 0 },
+        ..Default::default()
+    }
+}
+
+/// How reading a file ended
+enum Read {
+    /// At its end, after this many bytes
+    Done(usize),
+    Cancelled,
+    /// Nobody reads the output any more
+    Closed,
+}
+
+/// Read `path` chunk by chunk, streaming each chunk or appending it to
+/// `output`
+async fn read_file(
+    ctx: &CommandContext,
+    path: &Path,
+    output: &mut String,
+) -> std::io::Result<Read> {
+    let mut file = tokio::fs::File::open(path).await?;
+    if file.metadata().await?.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::IsADirectory,
+            "Is a directory",
+        ));
+    }
+
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut pending = Vec::new();
+    let mut total = 0;
+    loop {
+        if ctx.is_cancelled() {
+            return Ok(Read::Cancelled);
+        }
+        let count = file.read(&mut buf).await?;
+        let text = if count == 0 {
+            String::from_utf8_lossy(&std::mem::take(&mut pending)).into_owned()
+        } else {
+            total += count;
+            decode_utf8(&mut pending, &buf[..count])
+        };
+        if !text.is_empty() && !ctx.write_stdout(output, text).await {
+            return Ok(Read::Closed);
+        }
+        if count == 0 {
+            return Ok(Read::Done(total));
+        }
+    }
+}
+
+/// Decode `bytes` following the undecoded tail `pending` of earlier chunks
+///
+/// An incomplete character at the end is kept in `pending` for the next
+/// chunk; invalid sequences become U+FFFD.
+fn decode_utf8(pending: &mut Vec<u8>, bytes: &[u8]) -> String {
+    pending.extend_from_slice(bytes);
+    let mut text = String::with_capacity(pending.len());
+    let mut start = 0;
+    while start < pending.len() {
+        match std::str::from_utf8(&pending[start..]) {
+            Ok(valid) => {
+                text.push_str(valid);
+                start = pending.len();
+            }
+            Err(e) => {
+                let valid_end = start + e.valid_up_to();
+                // The prefix was just validated, so this cannot fail.
+                text.push_str(std::str::from_utf8(&pending[start..valid_end]).unwrap_or_default());
+                match e.error_len() {
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        start = valid_end + len;
+                    }
+                    None => {
+                        start = valid_end;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    pending.drain(..start);
+    text
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::commands::StreamChunk;
     use std::io::Write;
     use tempfile::NamedTempFile;
 
@@ -115,4 +253,65 @@ mod tests {
         assert!(result.is_success());
         assert_eq!(result.stdout, "file1file2");
     }
+
+    #[test]
+    fn test_decode_utf8_across_chunks() {
+        let mut pending = Vec::new();
+        let bytes = "héllo".as_bytes();
+        // Split inside the two-byte 'é'
+        assert_eq!(decode_utf8(&mut pending, &bytes[..2]), "h");
+        assert_eq!(pending.len(), 1);
+        assert_eq!(decode_utf8(&mut pending, &bytes[2..]), "éllo");
+        assert!(pending.is_empty());
+
+        assert_eq!(decode_utf8(&mut pending, b"a\xffb"), "a\u{FFFD}b");
+    }
+
+    #[tokio::test]
+    async fn test_cat_binary_file() {
+        let mut temp = NamedTempFile::new().unwrap();
+        temp.write_all(b"bin\x00\xfe\xffary").unwrap();
+
+        let ctx = CommandContext::new(vec![temp.path().to_string_lossy().to_string()]);
+        let result = cat(ctx).await;
+
+        assert!(result.is_success());
+        assert_eq!(result.stdout, "bin\0\u{FFFD}\u{FFFD}ary");
+    }
+
+    #[tokio::test]
+    async fn test_cat_streams_chunks() {
+        let mut temp = NamedTempFile::new().unwrap();
+        let content = "x".repeat(CHUNK_SIZE + 10);
+        write!(temp, "{}", content).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let mut ctx = CommandContext::new(vec![temp.path().to_string_lossy().to_string()]);
+        ctx.output_tx = Some(tx);
+        let result = cat(ctx).await;
+        assert!(result.is_success());
+        assert!(result.stdout.is_empty());
+
+        let mut chunks = Vec::new();
+        while let Some(StreamChunk::Stdout(chunk)) = rx.recv().await {
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks.concat(), content);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cat_stops_when_output_closes() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let mut ctx = CommandContext::new(vec!["/dev/zero".to_string()]);
+        ctx.output_tx = Some(tx);
+        let reader = tokio::spawn(async move {
+            rx.recv().await;
+        });
+
+        let result = cat(ctx).await;
+        reader.await.unwrap();
+        assert_eq!(result.code, OUTPUT_CLOSED);
+    }
 }
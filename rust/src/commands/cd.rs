@@ -3,7 +3,7 @@
 use crate::commands::CommandContext;
 use crate::utils::{trace, CommandResult};
 use std::env;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
 /// Execute the cd command
 ///
@@ -16,42 +16,16 @@ use std::path::PathBuf;
 ///
 /// Like a real shell, a successful `cd` updates the `PWD` and `OLDPWD`
 /// environment variables and changes the process directory so that subsequent
-/// commands (virtual or real) observe the new location.
+/// commands (virtual or real) observe the new location. A
+/// [`Session`](crate::Session), and a list run in-process such as
+/// `cd x && pwd`, handle `cd` themselves instead, so that only their own
+/// commands move.
 pub async fn cd(ctx: CommandContext) -> CommandResult {
-    let home = env::var("HOME")
-        .or_else(|_| env::var("USERPROFILE"))
-        .unwrap_or_else(|_| "/".to_string());
-
     let previous_dir = env::current_dir().ok();
-    let base = ctx.get_cwd();
-
-    let mut print_dir = false;
-    let target: String = match ctx.args.first().map(|s| s.as_str()) {
-        // `cd` with no argument goes to $HOME, just like sh.
-        None | Some("") => home.clone(),
-        // `cd -` switches to the previous directory and prints it (sh behavior).
-        Some("-") => match env::var("OLDPWD") {
-            Ok(oldpwd) if !oldpwd.is_empty() => {
-                print_dir = true;
-                oldpwd
-            }
-            _ => {
-                trace("VirtualCommand", "cd: OLDPWD not set");
-                return CommandResult::error("cd: OLDPWD not set\n");
-            }
-        },
-        Some("~") => home.clone(),
-        Some(t) if t.starts_with("~/") => PathBuf::from(&home).join(&t[2..]).display().to_string(),
-        Some(t) => t.to_string(),
-    };
-
-    // Resolve relative targets against the effective base directory so that the
-    // `cwd` option and chained `cd` commands behave consistently.
-    let target_path = PathBuf::from(&target);
-    let resolved = if target_path.is_absolute() {
-        target_path
-    } else {
-        base.join(&target_path)
+    let (resolved, print_dir) = match target(&ctx.args, &ctx.get_cwd(), |name| env::var(name).ok())
+    {
+        Ok(target) => target,
+        Err(result) => return result,
     };
 
     trace(
@@ -88,6 +62,79 @@ pub async fn cd(ctx: CommandContext) -> CommandResult {
     }
 }
 
+/// Where `cd args` goes from `base`, and whether it prints the directory, as
+/// `cd -` does; `var` looks up `HOME` and `OLDPWD`
+fn target(
+    args: &[String],
+    base: &Path,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<(PathBuf, bool), CommandResult> {
+    let home = var("HOME")
+        .or_else(|| var("USERPROFILE"))
+        .unwrap_or_else(|| "/".to_string());
+
+    let mut print_dir = false;
+    let target: String = match args.first().map(|s| s.as_str()) {
+        // `cd` with no argument goes to $HOME, just like sh.
+        None | Some("") => home.clone(),
+        // `cd -` switches to the previous directory and prints it (sh behavior).
+        Some("-") => match var("OLDPWD") {
+            Some(oldpwd) if !oldpwd.is_empty() => {
+                print_dir = true;
+                oldpwd
+            }
+            _ => {
+                trace("VirtualCommand", "cd: OLDPWD not set");
+                return Err(CommandResult::error("cd: OLDPWD not set\n"));
+            }
+        },
+        Some("~") => home.clone(),
+        Some(t) if t.starts_with("~/") => PathBuf::from(&home).join(&t[2..]).display().to_string(),
+        Some(t) => t.to_string(),
+    };
+
+    // Resolve relative targets against the effective base directory so that the
+    // `cwd` option and chained `cd` commands behave consistently.
+    Ok((base.join(target), print_dir))
+}
+
+/// Run `cd args` from `base` without touching the process: the directory to
+/// go to, with `..` resolved by name as shells do, and what `cd` prints; or
+/// the failed `cd`'s result
+pub(crate) fn change_dir(
+    args: &[String],
+    base: &Path,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<(PathBuf, String), CommandResult> {
+    let (target, print_dir) = target(args, base, var)?;
+    let mut dir = PathBuf::new();
+    for component in target.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                dir.pop();
+            }
+            component => dir.push(component),
+        }
+    }
+    match std::fs::metadata(&dir) {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => {
+            return Err(CommandResult::error(format!(
+                "cd: {}: Not a directory\n",
+                target.display()
+            )))
+        }
+        Err(e) => return Err(CommandResult::error(format!("cd: {}\n", e))),
+    }
+    let output = if print_dir {
+        format!("{}\n", dir.display())
+    } else {
+        String::new()
+    };
+    Ok((dir, output))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -281,4 +328,22 @@ mod tests {
 
         env::set_current_dir(original_dir).unwrap();
     }
+
+    #[test]
+    fn test_change_dir_leaves_the_process_alone() {
+        let temp = tempdir().unwrap();
+        std::fs::create_dir(temp.path().join("sub")).unwrap();
+        let original_dir = env::current_dir().unwrap();
+        let var = |name: &str| (name == "OLDPWD").then(|| "/".to_string());
+
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let (dir, output) = change_dir(&args(&["sub/../sub/."]), temp.path(), var).unwrap();
+        assert_eq!(dir, temp.path().join("sub"));
+        assert_eq!(output, "");
+        let (dir, output) = change_dir(&args(&["-"]), temp.path(), var).unwrap();
+        assert_eq!((dir, output), (PathBuf::from("/"), "/\n".to_string()));
+        let failed = change_dir(&args(&["missing"]), temp.path(), var).unwrap_err();
+        assert!(failed.stderr.starts_with("cd: "), "{}", failed.stderr);
+        assert_eq!(env::current_dir().unwrap(), original_dir);
+    }
 }
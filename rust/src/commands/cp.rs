@@ -1,6 +1,6 @@
 //! Virtual `cp` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{ArgParser, CommandContext};
 use crate::utils::{trace_lazy, CommandResult, VirtualUtils};
 use std::fs;
 use std::path::Path;
@@ -13,21 +13,17 @@ pub async fn cp(ctx: CommandContext) -> CommandResult {
         return VirtualUtils::invalid_argument_error("cp", "missing file operand");
     }
 
-    // Parse flags
-    let mut recursive = false;
-    let mut paths = Vec::new();
-
-    for arg in &ctx.args {
-        if arg == "-r" || arg == "-R" || arg == "--recursive" {
-            recursive = true;
-        } else if arg.starts_with('-') {
-            if arg.contains('r') || arg.contains('R') {
-                recursive = true;
-            }
-        } else {
-            paths.push(arg.clone());
-        }
-    }
+    let parsed = match ArgParser::new("cp")
+        .flag("rR", "recursive")
+        .flag("f", "force")
+        .flag("v", "verbose")
+        .parse(&ctx.args)
+    {
+        Ok(parsed) => parsed,
+        Err(result) => return result,
+    };
+    let recursive = parsed.has("recursive");
+    let mut paths = parsed.operands;
 
     if paths.len() < 2 {
         return VirtualUtils::invalid_argument_error("cp", "missing destination file operand");
@@ -1,6 +1,6 @@
 //! Virtual `dirname` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{ArgParser, CommandContext};
 use crate::utils::{CommandResult, VirtualUtils};
 use std::path::Path;
 
@@ -8,11 +8,15 @@ use std::path::Path;
 ///
 /// Strips the last component from filenames.
 pub async fn dirname(ctx: CommandContext) -> CommandResult {
-    if ctx.args.is_empty() {
+    let operands = match ArgParser::new("dirname").parse(&ctx.args) {
+        Ok(parsed) => parsed.operands,
+        Err(result) => return result,
+    };
+    if operands.is_empty() {
         return VirtualUtils::missing_operand_error("dirname");
     }
 
-    let path = &ctx.args[0];
+    let path = &operands[0];
     let parent = Path::new(path)
         .parent()
         .map(|p| p.to_string_lossy().to_string())
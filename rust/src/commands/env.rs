@@ -6,11 +6,21 @@ use std::env;
 
 /// Execute the env command
 ///
-/// Displays environment variables.
-pub async fn env(_ctx: CommandContext) -> CommandResult {
-    let mut output = String::new();
+/// Displays environment variables: this process's, with the command's own,
+/// such as `FOO=bar` in `FOO=bar env`, over them.
+pub async fn env(ctx: CommandContext) -> CommandResult {
+    let mut vars: Vec<(String, String)> = env::vars().collect();
+    let mut own: Vec<_> = ctx.env.into_iter().flatten().collect();
+    own.sort();
+    for (key, value) in own {
+        match vars.iter_mut().find(|(name, _)| *name == key) {
+            Some(var) => var.1 = value,
+            None => vars.push((key, value)),
+        }
+    }
 
-    for (key, value) in env::vars() {
+    let mut output = String::new();
+    for (key, value) in vars {
         output.push_str(&format!("{}={}\n", key, value));
     }
 
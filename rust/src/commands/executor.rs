@@ -0,0 +1,121 @@
+//! Running other commands from inside a virtual command
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+
+use crate::state::get_shell_settings;
+use crate::{CommandResult, ProcessRunner, Result, RunOptions, ShellSettings, StdinOption};
+
+/// Handle a virtual command runs other commands through, as `xargs` or
+/// `find -exec` would, instead of spawning them with `std::process`
+///
+/// Commands run like [`ProcessRunner::run`] runs them, virtual commands
+/// included, with the options of the command that invoked them: its
+/// directory and environment, policy, confirmation gate, shell and shell
+/// settings. They are cancelled along with it. Their output is captured
+/// rather than mirrored, so the virtual command decides what to write.
+///
+/// ```rust
+/// use command_stream::commands::CommandContext;
+/// use command_stream::CommandResult;
+///
+/// async fn twice(ctx: CommandContext) -> CommandResult {
+///     let command = ctx.args.join(" ");
+///     let mut stdout = String::new();
+///     for _ in 0..2 {
+///         match ctx.executor().run(&command).await {
+///             Ok(result) => stdout.push_str(&result.stdout),
+///             Err(e) => return CommandResult::error(format!("twice: {}\n", e)),
+///         }
+///     }
+///     CommandResult::success(stdout)
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandExecutor {
+    pub(super) options: RunOptions,
+    session_settings: Option<Arc<RwLock<ShellSettings>>>,
+}
+
+impl CommandExecutor {
+    /// An executor running commands with `options`, except that their
+    /// output is only captured and they read no stdin unless given some
+    pub fn new(options: RunOptions) -> Self {
+        CommandExecutor {
+            options: RunOptions {
+                mirror: false,
+                capture: true,
+                stdin: StdinOption::Null,
+                timeout: None,
+                heartbeat: None,
+                timed_lines: false,
+                output_filters: Vec::new(),
+                capture_tail: None,
+                ..options
+            },
+            session_settings: None,
+        }
+    }
+
+    /// Read and change `settings` instead of the global shell settings, as
+    /// the invoking command does
+    pub(crate) fn with_session_settings(
+        mut self,
+        settings: Option<Arc<RwLock<ShellSettings>>>,
+    ) -> Self {
+        self.session_settings = settings;
+        self
+    }
+
+    /// The options commands run with
+    pub fn options(&self) -> &RunOptions {
+        &self.options
+    }
+
+    /// A runner for `command`, for when the virtual command needs more
+    /// control than [`run`](Self::run) gives, such as streaming its output
+    pub async fn runner(&self, command: impl Into<String>) -> ProcessRunner {
+        let mut options = self.options.clone();
+        let settings = match (&options.shell_settings, &self.session_settings) {
+            (Some(settings), _) => settings.clone(),
+            (None, Some(session)) => session.read().await.clone(),
+            (None, None) => get_shell_settings().await,
+        };
+        // A failing command is reported to the virtual command, not raised
+        // past it
+        options.shell_settings = Some(ShellSettings {
+            errexit: false,
+            ..settings
+        });
+        let runner = ProcessRunner::new(command, options);
+        match &self.session_settings {
+            Some(settings) => runner.with_session_settings(settings.clone()),
+            None => runner,
+        }
+    }
+
+    /// Run `command` to completion
+    ///
+    /// A command that exits non-zero is a successful run with a failing
+    /// result, whatever `errexit` says.
+    pub fn run<'a>(
+        &'a self,
+        command: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<CommandResult>> + Send + 'a>> {
+        // Boxed, since the command may be a virtual command running this
+        Box::pin(async move { self.runner(command).await.run().await })
+    }
+
+    /// Run `command` to completion with `stdin` as its input
+    pub fn run_with_stdin<'a>(
+        &'a self,
+        command: &'a str,
+        stdin: impl Into<String>,
+    ) -> Pin<Box<dyn Future<Output = Result<CommandResult>> + Send + 'a>> {
+        let stdin = StdinOption::Content(stdin.into());
+        Box::pin(async move { self.runner(command).await.with_stdin(stdin).run().await })
+    }
+}
@@ -0,0 +1,216 @@
+//! Virtual `flock` command implementation
+
+use crate::commands::CommandContext;
+use crate::lock::FileLock;
+use crate::quote::quote_args;
+use crate::units::parse_duration;
+use crate::utils::{trace_lazy, CommandResult, VirtualUtils};
+use crate::{ProcessRunner, Result, RunOptions, StdinOption};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// Options given before the lock file
+struct FlockArgs {
+    nonblock: bool,
+    timeout: Option<Duration>,
+    conflict_code: i32,
+    file: String,
+    /// The command to run, as a shell string
+    command: String,
+}
+
+/// Execute the flock command
+///
+/// `flock [-n] [-w seconds] [-E code] file command [args...]` or
+/// `flock ... file -c 'command'` runs the command while holding an exclusive
+/// lock on `file`, like the util-linux tool. The lock is the same one
+/// [`with_lock`](crate::lock::with_lock) takes. When the lock can't be taken
+/// (`-n`, or `-w` expired) the exit code is 1, or the `-E` code.
+pub async fn flock(ctx: CommandContext) -> CommandResult {
+    let args = match parse_args(&ctx.args) {
+        Ok(args) => args,
+        Err(result) => return result,
+    };
+    let path = VirtualUtils::resolve_path(&args.file, ctx.cwd.as_deref());
+
+    let lock = if args.nonblock {
+        FileLock::try_acquire(&path)
+    } else {
+        let acquire = async {
+            match args.timeout {
+                Some(timeout) => FileLock::acquire_timeout(&path, timeout).await.map(Some),
+                None => FileLock::acquire(&path).await.map(Some),
+            }
+        };
+        tokio::select! {
+            lock = acquire => lock,
+            _ = ctx.cancelled() => return CommandResult::error_with_code("", 130),
+        }
+    };
+    let _lock = match lock {
+        Ok(Some(lock)) => lock,
+        Ok(None) => return CommandResult::error_with_code("", args.conflict_code),
+        Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+            return CommandResult::error_with_code("", args.conflict_code)
+        }
+        Err(e) => {
+            return CommandResult::error(format!(
+                "flock: cannot open lock file {}: {}\n",
+                args.file, e
+            ))
+        }
+    };
+
+    trace_lazy("VirtualCommand", || {
+        format!("flock: holding {:?}, running {}", path, args.command)
+    });
+    let options = RunOptions {
+        mirror: false,
+        capture: true,
+        cwd: ctx.cwd.clone(),
+        env: ctx.env.clone(),
+        stdin: match &ctx.stdin {
+            Some(content) => StdinOption::Content(content.clone()),
+            None => StdinOption::Null,
+        },
+        cancel: ctx.cancel_token.clone(),
+        ..Default::default()
+    };
+    match run_nested(args.command, options).await {
+        Ok(result) => result,
+        Err(e) => CommandResult::error(format!("flock: {}\n", e)),
+    }
+}
+
+/// Run `command` with a fresh runner
+///
+/// Boxed because the nested runner may run builtins, this one included.
+fn run_nested(
+    command: String,
+    options: RunOptions,
+) -> Pin<Box<dyn Future<Output = Result<CommandResult>> + Send>> {
+    Box::pin(async move { ProcessRunner::new(command, options).run().await })
+}
+
+fn parse_args(args: &[String]) -> std::result::Result<FlockArgs, CommandResult> {
+    let mut parsed = FlockArgs {
+        nonblock: false,
+        timeout: None,
+        conflict_code: 1,
+        file: String::new(),
+        command: String::new(),
+    };
+    let mut iter = args.iter();
+    let value = |iter: &mut std::slice::Iter<String>, name: &str| {
+        iter.next().cloned().ok_or_else(|| {
+            CommandResult::error(format!("flock: option '{}' requires an argument\n", name))
+        })
+    };
+
+    while let Some(arg) = iter.next() {
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, inline)) if name.starts_with("--") => (name, Some(inline.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        match name {
+            "-n" | "--nonblock" | "--nb" => parsed.nonblock = true,
+            "-x" | "-e" | "--exclusive" => {}
+            "-w" | "--timeout" | "--wait" => {
+                let seconds = match inline {
+                    Some(seconds) => seconds,
+                    None => value(&mut iter, name)?,
+                };
+                let timeout = parse_duration(&seconds).map_err(|_| {
+                    CommandResult::error(format!("flock: invalid timeout value: '{}'\n", seconds))
+                })?;
+                parsed.timeout = Some(timeout);
+            }
+            "-E" | "--conflict-exit-code" => {
+                let code = match inline {
+                    Some(code) => code,
+                    None => value(&mut iter, name)?,
+                };
+                parsed.conflict_code = code.parse().map_err(|_| {
+                    CommandResult::error(format!("flock: invalid exit code: '{}'\n", code))
+                })?;
+            }
+            "--" => {
+                parsed.file = value(&mut iter, "--")?;
+                break;
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(CommandResult::error(format!(
+                    "flock: invalid option '{}'\n",
+                    arg
+                )))
+            }
+            _ => {
+                parsed.file = arg.clone();
+                break;
+            }
+        }
+    }
+
+    let rest: Vec<&String> = iter.collect();
+    parsed.command = match rest.as_slice() {
+        [flag, command] if *flag == "-c" || *flag == "--command" => (*command).clone(),
+        [] => {
+            return Err(VirtualUtils::missing_operand_error_with_message(
+                "flock",
+                "requires a file and a command",
+            ))
+        }
+        words => quote_args(words.iter().map(|word| word.as_str())),
+    };
+    if parsed.file.is_empty() {
+        return Err(VirtualUtils::missing_operand_error("flock"));
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn ctx(dir: &TempDir, args: &[&str]) -> CommandContext {
+        let mut ctx = CommandContext::new(args.iter().map(|s| s.to_string()).collect());
+        ctx.cwd = Some(dir.path().to_path_buf());
+        ctx
+    }
+
+    #[tokio::test]
+    async fn test_flock_runs_command() {
+        let dir = TempDir::new().unwrap();
+        let result = flock(ctx(&dir, &["my.lock", "echo", "locked in"])).await;
+        assert!(result.is_success(), "{}", result.stderr);
+        assert_eq!(result.stdout, "locked in\n");
+        assert!(dir.path().join("my.lock").exists());
+
+        let result = flock(ctx(&dir, &["-w", "1", "my.lock", "-c", "echo a && echo b"])).await;
+        assert_eq!(result.stdout, "a\nb\n");
+    }
+
+    #[tokio::test]
+    async fn test_flock_conflict() {
+        let dir = TempDir::new().unwrap();
+        let _held = FileLock::try_acquire(dir.path().join("busy.lock"))
+            .unwrap()
+            .unwrap();
+
+        let result = flock(ctx(&dir, &["-n", "busy.lock", "true"])).await;
+        assert_eq!(result.code, 1);
+        let result = flock(ctx(&dir, &["-E", "75", "-w", "0.05", "busy.lock", "true"])).await;
+        assert_eq!(result.code, 75);
+    }
+
+    #[tokio::test]
+    async fn test_flock_usage_errors() {
+        let dir = TempDir::new().unwrap();
+        assert!(!flock(ctx(&dir, &[])).await.is_success());
+        assert!(!flock(ctx(&dir, &["only.lock"])).await.is_success());
+        let result = flock(ctx(&dir, &["--bogus", "f", "true"])).await;
+        assert_eq!(result.stderr, "flock: invalid option '--bogus'\n");
+    }
+}
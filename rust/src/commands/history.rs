@@ -0,0 +1,47 @@
+//! Virtual `history` command implementation
+
+use crate::commands::CommandContext;
+use crate::history::{self, HistoryFilter};
+use crate::utils::CommandResult;
+
+/// Execute the history command
+///
+/// Lists the recorded commands (see [`crate::history`]) as numbered lines.
+/// `history N` lists the last N commands and `history -c` clears the history.
+pub async fn history(ctx: CommandContext) -> CommandResult {
+    let mut filter = HistoryFilter::new();
+    match ctx.args.first().map(String::as_str) {
+        None => {}
+        Some("-c") => {
+            history::clear_history();
+            return CommandResult::success_empty();
+        }
+        Some(count) => match count.parse() {
+            Ok(count) => filter = filter.last(count),
+            Err(_) => {
+                return CommandResult::error(format!(
+                    "history: {}: numeric argument required\n",
+                    count
+                ))
+            }
+        },
+    }
+
+    let output: String = history::history_filtered(&filter)
+        .iter()
+        .map(|entry| format!("{:>5}  {}\n", entry.id, entry.command))
+        .collect();
+    CommandResult::success(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_history_rejects_non_numeric_count() {
+        let result = history(CommandContext::new(vec!["abc".to_string()])).await;
+        assert_eq!(result.code, 1);
+        assert!(result.stderr.contains("numeric argument required"));
+    }
+}
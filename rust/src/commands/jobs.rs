@@ -0,0 +1,49 @@
+//! Virtual `jobs` command implementation
+
+use crate::commands::CommandContext;
+use crate::jobs::{list_jobs, resolve_job_spec, JobStatus};
+use crate::utils::CommandResult;
+
+/// Execute the jobs command
+///
+/// Lists the background jobs (see [`crate::jobs`]) the way bash does, `+`
+/// marking the most recent one and `-` the one before it. Job specs such as
+/// `%1` limit the listing to those jobs.
+pub async fn jobs(ctx: CommandContext) -> CommandResult {
+    let mut selected = Vec::new();
+    for spec in &ctx.args {
+        if spec.starts_with('-') {
+            return CommandResult::error_with_code(format!("jobs: {}: invalid option\n", spec), 2);
+        }
+        match resolve_job_spec(spec) {
+            Some(id) => selected.push(id),
+            None => return CommandResult::error(format!("jobs: {}: no such job\n", spec)),
+        }
+    }
+
+    let all = list_jobs();
+    let current = all.last().map(|job| job.id);
+    let previous = all.iter().rev().nth(1).map(|job| job.id);
+    let output = all
+        .iter()
+        .filter(|job| selected.is_empty() || selected.contains(&job.id))
+        .map(|job| {
+            let mark = match Some(job.id) {
+                id if id == current => '+',
+                id if id == previous => '-',
+                _ => ' ',
+            };
+            let (status, suffix) = match job.status {
+                JobStatus::Running => ("Running".to_string(), " &"),
+                JobStatus::Done(0) => ("Done".to_string(), ""),
+                JobStatus::Done(code) => (format!("Exit {}", code), ""),
+                JobStatus::Killed => ("Terminated".to_string(), ""),
+            };
+            format!(
+                "[{}]{}  {:<24}{}{}\n",
+                job.id, mark, status, job.command, suffix
+            )
+        })
+        .collect::<String>();
+    CommandResult::success(output)
+}
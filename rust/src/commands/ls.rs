@@ -1,6 +1,6 @@
 //! Virtual `ls` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{ArgParser, CommandContext};
 use crate::utils::{trace_lazy, CommandResult};
 use std::fs;
 use std::path::Path;
@@ -9,30 +9,18 @@ use std::path::Path;
 ///
 /// Lists directory contents.
 pub async fn ls(ctx: CommandContext) -> CommandResult {
-    // Parse flags
-    let mut show_all = false;
-    let mut long_format = false;
-    let mut paths = Vec::new();
-
-    for arg in &ctx.args {
-        if arg == "-a" || arg == "--all" {
-            show_all = true;
-        } else if arg == "-l" {
-            long_format = true;
-        } else if arg == "-la" || arg == "-al" {
-            show_all = true;
-            long_format = true;
-        } else if arg.starts_with('-') {
-            if arg.contains('a') {
-                show_all = true;
-            }
-            if arg.contains('l') {
-                long_format = true;
-            }
-        } else {
-            paths.push(arg.clone());
-        }
-    }
+    let parsed = match ArgParser::new("ls")
+        .flag("aA", "all")
+        .flag("l", "long")
+        .flag("1", "one-per-line")
+        .parse(&ctx.args)
+    {
+        Ok(parsed) => parsed,
+        Err(result) => return result,
+    };
+    let show_all = parsed.has("all");
+    let long_format = parsed.has("long");
+    let mut paths = parsed.operands;
 
     // Default to current directory
     if paths.is_empty() {
@@ -67,20 +55,18 @@ pub async fn ls(ctx: CommandContext) -> CommandResult {
                 Ok(entries) => {
                     let mut entry_strs = Vec::new();
 
-                    for entry in entries {
-                        if let Ok(entry) = entry {
-                            let name = entry.file_name().to_string_lossy().to_string();
+                    for entry in entries.flatten() {
+                        let name = entry.file_name().to_string_lossy().to_string();
 
-                            // Skip hidden files unless -a is specified
-                            if !show_all && name.starts_with('.') {
-                                continue;
-                            }
+                        // Skip hidden files unless -a is specified
+                        if !show_all && name.starts_with('.') {
+                            continue;
+                        }
 
-                            if long_format {
-                                entry_strs.push(format_entry(&entry.path(), true));
-                            } else {
-                                entry_strs.push(name);
-                            }
+                        if long_format {
+                            entry_strs.push(format_entry(&entry.path(), true));
+                        } else {
+                            entry_strs.push(name);
                         }
                     }
 
@@ -115,23 +101,107 @@ fn format_entry(path: &Path, long_format: bool) -> String {
         return name;
     }
 
-    // Long format: permissions, links, owner, group, size, date, name
-    let metadata = match fs::metadata(path) {
+    // Long format in the coreutils layout, so parsers::LsEntries can read it:
+    // permissions, links, owner, group, size, date, name
+    let metadata = match fs::symlink_metadata(path) {
         Ok(m) => m,
         Err(_) => return name,
     };
 
-    let file_type = if metadata.is_dir() { "d" } else { "-" };
-    let size = metadata.len();
+    let (perms, links, owner, group) = ownership(&metadata);
+    let modified = metadata
+        .modified()
+        .map(format_mtime)
+        .unwrap_or_else(|_| "?".to_string());
+    let target = if metadata.is_symlink() {
+        fs::read_link(path)
+            .map(|target| format!(" -> {}", target.display()))
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
 
-    // Simplified permissions
-    let perms = if metadata.is_dir() {
-        "drwxr-xr-x"
+    format!(
+        "{} {:>2} {} {} {:>8} {} {}{}",
+        perms,
+        links,
+        owner,
+        group,
+        metadata.len(),
+        modified,
+        name,
+        target
+    )
+}
+
+/// `ls -l` date: month, day and time for recent files, the year otherwise
+fn format_mtime(time: std::time::SystemTime) -> String {
+    let time = chrono::DateTime::<chrono::Local>::from(time);
+    let age = chrono::Local::now() - time;
+    if age < chrono::Duration::zero() || age > chrono::Duration::days(182) {
+        time.format("%b %e  %Y").to_string()
+    } else {
+        time.format("%b %e %H:%M").to_string()
+    }
+}
+
+#[cfg(unix)]
+fn ownership(metadata: &fs::Metadata) -> (String, u64, String, String) {
+    use nix::unistd::{Gid, Group, Uid, User};
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_block_device() {
+        'b'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
     } else {
-        "-rw-r--r--"
+        '-'
     };
+    let mode = metadata.mode();
+    let mut perms = String::with_capacity(10);
+    perms.push(kind);
+    for (bit, c) in [
+        (0o400, 'r'),
+        (0o200, 'w'),
+        (0o100, 'x'),
+        (0o040, 'r'),
+        (0o020, 'w'),
+        (0o010, 'x'),
+        (0o004, 'r'),
+        (0o002, 'w'),
+        (0o001, 'x'),
+    ] {
+        perms.push(if mode & bit != 0 { c } else { '-' });
+    }
+
+    let owner = User::from_uid(Uid::from_raw(metadata.uid()))
+        .ok()
+        .flatten()
+        .map_or_else(|| metadata.uid().to_string(), |user| user.name);
+    let group = Group::from_gid(Gid::from_raw(metadata.gid()))
+        .ok()
+        .flatten()
+        .map_or_else(|| metadata.gid().to_string(), |group| group.name);
+    (perms, metadata.nlink(), owner, group)
+}
 
-    format!("{} {:>8} {}", perms, size, name)
+#[cfg(not(unix))]
+fn ownership(metadata: &fs::Metadata) -> (String, u64, String, String) {
+    let perms = match (metadata.is_dir(), metadata.permissions().readonly()) {
+        (true, _) => "drwxr-xr-x",
+        (false, true) => "-r--r--r--",
+        (false, false) => "-rw-r--r--",
+    };
+    (perms.to_string(), 1, "-".to_string(), "-".to_string())
 }
 
 #[cfg(test)]
@@ -1,6 +1,6 @@
 //! Virtual `mkdir` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{ArgParser, CommandContext};
 use crate::utils::{trace_lazy, CommandResult, VirtualUtils};
 use std::fs;
 
@@ -12,19 +12,16 @@ pub async fn mkdir(ctx: CommandContext) -> CommandResult {
         return VirtualUtils::missing_operand_error("mkdir");
     }
 
-    // Check for -p flag
-    let mut create_parents = false;
-    let mut dirs = Vec::new();
-
-    for arg in &ctx.args {
-        if arg == "-p" {
-            create_parents = true;
-        } else if arg.starts_with('-') {
-            // Skip other flags
-        } else {
-            dirs.push(arg.clone());
-        }
-    }
+    let parsed = match ArgParser::new("mkdir")
+        .flag("p", "parents")
+        .flag("v", "verbose")
+        .parse(&ctx.args)
+    {
+        Ok(parsed) => parsed,
+        Err(result) => return result,
+    };
+    let create_parents = parsed.has("parents");
+    let dirs = parsed.operands;
 
     if dirs.is_empty() {
         return VirtualUtils::missing_operand_error("mkdir");
@@ -0,0 +1,191 @@
+//! Virtual `mktemp` command implementation
+
+use crate::commands::{ArgParser, CommandContext};
+use crate::utils::{trace_lazy, CommandResult, VirtualUtils};
+use std::path::{Path, PathBuf};
+
+/// Template used when none is given
+const DEFAULT_TEMPLATE: &str = "tmp.XXXXXXXXXX";
+
+/// Execute the mktemp command
+///
+/// Creates a uniquely named file, or directory with `-d`, and prints its
+/// path. The trailing `X`s of the template are replaced with random
+/// characters. Without a template, or with `-t`/`-p`, it is created in
+/// `-p DIR`, `$TMPDIR` or the system temporary directory; otherwise the
+/// template is relative to the working directory.
+///
+/// Created paths are tracked for [`cleanup_temp`](crate::temp::cleanup_temp).
+pub async fn mktemp(ctx: CommandContext) -> CommandResult {
+    let parsed = match ArgParser::new("mktemp")
+        .flag("d", "directory")
+        .flag("u", "dry-run")
+        .flag("q", "quiet")
+        // Only `-t` in GNU mktemp; the long name just keys the flag
+        .flag("t", "template-in-tmpdir")
+        .option("p", "tmpdir")
+        .option("", "suffix")
+        .parse(&ctx.args)
+    {
+        Ok(parsed) => parsed,
+        Err(result) => return result,
+    };
+    let quiet = parsed.has("quiet");
+    let fail = |message: String| {
+        CommandResult::error(if quiet {
+            String::new()
+        } else {
+            format!("mktemp: {}\n", message)
+        })
+    };
+    if parsed.operands.len() > 1 {
+        return fail("too many templates".to_string());
+    }
+
+    let template = parsed.operands.first().map(String::as_str);
+    let in_tmpdir = template.is_none() || parsed.has("template-in-tmpdir") || parsed.has("tmpdir");
+    let template = template.unwrap_or(DEFAULT_TEMPLATE);
+    let (prefix, random_len) = match split_template(template) {
+        Some(split) => split,
+        None => return fail(format!("too few X's in template '{}'", template)),
+    };
+
+    // The directory as it is printed, and as it is resolved
+    let template_path = Path::new(prefix);
+    let mut shown_dir = template_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    if in_tmpdir {
+        shown_dir = tmpdir(&ctx, parsed.value("tmpdir")).join(shown_dir);
+    }
+    let dir = VirtualUtils::resolve_path(&shown_dir.to_string_lossy(), ctx.cwd.as_deref());
+    let file_prefix = template_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut builder = tempfile::Builder::new();
+    builder
+        .prefix(&file_prefix)
+        .suffix(parsed.value("suffix").unwrap_or(""))
+        .rand_bytes(random_len);
+    let created = if parsed.has("directory") {
+        builder.tempdir_in(&dir).map(|dir| dir.keep())
+    } else {
+        builder
+            .tempfile_in(&dir)
+            .and_then(|file| file.into_temp_path().keep().map_err(|e| e.error))
+    };
+    let path = match created {
+        Ok(path) => path,
+        Err(e) => {
+            return fail(format!(
+                "failed to create {} via template '{}': {}",
+                if parsed.has("directory") {
+                    "directory"
+                } else {
+                    "file"
+                },
+                template,
+                e
+            ))
+        }
+    };
+
+    trace_lazy("VirtualCommand", || format!("mktemp: created {:?}", path));
+    if parsed.has("dry-run") {
+        let _ = if path.is_dir() {
+            std::fs::remove_dir(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+    } else {
+        crate::temp::track(path.clone());
+    }
+
+    let name = path.file_name().unwrap_or_default();
+    CommandResult::success(format!("{}\n", shown_dir.join(name).display()))
+}
+
+/// Split a template into the part before its trailing `X`s and their count,
+/// `None` if there are fewer than three
+fn split_template(template: &str) -> Option<(&str, usize)> {
+    let prefix = template.trim_end_matches('X');
+    let random_len = template.len() - prefix.len();
+    (random_len >= 3).then_some((prefix, random_len))
+}
+
+/// Directory for `-t` and template-less names
+fn tmpdir(ctx: &CommandContext, option: Option<&str>) -> PathBuf {
+    let from_env = ctx
+        .env
+        .as_ref()
+        .and_then(|env| env.get("TMPDIR").cloned())
+        .or_else(|| std::env::var("TMPDIR").ok());
+    match option.map(str::to_string).or(from_env) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::temp_dir(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn ctx(dir: &TempDir, args: &[&str]) -> CommandContext {
+        let mut ctx = CommandContext::new(args.iter().map(|s| s.to_string()).collect());
+        ctx.cwd = Some(dir.path().to_path_buf());
+        ctx
+    }
+
+    #[tokio::test]
+    async fn test_mktemp_file_and_directory() {
+        let dir = TempDir::new().unwrap();
+        let dir_arg = dir.path().to_string_lossy().into_owned();
+
+        let result = mktemp(ctx(&dir, &["-p", &dir_arg])).await;
+        assert!(result.is_success(), "{}", result.stderr);
+        let path = PathBuf::from(result.stdout.trim_end());
+        assert!(path.is_file());
+        assert!(path.starts_with(dir.path()));
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("tmp."));
+
+        let result = mktemp(ctx(&dir, &["-d", "--suffix", ".work", "build.XXXX"])).await;
+        assert!(result.is_success(), "{}", result.stderr);
+        let shown = result.stdout.trim_end();
+        assert!(shown.starts_with("build.") && shown.ends_with(".work"));
+        assert_eq!(shown.len(), "build.XXXX.work".len());
+        assert!(dir.path().join(shown).is_dir());
+
+        let scratch = dir.path().join("scratch");
+        std::fs::create_dir(&scratch).unwrap();
+        let mut with_tmpdir = ctx(&dir, &["-t", "job.XXX"]);
+        with_tmpdir.env = Some([("TMPDIR".to_string(), "scratch".to_string())].into());
+        let result = mktemp(with_tmpdir).await;
+        assert!(
+            result.stdout.starts_with("scratch/job."),
+            "{}",
+            result.stdout
+        );
+        assert!(dir.path().join(result.stdout.trim_end()).is_file());
+    }
+
+    #[tokio::test]
+    async fn test_mktemp_dry_run_and_errors() {
+        let dir = TempDir::new().unwrap();
+        let result = mktemp(ctx(&dir, &["-u", "probe.XXXXXX"])).await;
+        assert!(result.is_success());
+        assert!(!dir.path().join(result.stdout.trim_end()).exists());
+
+        let result = mktemp(ctx(&dir, &["nope.XX"])).await;
+        assert_eq!(result.stderr, "mktemp: too few X's in template 'nope.XX'\n");
+        let result = mktemp(ctx(&dir, &["-q", "nope.XX"])).await;
+        assert!(!result.is_success() && result.stderr.is_empty());
+    }
+}
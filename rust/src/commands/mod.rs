@@ -4,6 +4,8 @@
 //! without spawning external processes. These provide faster execution and
 //! consistent behavior across platforms.
 
+mod alias;
+mod args;
 mod basename;
 mod cat;
 mod cd;
@@ -11,47 +13,71 @@ mod cp;
 mod dirname;
 mod echo;
 mod env;
+mod executor;
 mod exit;
 mod r#false;
+mod flock;
+mod history;
+mod jobs;
 mod ls;
 mod mkdir;
+mod mktemp;
 mod mv;
 mod pwd;
 mod rm;
 mod seq;
+mod set;
+mod shopt;
 mod sleep;
+mod streams;
 mod test;
 mod touch;
 mod r#true;
+mod wait;
 mod which;
 mod yes;
 
+pub use alias::{alias, unalias};
+pub use args::{ArgParser, ParsedArgs};
 pub use basename::basename;
 pub use cat::cat;
 pub use cd::cd;
+pub(crate) use cd::change_dir;
 pub use cp::cp;
 pub use dirname::dirname;
 pub use echo::echo;
 pub use env::env;
+pub use executor::CommandExecutor;
 pub use exit::exit;
+pub use flock::flock;
+pub use history::history;
+pub use jobs::jobs;
 pub use ls::ls;
 pub use mkdir::mkdir;
+pub use mktemp::mktemp;
 pub use mv::mv;
 pub use pwd::pwd;
 pub use r#false::r#false;
 pub use r#true::r#true;
 pub use rm::rm;
 pub use seq::seq;
+pub use set::set;
+pub use shopt::shopt;
 pub use sleep::sleep;
+pub use streams::{null_device, SpecialFile};
 pub use test::test;
 pub use touch::touch;
+pub use wait::wait;
 pub use which::which;
 pub use yes::yes;
 
+use crate::state::ShellSettings;
 use crate::utils::CommandResult;
+use crate::RunOptions;
 use std::collections::HashMap;
-use std::path::Path;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+use tokio_util::sync::CancellationToken;
 
 /// Context for virtual command execution
 pub struct CommandContext {
@@ -67,6 +93,14 @@ pub struct CommandContext {
     pub output_tx: Option<mpsc::Sender<StreamChunk>>,
     /// Cancellation check function
     pub is_cancelled: Option<Box<dyn Fn() -> bool + Send + Sync>>,
+    /// Token that cancels the command when triggered
+    pub cancel_token: Option<CancellationToken>,
+    /// Shell settings that builtins like `set` read and modify. `None` means
+    /// the global settings.
+    pub shell_settings: Option<Arc<RwLock<ShellSettings>>>,
+    /// Runs other commands with the options of the command this one runs
+    /// for; see [`executor`](Self::executor)
+    pub executor: Option<CommandExecutor>,
 }
 
 impl std::fmt::Debug for CommandContext {
@@ -78,6 +112,9 @@ impl std::fmt::Debug for CommandContext {
             .field("env", &self.env)
             .field("output_tx", &self.output_tx.is_some())
             .field("is_cancelled", &self.is_cancelled.is_some())
+            .field("cancel_token", &self.cancel_token)
+            .field("shell_settings", &self.shell_settings.is_some())
+            .field("executor", &self.executor.is_some())
             .finish()
     }
 }
@@ -99,12 +136,82 @@ impl CommandContext {
             env: None,
             output_tx: None,
             is_cancelled: None,
+            cancel_token: None,
+            shell_settings: None,
+            executor: None,
         }
     }
 
     /// Check if the command has been cancelled
     pub fn is_cancelled(&self) -> bool {
-        self.is_cancelled.as_ref().map(|f| f()).unwrap_or(false)
+        self.cancel_token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+            || self.is_cancelled.as_ref().map(|f| f()).unwrap_or(false)
+    }
+
+    /// Wait until the command is cancelled
+    ///
+    /// Never completes when the context has no way to be cancelled.
+    pub async fn cancelled(&self) {
+        match (&self.cancel_token, &self.is_cancelled) {
+            (Some(token), None) => token.cancelled().await,
+            (None, None) => std::future::pending().await,
+            _ => {
+                while !self.is_cancelled() {
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                }
+            }
+        }
+    }
+
+    /// Write `text` to stdout as it is produced: through the output channel
+    /// when there is one, otherwise into `buffer` for the final result
+    ///
+    /// Returns `false` once nobody reads the output channel any more, like a
+    /// write to a closed pipe; the command should then stop with
+    /// [`OUTPUT_CLOSED`].
+    pub async fn write_stdout(&self, buffer: &mut String, text: impl Into<String>) -> bool {
+        self.write(buffer, StreamChunk::Stdout(text.into())).await
+    }
+
+    /// Write `text` to stderr as it is produced: through the output channel
+    /// when there is one, otherwise into `buffer` for the final result
+    ///
+    /// Returns `false` once nobody reads the output channel any more.
+    pub async fn write_stderr(&self, buffer: &mut String, text: impl Into<String>) -> bool {
+        self.write(buffer, StreamChunk::Stderr(text.into())).await
+    }
+
+    async fn write(&self, buffer: &mut String, chunk: StreamChunk) -> bool {
+        match &self.output_tx {
+            Some(tx) => tx.send(chunk).await.is_ok(),
+            None => {
+                match chunk {
+                    StreamChunk::Stdout(text) | StreamChunk::Stderr(text) => buffer.push_str(&text),
+                }
+                true
+            }
+        }
+    }
+
+    /// The handle for running other commands from this one, in its
+    /// directory and environment and cancelled along with it
+    ///
+    /// Without an [`executor`](Self::executor) in the context, commands run
+    /// with the default options and [`shell_settings`](Self::shell_settings).
+    pub fn executor(&self) -> CommandExecutor {
+        let mut executor = match &self.executor {
+            Some(executor) => executor.clone(),
+            None => CommandExecutor::new(RunOptions::default())
+                .with_session_settings(self.shell_settings.clone()),
+        };
+        executor.options.cwd = self.cwd.clone();
+        executor.options.env = self.env.clone();
+        if let Some(token) = &self.cancel_token {
+            executor.options.cancel = Some(token.clone());
+        }
+        executor
     }
 
     /// Get the current working directory
@@ -115,6 +222,71 @@ impl CommandContext {
     }
 }
 
+/// Exit code of a command that stopped because nobody reads its output any
+/// more, as a process killed by `SIGPIPE` reports it in the shell
+pub const OUTPUT_CLOSED: i32 = 141;
+
+/// Names of the built-in virtual commands
+pub const BUILTIN_COMMANDS: &[&str] = &[
+    "alias", "basename", "cat", "cd", "cp", "dirname", "echo", "env", "exit", "false", "flock",
+    "history", "jobs", "ls", "mkdir", "mktemp", "mv", "pwd", "rm", "seq", "set", "shopt", "sleep",
+    "test", "touch", "true", "unalias", "wait", "which", "yes",
+];
+
+/// Run the built-in virtual command `name`, or return `None` when there is no
+/// such builtin.
+///
+/// When the context has an output channel, whatever the command left in its
+/// result's stdout and stderr is sent through the channel too, so streamed
+/// output is complete and callers only need to watch one place.
+pub async fn execute_builtin(name: &str, ctx: CommandContext) -> Option<CommandResult> {
+    let output_tx = ctx.output_tx.clone();
+    let mut result = match name {
+        "echo" => echo(ctx).await,
+        "pwd" => pwd(ctx).await,
+        "cd" => cd(ctx).await,
+        "true" => r#true(ctx).await,
+        "false" => r#false(ctx).await,
+        "sleep" => sleep(ctx).await,
+        "cat" => cat(ctx).await,
+        "ls" => ls(ctx).await,
+        "mkdir" => mkdir(ctx).await,
+        "mktemp" => mktemp(ctx).await,
+        "rm" => rm(ctx).await,
+        "touch" => touch(ctx).await,
+        "cp" => cp(ctx).await,
+        "mv" => mv(ctx).await,
+        "basename" => basename(ctx).await,
+        "dirname" => dirname(ctx).await,
+        "env" => env(ctx).await,
+        "exit" => exit(ctx).await,
+        "flock" => flock(ctx).await,
+        "history" => history(ctx).await,
+        "jobs" => jobs(ctx).await,
+        "which" => which(ctx).await,
+        "yes" => yes(ctx).await,
+        "seq" => seq(ctx).await,
+        "set" => set(ctx).await,
+        "shopt" => shopt(ctx).await,
+        "test" => test(ctx).await,
+        "wait" => wait(ctx).await,
+        "alias" => alias(ctx).await,
+        "unalias" => unalias(ctx).await,
+        _ => return None,
+    };
+    if let Some(tx) = output_tx {
+        let stdout = std::mem::take(&mut result.stdout);
+        let stderr = std::mem::take(&mut result.stderr);
+        if !stdout.is_empty() {
+            let _ = tx.send(StreamChunk::Stdout(stdout)).await;
+        }
+        if !stderr.is_empty() {
+            let _ = tx.send(StreamChunk::Stderr(stderr)).await;
+        }
+    }
+    Some(result)
+}
+
 /// Type for virtual command handler functions
 pub type VirtualCommandHandler =
     fn(
@@ -194,7 +366,13 @@ pub fn disable_virtual_commands() {
     VIRTUAL_COMMANDS_ENABLED.store(false, std::sync::atomic::Ordering::SeqCst);
 }
 
-/// Check if virtual commands are enabled
+/// Check if virtual commands are enabled, here or in the enclosing
+/// [`Config::scoped`](crate::config::Config::scoped)
 pub fn are_virtual_commands_enabled() -> bool {
+    crate::config::scoped_virtual_commands().unwrap_or_else(virtual_commands_enabled_globally)
+}
+
+/// The global setting, ignoring any scoped configuration
+pub(crate) fn virtual_commands_enabled_globally() -> bool {
     VIRTUAL_COMMANDS_ENABLED.load(std::sync::atomic::Ordering::SeqCst)
 }
@@ -1,6 +1,6 @@
 //! Virtual `mv` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{ArgParser, CommandContext};
 use crate::utils::{trace_lazy, CommandResult, VirtualUtils};
 use std::fs;
 
@@ -12,14 +12,16 @@ pub async fn mv(ctx: CommandContext) -> CommandResult {
         return VirtualUtils::invalid_argument_error("mv", "missing file operand");
     }
 
-    // Parse flags (currently just skip them)
-    let mut paths = Vec::new();
-
-    for arg in &ctx.args {
-        if !arg.starts_with('-') {
-            paths.push(arg.clone());
-        }
-    }
+    // Moving always overwrites, so -f is accepted but changes nothing
+    let parsed = match ArgParser::new("mv")
+        .flag("f", "force")
+        .flag("v", "verbose")
+        .parse(&ctx.args)
+    {
+        Ok(parsed) => parsed,
+        Err(result) => return result,
+    };
+    let mut paths = parsed.operands;
 
     if paths.len() < 2 {
         return VirtualUtils::invalid_argument_error("mv", "missing destination file operand");
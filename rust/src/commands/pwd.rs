@@ -6,9 +6,10 @@ use std::env;
 
 /// Execute the pwd command
 ///
-/// Prints the current working directory.
-pub async fn pwd(_ctx: CommandContext) -> CommandResult {
-    match env::current_dir() {
+/// Prints the directory the command runs in: its `cwd`, or else the
+/// process's current directory.
+pub async fn pwd(ctx: CommandContext) -> CommandResult {
+    match ctx.cwd.map_or_else(env::current_dir, Ok) {
         Ok(path) => CommandResult::success(format!("{}\n", path.display())),
         Err(e) => CommandResult::error(format!("pwd: {}\n", e)),
     }
@@ -1,6 +1,6 @@
 //! Virtual `rm` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{ArgParser, CommandContext};
 use crate::utils::{trace_lazy, CommandResult, VirtualUtils};
 use std::fs;
 
@@ -12,31 +12,18 @@ pub async fn rm(ctx: CommandContext) -> CommandResult {
         return VirtualUtils::missing_operand_error("rm");
     }
 
-    // Parse flags
-    let mut recursive = false;
-    let mut force = false;
-    let mut paths = Vec::new();
-
-    for arg in &ctx.args {
-        if arg == "-r" || arg == "-R" || arg == "--recursive" {
-            recursive = true;
-        } else if arg == "-f" || arg == "--force" {
-            force = true;
-        } else if arg == "-rf" || arg == "-fr" {
-            recursive = true;
-            force = true;
-        } else if arg.starts_with('-') {
-            // Check for combined flags like -rf
-            if arg.contains('r') || arg.contains('R') {
-                recursive = true;
-            }
-            if arg.contains('f') {
-                force = true;
-            }
-        } else {
-            paths.push(arg.clone());
-        }
-    }
+    let parsed = match ArgParser::new("rm")
+        .flag("rR", "recursive")
+        .flag("f", "force")
+        .flag("v", "verbose")
+        .parse(&ctx.args)
+    {
+        Ok(parsed) => parsed,
+        Err(result) => return result,
+    };
+    let recursive = parsed.has("recursive");
+    let force = parsed.has("force");
+    let paths = parsed.operands;
 
     if paths.is_empty() {
         return VirtualUtils::missing_operand_error("rm");
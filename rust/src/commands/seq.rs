@@ -1,11 +1,16 @@
 //! Virtual `seq` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{CommandContext, OUTPUT_CLOSED};
 use crate::utils::{CommandResult, VirtualUtils};
 
+/// Size of the blocks of lines written at once
+const CHUNK_SIZE: usize = 8 * 1024;
+
 /// Execute the seq command
 ///
-/// Prints a sequence of numbers.
+/// Prints a sequence of numbers. With an output channel in the context the
+/// lines are sent a block at a time as they are produced, and `seq` stops
+/// with [`OUTPUT_CLOSED`] once nobody reads them.
 pub async fn seq(ctx: CommandContext) -> CommandResult {
     if ctx.args.is_empty() {
         return VirtualUtils::missing_operand_error("seq");
@@ -82,36 +87,33 @@ pub async fn seq(ctx: CommandContext) -> CommandResult {
     }
 
     let mut output = String::new();
+    let mut block = String::new();
     let mut current = first;
 
-    if increment > 0.0 {
-        while current <= last {
-            if ctx.is_cancelled() {
-                return CommandResult::error_with_code("", 130);
-            }
-
-            // Format as integer if possible
-            if current.fract() == 0.0 {
-                output.push_str(&format!("{}\n", current as i64));
-            } else {
-                output.push_str(&format!("{}\n", current));
-            }
-            current += increment;
+    while (increment > 0.0 && current <= last) || (increment < 0.0 && current >= last) {
+        if ctx.is_cancelled() {
+            return CommandResult::error_with_code("", 130);
+        }
+
+        // Format as integer if possible
+        if current.fract() == 0.0 {
+            block.push_str(&format!("{}\n", current as i64));
+        } else {
+            block.push_str(&format!("{}\n", current));
         }
-    } else {
-        while current >= last {
-            if ctx.is_cancelled() {
-                return CommandResult::error_with_code("", 130);
-            }
-
-            if current.fract() == 0.0 {
-                output.push_str(&format!("{}\n", current as i64));
-            } else {
-                output.push_str(&format!("{}\n", current));
-            }
-            current += increment;
+        current += increment;
+
+        if block.len() >= CHUNK_SIZE
+            && !ctx
+                .write_stdout(&mut output, std::mem::take(&mut block))
+                .await
+        {
+            return CommandResult::error_with_code("", OUTPUT_CLOSED);
         }
     }
+    if !block.is_empty() && !ctx.write_stdout(&mut output, block).await {
+        return CommandResult::error_with_code("", OUTPUT_CLOSED);
+    }
 
     CommandResult::success(output)
 }
@@ -164,4 +166,18 @@ mod tests {
         assert!(!result.is_success());
         assert!(result.stderr.contains("zero increment"));
     }
+
+    #[tokio::test]
+    async fn test_seq_stops_when_output_closes() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let mut ctx = CommandContext::new(vec!["1000000000".to_string()]);
+        ctx.output_tx = Some(tx);
+        let reader = tokio::spawn(async move {
+            rx.recv().await;
+        });
+
+        let result = seq(ctx).await;
+        reader.await.unwrap();
+        assert_eq!(result.code, OUTPUT_CLOSED);
+    }
 }
@@ -0,0 +1,188 @@
+//! Virtual `set` command implementation
+
+use crate::commands::CommandContext;
+use crate::state::{global_state, ShellSettings};
+use crate::utils::CommandResult;
+
+/// Options `set` understands, as (short flag, long name) pairs
+const OPTIONS: &[(Option<char>, &str)] = &[
+    (Some('a'), "allexport"),
+    (Some('e'), "errexit"),
+    (Some('f'), "noglob"),
+    (Some('u'), "nounset"),
+    (Some('v'), "verbose"),
+    (Some('x'), "xtrace"),
+    (None, "pipefail"),
+];
+
+/// Execute the set command
+///
+/// Toggles shell options: `-e`/`+e`, combined flags like `-eux`, and
+/// `-o name`/`+o name` for long names such as `pipefail`. Changes apply to
+/// the context's shell settings, those of the session or the list of
+/// commands `set` is part of, so the commands after it see them. Like
+/// `sh -c 'set -e'`, a `set` run on its own changes nothing that lasts: the
+/// global settings are only changed with
+/// [`set_shell_option`](crate::state::set_shell_option).
+///
+/// `set`, `set -o` and `set +o` without an option name print the current
+/// settings.
+pub async fn set(ctx: CommandContext) -> CommandResult {
+    let changes = match parse_changes(&ctx.args) {
+        Ok(Some(changes)) => changes,
+        Ok(None) => {
+            let reusable = ctx.args.first().map(String::as_str) == Some("+o");
+            let settings = current_settings(&ctx).await;
+            return CommandResult::success(format_settings(&settings, reusable));
+        }
+        Err(message) => return CommandResult::error_with_code(format!("set: {}\n", message), 2),
+    };
+
+    let apply = |settings: &mut ShellSettings| {
+        for (name, value) in &changes {
+            settings.set(name, *value);
+        }
+    };
+    if let Some(settings) = &ctx.shell_settings {
+        apply(&mut *settings.write().await);
+    }
+
+    CommandResult::success_empty()
+}
+
+/// Parse the arguments into (long name, value) changes
+///
+/// Returns `Ok(None)` when the arguments ask for a listing instead.
+fn parse_changes(args: &[String]) -> Result<Option<Vec<(&'static str, bool)>>, String> {
+    if args.is_empty() {
+        return Ok(None);
+    }
+
+    let mut changes = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" || arg == "-" {
+            break;
+        }
+        let (value, flags) = match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+            (Some(flags), _) => (true, flags),
+            (_, Some(flags)) => (false, flags),
+            _ => return Err(format!("{}: positional parameters are not supported", arg)),
+        };
+
+        if flags == "o" {
+            match iter.next() {
+                Some(name) => changes.push((long_name(name)?, value)),
+                None if changes.is_empty() => return Ok(None),
+                None => return Err(format!("{}: option name required", arg)),
+            }
+            continue;
+        }
+
+        for flag in flags.chars() {
+            let name = OPTIONS
+                .iter()
+                .find(|(short, _)| *short == Some(flag))
+                .map(|(_, long)| *long)
+                .ok_or_else(|| format!("{}{}: invalid option", &arg[..1], flag))?;
+            changes.push((name, value));
+        }
+    }
+    Ok(Some(changes))
+}
+
+fn long_name(name: &str) -> Result<&'static str, String> {
+    OPTIONS
+        .iter()
+        .map(|(_, long)| *long)
+        .find(|long| *long == name)
+        .ok_or_else(|| format!("{}: invalid option name", name))
+}
+
+async fn current_settings(ctx: &CommandContext) -> ShellSettings {
+    match &ctx.shell_settings {
+        Some(settings) => settings.read().await.clone(),
+        None => global_state().get_shell_settings().await,
+    }
+}
+
+/// Format the settings like `set -o`, or as `set +o` commands that restore
+/// them when `reusable` is true.
+fn format_settings(settings: &ShellSettings, reusable: bool) -> String {
+    OPTIONS
+        .iter()
+        .map(|(_, name)| {
+            let enabled = match *name {
+                "allexport" => settings.allexport,
+                "errexit" => settings.errexit,
+                "noglob" => settings.noglob,
+                "nounset" => settings.nounset,
+                "verbose" => settings.verbose,
+                "xtrace" => settings.xtrace,
+                _ => settings.pipefail,
+            };
+            if reusable {
+                format!("set {}o {}\n", if enabled { '-' } else { '+' }, name)
+            } else {
+                format!("{:<15}{}\n", name, if enabled { "on" } else { "off" })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn ctx_with_settings(args: &[&str], settings: &Arc<RwLock<ShellSettings>>) -> CommandContext {
+        let mut ctx = CommandContext::new(args.iter().map(|s| s.to_string()).collect());
+        ctx.shell_settings = Some(settings.clone());
+        ctx
+    }
+
+    #[tokio::test]
+    async fn test_set_toggles_options() {
+        let settings = Arc::new(RwLock::new(ShellSettings::new()));
+
+        let result = set(ctx_with_settings(&["-ex", "-o", "pipefail"], &settings)).await;
+        assert!(result.is_success());
+        {
+            let s = settings.read().await;
+            assert!(s.errexit && s.xtrace && s.pipefail);
+        }
+
+        set(ctx_with_settings(&["+e", "+o", "pipefail"], &settings)).await;
+        let s = settings.read().await;
+        assert!(!s.errexit && !s.pipefail);
+        assert!(s.xtrace);
+    }
+
+    #[tokio::test]
+    async fn test_set_lists_options() {
+        let settings = Arc::new(RwLock::new(ShellSettings::new()));
+        settings.write().await.errexit = true;
+
+        let result = set(ctx_with_settings(&["-o"], &settings)).await;
+        assert!(result.stdout.contains("errexit        on\n"));
+        assert!(result.stdout.contains("pipefail       off\n"));
+
+        let result = set(ctx_with_settings(&["+o"], &settings)).await;
+        assert!(result.stdout.contains("set -o errexit\n"));
+        assert!(result.stdout.contains("set +o xtrace\n"));
+    }
+
+    #[tokio::test]
+    async fn test_set_rejects_unknown_options() {
+        let settings = Arc::new(RwLock::new(ShellSettings::new()));
+
+        let result = set(ctx_with_settings(&["-z"], &settings)).await;
+        assert_eq!(result.code, 2);
+        assert!(result.stderr.contains("-z: invalid option"));
+
+        let result = set(ctx_with_settings(&["-o", "nosuch"], &settings)).await;
+        assert_eq!(result.code, 2);
+        assert!(!settings.read().await.errexit);
+    }
+}
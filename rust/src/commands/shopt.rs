@@ -0,0 +1,137 @@
+//! Virtual `shopt` command implementation
+
+use crate::commands::CommandContext;
+use crate::state::{global_state, ShellSettings};
+use crate::utils::CommandResult;
+
+/// Options `shopt` understands
+const OPTIONS: &[&str] = &["globstar"];
+
+/// Execute the shopt command
+///
+/// Toggles the shell options bash keeps apart from `set`: `shopt -s name`
+/// enables one, `shopt -u name` disables it. Like `set`'s, changes apply to
+/// the context's shell settings, and without any they don't last.
+///
+/// Without `-s` or `-u` it prints the named options, or all of them, and
+/// succeeds only if they are all on; `-p` prints them as `shopt` commands
+/// and `-q` prints nothing.
+pub async fn shopt(ctx: CommandContext) -> CommandResult {
+    let mut change = None;
+    let (mut reusable, mut quiet) = (false, false);
+    let mut names = Vec::new();
+    let mut args = ctx.args.iter();
+    for arg in args.by_ref() {
+        match arg.as_str() {
+            "--" => break,
+            flags if flags.len() > 1 && flags.starts_with('-') => {
+                for flag in flags[1..].chars() {
+                    match flag {
+                        's' => change = Some(true),
+                        'u' => change = Some(false),
+                        'p' => reusable = true,
+                        'q' => quiet = true,
+                        _ => {
+                            return CommandResult::error_with_code(
+                                format!("shopt: -{}: invalid option\n", flag),
+                                2,
+                            )
+                        }
+                    }
+                }
+            }
+            name => names.push(name),
+        }
+    }
+    names.extend(args.map(String::as_str));
+    if let Some(name) = names.iter().find(|name| !OPTIONS.contains(name)) {
+        return CommandResult::error(format!("shopt: {}: invalid shell option name\n", name));
+    }
+
+    if let Some(value) = change.filter(|_| !names.is_empty()) {
+        let apply = |settings: &mut ShellSettings| {
+            for name in &names {
+                settings.set(name, value);
+            }
+        };
+        if let Some(settings) = &ctx.shell_settings {
+            apply(&mut *settings.write().await);
+        }
+        return CommandResult::success_empty();
+    }
+
+    let settings = match &ctx.shell_settings {
+        Some(settings) => settings.read().await.clone(),
+        None => global_state().get_shell_settings().await,
+    };
+    let listed: Vec<(&str, bool)> = if names.is_empty() {
+        // `shopt -s` and `shopt -u` alone list the options that are on or off
+        OPTIONS
+            .iter()
+            .map(|name| (*name, enabled(&settings, name)))
+            .filter(|(_, on)| change.is_none_or(|value| *on == value))
+            .collect()
+    } else {
+        names
+            .iter()
+            .map(|name| (*name, enabled(&settings, name)))
+            .collect()
+    };
+    let output = if quiet {
+        String::new()
+    } else {
+        listed
+            .iter()
+            .map(|(name, on)| match reusable {
+                true => format!("shopt -{} {}\n", if *on { 's' } else { 'u' }, name),
+                false => format!("{:<15}\t{}\n", name, if *on { "on" } else { "off" }),
+            })
+            .collect()
+    };
+    CommandResult {
+        code: i32::from(!names.is_empty() && listed.iter().any(|(_, on)| !on)),
+        ..CommandResult::success(output)
+    }
+}
+
+fn enabled(settings: &ShellSettings, name: &str) -> bool {
+    match name {
+        "globstar" => settings.globstar,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn ctx_with_settings(args: &[&str], settings: &Arc<RwLock<ShellSettings>>) -> CommandContext {
+        let mut ctx = CommandContext::new(args.iter().map(|s| s.to_string()).collect());
+        ctx.shell_settings = Some(settings.clone());
+        ctx
+    }
+
+    #[tokio::test]
+    async fn test_shopt_toggles_and_queries_options() {
+        let settings = Arc::new(RwLock::new(ShellSettings::new()));
+
+        let result = shopt(ctx_with_settings(&["globstar"], &settings)).await;
+        assert_eq!(result.code, 1);
+        assert_eq!(result.stdout, "globstar       \toff\n");
+
+        assert!(shopt(ctx_with_settings(&["-s", "globstar"], &settings))
+            .await
+            .is_success());
+        assert!(settings.read().await.globstar);
+        let result = shopt(ctx_with_settings(&["-p", "globstar"], &settings)).await;
+        assert_eq!(result.stdout, "shopt -s globstar\n");
+        assert!(shopt(ctx_with_settings(&["-q", "globstar"], &settings))
+            .await
+            .is_success());
+
+        let result = shopt(ctx_with_settings(&["-s", "nosuch"], &settings)).await;
+        assert!(result.stderr.contains("nosuch: invalid shell option name"));
+    }
+}
@@ -1,35 +1,34 @@
 //! Virtual `sleep` command implementation
 
 use crate::commands::CommandContext;
+use crate::units::parse_duration;
 use crate::utils::{trace_lazy, CommandResult};
 use tokio::time::{sleep as tokio_sleep, Duration};
 
 /// Execute the sleep command
 ///
-/// Pauses for the specified number of seconds.
+/// Pauses for the given duration. Like GNU `sleep`, a bare number is seconds,
+/// units such as `500ms`, `2m` or `1h30m` are accepted (see
+/// [`parse_duration`]), and several operands are added together.
 pub async fn sleep(ctx: CommandContext) -> CommandResult {
-    let seconds_str = ctx.args.first().map(|s| s.as_str()).unwrap_or("0");
-
-    let seconds: f64 = match seconds_str.parse() {
-        Ok(s) => s,
-        Err(_) => {
-            return CommandResult::error(format!(
-                "sleep: invalid time interval '{}'\n",
-                seconds_str
-            ));
+    let mut duration = Duration::ZERO;
+    for operand in &ctx.args {
+        match parse_duration(operand) {
+            Ok(part) => duration += part,
+            Err(_) => {
+                return CommandResult::error(format!(
+                    "sleep: invalid time interval '{}'\n",
+                    operand
+                ));
+            }
         }
-    };
-
-    if seconds < 0.0 {
-        return CommandResult::error(format!("sleep: invalid time interval '{}'\n", seconds_str));
     }
+    let seconds = duration.as_secs_f64();
 
     trace_lazy("VirtualCommand", || {
         format!("sleep: starting {} seconds", seconds)
     });
 
-    let duration = Duration::from_secs_f64(seconds);
-
     // Check for cancellation during sleep
     tokio::select! {
         _ = tokio_sleep(duration) => {
@@ -38,16 +37,9 @@ pub async fn sleep(ctx: CommandContext) -> CommandResult {
             });
             CommandResult::success_empty()
         }
-        _ = async {
-            loop {
-                tokio::time::sleep(Duration::from_millis(100)).await;
-                if ctx.is_cancelled() {
-                    break;
-                }
-            }
-        } => {
+        _ = ctx.cancelled() => {
             trace_lazy("VirtualCommand", || {
-                format!("sleep: cancelled after partial sleep")
+                "sleep: cancelled after partial sleep".to_string()
             });
             CommandResult::error_with_code("", 130) // SIGINT exit code
         }
@@ -91,4 +83,19 @@ mod tests {
         let result = sleep(ctx).await;
         assert!(!result.is_success());
     }
+
+    #[tokio::test]
+    async fn test_sleep_units_are_summed() {
+        let ctx = CommandContext::new(vec!["50ms".to_string(), "0.05s".to_string()]);
+        let start = Instant::now();
+        let result = sleep(ctx).await;
+        assert!(result.is_success());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let ctx = CommandContext::new(vec!["1x".to_string()]);
+        assert_eq!(
+            sleep(ctx).await.stderr,
+            "sleep: invalid time interval '1x'\n"
+        );
+    }
 }
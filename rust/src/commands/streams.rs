@@ -0,0 +1,52 @@
+//! Paths that stand for standard streams or the null device
+//!
+//! Shell idioms like `cat /dev/null`, `cat -` or `> /dev/null` rely on
+//! device paths that don't exist on Windows. Virtual commands and the
+//! in-process executor recognize them by name instead of opening them, so
+//! they behave the same on every platform.
+
+use std::path::Path;
+
+/// A path naming a standard stream or the null device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpecialFile {
+    /// `/dev/null`, or `NUL` on Windows: reads nothing, discards writes
+    Null,
+    /// `/dev/stdin` or `/dev/fd/0`
+    Stdin,
+    /// `/dev/stdout` or `/dev/fd/1`
+    Stdout,
+    /// `/dev/stderr` or `/dev/fd/2`
+    Stderr,
+}
+
+impl SpecialFile {
+    /// The special file `path` names, if any
+    ///
+    /// `-`, which commands like `cat` read as stdin, is an operand
+    /// convention rather than a path, so it isn't recognized here.
+    ///
+    /// ```
+    /// use command_stream::commands::SpecialFile;
+    ///
+    /// assert_eq!(SpecialFile::parse("/dev/null"), Some(SpecialFile::Null));
+    /// assert_eq!(SpecialFile::parse("/dev/fd/2"), Some(SpecialFile::Stderr));
+    /// assert_eq!(SpecialFile::parse("notes.txt"), None);
+    /// ```
+    pub fn parse(path: &str) -> Option<Self> {
+        match path {
+            "/dev/null" => Some(SpecialFile::Null),
+            "/dev/stdin" | "/dev/fd/0" => Some(SpecialFile::Stdin),
+            "/dev/stdout" | "/dev/fd/1" => Some(SpecialFile::Stdout),
+            "/dev/stderr" | "/dev/fd/2" => Some(SpecialFile::Stderr),
+            _ if cfg!(windows) && path.eq_ignore_ascii_case("NUL") => Some(SpecialFile::Null),
+            _ => None,
+        }
+    }
+}
+
+/// The null device of this platform, for opening where a real file is
+/// needed
+pub fn null_device() -> &'static Path {
+    Path::new(if cfg!(windows) { "NUL" } else { "/dev/null" })
+}
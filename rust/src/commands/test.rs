@@ -1,6 +1,6 @@
 //! Virtual `test` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{CommandContext, SpecialFile};
 use crate::utils::CommandResult;
 use std::fs;
 use std::path::Path;
@@ -33,7 +33,7 @@ fn evaluate_expression(args: &[String]) -> bool {
         let arg = &args[1];
 
         return match op.as_str() {
-            "-e" => Path::new(arg).exists(),
+            "-e" => SpecialFile::parse(arg).is_some() || Path::new(arg).exists(),
             "-f" => Path::new(arg).is_file(),
             "-d" => Path::new(arg).is_dir(),
             "-r" => {
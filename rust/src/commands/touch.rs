@@ -1,26 +1,30 @@
 //! Virtual `touch` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{ArgParser, CommandContext};
 use crate::utils::{trace_lazy, CommandResult, VirtualUtils};
 use std::fs::{self, OpenOptions};
 use std::time::SystemTime;
 
 /// Execute the touch command
 ///
-/// Updates file timestamps or creates empty files.
+/// Updates file timestamps or creates empty files. With `-c` missing files
+/// are not created.
 pub async fn touch(ctx: CommandContext) -> CommandResult {
-    if ctx.args.is_empty() {
+    let parsed = match ArgParser::new("touch")
+        .flag("c", "no-create")
+        .parse(&ctx.args)
+    {
+        Ok(parsed) => parsed,
+        Err(result) => return result,
+    };
+    if parsed.operands.is_empty() {
         return VirtualUtils::missing_operand_error("touch");
     }
+    let no_create = parsed.has("no-create");
 
     let cwd = ctx.get_cwd();
 
-    for file in &ctx.args {
-        if file.starts_with('-') {
-            // Skip flags for now
-            continue;
-        }
-
+    for file in &parsed.operands {
         let resolved_path = VirtualUtils::resolve_path(file, Some(&cwd));
 
         trace_lazy("VirtualCommand", || {
@@ -30,8 +34,8 @@ pub async fn touch(ctx: CommandContext) -> CommandResult {
         if resolved_path.exists() {
             // Update modification time
             let now = SystemTime::now();
-            if let Err(e) =
-                filetime::set_file_mtime(&resolved_path, filetime::FileTime::from_system_time(now))
+            if filetime::set_file_mtime(&resolved_path, filetime::FileTime::from_system_time(now))
+                .is_err()
             {
                 // Fallback: try to just open and close the file
                 if let Err(e2) = OpenOptions::new().write(true).open(&resolved_path) {
@@ -41,7 +45,7 @@ pub async fn touch(ctx: CommandContext) -> CommandResult {
                     ));
                 }
             }
-        } else {
+        } else if !no_create {
             // Create the file
             if let Some(parent) = resolved_path.parent() {
                 if !parent.exists() {
@@ -0,0 +1,48 @@
+//! Virtual `wait` command implementation
+
+use crate::commands::CommandContext;
+use crate::jobs::{resolve_job_spec, wait_all_jobs, wait_job};
+use crate::utils::CommandResult;
+use crate::Error;
+
+/// Exit status of a job that was killed, as for `SIGTERM`
+const KILLED: i32 = 128 + 15;
+
+/// Execute the wait command
+///
+/// Waits for the background jobs named by job specs such as `%1`, or for
+/// all of them, and removes them from the job table (see [`crate::jobs`]).
+/// Exits with the status of the last job named, 127 if it doesn't exist, or
+/// 0 after waiting for all jobs.
+pub async fn wait(ctx: CommandContext) -> CommandResult {
+    let waiting = async {
+        if ctx.args.is_empty() {
+            wait_all_jobs().await;
+            return CommandResult::success_empty();
+        }
+        let exited = |code| CommandResult {
+            code,
+            ..CommandResult::default()
+        };
+        let mut last = CommandResult::success_empty();
+        for spec in &ctx.args {
+            let outcome = match resolve_job_spec(spec) {
+                Some(id) => wait_job(id).await,
+                None => None,
+            };
+            last = match outcome {
+                Some(Ok(result)) => exited(result.code),
+                Some(Err(Error::Cancelled)) => exited(KILLED),
+                Some(Err(e)) => exited(e.exit_code().unwrap_or(1)),
+                None => {
+                    CommandResult::error_with_code(format!("wait: {}: no such job\n", spec), 127)
+                }
+            };
+        }
+        last
+    };
+    tokio::select! {
+        result = waiting => result,
+        _ = ctx.cancelled() => CommandResult::error_with_code("", 130),
+    }
+}
@@ -1,6 +1,6 @@
 //! Virtual `which` command implementation
 
-use crate::commands::CommandContext;
+use crate::commands::{ArgParser, CommandContext};
 use crate::utils::{CommandResult, VirtualUtils};
 
 /// List of virtual (shell builtin) commands
@@ -13,7 +13,11 @@ const VIRTUAL_COMMANDS: &[&str] = &[
 ///
 /// Locates commands in the PATH or identifies shell builtins.
 pub async fn which(ctx: CommandContext) -> CommandResult {
-    if ctx.args.is_empty() {
+    let names = match ArgParser::new("which").parse(&ctx.args) {
+        Ok(parsed) => parsed.operands,
+        Err(result) => return result,
+    };
+    if names.is_empty() {
         return VirtualUtils::missing_operand_error("which");
     }
 
@@ -21,11 +25,7 @@ pub async fn which(ctx: CommandContext) -> CommandResult {
     let mut errors = String::new();
     let mut found_all = true;
 
-    for cmd in &ctx.args {
-        if cmd.starts_with('-') {
-            continue;
-        }
-
+    for cmd in &names {
         // Check if it's a virtual/builtin command
         if VIRTUAL_COMMANDS.contains(&cmd.as_str()) {
             output.push_str(&format!("{}: shell builtin\n", cmd));
@@ -48,6 +48,7 @@ pub async fn which(ctx: CommandContext) -> CommandResult {
             stdout: String::new(),
             stderr: errors,
             code: 1,
+            ..Default::default()
         }
     } else if !found_all {
         // Some commands found, some not
@@ -55,6 +56,7 @@ pub async fn which(ctx: CommandContext) -> CommandResult {
             stdout: output,
             stderr: errors,
             code: 1,
+            ..Default::default()
         }
     } else {
         CommandResult::success(output)
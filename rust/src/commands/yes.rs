@@ -1,12 +1,13 @@
 //! Virtual `yes` command implementation
 
-use crate::commands::{CommandContext, StreamChunk};
+use crate::commands::{CommandContext, StreamChunk, OUTPUT_CLOSED};
 use crate::utils::{trace_lazy, CommandResult};
 use tokio::time::Duration;
 
 /// Execute the yes command
 ///
-/// Outputs a string repeatedly until cancelled.
+/// Outputs a string repeatedly until cancelled, or with an output channel in
+/// the context until nobody reads it, when it stops with [`OUTPUT_CLOSED`].
 pub async fn yes(ctx: CommandContext) -> CommandResult {
     let output_str = if ctx.args.is_empty() {
         "y".to_string()
@@ -29,8 +30,8 @@ pub async fn yes(ctx: CommandContext) -> CommandResult {
             }
 
             if tx.send(StreamChunk::Stdout(line.clone())).await.is_err() {
-                // Channel closed
-                break;
+                trace_lazy("VirtualCommand", || "yes: output closed".to_string());
+                return CommandResult::error_with_code("", OUTPUT_CLOSED);
             }
 
             // Small delay to prevent overwhelming
@@ -50,10 +51,8 @@ pub async fn yes(ctx: CommandContext) -> CommandResult {
             output.push_str(&line);
         }
 
-        return CommandResult::success(output);
+        CommandResult::success(output)
     }
-
-    CommandResult::error_with_code("", 130)
 }
 
 #[cfg(test)]
@@ -0,0 +1,365 @@
+//! Tab completion scripts for the `command-stream` CLI
+//!
+//! [`completion_script`] writes a script that completes the CLI's flags,
+//! their values, and the virtual command names in command position, falling
+//! back to the shell's own completion of programs and files. The CLI prints
+//! it with `--completions <shell>`:
+//!
+//! ```text
+//! command-stream --completions bash > ~/.local/share/bash-completion/completions/command-stream
+//! command-stream --completions zsh > "${fpath[1]}/_command-stream"
+//! command-stream --completions fish > ~/.config/fish/completions/command-stream.fish
+//! command-stream --completions powershell >> $PROFILE
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::commands::BUILTIN_COMMANDS;
+use crate::Error;
+
+/// Name of the CLI binary the scripts complete
+const PROGRAM: &str = "command-stream";
+
+/// A shell [`completion_script`] can write for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+}
+
+impl FromStr for CompletionShell {
+    type Err = Error;
+
+    /// `bash`, `zsh`, `fish`, or `powershell` (or `pwsh`)
+    fn from_str(name: &str) -> Result<Self, Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "bash" => Ok(CompletionShell::Bash),
+            "zsh" => Ok(CompletionShell::Zsh),
+            "fish" => Ok(CompletionShell::Fish),
+            "powershell" | "pwsh" => Ok(CompletionShell::PowerShell),
+            _ => Err(Error::ParseError(format!(
+                "no completions for `{}`: expected bash, zsh, fish or powershell",
+                name
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for CompletionShell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CompletionShell::Bash => "bash",
+            CompletionShell::Zsh => "zsh",
+            CompletionShell::Fish => "fish",
+            CompletionShell::PowerShell => "powershell",
+        })
+    }
+}
+
+/// What follows a flag
+#[derive(Clone, Copy)]
+enum Value {
+    None,
+    /// A path
+    File,
+    /// One of these words, though others may do too
+    Words(&'static [&'static str]),
+}
+
+/// A flag of the CLI
+struct Flag {
+    short: Option<char>,
+    long: &'static str,
+    value: Value,
+    help: &'static str,
+}
+
+const SHELLS: &[&str] = &["auto", "posix", "powershell", "bash", "zsh", "sh"];
+const BACKENDS: &[&str] = &["local", "ssh://", "docker://", "wsl", "wsl://"];
+const COMPLETION_SHELLS: &[&str] = &["bash", "zsh", "fish", "powershell"];
+
+const FLAGS: &[Flag] = &[
+    Flag {
+        short: Some('y'),
+        long: "yes",
+        value: Value::None,
+        help: "Run destructive commands without asking",
+    },
+    Flag {
+        short: Some('f'),
+        long: "file",
+        value: Value::File,
+        help: "Run a script file, statement by statement",
+    },
+    Flag {
+        short: None,
+        long: "shell",
+        value: Value::Words(SHELLS),
+        help: "Shell syntax, or a shell to run commands with",
+    },
+    Flag {
+        short: None,
+        long: "backend",
+        value: Value::Words(BACKENDS),
+        help: "Where commands run",
+    },
+    Flag {
+        short: None,
+        long: "ssh-identity",
+        value: Value::File,
+        help: "Private key for an ssh:// backend",
+    },
+    Flag {
+        short: None,
+        long: "time",
+        value: Value::None,
+        help: "Print the real, user and system time at the end",
+    },
+    Flag {
+        short: None,
+        long: "stats",
+        value: Value::None,
+        help: "Also print CPU usage, max RSS and bytes of output",
+    },
+    Flag {
+        short: None,
+        long: "completions",
+        value: Value::Words(COMPLETION_SHELLS),
+        help: "Print a tab completion script for a shell",
+    },
+];
+
+/// The completion script for `shell`
+///
+/// ```
+/// use command_stream::completions::{completion_script, CompletionShell};
+///
+/// let script = completion_script(CompletionShell::Bash);
+/// assert!(script.contains("complete -F _command_stream command-stream"));
+/// ```
+pub fn completion_script(shell: CompletionShell) -> String {
+    match shell {
+        CompletionShell::Bash => bash(),
+        CompletionShell::Zsh => zsh(),
+        CompletionShell::Fish => fish(),
+        CompletionShell::PowerShell => powershell(),
+    }
+}
+
+/// Every spelling of every flag, `--time=json` and `--stats=json` included
+fn flag_words() -> Vec<String> {
+    let mut words = Vec::new();
+    for flag in FLAGS {
+        if let Some(short) = flag.short {
+            words.push(format!("-{}", short));
+        }
+        words.push(format!("--{}", flag.long));
+        if matches!(flag.long, "time" | "stats") {
+            words.push(format!("--{}=json", flag.long));
+        }
+    }
+    words
+}
+
+/// The spellings of the flags taking a value, as a `case` pattern
+fn value_flags() -> String {
+    FLAGS
+        .iter()
+        .filter(|flag| !matches!(flag.value, Value::None))
+        .flat_map(|flag| {
+            let short = flag.short.map(|short| format!("-{}", short));
+            short.into_iter().chain([format!("--{}", flag.long)])
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn bash() -> String {
+    let mut values = String::new();
+    for flag in FLAGS {
+        let pattern = match flag.short {
+            Some(short) => format!("-{}|--{}", short, flag.long),
+            None => format!("--{}", flag.long),
+        };
+        let reply = match flag.value {
+            Value::None => continue,
+            Value::File => "compgen -f -- \"$cur\"".to_string(),
+            Value::Words(words) => format!("compgen -W \"{}\" -- \"$cur\"", words.join(" ")),
+        };
+        values.push_str(&format!(
+            "        {}) COMPREPLY=($({})); return ;;\n",
+            pattern, reply
+        ));
+    }
+    format!(
+        r#"# bash completion for {program}
+
+_command_stream() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    case "$prev" in
+{values}    esac
+
+    # After the command, its arguments are files
+    local i=1
+    while [[ $i -lt $COMP_CWORD ]]; do
+        case "${{COMP_WORDS[i]}}" in
+            {value_flags}) i=$((i + 2)) ;;
+            -*) i=$((i + 1)) ;;
+            *) COMPREPLY=($(compgen -f -- "$cur")); return ;;
+        esac
+    done
+
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{flags}" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -W "{commands}" -- "$cur") $(compgen -c -- "$cur") $(compgen -f -- "$cur"))
+    fi
+}}
+
+complete -F _command_stream {program}
+"#,
+        program = PROGRAM,
+        values = values,
+        value_flags = value_flags(),
+        flags = flag_words().join(" "),
+        commands = BUILTIN_COMMANDS.join(" "),
+    )
+}
+
+fn zsh() -> String {
+    let mut specs = String::new();
+    for flag in FLAGS {
+        let help = flag.help.replace(['[', ']'], "");
+        let value = match flag.value {
+            Value::None if matches!(flag.long, "time" | "stats") => {
+                // `--time` or `--time=json`
+                specs.push_str(&format!(
+                    "    '--{}=-[{}]::format:(json)' \\\n",
+                    flag.long, help
+                ));
+                continue;
+            }
+            Value::None => String::new(),
+            Value::File => ":file:_files".to_string(),
+            Value::Words(words) => format!(":{}:({})", flag.long, words.join(" ")),
+        };
+        let names = match flag.short {
+            Some(short) => format!("{{-{},--{}}}", short, flag.long),
+            None => format!("--{}", flag.long),
+        };
+        specs.push_str(&format!("    {}'[{}]{}' \\\n", names, help, value));
+    }
+    format!(
+        r#"#compdef {program}
+
+_command_stream() {{
+  local -a virtual_commands
+  virtual_commands=({commands})
+  _arguments -S \
+{specs}    '1: :->command' \
+    '*:: :_normal'
+  if [[ $state == command ]]; then
+    _alternative \
+      'virtual:virtual command:compadd -a virtual_commands' \
+      'commands:command:_command_names -e' \
+      'files:script:_files'
+  fi
+}}
+
+_command_stream "$@"
+"#,
+        program = PROGRAM,
+        commands = BUILTIN_COMMANDS.join(" "),
+        specs = specs,
+    )
+}
+
+fn fish() -> String {
+    let mut script = format!("# fish completion for {}\n\n", PROGRAM);
+    for flag in FLAGS {
+        let mut line = format!("complete -c {}", PROGRAM);
+        if let Some(short) = flag.short {
+            line.push_str(&format!(" -s {}", short));
+        }
+        line.push_str(&format!(" -l {}", flag.long));
+        match flag.value {
+            Value::None => {}
+            Value::File => line.push_str(" -r -F"),
+            Value::Words(words) => line.push_str(&format!(" -x -a '{}'", words.join(" "))),
+        }
+        line.push_str(&format!(" -d '{}'\n", flag.help.replace('\'', "\\'")));
+        script.push_str(&line);
+    }
+    script.push_str(&format!(
+        "complete -c {program} -n __fish_use_subcommand -a '{commands}' -d 'Virtual command'\n\
+         complete -c {program} -n __fish_use_subcommand -a '(__fish_complete_command)'\n",
+        program = PROGRAM,
+        commands = BUILTIN_COMMANDS.join(" "),
+    ));
+    script
+}
+
+fn powershell() -> String {
+    let list = |words: &[&str]| {
+        let quoted: Vec<String> = words.iter().map(|word| format!("'{}'", word)).collect();
+        format!("@({})", quoted.join(", "))
+    };
+    let mut values = String::new();
+    for flag in FLAGS {
+        if let Value::Words(words) = flag.value {
+            values.push_str(&format!(
+                "        '--{}' {{ {} }}\n",
+                flag.long,
+                list(words)
+            ));
+        }
+    }
+    let flags = flag_words();
+    let flags: Vec<&str> = flags.iter().map(String::as_str).collect();
+    format!(
+        r#"# PowerShell completion for {program}
+
+Register-ArgumentCompleter -Native -CommandName '{program}' -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $words = @($commandAst.CommandElements | ForEach-Object {{ $_.ToString() }})
+    $previous = if ($wordToComplete) {{ $words[-2] }} else {{ $words[-1] }}
+    $candidates = switch ($previous) {{
+{values}        default {{
+            if ($wordToComplete -like '-*') {{ {flags} }} else {{ {commands} }}
+        }}
+    }}
+    $candidates | Where-Object {{ $_ -like "$wordToComplete*" }} | ForEach-Object {{
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }}
+}}
+"#,
+        program = PROGRAM,
+        values = values,
+        flags = list(&flags),
+        commands = list(BUILTIN_COMMANDS),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_cover_flags_and_virtual_commands() {
+        for shell in COMPLETION_SHELLS {
+            let shell: CompletionShell = shell.parse().unwrap();
+            assert_eq!(shell.to_string().parse::<CompletionShell>().unwrap(), shell);
+            let script = completion_script(shell);
+            for needle in ["ssh-identity", "mktemp", "docker://"] {
+                assert!(script.contains(needle), "{}: no {}", shell, needle);
+            }
+        }
+        assert!(bash()
+            .contains("        -f|--file) COMPREPLY=($(compgen -f -- \"$cur\")); return ;;\n"));
+        assert!("tcsh".parse::<CompletionShell>().is_err());
+    }
+}
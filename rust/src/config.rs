@@ -0,0 +1,177 @@
+//! Snapshots of the effective configuration, and scoped overrides
+//!
+//! [`Config::current`] reads the defaults commands start from: the
+//! `COMMAND_STREAM_*` environment variables, whether virtual commands are
+//! enabled, and so on. Rather than changing those globals, which races with
+//! every other task, a library can run its own work under different
+//! defaults with [`Config::scoped`]:
+//!
+//! ```rust,no_run
+//! use command_stream::config::Config;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let quiet = Config::current().mirror(false).virtual_commands(false);
+//! let result = Config::scoped(quiet, || async { command_stream::run("ls").await }).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The override lives in task-local storage: it covers the future passed to
+//! `scoped` and nothing else, so tasks that future spawns with
+//! `tokio::spawn` see the global defaults again.
+
+use std::future::Future;
+use std::time::Duration;
+
+use crate::commands::virtual_commands_enabled_globally;
+use crate::{RunOptions, ShellChoice};
+
+tokio::task_local! {
+    static SCOPED: Config;
+}
+
+/// The defaults commands start from
+///
+/// Build one from [`Config::current`] and the setters; the fields can be
+/// read directly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    /// Mirror output to this process's stdout/stderr
+    pub mirror: bool,
+    /// Capture output in the result
+    pub capture: bool,
+    /// Kill commands that run longer than this
+    pub timeout: Option<Duration>,
+    /// The shell language commands are written in
+    pub shell: ShellChoice,
+    /// Run builtins like `echo` and `ls` in-process
+    pub virtual_commands: bool,
+}
+
+impl Config {
+    /// The configuration in effect for the current task: the one passed to
+    /// the enclosing [`Config::scoped`], or the global one
+    pub fn current() -> Config {
+        SCOPED
+            .try_with(Config::clone)
+            .unwrap_or_else(|_| Config::global())
+    }
+
+    /// The global configuration, ignoring any [`Config::scoped`] override
+    pub fn global() -> Config {
+        let options = RunOptions::from_env_vars();
+        Config {
+            mirror: options.mirror,
+            capture: options.capture,
+            timeout: options.timeout,
+            shell: options.shell,
+            virtual_commands: virtual_commands_enabled_globally(),
+        }
+    }
+
+    /// Run the future `f` returns with `config` in effect, for it alone
+    ///
+    /// Scopes nest: an inner `scoped` replaces the outer configuration
+    /// until its future completes.
+    pub async fn scoped<F, Fut, T>(config: Config, f: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        SCOPED.scope(config, f()).await
+    }
+
+    /// Options for a command under this configuration
+    pub fn run_options(&self) -> RunOptions {
+        RunOptions {
+            mirror: self.mirror,
+            capture: self.capture,
+            timeout: self.timeout,
+            shell: self.shell,
+            ..RunOptions::default()
+        }
+    }
+
+    /// Set whether output is mirrored
+    pub fn mirror(mut self, enabled: bool) -> Self {
+        self.mirror = enabled;
+        self
+    }
+
+    /// Set whether output is captured
+    pub fn capture(mut self, enabled: bool) -> Self {
+        self.capture = enabled;
+        self
+    }
+
+    /// Set the timeout, or remove it with `None`
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the shell language
+    pub fn shell(mut self, shell: ShellChoice) -> Self {
+        self.shell = shell;
+        self
+    }
+
+    /// Set whether virtual commands are used
+    pub fn virtual_commands(mut self, enabled: bool) -> Self {
+        self.virtual_commands = enabled;
+        self
+    }
+}
+
+/// Whether virtual commands are enabled by the enclosing
+/// [`Config::scoped`], if there is one
+pub(crate) fn scoped_virtual_commands() -> Option<bool> {
+    SCOPED.try_with(|config| config.virtual_commands).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scoped_overrides_only_inside() {
+        let outside = Config::current();
+        let inner = outside
+            .clone()
+            .mirror(!outside.mirror)
+            .timeout(Some(Duration::from_secs(7)));
+
+        let seen = Config::scoped(inner.clone(), || async {
+            let nested = Config::scoped(Config::current().capture(false), || async {
+                Config::current()
+            })
+            .await;
+            assert!(!nested.capture);
+            assert_eq!(nested.timeout, Some(Duration::from_secs(7)));
+            (Config::current(), RunOptions::from_env())
+        })
+        .await;
+
+        assert_eq!(seen.0, inner);
+        assert_eq!(seen.1.mirror, inner.mirror);
+        assert_eq!(seen.1.timeout, Some(Duration::from_secs(7)));
+        assert_eq!(Config::current(), outside);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_scopes_do_not_race() {
+        let with_virtual = |enabled: bool| {
+            Config::scoped(
+                Config::current().virtual_commands(enabled),
+                move || async move {
+                    for _ in 0..20 {
+                        tokio::task::yield_now().await;
+                        assert_eq!(crate::commands::are_virtual_commands_enabled(), enabled);
+                    }
+                },
+            )
+        };
+        tokio::join!(with_virtual(true), with_virtual(false));
+    }
+}
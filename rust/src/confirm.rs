@@ -0,0 +1,315 @@
+//! Asking before destructive commands run
+//!
+//! A [`ConfirmationGate`] classifies each command before it runs. Commands
+//! that look destructive (`rm -rf`, `dd of=`, `mkfs`, `git push --force`,
+//! ...) are only run if the gate's callback approves them; otherwise they
+//! fail with [`Error::NotConfirmed`]. Every decision is kept in the gate's
+//! audit log.
+//!
+//! ```rust,no_run
+//! use command_stream::confirm::{set_confirmation_gate, ConfirmationGate};
+//!
+//! # async fn example() {
+//! let gate = ConfirmationGate::new(|command, risk| {
+//!     eprintln!("refusing `{}` ({})", command, risk.rule);
+//!     false
+//! });
+//! set_confirmation_gate(Some(gate.into()));
+//!
+//! assert!(command_stream::run("rm -rf build").await.is_err());
+//! # }
+//! ```
+//!
+//! [`ConfirmationGate::prompt`] asks on the terminal instead, which is what
+//! the `command-stream` CLI does.
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufRead, IsTerminal, Write};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::redact::redact;
+use crate::{Error, Result};
+
+/// Decisions kept in a gate's audit log; older ones are dropped
+pub const AUDIT_LOG_CAPACITY: usize = 1000;
+
+static BUILTIN_RULES: Lazy<Vec<(String, Regex)>> = Lazy::new(|| {
+    [
+        ("recursive delete", r"\brm\s+(?:\S+\s+)*-[a-zA-Z]*[rR]"),
+        ("raw disk write", r"\bdd\b.*\bof="),
+        ("filesystem creation", r"\bmkfs(?:\.\w+)?\b"),
+        ("disk wipe", r"\b(?:shred|wipefs)\b"),
+        (
+            "write to a block device",
+            r">\s*/dev/(?:sd|hd|nvme|vd|disk|mmcblk)",
+        ),
+        (
+            "git force push",
+            r"\bgit\s+push\b.*\s(?:--force(?:-with-lease)?|-[a-zA-Z]*f)\b",
+        ),
+        ("git hard reset", r"\bgit\s+reset\b.*\s--hard\b"),
+        ("git clean", r"\bgit\s+clean\b.*\s-[a-zA-Z]*f"),
+    ]
+    .iter()
+    .map(|(name, rule)| {
+        let rule = Regex::new(rule).expect("built-in destructive command rule");
+        (name.to_string(), rule)
+    })
+    .collect()
+});
+
+static GATE: Lazy<RwLock<Option<Arc<ConfirmationGate>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Why a command was flagged as destructive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Risk {
+    /// Name of the rule that matched, e.g. `"recursive delete"`
+    pub rule: String,
+    /// The part of the command it matched
+    pub matched: String,
+}
+
+/// Decides which commands are destructive
+#[derive(Debug, Clone)]
+pub struct Classifier {
+    rules: Vec<(String, Regex)>,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Classifier::new()
+    }
+}
+
+impl Classifier {
+    /// The built-in rules
+    pub fn new() -> Self {
+        Classifier {
+            rules: BUILTIN_RULES.clone(),
+        }
+    }
+
+    /// No rules at all
+    pub fn empty() -> Self {
+        Classifier { rules: Vec::new() }
+    }
+
+    /// Also flag commands matching `pattern`, reporting them as `name`
+    pub fn rule(mut self, name: impl Into<String>, pattern: &str) -> Result<Self> {
+        let rule = Regex::new(pattern)
+            .map_err(|e| Error::ParseError(format!("invalid classifier pattern: {}", e)))?;
+        self.rules.push((name.into(), rule));
+        Ok(self)
+    }
+
+    /// The first rule `command` matches, `None` if it looks safe
+    pub fn classify(&self, command: &str) -> Option<Risk> {
+        self.rules.iter().find_map(|(name, rule)| {
+            rule.find(command).map(|found| Risk {
+                rule: name.clone(),
+                matched: found.as_str().to_string(),
+            })
+        })
+    }
+}
+
+/// A decision recorded by a [`ConfirmationGate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// When the decision was made
+    pub time: DateTime<Utc>,
+    /// The command, [redacted](crate::redact)
+    pub command: String,
+    /// The rule that flagged it
+    pub rule: String,
+    /// Whether it was allowed to run
+    pub approved: bool,
+}
+
+/// Decides whether a flagged command may run
+type ConfirmFn = Arc<dyn Fn(&str, &Risk) -> bool + Send + Sync>;
+
+/// Classifies commands and asks before destructive ones; see the
+/// [module docs](self)
+pub struct ConfirmationGate {
+    classifier: Classifier,
+    confirm: ConfirmFn,
+    audit: Mutex<VecDeque<AuditEntry>>,
+}
+
+impl fmt::Debug for ConfirmationGate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfirmationGate")
+            .field("classifier", &self.classifier)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ConfirmationGate {
+    /// A gate that calls `confirm` with each flagged command and runs it
+    /// only if `confirm` returns `true`
+    ///
+    /// `confirm` runs on a blocking thread, so it may wait for input.
+    pub fn new(confirm: impl Fn(&str, &Risk) -> bool + Send + Sync + 'static) -> Self {
+        ConfirmationGate {
+            classifier: Classifier::new(),
+            confirm: Arc::new(confirm),
+            audit: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// A gate that asks on the terminal, declining when there is none
+    pub fn prompt() -> Self {
+        ConfirmationGate::new(prompt_on_terminal)
+    }
+
+    /// Classify commands with `classifier` instead of the built-in rules
+    pub fn classifier(mut self, classifier: Classifier) -> Self {
+        self.classifier = classifier;
+        self
+    }
+
+    /// Ask about `command` if it looks destructive
+    ///
+    /// Returns [`Error::NotConfirmed`] if it was declined.
+    pub async fn check(&self, command: &str) -> Result<()> {
+        let Some(risk) = self.classifier.classify(command) else {
+            return Ok(());
+        };
+        let confirm = Arc::clone(&self.confirm);
+        let (shown, asked_risk) = (redact(command).into_owned(), risk.clone());
+        let approved = tokio::task::spawn_blocking(move || confirm(&shown, &asked_risk))
+            .await
+            .unwrap_or(false);
+
+        let mut audit = self.audit.lock().unwrap_or_else(|e| e.into_inner());
+        if audit.len() >= AUDIT_LOG_CAPACITY {
+            audit.pop_front();
+        }
+        audit.push_back(AuditEntry {
+            time: Utc::now(),
+            command: redact(command).into_owned(),
+            rule: risk.rule.clone(),
+            approved,
+        });
+
+        if approved {
+            Ok(())
+        } else {
+            Err(Error::not_confirmed(command, risk.rule))
+        }
+    }
+
+    /// Decisions made so far, oldest first
+    pub fn audit_log(&self) -> Vec<AuditEntry> {
+        let audit = self.audit.lock().unwrap_or_else(|e| e.into_inner());
+        audit.iter().cloned().collect()
+    }
+}
+
+/// Whether there is a terminal for [`ConfirmationGate::prompt`] to ask on;
+/// without one, its gate declines every destructive command
+pub fn terminal_available() -> bool {
+    terminal().is_some()
+}
+
+/// The controlling terminal, or stdin if it is one
+fn terminal() -> Option<Box<dyn BufRead>> {
+    #[cfg(unix)]
+    let tty = std::fs::File::open("/dev/tty")
+        .ok()
+        .map(|tty| Box::new(std::io::BufReader::new(tty)) as Box<dyn BufRead>);
+    #[cfg(not(unix))]
+    let tty: Option<Box<dyn BufRead>> = None;
+    match tty {
+        Some(tty) => Some(tty),
+        None if std::io::stdin().is_terminal() => Some(Box::new(std::io::stdin().lock())),
+        None => None,
+    }
+}
+
+/// Ask on stderr and read the answer from the terminal
+fn prompt_on_terminal(command: &str, risk: &Risk) -> bool {
+    let Some(mut input) = terminal() else {
+        return false;
+    };
+
+    let mut stderr = std::io::stderr().lock();
+    let _ = write!(
+        stderr,
+        "`{}` looks destructive ({}). Run it? [y/N] ",
+        command, risk.rule
+    );
+    let _ = stderr.flush();
+    let mut answer = String::new();
+    if input.read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// Ask `gate` about every command; `None` removes the global gate
+///
+/// A gate set on [`RunOptions`](crate::RunOptions) takes precedence.
+pub fn set_confirmation_gate(gate: Option<Arc<ConfirmationGate>>) {
+    *GATE.write().unwrap_or_else(|e| e.into_inner()) = gate;
+}
+
+/// The global gate set with [`set_confirmation_gate`]
+pub fn confirmation_gate() -> Option<Arc<ConfirmationGate>> {
+    GATE.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Check `command` with `gate`, or the global gate if it is `None`
+pub(crate) async fn confirm(gate: Option<&Arc<ConfirmationGate>>, command: &str) -> Result<()> {
+    match gate.cloned().or_else(confirmation_gate) {
+        Some(gate) => gate.check(command).await,
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_rules() {
+        let classifier = Classifier::new();
+        let rule = |command: &str| classifier.classify(command).map(|risk| risk.rule);
+        for (command, expected) in [
+            ("rm -rf build", Some("recursive delete")),
+            ("rm -v -R out", Some("recursive delete")),
+            ("rm file.txt", None),
+            ("dd if=image.iso of=/dev/sdb bs=4M", Some("raw disk write")),
+            ("sudo mkfs.ext4 /dev/sdb1", Some("filesystem creation")),
+            ("git push --force origin main", Some("git force push")),
+            ("git push -f", Some("git force push")),
+            ("git push origin feature-fix", None),
+            ("git reset --hard HEAD~1", Some("git hard reset")),
+            ("git clean -fdx", Some("git clean")),
+            ("ls -la && echo done", None),
+        ] {
+            assert_eq!(rule(command).as_deref(), expected, "{}", command);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_records_decisions() {
+        let gate = ConfirmationGate::new(|command, _| command.contains("tmp"))
+            .classifier(Classifier::empty().rule("delete", r"\brm\b").unwrap());
+        assert!(gate.check("echo hi").await.is_ok());
+        assert!(gate.check("rm /tmp/x").await.is_ok());
+        let err = gate.check("rm /etc/passwd").await.unwrap_err();
+        assert!(matches!(err, Error::NotConfirmed { .. }));
+
+        let log = gate.audit_log();
+        assert_eq!(log.len(), 2);
+        assert!(log[0].approved && !log[1].approved);
+        assert_eq!(log[1].command, "rm /etc/passwd");
+        assert_eq!(log[1].rule, "delete");
+    }
+}
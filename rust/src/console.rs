@@ -0,0 +1,106 @@
+//! Preparing this process's console for mirrored output
+//!
+//! Commands often color their output with ANSI escape sequences. Terminals
+//! on Unix render them; the Windows console only does once virtual terminal
+//! processing is turned on for it, and shows them as garbage otherwise.
+//! Before output is mirrored, [`ansi_supported`] turns it on, once; where
+//! that fails (an old console host), mirrored output has its sequences
+//! stripped instead.
+//!
+//! Children that mirror to a Windows console also get `TERM` set, when it
+//! isn't already, so tools that check it know whether colors will render.
+
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+use crate::ansi::AnsiUtils;
+use crate::RunOptions;
+
+/// Whether ANSI escape sequences written to this process's stdout and
+/// stderr are rendered
+///
+/// On Windows the first call enables virtual terminal processing on both
+/// consoles. Streams redirected to a file or pipe pass sequences through
+/// untouched, so they count as supported.
+pub fn ansi_supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(enable_virtual_terminal)
+}
+
+/// `text` as it should be written to this process's stdout or stderr:
+/// unchanged, or without escape sequences where they wouldn't render
+pub fn for_terminal(text: &str) -> Cow<'_, str> {
+    if ansi_supported() {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(AnsiUtils::strip_ansi(text))
+    }
+}
+
+/// Variables telling a child whose output is mirrored to a Windows console
+/// whether colors render there; names the child's environment already sets
+/// are left alone
+pub(crate) fn child_env(options: &RunOptions) -> Vec<(&'static str, &'static str)> {
+    if !cfg!(windows) || !options.mirror {
+        return Vec::new();
+    }
+    let vars: &[(&str, &str)] = if ansi_supported() {
+        &[("TERM", "xterm-256color")]
+    } else {
+        &[("TERM", "dumb"), ("NO_COLOR", "1")]
+    };
+    vars.iter()
+        .copied()
+        .filter(|(name, _)| {
+            std::env::var_os(name).is_none()
+                && !options
+                    .env
+                    .as_ref()
+                    .is_some_and(|env| env.contains_key(*name))
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+fn enable_virtual_terminal() -> bool {
+    use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+    use windows_sys::Win32::System::Console::{
+        GetConsoleMode, GetStdHandle, SetConsoleMode, CONSOLE_MODE,
+        ENABLE_VIRTUAL_TERMINAL_PROCESSING, STD_ERROR_HANDLE, STD_OUTPUT_HANDLE,
+    };
+
+    [STD_OUTPUT_HANDLE, STD_ERROR_HANDLE]
+        .into_iter()
+        .all(|which| unsafe {
+            let handle = GetStdHandle(which);
+            if handle.is_null() || handle == INVALID_HANDLE_VALUE {
+                return true;
+            }
+            let mut mode: CONSOLE_MODE = 0;
+            if GetConsoleMode(handle, &mut mode) == 0 {
+                // Not a console
+                return true;
+            }
+            mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+                || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+        })
+}
+
+#[cfg(not(windows))]
+fn enable_virtual_terminal() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_unix_terminals_keep_sequences() {
+        let text = "\x1b[32mok\x1b[0m";
+        assert!(ansi_supported());
+        assert!(matches!(for_terminal(text), Cow::Borrowed(t) if t == text));
+        assert!(child_env(&RunOptions::default()).is_empty());
+    }
+}
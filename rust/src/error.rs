@@ -0,0 +1,339 @@
+//! Error type for command-stream operations
+//!
+//! Failures that come from a command carry its context: the command string,
+//! the exit code or signal, and the tail of its captured stderr, so callers
+//! can report or branch on them without re-running anything. The command
+//! string is [redacted](crate::redact) so errors can be logged safely.
+
+use std::time::Duration;
+
+/// Number of trailing stderr lines kept in command errors
+pub const STDERR_TAIL_LINES: usize = 20;
+
+/// Error type for command-stream operations
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The command exited with a non-zero code (reported when `errexit` is on)
+    #[error("Command failed with exit code {code}: {message}")]
+    #[non_exhaustive]
+    CommandFailed {
+        /// Exit code of the command
+        code: i32,
+        /// Description of the failure (the command, unless stated otherwise)
+        message: String,
+        /// The command that failed
+        command: String,
+        /// Last lines of the command's stderr
+        stderr_tail: String,
+        /// Signal that ended the command, if any (Unix)
+        signal: Option<i32>,
+    },
+
+    #[error("Command not found: {0}")]
+    CommandNotFound(String),
+
+    #[error("Parse error: {0}")]
+    ParseError(String),
+
+    #[error("Cancelled")]
+    Cancelled,
+
+    /// The command ran longer than its timeout and was killed
+    #[error("Command timed out after {timeout:?}: {command}")]
+    #[non_exhaustive]
+    Timeout {
+        /// The command that timed out
+        command: String,
+        /// The timeout that expired
+        timeout: Duration,
+    },
+
+    /// The command went quiet for longer than its
+    /// [`Heartbeat`](crate::heartbeat::Heartbeat) allows and was killed
+    #[error("Command stalled with no output for {idle:?}: {command}")]
+    #[non_exhaustive]
+    Stalled {
+        /// The stalled command
+        command: String,
+        /// How long it had been quiet
+        idle: Duration,
+    },
+
+    /// The command was terminated by a signal (reported when `errexit` is on)
+    #[error("Command killed by signal {signal}: {command}")]
+    #[non_exhaustive]
+    KilledBySignal {
+        /// The command that was killed
+        command: String,
+        /// The terminating signal number
+        signal: i32,
+        /// Last lines of the command's stderr
+        stderr_tail: String,
+    },
+
+    /// The command was rejected by an [`ExecutionPolicy`](crate::ExecutionPolicy)
+    /// before anything ran
+    #[error("Policy violation: {violation}: {command}")]
+    #[non_exhaustive]
+    PolicyViolation {
+        /// The rejected command
+        command: String,
+        /// The rule it broke
+        violation: crate::policy::Violation,
+    },
+
+    /// The command looked destructive and its
+    /// [confirmation](crate::confirm) was declined
+    #[error("Command not confirmed ({rule}): {command}")]
+    #[non_exhaustive]
+    NotConfirmed {
+        /// The declined command
+        command: String,
+        /// The rule that flagged it
+        rule: String,
+    },
+
+    /// The command uses a variable that isn't set while `nounset` is on
+    #[error("Unset variable {name}: {command}")]
+    #[non_exhaustive]
+    UnsetVariable {
+        /// The command that uses it
+        command: String,
+        /// The variable's name
+        name: String,
+    },
+
+    /// A statement of a [script](crate::script) failed, or the script
+    /// couldn't be split into statements
+    #[error("{file}:{line}:{column}: {source}\n{snippet}")]
+    #[non_exhaustive]
+    Script {
+        /// The script's path, or the name it was run under
+        file: String,
+        /// Line of the script where it went wrong, from 1
+        line: usize,
+        /// Column on that line, from 1
+        column: usize,
+        /// The line, with a caret under the spot
+        snippet: String,
+        /// What went wrong there
+        source: Box<Error>,
+    },
+
+    /// A stage of a [pipeline](crate::pipeline) failed, such as one whose
+    /// program isn't installed
+    #[error("Pipeline stage {} ({command}): {source}", .index + 1)]
+    #[non_exhaustive]
+    PipelineStage {
+        /// Position of the stage in the pipeline, from 0
+        index: usize,
+        /// The stage's command
+        command: String,
+        /// What went wrong with it
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// A [`CommandFailed`](Error::CommandFailed) error for `command`
+    pub fn command_failed(
+        command: impl Into<String>,
+        code: i32,
+        stderr: &str,
+        signal: Option<i32>,
+    ) -> Self {
+        let command = redacted(command.into());
+        Error::CommandFailed {
+            code,
+            message: command.clone(),
+            command,
+            stderr_tail: tail_lines(stderr, STDERR_TAIL_LINES),
+            signal,
+        }
+    }
+
+    /// A [`Timeout`](Error::Timeout) error for `command`
+    pub fn timeout(command: impl Into<String>, timeout: Duration) -> Self {
+        Error::Timeout {
+            command: redacted(command.into()),
+            timeout,
+        }
+    }
+
+    /// A [`Stalled`](Error::Stalled) error for `command`
+    pub fn stalled(command: impl Into<String>, idle: Duration) -> Self {
+        Error::Stalled {
+            command: redacted(command.into()),
+            idle,
+        }
+    }
+
+    /// A [`KilledBySignal`](Error::KilledBySignal) error for `command`
+    pub fn killed_by_signal(command: impl Into<String>, signal: i32, stderr: &str) -> Self {
+        Error::KilledBySignal {
+            command: redacted(command.into()),
+            signal,
+            stderr_tail: tail_lines(stderr, STDERR_TAIL_LINES),
+        }
+    }
+
+    /// A [`PolicyViolation`](Error::PolicyViolation) error for `command`
+    pub fn policy_violation(
+        command: impl Into<String>,
+        violation: crate::policy::Violation,
+    ) -> Self {
+        Error::PolicyViolation {
+            command: redacted(command.into()),
+            violation,
+        }
+    }
+
+    /// A [`NotConfirmed`](Error::NotConfirmed) error for `command`
+    pub fn not_confirmed(command: impl Into<String>, rule: impl Into<String>) -> Self {
+        Error::NotConfirmed {
+            command: redacted(command.into()),
+            rule: rule.into(),
+        }
+    }
+
+    /// A [`PipelineStage`](Error::PipelineStage) error for the `index`th
+    /// stage, `command`
+    pub fn pipeline_stage(index: usize, command: impl Into<String>, source: Error) -> Self {
+        Error::PipelineStage {
+            index,
+            command: redacted(command.into()),
+            source: Box::new(source),
+        }
+    }
+
+    /// An [`UnsetVariable`](Error::UnsetVariable) error for `command`
+    pub fn unset_variable(command: impl Into<String>, name: impl Into<String>) -> Self {
+        Error::UnsetVariable {
+            command: redacted(command.into()),
+            name: name.into(),
+        }
+    }
+
+    /// The command this error is about, if it came from running one
+    pub fn command(&self) -> Option<&str> {
+        match self {
+            Error::CommandFailed { command, .. }
+            | Error::Timeout { command, .. }
+            | Error::Stalled { command, .. }
+            | Error::KilledBySignal { command, .. }
+            | Error::PolicyViolation { command, .. }
+            | Error::NotConfirmed { command, .. }
+            | Error::UnsetVariable { command, .. }
+            | Error::PipelineStage { command, .. } => Some(command),
+            Error::Script { source, .. } => source.command(),
+            _ => None,
+        }
+    }
+
+    /// The command's exit code, for [`CommandFailed`](Error::CommandFailed)
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            Error::CommandFailed { code, .. } => Some(*code),
+            Error::Script { source, .. } | Error::PipelineStage { source, .. } => {
+                source.exit_code()
+            }
+            _ => None,
+        }
+    }
+
+    /// How the command ended, for errors that come from its exit status or
+    /// from it not being found or timing out
+    pub fn exit_kind(&self) -> Option<crate::ExitKind> {
+        match self {
+            Error::CommandFailed {
+                signal: Some(signal),
+                ..
+            }
+            | Error::KilledBySignal { signal, .. } => {
+                Some(crate::ExitKind::SignalTerminated(*signal))
+            }
+            Error::CommandFailed { code, .. } => Some(crate::ExitKind::from_code(*code)),
+            Error::CommandNotFound(_) => Some(crate::ExitKind::NotFound),
+            Error::Timeout { .. } | Error::Stalled { .. } => Some(crate::ExitKind::Timeout),
+            Error::Script { source, .. } | Error::PipelineStage { source, .. } => {
+                source.exit_kind()
+            }
+            _ => None,
+        }
+    }
+
+    /// The tail of the command's stderr, when the error captured it
+    pub fn stderr_tail(&self) -> Option<&str> {
+        match self {
+            Error::CommandFailed { stderr_tail, .. }
+            | Error::KilledBySignal { stderr_tail, .. } => Some(stderr_tail),
+            Error::Script { source, .. } | Error::PipelineStage { source, .. } => {
+                source.stderr_tail()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Result type for command-stream operations
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// `command` with its secrets replaced
+fn redacted(command: String) -> String {
+    match crate::redact::redact(&command) {
+        std::borrow::Cow::Borrowed(_) => command,
+        std::borrow::Cow::Owned(redacted) => redacted,
+    }
+}
+
+/// The last `count` lines of `text`
+fn tail_lines(text: &str, count: usize) -> String {
+    let start = text
+        .trim_end_matches('\n')
+        .rmatch_indices('\n')
+        .nth(count.saturating_sub(1))
+        .map_or(0, |(index, _)| index + 1);
+    text[start..].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail_lines() {
+        assert_eq!(tail_lines("a\nb\nc\n", 2), "b\nc\n");
+        assert_eq!(tail_lines("a\nb\nc", 2), "b\nc");
+        assert_eq!(tail_lines("a\nb\n", 5), "a\nb\n");
+        assert_eq!(tail_lines("", 3), "");
+    }
+
+    #[test]
+    fn test_command_failed_keeps_context() {
+        let stderr: String = (1..=30).map(|i| format!("line {}\n", i)).collect();
+        let err = Error::command_failed("make all", 2, &stderr, None);
+
+        assert_eq!(err.command(), Some("make all"));
+        assert_eq!(err.exit_code(), Some(2));
+        let tail = err.stderr_tail().unwrap();
+        assert!(tail.starts_with("line 11\n"));
+        assert!(tail.ends_with("line 30\n"));
+        assert_eq!(err.to_string(), "Command failed with exit code 2: make all");
+    }
+
+    #[test]
+    fn test_exit_kind() {
+        use crate::ExitKind;
+        let failed = |code, signal| Error::command_failed("x", code, "", signal).exit_kind();
+        assert_eq!(failed(2, None), Some(ExitKind::Failure(2)));
+        assert_eq!(failed(127, None), Some(ExitKind::NotFound));
+        assert_eq!(failed(137, Some(9)), Some(ExitKind::SignalTerminated(9)));
+        let timeout = Error::timeout("x", Duration::from_secs(1));
+        assert_eq!(timeout.exit_kind(), Some(ExitKind::Timeout));
+        assert_eq!(Error::Cancelled.exit_kind(), None);
+    }
+}
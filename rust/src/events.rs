@@ -26,6 +26,18 @@ pub enum EventType {
     Error,
     /// Process spawned
     Spawn,
+    /// No output or heartbeat within the
+    /// [`Heartbeat`](crate::heartbeat::Heartbeat) interval
+    Stalled,
+    /// A command of an `&&`/`||`/`;` list is about to run
+    ///
+    /// With a listener for this or [`CommandFinish`](EventType::CommandFinish),
+    /// the runner runs a list command by command rather than in one shell,
+    /// so each step can be reported. Commands skipped by `&&` or `||` have
+    /// no events.
+    CommandStart,
+    /// A command of an `&&`/`||`/`;` list has finished, with its exit code
+    CommandFinish,
 }
 
 impl std::fmt::Display for EventType {
@@ -38,6 +50,9 @@ impl std::fmt::Display for EventType {
             EventType::Exit => write!(f, "exit"),
             EventType::Error => write!(f, "error"),
             EventType::Spawn => write!(f, "spawn"),
+            EventType::Stalled => write!(f, "stalled"),
+            EventType::CommandStart => write!(f, "command_start"),
+            EventType::CommandFinish => write!(f, "command_finish"),
         }
     }
 }
@@ -55,6 +70,17 @@ pub enum EventData {
     Result(crate::CommandResult),
     /// Error message
     Error(String),
+    /// How long the command has been quiet (for `Stalled`)
+    Idle(std::time::Duration),
+    /// A command of a list (for `CommandStart` and `CommandFinish`)
+    Command {
+        /// Position of the command in the list, from 0
+        index: usize,
+        /// The command's text
+        command: String,
+        /// Its exit code, once it has finished
+        code: Option<i32>,
+    },
     /// No data
     None,
 }
@@ -156,6 +182,25 @@ impl StreamEmitter {
         }
     }
 
+    /// Emit a chunk of output as a `Stdout` or `Stderr` event followed by
+    /// the combined `Data` event
+    pub async fn emit_output(&self, event: EventType, data: impl Into<String>) {
+        let data = data.into();
+        if data.is_empty() {
+            return;
+        }
+        self.emit(event.clone(), EventData::String(data.clone()))
+            .await;
+        self.emit(
+            EventType::Data,
+            EventData::TypedData {
+                data_type: event.to_string(),
+                data,
+            },
+        )
+        .await;
+    }
+
     /// Remove all listeners for an event
     ///
     /// # Arguments
@@ -0,0 +1,797 @@
+//! Expanding shell words in-process
+//!
+//! Virtual commands receive their arguments from the shell parser rather
+//! than from a shell, so expansions a shell would perform on the words have
+//! to happen here, the way `sh` performs them:
+//!
+//! - Variables (`$VAR`, `${VAR}`) come from
+//!   [`RunOptions::env`](crate::RunOptions::env), then the shell variables
+//!   in [`Parameters::variables`], then this process's environment. Unset
+//!   ones are empty, or an error with `nounset` on.
+//!   Outside double quotes their values are split into words at whitespace.
+//! - Command substitutions (`$(command)`) run the command with the same
+//!   options, virtual commands included, and stand for its stdout without
+//!   trailing newlines. Outside double quotes it is split into words too.
+//! - Filename globs (`*`, `?`, `[...]`) are matched in the command's working
+//!   directory unless `noglob` is on. Quoted or escaped characters match
+//!   literally, hidden files only match a pattern that starts with `.`,
+//!   matches are sorted, and a pattern matching nothing is passed on as
+//!   written. With `globstar` on, `**` as a whole path component matches
+//!   any number of directories, so `src/**/*.rs` finds sources at any
+//!   depth; otherwise it is the same as `*`.
+//! - A `~` starting a word, up to the first `/`, is the home directory:
+//!   `$HOME` for `~`, that user's for `~user`, and `$PWD` or `$OLDPWD` for
+//!   `~+` or `~-`. One that names no user is left as written.
+//! - Special and positional parameters (`$?`, `$#`, `$@`, `$0`, `$1`, ...)
+//!   come from [`RunOptions::parameters`](crate::RunOptions::parameters),
+//!   and `$$` is this process's id. A quoted `"$@"` stands for one word per
+//!   positional parameter.
+//!
+//! Words using other expansions, such as `$!` or `${VAR:-default}`, are left
+//! to a real shell.
+
+mod fields;
+
+use fields::Fields;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+
+use crate::commands::BUILTIN_COMMANDS;
+use crate::shell_parser::{
+    is_assignment, needs_real_shell_except_expansions, parse_shell_command, substitution_len,
+    ParsedCommand, Redirect,
+};
+use crate::{
+    console, CancellationToken, Error, ProcessRunner, Result, RunOptions, ShellSettings,
+    StdinOption,
+};
+
+/// What words are expanded against
+pub(crate) struct Context<'a> {
+    /// Options of the command the words belong to; its variables take
+    /// precedence over this process's environment
+    pub options: &'a RunOptions,
+    /// Directory globs and substitutions run in; this process's if `None`
+    pub cwd: Option<&'a Path>,
+    pub settings: &'a ShellSettings,
+    /// Cancels the substitutions along with the command
+    pub cancel: &'a CancellationToken,
+    /// The parameters `$?`, `$1` and the like stand for
+    pub parameters: &'a Parameters,
+}
+
+impl Context<'_> {
+    /// The value of the variable `name`: the command's, a shell variable,
+    /// or this process's
+    pub fn variable(&self, name: &str) -> Option<String> {
+        self.options
+            .env
+            .as_ref()
+            .and_then(|env| env.get(name))
+            .or_else(|| self.parameters.variables.get(name))
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+
+    /// Run `command` for a `$(...)`, returning its stdout without trailing
+    /// newlines
+    fn substitute<'b>(
+        &'b self,
+        command: &'b str,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'b>> {
+        Box::pin(async move {
+            let options = RunOptions {
+                cwd: self.cwd.map(Path::to_path_buf),
+                stdin: StdinOption::Null,
+                mirror: false,
+                capture: true,
+                timed_lines: false,
+                output_filters: Vec::new(),
+                cancel: Some(self.cancel.clone()),
+                shell_settings: Some(ShellSettings {
+                    errexit: false,
+                    ..self.settings.clone()
+                }),
+                parameters: self.parameters.clone(),
+                ..self.options.clone()
+            };
+            let result = ProcessRunner::new(command, options).run().await?;
+            if self.options.mirror {
+                eprint!("{}", console::for_terminal(&result.stderr));
+            }
+            Ok(result.stdout.trim_end_matches('\n').to_string())
+        })
+    }
+}
+
+/// The positional and special parameters a command's words can use
+///
+/// `$0` is the `name`, `$1`, `$2`, ... are the `args`, which `$#` counts
+/// and `$@` and `$*` list, and `$?` is the `status`. `$$` isn't kept here:
+/// it is this process's id. The `variables` are shell variables, which the
+/// command's words see but programs don't get in their environment.
+///
+/// ```
+/// use command_stream::expand::Parameters;
+///
+/// let parameters = Parameters::new("deploy.sh", ["staging", "--dry-run"]);
+/// assert_eq!(parameters.args[0], "staging");
+/// assert_eq!(parameters.status, 0);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameters {
+    /// `$0`, the name of the script or shell; `sh` by default
+    pub name: String,
+    /// The positional parameters, `$1` onward
+    pub args: Vec<String>,
+    /// `$?`, the exit status of the command before
+    pub status: i32,
+    /// Variables set for the command's words, and for the shell if one
+    /// runs it, without being exported
+    pub variables: BTreeMap<String, String>,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Parameters {
+            name: "sh".to_string(),
+            args: Vec::new(),
+            status: 0,
+            variables: BTreeMap::new(),
+        }
+    }
+}
+
+impl Parameters {
+    /// The parameters of a script `name` run with `args`
+    pub fn new(name: impl Into<String>, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Parameters {
+            name: name.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            status: 0,
+            variables: BTreeMap::new(),
+        }
+    }
+
+    /// The values the parameter `name` (`?`, `#`, `1`, ...) stands for:
+    /// one, or one per positional parameter for `@`, or none if it is a
+    /// positional parameter that isn't set
+    fn values(&self, name: &str) -> Option<Vec<String>> {
+        let value = match name {
+            "?" => self.status.to_string(),
+            "$" => std::process::id().to_string(),
+            "#" => self.args.len().to_string(),
+            "@" => return Some(self.args.clone()),
+            "*" => self.args.join(" "),
+            "0" => self.name.clone(),
+            position => {
+                let index = position.parse::<usize>().ok()?.checked_sub(1)?;
+                self.args.get(index)?.clone()
+            }
+        };
+        Some(vec![value])
+    }
+}
+
+/// Whether `name` names a special or positional parameter
+fn is_parameter(name: &str) -> bool {
+    matches!(name, "?" | "$" | "#" | "@" | "*")
+        || (!name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// What a `$` in a word stands for
+#[derive(Debug, PartialEq, Eq)]
+enum Expansion {
+    Variable(String),
+    /// A special or positional parameter, by name: `?`, `@`, `1`, ...
+    Parameter(String),
+    /// A `$(...)` and the command in it
+    Command(String),
+}
+
+/// Why a word wasn't expanded
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Unexpanded {
+    /// It uses an expansion only a shell performs
+    Unsupported,
+    /// `nounset` is on and the named variable isn't set
+    Unset(String),
+}
+
+/// Whether `word`, as written, has a glob character outside quotes that
+/// isn't escaped
+///
+/// ```
+/// use command_stream::expand::has_glob;
+///
+/// assert!(has_glob("*.txt"));
+/// assert!(has_glob("'my dir'/*"));
+/// assert!(!has_glob("'*.txt'"));
+/// assert!(!has_glob(r"\*.txt"));
+/// ```
+pub fn has_glob(word: &str) -> bool {
+    let mut fields = Fields::default();
+    scan(word, None, &mut fields).is_ok() && fields.finish().iter().any(|field| field.glob)
+}
+
+/// The files `word` matches in `cwd` (this process's directory if `None`),
+/// or the word with its quotes removed if it matches none
+///
+/// Matches are returned as the pattern spells them: relative patterns give
+/// paths relative to `cwd`. `**` matches recursively, as with `globstar` on.
+pub fn expand_glob(word: &str, cwd: Option<&Path>) -> Vec<String> {
+    let mut fields = Fields::default();
+    // Without variables the scan can't fail
+    let _ = scan(word, None, &mut fields);
+    fields
+        .finish()
+        .into_iter()
+        .flat_map(|field| field.matches(cwd, true))
+        .collect()
+}
+
+/// `word` with the `~` or `~user` it starts with replaced by that home
+/// directory, or `None` if it doesn't start with one or the user doesn't
+/// exist
+///
+/// ```
+/// use command_stream::expand::expand_tilde;
+///
+/// let home = std::env::var("HOME").unwrap();
+/// assert_eq!(expand_tilde("~/notes.txt"), Some(format!("{}/notes.txt", home)));
+/// assert_eq!(expand_tilde("notes~"), None);
+/// ```
+pub fn expand_tilde(word: &str) -> Option<String> {
+    let (home, len) = tilde_prefix(word, None)?;
+    Some(home + &word[len..])
+}
+
+/// The directory the `~` prefix of `word` stands for and the prefix's
+/// length, with `HOME`, `PWD` and `OLDPWD` taken from `env` first
+pub(crate) fn tilde_prefix(
+    word: &str,
+    env: Option<&HashMap<String, String>>,
+) -> Option<(String, usize)> {
+    let rest = word.strip_prefix('~')?;
+    let user = &rest[..rest.find('/').unwrap_or(rest.len())];
+    let var = |name: &str| {
+        env.and_then(|env| env.get(name).cloned())
+            .or_else(|| std::env::var(name).ok())
+    };
+    let home = match user {
+        "" => var("HOME").or_else(|| var("USERPROFILE").filter(|_| cfg!(windows))),
+        "+" => var("PWD"),
+        "-" => var("OLDPWD"),
+        user if user
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)) =>
+        {
+            user_home(user)
+        }
+        _ => None,
+    }?;
+    Some((home, 1 + user.len()))
+}
+
+/// The home directory of `user`, from the user database
+#[cfg(unix)]
+fn user_home(user: &str) -> Option<String> {
+    let user = nix::unistd::User::from_name(user).ok()??;
+    Some(user.dir.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn user_home(_user: &str) -> Option<String> {
+    None
+}
+
+/// The commands of the `$(...)` substitutions in `word`, in order
+fn substitutions(word: &str) -> std::result::Result<Vec<String>, Unexpanded> {
+    let found = RefCell::new(Vec::new());
+    let lookup = |expansion| {
+        if let Expansion::Command(command) = expansion {
+            found.borrow_mut().push(command);
+        }
+        Ok(Vec::new())
+    };
+    scan(word, Some(&lookup), &mut Fields::default())?;
+    Ok(found.into_inner())
+}
+
+/// The arguments `word` expands to: none, one, or several when a value is
+/// split or a glob matches more than one file
+///
+/// Its substitutions take their output from the front of `outputs`.
+fn expand_word(
+    word: &str,
+    context: &Context,
+    outputs: &RefCell<VecDeque<String>>,
+) -> std::result::Result<Vec<String>, Unexpanded> {
+    let mut fields = Fields::default();
+    scan_word(word, context, outputs, &mut fields)?;
+    Ok(fields
+        .finish()
+        .into_iter()
+        .flat_map(|field| {
+            if field.glob && !context.settings.noglob {
+                field.matches(context.cwd, context.settings.globstar)
+            } else {
+                vec![field.text]
+            }
+        })
+        .collect())
+}
+
+/// The value `word` assigns to a variable: expanded like an argument, but
+/// kept as one word, without splitting or globs
+fn expand_value(
+    word: &str,
+    context: &Context,
+    outputs: &RefCell<VecDeque<String>>,
+) -> std::result::Result<String, Unexpanded> {
+    let mut fields = Fields::whole();
+    scan_word(word, context, outputs, &mut fields)?;
+    Ok(fields
+        .finish()
+        .into_iter()
+        .map(|field| field.text)
+        .collect())
+}
+
+/// Scan `word` into `fields`, expanding it in `context`
+fn scan_word(
+    word: &str,
+    context: &Context,
+    outputs: &RefCell<VecDeque<String>>,
+    fields: &mut Fields,
+) -> std::result::Result<(), Unexpanded> {
+    let lookup = |expansion| -> std::result::Result<Vec<String>, Unexpanded> {
+        let (name, values) = match expansion {
+            Expansion::Variable(name) => {
+                let value = context.variable(&name);
+                (name, value.map(|value| vec![value]))
+            }
+            Expansion::Parameter(name) => {
+                let values = context.parameters.values(&name);
+                (name, values)
+            }
+            Expansion::Command(_) => {
+                return Ok(vec![outputs.borrow_mut().pop_front().unwrap_or_default()])
+            }
+        };
+        match values {
+            Some(values) => Ok(values),
+            None if context.settings.nounset => Err(Unexpanded::Unset(name)),
+            None => Ok(vec![String::new()]),
+        }
+    };
+    // The directory is taken literally, as if quoted
+    let word = match tilde_prefix(word, context.options.env.as_ref()) {
+        Some((home, len)) => {
+            fields.push_value(&home, true);
+            &word[len..]
+        }
+        None => word,
+    };
+    scan(word, Some(&lookup), fields)
+}
+
+/// `text` with its variables and parameters expanded, as in a prompt like
+/// `PS4`: as if double-quoted, with unset variables empty, and what can't
+/// be expanded here, such as `$(...)`, kept as written
+pub(crate) fn expand_prompt(text: &str, context: &Context) -> String {
+    let mut expanded = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+        let before = chars.clone();
+        match expansion(&mut chars) {
+            Ok(Some(Expansion::Variable(name))) => {
+                expanded.push_str(&context.variable(&name).unwrap_or_default())
+            }
+            Ok(Some(Expansion::Parameter(name))) => {
+                let values = context.parameters.values(&name).unwrap_or_default();
+                expanded.push_str(&values.join(" "));
+            }
+            _ => {
+                let len = before.clone().count() - chars.clone().count();
+                expanded.push('$');
+                expanded.extend(before.take(len));
+            }
+        }
+    }
+    expanded
+}
+
+/// A virtual command ready to run
+#[derive(Debug)]
+pub(crate) struct VirtualCall {
+    pub name: String,
+    /// The arguments, expanded
+    pub args: Vec<String>,
+    /// The redirects, with their targets expanded
+    pub redirects: Vec<Redirect>,
+    /// The variables assigned in front of the command, with their values
+    /// expanded
+    pub env: Vec<(String, String)>,
+}
+
+impl VirtualCall {
+    /// `options` with the call's variables added to their environment
+    pub fn options(&self, options: &RunOptions) -> RunOptions {
+        let mut options = options.clone();
+        if !self.env.is_empty() {
+            let env = options.env.get_or_insert_with(Default::default);
+            env.extend(self.env.iter().cloned());
+        }
+        options
+    }
+}
+
+/// `command` as a [`VirtualCall`], if it is a simple command naming a
+/// virtual command whose words and redirect targets can all be expanded
+/// here
+///
+/// Substitutions run first, left to right. The values of the variables
+/// assigned in front of the command are expanded after its words, so those
+/// don't see them. Fails when a substitution does, or when a variable is
+/// unset while `nounset` is on.
+pub(crate) async fn virtual_command(
+    command: &str,
+    context: &Context<'_>,
+) -> Result<Option<VirtualCall>> {
+    call(command, BUILTIN_COMMANDS, context).await
+}
+
+/// `command` as a [`VirtualCall`] like [`virtual_command`], for a command
+/// named one of `names`
+pub(crate) async fn call(
+    command: &str,
+    names: &[&str],
+    context: &Context<'_>,
+) -> Result<Option<VirtualCall>> {
+    if needs_real_shell_except_expansions(command) {
+        return Ok(None);
+    }
+    let (assignments, name, args, mut redirects) = match parse_shell_command(command) {
+        Ok(ParsedCommand::Simple {
+            assignments,
+            cmd,
+            args,
+            redirects,
+        }) if names.contains(&cmd.as_str()) => (assignments, cmd, args, redirects),
+        _ => return Ok(None),
+    };
+    let words: Vec<String> = args.iter().map(ToString::to_string).collect();
+    let values: Vec<String> = assignments.iter().map(|a| a.value.to_string()).collect();
+    let targets = redirects.iter().map(|redirect| &redirect.target);
+    let mut outputs = VecDeque::new();
+    for word in words.iter().chain(targets).chain(&values) {
+        let Ok(commands) = substitutions(word) else {
+            return Ok(None);
+        };
+        for inner in commands {
+            outputs.push_back(context.substitute(&inner).await?);
+        }
+    }
+
+    let outputs = RefCell::new(outputs);
+    let expand = |word: &str| match expand_word(word, context, &outputs) {
+        Ok(words) => Ok(Some(words)),
+        Err(Unexpanded::Unsupported) => Ok(None),
+        Err(Unexpanded::Unset(name)) => Err(Error::unset_variable(command, name)),
+    };
+    let mut expanded = Vec::with_capacity(words.len());
+    for word in &words {
+        let Some(words) = expand(word)? else {
+            return Ok(None);
+        };
+        expanded.extend(words);
+    }
+    for redirect in redirects
+        .iter_mut()
+        .filter(|r| !r.redirect_type.is_duplication())
+    {
+        // A target must stay one word; the shell reports it otherwise
+        match expand(&redirect.target)?.as_deref() {
+            Some([target]) => redirect.target = target.clone(),
+            _ => return Ok(None),
+        }
+    }
+    let mut env = Vec::with_capacity(values.len());
+    for (assignment, value) in assignments.iter().zip(&values) {
+        match expand_value(value, context, &outputs) {
+            Ok(value) => env.push((assignment.name.clone(), value)),
+            Err(Unexpanded::Unsupported) => return Ok(None),
+            Err(Unexpanded::Unset(name)) => return Err(Error::unset_variable(command, name)),
+        }
+    }
+    Ok(Some(VirtualCall {
+        name,
+        args: expanded,
+        redirects,
+        env,
+    }))
+}
+
+/// The variables `command` sets, with their values expanded, if it is
+/// nothing but assignments, as in `NAME=world GREETING="hello $NAME"`
+///
+/// Each value is expanded before any of them is set, so `GREETING` sees
+/// the `NAME` from before the command.
+pub(crate) async fn assignments(
+    command: &str,
+    context: &Context<'_>,
+) -> Result<Option<Vec<(String, String)>>> {
+    if needs_real_shell_except_expansions(command) {
+        return Ok(None);
+    }
+    let words: Vec<String> = match parse_shell_command(command) {
+        Ok(ParsedCommand::Simple {
+            assignments,
+            cmd,
+            args,
+            redirects,
+        }) if assignments.is_empty() && redirects.is_empty() => std::iter::once(cmd)
+            .chain(args.iter().map(ToString::to_string))
+            .collect(),
+        _ => return Ok(None),
+    };
+    let mut assigned = Vec::with_capacity(words.len());
+    for word in &words {
+        match word.split_once('=') {
+            Some((name, value)) if is_assignment(word) => assigned.push((name, value)),
+            _ => return Ok(None),
+        }
+    }
+    let mut outputs = VecDeque::new();
+    for (_, value) in &assigned {
+        let Ok(commands) = substitutions(value) else {
+            return Ok(None);
+        };
+        for inner in commands {
+            outputs.push_back(context.substitute(&inner).await?);
+        }
+    }
+
+    let outputs = RefCell::new(outputs);
+    let mut variables = Vec::with_capacity(assigned.len());
+    for (name, value) in assigned {
+        match expand_value(value, context, &outputs) {
+            Ok(value) => variables.push((name.to_string(), value)),
+            Err(Unexpanded::Unsupported) => return Ok(None),
+            Err(Unexpanded::Unset(name)) => return Err(Error::unset_variable(command, name)),
+        }
+    }
+    Ok(Some(variables))
+}
+
+type Lookup<'a> = &'a dyn Fn(Expansion) -> std::result::Result<Vec<String>, Unexpanded>;
+
+/// Remove the quotes from `word`, expanding what its `$`s stand for with
+/// `lookup`, or taking them literally without one, and collect the result in
+/// `fields`
+fn scan(
+    word: &str,
+    lookup: Option<Lookup>,
+    fields: &mut Fields,
+) -> std::result::Result<(), Unexpanded> {
+    let mut chars = word.chars().peekable();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                fields.open_quote();
+            }
+            (Some(q), c) if c == q => {
+                quote = None;
+                fields.close_quote();
+            }
+            (Some('\''), c) => fields.push(c, true),
+            (_, '$') => match lookup {
+                Some(lookup) => match expansion(&mut chars)? {
+                    Some(expansion) => fields.push_values(&lookup(expansion)?, quote.is_some()),
+                    None => fields.push('$', true),
+                },
+                None => fields.push('$', true),
+            },
+            (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    fields.push(next, true);
+                }
+            }
+            (Some(_), '\\') => match chars.peek() {
+                Some(&next) if "$`\"\\\n".contains(next) => {
+                    chars.next();
+                    fields.push(next, true);
+                }
+                _ => fields.push('\\', true),
+            },
+            (Some(_), c) => fields.push(c, true),
+            (None, ' ' | '\t' | '\n') => fields.split(),
+            (None, c) => fields.push(c, false),
+        }
+    }
+    Ok(())
+}
+
+/// The expansion after a `$`, or `None` if the `$` is literal
+fn expansion(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+) -> std::result::Result<Option<Expansion>, Unexpanded> {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    match chars.peek() {
+        Some('(') => {
+            let rest: Vec<char> = std::iter::once('$').chain(chars.clone()).collect();
+            let len = substitution_len(&rest);
+            chars.nth(len - 2);
+            match &rest[..len] {
+                ['$', '(', '(', ..] => Err(Unexpanded::Unsupported),
+                ['$', '(', inner @ .., ')'] => Ok(Some(Expansion::Command(inner.iter().collect()))),
+                _ => Err(Unexpanded::Unsupported),
+            }
+        }
+        Some('{') => {
+            chars.next();
+            let (mut name, mut closed) = (String::new(), false);
+            for c in chars.by_ref() {
+                if c == '}' {
+                    closed = true;
+                    break;
+                }
+                name.push(c);
+            }
+            let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(is_name);
+            match (closed, valid) {
+                (true, true) => Ok(Some(Expansion::Variable(name))),
+                (true, false) if is_parameter(&name) => Ok(Some(Expansion::Parameter(name))),
+                _ => Err(Unexpanded::Unsupported),
+            }
+        }
+        Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek().filter(|&&c| is_name(c)) {
+                name.push(c);
+                chars.next();
+            }
+            Ok(Some(Expansion::Variable(name)))
+        }
+        // `$10` is `$1` followed by a 0, as in sh
+        Some(&c) if c.is_ascii_digit() || "?$#@*".contains(c) => {
+            chars.next();
+            Ok(Some(Expansion::Parameter(c.to_string())))
+        }
+        Some('!' | '-') => Err(Unexpanded::Unsupported),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_glob_matches_like_sh() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.txt", "a.txt", ".hidden.txt", "c.md", "*.txt"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let cwd = Some(dir.path());
+
+        assert_eq!(expand_glob("*.txt", cwd), ["*.txt", "a.txt", "b.txt"]);
+        assert_eq!(expand_glob("'*'.txt", cwd), ["*.txt"]);
+        assert_eq!(expand_glob("[ab].t?t", cwd), ["a.txt", "b.txt"]);
+        assert_eq!(expand_glob("*.rs", cwd), ["*.rs"]);
+    }
+
+    #[test]
+    fn test_expand_word_variables() {
+        let options = RunOptions::builder()
+            .env("WORDS", "a  b")
+            .env("EMPTY", "")
+            .parameters(Parameters {
+                status: 2,
+                ..Parameters::new("script", ["one", "two words"])
+            })
+            .build();
+        let cancel = CancellationToken::new();
+        let mut settings = ShellSettings::default();
+        let expand = |word: &str, settings: &ShellSettings| {
+            let context = Context {
+                options: &options,
+                cwd: None,
+                settings,
+                cancel: &cancel,
+                parameters: &options.parameters,
+            };
+            expand_word(word, &context, &RefCell::default())
+        };
+
+        assert_eq!(expand("$WORDS", &settings).unwrap(), ["a", "b"]);
+        assert_eq!(expand("\"$WORDS\"", &settings).unwrap(), ["a  b"]);
+        assert_eq!(expand("x${WORDS}y", &settings).unwrap(), ["xa", "by"]);
+        assert_eq!(expand("'$WORDS'", &settings).unwrap(), ["$WORDS"]);
+        assert_eq!(expand("$EMPTY", &settings).unwrap(), Vec::<String>::new());
+        assert_eq!(expand("\"$EMPTY\"", &settings).unwrap(), [""]);
+        assert_eq!(expand("cost: $", &settings).unwrap(), ["cost:", "$"]);
+        assert_eq!(expand("$?", &settings).unwrap(), ["2"]);
+        assert_eq!(expand("$0:$#", &settings).unwrap(), ["script:2"]);
+        assert_eq!(expand("${2}", &settings).unwrap(), ["two", "words"]);
+        assert_eq!(expand("$10", &settings).unwrap(), ["one0"]);
+        assert_eq!(
+            expand("\"x$@y\"", &settings).unwrap(),
+            ["xone", "two wordsy"]
+        );
+        assert_eq!(expand("\"$*\"", &settings).unwrap(), ["one two words"]);
+        assert_eq!(expand("$!", &settings), Err(Unexpanded::Unsupported));
+        assert_eq!(
+            expand("${WORDS:-x}", &settings),
+            Err(Unexpanded::Unsupported)
+        );
+
+        assert_eq!(
+            expand("$UNSET_IN_TEST", &settings).unwrap(),
+            Vec::<String>::new()
+        );
+        settings.nounset = true;
+        assert_eq!(
+            expand("$UNSET_IN_TEST", &settings),
+            Err(Unexpanded::Unset("UNSET_IN_TEST".to_string()))
+        );
+        assert_eq!(
+            expand("$3", &settings),
+            Err(Unexpanded::Unset("3".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_quoted_at_without_parameters_is_no_word() {
+        let options = RunOptions::default();
+        let cancel = CancellationToken::new();
+        let context = Context {
+            options: &options,
+            cwd: None,
+            settings: &ShellSettings::default(),
+            cancel: &cancel,
+            parameters: &options.parameters,
+        };
+        let expand = |word: &str| expand_word(word, &context, &RefCell::default()).unwrap();
+        assert_eq!(expand("\"$@\""), Vec::<String>::new());
+        assert_eq!(expand("\"$@\"x"), ["x"]);
+        assert_eq!(expand("\"\"\"$@\""), [""]);
+    }
+
+    #[test]
+    fn test_tilde_prefix_is_a_home_directory() {
+        let env = HashMap::from([
+            ("HOME".to_string(), "/home/me".to_string()),
+            ("OLDPWD".to_string(), "/before".to_string()),
+        ]);
+        let tilde = |word| tilde_prefix(word, Some(&env));
+        assert_eq!(tilde("~"), Some(("/home/me".to_string(), 1)));
+        assert_eq!(tilde("~/notes.txt"), Some(("/home/me".to_string(), 1)));
+        assert_eq!(tilde("~-/x"), Some(("/before".to_string(), 2)));
+        assert_eq!(tilde("~no_such_user_here/x"), None);
+        assert_eq!(tilde("~'quoted'"), None);
+        assert_eq!(tilde("a~"), None);
+    }
+
+    #[test]
+    fn test_substitutions_are_found_in_order() {
+        assert_eq!(
+            substitutions(r#"a$(echo "x)")b"$(pwd)""#).unwrap(),
+            [r#"echo "x)""#, "pwd"]
+        );
+        assert_eq!(substitutions("'$(pwd)'").unwrap(), Vec::<String>::new());
+        assert_eq!(substitutions("$((1 + 2))"), Err(Unexpanded::Unsupported));
+    }
+}
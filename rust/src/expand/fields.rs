@@ -0,0 +1,214 @@
+//! The words, or fields, a word expands to: where values split it, which
+//! of its characters are quoted, and the files its globs match
+
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+
+/// The words a scanned word splits into
+#[derive(Default)]
+pub(super) struct Fields {
+    done: Vec<Field>,
+    /// The word being built; quotes start one even if nothing is in them
+    current: Option<Field>,
+    /// Whether the open quotes hold a `"$@"` with no positional parameters
+    empty_at: bool,
+    /// Whether this is one word whatever it holds, as a value assigned to a
+    /// variable is: values are joined with spaces rather than split
+    whole: bool,
+}
+
+#[derive(Default)]
+pub(super) struct Field {
+    pub(super) text: String,
+    pattern: String,
+    pub(super) glob: bool,
+    /// Stands for no word at all, as when it is only a quoted `"$@"`
+    /// without positional parameters
+    vanishes: bool,
+}
+
+impl Fields {
+    /// Fields for a word that stays one word, as a value assigned is
+    pub(super) fn whole() -> Self {
+        Fields {
+            whole: true,
+            ..Fields::default()
+        }
+    }
+
+    pub(super) fn start(&mut self) {
+        self.current.get_or_insert_with(Field::default);
+    }
+
+    /// Start a quoted part; a word that starts with it vanishes if all
+    /// its quotes hold is an empty `"$@"`
+    pub(super) fn open_quote(&mut self) {
+        let fresh = self.current.is_none();
+        let field = self.current.get_or_insert_with(Field::default);
+        field.vanishes |= fresh;
+        self.empty_at = false;
+    }
+
+    pub(super) fn close_quote(&mut self) {
+        if let Some(field) = self.current.as_mut().filter(|_| !self.empty_at) {
+            field.vanishes = false;
+        }
+    }
+
+    /// Add a character that was (or wasn't) quoted
+    pub(super) fn push(&mut self, c: char, literal: bool) {
+        let field = self.current.get_or_insert_with(Field::default);
+        field.vanishes = false;
+        field.text.push(c);
+        if literal && is_glob_char(c) {
+            field.pattern.push_str(&Pattern::escape(&c.to_string()));
+        } else {
+            field.pattern.push(c);
+            field.glob |= !literal && is_glob_char(c);
+        }
+    }
+
+    /// Add the values of an expansion, each after the first in a word of
+    /// its own
+    pub(super) fn push_values(&mut self, values: &[String], quoted: bool) {
+        self.empty_at |= values.is_empty() && quoted;
+        for (i, value) in values.iter().enumerate() {
+            match i {
+                0 => {}
+                _ if self.whole => self.push(' ', true),
+                _ => self.split(),
+            }
+            self.push_value(value, quoted);
+        }
+    }
+
+    /// Add a variable's value, split into words unless `quoted`
+    pub(super) fn push_value(&mut self, value: &str, quoted: bool) {
+        if quoted {
+            self.start();
+        }
+        for c in value.chars() {
+            match c {
+                ' ' | '\t' | '\n' if !quoted && !self.whole => self.split(),
+                c => self.push(c, quoted),
+            }
+        }
+    }
+
+    pub(super) fn split(&mut self) {
+        self.done
+            .extend(self.current.take().filter(|field| !field.vanishes));
+    }
+
+    pub(super) fn finish(mut self) -> Vec<Field> {
+        self.split();
+        self.done
+    }
+}
+
+impl Field {
+    /// The paths the field's pattern matches, or its text if none; with
+    /// `globstar` on, a `**` path component matches any number of
+    /// directories
+    pub(super) fn matches(self, cwd: Option<&Path>, globstar: bool) -> Vec<String> {
+        let base = cwd.filter(|_| !Path::new(&self.text).is_absolute());
+        let full = |pattern: &str| match base {
+            Some(dir) => format!(
+                "{}/{}",
+                Pattern::escape(&dir.to_string_lossy()).trim_end_matches('/'),
+                pattern
+            ),
+            None => pattern.to_string(),
+        };
+        let options = MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: true,
+        };
+        let relative = |path: PathBuf| {
+            let path = match base {
+                Some(dir) => path
+                    .strip_prefix(dir)
+                    .map(Path::to_path_buf)
+                    .unwrap_or(path),
+                None => path,
+            };
+            path.to_string_lossy().into_owned()
+        };
+        let glob = |pattern: &str| {
+            glob::glob_with(&full(pattern), options)
+                .map(|paths| paths.flatten().collect::<Vec<_>>())
+                .ok()
+        };
+
+        let pattern = glob_pattern(&self.pattern, globstar);
+        let matches: Option<Vec<String>> = match pattern.strip_suffix("**") {
+            // `dir/**` also matches the files below `dir`, and `dir/` itself
+            Some(dir) if globstar && (dir.is_empty() || dir.ends_with('/')) => {
+                let parents = match dir.strip_suffix('/') {
+                    Some(parent) => glob(parent),
+                    None => Some(Vec::new()),
+                };
+                parents
+                    .zip(glob(&format!("{}**/*", dir)))
+                    .map(|(parents, below)| {
+                        let parents = parents
+                            .into_iter()
+                            .filter(|path| path.is_dir())
+                            .map(|path| relative(path) + "/");
+                        parents.chain(below.into_iter().map(relative)).collect()
+                    })
+            }
+            _ => glob(&pattern).map(|paths| paths.into_iter().map(relative).collect()),
+        };
+        match matches {
+            Some(matches) if !matches.is_empty() => matches,
+            _ => vec![self.text],
+        }
+    }
+}
+
+/// `pattern` with each run of `*`s made one `*`, or `**` where it is a
+/// whole path component and `globstar` is on, as the glob matcher takes
+/// them
+fn glob_pattern(pattern: &str, globstar: bool) -> String {
+    let mut result = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '*' {
+            result.push(c);
+            continue;
+        }
+        let mut run = 1;
+        while chars.next_if_eq(&'*').is_some() {
+            run += 1;
+        }
+        let component = (result.is_empty() || result.ends_with('/'))
+            && matches!(chars.peek(), None | Some('/'));
+        result.push_str(if globstar && run > 1 && component {
+            "**"
+        } else {
+            "*"
+        });
+    }
+    result
+}
+
+pub(super) fn is_glob_char(c: char) -> bool {
+    matches!(c, '*' | '?' | '[')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_pattern_keeps_globstar_to_whole_components() {
+        assert_eq!(glob_pattern("src/**/*.rs", true), "src/**/*.rs");
+        assert_eq!(glob_pattern("src/**/*.rs", false), "src/*/*.rs");
+        assert_eq!(glob_pattern("***", true), "**");
+        assert_eq!(glob_pattern("a**/b**", true), "a*/b*");
+        assert_eq!(glob_pattern("[*]**", true), "[*]*");
+    }
+}
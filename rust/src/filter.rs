@@ -0,0 +1,142 @@
+//! Output filters: transforming command output before anything sees it
+//!
+//! Filters added with
+//! [`RunOptionsBuilder::output_filter`](crate::RunOptionsBuilder::output_filter)
+//! run, in order, on every line a command writes, before the line is
+//! captured, mirrored, emitted as an event or sent to a sink. Each filter
+//! returns the line to pass on, possibly rewritten, or `None` to drop it:
+//!
+//! ```rust,no_run
+//! use command_stream::filter::{MaskSecrets, StripAnsi};
+//! use command_stream::{ProcessRunner, RunOptions, StreamKind};
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let options = RunOptions::builder()
+//!     .output_filter(StripAnsi)
+//!     .output_filter(MaskSecrets)
+//!     .output_filter(|stream: StreamKind, line: String| {
+//!         (!line.starts_with("debug:")).then(|| match stream {
+//!             StreamKind::Stdout => line,
+//!             StreamKind::Stderr => format!("[stderr] {}", line),
+//!         })
+//!     })
+//!     .build();
+//! let result = ProcessRunner::new("./deploy.sh", options).run().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Filters see lines without their line ending. Output a virtual command
+//! writes without a trailing newline is filtered as it is written.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::ansi::AnsiUtils;
+use crate::redact::redact;
+use crate::StreamKind;
+
+/// A step in the output filter chain
+///
+/// Any `Fn(StreamKind, String) -> Option<String>` closure is a filter.
+pub trait OutputFilter: Send + Sync {
+    /// `line`, read from `stream`, as it should be passed on, or `None` to
+    /// drop it
+    fn filter(&self, stream: StreamKind, line: String) -> Option<String>;
+}
+
+impl<F> OutputFilter for F
+where
+    F: Fn(StreamKind, String) -> Option<String> + Send + Sync,
+{
+    fn filter(&self, stream: StreamKind, line: String) -> Option<String> {
+        self(stream, line)
+    }
+}
+
+impl fmt::Debug for dyn OutputFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OutputFilter")
+    }
+}
+
+/// Removes ANSI escape sequences, such as colors
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StripAnsi;
+
+impl OutputFilter for StripAnsi {
+    fn filter(&self, _stream: StreamKind, line: String) -> Option<String> {
+        Some(AnsiUtils::strip_ansi(&line))
+    }
+}
+
+/// Replaces secrets with `***`, using the rules commands are
+/// [redacted](crate::redact) with
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MaskSecrets;
+
+impl OutputFilter for MaskSecrets {
+    fn filter(&self, _stream: StreamKind, line: String) -> Option<String> {
+        Some(redact(&line).into_owned())
+    }
+}
+
+/// Pass `line` through every filter, stopping at the first that drops it
+pub(crate) fn apply(
+    filters: &[Arc<dyn OutputFilter>],
+    stream: StreamKind,
+    line: String,
+) -> Option<String> {
+    filters
+        .iter()
+        .try_fold(line, |line, filter| filter.filter(stream, line))
+}
+
+/// Pass each line of `text` through the filters, keeping the line endings
+/// of the lines that are kept
+pub(crate) fn apply_text(
+    filters: &[Arc<dyn OutputFilter>],
+    stream: StreamKind,
+    text: String,
+) -> String {
+    if filters.is_empty() {
+        return text;
+    }
+    let mut filtered = String::with_capacity(text.len());
+    for segment in text.split_inclusive('\n') {
+        let line = segment.trim_end_matches(['\n', '\r']);
+        let ending = &segment[line.len()..];
+        if let Some(line) = apply(filters, stream, line.to_string()) {
+            filtered.push_str(&line);
+            filtered.push_str(ending);
+        }
+    }
+    filtered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_text_filters_each_line() {
+        let drop_blank = |_: StreamKind, line: String| (!line.is_empty()).then_some(line);
+        let shout = |_: StreamKind, line: String| Some(line.to_uppercase());
+        let filters: Vec<Arc<dyn OutputFilter>> =
+            vec![Arc::new(StripAnsi), Arc::new(drop_blank), Arc::new(shout)];
+
+        assert_eq!(
+            apply_text(
+                &filters,
+                StreamKind::Stdout,
+                "\x1b[1mone\x1b[0m\r\n\ntwo".to_string()
+            ),
+            "ONE\r\nTWO"
+        );
+        assert_eq!(apply(&filters, StreamKind::Stderr, String::new()), None);
+        assert_eq!(
+            apply_text(&[], StreamKind::Stdout, "as is\n".to_string()),
+            "as is\n"
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Helpers for common git operations
+//!
+//! Each helper builds its git command from quoted arguments, so URLs, paths
+//! and revisions can't inject options or shell syntax, runs it without
+//! mirroring and in the [`STABLE_LOCALE`](crate::STABLE_LOCALE), and turns
+//! the output into typed values:
+//!
+//! ```rust,no_run
+//! use command_stream::git;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! git::clone("https://github.com/link-foundation/command-stream", "/tmp/cs")
+//!     .depth(1)
+//!     .on_progress(|line| eprintln!("{}", line))
+//!     .await?;
+//!
+//! let status = git::status("/tmp/cs").await?;
+//! assert!(status.is_clean());
+//! let head = git::Git::in_dir("/tmp/cs").rev_parse("HEAD").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A failing git command returns [`Error::CommandFailed`] with git's
+//! message in its stderr tail.
+
+use std::future::{Future, IntoFuture};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use crate::parsers::GitStatus;
+use crate::quote::quote_args;
+use crate::{
+    CommandResult, Error, OutputChunk, ProcessRunner, Result, RunOptions, StreamingRunner,
+};
+
+/// Runs git commands in one repository
+#[derive(Debug, Clone)]
+pub struct Git {
+    options: RunOptions,
+}
+
+impl Default for Git {
+    fn default() -> Self {
+        Git::new()
+    }
+}
+
+impl Git {
+    /// Git in the current directory
+    pub fn new() -> Self {
+        Git::with_options(RunOptions::from_env())
+    }
+
+    /// Git in `dir`
+    pub fn in_dir(dir: impl Into<PathBuf>) -> Self {
+        let mut git = Git::new();
+        git.options.cwd = Some(dir.into());
+        git
+    }
+
+    /// Git with `options`, apart from mirroring, locale and prompting, which
+    /// the helpers control
+    pub fn with_options(options: RunOptions) -> Self {
+        let mut options = options.quiet().stable_locale();
+        options
+            .env
+            .get_or_insert_with(Default::default)
+            .insert("GIT_TERMINAL_PROMPT".to_string(), "0".to_string());
+        Git { options }
+    }
+
+    /// The options git commands run with
+    pub fn options(&self) -> &RunOptions {
+        &self.options
+    }
+
+    /// Run `git` with `args`, failing unless it exits with 0
+    pub async fn run<I>(&self, args: I) -> Result<CommandResult>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let command = git_command(args);
+        let result = ProcessRunner::new(&command, self.options.clone())
+            .run()
+            .await?;
+        if !result.is_success() {
+            return Err(Error::command_failed(
+                command,
+                result.code,
+                &result.stderr,
+                result.signal,
+            ));
+        }
+        Ok(result)
+    }
+
+    /// The working tree status, including the branch and its upstream
+    pub async fn status(&self) -> Result<GitStatus> {
+        self.run(["status", "--porcelain", "--branch"])
+            .await?
+            .parse()
+    }
+
+    /// The full commit id `rev` names, e.g. `HEAD` or `v1.0^`
+    pub async fn rev_parse(&self, rev: &str) -> Result<String> {
+        let result = self
+            .run(["rev-parse", "--verify", "--end-of-options", rev])
+            .await?;
+        Ok(result.stdout.trim().to_string())
+    }
+
+    /// The checked out branch, or `None` when HEAD is detached
+    pub async fn current_branch(&self) -> Result<Option<String>> {
+        Ok(self.status().await?.branch)
+    }
+}
+
+/// The status of the working tree in `dir`
+pub async fn status(dir: impl AsRef<Path>) -> Result<GitStatus> {
+    Git::in_dir(dir.as_ref()).status().await
+}
+
+/// The full commit id `rev` names in the current directory's repository
+pub async fn rev_parse(rev: &str) -> Result<String> {
+    Git::new().rev_parse(rev).await
+}
+
+/// Clone `url` into `dest`; await the returned builder to run it
+pub fn clone(url: impl Into<String>, dest: impl Into<PathBuf>) -> GitClone {
+    GitClone {
+        url: url.into(),
+        dest: dest.into(),
+        depth: None,
+        branch: None,
+        git: Git::new(),
+        on_progress: None,
+    }
+}
+
+/// Progress callback of a [`GitClone`]
+type ProgressFn = Box<dyn FnMut(&str) + Send>;
+
+/// A `git clone` being configured, created by [`clone`]
+pub struct GitClone {
+    url: String,
+    dest: PathBuf,
+    depth: Option<u32>,
+    branch: Option<String>,
+    git: Git,
+    on_progress: Option<ProgressFn>,
+}
+
+impl GitClone {
+    /// Fetch only the last `depth` commits
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Check out `branch` (or tag) instead of the remote's default branch
+    pub fn branch(mut self, branch: impl Into<String>) -> Self {
+        self.branch = Some(branch.into());
+        self
+    }
+
+    /// Run git with these options, e.g. a working directory for a relative
+    /// `dest`
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.git = Git::with_options(options);
+        self
+    }
+
+    /// Call `callback` with each progress line git reports, such as
+    /// `Receiving objects:  45% (450/1000)`
+    pub fn on_progress(mut self, callback: impl FnMut(&str) + Send + 'static) -> Self {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Run the clone
+    pub async fn run(mut self) -> Result<()> {
+        let mut args = vec!["clone".to_string(), "--progress".to_string()];
+        if let Some(depth) = self.depth {
+            args.push(format!("--depth={}", depth));
+        }
+        if let Some(branch) = &self.branch {
+            args.push(format!("--branch={}", branch));
+        }
+        args.push("--".to_string());
+        args.push(self.url.clone());
+        args.push(self.dest.to_string_lossy().into_owned());
+        let command = git_command(&args);
+
+        // Progress is written to stderr, updated in place with `\r`
+        let mut stream = StreamingRunner::new(&command)
+            .with_options(self.git.options())
+            .stream();
+        let mut stderr = String::new();
+        let mut pending = String::new();
+        let mut code = -1;
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                OutputChunk::Stderr(data) => {
+                    let text = String::from_utf8_lossy(&data);
+                    stderr.push_str(&text);
+                    pending.push_str(&text);
+                    while let Some(end) = pending.find(['\r', '\n']) {
+                        let line: String = pending.drain(..=end).collect();
+                        let line = line.trim_end();
+                        match &mut self.on_progress {
+                            Some(callback) if !line.is_empty() => callback(line),
+                            _ => {}
+                        }
+                    }
+                }
+                OutputChunk::Stdout(_) => {}
+                OutputChunk::Exit(exit_code) => code = exit_code,
+            }
+        }
+
+        if code != 0 {
+            return Err(Error::command_failed(command, code, &stderr, None));
+        }
+        Ok(())
+    }
+}
+
+impl IntoFuture for GitClone {
+    type Output = Result<()>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
+fn git_command<I>(args: I) -> String
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    format!("git {}", quote_args(args))
+}
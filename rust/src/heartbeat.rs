@@ -0,0 +1,127 @@
+//! Watchdog for long-running commands that must show signs of life
+//!
+//! A [`Heartbeat`] set on [`RunOptions::heartbeat`](crate::RunOptions::heartbeat)
+//! expects the command to write output, or the caller to call
+//! [`beat`](Heartbeat::beat), at least once per interval. When neither
+//! happens the command is stalled: the runner emits
+//! [`EventType::Stalled`](crate::EventType::Stalled) and then applies the
+//! heartbeat's [`StallAction`].
+//!
+//! ```rust,no_run
+//! use command_stream::heartbeat::{Heartbeat, StallAction};
+//! use command_stream::{ProcessRunner, RunOptions};
+//! use std::time::Duration;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! // Restart the tunnel up to 3 times when it goes quiet for 30 seconds
+//! let heartbeat = Heartbeat::new(Duration::from_secs(30)).on_stall(StallAction::Restart(3));
+//! let options = RunOptions::builder().heartbeat(heartbeat).build();
+//! ProcessRunner::new("ssh -N -v tunnel-host", options).run().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The watchdog covers spawned processes; virtual commands run in-process
+//! and are not watched.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// What to do with a stalled command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallAction {
+    /// Only emit the event, then keep watching
+    Notify,
+    /// Kill the command and fail with [`Error::Stalled`](crate::Error::Stalled)
+    Kill,
+    /// Kill the command and start it again, at most this many times; the
+    /// stall after that kills it
+    Restart(usize),
+}
+
+/// Requires a sign of life at least every interval; see the
+/// [module docs](self)
+///
+/// Clones share the time of the last beat, so a clone kept by the caller
+/// can [`beat`](Heartbeat::beat) for the command. Use one heartbeat per
+/// command.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    interval: Duration,
+    action: StallAction,
+    last_beat: Arc<Mutex<Instant>>,
+}
+
+impl Heartbeat {
+    /// Expect a sign of life every `interval`, only notifying on stalls
+    pub fn new(interval: Duration) -> Self {
+        Heartbeat {
+            interval,
+            action: StallAction::Notify,
+            last_beat: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// What to do when the command stalls
+    pub fn on_stall(mut self, action: StallAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// How long the command may stay quiet
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// What happens when it stays quiet longer
+    pub fn action(&self) -> StallAction {
+        self.action
+    }
+
+    /// Record a sign of life; output from the command counts as one
+    pub fn beat(&self) {
+        *self.last_beat.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    /// Time since the last sign of life
+    pub fn idle(&self) -> Duration {
+        self.last_beat
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .elapsed()
+    }
+
+    /// Wait until the command has been quiet for a whole interval and
+    /// return how long that was
+    pub(crate) async fn stalled(&self) -> Duration {
+        loop {
+            let idle = self.idle();
+            if idle >= self.interval {
+                return idle;
+            }
+            tokio::time::sleep(self.interval - idle).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_beats_postpone_the_stall() {
+        let heartbeat = Heartbeat::new(Duration::from_millis(60));
+        let beater = heartbeat.clone();
+        let started = Instant::now();
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                beater.beat();
+            }
+        });
+
+        let idle = heartbeat.stalled().await;
+        assert!(idle >= Duration::from_millis(60));
+        assert!(started.elapsed() >= Duration::from_millis(150));
+    }
+}
@@ -0,0 +1,283 @@
+//! Command execution history
+//!
+//! When enabled with [`enable_history`], every command run through a
+//! [`ProcessRunner`](crate::ProcessRunner) is recorded with its timing and a
+//! summary of its result. The history is bounded: once it holds `capacity`
+//! entries, the oldest is dropped for each new one. It is off by default.
+//!
+//! ```rust,no_run
+//! use command_stream::{history, run, HistoryFilter};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     history::enable_history(100);
+//!     let _ = run("make build").await;
+//!
+//!     for entry in history::history_filtered(&HistoryFilter::new().failed()) {
+//!         eprintln!("{} failed with {:?}", entry.command, entry.code);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use crate::redact::redact;
+use crate::state::global_state;
+use crate::{CommandResult, Error};
+
+/// One executed command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Sequence number, starting at 1 for the first recorded command
+    pub id: u64,
+    /// The command string, [redacted](crate::redact)
+    pub command: String,
+    /// Working directory the command ran in, if set explicitly
+    pub cwd: Option<PathBuf>,
+    /// When the command started
+    pub started_at: DateTime<Utc>,
+    /// When the command finished
+    pub finished_at: DateTime<Utc>,
+    /// Exit code, when the command ran to completion
+    pub code: Option<i32>,
+    /// Terminating signal, if any (Unix)
+    pub signal: Option<i32>,
+    /// Size of the captured stdout in bytes
+    pub stdout_len: usize,
+    /// Size of the captured stderr in bytes
+    pub stderr_len: usize,
+    /// The error the command returned instead of a result
+    pub error: Option<String>,
+}
+
+impl HistoryEntry {
+    /// Whether the command completed with exit code 0
+    pub fn succeeded(&self) -> bool {
+        self.code == Some(0) && self.error.is_none()
+    }
+
+    /// How long the command took
+    pub fn duration(&self) -> chrono::Duration {
+        self.finished_at - self.started_at
+    }
+}
+
+/// Criteria for selecting history entries; all set criteria must match
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    contains: Option<String>,
+    failed_only: bool,
+    since: Option<DateTime<Utc>>,
+    last: Option<usize>,
+}
+
+impl HistoryFilter {
+    /// A filter that matches every entry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only commands whose text contains `text`
+    pub fn contains(mut self, text: impl Into<String>) -> Self {
+        self.contains = Some(text.into());
+        self
+    }
+
+    /// Only commands that failed or returned an error
+    pub fn failed(mut self) -> Self {
+        self.failed_only = true;
+        self
+    }
+
+    /// Only commands started at or after `time`
+    pub fn since(mut self, time: DateTime<Utc>) -> Self {
+        self.since = Some(time);
+        self
+    }
+
+    /// At most the `count` most recent matching entries
+    pub fn last(mut self, count: usize) -> Self {
+        self.last = Some(count);
+        self
+    }
+
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.contains
+            .as_ref()
+            .is_none_or(|text| entry.command.contains(text.as_str()))
+            && (!self.failed_only || !entry.succeeded())
+            && self.since.is_none_or(|since| entry.started_at >= since)
+    }
+}
+
+/// Bounded store of history entries, kept in [`GlobalState`](crate::GlobalState)
+#[derive(Debug, Default)]
+pub(crate) struct History {
+    capacity: usize,
+    next_id: u64,
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl History {
+    fn push(&mut self, mut entry: HistoryEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.next_id += 1;
+        entry.id = self.next_id;
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    fn select(&self, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+        let mut selected: Vec<HistoryEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .cloned()
+            .collect();
+        if let Some(last) = filter.last {
+            selected.drain(..selected.len().saturating_sub(last));
+        }
+        selected
+    }
+}
+
+/// Start recording commands, keeping at most `capacity` entries
+///
+/// Calling it again changes the capacity and keeps the newest entries.
+pub fn enable_history(capacity: usize) {
+    global_state().with_history(|history| history.set_capacity(capacity));
+}
+
+/// Stop recording commands and discard the history
+pub fn disable_history() {
+    global_state().with_history(|history| {
+        history.set_capacity(0);
+    });
+}
+
+/// Whether commands are being recorded
+pub fn is_history_enabled() -> bool {
+    global_state().with_history(|history| history.capacity > 0)
+}
+
+/// All recorded commands, oldest first
+pub fn history() -> Vec<HistoryEntry> {
+    history_filtered(&HistoryFilter::new())
+}
+
+/// The recorded commands matching `filter`, oldest first
+pub fn history_filtered(filter: &HistoryFilter) -> Vec<HistoryEntry> {
+    global_state().with_history(|history| history.select(filter))
+}
+
+/// Remove all recorded commands, keeping recording enabled
+pub fn clear_history() {
+    global_state().with_history(|history| history.entries.clear());
+}
+
+/// Record a finished command if history is enabled
+pub(crate) fn record(
+    command: &str,
+    cwd: Option<&PathBuf>,
+    started_at: DateTime<Utc>,
+    outcome: &Result<CommandResult, Error>,
+) {
+    let state = global_state();
+    if !state.with_history(|history| history.capacity > 0) {
+        return;
+    }
+
+    let mut entry = HistoryEntry {
+        id: 0,
+        command: redact(command).into_owned(),
+        cwd: cwd.cloned(),
+        started_at,
+        finished_at: Utc::now(),
+        code: None,
+        signal: None,
+        stdout_len: 0,
+        stderr_len: 0,
+        error: None,
+    };
+    match outcome {
+        Ok(result) => {
+            entry.code = Some(result.code);
+            entry.signal = result.signal;
+            entry.stdout_len = result.stdout.len();
+            entry.stderr_len = result.stderr.len();
+        }
+        Err(err) => {
+            entry.code = err.exit_code();
+            entry.error = Some(err.to_string());
+        }
+    }
+    state.with_history(|history| history.push(entry));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str, code: i32) -> HistoryEntry {
+        HistoryEntry {
+            id: 0,
+            command: command.to_string(),
+            cwd: None,
+            started_at: Utc::now(),
+            finished_at: Utc::now(),
+            code: Some(code),
+            signal: None,
+            stdout_len: 0,
+            stderr_len: 0,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = History::default();
+        history.push(entry("ignored while disabled", 0));
+        assert!(history.entries.is_empty());
+
+        history.set_capacity(2);
+        for command in ["one", "two", "three"] {
+            history.push(entry(command, 0));
+        }
+        let commands: Vec<_> = history.entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["two", "three"]);
+        assert_eq!(history.entries.back().unwrap().id, 3);
+    }
+
+    #[test]
+    fn test_history_filters() {
+        let mut history = History::default();
+        history.set_capacity(10);
+        history.push(entry("cargo build", 0));
+        history.push(entry("cargo test", 101));
+        history.push(entry("git status", 0));
+        history.push(entry("git push", 1));
+
+        let failed = history.select(&HistoryFilter::new().failed());
+        assert_eq!(failed.len(), 2);
+
+        let git = history.select(&HistoryFilter::new().contains("git"));
+        assert_eq!(git.len(), 2);
+
+        let last = history.select(&HistoryFilter::new().contains("cargo").last(1));
+        assert_eq!(last[0].command, "cargo test");
+    }
+}
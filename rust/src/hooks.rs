@@ -0,0 +1,162 @@
+//! Hooks run before and after commands
+//!
+//! A hook added with [`before_exec`] gets each command's [`ExecSpec`] before
+//! it runs and returns the spec to run, so it can add environment
+//! variables, change the directory or rewrite the command; returning an
+//! error stops the command. A hook added with [`after_exec`] sees the spec
+//! and the outcome once the command is done, for metrics or logs:
+//!
+//! ```rust,no_run
+//! use command_stream::hooks::{after_exec, before_exec};
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! before_exec(|mut spec| async move {
+//!     if spec.command.starts_with("gh ") {
+//!         let env = spec.options.env.get_or_insert_with(Default::default);
+//!         env.insert("GH_TOKEN".into(), "...".into());
+//!     }
+//!     Ok(spec)
+//! });
+//! after_exec(|spec, outcome| {
+//!     let (command, ok) = (spec.command.clone(), outcome.is_ok());
+//!     async move { eprintln!("{} finished, ok: {}", command, ok) }
+//! });
+//!
+//! command_stream::run("gh pr list").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! These hooks apply to every command run with
+//! [`ProcessRunner::run`](crate::ProcessRunner::run), which is what
+//! [`run`](crate::run), [`cmd!`](crate::cmd) and sessions use, but not to
+//! the commands inside a compound command, which run as part of it. A
+//! [`Session`](crate::Session) has hooks of its own, run around these:
+//! the session's before hooks first and its after hooks last. Hooks run in
+//! the order they were added.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use once_cell::sync::Lazy;
+
+use crate::{CommandResult, Result, RunOptions};
+
+/// A command about to run, and the options it runs with
+#[derive(Debug, Clone)]
+pub struct ExecSpec {
+    pub command: String,
+    pub options: RunOptions,
+}
+
+type BeforeHook =
+    Arc<dyn Fn(ExecSpec) -> Pin<Box<dyn Future<Output = Result<ExecSpec>> + Send>> + Send + Sync>;
+
+type AfterHook = Arc<
+    dyn Fn(&ExecSpec, &Result<CommandResult>) -> Pin<Box<dyn Future<Output = ()> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// Hooks to run before and after commands
+#[derive(Clone, Default)]
+pub struct ExecHooks {
+    before: Vec<BeforeHook>,
+    after: Vec<AfterHook>,
+}
+
+impl fmt::Debug for ExecHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExecHooks")
+            .field("before", &self.before.len())
+            .field("after", &self.after.len())
+            .finish()
+    }
+}
+
+impl ExecHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a hook that gets each spec before it runs and returns the one
+    /// to run
+    pub fn before_exec<F, Fut>(&mut self, hook: F)
+    where
+        F: Fn(ExecSpec) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<ExecSpec>> + Send + 'static,
+    {
+        self.before.push(Arc::new(move |spec| Box::pin(hook(spec))));
+    }
+
+    /// Add a hook that sees each spec and its outcome after it ran
+    ///
+    /// The hook is called with borrows; the future it returns owns what it
+    /// needs of them.
+    pub fn after_exec<F, Fut>(&mut self, hook: F)
+    where
+        F: Fn(&ExecSpec, &Result<CommandResult>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.after
+            .push(Arc::new(move |spec, outcome| Box::pin(hook(spec, outcome))));
+    }
+
+    /// Whether there are no hooks
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
+    }
+
+    /// Pass `spec` through the before hooks, stopping at the first error
+    pub async fn run_before(&self, mut spec: ExecSpec) -> Result<ExecSpec> {
+        for hook in &self.before {
+            spec = hook(spec).await?;
+        }
+        Ok(spec)
+    }
+
+    /// Show `spec` and its `outcome` to the after hooks
+    pub async fn run_after(&self, spec: &ExecSpec, outcome: &Result<CommandResult>) {
+        for hook in &self.after {
+            hook(spec, outcome).await;
+        }
+    }
+}
+
+static HOOKS: Lazy<RwLock<ExecHooks>> = Lazy::new(|| RwLock::new(ExecHooks::new()));
+
+/// Run `hook` before every command; see the [module](self) docs
+pub fn before_exec<F, Fut>(hook: F)
+where
+    F: Fn(ExecSpec) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<ExecSpec>> + Send + 'static,
+{
+    HOOKS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .before_exec(hook);
+}
+
+/// Run `hook` after every command; see the [module](self) docs
+pub fn after_exec<F, Fut>(hook: F)
+where
+    F: Fn(&ExecSpec, &Result<CommandResult>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    HOOKS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .after_exec(hook);
+}
+
+/// Remove the hooks added with [`before_exec`] and [`after_exec`]
+pub fn clear_exec_hooks() {
+    *HOOKS.write().unwrap_or_else(|e| e.into_inner()) = ExecHooks::new();
+}
+
+/// The hooks added with [`before_exec`] and [`after_exec`]
+pub fn exec_hooks() -> ExecHooks {
+    HOOKS.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
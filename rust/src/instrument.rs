@@ -0,0 +1,111 @@
+//! Metrics for executed commands
+//!
+//! With the `metrics` feature, every command run by a
+//! [`ProcessRunner`](crate::ProcessRunner) is reported through the
+//! [`metrics`](https://docs.rs/metrics) facade. Install any recorder, such
+//! as `metrics-exporter-prometheus`, and these series appear:
+//!
+//! | Name | Kind | Labels |
+//! |------|------|--------|
+//! | [`COMMANDS_TOTAL`] | counter | |
+//! | [`FAILURES_TOTAL`] | counter | `reason`: `exit_code` or `error` |
+//! | [`DURATION_SECONDS`] | histogram | |
+//! | [`BYTES_STREAMED_TOTAL`] | counter | `stream`: `stdout` or `stderr` |
+//! | [`ACTIVE_CHILDREN`] | gauge | |
+//!
+//! Without the feature nothing is recorded and the facade is not a
+//! dependency.
+
+use std::time::Duration;
+
+use crate::{CommandResult, Result, StreamKind};
+
+/// Commands run
+pub const COMMANDS_TOTAL: &str = "command_stream_commands_total";
+/// Commands that exited with a non-zero code or returned an error
+pub const FAILURES_TOTAL: &str = "command_stream_command_failures_total";
+/// How long commands took, in seconds
+pub const DURATION_SECONDS: &str = "command_stream_command_duration_seconds";
+/// Bytes of output read from commands, line endings included
+pub const BYTES_STREAMED_TOTAL: &str = "command_stream_bytes_streamed_total";
+/// Child processes currently running
+pub const ACTIVE_CHILDREN: &str = "command_stream_active_children";
+
+/// Record a command that finished after `elapsed`
+#[cfg(feature = "metrics")]
+pub(crate) fn command_finished(elapsed: Duration, outcome: &Result<CommandResult>) {
+    metrics::counter!(COMMANDS_TOTAL).increment(1);
+    metrics::histogram!(DURATION_SECONDS).record(elapsed.as_secs_f64());
+    let reason = match outcome {
+        Ok(result) if result.is_success() => return,
+        Ok(_) => "exit_code",
+        Err(_) => "error",
+    };
+    metrics::counter!(FAILURES_TOTAL, "reason" => reason).increment(1);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn command_finished(_elapsed: Duration, _outcome: &Result<CommandResult>) {}
+
+/// Record `bytes` of output read from `stream`
+#[cfg(feature = "metrics")]
+pub(crate) fn bytes_streamed(stream: StreamKind, bytes: usize) {
+    let stream = match stream {
+        StreamKind::Stdout => "stdout",
+        StreamKind::Stderr => "stderr",
+    };
+    metrics::counter!(BYTES_STREAMED_TOTAL, "stream" => stream).increment(bytes as u64);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn bytes_streamed(_stream: StreamKind, _bytes: usize) {}
+
+/// Record a child process starting (`1`) or ending (`-1`)
+#[cfg(feature = "metrics")]
+pub(crate) fn active_children(change: i8) {
+    metrics::gauge!(ACTIVE_CHILDREN).increment(f64::from(change));
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn active_children(_change: i8) {}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    #[test]
+    fn test_records_through_the_facade() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            command_finished(Duration::from_millis(5), &Ok(CommandResult::success("")));
+            command_finished(Duration::from_millis(5), &Ok(CommandResult::error("")));
+            bytes_streamed(StreamKind::Stderr, 12);
+            active_children(1);
+        });
+
+        let values: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key.kind(), key.key().name().to_string(), value))
+            .collect();
+        assert!(values.contains(&(
+            MetricKind::Counter,
+            COMMANDS_TOTAL.to_string(),
+            DebugValue::Counter(2)
+        )));
+        assert!(values.contains(&(
+            MetricKind::Counter,
+            FAILURES_TOTAL.to_string(),
+            DebugValue::Counter(1)
+        )));
+        assert!(values.contains(&(
+            MetricKind::Counter,
+            BYTES_STREAMED_TOTAL.to_string(),
+            DebugValue::Counter(12)
+        )));
+    }
+}
@@ -0,0 +1,182 @@
+//! Commands running in the background
+//!
+//! A command followed by `&`, as in `sleep 10 & echo started`, starts as a
+//! job and the commands after it run right away. Jobs are numbered from 1,
+//! like a shell's `%1`, and stay in a global table until they are waited
+//! for: the `jobs` virtual command lists them and `wait` waits for them.
+//! From Rust, [`spawn_job`] runs any runner as a job, and [`wait_job`] and
+//! [`kill_job`] wait for or stop one:
+//!
+//! ```rust,no_run
+//! use command_stream::jobs;
+//! use command_stream::{ProcessRunner, RunOptions};
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let server = jobs::spawn_job(ProcessRunner::new("npm run dev", RunOptions::default()));
+//! let build = jobs::spawn_job(ProcessRunner::new("cargo build", RunOptions::default()));
+//!
+//! if let Some(outcome) = jobs::wait_job(build).await {
+//!     println!("build exited with {}", outcome?.code);
+//! }
+//! jobs::kill_job(server);
+//! let _ = jobs::wait_job(server).await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A job's output is mirrored while it runs, if its options say so, and
+//! captured in the result it is waited for with. It isn't part of the
+//! result of the command that started it.
+
+use std::collections::BTreeMap;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::state::global_state;
+use crate::{CancellationToken, CommandResult, Error, ProcessRunner, Result};
+
+/// Where a job is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JobStatus {
+    Running,
+    /// Finished, with this exit code
+    Done(i32),
+    /// Stopped by [`kill_job`] or by its cancellation token
+    Killed,
+}
+
+/// A job in the table, as [`list_jobs`] reports it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobInfo {
+    /// The job number, `N` in `%N`
+    pub id: usize,
+    pub command: String,
+    pub status: JobStatus,
+}
+
+struct Job {
+    command: String,
+    cancel: CancellationToken,
+    status: watch::Receiver<JobStatus>,
+    task: JoinHandle<Result<CommandResult>>,
+}
+
+/// The background jobs that haven't been waited for, by job number
+#[derive(Default)]
+pub(crate) struct JobTable {
+    jobs: BTreeMap<usize, Job>,
+}
+
+impl JobTable {
+    /// Kill every job and forget them
+    pub(crate) fn clear(&mut self) {
+        for job in self.jobs.values() {
+            job.cancel.cancel();
+        }
+        self.jobs.clear();
+    }
+}
+
+/// Run `runner` in the background, returning its job number
+///
+/// Like a shell, numbering continues after the highest job still in the
+/// table, so it starts again at 1 once every job has been waited for. Must
+/// be called from within a Tokio runtime.
+pub fn spawn_job(mut runner: ProcessRunner) -> usize {
+    let command = runner.command().to_string();
+    let cancel = runner.cancel_token();
+    let (status, receiver) = watch::channel(JobStatus::Running);
+    let task = tokio::spawn(async move {
+        let outcome = runner.run().await;
+        status.send_replace(match &outcome {
+            Ok(result) => JobStatus::Done(result.code),
+            Err(Error::Cancelled) => JobStatus::Killed,
+            Err(e) => JobStatus::Done(e.exit_code().unwrap_or(1)),
+        });
+        outcome
+    });
+    let job = Job {
+        command,
+        cancel,
+        status: receiver,
+        task,
+    };
+    global_state().with_jobs(|table| {
+        let id = table.jobs.keys().next_back().map_or(1, |last| last + 1);
+        table.jobs.insert(id, job);
+        id
+    })
+}
+
+/// The jobs that haven't been waited for, oldest first
+pub fn list_jobs() -> Vec<JobInfo> {
+    global_state().with_jobs(|table| {
+        table
+            .jobs
+            .iter()
+            .map(|(id, job)| JobInfo {
+                id: *id,
+                command: job.command.clone(),
+                status: *job.status.borrow(),
+            })
+            .collect()
+    })
+}
+
+/// Wait for job `id` to finish and remove it from the table, returning
+/// what its runner returned
+///
+/// Returns `None` if there is no such job, as when it was already waited
+/// for. Dropping the future before the job finishes leaves it in the table.
+pub async fn wait_job(id: usize) -> Option<Result<CommandResult>> {
+    let mut status = global_state().with_jobs(|table| Some(table.jobs.get(&id)?.status.clone()))?;
+    // Fails only if the task panicked, which awaiting it reports
+    let _ = status
+        .wait_for(|status| *status != JobStatus::Running)
+        .await;
+    let job = global_state().with_jobs(|table| table.jobs.remove(&id))?;
+    Some(
+        job.task
+            .await
+            .unwrap_or_else(|e| Err(Error::Io(std::io::Error::other(e)))),
+    )
+}
+
+/// Wait for every job in the table, returning their outcomes by job number
+pub async fn wait_all_jobs() -> Vec<(usize, Result<CommandResult>)> {
+    let ids: Vec<usize> = global_state().with_jobs(|table| table.jobs.keys().copied().collect());
+    let mut outcomes = Vec::with_capacity(ids.len());
+    for id in ids {
+        if let Some(outcome) = wait_job(id).await {
+            outcomes.push((id, outcome));
+        }
+    }
+    outcomes
+}
+
+/// Kill job `id`, returning whether it was still running
+///
+/// The job stays in the table, [`Killed`](JobStatus::Killed) once its
+/// process is gone, until it is waited for.
+pub fn kill_job(id: usize) -> bool {
+    global_state().with_jobs(|table| match table.jobs.get(&id) {
+        Some(job) if *job.status.borrow() == JobStatus::Running => {
+            job.cancel.cancel();
+            true
+        }
+        _ => false,
+    })
+}
+
+/// The job number a job spec like `%2` names: `%N` for job `N`, and `%%`
+/// or `%+` for the most recent job, `%-` for the one before it
+pub fn resolve_job_spec(spec: &str) -> Option<usize> {
+    let ids: Vec<usize> = global_state().with_jobs(|table| table.jobs.keys().copied().collect());
+    let from_end = |n: usize| ids.iter().rev().nth(n).copied();
+    match spec.strip_prefix('%')? {
+        "" | "%" | "+" => from_end(0),
+        "-" => from_end(1),
+        number => number.parse().ok().filter(|id| ids.contains(id)),
+    }
+}
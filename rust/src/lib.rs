@@ -21,17 +21,59 @@
 //!
 //! The codebase follows a modular architecture similar to the JavaScript implementation:
 //!
+//! - `aliases` - Aliases expanded where a command name goes
 //! - `ansi` - ANSI escape code handling utilities
+//! - `backend` - Running commands over SSH, in Docker containers or in WSL
+//! - `cache` - Cached results for idempotent commands
+//! - `color` - Whether commands color their output (`NO_COLOR`/`FORCE_COLOR`)
 //! - `commands` - Virtual command implementations
+//! - `completions` - Tab completion scripts for the CLI
+//! - `config` - Snapshots of the effective configuration and scoped overrides
+//! - `confirm` - Confirmation before destructive commands run
+//! - `console` - Rendering mirrored colors on the Windows console
+//! - `error` - Error type carrying the failed command's context
 //! - `events` - Event emitter for stream events
+//! - `expand` - In-process expansion of virtual command arguments
+//! - `filter` - Output filters that transform or drop lines before they are captured
+//! - `git` - Helpers for common git operations
+//! - `heartbeat` - Watchdog that flags commands that stop producing output
+//! - `history` - Optional record of executed commands
+//! - `hooks` - Hooks that change commands before they run and see their outcome
+//! - `instrument` - Metrics for executed commands (`metrics` feature)
+//! - `jobs` - Background jobs started with `&`, and waiting for them
+//! - `lock` - File locks for serializing work across processes
 //! - `macros` - The `cmd!` macro for ergonomic command creation
+//! - `options` - Run options and their environment defaults
+//! - `otel` - OpenTelemetry spans and trace context for commands (`otel` feature)
+//! - `parsers` - Typed parsers for the output of common commands
+//! - `paths` - Path translation between Unix, Windows and WSL
 //! - `pipeline` - Pipeline execution support
+//! - `policy` - Allow/deny rules checked before a command runs
+//! - `powershell` - PowerShell command parsing
+//! - `preflight` - Checks that required external commands are installed
 //! - `quote` - Shell quoting utilities
+//! - `redact` - Secret redaction for logged command strings
+//! - `replay` - Exporting recorded output as asciicast and replaying it
+//! - `runner` - The process runner behind every command
+//! - `script` - Running multi-line scripts statement by statement
+//! - `session` - Sessions owning cwd, variables, aliases, functions and settings
+//! - `sh` - Reusable shell handle with default options
+//! - `shell` - Shell detection shared by every runner
 //! - `shell_parser` - Shell command parsing
+//! - `shell_session` - Persistent shell process for low-latency repeated commands
+//! - `sink` - Forwarding command output line by line to channels and callbacks
 //! - `state` - Global state management
 //! - `stream` - Async streaming and iteration support
+//! - `supervisor` - Keeping a process running with restart policies
+//! - `tail` - Keeping only the last lines or bytes of long output
+//! - `temp` - Temporary files and directories, and cleanup of what `mktemp` creates
+//! - `testing` - Assertions and snapshots for testing command flows
 //! - `trace` - Logging and tracing utilities
+//! - `usage` - CPU time and peak memory of this process and its children
 //! - `utils` - Command results and virtual command helpers
+//! - `visit` - Visitors for inspecting and rewriting parsed commands
+//! - `wait` - Waiting for ports, files and output before moving on
+//! - `xtrace` - Prefix and destination of `set -x` traces
 //!
 //! ## Quick Start
 //!
@@ -62,43 +104,118 @@
 //! ```
 
 // Modular utility modules (following JavaScript modular pattern)
+pub mod aliases;
 pub mod ansi;
+pub mod backend;
+pub mod cache;
+pub mod color;
+pub mod completions;
+pub mod config;
+pub mod confirm;
+pub mod console;
+pub mod error;
 pub mod events;
+pub mod expand;
+pub mod filter;
+pub mod git;
+pub mod heartbeat;
+pub mod history;
+pub mod hooks;
+pub mod instrument;
+pub mod jobs;
+pub mod lock;
 #[doc(hidden)]
 pub mod macros;
+pub mod options;
+pub mod otel;
+pub mod parsers;
+pub mod paths;
 pub mod pipeline;
+pub mod policy;
+pub mod powershell;
+pub mod preflight;
 pub mod quote;
+pub mod redact;
+pub mod replay;
+pub mod runner;
+pub mod script;
+pub mod session;
+pub mod sh;
+pub mod shell;
+pub mod shell_session;
+pub mod sink;
 pub mod state;
 pub mod stream;
+pub mod supervisor;
+pub mod tail;
+pub mod temp;
+pub mod testing;
 pub mod trace;
+pub mod units;
+pub mod usage;
+pub mod visit;
+pub mod wait;
+pub mod xtrace;
 
 // Core modules
 pub mod commands;
 pub mod shell_parser;
 pub mod utils;
 
-use std::collections::HashMap;
 use std::path::PathBuf;
-use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, Command};
-use tokio::sync::mpsc;
-
-pub use commands::{CommandContext, StreamChunk};
-pub use shell_parser::{needs_real_shell, parse_shell_command, ParsedCommand};
-pub use utils::{CommandResult, VirtualUtils};
+use std::sync::Arc;
+pub use tokio_util::sync::CancellationToken;
+
+pub use commands::{CommandContext, CommandExecutor, StreamChunk};
+pub use confirm::ConfirmationGate;
+pub use error::{Error, Result};
+pub use shell_parser::{
+    check_syntax, literal_argv, needs_real_shell, parse_shell_command, Assignment, ParseError,
+    ParsedArg, ParsedCommand,
+};
+pub use utils::{CommandResult, ExitKind, OutputSnapshot, StreamKind, TimedLine, VirtualUtils};
 
 // Re-export modular utilities at crate root for convenient access
 pub use ansi::{AnsiConfig, AnsiUtils};
+pub use cache::{run_cached, CachePolicy};
+pub use color::ColorPolicy;
 pub use events::{EventData, EventType, StreamEmitter};
+pub use expand::Parameters;
+pub use history::{HistoryEntry, HistoryFilter};
+pub use jobs::{JobInfo, JobStatus};
+pub use options::{RunOptions, RunOptionsBuilder, StdinOption, STABLE_LOCALE};
+pub use parsers::FromOutput;
+pub use paths::{translate_path, PathStyle, PlatformPath};
 pub use pipeline::{Pipeline, PipelineBuilder, PipelineExt};
+pub use policy::{ExecutionPolicy, Violation};
 pub use quote::quote;
+pub use redact::{redact, Redactor};
+pub(crate) use runner::virtual_command;
+pub use runner::ProcessRunner;
+pub use script::{run_script, run_script_in};
+pub use session::Session;
+pub use sh::Sh;
+pub use shell::{find_shell, Shell, ShellChoice, ShellKind};
+pub use shell_session::{SessionShell, ShellSession};
 pub use state::{
-    get_shell_settings, global_state, reset_global_state, set_shell_option, unset_shell_option,
-    GlobalState, ShellSettings,
+    get_shell_settings, global_state, install_cleanup_handlers, list_active, reset_global_state,
+    set_shell_option, unset_shell_option, GlobalState, RunnerInfo, ShellSettings, TrackedChild,
 };
 pub use stream::{AsyncIterator, IntoStream, OutputChunk, OutputStream, StreamingRunner};
+pub use tail::TailLimit;
 pub use trace::trace;
+pub use units::{parse_duration, parse_size};
+
+/// Internals the benchmarks measure separately; not a stable API
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench {
+    /// How [`ProcessRunner`](crate::ProcessRunner) picks a virtual command:
+    /// its name and unquoted arguments, or `None`
+    pub fn virtual_command(command: &str) -> Option<(String, Vec<String>)> {
+        crate::runner::virtual_command(command, None)
+    }
+}
 
 /// Resolve a working directory that is safe to spawn a child process in.
 ///
@@ -143,405 +260,168 @@ fn resolve_spawn_cwd(cwd: Option<&PathBuf>) -> Option<PathBuf> {
     }
 }
 
-/// Error type for command-stream operations
-#[derive(Debug, thiserror::Error)]
-pub enum Error {
-    #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
-
-    #[error("Command failed with exit code {code}: {message}")]
-    CommandFailed { code: i32, message: String },
-
-    #[error("Command not found: {0}")]
-    CommandNotFound(String),
-
-    #[error("Parse error: {0}")]
-    ParseError(String),
-
-    #[error("Cancelled")]
-    Cancelled,
-}
-
-/// Result type for command-stream operations
-pub type Result<T> = std::result::Result<T, Error>;
-
-/// Options for command execution
-#[derive(Debug, Clone)]
-pub struct RunOptions {
-    /// Mirror output to parent stdout/stderr
-    pub mirror: bool,
-    /// Capture output in result
-    pub capture: bool,
-    /// Standard input handling
-    pub stdin: StdinOption,
-    /// Working directory
-    pub cwd: Option<PathBuf>,
-    /// Environment variables
-    pub env: Option<HashMap<String, String>>,
-    /// Interactive mode (TTY forwarding)
-    pub interactive: bool,
-    /// Enable shell operator parsing
-    pub shell_operators: bool,
-    /// Enable tracing for this command
-    pub trace: bool,
+/// Execute a command and return the result
+///
+/// This is the main entry point for simple command execution.
+/// Named `run` instead of `$` since `$` is not a valid Rust identifier.
+///
+/// Uses [`RunOptions::from_env`], so `COMMAND_STREAM_MIRROR` and
+/// `COMMAND_STREAM_CAPTURE` change its defaults.
+pub async fn run(command: impl Into<String>) -> Result<CommandResult> {
+    let mut runner = ProcessRunner::new(command, RunOptions::from_env());
+    runner.run().await
 }
 
-impl Default for RunOptions {
-    fn default() -> Self {
-        RunOptions {
-            mirror: true,
-            capture: true,
-            stdin: StdinOption::Inherit,
-            cwd: None,
-            env: None,
-            interactive: false,
-            shell_operators: true,
-            trace: true,
-        }
-    }
+/// Execute a command without mirroring its output, whatever the environment
+/// defaults say
+///
+/// The output is still captured in the result.
+pub async fn quiet(command: impl Into<String>) -> Result<CommandResult> {
+    exec(command, RunOptions::from_env().quiet()).await
 }
 
-/// Standard input options
-#[derive(Debug, Clone)]
-pub enum StdinOption {
-    /// Inherit from parent process
-    Inherit,
-    /// Pipe (allow writing to stdin)
-    Pipe,
-    /// Provide string content
-    Content(String),
-    /// Null device
-    Null,
+/// Execute a command mirroring its output, whatever the environment
+/// defaults say
+pub async fn loud(command: impl Into<String>) -> Result<CommandResult> {
+    exec(command, RunOptions::from_env().loud()).await
 }
 
-/// A running or completed process
-pub struct ProcessRunner {
-    command: String,
-    options: RunOptions,
-    child: Option<Child>,
-    result: Option<CommandResult>,
-    started: bool,
-    finished: bool,
-    cancelled: bool,
-    output_tx: Option<mpsc::Sender<StreamChunk>>,
-    output_rx: Option<mpsc::Receiver<StreamChunk>>,
+/// Execute a command string with the shell, exactly as written
+///
+/// Unlike [`run`], nothing is parsed or handled in-process: virtual commands
+/// and direct execution are skipped, so every shell feature (functions,
+/// `eval`, process substitution, shell-specific builtins) is available.
+///
+/// **The command is shell code.** Never build it from untrusted input; a
+/// value such as `x; rm -rf ~` runs as written. Use [`cmd!`] or
+/// [`quote`](crate::quote()) to pass values as arguments instead.
+///
+/// ```rust,no_run
+/// # async fn example() -> command_stream::Result<()> {
+/// let result = command_stream::run_raw_shell("diff <(sort a.txt) <(sort b.txt)").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn run_raw_shell(command: impl Into<String>) -> Result<CommandResult> {
+    let mut options = RunOptions::from_env();
+    options.raw_shell = true;
+    exec(command, options).await
 }
 
-impl ProcessRunner {
-    /// Create a new process runner
-    pub fn new(command: impl Into<String>, options: RunOptions) -> Self {
-        let (tx, rx) = mpsc::channel(1024);
-        ProcessRunner {
-            command: command.into(),
-            options,
-            child: None,
-            result: None,
-            started: false,
-            finished: false,
-            cancelled: false,
-            output_tx: Some(tx),
-            output_rx: Some(rx),
-        }
-    }
-
-    /// Start the process
-    pub async fn start(&mut self) -> Result<()> {
-        if self.started {
-            return Ok(());
-        }
-        self.started = true;
-
-        utils::trace_lazy("ProcessRunner", || {
-            format!("Starting command: {}", self.command)
-        });
-
-        // Check if this is a virtual command
-        let first_word = self.command.split_whitespace().next().unwrap_or("");
-        if let Some(result) = self.try_virtual_command(first_word).await {
-            self.result = Some(result);
-            self.finished = true;
-            return Ok(());
-        }
-
-        // Parse command for shell operators (for future use with virtual command pipelines)
-        let _parsed = if self.options.shell_operators && !needs_real_shell(&self.command) {
-            parse_shell_command(&self.command)
-        } else {
-            None
-        };
-
-        // Execute via real shell if needed
-        let shell = find_available_shell();
-
-        let mut cmd = Command::new(&shell.cmd);
-        for arg in &shell.args {
-            cmd.arg(arg);
-        }
-        cmd.arg(&self.command);
-
-        // Configure stdin
-        match &self.options.stdin {
-            StdinOption::Inherit => {
-                cmd.stdin(Stdio::inherit());
-            }
-            StdinOption::Pipe => {
-                cmd.stdin(Stdio::piped());
-            }
-            StdinOption::Content(_) => {
-                cmd.stdin(Stdio::piped());
-            }
-            StdinOption::Null => {
-                cmd.stdin(Stdio::null());
-            }
-        }
-
-        // Configure stdout/stderr
-        if self.options.capture || self.options.mirror {
-            cmd.stdout(Stdio::piped());
-            cmd.stderr(Stdio::piped());
-        } else {
-            cmd.stdout(Stdio::inherit());
-            cmd.stderr(Stdio::inherit());
-        }
-
-        // Set working directory. Fall back to a valid directory when the
-        // inherited working directory has been deleted (issue #44).
-        if let Some(cwd) = resolve_spawn_cwd(self.options.cwd.as_ref()) {
-            cmd.current_dir(cwd);
-        }
-
-        // Set environment
-        if let Some(ref env_vars) = self.options.env {
-            for (key, value) in env_vars {
-                cmd.env(key, value);
-            }
-        }
-
-        // Spawn the process
-        let child = cmd.spawn()?;
-        self.child = Some(child);
-
-        Ok(())
-    }
-
-    /// Run the process to completion
-    pub async fn run(&mut self) -> Result<CommandResult> {
-        self.start().await?;
-
-        if let Some(result) = &self.result {
-            return Ok(result.clone());
-        }
-
-        let mut child = self.child.take().ok_or_else(|| {
-            Error::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Process not started",
-            ))
-        })?;
-
-        // Handle stdin content if provided
-        if let StdinOption::Content(ref content) = self.options.stdin {
-            if let Some(mut stdin) = child.stdin.take() {
-                let content = content.clone();
-                tokio::spawn(async move {
-                    let _ = stdin.write_all(content.as_bytes()).await;
-                    let _ = stdin.shutdown().await;
-                });
-            }
-        }
-
-        // Collect output
-        let mut stdout_content = String::new();
-        let mut stderr_content = String::new();
-
-        if let Some(stdout) = child.stdout.take() {
-            let mut reader = BufReader::new(stdout).lines();
-            while let Ok(Some(line)) = reader.next_line().await {
-                if self.options.mirror {
-                    println!("{}", line);
-                }
-                stdout_content.push_str(&line);
-                stdout_content.push('\n');
-            }
-        }
-
-        if let Some(stderr) = child.stderr.take() {
-            let mut reader = BufReader::new(stderr).lines();
-            while let Ok(Some(line)) = reader.next_line().await {
-                if self.options.mirror {
-                    eprintln!("{}", line);
-                }
-                stderr_content.push_str(&line);
-                stderr_content.push('\n');
-            }
-        }
-
-        let status = child.wait().await?;
-        let code = status.code().unwrap_or(-1);
-
-        let result = CommandResult {
-            stdout: stdout_content,
-            stderr: stderr_content,
-            code,
-        };
-
-        self.result = Some(result.clone());
-        self.finished = true;
-
-        Ok(result)
-    }
-
-    /// Try to execute as a virtual command
-    async fn try_virtual_command(&self, cmd_name: &str) -> Option<CommandResult> {
-        if !commands::are_virtual_commands_enabled() {
-            return None;
-        }
-
-        // Parse args from command string
-        let parts: Vec<&str> = self.command.split_whitespace().collect();
-        let args: Vec<String> = parts.iter().skip(1).map(|s| s.to_string()).collect();
-
-        let ctx = CommandContext {
-            args,
-            stdin: match &self.options.stdin {
-                StdinOption::Content(s) => Some(s.clone()),
-                _ => None,
-            },
-            cwd: self.options.cwd.clone(),
-            env: self.options.env.clone(),
-            output_tx: self.output_tx.clone(),
-            is_cancelled: None,
-        };
-
-        match cmd_name {
-            "echo" => Some(commands::echo(ctx).await),
-            "pwd" => Some(commands::pwd(ctx).await),
-            "cd" => Some(commands::cd(ctx).await),
-            "true" => Some(commands::r#true(ctx).await),
-            "false" => Some(commands::r#false(ctx).await),
-            "sleep" => Some(commands::sleep(ctx).await),
-            "cat" => Some(commands::cat(ctx).await),
-            "ls" => Some(commands::ls(ctx).await),
-            "mkdir" => Some(commands::mkdir(ctx).await),
-            "rm" => Some(commands::rm(ctx).await),
-            "touch" => Some(commands::touch(ctx).await),
-            "cp" => Some(commands::cp(ctx).await),
-            "mv" => Some(commands::mv(ctx).await),
-            "basename" => Some(commands::basename(ctx).await),
-            "dirname" => Some(commands::dirname(ctx).await),
-            "env" => Some(commands::env(ctx).await),
-            "exit" => Some(commands::exit(ctx).await),
-            "which" => Some(commands::which(ctx).await),
-            "yes" => Some(commands::yes(ctx).await),
-            "seq" => Some(commands::seq(ctx).await),
-            "test" => Some(commands::test(ctx).await),
-            _ => None,
-        }
-    }
-
-    /// Kill the process
-    pub fn kill(&mut self) -> Result<()> {
-        self.cancelled = true;
-        if let Some(ref mut child) = self.child {
-            child.start_kill()?;
-        }
-        Ok(())
-    }
-
-    /// Check if the process is finished
-    pub fn is_finished(&self) -> bool {
-        self.finished
-    }
-
-    /// Get the result if available
-    pub fn result(&self) -> Option<&CommandResult> {
-        self.result.as_ref()
-    }
-
-    /// Get the command string
-    pub fn command(&self) -> &str {
-        &self.command
-    }
-
-    /// Get the options
-    pub fn options(&self) -> &RunOptions {
-        &self.options
-    }
-}
+/// Alias for `run` function - for JavaScript-like API feel
+/// Since `$` is not valid in Rust, this provides a similar short name
+pub use run as execute;
 
-/// Shell configuration
-#[derive(Debug, Clone)]
-struct ShellConfig {
-    cmd: String,
-    args: Vec<String>,
+/// Execute a command with custom options
+pub async fn exec(command: impl Into<String>, options: RunOptions) -> Result<CommandResult> {
+    let mut runner = ProcessRunner::new(command, options);
+    runner.run().await
 }
 
-/// Find an available shell
-fn find_available_shell() -> ShellConfig {
-    let is_windows = cfg!(windows);
-
-    if is_windows {
-        // Windows shells
-        let shells = [
-            ("cmd.exe", vec!["/c"]),
-            ("powershell.exe", vec!["-Command"]),
-        ];
-
-        for (cmd, args) in shells {
-            if which::which(cmd).is_ok() {
-                return ShellConfig {
-                    cmd: cmd.to_string(),
-                    args: args.into_iter().map(String::from).collect(),
-                };
-            }
-        }
-
-        ShellConfig {
-            cmd: "cmd.exe".to_string(),
-            args: vec!["/c".to_string()],
-        }
-    } else {
-        // Unix shells
-        let shells = [
-            ("/bin/sh", vec!["-c"]),
-            ("/usr/bin/sh", vec!["-c"]),
-            ("/bin/bash", vec!["-c"]),
-            ("sh", vec!["-c"]),
-        ];
-
-        for (cmd, args) in shells {
-            if std::path::Path::new(cmd).exists() || which::which(cmd).is_ok() {
-                return ShellConfig {
-                    cmd: cmd.to_string(),
-                    args: args.into_iter().map(String::from).collect(),
-                };
+/// Execute a command, calling `on_stdout_line` and `on_stderr_line` with
+/// each line of its output as it arrives
+///
+/// Lines come without their line endings, and the result still holds the
+/// whole output. The callbacks run on the calling task, so they can borrow
+/// and update local state:
+///
+/// ```rust,no_run
+/// use command_stream::{exec_with, RunOptions};
+///
+/// # async fn example() -> command_stream::Result<()> {
+/// let mut warnings = 0;
+/// let result = exec_with(
+///     "cargo build",
+///     RunOptions::default().quiet(),
+///     |line| println!("{}", line),
+///     |line| {
+///         if line.starts_with("warning") {
+///             warnings += 1;
+///         }
+///     },
+/// )
+/// .await?;
+/// println!("{} warnings, exit code {}", warnings, result.code);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn exec_with(
+    command: impl Into<String>,
+    options: RunOptions,
+    mut on_stdout_line: impl FnMut(String),
+    mut on_stderr_line: impl FnMut(String),
+) -> Result<CommandResult> {
+    let (stdout_tx, mut stdout_rx) = tokio::sync::mpsc::channel(64);
+    let (stderr_tx, mut stderr_rx) = tokio::sync::mpsc::channel(64);
+    let runner = ProcessRunner::new(command, options)
+        .pipe_stdout_to(stdout_tx)
+        .pipe_stderr_to(stderr_tx);
+    // The sinks close with the runner, ending the loop below
+    let run = async move {
+        let mut runner = runner;
+        runner.run().await
+    };
+    let forward = async {
+        loop {
+            tokio::select! {
+                Some(line) = stdout_rx.recv() => on_stdout_line(line),
+                Some(line) = stderr_rx.recv() => on_stderr_line(line),
+                else => break,
             }
         }
-
-        ShellConfig {
-            cmd: "/bin/sh".to_string(),
-            args: vec!["-c".to_string()],
-        }
-    }
+    };
+    let (result, ()) = tokio::join!(run, forward);
+    result
 }
 
-/// Execute a command and return the result
+/// Execute a command, reporting its output and lifecycle to `emitter`
 ///
-/// This is the main entry point for simple command execution.
-/// Named `run` instead of `$` since `$` is not a valid Rust identifier.
-pub async fn run(command: impl Into<String>) -> Result<CommandResult> {
-    let mut runner = ProcessRunner::new(command, RunOptions::default());
+/// Register listeners on the emitter before awaiting; this is the equivalent
+/// of `$(...).on('data', ...)` in the JavaScript library.
+pub async fn run_with_events(
+    command: impl Into<String>,
+    options: RunOptions,
+    emitter: Arc<StreamEmitter>,
+) -> Result<CommandResult> {
+    let mut runner = ProcessRunner::new(command, options).with_emitter(emitter);
     runner.run().await
 }
 
-/// Alias for `run` function - for JavaScript-like API feel
-/// Since `$` is not valid in Rust, this provides a similar short name
-pub use run as execute;
-
-/// Execute a command with custom options
-pub async fn exec(command: impl Into<String>, options: RunOptions) -> Result<CommandResult> {
-    let mut runner = ProcessRunner::new(command, options);
-    runner.run().await
+/// Prepare a command together with a new emitter for its events
+///
+/// Nothing runs until the returned future is awaited, so listeners added to
+/// the emitter first see every event.
+///
+/// ```rust,no_run
+/// use command_stream::{run_events, EventData, EventType};
+///
+/// # async fn example() -> command_stream::Result<()> {
+/// let (emitter, result) = run_events("ls -la");
+/// emitter
+///     .on(EventType::Stdout, |data| {
+///         if let EventData::String(chunk) = data {
+///             print!("{}", chunk);
+///         }
+///     })
+///     .await;
+/// let result = result.await?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn run_events(
+    command: impl Into<String>,
+) -> (
+    Arc<StreamEmitter>,
+    impl std::future::Future<Output = Result<CommandResult>>,
+) {
+    let emitter = Arc::new(StreamEmitter::new());
+    let command = command.into();
+    let options = RunOptions {
+        mirror: false,
+        ..Default::default()
+    };
+    let future = run_with_events(command, options, emitter.clone());
+    (emitter, future)
 }
 
 /// Create a new process runner without starting it
@@ -0,0 +1,161 @@
+//! File locks for serializing work across processes
+//!
+//! [`with_lock`] holds an exclusive lock on a file while a future runs, so
+//! concurrent invocations of a script, in this process or others, take turns
+//! with a shared resource:
+//!
+//! ```rust,no_run
+//! use command_stream::lock::with_lock;
+//! use command_stream::run;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let result = with_lock("/tmp/deploy.lock", || async { run("./deploy.sh").await }).await??;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The locks are advisory OS file locks (`flock` on Unix, `LockFileEx` on
+//! Windows), the same kind the `flock` utility takes, so shell scripts using
+//! `flock` and the virtual `flock` builtin cooperate with them. A lock is
+//! released when its [`FileLock`] is dropped or the process exits.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::Result;
+
+/// Longest pause between attempts to take a busy lock
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// An exclusive lock on a file, held until dropped
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+    // Closing the file releases the lock
+    _file: File,
+}
+
+impl FileLock {
+    /// Take the lock on `path`, creating the file if needed, and wait as
+    /// long as another holder keeps it
+    pub async fn acquire(path: impl AsRef<Path>) -> Result<FileLock> {
+        Self::wait(path.as_ref(), None).await
+    }
+
+    /// Take the lock on `path`, giving up with an
+    /// [`io::ErrorKind::TimedOut`] error after `timeout`
+    pub async fn acquire_timeout(path: impl AsRef<Path>, timeout: Duration) -> Result<FileLock> {
+        Self::wait(path.as_ref(), Some(timeout)).await
+    }
+
+    /// Take the lock on `path` if it is free, or return `None`
+    pub fn try_acquire(path: impl AsRef<Path>) -> Result<Option<FileLock>> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(FileLock {
+                path: path.to_path_buf(),
+                _file: file,
+            })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    /// The locked file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn wait(path: &Path, timeout: Option<Duration>) -> Result<FileLock> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut interval = Duration::from_millis(10);
+        loop {
+            if let Some(lock) = Self::try_acquire(path)? {
+                return Ok(lock);
+            }
+            let mut pause = interval;
+            if let Some(deadline) = deadline {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("timed out waiting for the lock on {}", path.display()),
+                    )
+                    .into());
+                }
+                pause = pause.min(left);
+            }
+            tokio::time::sleep(pause).await;
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Run `f` while holding the lock on `path`; see the [module docs](self)
+///
+/// Waits for the lock as long as it is held elsewhere.
+pub async fn with_lock<F, Fut, T>(path: impl AsRef<Path>, f: F) -> Result<T>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = T>,
+{
+    let _lock = FileLock::acquire(path).await?;
+    Ok(f().await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_lock_is_exclusive() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("lock");
+
+        let held = FileLock::try_acquire(&path).unwrap().unwrap();
+        assert_eq!(held.path(), path);
+        assert!(FileLock::try_acquire(&path).unwrap().is_none());
+        let err = FileLock::acquire_timeout(&path, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+
+        drop(held);
+        assert!(FileLock::try_acquire(&path).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_with_lock_serializes() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("lock");
+        let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let task = |name: &'static str| {
+            let (path, log) = (path.clone(), log.clone());
+            async move {
+                with_lock(&path, || async {
+                    log.lock().unwrap().push(format!("{} start", name));
+                    tokio::time::sleep(Duration::from_millis(30)).await;
+                    log.lock().unwrap().push(format!("{} end", name));
+                })
+                .await
+                .unwrap()
+            }
+        };
+        tokio::join!(task("a"), task("b"));
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 4);
+        assert!(log[0].ends_with("start") && log[1].ends_with("end"));
+        assert_eq!(log[0][..1], log[1][..1]);
+    }
+}
@@ -31,6 +31,10 @@
 //!     let dir = "/tmp";
 //!     let result = s!("cp {} {}", file, dir).await?;
 //!
+//!     // A list splatted into separately quoted arguments
+//!     let files = vec!["a.txt", "my notes.md"];
+//!     let result = s!("tar -czf {} {files...}", "out.tgz", files = files).await?;
+//!
 //!     Ok(())
 //! }
 //! ```
@@ -53,16 +57,98 @@ pub fn build_shell_command(parts: &[&str], values: &[&str]) -> String {
     result
 }
 
+/// Build a shell command from a template with positional and named values
+///
+/// Each `{}` takes the next positional value, quoted as one argument. A
+/// `{name...}` placeholder expands to the items of the named value `name`,
+/// each quoted as a separate argument, and `{name}` to those items joined
+/// with spaces as a single quoted argument. Braces that match neither form,
+/// such as `{a,b}` or a `find -exec {} \;` past the last positional value,
+/// are kept as written.
+///
+/// This is what the `cmd!` macro family calls; it is public so command lines
+/// can be assembled the same way without a macro.
+///
+/// ```
+/// use command_stream::macros::build_template;
+///
+/// let files = vec!["a.txt".to_string(), "my notes.md".to_string()];
+/// let command = build_template(
+///     "tar -czf {} {files...}",
+///     &["out.tgz".to_string()],
+///     &[("files", files)],
+/// );
+/// assert_eq!(command, "tar -czf out.tgz a.txt 'my notes.md'");
+/// ```
+pub fn build_template(
+    template: &str,
+    positional: &[String],
+    named: &[(&str, Vec<String>)],
+) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut positional = positional.iter();
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        result.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let close = after.find('}');
+        let inner = close.map(|close| &after[..close]);
+
+        let replacement = match inner {
+            Some("") => positional.next().map(|value| crate::quote::quote(value)),
+            Some(inner) => {
+                let (name, splat) = match inner.strip_suffix("...") {
+                    Some(name) => (name, true),
+                    None => (inner, false),
+                };
+                named
+                    .iter()
+                    .find(|(named, _)| *named == name)
+                    .map(|(_, values)| {
+                        if splat {
+                            crate::quote::quote_args(values)
+                        } else {
+                            crate::quote::quote(&values.join(" "))
+                        }
+                    })
+            }
+            None => None,
+        };
+
+        match (replacement, close) {
+            (Some(replacement), Some(close)) => {
+                result.push_str(&replacement);
+                rest = &after[close + 1..];
+            }
+            _ => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+
+    crate::quote::warn_on_split_template(&result);
+    result
+}
+
+/// Convert a list value passed to the `cmd!` macros into strings
+#[doc(hidden)]
+pub fn splat_values<I>(values: I) -> Vec<String>
+where
+    I: IntoIterator,
+    I::Item: std::fmt::Display,
+{
+    values.into_iter().map(|value| value.to_string()).collect()
+}
+
 /// Helper function to create a ProcessRunner from a command string
+///
+/// Uses [`RunOptions::from_env`](crate::RunOptions::from_env) for the
+/// mirror/capture defaults.
 pub fn create_runner(command: String) -> crate::ProcessRunner {
-    crate::ProcessRunner::new(
-        command,
-        crate::RunOptions {
-            mirror: true,
-            capture: true,
-            ..Default::default()
-        },
-    )
+    crate::ProcessRunner::new(command, crate::RunOptions::from_env())
 }
 
 /// Helper function to create a ProcessRunner with custom options
@@ -101,6 +187,10 @@ pub fn create_runner_with_options(
 /// // Values with special characters are automatically quoted
 /// let filename = "file with spaces.txt";
 /// let result = s!("cat {}", filename).await?; // Safely handles spaces
+///
+/// // Named lists expand to one quoted argument per item with `{name...}`
+/// let files = vec!["one.txt", "two words.txt"];
+/// let result = s!("wc -l {files...}", files = &files).await?;
 /// # Ok(())
 /// # }
 /// ```
@@ -118,20 +208,12 @@ macro_rules! cmd {
         }
     }};
 
-    // With format-style interpolation
-    ($fmt:expr, $($arg:expr),+ $(,)?) => {{
-        // Build command with quoted values
-        let mut result = String::new();
-        let values: Vec<String> = vec![$(format!("{}", $arg)),+];
-        let values_ref: Vec<&str> = values.iter().map(|s| s.as_str()).collect();
-        let fmt_parts: Vec<&str> = $fmt.split("{}").collect();
-        for (i, part) in fmt_parts.iter().enumerate() {
-            result.push_str(part);
-            if i < values_ref.len() {
-                result.push_str(&$crate::quote::quote(values_ref[i]));
-            }
-        }
-        $crate::quote::warn_on_split_template(&result);
+    // With interpolation: positional `{}` values and named `{name...}` lists
+    ($fmt:expr, $($args:tt)+) => {{
+        let mut positional: Vec<String> = Vec::new();
+        let mut named: Vec<(&str, Vec<String>)> = Vec::new();
+        $crate::__cmd_args!(positional, named; $($args)+);
+        let result = $crate::macros::build_template($fmt, &positional, &named);
 
         async move {
             $crate::run(result).await
@@ -139,6 +221,22 @@ macro_rules! cmd {
     }};
 }
 
+/// Collect the `cmd!` arguments: `name = list` pairs into `named`, anything
+/// else into `positional`
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cmd_args {
+    ($positional:ident, $named:ident; ) => {};
+    ($positional:ident, $named:ident; $name:ident = $value:expr $(, $($rest:tt)*)?) => {
+        $named.push((stringify!($name), $crate::macros::splat_values($value)));
+        $crate::__cmd_args!($positional, $named; $($($rest)*)?);
+    };
+    ($positional:ident, $named:ident; $value:expr $(, $($rest:tt)*)?) => {
+        $positional.push(format!("{}", $value));
+        $crate::__cmd_args!($positional, $named; $($($rest)*)?);
+    };
+}
+
 /// The `sh!` macro - alias for `cmd!`
 ///
 /// This is an alternative name for `cmd!` that some users may find
@@ -2,36 +2,301 @@
 //!
 //! A simple CLI wrapper for the command-stream library.
 
-use command_stream::{run, ProcessRunner, RunOptions};
+use command_stream::backend::Backend;
+use command_stream::completions::{completion_script, CompletionShell};
+use command_stream::confirm::{set_confirmation_gate, terminal_available, ConfirmationGate};
+use command_stream::hooks::before_exec;
+use command_stream::quote::quote_args;
+use command_stream::shell::{set_shell_preference, ShellChoice};
+use command_stream::usage::ResourceUsage;
+use command_stream::{
+    exec, install_cleanup_handlers, run_script_in, Parameters, RunOptions, Session,
+};
 use std::env;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().skip(1).collect();
+    // Commands still running when the CLI is interrupted are stopped with it
+    install_cleanup_handlers();
+    let mut args: Vec<String> = env::args().skip(1).collect();
 
-    if args.is_empty() {
-        eprintln!("Usage: command-stream <command> [args...]");
+    // Destructive commands are confirmed on a terminal unless --yes is given
+    let mut yes = false;
+    let mut file = None;
+    let (mut shell, mut backend, mut identity) = (None, None, None);
+    // What to report on stderr once the command is done, and whether as JSON
+    let (mut time, mut stats, mut json) = (false, false, false);
+    while let Some(flag) = args.first() {
+        match flag.as_str() {
+            "-y" | "--yes" => yes = true,
+            "-f" | "--file" if args.len() > 1 => file = Some(args.remove(1)),
+            "--shell" if args.len() > 1 => shell = Some(args.remove(1)),
+            "--backend" if args.len() > 1 => backend = Some(args.remove(1)),
+            "--ssh-identity" if args.len() > 1 => identity = Some(args.remove(1)),
+            "--completions" if args.len() > 1 => {
+                let shell: CompletionShell = args[1].parse().unwrap_or_else(|e| fail(e));
+                return Ok(print_stdout(&completion_script(shell))?);
+            }
+            "--time" => time = true,
+            "--time=json" => (time, json) = (true, true),
+            "--stats" => stats = true,
+            "--stats=json" => (stats, json) = (true, true),
+            _ => break,
+        }
+        args.remove(0);
+    }
+    if file.is_none() && args.first().is_some_and(|arg| is_script(arg)) {
+        file = Some(args.remove(0));
+    }
+
+    if args.is_empty() && file.is_none() {
+        eprintln!("Usage: command-stream [options] <command> [args...]");
+        eprintln!("       command-stream [options] [--file] <script> [args...]");
         eprintln!();
         eprintln!("Execute shell commands with streaming support.");
         eprintln!();
+        eprintln!("Options:");
+        eprintln!("  -y, --yes             Run destructive commands without asking");
+        eprintln!("  -f, --file <path>     Run a script file, statement by statement");
+        eprintln!("  --shell <shell>       auto, posix, powershell, or a shell to run");
+        eprintln!("                        commands with, like bash or /bin/zsh");
+        eprintln!("  --backend <backend>   Where commands run: local, ssh://[user@]host[:port],");
+        eprintln!("                        docker://[user@]container or wsl[://distribution];");
+        eprintln!("                        a script is sent there whole");
+        eprintln!("  --ssh-identity <key>  Private key for an ssh:// backend");
+        eprintln!("  --time[=json]         Print the real, user and system time at the end");
+        eprintln!("  --stats[=json]        Also print CPU usage, max RSS and bytes of output");
+        eprintln!("  --completions <shell> Print a tab completion script for bash, zsh, fish");
+        eprintln!("                        or powershell");
+        eprintln!();
+        eprintln!("COMMAND_STREAM_SHELL, COMMAND_STREAM_BACKEND and");
+        eprintln!("COMMAND_STREAM_SSH_IDENTITY stand in for flags not given.");
+        eprintln!();
+        eprintln!("A first argument naming a .csh file, or a file starting with");
+        eprintln!("#!/usr/bin/env command-stream, is run as a script; other files");
+        eprintln!("run as commands unless given with --file.");
+        eprintln!();
         eprintln!("Examples:");
         eprintln!("  command-stream echo hello world");
         eprintln!("  command-stream ls -la");
         eprintln!("  command-stream 'echo hello && echo world'");
+        eprintln!("  command-stream ./deploy.csh staging");
+        eprintln!("  command-stream --backend ssh://deploy@web1 'systemctl status app'");
         std::process::exit(1);
     }
 
-    let command = args.join(" ");
+    let shell = match setting(shell, "COMMAND_STREAM_SHELL") {
+        None => ShellChoice::Auto,
+        Some(name) => name.parse().unwrap_or_else(|_| {
+            // Another shell, by name or path, for commands in POSIX syntax
+            set_shell_preference([name]);
+            ShellChoice::Auto
+        }),
+    };
+    // Whether commands run on another machine or in a container
+    let mut remote = false;
+    if let Some(spec) = setting(backend, "COMMAND_STREAM_BACKEND") {
+        let mut backend: Backend = spec.parse().unwrap_or_else(|e| fail(e));
+        if let Some(key) = setting(identity, "COMMAND_STREAM_SSH_IDENTITY") {
+            backend = backend.identity(key);
+        }
+        if backend != Backend::Local {
+            remote = true;
+            before_exec(move |mut spec| {
+                spec.command = backend.command(&spec.command);
+                async move { Ok(spec) }
+            });
+        }
+    }
+    let options = RunOptions {
+        shell,
+        ..RunOptions::from_env()
+    };
+
+    // Without a terminal to ask on, as in CI, commands run as they always did
+    if !yes && terminal_available() {
+        set_confirmation_gate(Some(ConfirmationGate::prompt().into()));
+    }
 
-    let result = run(command).await?;
+    let started = Instant::now();
+    let (code, result) = match file {
+        Some(file) if remote => {
+            // The script runs whole on the backend, so its `cd`s and
+            // variables apply there rather than to a session here
+            let script =
+                std::fs::read_to_string(&file).unwrap_or_else(|e| fail(format!("{}: {}", file, e)));
+            let script = format!("set -- {}\n{}", quote_args(&args), script);
+            let result = exec(script, options).await?;
+            (result.code, Some(result))
+        }
+        Some(file) => {
+            // The arguments after the script are its positional parameters
+            let options = RunOptions {
+                parameters: Parameters::new(file.clone(), args),
+                ..options
+            };
+            let session = Session::with_options(options);
+            let outcome = match run_script_in(&session, &file).await {
+                Ok(result) => (result.code, Some(result)),
+                Err(e) => {
+                    eprintln!("command-stream: {}", e);
+                    // A script that couldn't be split ran nothing; like sh,
+                    // that is status 2
+                    let code = match session.last_status() {
+                        0 => 2,
+                        status => status,
+                    };
+                    (code, None)
+                }
+            };
+            // Exiting runs the script's `trap ... EXIT`
+            session.close().await;
+            outcome
+        }
+        None => {
+            // Output is mirrored to our stdout/stderr as the command runs.
+            let result = exec(args.join(" "), options).await?;
+            (result.code, Some(result))
+        }
+    };
 
-    // Print any output that wasn't mirrored
-    if !result.stdout.is_empty() && !result.stdout.ends_with('\n') {
-        println!("{}", result.stdout);
+    if time || stats {
+        let output = result.map(|result| (result.stdout.len(), result.stderr.len()));
+        let usage = ResourceUsage::total();
+        eprint!("{}", report(started.elapsed(), &usage, output, stats, json));
     }
-    if !result.stderr.is_empty() {
-        eprint!("{}", result.stderr);
+    std::process::exit(code);
+}
+
+/// What `--time`, or with `stats` `--stats`, prints: times as bash's `time`
+/// does, then the rest a line each, or all of it as one JSON object
+///
+/// `output` is the bytes written to stdout and stderr, when known.
+fn report(
+    real: Duration,
+    usage: &ResourceUsage,
+    output: Option<(usize, usize)>,
+    stats: bool,
+    json: bool,
+) -> String {
+    let cpu_percent = if real.is_zero() {
+        0.0
+    } else {
+        usage.cpu().as_secs_f64() / real.as_secs_f64() * 100.0
+    };
+    if json {
+        let mut fields = vec![
+            format!("\"real\":{:.3}", real.as_secs_f64()),
+            format!("\"user\":{:.3}", usage.user.as_secs_f64()),
+            format!("\"sys\":{:.3}", usage.system.as_secs_f64()),
+        ];
+        if stats {
+            let bytes = |count: Option<usize>| count.map_or("null".to_string(), |n| n.to_string());
+            fields.extend([
+                format!("\"cpu_percent\":{:.1}", cpu_percent),
+                format!("\"max_rss_bytes\":{}", usage.max_rss),
+                format!("\"stdout_bytes\":{}", bytes(output.map(|o| o.0))),
+                format!("\"stderr_bytes\":{}", bytes(output.map(|o| o.1))),
+            ]);
+        }
+        return format!("{{{}}}\n", fields.join(","));
     }
+    let clock = |time: Duration| {
+        let secs = time.as_secs_f64();
+        format!("{}m{:.3}s", time.as_secs() / 60, secs % 60.0)
+    };
+    let mut text = format!(
+        "\nreal\t{}\nuser\t{}\nsys\t{}\n",
+        clock(real),
+        clock(usage.user),
+        clock(usage.system)
+    );
+    if stats {
+        text.push_str(&format!("cpu\t{:.0}%\n", cpu_percent));
+        text.push_str(&format!("maxrss\t{}\n", size(usage.max_rss)));
+        if let Some((stdout, stderr)) = output {
+            text.push_str(&format!(
+                "output\t{} (stdout {}, stderr {})\n",
+                size((stdout + stderr) as u64),
+                size(stdout as u64),
+                size(stderr as u64)
+            ));
+        }
+    }
+    text
+}
 
-    std::process::exit(result.code);
+/// `bytes` in the largest binary unit that keeps it at 1 or more
+fn size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// The value of a flag, or else of the environment variable `var`
+fn setting(flag: Option<String>, var: &str) -> Option<String> {
+    flag.or_else(|| env::var(var).ok().filter(|value| !value.is_empty()))
+}
+
+/// Write `text` to stdout; a reader that has gone away, as `| head` does,
+/// isn't an error
+fn print_stdout(text: &str) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    match stdout
+        .write_all(text.as_bytes())
+        .and_then(|()| stdout.flush())
+    {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        outcome => outcome,
+    }
+}
+
+/// Report a bad option and exit with status 2, as for a usage error
+fn fail(error: impl std::fmt::Display) -> ! {
+    eprintln!("command-stream: {}", error);
+    std::process::exit(2)
+}
+
+/// Whether `arg` names a script to run rather than a command: a `.csh`
+/// file, or one whose `#!` line runs command-stream
+///
+/// Files for other interpreters, such as `#!/usr/bin/env python3`, run as
+/// commands; `--file` runs any file as a script.
+fn is_script(arg: &str) -> bool {
+    let path = Path::new(arg);
+    if !path.is_file() {
+        return false;
+    }
+    if path.extension().is_some_and(|extension| extension == "csh") {
+        return true;
+    }
+    let mut line = String::new();
+    let Ok(_) = std::fs::File::open(path)
+        .map(std::io::BufReader::new)
+        .and_then(|mut file| file.read_line(&mut line))
+    else {
+        return false;
+    };
+    let Some(shebang) = line.strip_prefix("#!") else {
+        return false;
+    };
+    let mut words = shebang.split_whitespace();
+    let mut interpreter = words.next();
+    if interpreter.is_some_and(|program| Path::new(program).ends_with("env")) {
+        interpreter = words.find(|word| !word.starts_with('-'));
+    }
+    interpreter
+        .and_then(|program| Path::new(program).file_stem())
+        .is_some_and(|name| name == "command-stream")
 }
@@ -0,0 +1,521 @@
+//! Options controlling how a command runs
+//!
+//! [`RunOptions::default`] mirrors and captures output. Applications that want
+//! a different policy everywhere can set `COMMAND_STREAM_MIRROR` and
+//! `COMMAND_STREAM_CAPTURE` and use [`RunOptions::from_env`], which [`run`](crate::run)
+//! and the `cmd!` family do; [`quiet`](crate::quiet) and [`loud`](crate::loud)
+//! override mirroring for a single command.
+
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use crate::color::ColorPolicy;
+use crate::confirm::ConfirmationGate;
+use crate::expand::Parameters;
+use crate::filter::OutputFilter;
+use crate::heartbeat::Heartbeat;
+pub use crate::shell::ShellChoice;
+use crate::state::ShellSettings;
+use crate::tail::TailLimit;
+use crate::{parse_duration, CancellationToken, ExecutionPolicy};
+
+/// Options for command execution
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Mirror output to parent stdout/stderr
+    pub mirror: bool,
+    /// Capture output in result
+    pub capture: bool,
+    /// Standard input handling
+    pub stdin: StdinOption,
+    /// Working directory
+    pub cwd: Option<PathBuf>,
+    /// Environment variables
+    pub env: Option<HashMap<String, String>>,
+    /// Interactive mode (TTY forwarding)
+    pub interactive: bool,
+    /// Enable shell operator parsing
+    pub shell_operators: bool,
+    /// Enable tracing for this command
+    pub trace: bool,
+    /// Shell settings for this command. `None` uses the global settings
+    /// (see [`set_shell_option`](crate::set_shell_option)); `Some` replaces them for this execution
+    /// only, so concurrent callers can use different errexit/pipefail policies.
+    pub shell_settings: Option<ShellSettings>,
+    /// Execute plain commands (a program plus literal arguments) directly,
+    /// without spawning a shell. Disable to always go through the shell, e.g.
+    /// when relying on shell-specific PATH resolution, functions, or aliases.
+    pub direct_exec: bool,
+    /// Kill the command and return [`Error::Timeout`](crate::Error::Timeout) if it runs longer than
+    /// this
+    pub timeout: Option<Duration>,
+    /// Token that kills the command and returns [`Error::Cancelled`](crate::Error::Cancelled) when
+    /// triggered. One token can cancel many runners and pipelines at once.
+    pub cancel: Option<CancellationToken>,
+    /// Report what was written to the command's stdin in
+    /// [`CommandResult::stdin`](crate::CommandResult::stdin), keeping at most
+    /// this many bytes. Only [`StdinOption::Content`] is recorded; off by
+    /// default.
+    pub capture_stdin: Option<usize>,
+    /// Which shell language the command is written in
+    pub shell: ShellChoice,
+    /// Hand the command string to the shell as-is, skipping virtual commands
+    /// and direct execution. Anything interpolated into the string is shell
+    /// syntax, so this is the injection-risk path; see
+    /// [`run_raw_shell`](crate::run_raw_shell).
+    pub raw_shell: bool,
+    /// Ask the command to flush its output after every line, so streamed
+    /// output arrives as it is written instead of in block-buffered chunks.
+    /// Runs it under `stdbuf -oL -eL` when available and sets
+    /// `PYTHONUNBUFFERED`; programs that manage their own buffering may
+    /// still buffer.
+    pub line_buffered: bool,
+    /// Rules the command must pass before it runs. `None` uses the global
+    /// policy, if any (see [`set_execution_policy`](crate::policy::set_execution_policy)).
+    pub policy: Option<Arc<ExecutionPolicy>>,
+    /// Gate that asks before destructive commands run. `None` uses the
+    /// global gate, if any (see [`set_confirmation_gate`](crate::confirm::set_confirmation_gate)).
+    pub confirm: Option<Arc<ConfirmationGate>>,
+    /// Watchdog that treats the command as stalled when it writes no output
+    /// for a while; see [`Heartbeat`]
+    pub heartbeat: Option<Heartbeat>,
+    /// Also record each output line with its stream and arrival time in
+    /// [`CommandResult::timed_lines`](crate::CommandResult::timed_lines), for
+    /// latency analysis or replaying the output at its original pace
+    pub timed_lines: bool,
+    /// Filters every output line passes through, in order, before it is
+    /// captured, mirrored, emitted or sent to a sink; see
+    /// [`filter`](crate::filter)
+    pub output_filters: Vec<Arc<dyn OutputFilter>>,
+    /// Tell the command whether to color its output; see
+    /// [`color`](crate::color). `None` leaves its color variables as this
+    /// process has them.
+    pub color: Option<ColorPolicy>,
+    /// Keep only the end of stdout and stderr in the result and the
+    /// [partial output](crate::ProcessRunner::partial_output), bounding the
+    /// memory a long, chatty command takes; see [`tail`](crate::tail)
+    pub capture_tail: Option<TailLimit>,
+    /// The positional and special parameters the command's words can use:
+    /// `$0`, `$1`... and `$?`. By default there are no positional
+    /// parameters and `$?` is 0.
+    pub parameters: Parameters,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            mirror: true,
+            capture: true,
+            stdin: StdinOption::Inherit,
+            cwd: None,
+            env: None,
+            interactive: false,
+            shell_operators: true,
+            trace: true,
+            shell_settings: None,
+            direct_exec: true,
+            timeout: None,
+            cancel: None,
+            capture_stdin: None,
+            shell: ShellChoice::Auto,
+            raw_shell: false,
+            line_buffered: false,
+            policy: None,
+            confirm: None,
+            heartbeat: None,
+            timed_lines: false,
+            output_filters: Vec::new(),
+            color: None,
+            capture_tail: None,
+            parameters: Parameters::default(),
+        }
+    }
+}
+
+impl RunOptions {
+    /// Start building options from the defaults
+    ///
+    /// ```rust
+    /// use command_stream::RunOptions;
+    /// use std::time::Duration;
+    ///
+    /// let options = RunOptions::builder()
+    ///     .mirror(false)
+    ///     .cwd("/tmp")
+    ///     .env("RUST_LOG", "debug")
+    ///     .timeout(Duration::from_secs(30))
+    ///     .build();
+    /// assert!(!options.mirror);
+    /// ```
+    pub fn builder() -> RunOptionsBuilder {
+        RunOptionsBuilder::default()
+    }
+
+    /// Default options with `mirror` and `capture` taken from the
+    /// `COMMAND_STREAM_MIRROR` and `COMMAND_STREAM_CAPTURE` environment
+    /// variables when they are set
+    ///
+    /// Each accepts `true`/`false`, `1`/`0`, `yes`/`no` or `on`/`off`; other
+    /// values are ignored. `COMMAND_STREAM_TIMEOUT` sets the timeout from a
+    /// duration such as `30s` or `1h30m` (see [`parse_duration`]).
+    ///
+    /// Inside [`Config::scoped`](crate::config::Config::scoped) the scoped
+    /// configuration is used instead.
+    pub fn from_env() -> Self {
+        crate::config::Config::current().run_options()
+    }
+
+    /// [`RunOptions::from_env`] without any scoped configuration
+    pub(crate) fn from_env_vars() -> Self {
+        let mut options = RunOptions::default();
+        if let Some(mirror) = env_flag("COMMAND_STREAM_MIRROR") {
+            options.mirror = mirror;
+        }
+        if let Some(capture) = env_flag("COMMAND_STREAM_CAPTURE") {
+            options.capture = capture;
+        }
+        if let Some(timeout) = env::var("COMMAND_STREAM_TIMEOUT")
+            .ok()
+            .and_then(|value| parse_duration(&value).ok())
+        {
+            options.timeout = Some(timeout);
+        }
+        options
+    }
+
+    /// These options with output mirroring turned off
+    pub fn quiet(self) -> Self {
+        RunOptions {
+            mirror: false,
+            ..self
+        }
+    }
+
+    /// These options with output mirroring turned on
+    pub fn loud(self) -> Self {
+        RunOptions {
+            mirror: true,
+            ..self
+        }
+    }
+
+    /// These options with the command's locale set to `locale`
+    ///
+    /// Sets `LC_ALL` and `LANG`, and clears `LANGUAGE`, which would otherwise
+    /// still pick the language of GNU tools' messages.
+    pub fn locale(mut self, locale: impl Into<String>) -> Self {
+        set_locale(&mut self.env, locale.into());
+        self
+    }
+
+    /// These options with the [`STABLE_LOCALE`], for commands whose output
+    /// is parsed
+    ///
+    /// Dates, number formats and messages then look the same whatever the
+    /// user's language, e.g. the dates in `ls -l` or the errors `git`
+    /// prints.
+    pub fn stable_locale(self) -> Self {
+        self.locale(STABLE_LOCALE)
+    }
+}
+
+/// Builder for [`RunOptions`], created with [`RunOptions::builder`]
+///
+/// Each setter replaces one field; fields that are never set keep their
+/// [`Default`] value, so adding options later does not break callers.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptionsBuilder {
+    options: RunOptions,
+}
+
+impl RunOptionsBuilder {
+    /// Mirror output to this process's stdout/stderr
+    pub fn mirror(mut self, enabled: bool) -> Self {
+        self.options.mirror = enabled;
+        self
+    }
+
+    /// Capture output in the result
+    pub fn capture(mut self, enabled: bool) -> Self {
+        self.options.capture = enabled;
+        self
+    }
+
+    /// How to provide standard input
+    pub fn stdin(mut self, stdin: StdinOption) -> Self {
+        self.options.stdin = stdin;
+        self
+    }
+
+    /// Working directory for the command
+    pub fn cwd(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.cwd = Some(path.into());
+        self
+    }
+
+    /// Set one environment variable, keeping any set before
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options
+            .env
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Set several environment variables, keeping any set before
+    pub fn envs<K, V>(mut self, vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.options
+            .env
+            .get_or_insert_with(HashMap::new)
+            .extend(vars.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Forward the terminal to the command
+    pub fn interactive(mut self, enabled: bool) -> Self {
+        self.options.interactive = enabled;
+        self
+    }
+
+    /// Parse shell operators (`&&`, `||`, `;`, `|`)
+    pub fn shell_operators(mut self, enabled: bool) -> Self {
+        self.options.shell_operators = enabled;
+        self
+    }
+
+    /// Trace this command
+    pub fn trace(mut self, enabled: bool) -> Self {
+        self.options.trace = enabled;
+        self
+    }
+
+    /// Shell settings to use instead of the global ones
+    pub fn shell_settings(mut self, settings: ShellSettings) -> Self {
+        self.options.shell_settings = Some(settings);
+        self
+    }
+
+    /// Positional and special parameters for the command, as a script run
+    /// with arguments has them
+    pub fn parameters(mut self, parameters: Parameters) -> Self {
+        self.options.parameters = parameters;
+        self
+    }
+
+    /// Run plain commands without a shell
+    pub fn direct_exec(mut self, enabled: bool) -> Self {
+        self.options.direct_exec = enabled;
+        self
+    }
+
+    /// Kill the command if it runs longer than `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Kill the command when `token` is cancelled
+    pub fn cancel(mut self, token: CancellationToken) -> Self {
+        self.options.cancel = Some(token);
+        self
+    }
+
+    /// Run the command in `locale` (see [`RunOptions::locale`])
+    pub fn locale(mut self, locale: impl Into<String>) -> Self {
+        set_locale(&mut self.options.env, locale.into());
+        self
+    }
+
+    /// Run the command in the [`STABLE_LOCALE`]
+    pub fn stable_locale(self) -> Self {
+        self.locale(STABLE_LOCALE)
+    }
+
+    /// Record up to `max_bytes` of the stdin content in the result
+    pub fn capture_stdin(mut self, max_bytes: usize) -> Self {
+        self.options.capture_stdin = Some(max_bytes);
+        self
+    }
+
+    /// Keep only the `limit` tail of the output (see
+    /// [`RunOptions::capture_tail`])
+    pub fn capture_tail(mut self, limit: TailLimit) -> Self {
+        self.options.capture_tail = Some(limit);
+        self
+    }
+
+    /// Parse and run the command as `shell` syntax
+    pub fn shell(mut self, shell: ShellChoice) -> Self {
+        self.options.shell = shell;
+        self
+    }
+
+    /// Always run the command through the shell, unparsed (see
+    /// [`RunOptions::raw_shell`])
+    pub fn raw_shell(mut self, enabled: bool) -> Self {
+        self.options.raw_shell = enabled;
+        self
+    }
+
+    /// Ask the command to flush its output per line (see
+    /// [`RunOptions::line_buffered`])
+    pub fn line_buffered(mut self, enabled: bool) -> Self {
+        self.options.line_buffered = enabled;
+        self
+    }
+
+    /// Check the command against `policy` before it runs
+    pub fn policy(mut self, policy: ExecutionPolicy) -> Self {
+        self.options.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Ask `gate` before running the command if it looks destructive
+    pub fn confirm(mut self, gate: impl Into<Arc<ConfirmationGate>>) -> Self {
+        self.options.confirm = Some(gate.into());
+        self
+    }
+
+    /// Watch the command with `heartbeat`
+    pub fn heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.options.heartbeat = Some(heartbeat);
+        self
+    }
+
+    /// Record each output line with its arrival time (see
+    /// [`RunOptions::timed_lines`])
+    pub fn timed_lines(mut self, enabled: bool) -> Self {
+        self.options.timed_lines = enabled;
+        self
+    }
+
+    /// Add `filter` to the end of the output filter chain (see
+    /// [`RunOptions::output_filters`])
+    pub fn output_filter(mut self, filter: impl OutputFilter + 'static) -> Self {
+        self.options.output_filters.push(Arc::new(filter));
+        self
+    }
+
+    /// Tell the command whether to color its output
+    pub fn color(mut self, policy: ColorPolicy) -> Self {
+        self.options.color = Some(policy);
+        self
+    }
+
+    /// The finished options
+    pub fn build(self) -> RunOptions {
+        self.options
+    }
+}
+
+impl From<RunOptionsBuilder> for RunOptions {
+    fn from(builder: RunOptionsBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// Locale whose output doesn't depend on the user's language settings
+///
+/// `C.UTF-8` keeps the `C` locale's formats while still treating text as
+/// UTF-8.
+pub const STABLE_LOCALE: &str = "C.UTF-8";
+
+fn set_locale(env: &mut Option<HashMap<String, String>>, locale: String) {
+    let env = env.get_or_insert_with(HashMap::new);
+    env.insert("LANGUAGE".to_string(), String::new());
+    env.insert("LANG".to_string(), locale.clone());
+    env.insert("LC_ALL".to_string(), locale);
+}
+
+/// Read a boolean environment variable
+fn env_flag(name: &str) -> Option<bool> {
+    parse_flag(&env::var(name).ok()?)
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_flag() {
+        assert_eq!(parse_flag("true"), Some(true));
+        assert_eq!(parse_flag(" ON "), Some(true));
+        assert_eq!(parse_flag("0"), Some(false));
+        assert_eq!(parse_flag("No"), Some(false));
+        assert_eq!(parse_flag("sometimes"), None);
+    }
+
+    #[test]
+    fn test_builder_sets_fields() {
+        let options = RunOptions::builder()
+            .mirror(false)
+            .cwd("/tmp")
+            .env("A", "1")
+            .envs([("B", "2")])
+            .timeout(Duration::from_secs(5))
+            .build();
+        assert!(!options.mirror && options.capture);
+        assert_eq!(options.cwd, Some(PathBuf::from("/tmp")));
+        let env = options.env.unwrap();
+        assert_eq!((env["A"].as_str(), env["B"].as_str()), ("1", "2"));
+        assert_eq!(options.timeout, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_locale_sets_locale_variables() {
+        let options = RunOptions::builder().env("A", "1").stable_locale().build();
+        let env = options.env.unwrap();
+        assert_eq!(env["LC_ALL"], STABLE_LOCALE);
+        assert_eq!(env["LANG"], STABLE_LOCALE);
+        assert_eq!(env["LANGUAGE"], "");
+        assert_eq!(env["A"], "1");
+
+        let env = RunOptions::default().locale("de_DE.UTF-8").env.unwrap();
+        assert_eq!(env["LC_ALL"], "de_DE.UTF-8");
+    }
+
+    #[test]
+    fn test_quiet_and_loud_only_change_mirror() {
+        let options = RunOptions {
+            capture: false,
+            ..Default::default()
+        };
+        let quiet = options.quiet();
+        assert!(!quiet.mirror && !quiet.capture);
+        assert!(quiet.loud().mirror);
+    }
+}
+
+/// Standard input options
+#[derive(Debug, Clone)]
+pub enum StdinOption {
+    /// Inherit from parent process
+    Inherit,
+    /// Pipe (allow writing to stdin)
+    Pipe,
+    /// Provide string content
+    Content(String),
+    /// Null device
+    Null,
+    /// Connect a file to stdin, like `< path` in a shell
+    ///
+    /// A relative path is resolved against [`RunOptions::cwd`]. The file is
+    /// passed to the child process directly rather than read into memory;
+    /// virtual commands, which take stdin as a string, get its contents.
+    File(PathBuf),
+}
@@ -0,0 +1,191 @@
+//! OpenTelemetry spans for commands, and trace context for their processes
+//!
+//! With the `otel` feature and [`set_propagation`] turned on, a command run
+//! while an OpenTelemetry span is active gets a child span of its own, named
+//! `command`, that ends with its exit code. Spawned processes receive the
+//! span's context in `TRACEPARENT` and `TRACESTATE` (the W3C Trace Context
+//! headers, named as the OpenTelemetry environment carrier specifies), so
+//! instrumented tools they run join the same trace:
+//!
+//! ```rust,ignore
+//! use command_stream::otel;
+//! use opentelemetry::context::FutureExt;
+//! use opentelemetry::trace::{TraceContextExt, Tracer};
+//! use opentelemetry::Context;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! otel::set_propagation(true);
+//! let tracer = opentelemetry::global::tracer("deploy");
+//! let span = tracer.start("deploy");
+//! let cx = Context::current_with_span(span);
+//! command_stream::run("./migrate.sh").with_context(cx).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Spans come from the global tracer provider, so install an OpenTelemetry
+//! SDK to export them. Without an active span nothing is created or passed
+//! on.
+
+use crate::{CommandResult, Result};
+
+/// A command's span, while it runs
+#[cfg(feature = "otel")]
+pub(crate) struct CommandSpan {
+    cx: opentelemetry::Context,
+}
+
+#[cfg(feature = "otel")]
+static PROPAGATION: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Turn spans for commands, and passing their context to spawned processes,
+/// on or off; off by default
+#[cfg(feature = "otel")]
+pub fn set_propagation(enabled: bool) {
+    PROPAGATION.store(enabled, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Whether commands get spans and pass their context on
+#[cfg(feature = "otel")]
+pub fn is_propagation_enabled() -> bool {
+    PROPAGATION.load(std::sync::atomic::Ordering::SeqCst)
+}
+
+#[cfg(feature = "otel")]
+impl CommandSpan {
+    /// Start a span for `command` under the current context, if propagation
+    /// is on and a span is active
+    pub(crate) fn start(command: &str) -> Option<CommandSpan> {
+        use opentelemetry::trace::{SpanKind, TraceContextExt, Tracer};
+        use opentelemetry::{Context, KeyValue};
+
+        let parent = Context::current();
+        if !is_propagation_enabled() || !parent.has_active_span() {
+            return None;
+        }
+        let tracer = opentelemetry::global::tracer("command-stream");
+        let span = tracer
+            .span_builder("command")
+            .with_kind(SpanKind::Internal)
+            .with_attributes([KeyValue::new(
+                "process.command_line",
+                crate::redact::redact(command).into_owned(),
+            )])
+            .start_with_context(&tracer, &parent);
+        Some(CommandSpan {
+            cx: parent.with_span(span),
+        })
+    }
+
+    /// The environment variables that carry this span's context
+    pub(crate) fn env(&self) -> Vec<(String, String)> {
+        use opentelemetry::trace::TraceContextExt;
+
+        let span = self.cx.span();
+        let context = span.span_context();
+        if !context.is_valid() {
+            return Vec::new();
+        }
+        let mut env = vec![(
+            "TRACEPARENT".to_string(),
+            format!(
+                "00-{}-{}-{:02x}",
+                context.trace_id(),
+                context.span_id(),
+                context.trace_flags().to_u8()
+            ),
+        )];
+        let state = context.trace_state().header();
+        if !state.is_empty() {
+            env.push(("TRACESTATE".to_string(), state));
+        }
+        env
+    }
+
+    /// End the span, recording how the command ended
+    pub(crate) fn end(self, outcome: &Result<CommandResult>) {
+        use opentelemetry::trace::{Status, TraceContextExt};
+        use opentelemetry::KeyValue;
+
+        let span = self.cx.span();
+        match outcome {
+            Ok(result) => {
+                span.set_attribute(KeyValue::new("process.exit.code", i64::from(result.code)));
+                if !result.is_success() {
+                    span.set_status(Status::error(format!("exit code {}", result.code)));
+                }
+            }
+            Err(e) => span.set_status(Status::error(e.to_string())),
+        }
+        span.end();
+    }
+}
+
+/// Without the `otel` feature no span is ever started
+#[cfg(not(feature = "otel"))]
+pub(crate) enum CommandSpan {}
+
+#[cfg(not(feature = "otel"))]
+impl CommandSpan {
+    pub(crate) fn start(_command: &str) -> Option<CommandSpan> {
+        None
+    }
+
+    pub(crate) fn env(&self) -> Vec<(String, String)> {
+        match *self {}
+    }
+
+    pub(crate) fn end(self, _outcome: &Result<CommandResult>) {
+        match self {}
+    }
+}
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use super::*;
+    use crate::{ProcessRunner, RunOptions};
+    use opentelemetry::context::FutureExt;
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+    use opentelemetry::Context;
+
+    async fn traceparent() -> String {
+        let options = RunOptions {
+            mirror: false,
+            ..RunOptions::default()
+        };
+        let result = ProcessRunner::new("sh -c 'echo \"$TRACEPARENT\"'", options)
+            .run()
+            .await
+            .unwrap();
+        result.stdout.trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_traceparent_follows_the_active_span() {
+        let trace_id = TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap();
+        let parent = Context::new().with_remote_span_context(SpanContext::new(
+            trace_id,
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        ));
+
+        set_propagation(true);
+        let inside = traceparent().with_context(parent.clone()).await;
+        let outside = traceparent().await;
+        set_propagation(false);
+        let disabled = traceparent().with_context(parent).await;
+
+        assert!(
+            inside.starts_with(&format!("00-{}-", trace_id)),
+            "{}",
+            inside
+        );
+        assert!(inside.ends_with("-01"), "{}", inside);
+        assert_eq!(outside, "");
+        assert_eq!(disabled, "");
+    }
+}
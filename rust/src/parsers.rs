@@ -0,0 +1,591 @@
+//! Typed parsers for the output of well-known commands
+//!
+//! Each type here implements [`FromOutput`], so a command's result can be
+//! turned into structs with [`CommandResult::parse`] instead of splitting
+//! strings by hand:
+//!
+//! ```rust,no_run
+//! use command_stream::parsers::LsEntries;
+//! use command_stream::RunOptions;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let options = RunOptions::default().quiet().stable_locale();
+//! let entries: LsEntries = command_stream::exec("ls -l", options).await?.parse()?;
+//! for entry in entries.iter().filter(|entry| entry.is_dir()) {
+//!     println!("{} ({} bytes)", entry.name, entry.size);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The parsers expect the `C` locale's formats; run the commands with
+//! [`RunOptions::stable_locale`](crate::RunOptions::stable_locale) so dates
+//! and headers aren't translated.
+
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use crate::{CommandResult, Error, Result};
+
+/// A type that can be parsed from a command's stdout
+pub trait FromOutput: Sized {
+    /// Parse `output`, the full stdout of the command
+    fn from_output(output: &str) -> Result<Self>;
+}
+
+impl CommandResult {
+    /// Parse stdout into `T`, e.g. `result.parse::<GitStatus>()`
+    pub fn parse<T: FromOutput>(&self) -> Result<T> {
+        T::from_output(&self.stdout)
+    }
+}
+
+fn parse_error(command: &str, line: &str) -> Error {
+    Error::ParseError(format!("{}: unexpected line '{}'", command, line))
+}
+
+/// Whitespace-separated words of `line` with the byte offset just past each
+fn words(line: &str) -> impl Iterator<Item = (&str, usize)> {
+    line.split_whitespace().map(move |word| {
+        let end = word.as_ptr() as usize - line.as_ptr() as usize + word.len();
+        (word, end)
+    })
+}
+
+macro_rules! list_type {
+    ($(#[$meta:meta])* $name:ident, $item:ty) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct $name(pub Vec<$item>);
+
+        impl Deref for $name {
+            type Target = [$item];
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        impl IntoIterator for $name {
+            type Item = $item;
+            type IntoIter = std::vec::IntoIter<$item>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.0.into_iter()
+            }
+        }
+    };
+}
+
+// ============================================================================
+// ls -l
+// ============================================================================
+
+/// One line of `ls -l` output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LsEntry {
+    /// Type and permission string, e.g. `drwxr-xr-x`
+    pub permissions: String,
+    /// Hard link count
+    pub links: u64,
+    pub owner: String,
+    pub group: String,
+    /// Size in bytes; 0 for device files
+    pub size: u64,
+    /// Modification time as printed, e.g. `Oct 16 12:00` or `2026-10-16 12:00`
+    pub modified: String,
+    pub name: String,
+    /// Where a symbolic link points
+    pub link_target: Option<String>,
+}
+
+impl LsEntry {
+    /// Whether this is a directory
+    pub fn is_dir(&self) -> bool {
+        self.permissions.starts_with('d')
+    }
+
+    /// Whether this is a symbolic link
+    pub fn is_symlink(&self) -> bool {
+        self.permissions.starts_with('l')
+    }
+}
+
+list_type!(
+    /// The entries listed by `ls -l`
+    ///
+    /// `total` lines and the `dir:` headers printed when listing several
+    /// directories are skipped.
+    LsEntries,
+    LsEntry
+);
+
+impl FromOutput for LsEntries {
+    fn from_output(output: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        for line in output.lines() {
+            let is_entry =
+                line.len() >= 10 && line.starts_with(['-', 'd', 'l', 'c', 'b', 'p', 's']);
+            if !is_entry {
+                continue;
+            }
+            entries.push(parse_ls_line(line).ok_or_else(|| parse_error("ls", line))?);
+        }
+        Ok(LsEntries(entries))
+    }
+}
+
+fn parse_ls_line(line: &str) -> Option<LsEntry> {
+    let mut words = words(line).peekable();
+    let permissions = words.next()?.0.to_string();
+    let links = words.next()?.0.parse().ok()?;
+    let owner = words.next()?.0.to_string();
+    let group = words.next()?.0.to_string();
+
+    // Device files show `major, minor` instead of a size
+    let (size_word, _) = words.next()?;
+    let size = if size_word.ends_with(',') {
+        words.next()?;
+        0
+    } else {
+        size_word.parse().ok()?
+    };
+
+    let (first, first_end) = words.next()?;
+    let date_start = first_end - first.len();
+    let is_iso = first.len() == 10 && first.as_bytes()[4] == b'-';
+    let (_, mut date_end) = words.next()?;
+    // `full-iso` adds a zone offset after the time; the locale format has a
+    // third part (year or time)
+    let has_third_part = match words.peek() {
+        Some((zone, _)) if is_iso => {
+            zone.len() == 5
+                && zone.starts_with(['+', '-'])
+                && zone[1..].bytes().all(|b| b.is_ascii_digit())
+        }
+        Some(_) => !is_iso,
+        None => false,
+    };
+    if has_third_part {
+        date_end = words.next()?.1;
+    }
+
+    let name = line[date_end..].trim_start();
+    if name.is_empty() {
+        return None;
+    }
+    let (name, link_target) = match name.split_once(" -> ") {
+        Some((name, target)) if permissions.starts_with('l') => {
+            (name.to_string(), Some(target.to_string()))
+        }
+        _ => (name.to_string(), None),
+    };
+
+    Some(LsEntry {
+        permissions,
+        links,
+        owner,
+        group,
+        size,
+        modified: line[date_start..date_end].to_string(),
+        name,
+        link_target,
+    })
+}
+
+// ============================================================================
+// df
+// ============================================================================
+
+/// One file system reported by `df` or `df -h`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DfRow {
+    pub filesystem: String,
+    /// Total size as printed: 1K blocks, or `20G` with `-h`
+    pub size: String,
+    pub used: String,
+    pub available: String,
+    /// The `Use%` column, or `None` when `df` prints `-`
+    pub use_percent: Option<u8>,
+    pub mounted_on: String,
+}
+
+list_type!(
+    /// The file systems listed by `df`
+    DfRows,
+    DfRow
+);
+
+impl FromOutput for DfRows {
+    fn from_output(output: &str) -> Result<Self> {
+        let mut rows = Vec::new();
+        let mut pending = String::new();
+        for line in output.lines().skip(1) {
+            // A long file system name is printed on a line of its own
+            let count = words(line).count();
+            if count == 0 {
+                continue;
+            }
+            if count == 1 {
+                pending = line.trim().to_string();
+                continue;
+            }
+            let joined = if pending.is_empty() {
+                line.to_string()
+            } else {
+                format!("{} {}", std::mem::take(&mut pending), line.trim())
+            };
+            rows.push(parse_df_line(&joined).ok_or_else(|| parse_error("df", line))?);
+        }
+        Ok(DfRows(rows))
+    }
+}
+
+fn parse_df_line(line: &str) -> Option<DfRow> {
+    let mut words = words(line);
+    let filesystem = words.next()?.0.to_string();
+    let size = words.next()?.0.to_string();
+    let used = words.next()?.0.to_string();
+    let available = words.next()?.0.to_string();
+    let (percent, percent_end) = words.next()?;
+    let use_percent = match percent {
+        "-" => None,
+        percent => Some(percent.strip_suffix('%')?.parse().ok()?),
+    };
+    let mounted_on = line[percent_end..].trim();
+    if mounted_on.is_empty() {
+        return None;
+    }
+    Some(DfRow {
+        filesystem,
+        size,
+        used,
+        available,
+        use_percent,
+        mounted_on: mounted_on.to_string(),
+    })
+}
+
+// ============================================================================
+// ps
+// ============================================================================
+
+/// One process listed by `ps`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PsRow {
+    pub pid: u32,
+    /// The `USER` column, when present
+    pub user: Option<String>,
+    /// The `%CPU` column, when present
+    pub cpu: Option<f32>,
+    /// The `%MEM` column, when present
+    pub mem: Option<f32>,
+    /// The last column (`CMD`, `COMMAND`, `ARGS`...), with its spaces kept
+    pub command: String,
+    /// Every column by its header, including those above
+    pub columns: HashMap<String, String>,
+}
+
+list_type!(
+    /// The processes listed by `ps`, read according to its header line
+    ///
+    /// Any column selection works (`ps`, `ps aux`, `ps -eo pid,comm`) as
+    /// long as it includes `PID`; the last column may contain spaces.
+    PsRows,
+    PsRow
+);
+
+impl FromOutput for PsRows {
+    fn from_output(output: &str) -> Result<Self> {
+        let mut lines = output.lines().filter(|line| !line.trim().is_empty());
+        let Some(header) = lines.next() else {
+            return Ok(PsRows::default());
+        };
+        let headers: Vec<&str> = header.split_whitespace().collect();
+        if !headers.contains(&"PID") {
+            return Err(parse_error("ps", header));
+        }
+
+        let mut rows = Vec::new();
+        for line in lines {
+            let mut columns = HashMap::new();
+            let mut words = words(line);
+            let mut rest_start = 0;
+            for name in &headers[..headers.len() - 1] {
+                let (value, end) = words.next().ok_or_else(|| parse_error("ps", line))?;
+                columns.insert(name.to_string(), value.to_string());
+                rest_start = end;
+            }
+            let command = line[rest_start..].trim().to_string();
+            columns.insert(headers[headers.len() - 1].to_string(), command.clone());
+
+            rows.push(PsRow {
+                pid: number(&columns, "PID").ok_or_else(|| parse_error("ps", line))?,
+                user: columns.get("USER").cloned(),
+                cpu: number(&columns, "%CPU"),
+                mem: number(&columns, "%MEM"),
+                command,
+                columns,
+            });
+        }
+        Ok(PsRows(rows))
+    }
+}
+
+fn number<T: std::str::FromStr>(columns: &HashMap<String, String>, name: &str) -> Option<T> {
+    columns.get(name).and_then(|value| value.parse().ok())
+}
+
+// ============================================================================
+// git status --porcelain
+// ============================================================================
+
+/// One path reported by `git status --porcelain`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitStatusEntry {
+    /// Status in the index (`X`), e.g. `M`, `A`, `R`, `?`
+    pub index: char,
+    /// Status in the working tree (`Y`)
+    pub worktree: char,
+    pub path: String,
+    /// The previous path of a renamed or copied file
+    pub orig_path: Option<String>,
+}
+
+impl GitStatusEntry {
+    /// Whether the path is not tracked by git
+    pub fn is_untracked(&self) -> bool {
+        self.index == '?'
+    }
+
+    /// Whether the path has changes staged in the index
+    pub fn is_staged(&self) -> bool {
+        !matches!(self.index, ' ' | '?' | '!') && !self.is_conflicted()
+    }
+
+    /// Whether the path has unstaged changes in the working tree
+    pub fn is_modified(&self) -> bool {
+        !matches!(self.worktree, ' ' | '?' | '!') && !self.is_conflicted()
+    }
+
+    /// Whether the path has a merge conflict
+    pub fn is_conflicted(&self) -> bool {
+        self.index == 'U'
+            || self.worktree == 'U'
+            || (self.index, self.worktree) == ('A', 'A')
+            || (self.index, self.worktree) == ('D', 'D')
+    }
+}
+
+/// Output of `git status --porcelain`, optionally with `-b`/`--branch`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitStatus {
+    /// Current branch, from the `-b` header; `None` when detached or not
+    /// requested
+    pub branch: Option<String>,
+    /// The upstream branch, e.g. `origin/main`
+    pub upstream: Option<String>,
+    /// Commits ahead of the upstream
+    pub ahead: u32,
+    /// Commits behind the upstream
+    pub behind: u32,
+    pub entries: Vec<GitStatusEntry>,
+}
+
+impl GitStatus {
+    /// Whether nothing is staged, modified or untracked
+    pub fn is_clean(&self) -> bool {
+        self.entries.iter().all(|entry| entry.index == '!')
+    }
+}
+
+impl FromOutput for GitStatus {
+    fn from_output(output: &str) -> Result<Self> {
+        let mut status = GitStatus::default();
+        for line in output.lines().filter(|line| !line.is_empty()) {
+            if let Some(header) = line.strip_prefix("## ") {
+                parse_git_branch(header, &mut status);
+                continue;
+            }
+
+            let mut chars = line.chars();
+            let (Some(index), Some(worktree), Some(' ')) =
+                (chars.next(), chars.next(), chars.next())
+            else {
+                return Err(parse_error("git status", line));
+            };
+            let paths = chars.as_str();
+            let (orig_path, path) = match paths.split_once(" -> ") {
+                Some((from, to)) if matches!(index, 'R' | 'C') => (Some(from), to),
+                _ => (None, paths),
+            };
+            status.entries.push(GitStatusEntry {
+                index,
+                worktree,
+                path: unquote_git_path(path),
+                orig_path: orig_path.map(unquote_git_path),
+            });
+        }
+        Ok(status)
+    }
+}
+
+fn parse_git_branch(header: &str, status: &mut GitStatus) {
+    let (names, tracking) = match header.split_once(" [") {
+        Some((names, tracking)) => (names, tracking.trim_end_matches(']')),
+        None => (header, ""),
+    };
+    for part in tracking.split(", ") {
+        if let Some(count) = part.strip_prefix("ahead ") {
+            status.ahead = count.parse().unwrap_or(0);
+        } else if let Some(count) = part.strip_prefix("behind ") {
+            status.behind = count.parse().unwrap_or(0);
+        }
+    }
+
+    if let Some(branch) = names
+        .strip_prefix("No commits yet on ")
+        .or_else(|| names.strip_prefix("Initial commit on "))
+    {
+        status.branch = Some(branch.to_string());
+    } else if !names.starts_with("HEAD (no branch)") {
+        let (branch, upstream) = match names.split_once("...") {
+            Some((branch, upstream)) => (branch, Some(upstream.to_string())),
+            None => (names, None),
+        };
+        status.branch = Some(branch.to_string());
+        status.upstream = upstream;
+    }
+}
+
+/// Undo git's C-style quoting of paths with special characters
+fn unquote_git_path(path: &str) -> String {
+    let Some(inner) = path
+        .strip_prefix('"')
+        .and_then(|path| path.strip_suffix('"'))
+    else {
+        return path.to_string();
+    };
+
+    let mut bytes = Vec::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some(digit @ '0'..='7') => {
+                // Non-ASCII bytes are written as three octal digits
+                let octal: String = std::iter::once(digit)
+                    .chain(chars.by_ref().take(2))
+                    .collect();
+                bytes.push(u8::from_str_radix(&octal, 8).unwrap_or(b'?'));
+            }
+            Some(other) => {
+                let mut buffer = [0; 4];
+                bytes.extend_from_slice(other.encode_utf8(&mut buffer).as_bytes());
+            }
+            None => bytes.push(b'\\'),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ls_entries() {
+        let output = "total 12\n\
+            drwxr-xr-x  2 alice staff  4096 Oct 16 12:00 src\n\
+            -rw-r--r--  1 alice staff   220 Jan  3  2025 my notes.txt\n\
+            lrwxrwxrwx  1 alice staff     7 2026-10-16 12:00 latest -> v1.2.3\n\
+            crw-rw-rw-  1 root  root   1, 3 2026-10-16 12:00:00.000000000 +0000 null\n";
+        let entries = LsEntries::from_output(output).unwrap();
+        assert_eq!(entries.len(), 4);
+
+        assert!(entries[0].is_dir());
+        assert_eq!(entries[0].modified, "Oct 16 12:00");
+        assert_eq!((entries[0].links, entries[0].size), (2, 4096));
+
+        assert_eq!(entries[1].name, "my notes.txt");
+        assert_eq!(entries[1].modified, "Jan  3  2025");
+
+        assert!(entries[2].is_symlink());
+        assert_eq!(entries[2].modified, "2026-10-16 12:00");
+        assert_eq!(entries[2].name, "latest");
+        assert_eq!(entries[2].link_target.as_deref(), Some("v1.2.3"));
+
+        assert_eq!(entries[3].name, "null");
+        assert_eq!(entries[3].size, 0);
+
+        assert!(LsEntries::from_output("-rw-r--r-- x alice staff 1 Oct 16 12:00 f").is_err());
+    }
+
+    #[test]
+    fn test_df_rows() {
+        let output = "Filesystem      Size  Used Avail Use% Mounted on\n\
+            /dev/sda1        20G  8.1G   11G  43% /\n\
+            /dev/mapper/very-long-volume-name\n\
+            \x20                100G   40G   60G  40% /mnt/my data\n\
+            proc               0     0     0    - /proc\n";
+        let rows = DfRows::from_output(output).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].size, "20G");
+        assert_eq!(rows[0].use_percent, Some(43));
+        assert_eq!(rows[1].filesystem, "/dev/mapper/very-long-volume-name");
+        assert_eq!(rows[1].mounted_on, "/mnt/my data");
+        assert_eq!(rows[2].use_percent, None);
+    }
+
+    #[test]
+    fn test_ps_rows() {
+        let output = "USER   PID %CPU %MEM    VSZ   RSS TTY STAT START TIME COMMAND\n\
+            root     1  0.0  0.1 167000 11000 ?   Ss   Oct15 0:03 /sbin/init splash\n\
+            alice  420 12.5  2.0 900000 80000 pts/0 Sl 10:00 1:00 cargo test --workspace\n";
+        let rows = PsRows::from_output(output).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].pid, 1);
+        assert_eq!(rows[0].command, "/sbin/init splash");
+        assert_eq!(rows[1].user.as_deref(), Some("alice"));
+        assert_eq!(rows[1].cpu, Some(12.5));
+        assert_eq!(rows[1].columns["TTY"], "pts/0");
+
+        let rows = PsRows::from_output("  PID COMM\n   7 sh\n").unwrap();
+        assert_eq!((rows[0].pid, rows[0].command.as_str()), (7, "sh"));
+        assert!(PsRows::from_output("USER COMMAND\nroot init\n").is_err());
+    }
+
+    #[test]
+    fn test_git_status() {
+        let output = "## main...origin/main [ahead 2, behind 1]\n\
+            M  staged.rs\n\
+            \x20M modified.rs\n\
+            R  old.rs -> new.rs\n\
+            UU conflict.rs\n\
+            ?? \"caf\\303\\251 notes.txt\"\n";
+        let status = GitStatus::from_output(output).unwrap();
+        assert_eq!(status.branch.as_deref(), Some("main"));
+        assert_eq!(status.upstream.as_deref(), Some("origin/main"));
+        assert_eq!((status.ahead, status.behind), (2, 1));
+        assert!(!status.is_clean());
+
+        let entries = &status.entries;
+        assert!(entries[0].is_staged() && !entries[0].is_modified());
+        assert!(entries[1].is_modified() && !entries[1].is_staged());
+        assert_eq!(entries[2].path, "new.rs");
+        assert_eq!(entries[2].orig_path.as_deref(), Some("old.rs"));
+        assert!(entries[3].is_conflicted() && !entries[3].is_staged());
+        assert!(entries[4].is_untracked());
+        assert_eq!(entries[4].path, "café notes.txt");
+
+        let status = GitStatus::from_output("## HEAD (no branch)\n").unwrap();
+        assert!(status.branch.is_none() && status.is_clean());
+        assert!(GitStatus::from_output("garbage").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+//! Path translation between Unix, Windows and WSL conventions
+//!
+//! Commands built on one platform sometimes run on another: a Windows
+//! program launched from WSL wants `C:\` paths, while a Linux tool under WSL
+//! wants `/mnt/c/`. [`translate_path`] rewrites a path for a target
+//! [`PathStyle`], and [`PlatformPath`] does so when it is interpolated, which
+//! makes it the opt-in for `cmd!` values:
+//!
+//! ```rust
+//! use command_stream::macros::build_template;
+//! use command_stream::paths::{PathStyle, PlatformPath};
+//!
+//! let src = PlatformPath::with_style(r"C:\Users\me\notes.txt", PathStyle::Wsl);
+//! let dst = PlatformPath::with_style("/mnt/d/backup", PathStyle::Windows);
+//! let command = build_template("cp {} {}", &[src.to_string(), dst.to_string()], &[]);
+//! assert_eq!(command, r"cp /mnt/c/Users/me/notes.txt 'D:\backup'");
+//! ```
+//!
+//! Values that don't look like paths (no separator or drive prefix, URLs,
+//! options such as `--out=/tmp/x`) pass through [`PlatformPath`] unchanged.
+
+use std::fmt;
+use std::path::Path;
+
+/// Path conventions of the platform a command runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathStyle {
+    /// `/` separators; drive prefixes are left alone
+    Unix,
+    /// `\` separators, with WSL's `/mnt/<drive>` mapped back to `<DRIVE>:`
+    Windows,
+    /// `/` separators, with `<drive>:` mapped to WSL's `/mnt/<drive>`
+    Wsl,
+}
+
+impl PathStyle {
+    /// The style of the platform this process runs on
+    pub fn native() -> Self {
+        if cfg!(windows) {
+            PathStyle::Windows
+        } else {
+            PathStyle::Unix
+        }
+    }
+}
+
+/// Rewrite `path` for `style`
+///
+/// ```rust
+/// use command_stream::paths::{translate_path, PathStyle};
+///
+/// assert_eq!(translate_path("/mnt/c/tools/app.exe", PathStyle::Windows), r"C:\tools\app.exe");
+/// assert_eq!(translate_path(r"D:\data\in.csv", PathStyle::Wsl), "/mnt/d/data/in.csv");
+/// assert_eq!(translate_path(r"src\main.rs", PathStyle::Unix), "src/main.rs");
+/// ```
+pub fn translate_path(path: &str, style: PathStyle) -> String {
+    match style {
+        PathStyle::Unix => path.replace('\\', "/"),
+        PathStyle::Windows => match wsl_mount(path) {
+            Some((drive, rest)) => {
+                format!(
+                    "{}:\\{}",
+                    drive.to_ascii_uppercase(),
+                    rest.replace('/', "\\")
+                )
+            }
+            None => path.replace('/', "\\"),
+        },
+        PathStyle::Wsl => match drive_prefix(path) {
+            Some((drive, rest)) => {
+                let rest = rest.trim_start_matches(['\\', '/']).replace('\\', "/");
+                let mut translated = format!("/mnt/{}", drive.to_ascii_lowercase());
+                if !rest.is_empty() {
+                    translated.push('/');
+                    translated.push_str(&rest);
+                }
+                translated
+            }
+            None => path.replace('\\', "/"),
+        },
+    }
+}
+
+/// Whether `value` looks like a file system path rather than some other
+/// argument
+pub fn is_path_like(value: &str) -> bool {
+    !value.starts_with('-')
+        && !value.contains("://")
+        && (value.contains(['/', '\\']) || drive_prefix(value).is_some())
+}
+
+/// The drive letter and remainder of a path like `C:\x` or `c:/x`
+fn drive_prefix(path: &str) -> Option<(char, &str)> {
+    let mut chars = path.chars();
+    let drive = chars.next().filter(char::is_ascii_alphabetic)?;
+    let rest = chars.as_str().strip_prefix(':')?;
+    (rest.is_empty() || rest.starts_with(['\\', '/'])).then_some((drive, rest))
+}
+
+/// The drive letter and remainder of a WSL path like `/mnt/c/x`
+fn wsl_mount(path: &str) -> Option<(char, &str)> {
+    let rest = path.strip_prefix("/mnt/")?;
+    let mut chars = rest.chars();
+    let drive = chars.next().filter(char::is_ascii_alphabetic)?;
+    let rest = chars.as_str();
+    if rest.is_empty() {
+        return Some((drive, ""));
+    }
+    rest.strip_prefix('/').map(|rest| (drive, rest))
+}
+
+/// A value that is translated to a [`PathStyle`] when formatted, if it looks
+/// like a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlatformPath {
+    value: String,
+    style: PathStyle,
+}
+
+impl PlatformPath {
+    /// Translate `path` to this platform's conventions
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self::with_style(path, PathStyle::native())
+    }
+
+    /// Translate `path` to `style`
+    pub fn with_style(path: impl AsRef<Path>, style: PathStyle) -> Self {
+        PlatformPath {
+            value: path.as_ref().to_string_lossy().into_owned(),
+            style,
+        }
+    }
+}
+
+impl fmt::Display for PlatformPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_path_like(&self.value) {
+            f.write_str(&translate_path(&self.value, self.style))
+        } else {
+            f.write_str(&self.value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate_path() {
+        for (path, style, expected) in [
+            ("/mnt/c", PathStyle::Windows, "C:\\"),
+            ("/mnt/c/a/b", PathStyle::Windows, r"C:\a\b"),
+            ("/mnt/cd/a", PathStyle::Windows, r"\mnt\cd\a"),
+            ("rel/dir", PathStyle::Windows, r"rel\dir"),
+            (r"C:\", PathStyle::Wsl, "/mnt/c"),
+            ("c:/a/b", PathStyle::Wsl, "/mnt/c/a/b"),
+            (r"\\server\share", PathStyle::Unix, "//server/share"),
+            (r"C:\a", PathStyle::Unix, "C:/a"),
+            ("/usr/bin", PathStyle::Wsl, "/usr/bin"),
+        ] {
+            assert_eq!(
+                translate_path(path, style),
+                expected,
+                "{} {:?}",
+                path,
+                style
+            );
+        }
+    }
+
+    #[test]
+    fn test_only_path_like_values_are_translated() {
+        for value in ["hello", "--out=/tmp/x", "https://example.com/a", "a:b"] {
+            assert!(!is_path_like(value), "{}", value);
+            assert_eq!(
+                PlatformPath::with_style(value, PathStyle::Windows).to_string(),
+                value
+            );
+        }
+        assert!(is_path_like("C:") && is_path_like("a/b") && is_path_like(r"a\b"));
+    }
+}
@@ -27,13 +27,23 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::process::Stdio;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::process::Command;
-
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::task::JoinHandle;
+
+use crate::confirm::{confirm, ConfirmationGate};
+use crate::console;
+use crate::policy;
+use crate::shell::{find_shell, ShellChoice};
+use crate::shell_parser::{parse_shell_command, unquote_word, ParsedCommand};
 use crate::trace::trace_lazy;
-use crate::{CommandResult, Result, RunOptions, StdinOption};
+use crate::{
+    CancellationToken, CommandResult, Error, ExecutionPolicy, Result, RunOptions, StdinOption,
+    TrackedChild,
+};
 
-/// A pipeline of commands to be executed sequentially
+/// A pipeline of commands, run concurrently
 ///
 /// Each command's stdout is piped to the next command's stdin.
 #[derive(Debug, Clone)]
@@ -50,6 +60,12 @@ pub struct Pipeline {
     mirror: bool,
     /// Whether to capture output
     capture: bool,
+    /// Token that stops the pipeline when cancelled
+    cancel: Option<CancellationToken>,
+    /// Rules every stage must pass; `None` uses the global policy
+    policy: Option<Arc<ExecutionPolicy>>,
+    /// Gate asked before destructive pipelines; `None` uses the global gate
+    confirm: Option<Arc<ConfirmationGate>>,
 }
 
 impl Default for Pipeline {
@@ -68,10 +84,14 @@ impl Pipeline {
             env: None,
             mirror: true,
             capture: true,
+            cancel: None,
+            policy: None,
+            confirm: None,
         }
     }
 
     /// Add a command to the pipeline
+    #[allow(clippy::should_implement_trait)]
     pub fn add(mut self, command: impl Into<String>) -> Self {
         self.commands.push(command.into());
         self
@@ -95,6 +115,30 @@ impl Pipeline {
         self
     }
 
+    /// Stop the pipeline when `token` is cancelled
+    ///
+    /// The running stage is killed and [`run`](Self::run) returns
+    /// [`Error::Cancelled`].
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Check the pipeline against `policy` before any stage runs
+    ///
+    /// The stages are checked together, as `a | b | c`, so a maximum
+    /// pipeline length applies too.
+    pub fn policy(mut self, policy: ExecutionPolicy) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Ask `gate` before running the pipeline if it looks destructive
+    pub fn confirm(mut self, gate: impl Into<Arc<ConfirmationGate>>) -> Self {
+        self.confirm = Some(gate.into());
+        self
+    }
+
     /// Set whether to mirror output to stdout/stderr
     pub fn mirror_output(mut self, mirror: bool) -> Self {
         self.mirror = mirror;
@@ -108,162 +152,262 @@ impl Pipeline {
     }
 
     /// Execute the pipeline and return the result
+    ///
+    /// The stages run at the same time, each spawned command reading the
+    /// previous one's stdout through an OS pipe, so output flows through
+    /// without being held in memory. Virtual commands take their input as a
+    /// whole, so a virtual stage reads everything before it runs.
+    ///
+    /// The result has the last stage's stdout and every stage's stderr, in
+    /// stage order. Its exit code is that of the first stage that failed, or
+    /// of the last stage; a stage killed by `SIGPIPE` because a later one
+    /// stopped reading doesn't count as failed.
+    ///
+    /// A stage whose program isn't installed makes the pipeline fail with
+    /// [`Error::PipelineStage`] holding [`Error::CommandNotFound`], so the
+    /// caller can name what is missing.
     pub async fn run(self) -> Result<CommandResult> {
         if self.commands.is_empty() {
             return Ok(CommandResult {
                 stdout: String::new(),
                 stderr: "No commands in pipeline".to_string(),
                 code: 1,
+                ..Default::default()
             });
         }
 
+        let whole = self.commands.join(" | ");
+        policy::enforce(self.policy.as_ref(), &whole)?;
+        confirm(self.confirm.as_ref(), &whole).await?;
+
         trace_lazy("Pipeline", || {
             format!("Running pipeline with {} commands", self.commands.len())
         });
 
-        let mut current_stdin = self.stdin.clone();
-        let mut last_result = CommandResult {
-            stdout: String::new(),
-            stderr: String::new(),
-            code: 0,
+        let cancel = self.cancel.clone().unwrap_or_default();
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        let mut spawned = Vec::new();
+        let outcome = tokio::select! {
+            outcome = self.run_stages(&mut spawned, &cancel) => outcome,
+            _ = cancel.cancelled() => Err(Error::Cancelled),
         };
-        let mut accumulated_stderr = String::new();
+        if let Err(ref e) = outcome {
+            trace_lazy("Pipeline", || format!("Stopping pipeline: {}", e));
+            for stage in &mut spawned {
+                let _ = stage.child.start_kill();
+            }
+            for stage in &mut spawned {
+                let _ = stage.child.wait().await;
+            }
+        }
+        outcome
+    }
 
-        for (i, cmd_str) in self.commands.iter().enumerate() {
-            let is_last = i == self.commands.len() - 1;
+    /// Start every stage, read the last one's output and wait for them all
+    ///
+    /// Spawned stages are pushed to `spawned` as they start, so the caller
+    /// can kill them if this is abandoned.
+    async fn run_stages(
+        &self,
+        spawned: &mut Vec<SpawnedStage>,
+        cancel: &CancellationToken,
+    ) -> Result<CommandResult> {
+        let last = self.commands.len() - 1;
+        let mut input = match &self.stdin {
+            Some(content) => StageInput::Content(content.clone()),
+            None => StageInput::Null,
+        };
+        // Results of virtual stages; `None` marks a spawned stage, waited
+        // for once the output has been read
+        let mut finished = Vec::with_capacity(self.commands.len());
 
+        for (i, cmd_str) in self.commands.iter().enumerate() {
             trace_lazy("Pipeline", || {
                 format!(
-                    "Executing command {}/{}: {}",
+                    "Starting command {}/{}: {}",
                     i + 1,
                     self.commands.len(),
                     cmd_str
                 )
             });
 
-            // Check if this is a virtual command
-            let first_word = cmd_str.split_whitespace().next().unwrap_or("");
-            if crate::commands::are_virtual_commands_enabled() {
-                if let Some(result) = self
-                    .try_virtual_command(first_word, cmd_str, &current_stdin)
-                    .await
-                {
-                    if result.code != 0 {
-                        return Ok(CommandResult {
-                            stdout: result.stdout,
-                            stderr: accumulated_stderr + &result.stderr,
-                            code: result.code,
-                        });
+            if let Some((name, args)) = self.virtual_stage(cmd_str) {
+                let stdin = match std::mem::replace(&mut input, StageInput::Null) {
+                    StageInput::Null => None,
+                    StageInput::Content(content) => Some(content),
+                    StageInput::Pipe(mut stdout) => {
+                        let mut content = String::new();
+                        stdout.read_to_string(&mut content).await?;
+                        Some(content)
                     }
-                    current_stdin = Some(result.stdout.clone());
-                    accumulated_stderr.push_str(&result.stderr);
-                    last_result = result;
+                };
+                if let Some(result) = self.try_virtual_command(&name, args, &stdin, cancel).await {
+                    input = StageInput::Content(result.stdout.clone());
+                    finished.push(Some(result));
                     continue;
                 }
+                input = stdin.map_or(StageInput::Null, StageInput::Content);
             }
 
-            // Execute via shell
-            let shell = find_available_shell();
-            let mut cmd = Command::new(&shell.cmd);
-            for arg in &shell.args {
-                cmd.arg(arg);
+            let mut child =
+                self.spawn_stage(cmd_str, std::mem::replace(&mut input, StageInput::Null))?;
+            if i < last {
+                if let Some(stdout) = child.stdout.take() {
+                    input = StageInput::Pipe(stdout);
+                }
             }
-            cmd.arg(cmd_str);
-
-            // Configure stdio
-            cmd.stdin(Stdio::piped());
-            cmd.stdout(Stdio::piped());
-            cmd.stderr(Stdio::piped());
+            let stderr = child.stderr.take();
+            spawned.push(SpawnedStage {
+                _tracked: crate::state::track_spawned(&child),
+                child,
+                stderr: tokio::spawn(async move {
+                    let mut bytes = Vec::new();
+                    if let Some(mut stderr) = stderr {
+                        let _ = stderr.read_to_end(&mut bytes).await;
+                    }
+                    String::from_utf8_lossy(&bytes).into_owned()
+                }),
+            });
+            finished.push(None);
+        }
 
-            // Set working directory. Fall back to a valid directory when the
-            // inherited working directory has been deleted (issue #44).
-            if let Some(cwd) = crate::resolve_spawn_cwd(self.cwd.as_ref()) {
-                cmd.current_dir(cwd);
+        let mut stdout = String::new();
+        match finished[last] {
+            Some(ref result) => {
+                if self.mirror {
+                    print!("{}", console::for_terminal(&result.stdout));
+                }
+                if self.capture {
+                    stdout.push_str(&result.stdout);
+                }
             }
-
-            // Set environment
-            if let Some(ref env_vars) = self.env {
-                for (key, value) in env_vars {
-                    cmd.env(key, value);
+            None => {
+                if let Some(output) = spawned
+                    .last_mut()
+                    .and_then(|stage| stage.child.stdout.take())
+                {
+                    let mut reader = BufReader::new(output);
+                    let mut line = Vec::new();
+                    while reader.read_until(b'\n', &mut line).await? > 0 {
+                        let text = String::from_utf8_lossy(&line);
+                        if self.mirror {
+                            print!("{}", console::for_terminal(&text));
+                        }
+                        if self.capture {
+                            stdout.push_str(&text);
+                        }
+                        line.clear();
+                    }
                 }
             }
+        }
 
-            // Spawn the process
-            let mut child = cmd.spawn()?;
-
-            // Write stdin if available
-            if let Some(ref stdin_content) = current_stdin {
-                if let Some(mut stdin) = child.stdin.take() {
-                    let content = stdin_content.clone();
-                    tokio::spawn(async move {
-                        let _ = stdin.write_all(content.as_bytes()).await;
-                        let _ = stdin.shutdown().await;
-                    });
+        let mut waiting = spawned.iter_mut();
+        let mut results = Vec::with_capacity(finished.len());
+        for result in finished {
+            let result = match result {
+                Some(result) => result,
+                None => {
+                    let stage = waiting.next().expect("a spawned stage for each `None`");
+                    let status = stage.child.wait().await?;
+                    let stderr = (&mut stage.stderr).await.unwrap_or_default();
+                    CommandResult::from_exit_status(String::new(), stderr, status)
                 }
-            }
+            };
+            results.push(result);
+        }
 
-            // Read stdout
-            let mut stdout_content = String::new();
-            if let Some(mut stdout) = child.stdout.take() {
-                stdout.read_to_string(&mut stdout_content).await?;
+        if self.mirror {
+            eprint!("{}", console::for_terminal(&results[last].stderr));
+        }
+        for (i, (command, result)) in self.commands.iter().zip(&results).enumerate() {
+            if let Some(error) = missing_program(i, command, result) {
+                return Err(error);
             }
+        }
+        let status = results[..last]
+            .iter()
+            .find(|result| !result.is_success() && !is_broken_pipe(result))
+            .unwrap_or(&results[last]);
+        Ok(CommandResult {
+            stdout,
+            stderr: results
+                .iter()
+                .map(|result| result.stderr.as_str())
+                .collect(),
+            code: status.code,
+            signal: status.signal,
+            ..Default::default()
+        })
+    }
 
-            // Read stderr
-            let mut stderr_content = String::new();
-            if let Some(mut stderr) = child.stderr.take() {
-                stderr.read_to_string(&mut stderr_content).await?;
-            }
+    /// The name and arguments of `cmd_str` if it runs as a virtual command
+    fn virtual_stage(&self, cmd_str: &str) -> Option<(String, Vec<String>)> {
+        if !crate::commands::are_virtual_commands_enabled() {
+            return None;
+        }
+        crate::virtual_command(cmd_str, self.env.as_ref())
+            .filter(|(name, _)| crate::commands::BUILTIN_COMMANDS.contains(&name.as_str()))
+    }
 
-            // Mirror output if enabled and this is the last command
-            if is_last && self.mirror {
-                if !stdout_content.is_empty() {
-                    print!("{}", stdout_content);
-                }
-                if !stderr_content.is_empty() {
-                    eprint!("{}", stderr_content);
-                }
-            }
+    /// Spawn `cmd_str` through the shell, reading from `input`
+    fn spawn_stage(&self, cmd_str: &str, input: StageInput) -> Result<Child> {
+        let argv = find_shell(ShellChoice::Auto).argv(cmd_str);
+        let mut cmd = Command::new(&argv[0]);
+        cmd.args(&argv[1..]);
 
-            // Wait for the process
-            let status = child.wait().await?;
-            let code = status.code().unwrap_or(-1);
+        let content = match input {
+            StageInput::Null => {
+                cmd.stdin(Stdio::null());
+                None
+            }
+            StageInput::Content(content) => {
+                cmd.stdin(Stdio::piped());
+                Some(content)
+            }
+            StageInput::Pipe(stdout) => {
+                let stdin: Stdio = stdout.try_into()?;
+                cmd.stdin(stdin);
+                None
+            }
+        };
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
 
-            accumulated_stderr.push_str(&stderr_content);
+        // Set working directory. Fall back to a valid directory when the
+        // inherited working directory has been deleted (issue #44).
+        if let Some(cwd) = crate::resolve_spawn_cwd(self.cwd.as_ref()) {
+            cmd.current_dir(cwd);
+        }
 
-            if code != 0 {
-                return Ok(CommandResult {
-                    stdout: stdout_content,
-                    stderr: accumulated_stderr,
-                    code,
-                });
+        // Set environment
+        if let Some(ref env_vars) = self.env {
+            for (key, value) in env_vars {
+                cmd.env(key, value);
             }
-
-            // Set up stdin for next command
-            current_stdin = Some(stdout_content.clone());
-            last_result = CommandResult {
-                stdout: stdout_content,
-                stderr: String::new(),
-                code,
-            };
         }
 
-        Ok(CommandResult {
-            stdout: last_result.stdout,
-            stderr: accumulated_stderr,
-            code: last_result.code,
-        })
+        let mut child = cmd.spawn()?;
+        if let (Some(content), Some(mut stdin)) = (content, child.stdin.take()) {
+            tokio::spawn(async move {
+                let _ = stdin.write_all(content.as_bytes()).await;
+                let _ = stdin.shutdown().await;
+            });
+        }
+        Ok(child)
     }
 
     /// Try to execute a virtual command
     async fn try_virtual_command(
         &self,
         cmd_name: &str,
-        full_cmd: &str,
+        args: Vec<String>,
         stdin: &Option<String>,
+        cancel: &CancellationToken,
     ) -> Option<CommandResult> {
-        let parts: Vec<&str> = full_cmd.split_whitespace().collect();
-        let args: Vec<String> = parts.iter().skip(1).map(|s| s.to_string()).collect();
-
         let ctx = crate::commands::CommandContext {
             args,
             stdin: stdin.clone(),
@@ -271,71 +415,64 @@ impl Pipeline {
             env: self.env.clone(),
             output_tx: None,
             is_cancelled: None,
+            cancel_token: Some(cancel.clone()),
+            shell_settings: None,
+            executor: None,
         };
 
-        match cmd_name {
-            "echo" => Some(crate::commands::echo(ctx).await),
-            "pwd" => Some(crate::commands::pwd(ctx).await),
-            "cd" => Some(crate::commands::cd(ctx).await),
-            "true" => Some(crate::commands::r#true(ctx).await),
-            "false" => Some(crate::commands::r#false(ctx).await),
-            "sleep" => Some(crate::commands::sleep(ctx).await),
-            "cat" => Some(crate::commands::cat(ctx).await),
-            "ls" => Some(crate::commands::ls(ctx).await),
-            "mkdir" => Some(crate::commands::mkdir(ctx).await),
-            "rm" => Some(crate::commands::rm(ctx).await),
-            "touch" => Some(crate::commands::touch(ctx).await),
-            "cp" => Some(crate::commands::cp(ctx).await),
-            "mv" => Some(crate::commands::mv(ctx).await),
-            "basename" => Some(crate::commands::basename(ctx).await),
-            "dirname" => Some(crate::commands::dirname(ctx).await),
-            "env" => Some(crate::commands::env(ctx).await),
-            "exit" => Some(crate::commands::exit(ctx).await),
-            "which" => Some(crate::commands::which(ctx).await),
-            "yes" => Some(crate::commands::yes(ctx).await),
-            "seq" => Some(crate::commands::seq(ctx).await),
-            "test" => Some(crate::commands::test(ctx).await),
-            _ => None,
-        }
+        crate::commands::execute_builtin(cmd_name, ctx).await
     }
 }
 
-/// Shell configuration
-#[derive(Debug, Clone)]
-struct ShellConfig {
-    cmd: String,
-    args: Vec<String>,
+/// Where a pipeline stage reads its input from
+enum StageInput {
+    /// Nothing; stdin is the null device
+    Null,
+    /// Text given up front, or produced by a virtual command
+    Content(String),
+    /// The previous stage's stdout
+    Pipe(ChildStdout),
 }
 
-/// Find an available shell
-fn find_available_shell() -> ShellConfig {
-    let is_windows = cfg!(windows);
+/// A running pipeline stage, its stderr read in the background
+struct SpawnedStage {
+    child: Child,
+    stderr: JoinHandle<String>,
+    _tracked: Option<TrackedChild>,
+}
 
-    if is_windows {
-        ShellConfig {
-            cmd: "cmd.exe".to_string(),
-            args: vec!["/c".to_string()],
-        }
-    } else {
-        let shells = [
-            ("/bin/sh", "-c"),
-            ("/usr/bin/sh", "-c"),
-            ("/bin/bash", "-c"),
-        ];
-
-        for (cmd, arg) in shells {
-            if std::path::Path::new(cmd).exists() {
-                return ShellConfig {
-                    cmd: cmd.to_string(),
-                    args: vec![arg.to_string()],
-                };
-            }
-        }
+/// The error for the `index`th stage, `command`, if it exited with 127
+/// because its program couldn't be found
+fn missing_program(index: usize, command: &str, result: &CommandResult) -> Option<Error> {
+    if result.code != 127 || !result.stderr.contains("not found") {
+        return None;
+    }
+    let ParsedCommand::Simple { cmd, .. } = parse_shell_command(command).ok()? else {
+        return None;
+    };
+    let program = unquote_word(&cmd);
+    // A program or builtin that exits with 127 itself is still found
+    if cmd.contains('=')
+        || crate::commands::BUILTIN_COMMANDS.contains(&program.as_str())
+        || which::which(&program).is_ok()
+    {
+        return None;
+    }
+    let missing = Error::CommandNotFound(program);
+    Some(Error::pipeline_stage(index, command, missing))
+}
 
-        ShellConfig {
-            cmd: "/bin/sh".to_string(),
-            args: vec!["-c".to_string()],
-        }
+/// Whether `result` is a stage that was killed writing to a pipe nobody
+/// reads any more, as `yes` is in `yes | head -1`
+fn is_broken_pipe(result: &CommandResult) -> bool {
+    #[cfg(unix)]
+    {
+        result.signal == Some(libc::SIGPIPE)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = result;
+        false
     }
 }
 
@@ -369,9 +506,19 @@ impl PipelineBuilder {
 
     /// Execute the pipeline
     pub async fn run(mut self) -> Result<CommandResult> {
+        let policy = self.first.options().policy.clone();
+        let whole = std::iter::once(self.first.command())
+            .chain(self.additional.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        policy::enforce(policy.as_ref(), &whole)?;
+
         // First, run the initial command
         let first_result = self.first.run().await?;
 
+        if let Some(error) = missing_program(0, self.first.command(), &first_result) {
+            return Err(error);
+        }
         if first_result.code != 0 {
             return Ok(first_result);
         }
@@ -383,20 +530,27 @@ impl PipelineBuilder {
             stdout: String::new(),
             stderr: String::new(),
             code: 0,
+            ..Default::default()
         };
 
-        for cmd_str in &self.additional {
+        for (i, cmd_str) in self.additional.iter().enumerate() {
             let mut runner = crate::ProcessRunner::new(
                 cmd_str.clone(),
                 RunOptions {
                     stdin: StdinOption::Content(current_stdin.take().unwrap_or_default()),
                     mirror: false,
                     capture: true,
+                    cancel: self.first.options().cancel.clone(),
+                    policy: policy.clone(),
+                    confirm: self.first.options().confirm.clone(),
                     ..Default::default()
                 },
             );
 
             let result = runner.run().await?;
+            if let Some(error) = missing_program(i + 1, cmd_str, &result) {
+                return Err(error);
+            }
             accumulated_stderr.push_str(&result.stderr);
 
             if result.code != 0 {
@@ -404,6 +558,7 @@ impl PipelineBuilder {
                     stdout: result.stdout,
                     stderr: accumulated_stderr,
                     code: result.code,
+                    ..Default::default()
                 });
             }
 
@@ -415,6 +570,7 @@ impl PipelineBuilder {
             stdout: last_result.stdout,
             stderr: accumulated_stderr,
             code: last_result.code,
+            ..Default::default()
         })
     }
 }
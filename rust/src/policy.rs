@@ -0,0 +1,417 @@
+//! Allow/deny rules checked before a command runs
+//!
+//! An [`ExecutionPolicy`] is checked by the runner before anything is spawned
+//! or a virtual command runs. A command that breaks it fails with
+//! [`Error::PolicyViolation`], which says which rule was broken. This is
+//! meant for running commands that are only partly trusted, such as commands
+//! generated by a tool or a model:
+//!
+//! ```rust
+//! use command_stream::policy::{ExecutionPolicy, Violation};
+//!
+//! let policy = ExecutionPolicy::new()
+//!     .allow(["ls", "cat", "grep", "wc"])
+//!     .deny_pattern(r"\.ssh/")
+//!     .unwrap()
+//!     .max_pipeline_len(3)
+//!     .forbid_network();
+//!
+//! assert!(policy.check("ls -la | grep src").is_ok());
+//! assert_eq!(
+//!     policy.check("ls && rm -rf target"),
+//!     Err(Violation::NotAllowed { program: "rm".to_string() })
+//! );
+//! ```
+//!
+//! Pass a policy to a single command with
+//! [`RunOptionsBuilder::policy`](crate::RunOptionsBuilder::policy), or to
+//! every command with [`set_execution_policy`].
+//!
+//! The check works on the parsed command, so it sees every stage of
+//! pipelines, sequences and subshells. Command substitutions can't be
+//! checked that way, so they are rejected whenever programs are restricted.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::shell_parser::{parse_shell_command, unquote_word, ParsedArg, ParsedCommand, Redirect};
+use crate::visit::Visit;
+use crate::{Error, Result};
+
+/// Programs that reach the network, rejected by
+/// [`ExecutionPolicy::forbid_network`]
+pub const NETWORK_PROGRAMS: &[&str] = &[
+    "curl", "wget", "nc", "ncat", "netcat", "socat", "telnet", "ftp", "sftp", "scp", "ssh",
+    "rsync", "aria2c", "http", "https",
+];
+
+/// Programs that run another program given as an argument, with their
+/// options that take a separate value
+const WRAPPERS: &[(&str, &[&str])] = &[
+    ("command", &[]),
+    ("doas", &["-u", "-C"]),
+    ("env", &["-u", "-C", "-S"]),
+    ("exec", &["-a"]),
+    ("ionice", &["-c", "-n", "-p"]),
+    ("nice", &["-n"]),
+    ("nohup", &[]),
+    ("stdbuf", &["-i", "-o", "-e"]),
+    (
+        "sudo",
+        &["-u", "-g", "-C", "-D", "-h", "-p", "-r", "-t", "-U"],
+    ),
+    ("time", &["-f", "-o"]),
+    ("timeout", &["-s", "-k"]),
+    ("xargs", &["-a", "-d", "-E", "-I", "-L", "-n", "-P", "-s"]),
+];
+
+static POLICY: Lazy<RwLock<Option<Arc<ExecutionPolicy>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Which commands may run; see the [module docs](self)
+///
+/// A new policy allows everything. Each builder call adds a restriction.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionPolicy {
+    allowed_names: HashSet<String>,
+    allowed_paths: Vec<PathBuf>,
+    denied_patterns: Vec<Regex>,
+    max_pipeline_len: Option<usize>,
+    forbid_network: bool,
+}
+
+/// Why a command was rejected by an [`ExecutionPolicy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Violation {
+    /// The program isn't on the allow-list
+    NotAllowed {
+        /// The program as written in the command
+        program: String,
+    },
+    /// The command matches a denied pattern
+    DeniedPattern {
+        /// The pattern that matched
+        pattern: String,
+    },
+    /// A pipeline has more stages than allowed
+    PipelineTooLong {
+        /// Stages in the pipeline
+        len: usize,
+        /// The most the policy allows
+        max: usize,
+    },
+    /// The program reaches the network, which the policy forbids
+    Network {
+        /// The program as written in the command
+        program: String,
+    },
+    /// The command uses syntax whose programs can't be checked
+    Unchecked {
+        /// What couldn't be checked
+        reason: String,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::NotAllowed { program } => write!(f, "`{}` is not allowed", program),
+            Violation::DeniedPattern { pattern } => {
+                write!(f, "matches denied pattern `{}`", pattern)
+            }
+            Violation::PipelineTooLong { len, max } => {
+                write!(f, "pipeline has {} stages, at most {} allowed", len, max)
+            }
+            Violation::Network { program } => {
+                write!(f, "`{}` uses the network, which is forbidden", program)
+            }
+            Violation::Unchecked { reason } => write!(f, "{} can't be checked", reason),
+        }
+    }
+}
+
+impl ExecutionPolicy {
+    /// A policy that allows everything
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow programs by name; once any program or path is allowed, every
+    /// other program is rejected
+    pub fn allow<I>(mut self, names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.allowed_names.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Allow the program at `path`, or every program in it if it is a
+    /// directory
+    ///
+    /// Programs given by name are looked up on `PATH` to see whether they
+    /// fall under an allowed path.
+    pub fn allow_path(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        self.allowed_paths
+            .push(std::fs::canonicalize(&path).unwrap_or(path));
+        self
+    }
+
+    /// Reject commands that match `pattern` anywhere
+    pub fn deny_pattern(mut self, pattern: &str) -> Result<Self> {
+        let pattern = Regex::new(pattern)
+            .map_err(|e| Error::ParseError(format!("invalid policy pattern: {}", e)))?;
+        self.denied_patterns.push(pattern);
+        Ok(self)
+    }
+
+    /// Reject pipelines with more than `max` stages
+    pub fn max_pipeline_len(mut self, max: usize) -> Self {
+        self.max_pipeline_len = Some(max);
+        self
+    }
+
+    /// Reject the programs in [`NETWORK_PROGRAMS`]
+    pub fn forbid_network(mut self) -> Self {
+        self.forbid_network = true;
+        self
+    }
+
+    /// Check `command` against the policy
+    pub fn check(&self, command: &str) -> std::result::Result<(), Violation> {
+        if let Some(pattern) = self
+            .denied_patterns
+            .iter()
+            .find(|pattern| pattern.is_match(command))
+        {
+            return Err(Violation::DeniedPattern {
+                pattern: pattern.as_str().to_string(),
+            });
+        }
+
+        let restricts_programs = self.restricts_programs() || self.forbid_network;
+        if restricts_programs && (command.contains("$(") || command.contains('`')) {
+            return Err(Violation::Unchecked {
+                reason: "command substitution".to_string(),
+            });
+        }
+
+        let Ok(parsed) = parse_shell_command(command) else {
+            return Ok(());
+        };
+        match (Checker { policy: self }).visit_command(&parsed) {
+            Err(Error::PolicyViolation { violation, .. }) => Err(violation),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check `command`, turning a violation into [`Error::PolicyViolation`]
+    pub fn enforce(&self, command: &str) -> Result<()> {
+        self.check(command)
+            .map_err(|violation| Error::policy_violation(command, violation))
+    }
+
+    fn restricts_programs(&self) -> bool {
+        !self.allowed_names.is_empty() || !self.allowed_paths.is_empty()
+    }
+
+    fn check_program(&self, program: &str) -> std::result::Result<(), Violation> {
+        let name = Path::new(program)
+            .file_name()
+            .map_or(program.to_string(), |name| {
+                name.to_string_lossy().into_owned()
+            });
+        if self.forbid_network && NETWORK_PROGRAMS.contains(&name.as_str()) {
+            return Err(Violation::Network {
+                program: program.to_string(),
+            });
+        }
+        if !self.restricts_programs() || self.is_allowed(program) {
+            return Ok(());
+        }
+        Err(Violation::NotAllowed {
+            program: program.to_string(),
+        })
+    }
+
+    fn is_allowed(&self, program: &str) -> bool {
+        let is_path = program.contains(['/', '\\']);
+        if !is_path && self.allowed_names.contains(program) {
+            return true;
+        }
+        if self.allowed_paths.is_empty() {
+            return false;
+        }
+        let resolved = if is_path {
+            std::fs::canonicalize(program).ok()
+        } else {
+            which::which(program).ok()
+        };
+        resolved.is_some_and(|path| {
+            let path = std::fs::canonicalize(&path).unwrap_or(path);
+            self.allowed_paths
+                .iter()
+                .any(|allowed| path == *allowed || (allowed.is_dir() && path.starts_with(allowed)))
+        })
+    }
+}
+
+/// Walks a parsed command, stopping at the first violation
+struct Checker<'a> {
+    policy: &'a ExecutionPolicy,
+}
+
+/// Stop the walk with `violation`; [`ExecutionPolicy::check`] takes it back
+/// out of the error
+fn fail(violation: Violation) -> Result<()> {
+    Err(Error::policy_violation(String::new(), violation))
+}
+
+impl Visit for Checker<'_> {
+    fn visit_simple(&mut self, cmd: &str, args: &[ParsedArg], _: &[Redirect]) -> Result<()> {
+        for program in programs(cmd, args) {
+            if let Err(violation) = self.policy.check_program(&program) {
+                return fail(violation);
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_pipeline(&mut self, commands: &[ParsedCommand]) -> Result<()> {
+        match self.policy.max_pipeline_len {
+            Some(max) if commands.len() > max => fail(Violation::PipelineTooLong {
+                len: commands.len(),
+                max,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The programs a simple command runs: its first word after any `NAME=value`
+/// assignments, and for wrappers like `env` or `sudo`, the program they run
+fn programs(cmd: &str, args: &[ParsedArg]) -> Vec<String> {
+    let mut words = std::iter::once(unquote_word(cmd)).chain(args.iter().map(ParsedArg::unquoted));
+    let mut programs = Vec::new();
+    // Options of the wrapper whose program comes next
+    let mut value_options: Option<&[&str]> = None;
+    while let Some(word) = words.next() {
+        let is_assignment = word
+            .split_once('=')
+            .is_some_and(|(name, _)| !name.is_empty() && !name.starts_with('-'));
+        if is_assignment {
+            continue;
+        }
+        if let Some(options) = value_options {
+            if word.starts_with('-') {
+                if options.contains(&word.as_str()) {
+                    words.next();
+                }
+                continue;
+            }
+            // `timeout 5 cmd`
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                continue;
+            }
+        }
+        value_options = WRAPPERS
+            .iter()
+            .find(|(wrapper, _)| *wrapper == word)
+            .map(|(_, options)| *options);
+        programs.push(word);
+        if value_options.is_none() {
+            break;
+        }
+    }
+    programs
+}
+
+/// Check every command against `policy`; `None` removes the global policy
+///
+/// A policy set on [`RunOptions`](crate::RunOptions) takes precedence.
+pub fn set_execution_policy(policy: Option<ExecutionPolicy>) {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy.map(Arc::new);
+}
+
+/// The global policy set with [`set_execution_policy`]
+pub fn execution_policy() -> Option<Arc<ExecutionPolicy>> {
+    POLICY.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Enforce `policy`, or the global policy if it is `None`
+pub(crate) fn enforce(policy: Option<&Arc<ExecutionPolicy>>, command: &str) -> Result<()> {
+    match policy.cloned().or_else(execution_policy) {
+        Some(policy) => policy.enforce(command),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_programs() {
+        let programs = |command: &str| match parse_shell_command(command).unwrap() {
+            ParsedCommand::Simple { cmd, args, .. } => programs(&cmd, &args),
+            _ => unreachable!(),
+        };
+        assert_eq!(programs("ls -la"), ["ls"]);
+        assert_eq!(programs("FOO=1 make all"), ["make"]);
+        assert_eq!(
+            programs("sudo -u root env A=b curl x"),
+            ["sudo", "env", "curl"]
+        );
+        assert_eq!(programs("timeout 5 wget url"), ["timeout", "wget"]);
+        assert_eq!(programs("'rm' -rf /"), ["rm"]);
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = ExecutionPolicy::new()
+            .allow(["ls", "grep", "echo", "env"])
+            .max_pipeline_len(2)
+            .forbid_network();
+        assert_eq!(policy.check("ls | grep x && echo ok"), Ok(()));
+        assert_eq!(
+            policy.check("(echo a; rm b)"),
+            Err(Violation::NotAllowed {
+                program: "rm".to_string()
+            })
+        );
+        assert_eq!(
+            policy.check("ls | grep a | grep b"),
+            Err(Violation::PipelineTooLong { len: 3, max: 2 })
+        );
+        assert_eq!(
+            policy.check("env curl http://example.com"),
+            Err(Violation::Network {
+                program: "curl".to_string()
+            })
+        );
+        assert!(matches!(
+            policy.check("echo $(rm -rf /)"),
+            Err(Violation::Unchecked { .. })
+        ));
+        assert_eq!(ExecutionPolicy::new().check("echo $(date)"), Ok(()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_allow_path() {
+        let Ok(sh) = which::which("sh") else {
+            return;
+        };
+        let dir = sh.parent().unwrap().to_path_buf();
+        let policy = ExecutionPolicy::new().allow_path(&dir);
+        assert_eq!(policy.check("sh -c true"), Ok(()));
+        assert_eq!(policy.check(&format!("{} -c true", sh.display())), Ok(()));
+        assert!(policy.check("./sh -c true").is_err());
+    }
+}
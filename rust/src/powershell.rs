@@ -0,0 +1,337 @@
+//! PowerShell command parsing
+//!
+//! Commands run with [`ShellChoice::PowerShell`](crate::ShellChoice::PowerShell)
+//! are parsed with PowerShell's rules rather than POSIX ones: single quotes
+//! are literal with `''` standing for a quote, double quotes expand `$` and
+//! use the backtick as their escape character, and a backslash is an ordinary
+//! character, so `C:\Tools\app.exe` needs no quoting.
+//!
+//! The parser understands `;`, `&&`, `||` and `|`. Anything it doesn't model
+//! (variables, subexpressions, script blocks, redirects, the call operator)
+//! makes the command unparseable, and the runner hands it to PowerShell.
+//!
+//! ```rust
+//! use command_stream::powershell::powershell_literal_argv;
+//!
+//! assert_eq!(
+//!     powershell_literal_argv(r#"C:\Tools\app.exe 'it''s' "a `"b`"""#),
+//!     Some(vec![r"C:\Tools\app.exe".to_string(), "it's".to_string(), "a \"b\"".to_string()])
+//! );
+//! assert_eq!(powershell_literal_argv("Write-Output $env:PATH"), None);
+//! ```
+
+use crate::shell_parser::{ParsedArg, ParsedCommand, Span, TokenType};
+
+/// A PowerShell token
+#[derive(Debug, Clone, PartialEq)]
+enum PsToken {
+    /// A word with quoting removed; `literal` is false when PowerShell would
+    /// expand part of it
+    Word {
+        value: String,
+        quote_char: Option<char>,
+        literal: bool,
+    },
+    Operator(TokenType),
+}
+
+/// Split `command` into PowerShell tokens, or `None` if it uses syntax this
+/// parser doesn't handle
+fn tokenize(command: &str) -> Option<Vec<PsToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = command.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => break,
+            ';' => {
+                chars.next();
+                tokens.push(PsToken::Operator(TokenType::Semicolon));
+            }
+            '|' | '&' => {
+                chars.next();
+                let doubled = chars.next_if_eq(&c).is_some();
+                tokens.push(PsToken::Operator(match (c, doubled) {
+                    ('|', false) => TokenType::Pipe,
+                    ('|', true) => TokenType::Or,
+                    ('&', true) => TokenType::And,
+                    // `&` alone is the call operator or a background job
+                    _ => return None,
+                }));
+            }
+            '(' | ')' | '{' | '}' | '<' | '>' | '@' | ',' => return None,
+            _ => tokens.push(read_word(&mut chars)?),
+        }
+    }
+
+    Some(tokens)
+}
+
+/// Read one word, which may mix bare and quoted parts
+fn read_word(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Option<PsToken> {
+    let mut value = String::new();
+    let mut literal = true;
+    let mut quote_char = None;
+    let mut bare_parts = 0;
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\'' => {
+                chars.next();
+                quote_char = Some('\'');
+                loop {
+                    match chars.next()? {
+                        '\'' if chars.next_if_eq(&'\'').is_some() => value.push('\''),
+                        '\'' => break,
+                        c => value.push(c),
+                    }
+                }
+            }
+            '"' => {
+                chars.next();
+                quote_char = Some('"');
+                loop {
+                    match chars.next()? {
+                        '"' if chars.next_if_eq(&'"').is_some() => value.push('"'),
+                        '"' => break,
+                        '`' => value.push(escaped(chars.next()?)),
+                        '$' => {
+                            literal = false;
+                            value.push('$');
+                        }
+                        c => value.push(c),
+                    }
+                }
+            }
+            '`' => {
+                chars.next();
+                bare_parts += 1;
+                value.push(escaped(chars.next()?));
+            }
+            _ if c.is_whitespace() || ";|&".contains(c) => break,
+            '(' | ')' | '{' | '}' | '<' | '>' | ',' => return None,
+            _ => {
+                chars.next();
+                bare_parts += 1;
+                if c == '$' {
+                    literal = false;
+                }
+                value.push(c);
+            }
+        }
+    }
+
+    if value == "--%" {
+        // The stop-parsing token changes how the rest of the line is read
+        return None;
+    }
+    Some(PsToken::Word {
+        value,
+        quote_char: if bare_parts == 0 { quote_char } else { None },
+        literal,
+    })
+}
+
+/// The character a backtick escape stands for
+fn escaped(c: char) -> char {
+    match c {
+        '0' => '\0',
+        'a' => '\x07',
+        'b' => '\x08',
+        'e' => '\x1b',
+        'f' => '\x0c',
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        'v' => '\x0b',
+        other => other,
+    }
+}
+
+/// Parse a PowerShell command line into the same structure as
+/// [`parse_shell_command`](crate::parse_shell_command)
+///
+/// Arguments have their PowerShell quoting already removed, so
+/// [`ParsedArg::value`] is what a native program would receive for literal
+/// words. Returns `None` when the command uses syntax beyond plain commands
+/// joined by `;`, `&&`, `||` and `|`.
+pub fn parse_powershell_command(command: &str) -> Option<ParsedCommand> {
+    let tokens = tokenize(command)?;
+
+    let mut commands = Vec::new();
+    let mut operators = Vec::new();
+    let mut pipeline: Vec<ParsedCommand> = Vec::new();
+    let mut words: Vec<PsToken> = Vec::new();
+
+    for token in tokens.into_iter().map(Some).chain([None]) {
+        match token {
+            Some(word @ PsToken::Word { .. }) => words.push(word),
+            Some(PsToken::Operator(TokenType::Pipe)) => {
+                pipeline.push(simple_command(std::mem::take(&mut words))?);
+            }
+            operator => {
+                if words.is_empty() && pipeline.is_empty() {
+                    // Only a trailing `;` (or nothing at all) may end a command
+                    if operator.is_none() && operators.last() == Some(&TokenType::Semicolon) {
+                        operators.pop();
+                        break;
+                    }
+                    return None;
+                }
+                pipeline.push(simple_command(std::mem::take(&mut words))?);
+                commands.push(if pipeline.len() == 1 {
+                    pipeline.pop()?
+                } else {
+                    ParsedCommand::Pipeline {
+                        commands: std::mem::take(&mut pipeline),
+                    }
+                });
+                if let Some(PsToken::Operator(op)) = operator {
+                    operators.push(op);
+                }
+            }
+        }
+    }
+
+    if commands.len() == 1 {
+        commands.pop()
+    } else {
+        Some(ParsedCommand::Sequence {
+            commands,
+            operators,
+        })
+    }
+}
+
+fn simple_command(words: Vec<PsToken>) -> Option<ParsedCommand> {
+    let mut words = words.into_iter().map(|token| match token {
+        PsToken::Word {
+            value, quote_char, ..
+        } => ParsedArg {
+            value,
+            quoted: quote_char.is_some(),
+            quote_char,
+            span: Span::default(),
+        },
+        PsToken::Operator(_) => unreachable!("only words are collected"),
+    });
+    let cmd = words.next()?.value;
+    Some(ParsedCommand::Simple {
+        assignments: Vec::new(),
+        cmd,
+        args: words.collect(),
+        redirects: Vec::new(),
+    })
+}
+
+/// The argv of a PowerShell command that can run without PowerShell
+///
+/// Only a single command whose words are all literal qualifies: no
+/// variables, no operators, and a program name that isn't quoted (a quoted
+/// string on its own is an expression in PowerShell, not a command).
+pub fn powershell_literal_argv(command: &str) -> Option<Vec<String>> {
+    let tokens = tokenize(command)?;
+    let mut argv = Vec::with_capacity(tokens.len());
+    for (index, token) in tokens.into_iter().enumerate() {
+        match token {
+            PsToken::Word {
+                value,
+                quote_char,
+                literal: true,
+            } if index > 0 || quote_char.is_none() => argv.push(value),
+            _ => return None,
+        }
+    }
+    if argv.is_empty() {
+        None
+    } else {
+        Some(argv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(list: &[&str]) -> Option<Vec<String>> {
+        Some(list.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn test_literal_argv_quoting() {
+        assert_eq!(
+            powershell_literal_argv(r"git commit -m 'it''s done'"),
+            argv(&["git", "commit", "-m", "it's done"])
+        );
+        assert_eq!(
+            powershell_literal_argv(r#"echo "tab`there" pre'fix'"#),
+            argv(&["echo", "tab\there", "prefix"])
+        );
+        assert_eq!(
+            powershell_literal_argv(r"dir C:\Users\me\my` file.txt"),
+            argv(&["dir", r"C:\Users\me\my file.txt"])
+        );
+        assert_eq!(
+            powershell_literal_argv("echo hi # comment"),
+            argv(&["echo", "hi"])
+        );
+    }
+
+    #[test]
+    fn test_literal_argv_rejects_powershell_syntax() {
+        for command in [
+            "echo $HOME",
+            r#"echo "$name""#,
+            "Get-Process | Select-Object Name",
+            "a; b",
+            "& 'C:\\Program Files\\app.exe'",
+            "'not a command'",
+            "echo (Get-Date)",
+            "echo @args",
+            "echo a,b",
+            "echo hi > out.txt",
+            "cmd --% /c dir",
+            "echo 'unterminated",
+            "",
+        ] {
+            assert_eq!(powershell_literal_argv(command), None, "{}", command);
+        }
+        // `$` inside single quotes is literal
+        assert_eq!(
+            powershell_literal_argv("echo '$HOME'"),
+            argv(&["echo", "$HOME"])
+        );
+    }
+
+    #[test]
+    fn test_parse_separators_and_pipes() {
+        let parsed = parse_powershell_command("a 1; b 'x y' && c | d || e;").unwrap();
+        let ParsedCommand::Sequence {
+            commands,
+            operators,
+        } = parsed
+        else {
+            panic!("expected a sequence");
+        };
+        assert_eq!(
+            operators,
+            [TokenType::Semicolon, TokenType::And, TokenType::Or]
+        );
+        assert_eq!(commands.len(), 4);
+        assert!(
+            matches!(&commands[2], ParsedCommand::Pipeline { commands } if commands.len() == 2)
+        );
+        let ParsedCommand::Simple { args, .. } = &commands[1] else {
+            panic!("expected a simple command");
+        };
+        assert_eq!(args[0].value, "x y");
+        assert_eq!(args[0].quote_char, Some('\''));
+
+        assert!(parse_powershell_command("a ;; b").is_none());
+        assert!(parse_powershell_command("| a").is_none());
+        assert!(parse_powershell_command("a &").is_none());
+    }
+}
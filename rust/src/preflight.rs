@@ -0,0 +1,306 @@
+//! Checks that the commands a workflow needs are installed
+//!
+//! [`preflight`] looks each command up on `PATH` and, for requirements with a
+//! minimum version, runs it with `--version` and compares the result. Checking
+//! up front turns a failure halfway through a long workflow into one clear
+//! message at the start:
+//!
+//! ```rust,no_run
+//! use command_stream::preflight::{preflight, Requirement};
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! preflight([
+//!     Requirement::new("docker"),
+//!     Requirement::new("git").min_version("2.30"),
+//!     Requirement::new("jq"),
+//! ])
+//! .await
+//! .into_result()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Versions are compared component by component as numbers, so `2.9` is
+//! older than `2.10`. Tools whose `--version` output is unusual can provide
+//! their own arguments and parser.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::quote::quote_args;
+use crate::{Error, ProcessRunner, Result, RunOptions, StdinOption};
+
+/// How long a version command may take
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+static VERSION_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d+(?:\.\d+)+|\d+").unwrap());
+
+/// Extracts a version from a command's version output
+type VersionParser = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// A command a workflow needs
+#[derive(Clone)]
+pub struct Requirement {
+    name: String,
+    min_version: Option<String>,
+    version_args: Vec<String>,
+    parse_version: VersionParser,
+}
+
+impl fmt::Debug for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Requirement")
+            .field("name", &self.name)
+            .field("min_version", &self.min_version)
+            .field("version_args", &self.version_args)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Requirement {
+    /// Require `name` to be on `PATH`
+    pub fn new(name: impl Into<String>) -> Self {
+        Requirement {
+            name: name.into(),
+            min_version: None,
+            version_args: vec!["--version".to_string()],
+            parse_version: Arc::new(default_version),
+        }
+    }
+
+    /// Also require at least `version`, e.g. `"2.30"`
+    pub fn min_version(mut self, version: impl Into<String>) -> Self {
+        self.min_version = Some(version.into());
+        self
+    }
+
+    /// Arguments that make the command print its version (`--version` by
+    /// default)
+    pub fn version_args<I>(mut self, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.version_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Extract the version from the version command's stdout and stderr
+    /// with `parser` instead of taking the first dotted number
+    pub fn parse_version(
+        mut self,
+        parser: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.parse_version = Arc::new(parser);
+        self
+    }
+
+    async fn check(&self) -> CommandCheck {
+        let mut check = CommandCheck {
+            name: self.name.clone(),
+            path: None,
+            version: None,
+            problem: None,
+        };
+        let path = match which::which(&self.name) {
+            Ok(path) => path,
+            Err(_) => {
+                check.problem = Some("not found in PATH".to_string());
+                return check;
+            }
+        };
+        check.path = Some(path.clone());
+
+        let Some(required) = &self.min_version else {
+            return check;
+        };
+        let path = path.to_string_lossy();
+        let command = quote_args(
+            std::iter::once(path.as_ref()).chain(self.version_args.iter().map(String::as_str)),
+        );
+        let options = RunOptions::builder()
+            .mirror(false)
+            .stdin(StdinOption::Null)
+            .stable_locale()
+            .timeout(VERSION_TIMEOUT)
+            .build();
+        let output = match ProcessRunner::new(&command, options).run().await {
+            Ok(result) => format!("{}\n{}", result.stdout, result.stderr),
+            Err(e) => {
+                check.problem = Some(format!("`{}` failed: {}", command, e));
+                return check;
+            }
+        };
+        check.version = (self.parse_version)(&output);
+        check.problem = match &check.version {
+            None => Some(format!(
+                "could not read a version from `{}` (need {} or newer)",
+                command, required
+            )),
+            Some(found) if compare_versions(found, required) == Ordering::Less => Some(format!(
+                "version {} is older than the required {}",
+                found, required
+            )),
+            Some(_) => None,
+        };
+        check
+    }
+}
+
+impl From<&str> for Requirement {
+    fn from(name: &str) -> Self {
+        Requirement::new(name)
+    }
+}
+
+impl From<String> for Requirement {
+    fn from(name: String) -> Self {
+        Requirement::new(name)
+    }
+}
+
+/// The outcome of checking one [`Requirement`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandCheck {
+    /// The command's name
+    pub name: String,
+    /// Where it was found on `PATH`
+    pub path: Option<PathBuf>,
+    /// The version it reported, when a minimum version was required
+    pub version: Option<String>,
+    /// Why the requirement isn't met, `None` if it is
+    pub problem: Option<String>,
+}
+
+impl CommandCheck {
+    /// Whether the requirement is met
+    pub fn is_ok(&self) -> bool {
+        self.problem.is_none()
+    }
+}
+
+/// The outcome of a [`preflight`] check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// One check per requirement, in the order given
+    pub checks: Vec<CommandCheck>,
+}
+
+impl Report {
+    /// Whether every requirement is met
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(CommandCheck::is_ok)
+    }
+
+    /// The checks whose requirement isn't met
+    pub fn failures(&self) -> impl Iterator<Item = &CommandCheck> {
+        self.checks.iter().filter(|check| !check.is_ok())
+    }
+
+    /// The report, or [`Error::CommandNotFound`] listing every unmet
+    /// requirement
+    pub fn into_result(self) -> Result<Report> {
+        if self.is_ok() {
+            return Ok(self);
+        }
+        let problems: Vec<String> = self
+            .failures()
+            .map(|check| format!("{}: {}", check.name, check.problem.as_deref().unwrap_or("")))
+            .collect();
+        Err(Error::CommandNotFound(problems.join("; ")))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            match (&check.problem, &check.version, &check.path) {
+                (Some(problem), _, _) => writeln!(f, "{}: {}", check.name, problem)?,
+                (None, Some(version), Some(path)) => {
+                    writeln!(f, "{}: ok ({}, {})", check.name, version, path.display())?
+                }
+                (None, _, Some(path)) => writeln!(f, "{}: ok ({})", check.name, path.display())?,
+                (None, _, None) => writeln!(f, "{}: ok", check.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that every requirement is met; see the [module docs](self)
+pub async fn preflight<I>(requirements: I) -> Report
+where
+    I: IntoIterator,
+    I::Item: Into<Requirement>,
+{
+    let mut tasks = tokio::task::JoinSet::new();
+    let requirements: Vec<Requirement> = requirements.into_iter().map(Into::into).collect();
+    for (index, requirement) in requirements.into_iter().enumerate() {
+        tasks.spawn(async move { (index, requirement.check().await) });
+    }
+    let mut checks = Vec::with_capacity(tasks.len());
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok(check) = joined {
+            checks.push(check);
+        }
+    }
+    checks.sort_by_key(|(index, _)| *index);
+    Report {
+        checks: checks.into_iter().map(|(_, check)| check).collect(),
+    }
+}
+
+/// The first dotted number in `output`
+fn default_version(output: &str) -> Option<String> {
+    VERSION_PATTERN
+        .find(output)
+        .map(|version| version.as_str().to_string())
+}
+
+/// Compare dotted versions numerically; missing components count as 0
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .split('.')
+            .map(|part| {
+                let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+                digits.parse().unwrap_or(0)
+            })
+            .collect()
+    };
+    let (a, b) = (parse(a), parse(b));
+    for i in 0..a.len().max(b.len()) {
+        let order = a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0));
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("2.10", "2.9"), Ordering::Greater);
+        assert_eq!(compare_versions("2.30", "2.30.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.2.3-rc1", "1.2.4"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_default_version() {
+        assert_eq!(
+            default_version("git version 2.43.0\n").as_deref(),
+            Some("2.43.0")
+        );
+        assert_eq!(default_version("jq-1.7.1").as_deref(), Some("1.7.1"));
+        assert_eq!(default_version("no version here"), None);
+    }
+}
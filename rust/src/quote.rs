@@ -3,9 +3,16 @@
 //! This module provides functions for safely quoting values for shell usage,
 //! preventing command injection and ensuring proper argument handling.
 
+use once_cell::sync::Lazy;
+use regex::Regex;
 use std::collections::HashSet;
 use std::sync::{Mutex, OnceLock};
 
+/// Characters that never need quoting: alphanumeric, dash, underscore, dot,
+/// slash, colon, equals, comma, plus, at. Compiled once and shared, since
+/// `quote()` runs for every macro interpolation.
+static SAFE_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_\-./=,+@:]+$").unwrap());
+
 /// Quote a value for safe shell usage
 ///
 /// This function quotes strings appropriately for use in shell commands,
@@ -48,10 +55,7 @@ pub fn quote(value: &str) -> String {
     }
 
     // Check if the string needs quoting at all
-    // Safe characters: alphanumeric, dash, underscore, dot, slash, colon, equals, comma, plus, at
-    let safe_pattern = regex::Regex::new(r"^[a-zA-Z0-9_\-./=,+@:]+$").unwrap();
-
-    if safe_pattern.is_match(value) {
+    if SAFE_PATTERN.is_match(value) {
         return value.to_string();
     }
 
@@ -81,6 +85,31 @@ pub fn quote_all(values: &[&str]) -> String {
         .join(" ")
 }
 
+/// Quote every item of a list of arguments and join them with spaces
+///
+/// Like [`quote_all`], but accepts any iterator of string-like items, such
+/// as a `Vec<String>` or a slice of paths converted to strings.
+///
+/// # Examples
+///
+/// ```
+/// use command_stream::quote::quote_args;
+///
+/// let files = vec!["a.txt".to_string(), "my notes.md".to_string()];
+/// assert_eq!(quote_args(&files), "a.txt 'my notes.md'");
+/// ```
+pub fn quote_args<I>(values: I) -> String
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    values
+        .into_iter()
+        .map(|v| quote(v.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 /// Check if a string needs quoting for shell usage
 ///
 /// Returns true if the string contains characters that would be interpreted
@@ -100,8 +129,7 @@ pub fn needs_quoting(value: &str) -> bool {
         return true;
     }
 
-    let safe_pattern = regex::Regex::new(r"^[a-zA-Z0-9_\-./=,+@:]+$").unwrap();
-    !safe_pattern.is_match(value)
+    !SAFE_PATTERN.is_match(value)
 }
 
 /// Scan a built command string for an unquoted Go/Handlebars-style template
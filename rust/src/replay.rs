@@ -0,0 +1,162 @@
+//! Exporting and replaying recorded output
+//!
+//! A command run with [`RunOptions::timed_lines`](crate::RunOptions::timed_lines)
+//! keeps each output line with the time it arrived. That recording can be
+//! saved in the [asciicast v2](https://docs.asciinema.org/manual/asciicast/v2/)
+//! format, to attach to a bug report and play back with `asciinema play`, or
+//! replayed here at its original pace:
+//!
+//! ```rust,no_run
+//! use command_stream::{replay, ProcessRunner, RunOptions};
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let options = RunOptions::builder().timed_lines(true).build();
+//! let result = ProcessRunner::new("cargo test", options).run().await?;
+//!
+//! replay::write_asciicast("test-run.cast", "cargo test", &result.timed_lines)?;
+//! replay::replay(&result.timed_lines).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redact::redact;
+use crate::trace::push_json_string;
+use crate::{Result, StreamKind, TimedLine};
+
+/// Terminal size written to the asciicast header
+const CAST_WIDTH: u32 = 80;
+const CAST_HEIGHT: u32 = 24;
+
+/// The recording as an asciicast v2 document
+///
+/// Both streams become output events, the way a terminal shows them, with
+/// `\r\n` line endings. `command` is [redacted](crate::redact) and left out
+/// of the header when empty.
+pub fn to_asciicast(command: &str, lines: &[TimedLine]) -> String {
+    let mut cast = format!(
+        "{{\"version\":2,\"width\":{},\"height\":{}",
+        CAST_WIDTH, CAST_HEIGHT
+    );
+    if !command.is_empty() {
+        cast.push_str(",\"command\":");
+        push_json_string(&mut cast, &redact(command));
+    }
+    cast.push_str("}\n");
+    for line in lines {
+        let _ = write!(
+            cast,
+            "[{}.{:06},\"o\",",
+            line.at.as_secs(),
+            line.at.subsec_micros()
+        );
+        push_json_string(&mut cast, &format!("{}\r\n", line.text));
+        cast.push_str("]\n");
+    }
+    cast
+}
+
+/// Save the recording to `path` as an asciicast v2 file; see
+/// [`to_asciicast`]
+pub fn write_asciicast(path: impl AsRef<Path>, command: &str, lines: &[TimedLine]) -> Result<()> {
+    std::fs::write(path, to_asciicast(command, lines))?;
+    Ok(())
+}
+
+/// Print the recording again with its original timing, stdout lines to
+/// this process's stdout and stderr lines to its stderr
+pub async fn replay(lines: &[TimedLine]) -> Result<()> {
+    let (mut stdout, mut stderr) = (tokio::io::stdout(), tokio::io::stderr());
+    let started = Instant::now();
+    for line in lines {
+        wait_until(started, line.at, 1.0).await;
+        match line.stream {
+            StreamKind::Stdout => write_line(&mut stdout, &line.text).await?,
+            StreamKind::Stderr => write_line(&mut stderr, &line.text).await?,
+        }
+    }
+    Ok(())
+}
+
+/// Write the recording, both streams interleaved, to `out`
+///
+/// `speed` scales the pace: 2.0 plays twice as fast, and anything not above
+/// zero writes everything at once.
+pub async fn replay_to<W>(lines: &[TimedLine], out: &mut W, speed: f64) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let started = Instant::now();
+    for line in lines {
+        wait_until(started, line.at, speed).await;
+        write_line(out, &line.text).await?;
+    }
+    Ok(())
+}
+
+/// Sleep until `at`, scaled by `speed`, has passed since `started`
+async fn wait_until(started: Instant, at: Duration, speed: f64) {
+    if speed > 0.0 {
+        let due = started + at.div_f64(speed);
+        tokio::time::sleep_until(due.into()).await;
+    }
+}
+
+async fn write_line<W: AsyncWrite + Unpin>(out: &mut W, line: &str) -> Result<()> {
+    out.write_all(line.as_bytes()).await?;
+    out.write_all(b"\n").await?;
+    out.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(stream: StreamKind, millis: u64, text: &str) -> TimedLine {
+        TimedLine {
+            stream,
+            at: Duration::from_millis(millis),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_to_asciicast() {
+        let lines = [
+            line(StreamKind::Stdout, 5, "say \"hi\""),
+            line(StreamKind::Stderr, 1500, "warn\tx"),
+        ];
+        assert_eq!(
+            to_asciicast("make", &lines),
+            "{\"version\":2,\"width\":80,\"height\":24,\"command\":\"make\"}\n\
+             [0.005000,\"o\",\"say \\\"hi\\\"\\r\\n\"]\n\
+             [1.500000,\"o\",\"warn\\tx\\r\\n\"]\n"
+        );
+        assert_eq!(
+            to_asciicast("", &[]),
+            "{\"version\":2,\"width\":80,\"height\":24}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_to_keeps_timing() {
+        let lines = [
+            line(StreamKind::Stdout, 0, "one"),
+            line(StreamKind::Stderr, 200, "two"),
+        ];
+        let mut out = Vec::new();
+        let started = Instant::now();
+        replay_to(&lines, &mut out, 2.0).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(out, b"one\ntwo\n");
+
+        let started = Instant::now();
+        replay_to(&lines, &mut Vec::new(), 0.0).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(100));
+    }
+}
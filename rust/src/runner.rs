@@ -0,0 +1,867 @@
+//! The process runner behind every command
+//!
+//! [`ProcessRunner`] decides how a command string runs: as a virtual
+//! command, as a program spawned directly, or through a shell. It applies
+//! the [`RunOptions`], streams and captures output, and enforces timeouts
+//! and cancellation.
+
+mod exec;
+mod output;
+mod plan;
+mod redirect;
+mod script;
+mod virtual_call;
+
+pub(crate) use exec::SessionEffects;
+pub(crate) use plan::{list_commands, virtual_command};
+
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::watch;
+
+use crate::aliases;
+use crate::color;
+use crate::commands::{null_device, SpecialFile};
+use crate::confirm::confirm;
+use crate::console;
+use crate::expand;
+use crate::filter;
+use crate::heartbeat::{Heartbeat, StallAction};
+use crate::hooks::{self, ExecSpec};
+use crate::instrument;
+use crate::otel::CommandSpan;
+use crate::policy;
+use crate::powershell::powershell_literal_argv;
+use crate::redact::redact;
+use crate::shell::find_shell;
+use crate::shell_parser::check_syntax;
+use crate::sink::LineSink;
+use crate::state::{self, get_shell_settings, ShellSettings, TrackedChild};
+use crate::tail::TailBuffer;
+use crate::trace;
+use crate::xtrace;
+use crate::{
+    commands, history, needs_real_shell, parse_shell_command, resolve_spawn_cwd, utils,
+    CancellationToken, CommandResult, Error, EventData, EventType, OutputSnapshot, Result,
+    RunOptions, ShellChoice, StdinOption, StreamEmitter, StreamKind,
+};
+use redirect::Routes;
+use virtual_call::mirror_text;
+
+/// A running or completed process
+pub struct ProcessRunner {
+    command: String,
+    options: RunOptions,
+    child: Option<Child>,
+    tracked: Option<TrackedChild>,
+    registration: Option<state::RunnerRegistration>,
+    /// Registry id, kept for tracing after the registration is dropped
+    id: Option<u64>,
+    result: Option<CommandResult>,
+    shell_settings: ShellSettings,
+    started: bool,
+    finished: bool,
+    cancel: CancellationToken,
+    emitter: Option<Arc<StreamEmitter>>,
+    stdout_sink: Option<LineSink>,
+    stderr_sink: Option<LineSink>,
+    /// Settings shared with a [`Session`](crate::session::Session), used
+    /// and changed (by `set`) instead of the global ones
+    session_settings: Option<Arc<tokio::sync::RwLock<ShellSettings>>>,
+    /// What a list of commands does to the session running it: its `cd`s
+    /// move the session instead of the process, and `exit` ends its script
+    session: Option<Arc<std::sync::Mutex<SessionEffects>>>,
+    /// Trace context for the spawned process, from the command's span
+    trace_env: Vec<(String, String)>,
+    /// Part of a compound command this library executes itself, which was
+    /// checked and echoed as a whole
+    nested: bool,
+    /// Output forwarded so far, for [`partial_output`](Self::partial_output)
+    partial: watch::Sender<OutputSnapshot>,
+}
+
+impl ProcessRunner {
+    /// Create a new process runner
+    pub fn new(command: impl Into<String>, options: RunOptions) -> Self {
+        let cancel = options
+            .cancel
+            .as_ref()
+            .map(CancellationToken::child_token)
+            .unwrap_or_default();
+        ProcessRunner {
+            command: command.into(),
+            options,
+            child: None,
+            tracked: None,
+            registration: None,
+            id: None,
+            result: None,
+            shell_settings: ShellSettings::default(),
+            started: false,
+            finished: false,
+            cancel,
+            emitter: None,
+            stdout_sink: None,
+            stderr_sink: None,
+            session_settings: None,
+            session: None,
+            trace_env: Vec::new(),
+            nested: false,
+            partial: watch::Sender::new(OutputSnapshot::default()),
+        }
+    }
+
+    /// Start the process
+    pub async fn start(&mut self) -> Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        if self.cancel.is_cancelled() {
+            self.finished = true;
+            return Err(Error::Cancelled);
+        }
+        // A session expands its aliases, and these, itself
+        if !self.nested && !self.options.raw_shell && self.session_settings.is_none() {
+            self.command = aliases::expand_global(&self.command);
+        }
+        // Malformed commands fail here, with where they went wrong
+        let syntax = match self.options.raw_shell || !self.options.shell_operators {
+            false if find_shell(self.options.shell).kind.is_posix() => check_syntax(&self.command),
+            _ => Ok(()),
+        };
+        let allowed = match syntax
+            .map_err(Error::from)
+            .and_then(|()| policy::enforce(self.options.policy.as_ref(), &self.command))
+        {
+            _ if self.nested => Ok(()),
+            Ok(()) => confirm(self.options.confirm.as_ref(), &self.command).await,
+            rejected => rejected,
+        };
+        if let Err(e) = allowed {
+            self.finished = true;
+            self.trace(|| format!("Not running: {}", e));
+            return Err(e);
+        }
+        let registration = state::RunnerRegistration::new(&self.command);
+        self.id = Some(registration.id());
+        self.registration = Some(registration);
+
+        self.trace(|| format!("Starting command: {}", self.command));
+
+        // Open a stdin file up front so a missing file fails before anything runs
+        let mut stdin_file = match &self.options.stdin {
+            StdinOption::File(path) => {
+                let path = match (
+                    path.to_str().and_then(SpecialFile::parse),
+                    &self.options.cwd,
+                ) {
+                    (Some(SpecialFile::Null), _) => null_device().to_path_buf(),
+                    (_, Some(cwd)) => cwd.join(path),
+                    (_, None) => path.clone(),
+                };
+                Some(std::fs::File::open(path)?)
+            }
+            _ => None,
+        };
+
+        self.shell_settings = self.effective_shell_settings().await;
+        if self.shell_settings.verbose && !self.nested {
+            eprintln!("{}", redact(&self.command));
+        }
+
+        // Check if this is a virtual command
+        let powershell = self.options.shell == ShellChoice::PowerShell;
+        let raw = self.options.raw_shell;
+        let context = expand::Context {
+            options: &self.options,
+            cwd: self.options.cwd.as_deref(),
+            settings: &self.shell_settings,
+            cancel: &self.cancel,
+            parameters: &self.options.parameters,
+        };
+        if self.shell_settings.xtrace && !self.nested {
+            xtrace::trace_command(&redact(&self.command), &context);
+        }
+        let builtin = if powershell || raw || !commands::are_virtual_commands_enabled() {
+            Ok(None)
+        } else {
+            expand::virtual_command(&self.command, &context).await
+        };
+        let builtin = match builtin {
+            Ok(builtin) => builtin,
+            Err(e) => {
+                self.finished = true;
+                self.registration = None;
+                self.trace(|| format!("Not running: {}", e));
+                return Err(e);
+            }
+        };
+        if let Some(result) = match builtin {
+            Some(call) => self.try_virtual_command(call, &mut stdin_file).await,
+            None => None,
+        } {
+            self.result = Some(self.captured(result));
+            self.finished = true;
+            self.registration = None;
+            if self.cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            return Ok(());
+        }
+
+        // Compound commands with virtual commands in them are executed here,
+        // command by command. A heartbeat watches a process as a whole, so
+        // commands with one still go to the shell.
+        let pipe = matches!(self.options.stdin, StdinOption::Pipe);
+        let whole = powershell || raw || pipe || self.options.heartbeat.is_some();
+        if let Some(parsed) = plan::plan(&self.command, self.reports_steps().await)
+            .filter(|_| !whole && self.options.shell_operators)
+        {
+            self.trace(|| format!("Executing in-process: {}", parsed));
+            drop(stdin_file);
+            let outcome = self.run_parsed(&parsed).await;
+            self.finished = true;
+            self.registration = None;
+            self.result = Some(self.captured(outcome?));
+            return Ok(());
+        }
+
+        // Plain commands are executed directly, with their redirects applied
+        // here; everything else goes through a real shell.
+        let literal = if raw || !self.options.shell_operators {
+            None
+        } else if powershell {
+            powershell_literal_argv(&self.command).map(|argv| (argv, Vec::new(), Vec::new()))
+        } else if needs_real_shell(&self.command) {
+            None
+        } else {
+            parse_shell_command(&self.command)
+                .ok()
+                .as_ref()
+                .and_then(|parsed| redirect::literal_command(parsed, self.options.env.as_ref()))
+        };
+        let direct = literal.and_then(|(argv, redirects, assigned)| {
+            let argv = self.direct_exec_argv(argv, &assigned)?;
+            let routes = Routes::open(&redirects, self.options.cwd.as_deref()).ok()?;
+            Some((argv, routes.stdio()?, assigned))
+        });
+        let (argv, redirected, assigned) = match direct {
+            Some((argv, stdio, assigned)) => {
+                self.trace(|| format!("Direct exec (no shell): {:?}", argv));
+                (argv, Some(stdio), assigned)
+            }
+            None => {
+                let shell = find_shell(self.options.shell);
+                let argv = if powershell {
+                    shell.argv(&self.command)
+                } else {
+                    script::shell_argv(
+                        &shell,
+                        &self.command,
+                        &self.shell_settings,
+                        &self.options.parameters,
+                    )
+                };
+                (argv, None, Vec::new())
+            }
+        };
+        let mut cmd = program_command(argv, self.options.line_buffered);
+
+        // Configure stdin
+        match &self.options.stdin {
+            StdinOption::Inherit => {
+                cmd.stdin(Stdio::inherit());
+            }
+            StdinOption::Pipe => {
+                cmd.stdin(Stdio::piped());
+            }
+            StdinOption::Content(_) => {
+                cmd.stdin(Stdio::piped());
+            }
+            StdinOption::Null => {
+                cmd.stdin(Stdio::null());
+            }
+            StdinOption::File(_) => {
+                if let Some(file) = stdin_file.take() {
+                    cmd.stdin(Stdio::from(file));
+                }
+            }
+        }
+
+        // Configure stdout/stderr
+        if self.options.capture || self.options.mirror {
+            cmd.stdout(Stdio::piped());
+            cmd.stderr(Stdio::piped());
+        } else {
+            cmd.stdout(Stdio::inherit());
+            cmd.stderr(Stdio::inherit());
+        }
+
+        if let Some(stdio) = redirected {
+            if let Some(stdin) = stdio.stdin {
+                cmd.stdin(stdin);
+            }
+            if let Some(stdout) = stdio.stdout {
+                cmd.stdout(stdout);
+            }
+            if let Some(stderr) = stdio.stderr {
+                cmd.stderr(stderr);
+            }
+        }
+
+        // Set working directory. Fall back to a valid directory when the
+        // inherited working directory has been deleted (issue #44).
+        if let Some(cwd) = resolve_spawn_cwd(self.options.cwd.as_ref()) {
+            cmd.current_dir(cwd);
+        }
+
+        // Set environment
+        if let Some(ref env_vars) = self.options.env {
+            for (key, value) in env_vars {
+                cmd.env(key, value);
+            }
+        }
+        cmd.envs(assigned);
+        cmd.envs(console::child_env(&self.options));
+        color::apply(&mut cmd, &self.options);
+        cmd.envs(self.trace_env.iter().map(|(key, value)| (key, value)));
+
+        // Spawn the process
+        let child = cmd.spawn()?;
+        self.tracked = state::track_spawned(&child);
+        if let Some(registration) = &self.registration {
+            registration.set_pid(child.id());
+        }
+        if let Some(emitter) = &self.emitter {
+            emitter.emit(EventType::Spawn, EventData::None).await;
+        }
+        self.child = Some(child);
+
+        Ok(())
+    }
+
+    /// Run the process to completion
+    ///
+    /// The [hooks](crate::hooks) run before and after it.
+    pub async fn run(&mut self) -> Result<CommandResult> {
+        let hooks = hooks::exec_hooks();
+        if !hooks.is_empty() {
+            let spec = ExecSpec {
+                command: self.command.clone(),
+                options: self.options.clone(),
+            };
+            let spec = hooks
+                .run_before(spec)
+                .await
+                .inspect_err(|_| self.finished = true)?;
+            (self.command, self.options) = (spec.command, spec.options);
+        }
+        let started_at = chrono::Utc::now();
+        let started = Instant::now();
+        let span = CommandSpan::start(&self.command);
+        self.trace_env = span.as_ref().map(CommandSpan::env).unwrap_or_default();
+        let outcome = self.run_to_completion().await;
+        instrument::command_finished(started.elapsed(), &outcome);
+        if let Some(span) = span {
+            span.end(&outcome);
+        }
+        history::record(
+            &self.command,
+            self.options.cwd.as_ref(),
+            started_at,
+            &outcome,
+        );
+        if let Some(emitter) = &self.emitter {
+            match &outcome {
+                Ok(result) => {
+                    emitter
+                        .emit(EventType::Exit, EventData::ExitCode(result.code))
+                        .await;
+                    emitter
+                        .emit(EventType::End, EventData::Result(result.clone()))
+                        .await;
+                }
+                Err(e) => {
+                    emitter
+                        .emit(EventType::Error, EventData::Error(e.to_string()))
+                        .await;
+                }
+            }
+        }
+        if !hooks.is_empty() {
+            let spec = ExecSpec {
+                command: self.command.clone(),
+                options: self.options.clone(),
+            };
+            hooks.run_after(&spec, &outcome).await;
+        }
+        outcome
+    }
+
+    /// Mirror output to this process's stdout/stderr; see [`RunOptions::mirror`]
+    ///
+    /// Like the other `with_*` methods this changes the runner's options, so
+    /// call it before the command starts.
+    pub fn with_mirror(mut self, enabled: bool) -> Self {
+        self.options.mirror = enabled;
+        self
+    }
+
+    /// Capture output in the result; see [`RunOptions::capture`]
+    pub fn with_capture(mut self, enabled: bool) -> Self {
+        self.options.capture = enabled;
+        self
+    }
+
+    /// Run the command in `path`
+    pub fn with_cwd(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.cwd = Some(path.into());
+        self
+    }
+
+    /// Set one environment variable for the command
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options
+            .env
+            .get_or_insert_with(Default::default)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// How to provide standard input
+    pub fn with_stdin(mut self, stdin: StdinOption) -> Self {
+        self.options.stdin = stdin;
+        self
+    }
+
+    /// Kill the command if it runs longer than `timeout`
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Kill the command when `token` is cancelled; see [`RunOptions::cancel`]
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.cancel = token.child_token();
+        self.options.cancel = Some(token);
+        self
+    }
+
+    /// Send this runner's events (`spawn`, `stdout`, `stderr`, `data`,
+    /// `exit`, `end`, `error`) to `emitter` as the command runs
+    pub fn with_emitter(mut self, emitter: Arc<StreamEmitter>) -> Self {
+        self.emitter = Some(emitter);
+        self
+    }
+
+    /// Forward each line the command writes to stdout, without its line
+    /// ending, to `sink`: an `mpsc::Sender<String>` or a
+    /// [`LineSink::callback`]
+    ///
+    /// Lines are still captured and mirrored as the options say.
+    pub fn pipe_stdout_to(mut self, sink: impl Into<LineSink>) -> Self {
+        self.stdout_sink = Some(sink.into());
+        self
+    }
+
+    /// Forward each line the command writes to stderr to `sink`; see
+    /// [`pipe_stdout_to`](Self::pipe_stdout_to)
+    pub fn pipe_stderr_to(mut self, sink: impl Into<LineSink>) -> Self {
+        self.stderr_sink = Some(sink.into());
+        self
+    }
+
+    /// Read and change `settings` instead of the global shell settings
+    pub(crate) fn with_session_settings(
+        mut self,
+        settings: Arc<tokio::sync::RwLock<ShellSettings>>,
+    ) -> Self {
+        self.session_settings = Some(settings);
+        self
+    }
+
+    /// The emitter this runner reports events to, if any
+    pub fn emitter(&self) -> Option<&Arc<StreamEmitter>> {
+        self.emitter.as_ref()
+    }
+
+    async fn run_to_completion(&mut self) -> Result<CommandResult> {
+        let mut restarts = 0;
+        loop {
+            self.start().await?;
+
+            if let Some(result) = &self.result {
+                let result = result.clone();
+                self.check_errexit(&result)?;
+                return Ok(result);
+            }
+
+            let outcome = self.wait_for_exit().await;
+            let allowed = match self.options.heartbeat.as_ref().map(Heartbeat::action) {
+                Some(StallAction::Restart(allowed)) => allowed,
+                _ => 0,
+            };
+            match outcome {
+                Err(Error::Stalled { .. }) if restarts < allowed => {
+                    restarts += 1;
+                    self.trace(|| format!("Restarting stalled command ({}/{})", restarts, allowed));
+                    self.started = false;
+                    self.finished = false;
+                    self.partial.send_replace(OutputSnapshot::default());
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+
+    /// Collect the output of the spawned child and wait for it to exit,
+    /// enforcing the timeout, cancellation and heartbeat
+    async fn wait_for_exit(&mut self) -> Result<CommandResult> {
+        let mut child = self
+            .child
+            .take()
+            .ok_or_else(|| Error::Io(std::io::Error::other("Process not started")))?;
+
+        // Handle stdin content if provided
+        if let StdinOption::Content(ref content) = self.options.stdin {
+            if let Some(mut stdin) = child.stdin.take() {
+                let content = content.clone();
+                tokio::spawn(async move {
+                    let _ = stdin.write_all(content.as_bytes()).await;
+                    let _ = stdin.shutdown().await;
+                });
+            }
+        }
+
+        let heartbeat = self.options.heartbeat.as_ref();
+        if let Some(heartbeat) = heartbeat {
+            heartbeat.beat();
+        }
+
+        // Collect output, reading both pipes at once so that output on either
+        // counts as a heartbeat
+        let started = Instant::now();
+        let limit = self.options.capture_tail;
+        let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+        let read_stdout = async {
+            let (mut content, mut timed) = (TailBuffer::new(limit), Vec::new());
+            if let Some(stdout) = stdout {
+                let mut reader = BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    if let Some(heartbeat) = heartbeat {
+                        heartbeat.beat();
+                    }
+                    let filters = &self.options.output_filters;
+                    let Some(line) = filter::apply(filters, StreamKind::Stdout, line) else {
+                        continue;
+                    };
+                    if self.options.mirror {
+                        println!("{}", console::for_terminal(&line));
+                    }
+                    if let Some(emitter) = &self.emitter {
+                        emitter
+                            .emit_output(EventType::Stdout, format!("{}\n", line))
+                            .await;
+                    }
+                    self.forward_line(StreamKind::Stdout, &line, started, &mut timed)
+                        .await;
+                    content.push_str(&line);
+                    content.push_str("\n");
+                }
+            }
+            (content.into_string(), timed)
+        };
+        let read_stderr = async {
+            let (mut content, mut timed) = (TailBuffer::new(limit), Vec::new());
+            if let Some(stderr) = stderr {
+                let mut reader = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = reader.next_line().await {
+                    if let Some(heartbeat) = heartbeat {
+                        heartbeat.beat();
+                    }
+                    let filters = &self.options.output_filters;
+                    let Some(line) = filter::apply(filters, StreamKind::Stderr, line) else {
+                        continue;
+                    };
+                    if self.options.mirror {
+                        eprintln!("{}", console::for_terminal(&line));
+                    }
+                    if let Some(emitter) = &self.emitter {
+                        emitter
+                            .emit_output(EventType::Stderr, format!("{}\n", line))
+                            .await;
+                    }
+                    self.forward_line(StreamKind::Stderr, &line, started, &mut timed)
+                        .await;
+                    content.push_str(&line);
+                    content.push_str("\n");
+                }
+            }
+            (content.into_string(), timed)
+        };
+        let output = async {
+            let ((stdout, mut timed), (stderr, stderr_timed)) =
+                tokio::join!(read_stdout, read_stderr);
+            timed.extend(stderr_timed);
+            timed.sort_by_key(|line| line.at);
+            let status = child.wait().await?;
+            Ok::<_, Error>((stdout, stderr, timed, status))
+        };
+
+        let limited = async {
+            match self.options.timeout {
+                Some(limit) => tokio::time::timeout(limit, output)
+                    .await
+                    .unwrap_or_else(|_| Err(Error::timeout(self.command.clone(), limit))),
+                None => output.await,
+            }
+        };
+        let watchdog = async {
+            let Some(heartbeat) = heartbeat else {
+                return std::future::pending().await;
+            };
+            loop {
+                let idle = heartbeat.stalled().await;
+                self.trace(|| format!("Stalled: no output for {:?}", idle));
+                if let Some(emitter) = &self.emitter {
+                    emitter
+                        .emit(EventType::Stalled, EventData::Idle(idle))
+                        .await;
+                }
+                if heartbeat.action() != StallAction::Notify {
+                    return Error::stalled(self.command.clone(), idle);
+                }
+                // Notify again after another quiet interval
+                heartbeat.beat();
+            }
+        };
+        let output = tokio::select! {
+            output = limited => output,
+            _ = self.cancel.cancelled() => Err(Error::Cancelled),
+            stalled = watchdog => Err(stalled),
+        };
+        if let Err(Error::Timeout { .. } | Error::Cancelled | Error::Stalled { .. }) = output {
+            self.trace(|| format!("Stopping {}: {:?}", self.command, output.as_ref().err()));
+            let _ = child.start_kill();
+            let _ = child.wait().await;
+            self.tracked = None;
+            self.registration = None;
+            self.finished = true;
+        }
+        let (stdout_content, stderr_content, timed_lines, status) = output?;
+
+        self.tracked = None;
+        self.registration = None;
+        let result = self.captured(CommandResult {
+            timed_lines,
+            ..CommandResult::from_exit_status(stdout_content, stderr_content, status)
+        });
+
+        self.result = Some(result.clone());
+        self.finished = true;
+
+        self.check_errexit(&result)?;
+        Ok(result)
+    }
+
+    /// The stdin content to report in the result, when
+    /// [`RunOptions::capture_stdin`] asks for it
+    fn recorded_stdin(&self) -> Option<String> {
+        let limit = self.options.capture_stdin?;
+        match &self.options.stdin {
+            StdinOption::Content(content) => Some(utils::truncate_at_char_boundary(content, limit)),
+            _ => None,
+        }
+    }
+
+    /// Trace an event of this runner
+    fn trace(&self, message_fn: impl FnOnce() -> String) {
+        trace::trace_runner("ProcessRunner", self.id, message_fn);
+    }
+
+    /// Shell settings in effect for this runner: the per-run override from
+    /// [`RunOptions::shell_settings`], the settings of the session it runs
+    /// in, or the global settings.
+    pub async fn effective_shell_settings(&self) -> ShellSettings {
+        match (&self.options.shell_settings, &self.session_settings) {
+            (Some(settings), _) => settings.clone(),
+            (None, Some(settings)) => settings.read().await.clone(),
+            (None, None) => get_shell_settings().await,
+        }
+    }
+
+    /// With `errexit` enabled, turn a non-zero exit code or a terminating
+    /// signal into an error
+    fn check_errexit(&self, result: &CommandResult) -> Result<()> {
+        let signal = result.signal;
+        if self.shell_settings.errexit && (result.code != 0 || signal.is_some()) {
+            self.trace(|| {
+                format!(
+                    "Errexit mode: command failed with code {} (signal {:?})",
+                    result.code, signal
+                )
+            });
+            return Err(match signal {
+                Some(signal) => Error::killed_by_signal(&self.command, signal, &result.stderr),
+                None => Error::command_failed(&self.command, result.code, &result.stderr, None),
+            });
+        }
+        Ok(())
+    }
+
+    /// Return `argv`, the literal words of a plain program invocation, when
+    /// it can be executed directly because the program resolves to an
+    /// executable on the PATH the command gets: the one `assigned` to it,
+    /// else the one in its `env` option, else this process's.
+    ///
+    /// Shell builtins and functions aren't on PATH and run through the shell.
+    fn direct_exec_argv(
+        &self,
+        argv: Vec<String>,
+        assigned: &[(String, String)],
+    ) -> Option<Vec<String>> {
+        if !self.options.direct_exec {
+            return None;
+        }
+
+        // A relative path like `./script.sh` is ambiguous once a cwd option
+        // applies; let the shell resolve it.
+        let program = std::path::Path::new(&argv[0]);
+        if program.is_relative() && program.components().count() > 1 {
+            return None;
+        }
+
+        let path = assigned
+            .iter()
+            .rev()
+            .find(|(name, _)| name == "PATH")
+            .map(|(_, path)| path.into())
+            .or_else(|| self.options.env.as_ref()?.get("PATH").map(Into::into))
+            .or_else(|| std::env::var_os("PATH"));
+        let cwd = match resolve_spawn_cwd(self.options.cwd.as_ref()) {
+            Some(cwd) => cwd,
+            None => std::env::current_dir().ok()?,
+        };
+        let resolved = which::which_in(&argv[0], path, cwd).ok()?;
+        // `.cmd`/`.bat` shims (e.g. `npm`) can't be spawned without cmd.exe.
+        if cfg!(windows)
+            && !resolved
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("exe"))
+        {
+            return None;
+        }
+        Some(argv)
+    }
+
+    /// Kill the process
+    pub fn kill(&mut self) -> Result<()> {
+        self.cancel.cancel();
+        if let Some(ref mut child) = self.child {
+            child.start_kill()?;
+        }
+        Ok(())
+    }
+
+    /// Finish with this runner: wait for a process that was started but not
+    /// waited for to exit, delivering the rest of its output to the mirror,
+    /// emitter and sinks, then close the sinks
+    ///
+    /// A runner dropped with its process still running leaves the process
+    /// behind and logs a warning. The command's own failure is not an
+    /// error here; only failing to wait for it is.
+    pub async fn close(mut self) -> Result<()> {
+        if self.child.is_some() {
+            if let Err(Error::Io(e)) = self.wait_for_exit().await {
+                return Err(Error::Io(e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Token that cancels this runner
+    ///
+    /// Cancelling it from another task kills the process and makes
+    /// [`run`](Self::run) return [`Error::Cancelled`]. It is a child of
+    /// [`RunOptions::cancel`], when set.
+    pub fn cancel_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Check if the process is finished
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Get the result if available
+    pub fn result(&self) -> Option<&CommandResult> {
+        self.result.as_ref()
+    }
+
+    /// Get the command string
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// Get the options
+    pub fn options(&self) -> &RunOptions {
+        &self.options
+    }
+}
+
+impl Drop for ProcessRunner {
+    fn drop(&mut self) {
+        if self.child.is_some() && !self.cancel.is_cancelled() {
+            trace::warn_not_closed(
+                "ProcessRunner",
+                &format!(
+                    "runner for `{}` dropped while its process may still be running; \
+                     call close() to wait for it",
+                    redact(&self.command)
+                ),
+            );
+        }
+    }
+}
+
+/// A command running `argv`, under `stdbuf` when `line_buffered` asks for
+/// per-line flushing
+///
+/// `stdbuf` works by preloading a library into the program, which children
+/// inherit, so a shell running a pipeline line-buffers every stage.
+pub(crate) fn program_command(argv: Vec<String>, line_buffered: bool) -> Command {
+    let stdbuf = line_buffered.then(stdbuf_path).flatten();
+    let mut cmd = match stdbuf {
+        Some(stdbuf) => {
+            let mut cmd = Command::new(stdbuf);
+            cmd.args(["-oL", "-eL"]).args(&argv);
+            cmd
+        }
+        None => {
+            let mut cmd = Command::new(&argv[0]);
+            cmd.args(&argv[1..]);
+            cmd
+        }
+    };
+    if line_buffered {
+        cmd.env("PYTHONUNBUFFERED", "1");
+    }
+    cmd
+}
+
+/// Where `stdbuf` is installed (`gstdbuf` with Homebrew's coreutils)
+fn stdbuf_path() -> Option<&'static PathBuf> {
+    static STDBUF: std::sync::OnceLock<Option<PathBuf>> = std::sync::OnceLock::new();
+    STDBUF
+        .get_or_init(|| {
+            which::which("stdbuf")
+                .or_else(|_| which::which("gstdbuf"))
+                .ok()
+        })
+        .as_ref()
+}
@@ -0,0 +1,788 @@
+//! Running `&&`/`||`/`;` lists, pipelines, subshells and background jobs
+//! in-process
+//!
+//! A compound command that contains a virtual command isn't handed to
+//! `sh` as a whole: the runner walks its [`ParsedCommand`] tree and runs
+//! each simple command as a nested runner, so builtins take part and `cd`
+//! affects the commands after it, as in `mkdir -p x && cd x && pwd`. Only
+//! the commands that aren't virtual spawn a process. A command followed by
+//! `&` starts as a [job](crate::jobs) and the list goes on right away, so
+//! these are executed here too, virtual commands or not.
+//!
+//! A list is also executed here when the runner's emitter has listeners
+//! for [`CommandStart`](EventType::CommandStart) or
+//! [`CommandFinish`](EventType::CommandFinish), which report each of its
+//! commands.
+//!
+//! Pipelines without a virtual stage still run in the shell. In those that
+//! have one, the stages run at the same time, connected through bounded
+//! channels, so output streams from a virtual stage into a spawned one and
+//! back; compound stages run in the shell.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+use super::plan::has_virtual_command;
+use super::redirect::Routes;
+use super::{mirror_text, ProcessRunner};
+use crate::commands::{change_dir, execute_builtin, CommandExecutor, SpecialFile};
+use crate::expand::{self, Parameters, VirtualCall};
+use crate::jobs;
+use crate::shell_parser::{parse_shell_command, TokenType};
+use crate::usage::ResourceUsage;
+use crate::{
+    CancellationToken, CommandContext, CommandResult, Error, EventData, EventType, ParsedCommand,
+    Result, ShellSettings, StdinOption, StreamChunk, StreamKind,
+};
+
+type Run<'a> = Pin<Box<dyn Future<Output = Result<CommandResult>> + Send + 'a>>;
+
+/// The stdout of a pipeline stage, on its way to the next one
+type Pipe = mpsc::Receiver<Vec<u8>>;
+
+/// Chunks a stage can write ahead of the stage reading them
+const PIPE_CHUNKS: usize = 16;
+
+impl ProcessRunner {
+    /// Leave in `effects` where the commands' `cd`s took them, without
+    /// moving the process, and whether they ran `exit`
+    pub(crate) fn with_session_effects(mut self, effects: Arc<Mutex<SessionEffects>>) -> Self {
+        self.session = Some(effects);
+        self
+    }
+
+    /// The settings `set` changes for this command: its session's, or a
+    /// copy of the runner's own, which go away with it
+    pub(super) fn settings_handle(&self) -> Arc<tokio::sync::RwLock<ShellSettings>> {
+        match &self.session_settings {
+            Some(settings) => settings.clone(),
+            None => Arc::new(tokio::sync::RwLock::new(self.shell_settings.clone())),
+        }
+    }
+
+    /// Whether the commands of a list are to be reported one by one
+    pub(super) async fn reports_steps(&self) -> bool {
+        let Some(emitter) = &self.emitter else {
+            return false;
+        };
+        emitter.listener_count(&EventType::CommandStart).await
+            + emitter.listener_count(&EventType::CommandFinish).await
+            > 0
+    }
+
+    /// Execute `parsed`, a plan from [`plan`], enforcing the timeout
+    pub(super) async fn run_parsed(&mut self, parsed: &ParsedCommand) -> Result<CommandResult> {
+        let executor = Executor {
+            runner: self,
+            settings: self.settings_handle(),
+            cancel: self.cancel.child_token(),
+            started: Instant::now(),
+            stdin: Mutex::new(Some(self.options.stdin.clone())),
+            cwd: Mutex::new(self.options.cwd.clone()),
+            dir_vars: Mutex::new(HashMap::new()),
+            exited: AtomicBool::new(false),
+            status: AtomicI32::new(self.options.parameters.status),
+        };
+        let outcome = {
+            let mut run = match parsed {
+                ParsedCommand::Sequence {
+                    commands,
+                    operators,
+                } if self.emitter.is_some() => {
+                    Box::pin(executor.run_sequence(commands, operators, true))
+                }
+                _ => executor.run(parsed),
+            };
+            match self.options.timeout {
+                None => run.await,
+                Some(limit) => tokio::select! {
+                    outcome = &mut run => outcome,
+                    _ = tokio::time::sleep(limit) => {
+                        // Let the running command be killed before giving up on it
+                        executor.cancel.cancel();
+                        let _ = run.await;
+                        Err(Error::timeout(self.command.clone(), limit))
+                    }
+                },
+            }
+        };
+        if let Some(session) = &self.session {
+            let mut effects = lock(session);
+            if let Some(cwd) = lock(&executor.cwd).take() {
+                effects.cwd = cwd;
+            }
+            effects.exited = executor.exited.load(Ordering::SeqCst);
+        }
+        // A `set -e` or `set +e` in the list applies to its result too
+        self.shell_settings.errexit = executor.settings().await.errexit;
+        outcome
+    }
+}
+
+/// What the commands of a list leave for the session that ran them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SessionEffects {
+    /// The directory, moved by `cd`
+    pub cwd: PathBuf,
+    /// Whether `exit` ended the list
+    pub exited: bool,
+}
+
+/// State shared by the commands of one parsed command
+struct Executor<'a> {
+    runner: &'a ProcessRunner,
+    /// The settings its commands' `set`s change
+    settings: Arc<tokio::sync::RwLock<ShellSettings>>,
+    cancel: CancellationToken,
+    started: Instant,
+    /// The runner's stdin, until the first command takes it
+    stdin: Mutex<Option<StdinOption>>,
+    /// Directory for the commands, following `cd`
+    cwd: Mutex<Option<PathBuf>>,
+    /// `PWD` and `OLDPWD` for the commands, once a `cd` has set them
+    dir_vars: Mutex<HashMap<String, String>>,
+    /// Set by `exit`, which ends the list (or the subshell) it is in
+    exited: AtomicBool,
+    /// `$?` for the next command
+    status: AtomicI32,
+}
+
+impl Executor<'_> {
+    /// Run `parsed`, making its exit status `$?` for the commands after it
+    fn run<'b>(&'b self, parsed: &'b ParsedCommand) -> Run<'b> {
+        Box::pin(async move {
+            let result = self.run_unrecorded(parsed).await?;
+            self.status.store(result.code, Ordering::SeqCst);
+            Ok(result)
+        })
+    }
+
+    fn run_unrecorded<'b>(&'b self, parsed: &'b ParsedCommand) -> Run<'b> {
+        Box::pin(async move {
+            match parsed {
+                ParsedCommand::Simple { cmd, .. } => {
+                    let command = parsed.to_string();
+                    if cmd == "cd" {
+                        return self.change_dir(&command).await;
+                    }
+                    let result = self.run_command(&command, None, true).await?;
+                    if cmd == "exit" {
+                        self.exited.store(true, Ordering::SeqCst);
+                    }
+                    Ok(result)
+                }
+                ParsedCommand::Sequence {
+                    commands,
+                    operators,
+                } => self.run_sequence(commands, operators, false).await,
+                ParsedCommand::Pipeline { commands } => {
+                    if commands.iter().any(has_virtual_command) {
+                        self.run_pipeline(commands).await
+                    } else {
+                        self.run_command(&parsed.to_string(), None, true).await
+                    }
+                }
+                ParsedCommand::Subshell { command } => self.run_subshell(command).await,
+                ParsedCommand::Background { command } => {
+                    self.background(command).await;
+                    Ok(CommandResult::success_empty())
+                }
+                ParsedCommand::Not { command } => {
+                    let result = self.run(command).await?;
+                    Ok(CommandResult {
+                        code: i32::from(result.is_success()),
+                        signal: None,
+                        ..result
+                    })
+                }
+                ParsedCommand::Time { command, posix } => self.run_timed(command, *posix).await,
+            }
+        })
+    }
+
+    /// Run `commands` as a list: `&&` and `||` skip the next command on
+    /// failure or success, and with `errexit` a failure outside of an
+    /// `&&`/`||` test ends the list
+    ///
+    /// With `steps`, each command that runs is reported to the emitter.
+    async fn run_sequence(
+        &self,
+        commands: &[ParsedCommand],
+        operators: &[TokenType],
+        steps: bool,
+    ) -> Result<CommandResult> {
+        let mut total = CommandResult::default();
+        for (i, command) in commands.iter().enumerate() {
+            let skip = match i.checked_sub(1).and_then(|before| operators.get(before)) {
+                Some(TokenType::And) => !total.is_success(),
+                Some(TokenType::Or) => total.is_success(),
+                _ => false,
+            };
+            if skip {
+                continue;
+            }
+            if steps {
+                self.step(EventType::CommandStart, i, command, None).await;
+            }
+            let result = self.run(command).await?;
+            if steps {
+                let code = Some(result.code);
+                self.step(EventType::CommandFinish, i, command, code).await;
+            }
+            append(&mut total, result);
+            // Like a command tested by `&&` or `||`, a negated one doesn't
+            // trip `errexit`
+            let tested = matches!(operators.get(i), Some(TokenType::And | TokenType::Or))
+                || matches!(command, ParsedCommand::Not { .. });
+            // Read after each command, which may have run `set -e`
+            let errexit = self.settings().await.errexit;
+            if self.exited.load(Ordering::SeqCst) || (errexit && !tested && !total.is_success()) {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Run the stages at once, each reading what the one before it writes
+    ///
+    /// The exit code is the last stage's, or with `pipefail` that of the
+    /// last stage that failed.
+    async fn run_pipeline(&self, stages: &[ParsedCommand]) -> Result<CommandResult> {
+        let last = stages.len() - 1;
+        let mut input = None;
+        let mut running = Vec::with_capacity(stages.len());
+        for (i, stage) in stages.iter().enumerate() {
+            let (output, next) = if i < last {
+                let (tx, rx) = mpsc::channel(PIPE_CHUNKS);
+                (Some(tx), Some(rx))
+            } else {
+                (None, None)
+            };
+            running.push(self.run_stage(stage, input.take(), output));
+            input = next;
+        }
+
+        let mut total = CommandResult::default();
+        let mut failed = None;
+        for result in join_all(running).await {
+            let result = result?;
+            if !result.is_success() {
+                failed = Some((result.code, result.signal));
+            }
+            append(&mut total, result);
+        }
+        if let (true, Some((code, signal))) = (self.settings().await.pipefail, failed) {
+            total.code = code;
+            total.signal = signal;
+        }
+        Ok(total)
+    }
+
+    /// Run one stage of a pipeline, reading `input` from the stage before
+    /// and writing its stdout to `output` for the stage after
+    ///
+    /// Spawned stages stream both ways. A virtual stage reads all of its
+    /// input before it runs, since builtins take stdin as a whole, and
+    /// streams its output. The last stage's output is shown like any
+    /// command's; the others only have their stderr mirrored.
+    fn run_stage<'b>(
+        &'b self,
+        stage: &'b ParsedCommand,
+        input: Option<Pipe>,
+        output: Option<mpsc::Sender<Vec<u8>>>,
+    ) -> Run<'b> {
+        Box::pin(async move {
+            let command = stage.to_string();
+            let Some(output) = output else {
+                // The nested runner expands and dispatches the command
+                let stdin = match input {
+                    Some(input) if has_virtual_command(stage) => {
+                        Some(StdinOption::Content(read_all(input).await))
+                    }
+                    Some(input) => {
+                        return self.run_process_stage(&command, Some(input), None).await
+                    }
+                    None => None,
+                };
+                return self.run_command(&command, stdin, true).await;
+            };
+            let builtin = match stage {
+                ParsedCommand::Simple { .. } if has_virtual_command(stage) => {
+                    let cwd = lock(&self.cwd).clone();
+                    let parameters = self.parameters();
+                    let settings = self.settings().await;
+                    let context = expand::Context {
+                        options: &self.runner.options,
+                        cwd: cwd.as_deref(),
+                        settings: &settings,
+                        cancel: &self.cancel,
+                        parameters: &parameters,
+                    };
+                    expand::virtual_command(&command, &context).await?
+                }
+                _ => None,
+            };
+            let result = match builtin {
+                Some(call) => self.run_builtin_stage(call, input, output).await,
+                None => self.run_process_stage(&command, input, Some(output)).await,
+            }?;
+            mirror_text(self.runner.options.mirror, true, &result.stderr);
+            Ok(result)
+        })
+    }
+
+    /// Run a virtual command as a pipeline stage, sending its stdout on
+    async fn run_builtin_stage(
+        &self,
+        call: VirtualCall,
+        input: Option<Pipe>,
+        output: mpsc::Sender<Vec<u8>>,
+    ) -> Result<CommandResult> {
+        let cwd = lock(&self.cwd).clone();
+        let mut routes = match Routes::open(&call.redirects, cwd.as_deref()) {
+            Ok(routes) => routes,
+            Err(message) => return Ok(CommandResult::error(format!("{}\n", message))),
+        };
+        let stdin = match (routes.stdin_text(), input) {
+            (Some(text), _) => Some(text?),
+            (None, Some(input)) => Some(read_all(input).await),
+            (None, None) => self.stdin_text(self.take_stdin())?,
+        };
+        let (tx, mut rx) = mpsc::channel(PIPE_CHUNKS);
+        let options = call.options(&self.runner.options);
+        let ctx = CommandContext {
+            args: call.args,
+            stdin,
+            cwd,
+            env: options.env.clone(),
+            output_tx: Some(tx),
+            is_cancelled: None,
+            cancel_token: Some(self.cancel.clone()),
+            shell_settings: Some(self.settings.clone()),
+            executor: Some(
+                CommandExecutor::new(options).with_session_settings(Some(self.settings.clone())),
+            ),
+        };
+        let forward = async move {
+            let mut stderr = String::new();
+            while let Some(chunk) = rx.recv().await {
+                let (stream, text) = match chunk {
+                    StreamChunk::Stdout(text) => (StreamKind::Stdout, text),
+                    StreamChunk::Stderr(text) => (StreamKind::Stderr, text),
+                };
+                // Once the next stage stops reading, closing the channel
+                // stops the command, as SIGPIPE would
+                let open = match routes.route(stream, &text) {
+                    Some(StreamKind::Stdout) => output.send(text.into_bytes()).await.is_ok(),
+                    Some(StreamKind::Stderr) => {
+                        stderr.push_str(&text);
+                        true
+                    }
+                    None => true,
+                };
+                if !open {
+                    break;
+                }
+            }
+            stderr
+        };
+        let (result, stderr) = tokio::join!(execute_builtin(&call.name, ctx), forward);
+        let result = result.unwrap_or_else(|| CommandResult::error_with_code("", 127));
+        Ok(CommandResult {
+            stdout: String::new(),
+            stderr,
+            ..result
+        })
+    }
+
+    /// Spawn a pipeline stage, feeding it `input` and sending its stdout to
+    /// `output`, if there is a stage after it
+    async fn run_process_stage(
+        &self,
+        command: &str,
+        input: Option<Pipe>,
+        output: Option<mpsc::Sender<Vec<u8>>>,
+    ) -> Result<CommandResult> {
+        let stdin = match input {
+            Some(_) => StdinOption::Pipe,
+            None => self.take_stdin(),
+        };
+        let shown = output.is_none();
+        let mut nested = self.nested(command, stdin, shown).await;
+        nested.start().await?;
+        // The runner keeps the ends this stage doesn't connect to another
+        let (stdin, stdout) = match nested.child.as_mut() {
+            Some(child) => (
+                input.as_ref().and_then(|_| child.stdin.take()),
+                output.as_ref().and_then(|_| child.stdout.take()),
+            ),
+            None => (None, None),
+        };
+
+        let feed = async move {
+            let (Some(mut input), Some(mut stdin)) = (input, stdin) else {
+                return;
+            };
+            while let Some(bytes) = input.recv().await {
+                if stdin.write_all(&bytes).await.is_err() {
+                    break;
+                }
+            }
+        };
+        let forward = async move {
+            let (Some(output), Some(mut stdout)) = (output, stdout) else {
+                return;
+            };
+            let mut buffer = vec![0; 8192];
+            while let Ok(read @ 1..) = stdout.read(&mut buffer).await {
+                if output.send(buffer[..read].to_vec()).await.is_err() {
+                    break;
+                }
+            }
+        };
+        let ((), (), result) = tokio::join!(feed, forward, self.finish(&mut nested, shown));
+        result
+    }
+
+    /// Run `cd`: move the directory of the commands after it, not the
+    /// process's, and set their `PWD` and `OLDPWD`
+    async fn change_dir(&self, command: &str) -> Result<CommandResult> {
+        let cwd = lock(&self.cwd).clone();
+        let parameters = self.parameters();
+        let settings = self.settings().await;
+        let context = expand::Context {
+            options: &self.runner.options,
+            cwd: cwd.as_deref(),
+            settings: &settings,
+            cancel: &self.cancel,
+            parameters: &parameters,
+        };
+        let Some(call) = expand::virtual_command(command, &context).await? else {
+            return self.run_command(command, None, true).await;
+        };
+        let routes = match Routes::open(&call.redirects, cwd.as_deref()) {
+            Ok(routes) => routes,
+            Err(message) => return Ok(CommandResult::error(format!("{}\n", message))),
+        };
+        // Variables assigned in front of `cd`, then those a `cd` before it
+        // set, then the runner's
+        let dir_vars = lock(&self.dir_vars).clone();
+        let env = self.runner.options.env.as_ref();
+        let var = |name: &str| {
+            let assigned = call.env.iter().rev().find(|(var, _)| var == name);
+            assigned
+                .map(|(_, value)| value)
+                .or_else(|| dir_vars.get(name))
+                .or_else(|| env.and_then(|env| env.get(name)))
+                .cloned()
+                .or_else(|| std::env::var(name).ok())
+        };
+        let base = cwd
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let result = match change_dir(&call.args, &base, var) {
+            Ok((dir, output)) => {
+                let mut dir_vars = lock(&self.dir_vars);
+                dir_vars.insert("OLDPWD".to_string(), base.display().to_string());
+                dir_vars.insert("PWD".to_string(), dir.display().to_string());
+                *lock(&self.cwd) = Some(dir);
+                CommandResult::success(output)
+            }
+            Err(result) => result,
+        };
+        let mut routed = CommandResult {
+            stdout: String::new(),
+            stderr: String::new(),
+            ..result.clone()
+        };
+        for (stream, text) in [
+            (StreamKind::Stdout, &result.stdout),
+            (StreamKind::Stderr, &result.stderr),
+        ] {
+            match routes.route(stream, text) {
+                Some(StreamKind::Stdout) => routed.stdout.push_str(text),
+                Some(StreamKind::Stderr) => routed.stderr.push_str(text),
+                None => {}
+            }
+        }
+        mirror_text(self.runner.options.mirror, false, &routed.stdout);
+        mirror_text(self.runner.options.mirror, true, &routed.stderr);
+        Ok(routed)
+    }
+
+    /// Run `command` so that a `cd` or `exit` in it doesn't affect the
+    /// commands after it
+    async fn run_subshell(&self, command: &ParsedCommand) -> Result<CommandResult> {
+        let cwd = lock(&self.cwd).clone();
+        let dir_vars = lock(&self.dir_vars).clone();
+
+        let outcome = self.run(command).await;
+
+        *lock(&self.cwd) = cwd;
+        *lock(&self.dir_vars) = dir_vars;
+        self.exited.store(false, Ordering::SeqCst);
+        outcome
+    }
+
+    /// Run `command`, then report the time it took on stderr, as `time`
+    /// does: elapsed, and the CPU time of the processes it ran
+    async fn run_timed(&self, command: &ParsedCommand, posix: bool) -> Result<CommandResult> {
+        let started = Instant::now();
+        let before = ResourceUsage::children();
+        let mut result = self.run(command).await?;
+        let spent = ResourceUsage::children().since(&before);
+        let report = time_report(started.elapsed(), spent.user, spent.system, posix);
+        mirror_text(self.runner.options.mirror, true, &report);
+        if let Some(emitter) = &self.runner.emitter {
+            emitter
+                .emit_output(EventType::Stderr, report.as_str())
+                .await;
+        }
+        result.stderr.push_str(&report);
+        Ok(result)
+    }
+
+    /// Start `command` as a background job
+    ///
+    /// The job outlives this command, so only the caller's cancellation
+    /// token stops it, not this command's timeout. Its output is mirrored,
+    /// if this command's is, but captured only in the job's own result.
+    async fn background(&self, command: &ParsedCommand) {
+        let mut job = self
+            .nested(&command.to_string(), StdinOption::Null, false)
+            .await;
+        job.options.mirror = self.runner.options.mirror;
+        job.options.cancel = self.runner.options.cancel.clone();
+        job.cancel = job
+            .options
+            .cancel
+            .as_ref()
+            .map(CancellationToken::child_token)
+            .unwrap_or_default();
+        jobs::spawn_job(job);
+    }
+
+    /// Run one command as a nested runner
+    ///
+    /// `stdin` replaces the runner's stdin, which otherwise goes to the
+    /// first command that runs.
+    async fn run_command(
+        &self,
+        command: &str,
+        stdin: Option<StdinOption>,
+        shown: bool,
+    ) -> Result<CommandResult> {
+        let stdin = stdin.unwrap_or_else(|| self.take_stdin());
+        let mut nested = self.nested(command, stdin, shown).await;
+        self.finish(&mut nested, shown).await
+    }
+
+    /// A nested runner for `command`, with the runner's options
+    ///
+    /// Output of commands that aren't `shown`, the inner stages of a
+    /// pipeline, is only captured. Commands other than simple ones run in
+    /// the shell.
+    async fn nested(&self, command: &str, stdin: StdinOption, shown: bool) -> ProcessRunner {
+        let runner = self.runner;
+        let mut options = runner.options.clone();
+        options.stdin = stdin;
+        options.cwd = lock(&self.cwd).clone();
+        let dir_vars = lock(&self.dir_vars).clone();
+        if !dir_vars.is_empty() {
+            options
+                .env
+                .get_or_insert_with(HashMap::new)
+                .extend(dir_vars);
+        }
+        options.cancel = Some(self.cancel.clone());
+        options.timeout = None;
+        options.parameters = self.parameters();
+        options.shell_settings = Some(ShellSettings {
+            errexit: false,
+            ..self.settings().await
+        });
+        options.raw_shell = !matches!(
+            parse_shell_command(command),
+            Ok(ParsedCommand::Simple { .. })
+        );
+        if !shown {
+            options.mirror = false;
+            options.capture = true;
+            options.timed_lines = false;
+            options.output_filters.clear();
+        }
+
+        let mut nested = ProcessRunner::new(command, options);
+        nested.nested = true;
+        nested.session_settings = Some(self.settings.clone());
+        nested.trace_env = runner.trace_env.clone();
+        if shown {
+            nested.stdout_sink = runner.stdout_sink.clone();
+            nested.stderr_sink = runner.stderr_sink.clone();
+            nested.partial = runner.partial.clone();
+        }
+        nested
+    }
+
+    /// Run `nested` to completion, placing its timed lines on this
+    /// command's clock and reporting its output if it is `shown`
+    async fn finish(&self, nested: &mut ProcessRunner, shown: bool) -> Result<CommandResult> {
+        let runner = self.runner;
+        let offset = self.started.elapsed();
+        let mut result = nested.run_to_completion().await?;
+        for line in &mut result.timed_lines {
+            line.at += offset;
+        }
+        if let (true, Some(emitter)) = (shown, &runner.emitter) {
+            emitter
+                .emit_output(EventType::Stdout, result.stdout.as_str())
+                .await;
+            emitter
+                .emit_output(EventType::Stderr, result.stderr.as_str())
+                .await;
+        }
+        Ok(result)
+    }
+
+    /// Report the start or finish of the `index`th command of a list
+    async fn step(
+        &self,
+        event: EventType,
+        index: usize,
+        command: &ParsedCommand,
+        code: Option<i32>,
+    ) {
+        if let Some(emitter) = &self.runner.emitter {
+            let command = command.to_string();
+            let data = EventData::Command {
+                index,
+                command,
+                code,
+            };
+            emitter.emit(event, data).await;
+        }
+    }
+
+    /// The stdin for the next command: the runner's for the first one to
+    /// ask, like the first reader of a shared stdin gets its content, and
+    /// nothing after that
+    fn take_stdin(&self) -> StdinOption {
+        let mut stdin = lock(&self.stdin);
+        match stdin.take() {
+            Some(StdinOption::Inherit) => {
+                *stdin = Some(StdinOption::Inherit);
+                StdinOption::Inherit
+            }
+            Some(taken) => taken,
+            None => StdinOption::Null,
+        }
+    }
+
+    /// The text of `stdin`, for a virtual command
+    fn stdin_text(&self, stdin: StdinOption) -> Result<Option<String>> {
+        Ok(match stdin {
+            StdinOption::Content(content) => Some(content),
+            StdinOption::File(path)
+                if path.to_str().and_then(SpecialFile::parse) == Some(SpecialFile::Null) =>
+            {
+                Some(String::new())
+            }
+            StdinOption::File(path) => {
+                let path = match lock(&self.cwd).as_ref() {
+                    Some(cwd) => cwd.join(path),
+                    None => path,
+                };
+                Some(String::from_utf8_lossy(&std::fs::read(path)?).into_owned())
+            }
+            StdinOption::Inherit | StdinOption::Pipe | StdinOption::Null => None,
+        })
+    }
+
+    /// The runner's parameters, with `$?` as the last command left it
+    fn parameters(&self) -> Parameters {
+        Parameters {
+            status: self.status.load(Ordering::SeqCst),
+            ..self.runner.options.parameters.clone()
+        }
+    }
+
+    /// The settings as the commands so far left them
+    async fn settings(&self) -> ShellSettings {
+        self.settings.read().await.clone()
+    }
+}
+
+/// Add a command's output to what the commands before it wrote, taking
+/// its exit status
+fn append(total: &mut CommandResult, result: CommandResult) {
+    total.stdout.push_str(&result.stdout);
+    total.stderr.push_str(&result.stderr);
+    total.timed_lines.extend(result.timed_lines);
+    total.code = result.code;
+    total.signal = result.signal;
+    total.core_dumped = result.core_dumped;
+}
+
+/// What `time` writes: bash's format, or with `posix` that of `time -p`
+fn time_report(real: Duration, user: Duration, sys: Duration, posix: bool) -> String {
+    let times = [("real", real), ("user", user), ("sys", sys)];
+    if posix {
+        return times
+            .iter()
+            .map(|(name, time)| format!("{} {:.2}\n", name, time.as_secs_f64()))
+            .collect();
+    }
+    let clock = |time: Duration| {
+        let secs = time.as_secs_f64();
+        format!("{}m{:.3}s", time.as_secs() / 60, secs % 60.0)
+    };
+    let lines: String = times
+        .iter()
+        .map(|(name, time)| format!("{}\t{}\n", name, clock(*time)))
+        .collect();
+    format!("\n{}", lines)
+}
+
+/// Everything the stage before wrote
+async fn read_all(mut input: Pipe) -> String {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = input.recv().await {
+        bytes.extend(chunk);
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Wait for all of `futures`, running them at the same time
+async fn join_all<'a, T>(mut futures: Vec<Pin<Box<dyn Future<Output = T> + Send + 'a>>>) -> Vec<T> {
+    let mut outputs: Vec<Option<T>> = futures.iter().map(|_| None).collect();
+    std::future::poll_fn(|cx| {
+        let mut pending = false;
+        for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            if output.is_none() {
+                match future.as_mut().poll(cx) {
+                    Poll::Ready(value) => *output = Some(value),
+                    Poll::Pending => pending = true,
+                }
+            }
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    })
+    .await;
+    outputs.into_iter().flatten().collect()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
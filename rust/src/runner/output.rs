@@ -0,0 +1,91 @@
+//! What the runner does with each line of output: forwarding it to sinks
+//! and the timed capture, keeping the snapshot of output so far, and
+//! cutting the captured output to its tail
+
+use std::time::Instant;
+use tokio::sync::watch;
+
+use super::ProcessRunner;
+use crate::{instrument, tail, CommandResult, OutputSnapshot, StreamKind, TimedLine};
+
+impl ProcessRunner {
+    /// The complete lines of output the command has produced so far
+    ///
+    /// While [`run`](Self::run) borrows the runner, watch the same output
+    /// through [`watch_output`](Self::watch_output) instead.
+    pub fn partial_output(&self) -> OutputSnapshot {
+        self.partial.borrow().clone()
+    }
+
+    /// A receiver that sees the output snapshot change as lines arrive,
+    /// usable while the command runs
+    ///
+    /// ```rust,no_run
+    /// use command_stream::{ProcessRunner, RunOptions, StreamKind};
+    ///
+    /// # async fn example() -> command_stream::Result<()> {
+    /// let mut runner = ProcessRunner::new("cargo build", RunOptions::default().quiet());
+    /// let mut output = runner.watch_output();
+    /// tokio::spawn(async move {
+    ///     while output.changed().await.is_ok() {
+    ///         let last = output.borrow().tail(StreamKind::Stderr, 1).to_string();
+    ///         eprint!("\r{}", last.trim_end());
+    ///     }
+    /// });
+    /// runner.run().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn watch_output(&self) -> watch::Receiver<OutputSnapshot> {
+        self.partial.subscribe()
+    }
+
+    /// `result` as the runner reports it: with the stdin it recorded and,
+    /// under [`RunOptions::capture_tail`], only the tail of its output
+    pub(super) fn captured(&self, mut result: CommandResult) -> CommandResult {
+        if let Some(limit) = self.options.capture_tail {
+            limit.apply(&mut result.stdout);
+            limit.apply(&mut result.stderr);
+        }
+        CommandResult {
+            stdin: self.recorded_stdin(),
+            ..result
+        }
+    }
+
+    /// Hand a complete output line to its sink and, when
+    /// [`RunOptions::timed_lines`] is set, to the timed capture, and count
+    /// its bytes
+    pub(super) async fn forward_line(
+        &self,
+        stream: StreamKind,
+        line: &str,
+        started: Instant,
+        timed: &mut Vec<TimedLine>,
+    ) {
+        instrument::bytes_streamed(stream, line.len() + 1);
+        if self.options.timed_lines {
+            timed.push(TimedLine {
+                stream,
+                at: started.elapsed(),
+                text: line.to_string(),
+            });
+        }
+        let limit = self.options.capture_tail;
+        self.partial.send_modify(|partial| {
+            let (text, lines) = match stream {
+                StreamKind::Stdout => (&mut partial.stdout, &mut partial.lines.0),
+                StreamKind::Stderr => (&mut partial.stderr, &mut partial.lines.1),
+            };
+            tail::push(text, lines, line, limit);
+            tail::push(text, lines, "\n", limit);
+        });
+        let sink = match stream {
+            StreamKind::Stdout => &self.stdout_sink,
+            StreamKind::Stderr => &self.stderr_sink,
+        };
+        if let Some(sink) = sink {
+            sink.send(line.to_string()).await;
+        }
+    }
+}
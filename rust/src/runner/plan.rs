@@ -0,0 +1,221 @@
+//! Which commands the runner executes itself rather than hand to the shell
+//!
+//! A command goes to the shell whole unless the parser reads all of it and
+//! running it in-process changes something: a virtual command in a list or
+//! pipeline, a background job, or a list whose steps are reported. Shell
+//! keywords, function definitions and commands that set the shell's own
+//! state, like `export` and `source`, still need the shell.
+
+use std::collections::HashMap;
+
+use crate::commands::{are_virtual_commands_enabled, BUILTIN_COMMANDS};
+use crate::expand;
+use crate::shell_parser::{
+    is_assignment, needs_real_shell, needs_real_shell_except_expansions, parse_shell_command,
+    tokenize, unquote_word, ParsedArg, TokenType,
+};
+use crate::ParsedCommand;
+
+/// The parsed `command`, if the runner should execute it itself: a compound
+/// command with at least one virtual command in it, or a list whose `steps`
+/// are reported, that the parser understands completely
+pub(super) fn plan(command: &str, steps: bool) -> Option<ParsedCommand> {
+    if !are_virtual_commands_enabled() || needs_real_shell_except_expansions(command) {
+        return None;
+    }
+    let parsed = parse_shell_command(command).ok()?;
+    let list = steps && matches!(parsed, ParsedCommand::Sequence { .. });
+    if matches!(parsed, ParsedCommand::Simple { .. })
+        || !(has_virtual_command(&parsed) || has_background(&parsed) || list)
+        || sets_shell_state(&parsed)
+        || has_compound_syntax(&parsed)
+    {
+        return None;
+    }
+    parsed_whole(command, &parsed).then_some(parsed)
+}
+
+/// The commands of `command` and the operators between them, if it is a
+/// list (`a && b`, `a; b`) the parser read whole and without shell keywords
+pub(crate) fn list_commands(command: &str) -> Option<(Vec<ParsedCommand>, Vec<TokenType>)> {
+    if needs_real_shell_except_expansions(command) {
+        return None;
+    }
+    let parsed = parse_shell_command(command).ok()?;
+    if has_compound_syntax(&parsed) || !parsed_whole(command, &parsed) {
+        return None;
+    }
+    match parsed {
+        ParsedCommand::Sequence {
+            commands,
+            operators,
+        } => Some((commands, operators)),
+        _ => None,
+    }
+}
+
+/// Whether `parsed` has all of `command`: the parser drops what it can't
+/// place, such as a redirect after a subshell, and such commands are left
+/// to the shell
+fn parsed_whole(command: &str, parsed: &ParsedCommand) -> bool {
+    let tokens = |text: &str| {
+        tokenize(text)
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect::<Vec<_>>()
+    };
+    tokens(command) == tokens(&parsed.to_string())
+}
+
+/// Name and arguments of the virtual command to dispatch `command` to
+///
+/// The arguments come from the shell parser with their quoting removed
+/// ([`ParsedArg::unquoted`]), so `echo "a   b"` and `cat "my file.txt"`
+/// see the same arguments a program run by the shell would, and a leading
+/// `~` is the home directory, `HOME` in `env` if it sets one. Only a single
+/// simple command without redirects or other shell syntax is eligible; compound commands (`a && b`, `a | b`) run in a real shell so the
+/// builtin doesn't receive the operators as arguments.
+pub(crate) fn virtual_command(
+    command: &str,
+    env: Option<&HashMap<String, String>>,
+) -> Option<(String, Vec<String>)> {
+    if needs_real_shell(command) {
+        return None;
+    }
+    match parse_shell_command(command).ok()? {
+        ParsedCommand::Simple {
+            assignments,
+            cmd,
+            args,
+            redirects,
+        } if redirects.is_empty() && assignments.is_empty() => {
+            let unquoted = |arg: &ParsedArg| {
+                let word = arg.to_string();
+                match expand::tilde_prefix(&word, env) {
+                    Some((home, len)) => home + &unquote_word(&word[len..]),
+                    None => arg.unquoted(),
+                }
+            };
+            Some((cmd, args.iter().map(unquoted).collect()))
+        }
+        _ => None,
+    }
+}
+
+pub(super) fn has_virtual_command(parsed: &ParsedCommand) -> bool {
+    match parsed {
+        // Globs are expanded and redirects opened when the command runs, in
+        // the directory it runs in
+        ParsedCommand::Simple { cmd, .. } => BUILTIN_COMMANDS.contains(&cmd.as_str()),
+        ParsedCommand::Sequence { commands, .. } | ParsedCommand::Pipeline { commands } => {
+            commands.iter().any(has_virtual_command)
+        }
+        ParsedCommand::Subshell { command }
+        | ParsedCommand::Background { command }
+        | ParsedCommand::Not { command }
+        | ParsedCommand::Time { command, .. } => has_virtual_command(command),
+    }
+}
+
+/// Whether `parsed` starts a background job, which goes in the job table,
+/// or times a command, which `sh` has no keyword for, rather than being
+/// left to the shell
+fn has_background(parsed: &ParsedCommand) -> bool {
+    match parsed {
+        ParsedCommand::Simple { .. } => false,
+        ParsedCommand::Sequence { commands, .. } | ParsedCommand::Pipeline { commands } => {
+            commands.iter().any(has_background)
+        }
+        ParsedCommand::Subshell { command } | ParsedCommand::Not { command } => {
+            has_background(command)
+        }
+        ParsedCommand::Background { .. } | ParsedCommand::Time { .. } => true,
+    }
+}
+
+/// Whether a command in `parsed` sets variables or other state of the shell
+/// it runs in, which the commands after it would need to see
+fn sets_shell_state(parsed: &ParsedCommand) -> bool {
+    const STATEFUL: &[&str] = &[
+        ".", "declare", "eval", "export", "local", "read", "readonly", "shift", "source", "trap",
+        "typeset", "umask", "unset",
+    ];
+    match parsed {
+        ParsedCommand::Simple { cmd, .. } => STATEFUL.contains(&cmd.as_str()) || is_assignment(cmd),
+        ParsedCommand::Sequence { commands, .. } | ParsedCommand::Pipeline { commands } => {
+            commands.iter().any(sets_shell_state)
+        }
+        ParsedCommand::Subshell { command }
+        | ParsedCommand::Not { command }
+        | ParsedCommand::Time { command, .. } => sets_shell_state(command),
+        // A background job runs in a subshell of its own
+        ParsedCommand::Background { .. } => false,
+    }
+}
+
+/// Whether `parsed` holds shell keywords, which the parser reads as
+/// commands named `if`, `then`, `do` and so on, or a function definition:
+/// its commands only work together, in the shell
+fn has_compound_syntax(parsed: &ParsedCommand) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "if", "then", "elif", "else", "fi", "for", "while", "until", "do", "done", "case", "esac",
+        "select", "function", "{", "}", "[[", "]]",
+    ];
+    match parsed {
+        ParsedCommand::Simple { cmd, args, .. } => {
+            KEYWORDS.contains(&cmd.as_str())
+                || cmd.ends_with("()")
+                || args
+                    .first()
+                    .is_some_and(|arg| arg.to_string().starts_with("()"))
+        }
+        ParsedCommand::Sequence { commands, .. } | ParsedCommand::Pipeline { commands } => {
+            commands.iter().any(has_compound_syntax)
+        }
+        ParsedCommand::Subshell { command }
+        | ParsedCommand::Background { command }
+        | ParsedCommand::Not { command }
+        | ParsedCommand::Time { command, .. } => has_compound_syntax(command),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_only_takes_compound_commands_with_builtins() {
+        let plan = |command| plan(command, false);
+        assert!(plan("mkdir -p x && cd x && pwd").is_some());
+        assert!(plan("(cd /tmp; pwd) | wc -l").is_some());
+        assert!(plan("echo hi").is_none());
+        assert!(plan("uname && whoami").is_none());
+        assert!(plan("(echo a) > out.txt").is_none());
+        assert!(plan("echo $(pwd) && true").is_some());
+        assert!(plan("echo `pwd` && true").is_none());
+        assert!(plan("NAME=x; echo $NAME").is_none());
+        assert!(plan("export NAME=x && echo $NAME").is_none());
+    }
+
+    #[test]
+    fn test_plan_takes_lists_whose_steps_are_reported() {
+        assert!(plan("uname && whoami", true).is_some());
+        assert!(plan("uname | wc -l", true).is_none());
+        assert!(plan("NAME=x; echo $NAME", true).is_none());
+    }
+
+    #[test]
+    fn test_plan_leaves_compound_syntax_to_the_shell() {
+        for steps in [false, true] {
+            let plan = |command| plan(command, steps);
+            assert!(plan("echo start; if true; then echo y; fi").is_none());
+            assert!(plan("for i in 1 2; do echo $i; done; echo end").is_none());
+            assert!(plan("while false; do echo x; done; echo end").is_none());
+            assert!(plan("until true; do echo x; done && echo end").is_none());
+            assert!(plan("echo a; { echo b; }").is_none());
+            assert!(plan("f() { echo hi; }; f; echo end").is_none());
+            assert!(plan("function f { echo hi; }; f; echo end").is_none());
+            assert!(plan("case x in x) echo y;; esac; echo end").is_none());
+        }
+    }
+}
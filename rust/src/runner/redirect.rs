@@ -0,0 +1,275 @@
+//! Applying a command's redirects in-process, the way a shell connects a
+//! program's descriptors to files: for virtual commands by routing what
+//! they write, for programs run directly by handing them the files
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use crate::commands::SpecialFile;
+use crate::expand::tilde_prefix;
+use crate::shell_parser::{literal_argv_in, literal_word, ParsedCommand, Redirect, TokenType};
+use crate::StreamKind;
+
+/// Where output written to a descriptor ends up
+#[derive(Debug, Clone)]
+enum Target {
+    Stream(StreamKind),
+    Null,
+    File(Arc<File>),
+}
+
+/// Where input is read from, when a redirect replaces stdin
+#[derive(Debug)]
+enum Input {
+    Null,
+    File(File),
+}
+
+/// Where a command's stdin comes from and its stdout and stderr go after
+/// its redirects
+#[derive(Debug)]
+pub(super) struct Routes {
+    stdin: Option<Input>,
+    stdout: Target,
+    stderr: Target,
+}
+
+/// How a program run directly is connected after its redirects; `None`
+/// leaves a stream as the runner sets it up
+pub(super) struct ProcessStdio {
+    pub stdin: Option<Stdio>,
+    pub stdout: Option<Stdio>,
+    pub stderr: Option<Stdio>,
+}
+
+impl Routes {
+    /// Apply `redirects` left to right, as the shell does, so `2>&1 > file`
+    /// and `> file 2>&1` differ; files are opened relative to `cwd`
+    ///
+    /// Fails, naming the target, when a file can't be opened.
+    pub(super) fn open(redirects: &[Redirect], cwd: Option<&Path>) -> Result<Routes, String> {
+        let mut routes = Routes {
+            stdin: None,
+            stdout: Target::Stream(StreamKind::Stdout),
+            stderr: Target::Stream(StreamKind::Stderr),
+        };
+        for redirect in redirects {
+            let open = |routes: &Routes, append: bool| {
+                routes
+                    .target(&redirect.target, append, cwd)
+                    .map_err(|e| format!("{}: {}", redirect.target, e))
+            };
+            match redirect.redirect_type {
+                TokenType::RedirectOut => routes.stdout = open(&routes, false)?,
+                TokenType::RedirectAppend => routes.stdout = open(&routes, true)?,
+                TokenType::RedirectErr => routes.stderr = open(&routes, false)?,
+                TokenType::RedirectErrAppend => routes.stderr = open(&routes, true)?,
+                TokenType::RedirectBoth | TokenType::RedirectBothAppend => {
+                    let append = redirect.redirect_type == TokenType::RedirectBothAppend;
+                    let target = open(&routes, append)?;
+                    routes.stdout = target.clone();
+                    routes.stderr = target;
+                }
+                TokenType::RedirectErrToOut => routes.stderr = routes.stdout.clone(),
+                TokenType::RedirectOutToErr => routes.stdout = routes.stderr.clone(),
+                TokenType::RedirectIn => {
+                    let input = Routes::input(&redirect.target, cwd)
+                        .map_err(|e| format!("{}: {}", redirect.target, e))?;
+                    if input.is_some() {
+                        routes.stdin = input;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(routes)
+    }
+
+    /// The input a redirect from `path` connects stdin to; `None` for
+    /// stdin itself
+    fn input(path: &str, cwd: Option<&Path>) -> io::Result<Option<Input>> {
+        match SpecialFile::parse(path) {
+            Some(SpecialFile::Null) => Ok(Some(Input::Null)),
+            Some(SpecialFile::Stdin) => Ok(None),
+            _ => {
+                let path = match cwd {
+                    Some(cwd) => cwd.join(path),
+                    None => Path::new(path).to_path_buf(),
+                };
+                Ok(Some(Input::File(File::open(path)?)))
+            }
+        }
+    }
+
+    /// The target a redirect to `path` connects a descriptor to
+    fn target(&self, path: &str, append: bool, cwd: Option<&Path>) -> io::Result<Target> {
+        match SpecialFile::parse(path) {
+            Some(SpecialFile::Null) => return Ok(Target::Null),
+            Some(SpecialFile::Stdout) => return Ok(self.stdout.clone()),
+            Some(SpecialFile::Stderr) => return Ok(self.stderr.clone()),
+            Some(SpecialFile::Stdin) | None => {}
+        }
+        let path = match cwd {
+            Some(cwd) => cwd.join(path),
+            None => Path::new(path).to_path_buf(),
+        };
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        Ok(Target::File(Arc::new(file)))
+    }
+
+    /// Deliver `text`, which the command wrote to `stream`, where that
+    /// stream is routed
+    ///
+    /// Returns the stream the text is now on, or `None` when it went to a
+    /// file or was discarded. Like a write to a closed pipe, a failed write
+    /// to a file loses the text.
+    pub(super) fn route(&self, stream: StreamKind, text: &str) -> Option<StreamKind> {
+        let target = match stream {
+            StreamKind::Stdout => &self.stdout,
+            StreamKind::Stderr => &self.stderr,
+        };
+        match target {
+            Target::Stream(stream) => Some(*stream),
+            Target::Null => None,
+            Target::File(file) => {
+                let _ = (&**file).write_all(text.as_bytes());
+                None
+            }
+        }
+    }
+
+    /// The content of the file stdin is redirected from, for a virtual
+    /// command; `None` when stdin isn't redirected
+    pub(super) fn stdin_text(&mut self) -> Option<io::Result<String>> {
+        let text = match self.stdin.take()? {
+            Input::Null => Ok(String::new()),
+            Input::File(mut file) => {
+                let mut bytes = Vec::new();
+                io::Read::read_to_end(&mut file, &mut bytes)
+                    .map(|_| String::from_utf8_lossy(&bytes).into_owned())
+            }
+        };
+        Some(text)
+    }
+
+    /// The redirected streams of a program run directly, or `None` when a
+    /// redirect joins stdout and stderr while they are still captured
+    /// separately, which only a shell can set up
+    pub(super) fn stdio(self) -> Option<ProcessStdio> {
+        let output = |target: Target, stream: StreamKind| match target {
+            Target::Stream(to) if to == stream => Some(None),
+            Target::Stream(_) => None,
+            Target::Null => Some(Some(Stdio::null())),
+            Target::File(file) => file.try_clone().ok().map(|file| Some(Stdio::from(file))),
+        };
+        Some(ProcessStdio {
+            stdin: self.stdin.map(|input| match input {
+                Input::Null => Stdio::null(),
+                Input::File(file) => Stdio::from(file),
+            }),
+            stdout: output(self.stdout, StreamKind::Stdout)?,
+            stderr: output(self.stderr, StreamKind::Stderr)?,
+        })
+    }
+}
+
+/// A command to run without a shell: its argv, redirects, and the variables
+/// set for it
+pub(super) type LiteralCommand = (Vec<String>, Vec<Redirect>, Vec<(String, String)>);
+
+/// The argv, redirects and variables assigned in front of `parsed`, if it
+/// is a simple command whose words, redirect targets and assigned values
+/// are all literal, so it can run without a shell
+///
+/// A leading `~` is expanded with the home directory in `env`, if it sets
+/// one.
+pub(super) fn literal_command(
+    parsed: &ParsedCommand,
+    env: Option<&HashMap<String, String>>,
+) -> Option<LiteralCommand> {
+    let ParsedCommand::Simple {
+        assignments,
+        cmd,
+        args,
+        redirects,
+    } = parsed
+    else {
+        return None;
+    };
+    let literal = |target: &str| {
+        !target.is_empty() && !target.contains(['\'', '"', '\\', '$', '`', '*', '?', '['])
+    };
+    if !redirects
+        .iter()
+        .all(|r| r.redirect_type.is_duplication() || literal(&r.target))
+    {
+        return None;
+    }
+    let assigned = assignments
+        .iter()
+        .map(|assignment| {
+            Some((
+                assignment.name.clone(),
+                literal_word(&assignment.value, env)?,
+            ))
+        })
+        .collect::<Option<_>>()?;
+    let argv = literal_argv_in(
+        &ParsedCommand::Simple {
+            assignments: Vec::new(),
+            cmd: cmd.clone(),
+            args: args.clone(),
+            redirects: Vec::new(),
+        },
+        env,
+    )?;
+    let redirects = redirects
+        .iter()
+        .map(|redirect| Redirect {
+            target: match tilde_prefix(&redirect.target, env) {
+                Some((home, len)) => home + &redirect.target[len..],
+                None => redirect.target.clone(),
+            },
+            ..redirect.clone()
+        })
+        .collect();
+    Some((argv, redirects, assigned))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_shell_command;
+
+    fn routes(command: &str, cwd: &Path) -> Routes {
+        let Ok(ParsedCommand::Simple { redirects, .. }) = parse_shell_command(command) else {
+            panic!("not a simple command: {}", command);
+        };
+        Routes::open(&redirects, Some(cwd)).unwrap()
+    }
+
+    #[test]
+    fn test_redirects_apply_left_to_right() {
+        let dir = tempfile::tempdir().unwrap();
+        let both = routes("cmd > out.txt 2>&1", dir.path());
+        assert_eq!(both.route(StreamKind::Stderr, "err\n"), None);
+        let swapped = routes("cmd 2>&1 > out.txt", dir.path());
+        assert_eq!(
+            swapped.route(StreamKind::Stderr, "shown\n"),
+            Some(StreamKind::Stdout)
+        );
+        assert_eq!(swapped.route(StreamKind::Stdout, "kept\n"), None);
+        let written = std::fs::read_to_string(dir.path().join("out.txt")).unwrap();
+        assert_eq!(written, "kept\n");
+        assert!(swapped.stdio().is_none());
+    }
+}
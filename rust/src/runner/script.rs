@@ -0,0 +1,115 @@
+//! The script a command runs as when a shell runs it: the command, after a
+//! prelude applying the shell settings, shell variables and `$?`, with the
+//! positional parameters passed after it
+
+use crate::expand::Parameters;
+use crate::quote::quote;
+use crate::shell::Shell;
+use crate::shell_parser::is_assignment;
+use crate::state::ShellSettings;
+
+/// The argv that runs `command` with `shell`, the settings and parameters
+/// applied
+///
+/// A POSIX shell takes `$0` and the positional parameters after the script,
+/// as in `sh -c 'echo "$1"' name value`.
+pub(super) fn shell_argv(
+    shell: &Shell,
+    command: &str,
+    settings: &ShellSettings,
+    parameters: &Parameters,
+) -> Vec<String> {
+    let mut argv = shell.argv(&shell_script(command, settings, parameters));
+    if shell.kind.is_posix() {
+        argv.push(parameters.name.clone());
+        argv.extend(parameters.args.iter().cloned());
+    }
+    argv
+}
+
+/// Prefix the command with `set` calls for the shell options that the shell
+/// itself must apply (`set -e`/`-u`/`-f`, `pipefail` and `globstar`), so multi-command
+/// strings behave like a script run with those options.
+///
+/// The shell variables of `parameters` are assigned after them, without
+/// being exported, and a non-zero status is set last, as `$?` of the
+/// command before.
+fn shell_script(command: &str, settings: &ShellSettings, parameters: &Parameters) -> String {
+    if cfg!(windows) {
+        return command.to_string();
+    }
+
+    let mut flags = String::new();
+    if settings.errexit {
+        flags.push('e');
+    }
+    if settings.nounset {
+        flags.push('u');
+    }
+    if settings.noglob {
+        flags.push('f');
+    }
+
+    let mut prelude = String::new();
+    if !flags.is_empty() {
+        prelude.push_str(&format!("set -{}; ", flags));
+    }
+    if settings.pipefail {
+        // Not every sh supports pipefail (e.g. older dash); probe in a subshell
+        // so an unsupported option doesn't abort the script.
+        prelude.push_str("(set -o pipefail) 2>/dev/null && set -o pipefail; ");
+    }
+    if settings.globstar {
+        prelude.push_str("(shopt -s globstar) 2>/dev/null && shopt -s globstar; ");
+    }
+    for (name, value) in &parameters.variables {
+        if is_assignment(&format!("{}=", name)) {
+            prelude.push_str(&format!("{}={}; ", name, quote(value)));
+        }
+    }
+    // The shell only knows statuses that fit in a byte
+    let status = parameters.status.rem_euclid(256);
+    if status != 0 {
+        // Failing on the left of `&&` doesn't trip `set -e`
+        prelude.push_str(&format!("(exit {}) && :; ", status));
+    }
+    prelude + command
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_argv_passes_status_and_positional_parameters() {
+        let parameters = Parameters {
+            status: 3,
+            ..Parameters::new("deploy.sh", ["staging"])
+        };
+        let argv = shell_argv(
+            &Shell::new("sh"),
+            "echo $?",
+            &ShellSettings::new(),
+            &parameters,
+        );
+        assert_eq!(
+            argv,
+            ["sh", "-c", "(exit 3) && :; echo $?", "deploy.sh", "staging"]
+        );
+    }
+
+    #[test]
+    fn test_shell_argv_sets_shell_variables() {
+        let mut parameters = Parameters::default();
+        parameters
+            .variables
+            .insert("NAME".to_string(), "it's".to_string());
+        let argv = shell_argv(
+            &Shell::new("sh"),
+            "echo $NAME",
+            &ShellSettings::new(),
+            &parameters,
+        );
+        assert_eq!(argv[2], "NAME='it'\\''s'; echo $NAME");
+    }
+}
@@ -0,0 +1,163 @@
+//! Running a virtual command in the runner: its redirects and stdin, and
+//! its output streamed through the runner's filters, sinks and emitter as
+//! it arrives
+
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+use super::redirect::Routes;
+use super::ProcessRunner;
+use crate::commands::{self, CommandExecutor};
+use crate::sink::LineSplitter;
+use crate::tail::TailBuffer;
+use crate::{
+    console, expand, filter, CommandContext, CommandResult, EventType, StdinOption, StreamChunk,
+    StreamKind,
+};
+
+impl ProcessRunner {
+    /// Try to execute as a virtual command
+    ///
+    /// Output the builtin streams through its context is collected (and
+    /// mirrored as it arrives) ahead of the output it returns.
+    pub(super) async fn try_virtual_command(
+        &self,
+        call: expand::VirtualCall,
+        stdin_file: &mut Option<std::fs::File>,
+    ) -> Option<CommandResult> {
+        let cmd_name = call.name.as_str();
+        if !commands::are_virtual_commands_enabled()
+            || !commands::BUILTIN_COMMANDS.contains(&cmd_name)
+        {
+            return None;
+        }
+        let mut routes = match Routes::open(&call.redirects, self.options.cwd.as_deref()) {
+            Ok(routes) => routes,
+            Err(message) => return Some(CommandResult::error(format!("{}\n", message))),
+        };
+
+        let stdin = match (routes.stdin_text(), &self.options.stdin, stdin_file.take()) {
+            (Some(Ok(text)), _, _) => Some(text),
+            (Some(Err(e)), _, _) => {
+                return Some(CommandResult::error(format!("{}: {}\n", cmd_name, e)));
+            }
+            (None, StdinOption::Content(s), _) => Some(s.clone()),
+            (None, _, Some(file)) => {
+                let mut bytes = Vec::new();
+                let mut file = tokio::fs::File::from_std(file);
+                if let Err(e) = tokio::io::AsyncReadExt::read_to_end(&mut file, &mut bytes).await {
+                    return Some(CommandResult::error(format!("{}: {}\n", cmd_name, e)));
+                }
+                Some(String::from_utf8_lossy(&bytes).into_owned())
+            }
+            _ => None,
+        };
+
+        let (tx, mut rx) = mpsc::channel(1024);
+        let options = call.options(&self.options);
+        let ctx = CommandContext {
+            args: call.args,
+            stdin,
+            cwd: self.options.cwd.clone(),
+            env: options.env.clone(),
+            output_tx: Some(tx),
+            is_cancelled: None,
+            cancel_token: Some(self.cancel.clone()),
+            shell_settings: Some(self.settings_handle()),
+            executor: Some(
+                CommandExecutor::new(options).with_session_settings(Some(self.settings_handle())),
+            ),
+        };
+
+        let mirror = self.options.mirror;
+        let emitter = self.emitter.clone();
+        let started = Instant::now();
+        let mut timed = Vec::new();
+        let (mut stdout_lines, mut stderr_lines) =
+            (LineSplitter::default(), LineSplitter::default());
+        let streamed = async {
+            let limit = self.options.capture_tail;
+            let (mut stdout, mut stderr) = (TailBuffer::new(limit), TailBuffer::new(limit));
+            while let Some(chunk) = rx.recv().await {
+                let (stream, text) = match chunk {
+                    StreamChunk::Stdout(text) => (StreamKind::Stdout, text),
+                    StreamChunk::Stderr(text) => (StreamKind::Stderr, text),
+                };
+                let Some(stream) = routes.route(stream, &text) else {
+                    continue;
+                };
+                let (collected, splitter) = match stream {
+                    StreamKind::Stdout => (&mut stdout, &mut stdout_lines),
+                    StreamKind::Stderr => (&mut stderr, &mut stderr_lines),
+                };
+                let text = filter::apply_text(&self.options.output_filters, stream, text);
+                mirror_text(mirror, stream == StreamKind::Stderr, &text);
+                if let Some(emitter) = &emitter {
+                    let event = match stream {
+                        StreamKind::Stdout => EventType::Stdout,
+                        StreamKind::Stderr => EventType::Stderr,
+                    };
+                    emitter.emit_output(event, text.as_str()).await;
+                }
+                for line in splitter.push(&text) {
+                    self.forward_line(stream, &line, started, &mut timed).await;
+                }
+                collected.push_str(&text);
+            }
+            (stdout, stderr)
+        };
+
+        let (result, (mut stdout, mut stderr)) =
+            tokio::join!(commands::execute_builtin(cmd_name, ctx), streamed);
+        let mut result = result?;
+        let filters = &self.options.output_filters;
+        result.stdout = filter::apply_text(filters, StreamKind::Stdout, result.stdout);
+        result.stderr = filter::apply_text(filters, StreamKind::Stderr, result.stderr);
+        for (stream, splitter, text) in [
+            (StreamKind::Stdout, &mut stdout_lines, &result.stdout),
+            (StreamKind::Stderr, &mut stderr_lines, &result.stderr),
+        ] {
+            for line in splitter.push(text).into_iter().chain(splitter.finish()) {
+                self.forward_line(stream, &line, started, &mut timed).await;
+            }
+        }
+        result.timed_lines = timed;
+        mirror_text(mirror, false, &result.stdout);
+        mirror_text(mirror, true, &result.stderr);
+        if let Some(emitter) = &self.emitter {
+            emitter
+                .emit_output(EventType::Stdout, result.stdout.as_str())
+                .await;
+            emitter
+                .emit_output(EventType::Stderr, result.stderr.as_str())
+                .await;
+        }
+        stdout.push_str(&result.stdout);
+        stderr.push_str(&result.stderr);
+        result.stdout = stdout.into_string();
+        result.stderr = stderr.into_string();
+        Some(result)
+    }
+}
+
+/// Write virtual command output to this process's stdout or stderr when
+/// mirroring is on
+pub(super) fn mirror_text(mirror: bool, to_stderr: bool, text: &str) {
+    use std::io::Write;
+
+    if !mirror || text.is_empty() {
+        return;
+    }
+    let text = console::for_terminal(text);
+    let _ = if to_stderr {
+        let mut stderr = std::io::stderr().lock();
+        stderr
+            .write_all(text.as_bytes())
+            .and_then(|_| stderr.flush())
+    } else {
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(text.as_bytes())
+            .and_then(|_| stdout.flush())
+    };
+}
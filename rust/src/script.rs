@@ -0,0 +1,528 @@
+//! Running multi-line scripts
+//!
+//! [`run_script`] splits a script into statements and runs them one after
+//! another in a [`Session`], so the session's state, such as `set -e`,
+//! carries over from one to the next as in a shell script. A statement ends at a newline
+//! unless it is still open: inside quotes or parentheses, after a trailing
+//! `\`, `&&`, `||` or `|`, or inside an `if`/`fi`, `for`/`done`,
+//! `while`/`done`, `case`/`esac` or `{`/`}` block. Comments are dropped,
+//! and with them a `#!` line at the top. A here-document's body, through
+//! its delimiter line, belongs to the statement that starts it.
+//!
+//! `exit` ends the script with the code it is given, or that of the last
+//! command, and `source file` (or `. file`) runs another script in the
+//! same session, as [`Session::source`] does.
+//!
+//! When a statement fails with an error, which with `set -e` includes a
+//! non-zero exit, the error is an [`Error::Script`] giving the line and a
+//! snippet of the script:
+//!
+//! ```text
+//! deploy.sh:12:1: Command failed with exit code 1: rsync -a dist/ web:/srv
+//!    12 | rsync -a dist/ web:/srv
+//!       | ^^^^^^^^^^^^^^^^^^^^^^^
+//! ```
+//!
+//! A script that can't be split, such as one with an unterminated quote,
+//! fails the same way before any of it runs, pointing at where the quote
+//! opened.
+
+use std::path::Path;
+
+use crate::{CommandResult, Error, Result, Session};
+
+/// A statement of a script, with where it starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Statement {
+    text: String,
+    /// Byte offset in the script
+    offset: usize,
+}
+
+/// Where and why a script couldn't be split into statements
+#[derive(Debug, PartialEq, Eq)]
+struct Syntax {
+    offset: usize,
+    message: String,
+}
+
+/// A here-document whose body is still to come
+#[derive(Debug)]
+struct Heredoc {
+    delimiter: String,
+    /// `<<-`, whose lines may be indented with tabs
+    strip_tabs: bool,
+    /// Where its `<<` is
+    offset: usize,
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::CharIndices<'a>>;
+
+/// What a statement still has open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Open {
+    Paren,
+    /// A compound command, closed by this keyword
+    Block(&'static str),
+}
+
+/// Run the script at `path` in a new session, statement by statement
+///
+/// Returns the output of all statements and the exit code of the last.
+/// A command the script set with `trap ... EXIT` runs at the end, and its
+/// output comes last. Errors carry the line they happened on; see the [module](self) docs.
+pub async fn run_script(path: impl AsRef<Path>) -> Result<CommandResult> {
+    let session = Session::new();
+    let outcome = run_script_in(&session, path).await;
+    // The script's shell exits here, running its `trap ... EXIT`
+    match (outcome, session.run_exit_trap().await) {
+        (Ok(mut total), Some(Ok(result))) => {
+            total.stdout.push_str(&result.stdout);
+            total.stderr.push_str(&result.stderr);
+            Ok(total)
+        }
+        (outcome, _) => outcome,
+    }
+}
+
+/// Run the script at `path` in `session`, like [`run_script`]
+///
+/// The script starts from the session's directory, variables and
+/// positional parameters, and the session keeps what the script changes.
+pub async fn run_script_in(session: &Session, path: impl AsRef<Path>) -> Result<CommandResult> {
+    let path = path.as_ref();
+    let script = std::fs::read_to_string(path)?;
+    run_in(session, &script, &path.display().to_string()).await
+}
+
+/// Run `script`, named `file` in errors, in `session`
+pub(crate) async fn run_in(session: &Session, script: &str, file: &str) -> Result<CommandResult> {
+    let statements = statements(script).map_err(|syntax| {
+        let snippet = snippet(script, syntax.offset, 1);
+        located(
+            script,
+            file,
+            syntax.offset,
+            snippet,
+            Error::ParseError(syntax.message),
+        )
+    })?;
+    let mut total = CommandResult::default();
+    for statement in statements {
+        let outcome = session.run(statement.text.as_str()).await;
+        if session.state().exited {
+            // `exit` ends the script, with its status even under `set -e`
+            if let Ok(result) = outcome {
+                total.stdout.push_str(&result.stdout);
+                total.stderr.push_str(&result.stderr);
+            }
+            total.code = session.last_status();
+            total.signal = None;
+            break;
+        }
+        let result = outcome.map_err(|e| {
+            let width = statement.text.lines().next().unwrap_or("").chars().count();
+            let snippet = snippet(script, statement.offset, width);
+            located(script, file, statement.offset, snippet, e)
+        })?;
+        total.stdout.push_str(&result.stdout);
+        total.stderr.push_str(&result.stderr);
+        total.code = result.code;
+        total.signal = result.signal;
+    }
+    Ok(total)
+}
+
+/// `error` as having happened at `offset` in `script`
+fn located(script: &str, file: &str, offset: usize, snippet: String, error: Error) -> Error {
+    let (line, column) = position(script, offset);
+    Error::Script {
+        file: file.to_string(),
+        line,
+        column,
+        snippet,
+        source: Box::new(error),
+    }
+}
+
+/// Line and column of `offset` in `script`, both from 1
+fn position(script: &str, offset: usize) -> (usize, usize) {
+    let before = &script[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// The line of `script` holding `offset`, numbered, with `width` carets
+/// under the text from `offset` on
+fn snippet(script: &str, offset: usize, width: usize) -> String {
+    let (line, column) = position(script, offset);
+    let text = script.lines().nth(line - 1).unwrap_or("");
+    let number = line.to_string();
+    format!(
+        "{:>w$} | {}\n{:>w$} | {}{}",
+        number,
+        text,
+        "",
+        " ".repeat(column - 1),
+        "^".repeat(width.max(1)),
+        w = number.len() + 2,
+    )
+}
+
+/// Split `script` into the statements it runs as
+fn statements(script: &str) -> std::result::Result<Vec<Statement>, Syntax> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut start = None;
+    let mut quote: Option<(char, usize)> = None;
+    let mut open: Vec<(Open, usize)> = Vec::new();
+    // An operator that continues the statement on the next line
+    let mut pending: Option<(&str, usize)> = None;
+    // Whether the next word is a command name, where keywords count
+    let mut command_position = true;
+    let mut word = String::new();
+    let mut word_start = 0;
+    // Here-documents started on the current line
+    let mut heredocs: Vec<Heredoc> = Vec::new();
+
+    let mut chars = script.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if let Some((q, _)) = quote {
+            current.push(c);
+            if c == q {
+                quote = None;
+            } else if c == '\\' && q != '\'' {
+                if let Some((_, next)) = chars.next() {
+                    current.push(next);
+                }
+            }
+            continue;
+        }
+        let word_char = !c.is_whitespace() && !";&|()<>".contains(c);
+        if !word_char && !word.is_empty() {
+            keyword(&word, word_start, &mut open, &mut command_position)?;
+            word.clear();
+        }
+        if c == '\n' && !heredocs.is_empty() {
+            // Their bodies follow the line the here-documents start on
+            current.push(c);
+            for heredoc in heredocs.drain(..) {
+                if !heredoc_body(&mut chars, &mut current, &heredoc) {
+                    let message = format!("here-document `{}` never ends", heredoc.delimiter);
+                    return Err(syntax(heredoc.offset, &message));
+                }
+            }
+            command_position = true;
+            if open.is_empty() && pending.take().is_none() {
+                if let Some(offset) = start.take() {
+                    statements.push(Statement {
+                        text: current.trim().to_string(),
+                        offset,
+                    });
+                }
+                current.clear();
+            }
+            continue;
+        }
+        if c == '\n' && open.is_empty() && pending.is_none() {
+            if let Some(offset) = start.take() {
+                statements.push(Statement {
+                    text: current.trim().to_string(),
+                    offset,
+                });
+            }
+            current.clear();
+            command_position = true;
+            continue;
+        }
+        if c.is_whitespace() {
+            if c == '\n' {
+                command_position = true;
+                if pending.take().is_some() {
+                    current.push(' ');
+                    continue;
+                }
+            }
+            current.push(c);
+            continue;
+        }
+        if c == '#' && word.is_empty() {
+            // A comment, up to the end of the line
+            while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+            continue;
+        }
+        if c == '\\' && chars.next_if(|(_, c)| *c == '\n').is_some() {
+            continue;
+        }
+        start.get_or_insert(i);
+        pending = None;
+        current.push(c);
+        match c {
+            '\'' | '"' | '`' => quote = Some((c, i)),
+            '\\' => {
+                if let Some((_, next)) = chars.next() {
+                    current.push(next);
+                }
+            }
+            '(' => {
+                open.push((Open::Paren, i));
+                command_position = true;
+            }
+            ')' => {
+                match open.last() {
+                    Some((Open::Paren, _)) => {
+                        open.pop();
+                    }
+                    // A pattern of a `case`
+                    Some((Open::Block("esac"), _)) => {}
+                    _ => return Err(syntax(i, "unexpected `)`")),
+                }
+                command_position = true;
+            }
+            ';' => command_position = true,
+            '&' | '|' => {
+                let doubled = chars.next_if(|(_, next)| *next == c).is_some();
+                if doubled {
+                    current.push(c);
+                }
+                let redirect = current[..current.len() - 1].ends_with(['>', '<'])
+                    || chars.peek().is_some_and(|(_, next)| *next == '>');
+                if !redirect {
+                    command_position = true;
+                    pending = match (c, doubled) {
+                        ('&', false) => None,
+                        ('&', true) => Some(("&&", i)),
+                        ('|', false) => Some(("|", i)),
+                        _ => Some(("||", i)),
+                    };
+                }
+            }
+            '<' if chars.next_if(|(_, next)| *next == '<').is_some() => {
+                current.push('<');
+                if chars.next_if(|(_, next)| *next == '<').is_some() {
+                    // A here-string
+                    current.push('<');
+                } else {
+                    heredocs.push(heredoc(&mut chars, &mut current, i)?);
+                }
+            }
+            '<' | '>' => {}
+            _ => {
+                if word.is_empty() {
+                    word_start = i;
+                }
+                word.push(c);
+            }
+        }
+    }
+    if !word.is_empty() {
+        keyword(&word, word_start, &mut open, &mut command_position)?;
+    }
+
+    if let Some((q, offset)) = quote {
+        return Err(syntax(offset, &format!("unterminated `{}` quote", q)));
+    }
+    if let Some(heredoc) = heredocs.first() {
+        let message = format!("here-document `{}` never ends", heredoc.delimiter);
+        return Err(syntax(heredoc.offset, &message));
+    }
+    if let Some(&(opened, offset)) = open.last() {
+        return Err(syntax(
+            offset,
+            &match opened {
+                Open::Paren => "unclosed `(`".to_string(),
+                Open::Block(end) => format!("missing `{}`", end),
+            },
+        ));
+    }
+    if let Some((operator, offset)) = pending {
+        return Err(syntax(
+            offset,
+            &format!("nothing after `{}` at the end of the script", operator),
+        ));
+    }
+    if let Some(offset) = start {
+        statements.push(Statement {
+            text: current.trim().to_string(),
+            offset,
+        });
+    }
+    Ok(statements)
+}
+
+/// The here-document whose `<<` is at `offset`: its delimiter, read from
+/// `chars` and copied to `current`, without quotes
+fn heredoc(
+    chars: &mut Chars<'_>,
+    current: &mut String,
+    offset: usize,
+) -> std::result::Result<Heredoc, Syntax> {
+    let strip_tabs = chars.next_if(|(_, c)| *c == '-').is_some();
+    if strip_tabs {
+        current.push('-');
+    }
+    while let Some((_, c)) = chars.next_if(|(_, c)| *c == ' ' || *c == '\t') {
+        current.push(c);
+    }
+    let mut delimiter = String::new();
+    let mut quote = None;
+    while let Some((_, c)) =
+        chars.next_if(|(_, c)| quote.is_some() || !(c.is_whitespace() || ";&|()<>".contains(*c)))
+    {
+        current.push(c);
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '\\') => {
+                if let Some((_, next)) = chars.next() {
+                    current.push(next);
+                    delimiter.push(next);
+                }
+            }
+            _ => delimiter.push(c),
+        }
+    }
+    if delimiter.is_empty() {
+        return Err(syntax(offset, "missing here-document delimiter"));
+    }
+    Ok(Heredoc {
+        delimiter,
+        strip_tabs,
+        offset,
+    })
+}
+
+/// Copy the lines of `heredoc`'s body from `chars` to `current`, through
+/// the line holding its delimiter; false if the script ends first
+fn heredoc_body(chars: &mut Chars<'_>, current: &mut String, heredoc: &Heredoc) -> bool {
+    while chars.peek().is_some() {
+        let mut line = String::new();
+        while let Some((_, c)) = chars.next_if(|(_, c)| *c != '\n') {
+            line.push(c);
+        }
+        current.push_str(&line);
+        if chars.next().is_some() {
+            current.push('\n');
+        }
+        let text = match heredoc.strip_tabs {
+            true => line.trim_start_matches('\t'),
+            false => &line,
+        };
+        if text == heredoc.delimiter {
+            return true;
+        }
+    }
+    false
+}
+
+/// Track the compound command `word` opens or closes, if it is a keyword
+/// where a command name goes
+fn keyword(
+    word: &str,
+    offset: usize,
+    open: &mut Vec<(Open, usize)>,
+    command_position: &mut bool,
+) -> std::result::Result<(), Syntax> {
+    // `{` opens a group wherever it stands alone, as after `f()`
+    if word == "{" {
+        open.push((Open::Block("}"), offset));
+        *command_position = true;
+        return Ok(());
+    }
+    if !*command_position {
+        return Ok(());
+    }
+    match word {
+        "if" => open.push((Open::Block("fi"), offset)),
+        "for" | "while" | "until" | "select" => open.push((Open::Block("done"), offset)),
+        "case" => open.push((Open::Block("esac"), offset)),
+        "fi" | "done" | "esac" | "}" => match open.last() {
+            Some((Open::Block(end), _)) if *end == word => {
+                open.pop();
+            }
+            _ => return Err(syntax(offset, &format!("unexpected `{}`", word))),
+        },
+        "then" | "do" | "else" | "elif" | "!" => return Ok(()),
+        _ => {}
+    }
+    *command_position = matches!(word, "if" | "while" | "until");
+    Ok(())
+}
+
+fn syntax(offset: usize, message: &str) -> Syntax {
+    Syntax {
+        offset,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(script: &str) -> Vec<String> {
+        statements(script)
+            .unwrap()
+            .into_iter()
+            .map(|statement| statement.text)
+            .collect()
+    }
+
+    #[test]
+    fn test_statements_span_what_is_still_open() {
+        let script = "#!/bin/sh\n\
+            echo 'a\nb'   # comment\n\
+            \n\
+            make build &&\n  make test\n\
+            cp a \\\n  b\n\
+            if true; then\n  echo yes\nfi\n\
+            deploy() {\n  echo go\n}\n\
+            case $x in\n  a) echo a ;;\nesac\n\
+            echo done";
+        assert_eq!(
+            texts(script),
+            [
+                "echo 'a\nb'",
+                "make build &&   make test",
+                "cp a   b",
+                "if true; then\n  echo yes\nfi",
+                "deploy() {\n  echo go\n}",
+                "case $x in\n  a) echo a ;;\nesac",
+                "echo done",
+            ]
+        );
+        assert_eq!(
+            texts("cat <<EOF > out\nif 'x\nEOF\ncat <<-'END' && cat <<<hi\n\tfi\n\tEND\necho"),
+            [
+                "cat <<EOF > out\nif 'x\nEOF",
+                "cat <<-'END' && cat <<<hi\n\tfi\n\tEND",
+                "echo",
+            ]
+        );
+        assert_eq!(
+            texts("ls 2>&1 | wc -l\nls &\necho"),
+            ["ls 2>&1 | wc -l", "ls &", "echo"]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors_point_where_they_start() {
+        let error = |script: &str| statements(script).unwrap_err();
+        assert_eq!(error("echo ok\necho 'oops\necho x").offset, 13);
+        assert_eq!(error("if true; then\n  echo\n").message, "missing `fi`");
+        assert_eq!(error("echo a\nfi").message, "unexpected `fi`");
+        assert_eq!(error("make &&").offset, 5);
+        assert_eq!(
+            error("echo\ncat <<EOF\nnever closed\n").message,
+            "here-document `EOF` never ends"
+        );
+        assert_eq!(error("cat << ;").offset, 4);
+        assert_eq!(
+            snippet("echo ok\necho 'oops\n", 13, 1),
+            "  2 | echo 'oops\n    |      ^"
+        );
+    }
+}
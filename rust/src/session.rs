@@ -0,0 +1,749 @@
+//! Sessions: shell state that commands run in
+//!
+//! A [`Session`] owns what a shell would keep between commands: the working
+//! directory, variables and which of them are exported, aliases, functions,
+//! shell settings and its own virtual commands. [`Session::run`] runs each
+//! command in that context, so two sessions in one program never see each
+//! other's state, nor the global settings.
+//!
+//! ```rust,no_run
+//! use command_stream::Session;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let session = Session::new();
+//! session.set_cwd("/srv/app")?;
+//! session.export("NODE_ENV", "production");
+//! session.alias("ll", "ls -la");
+//! session.define_function("deploy", "npm ci && npm run build");
+//!
+//! session.run("set -e").await?;
+//! session.run("ll").await?;
+//! session.run("deploy").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Commands change the session as they would a shell: `cd /tmp` followed by
+//! `pwd` prints `/tmp`, `NAME=value` sets a variable later commands can
+//! expand, `greet() { ...; }` defines a function, and `export` and `unset`
+//! change the variables commands get. Only the session changes, never this process's directory
+//! or environment, so sessions are safe to use from concurrent tasks.
+//!
+//! [`Session::save`] writes the state to a file and [`Session::load`] reads
+//! it back, so an automation agent or REPL can resume where it left off.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::sync::RwLock as AsyncRwLock;
+
+use crate::aliases;
+use crate::commands::{change_dir, CommandExecutor, VirtualCommandHandler, VirtualCommandRegistry};
+use crate::console;
+use crate::expand::{self, VirtualCall};
+use crate::hooks::{ExecHooks, ExecSpec};
+use crate::quote::quote;
+use crate::runner::SessionEffects;
+use crate::script;
+use crate::shell_parser::is_assignment;
+use crate::{
+    parse_shell_command, virtual_command, CancellationToken, CommandContext, CommandResult, Error,
+    ExitKind, ParsedCommand, ProcessRunner, Result, RunOptions, ShellSettings,
+};
+
+mod persist;
+mod steps;
+mod traps;
+
+/// What a session remembers between commands
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SessionState {
+    pub(crate) cwd: PathBuf,
+    pub(crate) variables: HashMap<String, String>,
+    /// Names of the variables passed to commands as environment variables
+    pub(crate) exported: HashSet<String>,
+    pub(crate) aliases: HashMap<String, String>,
+    /// Function bodies, in shell syntax, by name
+    pub(crate) functions: HashMap<String, String>,
+    /// Commands run, oldest first
+    pub(crate) history: Vec<String>,
+    /// Exit status of the last command run, `$?` for the next one
+    pub(crate) last_status: i32,
+    /// Whether the last command ran `exit`, which ends the script it is in
+    pub(crate) exited: bool,
+    /// Commands `trap` set, by condition: `EXIT`, `INT` or `TERM`
+    pub(crate) traps: BTreeMap<&'static str, String>,
+    /// Whether the `INT` trap ran for the cancellation of the session's
+    /// token
+    pub(crate) cancel_trapped: bool,
+}
+
+/// Builtins that change the session's own state rather than the process's
+const SESSION_BUILTINS: &[&str] = &[
+    ".", "alias", "cd", "export", "source", "trap", "unalias", "unset",
+];
+
+/// Most commands a session's history keeps
+const MAX_HISTORY: usize = 1000;
+
+/// Shell state shared by the commands run through it
+///
+/// Methods take `&self`, so a session can be shared between tasks in an
+/// [`Arc`].
+pub struct Session {
+    options: RunOptions,
+    state: RwLock<SessionState>,
+    settings: Arc<AsyncRwLock<ShellSettings>>,
+    registry: RwLock<VirtualCommandRegistry>,
+    hooks: RwLock<ExecHooks>,
+    cleanup: std::sync::Mutex<Vec<CleanupHook>>,
+    /// Cancels the commands running now; see [`Session::signal`]
+    interrupt: std::sync::Mutex<CancellationToken>,
+}
+
+/// A hook registered with [`Session::on_cleanup`]
+type CleanupHook = Box<dyn FnOnce() + Send + 'static>;
+
+impl Default for Session {
+    fn default() -> Self {
+        Session::new()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let pending = !self
+            .cleanup
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+            || self.has_exit_trap();
+        if pending {
+            // Drop can't wait for the EXIT trap, so only close() runs it
+            crate::trace::warn_not_closed(
+                "Session",
+                "session dropped without close(); its EXIT trap doesn't run, its cleanup hooks run now",
+            );
+        }
+        self.run_cleanup_hooks();
+    }
+}
+
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("options", &self.options)
+            .field("state", &*self.state())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Session {
+    /// A session in the current directory, with the defaults of
+    /// [`RunOptions::from_env`] and default shell settings
+    pub fn new() -> Self {
+        Session::with_options(RunOptions::from_env())
+    }
+
+    /// A session whose commands start from `options`
+    ///
+    /// The session takes its directory from `options.cwd`, exports
+    /// `options.env` and starts with `options.shell_settings`; from then on
+    /// it keeps track of them itself.
+    pub fn with_options(mut options: RunOptions) -> Self {
+        let cwd = options
+            .cwd
+            .take()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_else(|| PathBuf::from("/"));
+        let variables = options.env.take().unwrap_or_default();
+        let settings = options.shell_settings.take().unwrap_or_default();
+        Session {
+            state: RwLock::new(SessionState {
+                cwd,
+                exported: variables.keys().cloned().collect(),
+                variables,
+                ..Default::default()
+            }),
+            settings: Arc::new(AsyncRwLock::new(settings)),
+            registry: RwLock::new(VirtualCommandRegistry::new()),
+            hooks: Default::default(),
+            cleanup: Default::default(),
+            interrupt: std::sync::Mutex::new(traps::commands_token(options.cancel.as_ref())),
+            options,
+        }
+    }
+
+    pub(crate) fn state(&self) -> RwLockReadGuard<'_, SessionState> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn state_mut(&self) -> RwLockWriteGuard<'_, SessionState> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The directory commands run in
+    pub fn cwd(&self) -> PathBuf {
+        self.state().cwd.clone()
+    }
+
+    /// Run commands in `path`, resolved against the current directory
+    ///
+    /// Fails, leaving the directory as it was, if `path` is not a directory.
+    pub fn set_cwd(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = self.state().cwd.join(path);
+        if !path.is_dir() {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("not a directory: {}", path.display()),
+            )));
+        }
+        self.state_mut().cwd = path;
+        Ok(())
+    }
+
+    /// The value of the variable `name`, exported or not
+    pub fn var(&self, name: &str) -> Option<String> {
+        self.state().variables.get(name).cloned()
+    }
+
+    /// Set the variable `name`; it stays exported if it already was
+    pub fn set_var(&self, name: impl Into<String>, value: impl Into<String>) {
+        self.state_mut().variables.insert(name.into(), value.into());
+    }
+
+    /// Set the variable `name` and pass it to every command as an
+    /// environment variable
+    pub fn export(&self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        let mut state = self.state_mut();
+        state.variables.insert(name.clone(), value.into());
+        state.exported.insert(name);
+    }
+
+    /// Remove the variable `name`, returning its value
+    pub fn unset(&self, name: &str) -> Option<String> {
+        let mut state = self.state_mut();
+        state.exported.remove(name);
+        state.variables.remove(name)
+    }
+
+    /// The exported variables, as commands see them on top of this
+    /// process's environment
+    pub fn env(&self) -> HashMap<String, String> {
+        let state = self.state();
+        state
+            .variables
+            .iter()
+            .filter(|(name, _)| state.exported.contains(*name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Make `name` at the start of a command stand for `value`, over a
+    /// [global alias](crate::aliases) of the same name
+    pub fn alias(&self, name: impl Into<String>, value: impl Into<String>) {
+        self.state_mut().aliases.insert(name.into(), value.into());
+    }
+
+    /// Remove the alias `name`, returning whether there was one
+    pub fn unalias(&self, name: &str) -> bool {
+        self.state_mut().aliases.remove(name).is_some()
+    }
+
+    /// What the alias `name` stands for
+    pub fn alias_of(&self, name: &str) -> Option<String> {
+        self.state().aliases.get(name).cloned()
+    }
+
+    /// Define a shell function: running `name args...` runs `body` (shell
+    /// syntax) with the arguments as `$1`, `$2`, ...
+    ///
+    /// Functions run in the shell, not as virtual commands.
+    pub fn define_function(&self, name: impl Into<String>, body: impl Into<String>) {
+        self.state_mut().functions.insert(name.into(), body.into());
+    }
+
+    /// Remove the function `name`, returning whether there was one
+    pub fn undefine_function(&self, name: &str) -> bool {
+        self.state_mut().functions.remove(name).is_some()
+    }
+
+    /// The commands run in this session, oldest first (at most the last
+    /// 1000)
+    pub fn history(&self) -> Vec<String> {
+        self.state().history.clone()
+    }
+
+    /// The session's shell settings
+    pub async fn shell_settings(&self) -> ShellSettings {
+        self.settings.read().await.clone()
+    }
+
+    /// Turn a shell option (like `errexit` or `e`) on or off for this
+    /// session's commands
+    pub async fn set_shell_option(&self, option: &str, enabled: bool) {
+        self.settings.write().await.set(option, enabled);
+    }
+
+    /// Add a virtual command that only this session's commands can use,
+    /// taking precedence over the built-in ones
+    pub fn register(&self, name: &str, handler: VirtualCommandHandler) {
+        self.registry
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .register(name, handler);
+    }
+
+    /// Remove a virtual command added with [`register`](Self::register)
+    pub fn unregister(&self, name: &str) -> bool {
+        self.registry
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .unregister(name)
+    }
+
+    /// The options a command run now starts from: the session's directory,
+    /// exported variables, its other variables as shell variables, `$?`
+    /// and a token that [`signal`](Self::signal) cancels on top of the
+    /// options it was created with
+    pub fn options(&self) -> RunOptions {
+        let mut options = self.options.clone();
+        options.cancel = Some(
+            self.interrupt
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        );
+        options.cwd = Some(self.cwd());
+        options.env = Some(self.env());
+        options.parameters.status = self.last_status();
+        let state = self.state();
+        options.parameters.variables.extend(
+            state
+                .variables
+                .iter()
+                .filter(|(name, _)| !state.exported.contains(*name))
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        drop(state);
+        options
+    }
+
+    /// Exit status of the last command the session ran, which its next
+    /// command sees as `$?`
+    ///
+    /// A command that failed with an error counts with the status a shell
+    /// would give it: 127 if it wasn't found, 124 if it timed out and 1
+    /// for errors without a status.
+    pub fn last_status(&self) -> i32 {
+        self.state().last_status
+    }
+
+    /// Run `command` in this session
+    ///
+    /// Aliases where a command name goes are expanded first, the session's
+    /// and then the [global ones](crate::aliases). Then the command runs as
+    /// a function, as one of the session's own virtual commands, or like
+    /// [`ProcessRunner::run`] does, in the session's directory, environment
+    /// and shell settings. `set` changes the session's settings rather than
+    /// the global ones, `alias` and `unalias` its aliases, `cd`, `export`
+    /// and `unset` its directory and variables, and `trap` what runs
+    /// when it [closes](Self::close) or gets a [signal](Self::signal). Assignments alone, as in
+    /// `NAME=value`, set session variables that aren't exported, and
+    /// function definitions define session functions. A list with any of
+    /// these in it, as in `cd build && make`, runs one command at a time in
+    /// the session; none of them changes the process's directory or
+    /// environment.
+    pub async fn run(&self, command: impl Into<String>) -> Result<CommandResult> {
+        let command = command.into();
+        {
+            let history = &mut self.state_mut().history;
+            if history.len() == MAX_HISTORY {
+                history.remove(0);
+            }
+            history.push(command.clone());
+        }
+        self.state_mut().exited = false;
+        let outcome = self.run_command(command, self.options()).await;
+        if matches!(outcome, Err(Error::Cancelled)) {
+            self.trap_cancellation().await;
+        }
+        self.state_mut().last_status = match &outcome {
+            Ok(result) => result.code,
+            Err(e) => match e.exit_kind() {
+                Some(ExitKind::NotFound) => 127,
+                Some(ExitKind::Timeout) => 124,
+                Some(ExitKind::SignalTerminated(signal)) => 128 + signal,
+                _ => e.exit_code().unwrap_or(1),
+            },
+        };
+        outcome
+    }
+
+    /// Run `command` with its aliases expanded, through the session's hooks
+    async fn run_command(&self, command: String, options: RunOptions) -> Result<CommandResult> {
+        let hooks = self.hooks.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let spec = ExecSpec {
+            command: self.expand_aliases(&command),
+            options,
+        };
+        let spec = hooks.run_before(spec).await?;
+        let outcome = self
+            .run_spec(spec.command.clone(), spec.options.clone())
+            .await;
+        hooks.run_after(&spec, &outcome).await;
+        outcome
+    }
+
+    async fn run_spec(&self, command: String, mut options: RunOptions) -> Result<CommandResult> {
+        if let Some((name, body)) = steps::function_definition(&command) {
+            self.define_function(name, body);
+            return Ok(CommandResult::default());
+        }
+        if let Some((commands, operators)) = self.steps(&command) {
+            return self.run_steps(&commands, &operators, options).await;
+        }
+        if let Some(script) = self.with_functions(&command) {
+            options.raw_shell = true;
+            return ProcessRunner::new(script, options)
+                .with_session_settings(self.settings.clone())
+                .run()
+                .await;
+        }
+
+        if let Some(call) = self.builtin_call(&command, &options).await? {
+            if let ("source" | ".", [path]) = (call.name.as_str(), &call.args[..]) {
+                // A script may source another, so this future is boxed
+                return Box::pin(self.source(path)).await;
+            }
+            let result = self.state_mut().builtin(&call.name, &call.args);
+            return self.finish_builtin(&command, result, &options).await;
+        }
+        if let Some(result) = self.assign(&command, &options).await? {
+            return Ok(result);
+        }
+        if let Some((name, args)) = virtual_command(&command, options.env.as_ref()) {
+            let handler = self
+                .registry
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&name)
+                .copied();
+            if let Some(handler) = handler {
+                return self.run_registered(&command, handler, args, options).await;
+            }
+        }
+
+        // A `cd` in a list of commands moves the session, not the process
+        let start = options.cwd.clone().unwrap_or_else(|| self.cwd());
+        let effects = Arc::new(std::sync::Mutex::new(SessionEffects {
+            cwd: start.clone(),
+            exited: false,
+        }));
+        let exits = matches!(
+            parse_shell_command(&command),
+            Ok(ParsedCommand::Simple { cmd, .. }) if cmd == "exit"
+        );
+        let outcome = ProcessRunner::new(command, options)
+            .with_session_settings(self.settings.clone())
+            .with_session_effects(effects.clone())
+            .run()
+            .await;
+        let effects = effects.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let mut state = self.state_mut();
+        if effects.cwd != start {
+            state.moved_to(effects.cwd);
+        }
+        state.exited = exits || effects.exited;
+        outcome
+    }
+
+    /// Run the script at `path`, relative to the session's directory, in
+    /// this session, as `source` does; see [`run_script_in`](crate::run_script_in)
+    pub async fn source(&self, path: impl AsRef<Path>) -> Result<CommandResult> {
+        let path = self.cwd().join(path);
+        script::run_script_in(self, path).await
+    }
+
+    /// `command` as a call of a builtin that changes the session's state,
+    /// or runs a script in it, with its words expanded, unless it has
+    /// redirects or assignments
+    async fn builtin_call(
+        &self,
+        command: &str,
+        options: &RunOptions,
+    ) -> Result<Option<VirtualCall>> {
+        let settings = self.shell_settings().await;
+        let cancel = options.cancel.clone().unwrap_or_default();
+        let context = expand::Context {
+            options,
+            cwd: options.cwd.as_deref(),
+            settings: &settings,
+            cancel: &cancel,
+            parameters: &options.parameters,
+        };
+        let call = expand::call(command, SESSION_BUILTINS, &context).await?;
+        // `source` with arguments for the script is left to the shell
+        Ok(call.filter(|call| {
+            call.redirects.is_empty()
+                && call.env.is_empty()
+                && (!matches!(call.name.as_str(), "source" | ".") || call.args.len() == 1)
+        }))
+    }
+
+    /// Run `hook` before each command the session runs, to change the
+    /// command or its options
+    ///
+    /// Session hooks run before the global ones; see [`hooks`](crate::hooks).
+    pub fn before_exec<F, Fut>(&self, hook: F)
+    where
+        F: Fn(ExecSpec) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<ExecSpec>> + Send + 'static,
+    {
+        self.hooks
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .before_exec(hook);
+    }
+
+    /// Run `hook` after each command the session runs, with its outcome
+    ///
+    /// Session hooks run after the global ones.
+    pub fn after_exec<F, Fut>(&self, hook: F)
+    where
+        F: Fn(&ExecSpec, &Result<CommandResult>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.hooks
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .after_exec(hook);
+    }
+
+    /// Run `hook` when the session is closed, e.g. to remove files its
+    /// commands created
+    ///
+    /// Hooks run once, in reverse registration order, in
+    /// [`close`](Self::close), or when the session is dropped.
+    pub fn on_cleanup<F>(&self, hook: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.cleanup
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Box::new(hook));
+    }
+
+    /// End the session, running the command its `trap ... EXIT` set, then
+    /// its cleanup hooks
+    ///
+    /// A session dropped without being closed runs its cleanup hooks but
+    /// not its EXIT trap, which would have to block the dropping thread.
+    pub async fn close(self) {
+        self.run_exit_trap().await;
+        self.run_cleanup_hooks();
+    }
+
+    fn run_cleanup_hooks(&self) {
+        let hooks = std::mem::take(&mut *self.cleanup.lock().unwrap_or_else(|e| e.into_inner()));
+        for hook in hooks.into_iter().rev() {
+            hook();
+        }
+    }
+
+    /// `command` with the session's aliases, and the global ones, expanded
+    fn expand_aliases(&self, command: &str) -> String {
+        let state = self.state();
+        aliases::expand_aliases(command, |name| {
+            state
+                .aliases
+                .get(name)
+                .cloned()
+                .or_else(|| aliases::alias_of(name))
+        })
+    }
+
+    /// `command` preceded by the session's function definitions, when it
+    /// starts by calling one of them
+    fn with_functions(&self, command: &str) -> Option<String> {
+        let state = self.state();
+        let first = command.split_whitespace().next()?;
+        if !state.functions.contains_key(first) {
+            return None;
+        }
+        let mut script = String::new();
+        for (name, body) in &state.functions {
+            script.push_str(&format!("{}() {{\n{}\n}}\n", name, body));
+        }
+        script.push_str(command);
+        Some(script)
+    }
+
+    async fn run_registered(
+        &self,
+        command: &str,
+        handler: VirtualCommandHandler,
+        args: Vec<String>,
+        options: RunOptions,
+    ) -> Result<CommandResult> {
+        let executor = CommandExecutor::new(options.clone())
+            .with_session_settings(Some(self.settings.clone()));
+        let ctx = CommandContext {
+            cwd: options.cwd.clone(),
+            env: options.env.clone(),
+            cancel_token: options.cancel.clone(),
+            shell_settings: Some(self.settings.clone()),
+            executor: Some(executor),
+            ..CommandContext::new(args)
+        };
+        let result = handler(ctx).await;
+        self.finish_builtin(command, result, &options).await
+    }
+
+    /// Show the `result` of a virtual command if `options` mirror output,
+    /// failing with it under `errexit`
+    async fn finish_builtin(
+        &self,
+        command: &str,
+        result: CommandResult,
+        options: &RunOptions,
+    ) -> Result<CommandResult> {
+        let errexit = match &options.shell_settings {
+            Some(settings) => settings.errexit,
+            None => self.settings.read().await.errexit,
+        };
+        if options.mirror {
+            print!("{}", console::for_terminal(&result.stdout));
+            eprint!("{}", console::for_terminal(&result.stderr));
+        }
+        if errexit && !result.is_success() {
+            return Err(Error::command_failed(
+                command,
+                result.code,
+                &result.stderr,
+                result.signal,
+            ));
+        }
+        Ok(result)
+    }
+}
+
+impl SessionState {
+    /// Run `name`, one of the [`SESSION_BUILTINS`] other than `source`, on
+    /// this state
+    fn builtin(&mut self, name: &str, args: &[String]) -> CommandResult {
+        match name {
+            "cd" => self.cd(args),
+            "export" => self.export(args),
+            "unset" => self.unset(args),
+            "trap" => self.trap(args),
+            _ => aliases::builtin(name, args, &mut self.aliases),
+        }
+    }
+
+    /// `cd`, relative to the session's directory, `-` going back to its
+    /// `OLDPWD`
+    fn cd(&mut self, args: &[String]) -> CommandResult {
+        let variables = &self.variables;
+        let var = |name: &str| match variables.get(name) {
+            Some(value) => Some(value.clone()),
+            None => std::env::var(name).ok(),
+        };
+        match change_dir(args, &self.cwd, var) {
+            Ok((dir, output)) => {
+                self.moved_to(dir);
+                CommandResult::success(output)
+            }
+            Err(result) => result,
+        }
+    }
+
+    /// Make `dir` the directory, with `PWD` and `OLDPWD` exported as a
+    /// shell does
+    fn moved_to(&mut self, dir: PathBuf) {
+        let old = std::mem::replace(&mut self.cwd, dir);
+        for (name, dir) in [("OLDPWD", old), ("PWD", self.cwd.clone())] {
+            self.variables
+                .insert(name.to_string(), dir.display().to_string());
+            self.exported.insert(name.to_string());
+        }
+    }
+
+    /// `export name=value` or `export name`; alone, or with `-p`, it lists
+    /// the exported variables
+    fn export(&mut self, args: &[String]) -> CommandResult {
+        let mut output = String::new();
+        let mut errors = String::new();
+        let names: Vec<&String> = args.iter().filter(|arg| *arg != "-p").collect();
+        if names.is_empty() {
+            for (name, value) in sorted(&self.variables) {
+                if self.exported.contains(name) {
+                    let _ = writeln!(output, "export {}={}", name, quote(value));
+                }
+            }
+        }
+        for arg in names {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            if !is_variable_name(name) {
+                let _ = writeln!(errors, "export: `{}': not a valid identifier", arg);
+                continue;
+            }
+            if let Some(value) = value {
+                self.variables.insert(name.to_string(), value.to_string());
+            }
+            self.exported.insert(name.to_string());
+        }
+        builtin_result(output, errors)
+    }
+
+    /// `unset name...`: variables, or with `-f` functions; a name that
+    /// isn't a variable removes the function of that name, as in bash
+    fn unset(&mut self, args: &[String]) -> CommandResult {
+        let mut errors = String::new();
+        let (mut variables, mut functions) = (true, true);
+        for arg in args {
+            match arg.as_str() {
+                "-v" => (variables, functions) = (true, false),
+                "-f" => (variables, functions) = (false, true),
+                _ if variables && !is_variable_name(arg) => {
+                    let _ = writeln!(errors, "unset: `{}': not a valid identifier", arg);
+                }
+                name => {
+                    let removed = variables && self.variables.remove(name).is_some();
+                    if variables {
+                        self.exported.remove(name);
+                    }
+                    if functions && !removed {
+                        self.functions.remove(name);
+                    }
+                }
+            }
+        }
+        builtin_result(String::new(), errors)
+    }
+}
+
+/// The result of a builtin that wrote `output`, failing if it wrote `errors`
+fn builtin_result(output: String, errors: String) -> CommandResult {
+    CommandResult {
+        code: i32::from(!errors.is_empty()),
+        stdout: output,
+        stderr: errors,
+        ..CommandResult::default()
+    }
+}
+
+fn is_variable_name(name: &str) -> bool {
+    is_assignment(&format!("{}=", name))
+}
+
+fn sorted(map: &HashMap<String, String>) -> BTreeMap<&String, &String> {
+    map.iter().collect()
+}
@@ -0,0 +1,205 @@
+//! Saving a session to a file and loading it back
+//!
+//! A saved session is a header line and then one entry per line, its
+//! fields separated by tabs and escaped so that they fit on the line.
+
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use super::{sorted, traps, Session, SessionState};
+use crate::{Error, Result, ShellSettings};
+
+/// First line of a saved session
+const SAVE_HEADER: &str = "command-stream session 1";
+
+/// Shell options a saved session records
+const SAVED_OPTIONS: &[&str] = &[
+    "allexport",
+    "errexit",
+    "globstar",
+    "noglob",
+    "nounset",
+    "pipefail",
+    "verbose",
+    "xtrace",
+];
+
+impl Session {
+    /// Save the directory, variables, aliases, functions, shell settings
+    /// and history to `path`
+    ///
+    /// The file is replaced in one step, so a crash never leaves half of
+    /// it. Registered virtual commands are code and are not saved.
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let settings = self.shell_settings().await;
+        let text = {
+            let state = self.state();
+            let mut text = format!("{}\n", SAVE_HEADER);
+            let mut entry = |fields: &[&str]| {
+                let fields: Vec<_> = fields.iter().map(|field| escape_field(field)).collect();
+                let _ = writeln!(text, "{}", fields.join("\t"));
+            };
+            entry(&["cwd", &state.cwd.to_string_lossy()]);
+            for (name, value) in sorted(&state.variables) {
+                let kind = if state.exported.contains(name) {
+                    "export"
+                } else {
+                    "var"
+                };
+                entry(&[kind, name, value]);
+            }
+            for (name, value) in sorted(&state.aliases) {
+                entry(&["alias", name, value]);
+            }
+            for (name, body) in sorted(&state.functions) {
+                entry(&["function", name, body]);
+            }
+            for (condition, action) in &state.traps {
+                entry(&["trap", action, condition]);
+            }
+            for option in SAVED_OPTIONS {
+                if option_enabled(&settings, option) {
+                    entry(&["option", option]);
+                }
+            }
+            for command in &state.history {
+                entry(&["history", command]);
+            }
+            text
+        };
+
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let mut file = tempfile::NamedTempFile::new_in(dir)?;
+            file.write_all(text.as_bytes())?;
+            file.persist(&path).map_err(|e| Error::Io(e.error))?;
+            Ok(())
+        })
+        .await
+        .map_err(|e| Error::Io(std::io::Error::other(e)))?
+    }
+
+    /// Replace this session's state with what [`save`](Self::save) wrote to
+    /// `path`
+    ///
+    /// Nothing changes if the file can't be read or isn't a saved session.
+    pub async fn load(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = tokio::fs::read_to_string(path).await?;
+        let invalid = |line: usize, reason: &str| {
+            Error::ParseError(format!("{}:{}: {}", path.display(), line, reason))
+        };
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line) != Some(SAVE_HEADER) {
+            return Err(invalid(1, "not a saved session"));
+        }
+
+        let mut state = SessionState::default();
+        let mut settings = ShellSettings::default();
+        for (index, line) in lines {
+            let fields: Vec<String> = line.split('\t').map(unescape_field).collect();
+            let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+            match fields[..] {
+                ["cwd", cwd] => state.cwd = PathBuf::from(cwd),
+                ["var", name, value] => {
+                    state.variables.insert(name.to_string(), value.to_string());
+                }
+                ["export", name, value] => {
+                    state.variables.insert(name.to_string(), value.to_string());
+                    state.exported.insert(name.to_string());
+                }
+                ["alias", name, value] => {
+                    state.aliases.insert(name.to_string(), value.to_string());
+                }
+                ["function", name, body] => {
+                    state.functions.insert(name.to_string(), body.to_string());
+                }
+                ["trap", action, condition] => match traps::trap_condition(condition) {
+                    Some(condition) => {
+                        state.traps.insert(condition, action.to_string());
+                    }
+                    None => return Err(invalid(index + 1, "unrecognized trap")),
+                },
+                ["option", option] if SAVED_OPTIONS.contains(&option) => settings.set(option, true),
+                ["history", command] => state.history.push(command.to_string()),
+                [""] => {}
+                _ => return Err(invalid(index + 1, "unrecognized entry")),
+            }
+        }
+        if state.cwd.as_os_str().is_empty() {
+            state.cwd = self.cwd();
+        }
+
+        *self.state_mut() = state;
+        *self.settings.write().await = settings;
+        Ok(())
+    }
+}
+
+fn option_enabled(settings: &ShellSettings, option: &str) -> bool {
+    match option {
+        "allexport" => settings.allexport,
+        "errexit" => settings.errexit,
+        "globstar" => settings.globstar,
+        "noglob" => settings.noglob,
+        "nounset" => settings.nounset,
+        "pipefail" => settings.pipefail,
+        "verbose" => settings.verbose,
+        "xtrace" => settings.xtrace,
+        _ => false,
+    }
+}
+
+/// Escape a field of a saved session so it fits on one line between tabs
+fn escape_field(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_field(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_escaping_round_trips() {
+        for field in ["plain", "tab\there", "two\nlines\r\n", "back\\slash\\t", ""] {
+            let escaped = escape_field(field);
+            assert!(!escaped.contains(['\t', '\n']), "{:?}", escaped);
+            assert_eq!(unescape_field(&escaped), field);
+        }
+    }
+}
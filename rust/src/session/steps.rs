@@ -0,0 +1,220 @@
+//! What a session runs itself rather than hand to a shell: function
+//! definitions, assignments and lists with session commands in them
+//!
+//! `greet() { echo "hi $1"; }` defines a session function and `NAME=world`
+//! sets a session variable, which later commands see as `$NAME` but which
+//! isn't exported to them. A list such as `export A=1; echo $A` or
+//! `cd build && make`, with a command that changes the session or is one
+//! of its functions or virtual commands, runs one command at a time in the
+//! session, so the commands after that one see what it changed. Lists with
+//! shell keywords still go to the shell whole.
+
+use std::collections::HashMap;
+
+use super::{Session, SESSION_BUILTINS};
+use crate::expand;
+use crate::runner::list_commands;
+use crate::shell_parser::{is_assignment, TokenType};
+use crate::{CommandResult, ParsedCommand, Result, RunOptions, ShellSettings};
+
+impl Session {
+    /// The commands of `command` and the operators between them, if it is
+    /// a list with a command that has to run in the session
+    pub(super) fn steps(&self, command: &str) -> Option<(Vec<ParsedCommand>, Vec<TokenType>)> {
+        let (commands, operators) = list_commands(command)?;
+        let in_session = {
+            let state = self.state();
+            let registry = self.registry.read().unwrap_or_else(|e| e.into_inner());
+            commands.iter().any(|command| match command {
+                ParsedCommand::Simple { cmd, .. } => {
+                    SESSION_BUILTINS.contains(&cmd.as_str())
+                        || is_assignment(cmd)
+                        || state.functions.contains_key(cmd)
+                        || registry.get(cmd).is_some()
+                }
+                _ => false,
+            })
+        };
+        in_session.then_some((commands, operators))
+    }
+
+    /// Run `commands`, joined by `operators`, one at a time in the session:
+    /// `&&` and `||` skip the next command on failure or success, and with
+    /// `errexit` a failure outside of an `&&`/`||` test fails the list
+    pub(super) async fn run_steps(
+        &self,
+        commands: &[ParsedCommand],
+        operators: &[TokenType],
+        options: RunOptions,
+    ) -> Result<CommandResult> {
+        let env_before = self.env();
+        let mut total = CommandResult::default();
+        let mut status = options.parameters.status;
+        for (i, command) in commands.iter().enumerate() {
+            let skip = match i.checked_sub(1).and_then(|before| operators.get(before)) {
+                Some(TokenType::And) => status != 0,
+                Some(TokenType::Or) => status == 0,
+                _ => false,
+            };
+            if skip {
+                continue;
+            }
+            let mut step = self.step_options(&options, &env_before);
+            step.parameters.status = status;
+            let tested = matches!(operators.get(i), Some(TokenType::And | TokenType::Or))
+                || matches!(command, ParsedCommand::Not { .. });
+            if tested {
+                step.shell_settings = Some(ShellSettings {
+                    errexit: false,
+                    ..self.shell_settings().await
+                });
+            }
+            // A step may be a function that runs a list itself
+            let result = Box::pin(self.run_spec(command.to_string(), step)).await?;
+            status = result.code;
+            total.stdout.push_str(&result.stdout);
+            total.stderr.push_str(&result.stderr);
+            total.code = result.code;
+            total.signal = result.signal;
+            if self.state().exited {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    /// `options` brought up to date with the session for the next step:
+    /// its directory and variables, keeping the variables the caller added
+    /// unless a step unset them
+    fn step_options(
+        &self,
+        options: &RunOptions,
+        env_before: &HashMap<String, String>,
+    ) -> RunOptions {
+        let current = self.options();
+        let mut step = options.clone();
+        let env = current.env.unwrap_or_default();
+        let mut merged = step.env.take().unwrap_or_default();
+        merged.retain(|name, _| env.contains_key(name) || !env_before.contains_key(name));
+        merged.extend(env);
+        step.env = Some(merged);
+        step.cwd = current.cwd;
+        step.parameters.variables = current.parameters.variables;
+        step
+    }
+
+    /// Set the session variables `command` assigns, if all it does is
+    /// assign them, as in `NAME=value`
+    ///
+    /// With `allexport` the variables are exported as well; a variable that
+    /// was exported stays so.
+    pub(super) async fn assign(
+        &self,
+        command: &str,
+        options: &RunOptions,
+    ) -> Result<Option<CommandResult>> {
+        let settings = self.shell_settings().await;
+        let cancel = options.cancel.clone().unwrap_or_default();
+        let context = expand::Context {
+            options,
+            cwd: options.cwd.as_deref(),
+            settings: &settings,
+            cancel: &cancel,
+            parameters: &options.parameters,
+        };
+        let Some(assignments) = expand::assignments(command, &context).await? else {
+            return Ok(None);
+        };
+        let mut state = self.state_mut();
+        for (name, value) in assignments {
+            if settings.allexport {
+                state.exported.insert(name.clone());
+            }
+            state.variables.insert(name, value);
+        }
+        Ok(Some(CommandResult::default()))
+    }
+}
+
+/// The name and body of the function `command` defines, as in
+/// `greet() { echo "hi $1"; }` or `function greet { ...; }`
+pub(super) fn function_definition(command: &str) -> Option<(String, String)> {
+    let text = command.trim();
+    let (text, keyword) = match text.strip_prefix("function") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => (rest.trim_start(), true),
+        _ => (text, false),
+    };
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(text.len());
+    let (name, rest) = text.split_at(end);
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let group = match rest.strip_prefix('(') {
+        Some(rest) => rest.trim_start().strip_prefix(')')?.trim_start(),
+        None if keyword => rest,
+        None => return None,
+    };
+    // `{` is a word of its own, and the `}` that closes it ends the command
+    let body = group.strip_prefix('{')?;
+    if !body.starts_with(char::is_whitespace) || closing_brace(group)? != group.len() - 1 {
+        return None;
+    }
+    Some((name.to_string(), body[..body.len() - 1].trim().to_string()))
+}
+
+/// The offset of the `}` closing the `{` that `text` starts with, skipping
+/// quoted and escaped characters
+fn closing_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_function_definitions() {
+        let definition = function_definition;
+        assert_eq!(
+            definition("greet() { echo \"hi $1\"; }"),
+            Some(("greet".into(), "echo \"hi $1\";".into()))
+        );
+        assert_eq!(
+            definition("function build {\n  make '}'\n}"),
+            Some(("build".into(), "make '}'".into()))
+        );
+        assert_eq!(
+            definition("greet () {\n  echo ${A}\n}").unwrap().1,
+            "echo ${A}"
+        );
+        assert_eq!(definition("greet() { echo; } > out"), None);
+        assert_eq!(definition("greet() { echo; }; greet"), None);
+        assert_eq!(definition("greet() {echo; }"), None);
+        assert_eq!(definition("echo {a,b}"), None);
+    }
+}
@@ -0,0 +1,163 @@
+//! `trap` in a session: commands run when it closes or is interrupted
+//!
+//! `trap 'action' EXIT` runs `action` when the session is
+//! [closed](Session::close). `trap 'action' INT` and `trap 'action' TERM`
+//! run `action` when the session gets that signal from
+//! [`Session::signal`], which also cancels the commands it is running, and
+//! `INT` also when a command is cancelled through the token the session was
+//! created with. An empty action ignores the signal. Commands don't run in
+//! a shell of the session's own that other signals could reach, so other
+//! conditions are refused rather than never trapped.
+
+use std::fmt::Write as _;
+
+use super::{builtin_result, SessionState};
+use crate::quote::quote;
+use crate::{CancellationToken, CommandResult, Error, Result, Session};
+
+impl Session {
+    /// Send `signal` (`"INT"`, `"SIGTERM"`, ...) to the session: the
+    /// commands it is running are cancelled, then the command its `trap`
+    /// set for the signal runs, and its result is returned
+    ///
+    /// Returns `None` without a trap. A signal trapped with an empty
+    /// action is ignored, and commands keep running. Only INT and TERM
+    /// can be sent.
+    pub async fn signal(&self, signal: &str) -> Result<Option<CommandResult>> {
+        let condition = match trap_condition(signal) {
+            Some(condition @ ("INT" | "TERM")) => condition,
+            _ => {
+                return Err(Error::ParseError(format!(
+                    "{}: only INT and TERM can be sent to a session",
+                    signal
+                )))
+            }
+        };
+        if self
+            .state()
+            .traps
+            .get(condition)
+            .is_some_and(String::is_empty)
+        {
+            return Ok(None);
+        }
+        let fresh = commands_token(self.options.cancel.as_ref());
+        let running = std::mem::replace(
+            &mut *self.interrupt.lock().unwrap_or_else(|e| e.into_inner()),
+            fresh,
+        );
+        running.cancel();
+        self.run_trap(condition).await.transpose()
+    }
+
+    /// Run the `INT` trap after a command was cancelled, if the token the
+    /// session was created with cancelled it; once, as the token stays
+    /// cancelled
+    pub(super) async fn trap_cancellation(&self) {
+        let cancelled = self
+            .options
+            .cancel
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled);
+        if !cancelled || std::mem::replace(&mut self.state_mut().cancel_trapped, true) {
+            return;
+        }
+        // Its output is shown if the session mirrors output
+        let _ = self.run_trap("INT").await;
+    }
+
+    /// Run the command `trap` set for `EXIT`, once, as a shell does when it
+    /// exits; `None` if there is none
+    pub(crate) async fn run_exit_trap(&self) -> Option<Result<CommandResult>> {
+        self.run_trap("EXIT").await
+    }
+
+    pub(super) fn has_exit_trap(&self) -> bool {
+        self.state()
+            .traps
+            .get("EXIT")
+            .is_some_and(|action| !action.trim().is_empty())
+    }
+
+    /// Run the command trapped for `condition`, which is kept for the next
+    /// time unless the condition is `EXIT`; `None` if there is none
+    async fn run_trap(&self, condition: &str) -> Option<Result<CommandResult>> {
+        let action = {
+            let mut state = self.state_mut();
+            if condition == "EXIT" {
+                state.traps.remove(condition)?
+            } else {
+                state.traps.get(condition)?.clone()
+            }
+        };
+        if action.trim().is_empty() {
+            return None;
+        }
+        // The trap runs even though the session's commands were cancelled
+        let options = crate::RunOptions {
+            cancel: None,
+            ..self.options()
+        };
+        Some(self.run_command(action, options).await)
+    }
+}
+
+impl SessionState {
+    /// `trap action condition...`, which runs `action` on `EXIT`, `INT` or
+    /// `TERM`; `trap - condition` removes it and `trap` alone, or with
+    /// `-p`, shows the traps set
+    pub(super) fn trap(&mut self, args: &[String]) -> CommandResult {
+        let args = match args.first().map(String::as_str) {
+            Some("--") => &args[1..],
+            _ => args,
+        };
+        if args.first().is_none_or(|arg| arg == "-p") {
+            let mut output = String::new();
+            for (condition, action) in &self.traps {
+                let _ = writeln!(output, "trap -- {} {}", quote(action), condition);
+            }
+            return CommandResult::success(output);
+        }
+        // A lone condition, like a `-` action, resets it
+        let (action, conditions) = match args {
+            [condition] => (None, std::slice::from_ref(condition)),
+            [action, conditions @ ..] if action == "-" => (None, conditions),
+            [action, conditions @ ..] => (Some(action), conditions),
+            [] => return CommandResult::success_empty(),
+        };
+        let mut errors = String::new();
+        for condition in conditions {
+            let Some(condition) = trap_condition(condition) else {
+                let _ = writeln!(
+                    errors,
+                    "trap: {}: only EXIT, INT and TERM can be trapped in a session",
+                    condition
+                );
+                continue;
+            };
+            match action {
+                Some(action) => self.traps.insert(condition, action.clone()),
+                None => self.traps.remove(condition),
+            };
+        }
+        builtin_result(String::new(), errors)
+    }
+}
+
+/// The condition `name` stands for, with or without `SIG` or as a number,
+/// if a session can trap it
+pub(super) fn trap_condition(name: &str) -> Option<&'static str> {
+    let name = name.to_ascii_uppercase();
+    match name.strip_prefix("SIG").unwrap_or(&name) {
+        "EXIT" | "0" => Some("EXIT"),
+        "INT" | "2" => Some("INT"),
+        "TERM" | "15" => Some("TERM"),
+        _ => None,
+    }
+}
+
+/// A token for the commands a session runs, cancelled by
+/// [`Session::signal`] or along with `cancel`, the session's own
+pub(super) fn commands_token(cancel: Option<&CancellationToken>) -> CancellationToken {
+    cancel.map_or_else(CancellationToken::new, CancellationToken::child_token)
+}
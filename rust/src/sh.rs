@@ -0,0 +1,142 @@
+//! A reusable shell handle with its own defaults
+//!
+//! [`Sh`] plays the role of a configured `$` in zx or Bun: set the working
+//! directory, environment and output policy once, then run any number of
+//! commands with them.
+//!
+//! ```rust,no_run
+//! use command_stream::Sh;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let sh = Sh::new().cwd("/srv/app").env("NODE_ENV", "production").quiet();
+//!
+//! sh.run("npm ci").await?;
+//! let version = sh.run("node --version").await?.stdout;
+//!
+//! // Derived handles start from the parent's settings
+//! let tests = sh.clone().env("CI", "1");
+//! tests.run("npm test").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::{
+    CancellationToken, CommandResult, ProcessRunner, Result, RunOptions, StdinOption,
+    StreamingRunner,
+};
+
+/// Shell handle whose settings every command it runs inherits
+#[derive(Debug, Clone)]
+pub struct Sh {
+    options: RunOptions,
+}
+
+impl Default for Sh {
+    fn default() -> Self {
+        Sh::new()
+    }
+}
+
+impl Sh {
+    /// A handle with the defaults of [`RunOptions::from_env`]
+    pub fn new() -> Self {
+        Sh {
+            options: RunOptions::from_env(),
+        }
+    }
+
+    /// A handle whose commands use `options`
+    pub fn with_options(options: RunOptions) -> Self {
+        Sh { options }
+    }
+
+    /// Run commands in `path`
+    pub fn cwd(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.cwd = Some(path.into());
+        self
+    }
+
+    /// Set an environment variable for every command
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options
+            .env
+            .get_or_insert_with(Default::default)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Don't mirror output to this process's stdout/stderr
+    pub fn quiet(mut self) -> Self {
+        self.options.mirror = false;
+        self
+    }
+
+    /// Mirror output to this process's stdout/stderr
+    pub fn loud(mut self) -> Self {
+        self.options.mirror = true;
+        self
+    }
+
+    /// Run every command in `locale` (see [`RunOptions::locale`])
+    pub fn locale(self, locale: impl Into<String>) -> Self {
+        Sh {
+            options: self.options.locale(locale),
+        }
+    }
+
+    /// Kill any command that runs longer than `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Kill running commands when `token` is cancelled
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.options.cancel = Some(token);
+        self
+    }
+
+    /// The options each command starts from
+    pub fn options(&self) -> &RunOptions {
+        &self.options
+    }
+
+    /// A runner for `command` with this handle's settings, not yet started
+    pub fn create(&self, command: impl Into<String>) -> ProcessRunner {
+        ProcessRunner::new(command, self.options.clone())
+    }
+
+    /// Run `command` with this handle's settings
+    pub async fn run(&self, command: impl Into<String>) -> Result<CommandResult> {
+        self.create(command).run().await
+    }
+
+    /// Run `command` with `input` as its standard input
+    pub async fn run_with_stdin(
+        &self,
+        command: impl Into<String>,
+        input: impl Into<String>,
+    ) -> Result<CommandResult> {
+        self.create(command)
+            .with_stdin(StdinOption::Content(input.into()))
+            .run()
+            .await
+    }
+
+    /// Run `command` with the shell, unparsed; see
+    /// [`run_raw_shell`](crate::run_raw_shell) for why this is the
+    /// injection-risk path
+    pub async fn run_raw_shell(&self, command: impl Into<String>) -> Result<CommandResult> {
+        let mut options = self.options.clone();
+        options.raw_shell = true;
+        ProcessRunner::new(command, options).run().await
+    }
+
+    /// A streaming runner for `command` with this handle's settings
+    pub fn stream(&self, command: impl Into<String>) -> StreamingRunner {
+        StreamingRunner::new(command).with_options(&self.options)
+    }
+}
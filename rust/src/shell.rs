@@ -0,0 +1,356 @@
+//! Shell detection
+//!
+//! Commands that can't be run directly or as virtual commands are handed to
+//! a shell. [`find_shell`] picks it for a [`ShellChoice`]. On Unix that is the
+//! user's `$SHELL` when it is an executable POSIX shell, otherwise the first
+//! of `sh`, `bash`, `dash` and `zsh` found; on Windows `cmd.exe`, then `pwsh`
+//! or `powershell.exe`. Every runner uses it, so they all agree on which
+//! shell runs a command.
+//!
+//! Detection runs once per process and choice. [`set_shell_preference`]
+//! replaces the order shells are tried in:
+//!
+//! ```rust
+//! use command_stream::shell::{find_shell, set_shell_preference, ShellChoice};
+//!
+//! // Prefer bash, then whatever $SHELL is, then plain sh
+//! set_shell_preference(["bash", "$SHELL", "sh"]);
+//! let shell = find_shell(ShellChoice::Auto);
+//! let argv = shell.argv("echo hi");
+//! assert_eq!(argv.last().map(String::as_str), Some("echo hi"));
+//! ```
+
+use std::path::Path;
+use std::sync::RwLock;
+
+/// Arguments that make PowerShell run one command and exit
+const POWERSHELL_ARGS: &[&str] = &["-NoLogo", "-NoProfile", "-NonInteractive", "-Command"];
+
+/// Where to look for a shell on Unix, in order of preference; names are
+/// searched for on `PATH`
+const UNIX_SHELLS: &[&str] = &[
+    USER_SHELL,
+    "/bin/sh",
+    "/usr/bin/sh",
+    "/bin/bash",
+    "/usr/bin/bash",
+    "/bin/dash",
+    "/usr/bin/dash",
+    "/bin/zsh",
+    "/usr/bin/zsh",
+    "sh",
+    "bash",
+    "dash",
+    "zsh",
+];
+
+/// POSIX shells searched for on `PATH` on Windows
+const WINDOWS_POSIX_SHELLS: &[&str] = &["sh", "bash", "dash", "zsh"];
+
+const WINDOWS_SHELLS: &[&str] = &["cmd.exe", "pwsh", "powershell.exe"];
+
+const POWERSHELLS: &[&str] = &["pwsh", "powershell.exe"];
+
+/// Entry of a preference order that stands for the user's `$SHELL`
+pub const USER_SHELL: &str = "$SHELL";
+
+/// Order set with [`set_shell_preference`]
+static PREFERENCE: RwLock<Option<Vec<String>>> = RwLock::new(None);
+
+/// Detected shell per [`ShellChoice`]
+static DETECTED: RwLock<[Option<Shell>; 3]> = RwLock::new([None, None, None]);
+
+/// The shell language a command is written in
+///
+/// This decides how the command string is parsed, and so whether it can be
+/// run directly, as well as which shell runs it when it can't.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ShellChoice {
+    /// POSIX syntax, run by the platform's default shell (`$SHELL` or `sh`
+    /// on Unix, `cmd.exe` on Windows)
+    #[default]
+    Auto,
+    /// POSIX syntax, run by `sh`, also on Windows when one is on `PATH`
+    Posix,
+    /// PowerShell syntax, run by `pwsh` or `powershell.exe`
+    ///
+    /// Plain commands are still executed directly, after removing
+    /// PowerShell quoting (see
+    /// [`powershell_literal_argv`](crate::powershell::powershell_literal_argv)).
+    /// Virtual commands are not used, since PowerShell's own `ls`, `cat` and
+    /// friends behave differently.
+    PowerShell,
+}
+
+impl std::str::FromStr for ShellChoice {
+    type Err = crate::Error;
+
+    /// `auto`, `posix`, or `powershell` (or `pwsh`), in any case
+    fn from_str(name: &str) -> crate::Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Ok(ShellChoice::Auto),
+            "posix" => Ok(ShellChoice::Posix),
+            "powershell" | "pwsh" => Ok(ShellChoice::PowerShell),
+            _ => Err(crate::Error::ParseError(format!(
+                "invalid shell `{}`: expected auto, posix or powershell",
+                name
+            ))),
+        }
+    }
+}
+
+/// A family of shells that take a command the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    /// `sh`, or a compatible shell such as `ash` or `ksh`
+    Sh,
+    /// `bash`
+    Bash,
+    /// `dash`
+    Dash,
+    /// `zsh`
+    Zsh,
+    /// `pwsh` or `powershell.exe`
+    PowerShell,
+    /// `cmd.exe`
+    Cmd,
+    /// A shell this crate doesn't know, e.g. `fish`; not assumed to be POSIX
+    Other,
+}
+
+impl ShellKind {
+    /// The kind of shell `program` (a name, or a Unix or Windows path) is
+    pub fn of(program: &str) -> Self {
+        let name = program.rsplit(['/', '\\']).next().unwrap_or(program);
+        let name = name.to_ascii_lowercase();
+        match name.strip_suffix(".exe").unwrap_or(&name) {
+            "bash" => ShellKind::Bash,
+            "dash" => ShellKind::Dash,
+            "zsh" => ShellKind::Zsh,
+            "pwsh" | "powershell" => ShellKind::PowerShell,
+            "cmd" => ShellKind::Cmd,
+            "sh" | "ash" | "ksh" | "mksh" | "yash" | "busybox" => ShellKind::Sh,
+            _ => ShellKind::Other,
+        }
+    }
+
+    /// Whether the shell understands POSIX syntax
+    pub fn is_posix(self) -> bool {
+        !matches!(
+            self,
+            ShellKind::PowerShell | ShellKind::Cmd | ShellKind::Other
+        )
+    }
+
+    /// Arguments that come before the command string
+    fn command_args(self) -> &'static [&'static str] {
+        match self {
+            ShellKind::PowerShell => POWERSHELL_ARGS,
+            ShellKind::Cmd => &["/c"],
+            _ => &["-c"],
+        }
+    }
+}
+
+/// A shell and how to hand it a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shell {
+    /// Program name or path
+    pub program: String,
+    /// Arguments that come before the command string
+    pub args: Vec<String>,
+    /// What kind of shell it is
+    pub kind: ShellKind,
+}
+
+impl Shell {
+    /// `program`, with the arguments its kind needs to run one command
+    pub fn new(program: impl Into<String>) -> Self {
+        let program = program.into();
+        let kind = ShellKind::of(&program);
+        Shell {
+            args: kind.command_args().iter().map(|a| a.to_string()).collect(),
+            program,
+            kind,
+        }
+    }
+
+    /// The argv that runs `command` with this shell
+    pub fn argv(&self, command: &str) -> Vec<String> {
+        let mut argv = Vec::with_capacity(self.args.len() + 2);
+        argv.push(self.program.clone());
+        argv.extend(self.args.iter().cloned());
+        argv.push(command.to_string());
+        argv
+    }
+}
+
+/// The shell that runs commands written for `choice`
+///
+/// POSIX on Windows falls back to the default shell when no POSIX shell is
+/// on `PATH`. PowerShell has no such fallback: without it, spawning `pwsh`
+/// reports the missing shell instead of running PowerShell syntax through
+/// `cmd.exe`.
+pub fn find_shell(choice: ShellChoice) -> Shell {
+    let slot = choice as usize;
+    if let Some(shell) = &DETECTED.read().unwrap_or_else(|e| e.into_inner())[slot] {
+        return shell.clone();
+    }
+    let shell = detect_shell(choice);
+    DETECTED.write().unwrap_or_else(|e| e.into_inner())[slot] = Some(shell.clone());
+    shell
+}
+
+/// Try shells in `order` instead of the platform's default order
+///
+/// Entries are program names, searched for on `PATH`, or paths; the
+/// [`USER_SHELL`] entry (`"$SHELL"`) stands for the user's login shell.
+/// Entries that don't exist, or aren't POSIX shells where POSIX syntax is
+/// needed, are skipped. Applies to [`ShellChoice::Auto`] and
+/// [`ShellChoice::Posix`]; PowerShell is always `pwsh` or `powershell.exe`.
+pub fn set_shell_preference<I>(order: I)
+where
+    I: IntoIterator,
+    I::Item: Into<String>,
+{
+    *PREFERENCE.write().unwrap_or_else(|e| e.into_inner()) =
+        Some(order.into_iter().map(Into::into).collect());
+    reset_shell_detection();
+}
+
+/// Go back to the platform's default order
+pub fn clear_shell_preference() {
+    *PREFERENCE.write().unwrap_or_else(|e| e.into_inner()) = None;
+    reset_shell_detection();
+}
+
+/// Forget the detected shells, so the next command detects them again,
+/// e.g. after `$SHELL` or `PATH` changed
+pub fn reset_shell_detection() {
+    *DETECTED.write().unwrap_or_else(|e| e.into_inner()) = [None, None, None];
+}
+
+fn detect_shell(choice: ShellChoice) -> Shell {
+    let posix_only = choice == ShellChoice::Posix || !cfg!(windows);
+    let preference = PREFERENCE.read().unwrap_or_else(|e| e.into_inner()).clone();
+    let defaults = match choice {
+        ShellChoice::PowerShell => POWERSHELLS,
+        _ if cfg!(windows) && choice == ShellChoice::Posix => WINDOWS_POSIX_SHELLS,
+        _ if cfg!(windows) => WINDOWS_SHELLS,
+        _ => UNIX_SHELLS,
+    };
+    let candidates: Vec<String> = match preference {
+        Some(order) if choice != ShellChoice::PowerShell => order,
+        _ => defaults.iter().map(|s| s.to_string()).collect(),
+    };
+
+    let found = candidates
+        .iter()
+        .filter_map(|candidate| resolve(candidate))
+        .find(|shell| choice == ShellChoice::PowerShell || !posix_only || shell.kind.is_posix());
+    match (found, choice) {
+        (Some(shell), _) => shell,
+        (None, ShellChoice::PowerShell) => Shell::new("pwsh"),
+        (None, ShellChoice::Posix) if cfg!(windows) => find_shell(ShellChoice::Auto),
+        (None, _) if cfg!(windows) => Shell::new("cmd.exe"),
+        (None, _) => Shell::new("/bin/sh"),
+    }
+}
+
+/// The shell `candidate` names, if it is installed
+fn resolve(candidate: &str) -> Option<Shell> {
+    let program = match candidate {
+        USER_SHELL => std::env::var("SHELL").ok().filter(|s| !s.is_empty())?,
+        _ => candidate.to_string(),
+    };
+    let installed = if program.contains(['/', '\\']) {
+        is_executable(Path::new(&program))
+    } else {
+        which::which(&program).is_ok()
+    };
+    installed.then(|| Shell::new(program))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_kinds() {
+        for (program, kind) in [
+            ("/bin/sh", ShellKind::Sh),
+            ("/usr/local/bin/zsh", ShellKind::Zsh),
+            ("dash", ShellKind::Dash),
+            (
+                r"C:\Program Files\PowerShell\7\pwsh.exe",
+                ShellKind::PowerShell,
+            ),
+            ("powershell.exe", ShellKind::PowerShell),
+            ("CMD.EXE", ShellKind::Cmd),
+            ("/usr/bin/fish", ShellKind::Other),
+        ] {
+            assert_eq!(ShellKind::of(program), kind, "{}", program);
+        }
+        assert_eq!(
+            Shell::new("pwsh").argv("Get-Date"),
+            [
+                "pwsh",
+                "-NoLogo",
+                "-NoProfile",
+                "-NonInteractive",
+                "-Command",
+                "Get-Date"
+            ]
+        );
+        assert_eq!(Shell::new("/bin/zsh").args, ["-c"]);
+        assert_eq!(
+            "PWSH".parse::<ShellChoice>().unwrap(),
+            ShellChoice::PowerShell
+        );
+        assert!("fish".parse::<ShellChoice>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_shells_are_posix() {
+        assert!(detect_shell(ShellChoice::Auto).kind.is_posix());
+        assert!(detect_shell(ShellChoice::Posix).kind.is_posix());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_user_shell() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let fish = dir.path().join("fish");
+        std::fs::write(&fish, "#!/bin/sh\n").unwrap();
+        assert!(resolve(fish.to_str().unwrap()).is_none(), "not executable");
+
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&fish, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let shell = resolve(fish.to_str().unwrap()).unwrap();
+        assert_eq!(shell.kind, ShellKind::Other);
+        assert!(resolve("command-stream-no-such-shell").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_preference() {
+        set_shell_preference(["command-stream-no-such-shell", "/usr/bin/fish", "sh"]);
+        let shell = find_shell(ShellChoice::Auto);
+        clear_shell_preference();
+        assert_eq!(shell.program, "sh");
+        assert_eq!(shell.args, ["-c"]);
+    }
+}
@@ -3,11 +3,29 @@
 
 use std::fmt;
 
+use crate::quote::needs_quoting;
+
+mod assignment;
+mod literal;
+mod real_shell;
+mod syntax;
+mod tokenizer;
+
+pub(crate) use assignment::is_assignment;
+pub use assignment::Assignment;
+pub use literal::literal_argv;
+pub(crate) use literal::{literal_argv_in, literal_word};
+pub(crate) use real_shell::substitution_len;
+pub use real_shell::{needs_real_shell, needs_real_shell_except_expansions};
+pub use syntax::{check_syntax, ParseError};
+pub use tokenizer::tokenize;
+
 /// Token types for the parser
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     Word(String),
     And,            // &&
+    Background,     // &
     Or,             // ||
     Semicolon,      // ;
     Pipe,           // |
@@ -16,14 +34,54 @@ pub enum TokenType {
     RedirectOut,    // >
     RedirectAppend, // >>
     RedirectIn,     // <
+    /// `2>`
+    RedirectErr,
+    /// `2>>`
+    RedirectErrAppend,
+    /// `&>`, stdout and stderr to the same file
+    RedirectBoth,
+    /// `&>>`
+    RedirectBothAppend,
+    /// `2>&1`, stderr to wherever stdout goes
+    RedirectErrToOut,
+    /// `>&2` or `1>&2`, stdout to wherever stderr goes
+    RedirectOutToErr,
     Eof,
 }
 
+impl TokenType {
+    /// Whether this is a redirect operator
+    pub fn is_redirect(&self) -> bool {
+        matches!(
+            self,
+            TokenType::RedirectOut
+                | TokenType::RedirectAppend
+                | TokenType::RedirectIn
+                | TokenType::RedirectErr
+                | TokenType::RedirectErrAppend
+                | TokenType::RedirectBoth
+                | TokenType::RedirectBothAppend
+                | TokenType::RedirectErrToOut
+                | TokenType::RedirectOutToErr
+        )
+    }
+
+    /// Whether this redirect duplicates a descriptor rather than naming a
+    /// file, so it takes no target word
+    pub fn is_duplication(&self) -> bool {
+        matches!(
+            self,
+            TokenType::RedirectErrToOut | TokenType::RedirectOutToErr
+        )
+    }
+}
+
 impl fmt::Display for TokenType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             TokenType::Word(s) => write!(f, "Word({})", s),
             TokenType::And => write!(f, "&&"),
+            TokenType::Background => write!(f, "&"),
             TokenType::Or => write!(f, "||"),
             TokenType::Semicolon => write!(f, ";"),
             TokenType::Pipe => write!(f, "|"),
@@ -32,23 +90,65 @@ impl fmt::Display for TokenType {
             TokenType::RedirectOut => write!(f, ">"),
             TokenType::RedirectAppend => write!(f, ">>"),
             TokenType::RedirectIn => write!(f, "<"),
+            TokenType::RedirectErr => write!(f, "2>"),
+            TokenType::RedirectErrAppend => write!(f, "2>>"),
+            TokenType::RedirectBoth => write!(f, "&>"),
+            TokenType::RedirectBothAppend => write!(f, "&>>"),
+            TokenType::RedirectErrToOut => write!(f, "2>&1"),
+            TokenType::RedirectOutToErr => write!(f, ">&2"),
             TokenType::Eof => write!(f, "EOF"),
         }
     }
 }
 
+/// Where a token appears in the command string, as a byte range
+///
+/// Nodes built in code rather than parsed have an empty span at 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The range `start..end`
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// The number of bytes covered
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Whether the span covers nothing
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The text of `source` the span covers, or `None` if it doesn't fit
+    pub fn slice<'a>(&self, source: &'a str) -> Option<&'a str> {
+        source.get(self.start..self.end)
+    }
+}
+
 /// A token with its type and original value
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub value: String,
+    pub span: Span,
 }
 
 /// Redirect information
 #[derive(Debug, Clone)]
 pub struct Redirect {
     pub redirect_type: TokenType,
+    /// The file, as written; empty for a
+    /// [duplication](TokenType::is_duplication)
     pub target: String,
+    /// From the operator to the end of the target
+    pub span: Span,
 }
 
 /// Parsed argument with quote information
@@ -57,13 +157,116 @@ pub struct ParsedArg {
     pub value: String,
     pub quoted: bool,
     pub quote_char: Option<char>,
+    pub span: Span,
+}
+
+impl ParsedArg {
+    /// An argument the shell will pass through as exactly `value`, quoted
+    /// only if it has to be
+    ///
+    /// ```
+    /// use command_stream::shell_parser::ParsedArg;
+    ///
+    /// assert_eq!(ParsedArg::literal("--color=never").to_string(), "--color=never");
+    /// assert_eq!(ParsedArg::literal("it's").to_string(), "'it'\\''s'");
+    /// assert_eq!(ParsedArg::literal("it's").unquoted(), "it's");
+    /// ```
+    pub fn literal(value: impl Into<String>) -> Self {
+        let value = value.into();
+        if needs_quoting(&value) {
+            ParsedArg {
+                value: value.replace('\'', "'\\''"),
+                quoted: true,
+                quote_char: Some('\''),
+                span: Span::default(),
+            }
+        } else {
+            ParsedArg {
+                value,
+                quoted: false,
+                quote_char: None,
+                span: Span::default(),
+            }
+        }
+    }
+
+    /// The argument as the shell would pass it to a program
+    ///
+    /// `value` only has surrounding quotes removed; this also removes quotes
+    /// inside the word (`--name="a b"`) and resolves backslash escapes, as
+    /// in `a\ b` or `"say \"hi\""`. Expansions such as `$VAR` are left as
+    /// written.
+    pub fn unquoted(&self) -> String {
+        match self.quote_char {
+            Some(q) => unquote_word(&format!("{q}{}{q}", self.value)),
+            None => unquote_word(&self.value),
+        }
+    }
+}
+
+impl fmt::Display for ParsedArg {
+    /// The argument as it is written in a command
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.quote_char {
+            Some(q) => write!(f, "{q}{}{q}", self.value),
+            None => f.write_str(&self.value),
+        }
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.redirect_type.is_duplication() {
+            return write!(f, "{}", self.redirect_type);
+        }
+        write!(f, "{} {}", self.redirect_type, self.target)
+    }
+}
+
+/// Remove shell quoting from a single word
+///
+/// Single quotes keep everything literally; inside double quotes a backslash
+/// only escapes `$`, `` ` ``, `"`, `\` and newline; outside quotes it escapes
+/// any character.
+pub fn unquote_word(word: &str) -> String {
+    let mut result = String::with_capacity(word.len());
+    let mut chars = word.chars().peekable();
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    result.push(next);
+                }
+            }
+            (Some('"'), '\\') => match chars.peek() {
+                Some(&next) if "$`\"\\\n".contains(next) => {
+                    result.push(next);
+                    chars.next();
+                }
+                _ => result.push('\\'),
+            },
+            _ => result.push(c),
+        }
+    }
+
+    result
 }
 
 /// Types of parsed commands
 #[derive(Debug, Clone)]
 pub enum ParsedCommand {
     /// A simple command with command name, arguments, and optional redirects
+    ///
+    /// `NAME=value` words in front of the command name are `assignments`,
+    /// which set variables for the command alone. A command of nothing but
+    /// assignments, which sets the shell's variables, keeps its first one
+    /// as `cmd`.
     Simple {
+        assignments: Vec<Assignment>,
         cmd: String,
         args: Vec<ParsedArg>,
         redirects: Vec<Redirect>,
@@ -77,164 +280,142 @@ pub enum ParsedCommand {
     Pipeline { commands: Vec<ParsedCommand> },
     /// A subshell (commands in parentheses)
     Subshell { command: Box<ParsedCommand> },
+    /// A command run in the background, followed by `&`
+    ///
+    /// `&` ends an `&&`/`||` list like `;` does, so `a && b & c` puts
+    /// `a && b` in the background and runs `c` right away.
+    Background { command: Box<ParsedCommand> },
+    /// A pipeline whose exit status is inverted, after `!`
+    Not { command: Box<ParsedCommand> },
+    /// A pipeline timed with `time`, which reports how long it took on
+    /// stderr; `posix` for `time -p`, the POSIX format
+    Time {
+        command: Box<ParsedCommand>,
+        posix: bool,
+    },
 }
 
-/// Tokenize a shell command string
-pub fn tokenize(command: &str) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    let chars: Vec<char> = command.chars().collect();
-    let mut i = 0;
-
-    while i < chars.len() {
-        // Skip whitespace
-        while i < chars.len() && chars[i].is_whitespace() {
-            i += 1;
-        }
-
-        if i >= chars.len() {
-            break;
-        }
+impl ParsedCommand {
+    /// The command as a shell command string
+    ///
+    /// Arguments keep the quoting they were parsed with, so parsing the
+    /// result gives back the same command. Spacing is normalized:
+    ///
+    /// ```
+    /// use command_stream::parse_shell_command;
+    ///
+    /// let parsed = parse_shell_command("(cd src&&ls -l)|wc -l>out.txt").unwrap();
+    /// assert_eq!(parsed.to_shell_string(), "(cd src && ls -l) | wc -l > out.txt");
+    /// ```
+    pub fn to_shell_string(&self) -> String {
+        self.to_string()
+    }
+}
 
-        // Check for operators
-        if chars[i] == '&' && i + 1 < chars.len() && chars[i + 1] == '&' {
-            tokens.push(Token {
-                token_type: TokenType::And,
-                value: "&&".to_string(),
-            });
-            i += 2;
-        } else if chars[i] == '|' && i + 1 < chars.len() && chars[i + 1] == '|' {
-            tokens.push(Token {
-                token_type: TokenType::Or,
-                value: "||".to_string(),
-            });
-            i += 2;
-        } else if chars[i] == '|' {
-            tokens.push(Token {
-                token_type: TokenType::Pipe,
-                value: "|".to_string(),
-            });
-            i += 1;
-        } else if chars[i] == ';' {
-            tokens.push(Token {
-                token_type: TokenType::Semicolon,
-                value: ";".to_string(),
-            });
-            i += 1;
-        } else if chars[i] == '(' {
-            tokens.push(Token {
-                token_type: TokenType::LParen,
-                value: "(".to_string(),
-            });
-            i += 1;
-        } else if chars[i] == ')' {
-            tokens.push(Token {
-                token_type: TokenType::RParen,
-                value: ")".to_string(),
-            });
-            i += 1;
-        } else if chars[i] == '>' && i + 1 < chars.len() && chars[i + 1] == '>' {
-            tokens.push(Token {
-                token_type: TokenType::RedirectAppend,
-                value: ">>".to_string(),
-            });
-            i += 2;
-        } else if chars[i] == '>' {
-            tokens.push(Token {
-                token_type: TokenType::RedirectOut,
-                value: ">".to_string(),
-            });
-            i += 1;
-        } else if chars[i] == '<' {
-            tokens.push(Token {
-                token_type: TokenType::RedirectIn,
-                value: "<".to_string(),
-            });
-            i += 1;
-        } else {
-            // Parse word (respecting quotes)
-            let mut word = String::new();
-            let mut in_quote = false;
-            let mut quote_char = ' ';
-
-            while i < chars.len() {
-                let c = chars[i];
-
-                if !in_quote {
-                    if c == '"' || c == '\'' {
-                        in_quote = true;
-                        quote_char = c;
-                        word.push(c);
-                        i += 1;
-                    } else if c.is_whitespace() || "&|;()<>".contains(c) {
-                        break;
-                    } else if c == '\\' && i + 1 < chars.len() {
-                        // Handle escape sequences
-                        word.push(c);
-                        i += 1;
-                        if i < chars.len() {
-                            word.push(chars[i]);
-                            i += 1;
-                        }
-                    } else {
-                        word.push(c);
-                        i += 1;
-                    }
-                } else {
-                    let prev_char = if i > 0 { Some(chars[i - 1]) } else { None };
-                    if c == quote_char && prev_char != Some('\\') {
-                        in_quote = false;
-                        word.push(c);
-                        i += 1;
-                    } else if c == '\\' && i + 1 < chars.len() {
-                        let next_char = chars[i + 1];
-                        if next_char == quote_char || next_char == '\\' {
-                            // Handle escaped quotes and backslashes inside quotes
-                            word.push(c);
-                            i += 1;
-                            if i < chars.len() {
-                                word.push(chars[i]);
-                                i += 1;
-                            }
-                        } else {
-                            word.push(c);
-                            i += 1;
+impl fmt::Display for ParsedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParsedCommand::Simple {
+                assignments,
+                cmd,
+                args,
+                redirects,
+            } => {
+                for assignment in assignments {
+                    write!(f, "{} ", assignment)?;
+                }
+                f.write_str(cmd)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                for redirect in redirects {
+                    write!(f, " {}", redirect)?;
+                }
+                Ok(())
+            }
+            ParsedCommand::Sequence {
+                commands,
+                operators,
+            } => {
+                for (i, command) in commands.iter().enumerate() {
+                    if i > 0 {
+                        let background =
+                            matches!(commands[i - 1], ParsedCommand::Background { .. });
+                        match operators.get(i - 1) {
+                            // The `&` already separates the commands
+                            Some(TokenType::Semicolon) | None if background => f.write_str(" ")?,
+                            Some(TokenType::Semicolon) | None => f.write_str("; ")?,
+                            Some(op) => write!(f, " {} ", op)?,
                         }
-                    } else {
-                        word.push(c);
-                        i += 1;
                     }
+                    write!(f, "{}", command)?;
                 }
+                Ok(())
             }
-
-            if !word.is_empty() {
-                tokens.push(Token {
-                    token_type: TokenType::Word(word.clone()),
-                    value: word,
-                });
+            ParsedCommand::Pipeline { commands } => {
+                for (i, command) in commands.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" | ")?;
+                    }
+                    write!(f, "{}", command)?;
+                }
+                Ok(())
             }
+            ParsedCommand::Subshell { command } => write!(f, "({})", command),
+            ParsedCommand::Background { command } => write!(f, "{} &", command),
+            ParsedCommand::Not { command } => write!(f, "! {}", command),
+            ParsedCommand::Time { command, posix } => match posix {
+                true => write!(f, "time -p {}", command),
+                false => write!(f, "time {}", command),
+            },
         }
     }
-
-    tokens.push(Token {
-        token_type: TokenType::Eof,
-        value: String::new(),
-    });
-
-    tokens
 }
 
 /// Shell command parser
 pub struct ShellParser {
     tokens: Vec<Token>,
     pos: usize,
+    /// The first syntax error found
+    error: Option<ParseError>,
+    /// Where the first comment starts; nothing after it is an error
+    comment: usize,
 }
 
 impl ShellParser {
     /// Create a new parser for the given command
     pub fn new(command: &str) -> Self {
+        let tokens = tokenize(command);
+        let comment = tokens
+            .iter()
+            .find(|token| matches!(&token.token_type, TokenType::Word(w) if w.starts_with('#')))
+            .map_or(command.len(), |token| token.span.start);
         ShellParser {
-            tokens: tokenize(command),
+            tokens,
             pos: 0,
+            error: syntax::unclosed(command),
+            comment,
+        }
+    }
+
+    /// Record a syntax error, unless there is one before it
+    fn fail(&mut self, span: Span, message: impl Into<String>) {
+        let earlier = self
+            .error
+            .as_ref()
+            .is_some_and(|error| error.span.start <= span.start);
+        if !earlier && span.start < self.comment {
+            self.error = Some(ParseError::new(span, message));
+        }
+    }
+
+    /// Record `token` as out of place, if it is an operator
+    fn unexpected(&mut self, token: &Token) {
+        if !matches!(
+            token.token_type,
+            TokenType::Word(_) | TokenType::Eof | TokenType::RParen
+        ) {
+            self.fail(token.span, format!("unexpected `{}`", token.value));
         }
     }
 
@@ -242,6 +423,7 @@ impl ShellParser {
         self.tokens.get(self.pos).cloned().unwrap_or(Token {
             token_type: TokenType::Eof,
             value: String::new(),
+            span: Span::default(),
         })
     }
 
@@ -252,14 +434,25 @@ impl ShellParser {
     }
 
     /// Parse the main command sequence
-    pub fn parse(&mut self) -> Option<ParsedCommand> {
-        self.parse_sequence()
+    ///
+    /// What the parser doesn't understand after a complete command, such as
+    /// the rest of a `case` after its first pattern, is dropped; input a
+    /// shell would reject is an error.
+    pub fn parse(&mut self) -> Result<ParsedCommand, ParseError> {
+        let parsed = self.parse_sequence();
+        match (parsed, self.error.take()) {
+            (_, Some(error)) => Err(error),
+            (Some(parsed), None) => Ok(parsed),
+            (None, None) => Err(ParseError::new(self.current().span, "expected a command")),
+        }
     }
 
-    /// Parse a sequence of commands connected by &&, ||, ;
+    /// Parse a sequence of commands connected by &&, ||, ; and &
     fn parse_sequence(&mut self) -> Option<ParsedCommand> {
         let mut commands = Vec::new();
         let mut operators = Vec::new();
+        // Where the `&&`/`||` list a `&` would put in the background starts
+        let mut list_start = 0;
 
         // Parse first command
         if let Some(cmd) = self.parse_pipeline() {
@@ -271,9 +464,37 @@ impl ShellParser {
             match &self.current().token_type {
                 TokenType::Eof | TokenType::RParen => break,
                 TokenType::And | TokenType::Or | TokenType::Semicolon => {
-                    let op = self.consume().token_type;
-                    operators.push(op);
-
+                    let op = self.consume();
+                    if op.token_type == TokenType::Semicolon {
+                        list_start = commands.len();
+                    }
+                    match self.parse_pipeline() {
+                        Some(cmd) => commands.push(cmd),
+                        None if op.token_type != TokenType::Semicolon => {
+                            self.fail(op.span, format!("expected a command after `{}`", op.value))
+                        }
+                        None => {}
+                    }
+                    operators.push(op.token_type);
+                }
+                TokenType::Background => {
+                    self.consume();
+                    if commands.len() > list_start {
+                        let list = commands.split_off(list_start);
+                        let list_operators = operators.split_off(list_start);
+                        commands.push(ParsedCommand::Background {
+                            command: Box::new(sequence(list, list_operators)?),
+                        });
+                    }
+                    list_start = commands.len();
+                    if matches!(
+                        self.current().token_type,
+                        TokenType::Eof | TokenType::RParen
+                    ) {
+                        break;
+                    }
+                    // The commands after it run right away, as after `;`
+                    operators.push(TokenType::Semicolon);
                     if let Some(cmd) = self.parse_pipeline() {
                         commands.push(cmd);
                     }
@@ -282,32 +503,48 @@ impl ShellParser {
             }
         }
 
-        if commands.len() == 1 && operators.is_empty() {
-            return commands.into_iter().next();
-        }
-
-        if commands.is_empty() {
-            return None;
-        }
-
-        Some(ParsedCommand::Sequence {
-            commands,
-            operators,
-        })
+        sequence(commands, operators)
     }
 
-    /// Parse a pipeline (commands connected by |)
+    /// Parse a pipeline (commands connected by |), with the `!` and `time`
+    /// in front of it
     fn parse_pipeline(&mut self) -> Option<ParsedCommand> {
+        let prefix = self.current();
+        match &prefix.token_type {
+            TokenType::Word(word) if word == "!" => {
+                self.consume();
+                let command = self.parse_prefixed(&prefix)?;
+                return Some(ParsedCommand::Not {
+                    command: Box::new(command),
+                });
+            }
+            TokenType::Word(word) if word == "time" => {
+                self.consume();
+                let posix = matches!(&self.current().token_type, TokenType::Word(w) if w == "-p");
+                if posix {
+                    self.consume();
+                }
+                let command = self.parse_prefixed(&prefix)?;
+                return Some(ParsedCommand::Time {
+                    command: Box::new(command),
+                    posix,
+                });
+            }
+            _ => {}
+        }
+
         let mut commands = Vec::new();
 
-        if let Some(cmd) = self.parse_command() {
-            commands.push(cmd);
+        match self.parse_command() {
+            Some(cmd) => commands.push(cmd),
+            None => self.unexpected(&self.current()),
         }
 
         while matches!(self.current().token_type, TokenType::Pipe) {
-            self.consume();
-            if let Some(cmd) = self.parse_command() {
-                commands.push(cmd);
+            let pipe = self.consume();
+            match self.parse_command() {
+                Some(cmd) => commands.push(cmd),
+                None => self.fail(pipe.span, "expected a command after `|`"),
             }
         }
 
@@ -322,15 +559,27 @@ impl ShellParser {
         Some(ParsedCommand::Pipeline { commands })
     }
 
+    /// Parse the pipeline after `prefix`, which needs one
+    fn parse_prefixed(&mut self, prefix: &Token) -> Option<ParsedCommand> {
+        let command = self.parse_pipeline();
+        if command.is_none() {
+            let message = format!("expected a command after `{}`", prefix.value);
+            self.fail(prefix.span, message);
+        }
+        command
+    }
+
     /// Parse a single command or subshell
     fn parse_command(&mut self) -> Option<ParsedCommand> {
         // Check for subshell
         if matches!(self.current().token_type, TokenType::LParen) {
-            self.consume(); // consume (
+            let open = self.consume();
             let subshell = self.parse_sequence();
 
             if matches!(self.current().token_type, TokenType::RParen) {
                 self.consume(); // consume )
+            } else {
+                self.fail(open.span, "unclosed `(`");
             }
 
             return subshell.map(|cmd| ParsedCommand::Subshell {
@@ -348,20 +597,39 @@ impl ShellParser {
         let mut redirects = Vec::new();
 
         loop {
-            match &self.current().token_type {
+            let token = self.current();
+            match &token.token_type {
                 TokenType::Eof => break,
                 TokenType::Word(w) => {
-                    words.push(w.clone());
+                    words.push((w.clone(), token.span));
                     self.consume();
                 }
-                TokenType::RedirectOut | TokenType::RedirectAppend | TokenType::RedirectIn => {
-                    let redirect_type = self.consume().token_type;
-                    if let TokenType::Word(target) = &self.current().token_type {
-                        redirects.push(Redirect {
-                            redirect_type,
-                            target: target.clone(),
-                        });
-                        self.consume();
+                redirect if redirect.is_duplication() => {
+                    self.consume();
+                    redirects.push(Redirect {
+                        redirect_type: token.token_type,
+                        target: String::new(),
+                        span: token.span,
+                    });
+                }
+                redirect if redirect.is_redirect() => {
+                    self.consume();
+                    let target = self.current();
+                    match &target.token_type {
+                        TokenType::Word(word) => {
+                            redirects.push(Redirect {
+                                redirect_type: token.token_type,
+                                target: word.clone(),
+                                span: Span::new(token.span.start, target.span.end),
+                            });
+                            self.consume();
+                        }
+                        // A process substitution, `<(ls)`, is the shell's
+                        TokenType::LParen => {}
+                        _ => self.fail(
+                            token.span,
+                            format!("expected a file after `{}`", token.value),
+                        ),
                     }
                 }
                 _ => break,
@@ -372,30 +640,25 @@ impl ShellParser {
             return None;
         }
 
-        let cmd = words.remove(0);
+        let assigning = words
+            .iter()
+            .map_while(|(word, span)| Assignment::parse(word, *span))
+            .collect::<Vec<_>>();
+        let assignments = match assigning.len() < words.len() {
+            true => {
+                words.drain(..assigning.len());
+                assigning
+            }
+            false => Vec::new(),
+        };
+        let (cmd, _) = words.remove(0);
         let args: Vec<ParsedArg> = words
             .into_iter()
-            .map(|word| {
-                // Remove quotes if present
-                if (word.starts_with('"') && word.ends_with('"'))
-                    || (word.starts_with('\'') && word.ends_with('\''))
-                {
-                    ParsedArg {
-                        value: word[1..word.len() - 1].to_string(),
-                        quoted: true,
-                        quote_char: Some(word.chars().next().unwrap()),
-                    }
-                } else {
-                    ParsedArg {
-                        value: word,
-                        quoted: false,
-                        quote_char: None,
-                    }
-                }
-            })
+            .map(|(word, span)| parsed_arg(word, span))
             .collect();
 
         Some(ParsedCommand::Simple {
+            assignments,
             cmd,
             args,
             redirects,
@@ -403,37 +666,50 @@ impl ShellParser {
     }
 }
 
-/// Parse a shell command with support for &&, ||, ;, and ()
-pub fn parse_shell_command(command: &str) -> Option<ParsedCommand> {
-    let mut parser = ShellParser::new(command);
-    parser.parse()
+/// `word` as an argument, with the quotes around it removed if it is
+/// quoted as a whole
+fn parsed_arg(word: String, span: Span) -> ParsedArg {
+    if word.len() >= 2
+        && ((word.starts_with('"') && word.ends_with('"'))
+            || (word.starts_with('\'') && word.ends_with('\'')))
+    {
+        ParsedArg {
+            value: word[1..word.len() - 1].to_string(),
+            quoted: true,
+            quote_char: word.chars().next(),
+            span,
+        }
+    } else {
+        ParsedArg {
+            value: word,
+            quoted: false,
+            quote_char: None,
+            span,
+        }
+    }
 }
 
-/// Check if a command needs shell features we don't handle
-pub fn needs_real_shell(command: &str) -> bool {
-    // Check for features we don't handle yet
-    let unsupported = [
-        "`",   // Command substitution
-        "$(",  // Command substitution
-        "${",  // Variable expansion
-        "~",   // Home expansion (at start of word)
-        "*",   // Glob patterns
-        "?",   // Glob patterns
-        "[",   // Glob patterns
-        "2>",  // stderr redirection
-        "&>",  // Combined redirection
-        ">&",  // File descriptor duplication
-        "<<",  // Here documents
-        "<<<", // Here strings
-    ];
-
-    for feature in &unsupported {
-        if command.contains(feature) {
-            return true;
-        }
+/// `commands` joined by `operators`, or the command itself if there is
+/// only one
+fn sequence(mut commands: Vec<ParsedCommand>, operators: Vec<TokenType>) -> Option<ParsedCommand> {
+    if commands.len() == 1 && operators.is_empty() {
+        return commands.pop();
+    }
+
+    if commands.is_empty() {
+        return None;
     }
 
-    false
+    Some(ParsedCommand::Sequence {
+        commands,
+        operators,
+    })
+}
+
+/// Parse a shell command with support for &&, ||, ;, & and ()
+pub fn parse_shell_command(command: &str) -> Result<ParsedCommand, ParseError> {
+    let mut parser = ShellParser::new(command);
+    parser.parse()
 }
 
 #[cfg(test)]
@@ -474,6 +750,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_token_spans() {
+        let command = "echo 'héllo world' && cat<in.txt";
+        let tokens = tokenize(command);
+        let text: Vec<&str> = tokens
+            .iter()
+            .map(|t| t.span.slice(command).unwrap())
+            .collect();
+        assert_eq!(
+            text,
+            ["echo", "'héllo world'", "&&", "cat", "<", "in.txt", ""]
+        );
+        assert_eq!(tokens[1].span, Span::new(5, 19));
+    }
+
+    #[test]
+    fn test_parsed_spans() {
+        let command = "grep -n \"a b\" > out.txt";
+        let ParsedCommand::Simple {
+            args, redirects, ..
+        } = parse_shell_command(command).unwrap()
+        else {
+            panic!("Expected Simple command");
+        };
+        assert_eq!(args[1].span.slice(command), Some("\"a b\""));
+        assert_eq!(redirects[0].span.slice(command), Some("> out.txt"));
+    }
+
     #[test]
     fn test_parse_simple_command() {
         let cmd = parse_shell_command("echo hello world").unwrap();
@@ -517,12 +821,20 @@ mod tests {
     }
 
     #[test]
-    fn test_needs_real_shell() {
-        assert!(needs_real_shell("echo $(date)"));
-        assert!(needs_real_shell("ls *.txt"));
-        assert!(needs_real_shell("echo ${HOME}"));
-        assert!(!needs_real_shell("echo hello"));
-        assert!(!needs_real_shell("ls | grep foo"));
+    fn test_tokenize_lone_ampersand_terminates() {
+        let tokens = tokenize("sleep 1 & echo done");
+        assert!(matches!(tokens.last().unwrap().token_type, TokenType::Eof));
+    }
+
+    #[test]
+    fn test_unquote_word() {
+        assert_eq!(unquote_word("plain"), "plain");
+        assert_eq!(unquote_word("--name=\"a b\""), "--name=a b");
+        assert_eq!(unquote_word("a\\ b"), "a b");
+        assert_eq!(unquote_word("\"say \\\"hi\\\"\""), "say \"hi\"");
+        assert_eq!(unquote_word("'\\n stays'"), "\\n stays");
+        assert_eq!(unquote_word("\"\\n stays\""), "\\n stays");
+        assert_eq!(unquote_word("'it'\\''s'"), "it's");
     }
 
     #[test]
@@ -533,6 +845,7 @@ mod tests {
                 cmd,
                 args,
                 redirects,
+                ..
             } => {
                 assert_eq!(cmd, "echo");
                 assert_eq!(args.len(), 1);
@@ -0,0 +1,56 @@
+//! `NAME=value` words in front of a command
+
+use std::fmt;
+
+use super::{parsed_arg, ParsedArg, Span};
+
+/// A variable set for one command, as `RUST_LOG=debug` in
+/// `RUST_LOG=debug cargo run`
+///
+/// ```
+/// use command_stream::{parse_shell_command, ParsedCommand};
+///
+/// let parsed = parse_shell_command("A=1 B='x y' make").unwrap();
+/// let ParsedCommand::Simple { assignments, cmd, .. } = &parsed else {
+///     unreachable!()
+/// };
+/// assert_eq!(cmd, "make");
+/// assert_eq!(assignments[1].name, "B");
+/// assert_eq!(assignments[1].value.unquoted(), "x y");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub name: String,
+    /// The value as written, to be expanded like an argument but not split
+    /// or globbed
+    pub value: ParsedArg,
+}
+
+impl Assignment {
+    /// The assignment `word` is, if it starts with a variable name and `=`
+    pub(super) fn parse(word: &str, span: Span) -> Option<Self> {
+        let (name, value) = word.split_once('=')?;
+        is_assignment(word).then(|| Assignment {
+            name: name.to_string(),
+            value: parsed_arg(
+                value.to_string(),
+                Span::new(span.start + name.len() + 1, span.end),
+            ),
+        })
+    }
+}
+
+impl fmt::Display for Assignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+/// Whether `word` assigns to a variable: a name of letters, digits and
+/// underscores, not starting with a digit, then `=`
+pub(crate) fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
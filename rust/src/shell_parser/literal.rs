@@ -0,0 +1,105 @@
+//! Commands that can run without a shell, and their argv
+
+use std::collections::HashMap;
+
+use super::{ParsedArg, ParsedCommand};
+
+/// Return the argv of a command that can be executed directly, without a shell
+///
+/// Only a `Simple` command without redirects or assignments in front of it
+/// qualifies, and every word must be a literal: unquoted words free of
+/// quotes, escapes, `$`, backticks and assignments, or quoted words whose
+/// content the shell would pass through unchanged. Anything else returns
+/// `None` so the caller falls back to a shell.
+/// A `~` starting an unquoted word is expanded to the home directory, as
+/// the shell would (see [`expand_tilde`](crate::expand::expand_tilde)).
+pub fn literal_argv(parsed: &ParsedCommand) -> Option<Vec<String>> {
+    literal_argv_in(parsed, None)
+}
+
+/// [`literal_argv`] for a command whose environment is `env` on top of
+/// this process's, which the home directory comes from
+pub(crate) fn literal_argv_in(
+    parsed: &ParsedCommand,
+    env: Option<&HashMap<String, String>>,
+) -> Option<Vec<String>> {
+    let (cmd, args) = match parsed {
+        ParsedCommand::Simple {
+            assignments,
+            cmd,
+            args,
+            redirects,
+        } if redirects.is_empty() && assignments.is_empty() => (cmd, args),
+        _ => return None,
+    };
+
+    if cmd.contains('=') {
+        return None;
+    }
+    let cmd = ParsedArg {
+        value: cmd.clone(),
+        quoted: false,
+        quote_char: None,
+        span: Default::default(),
+    };
+    std::iter::once(&cmd)
+        .chain(args)
+        .map(|arg| literal_word(arg, env))
+        .collect()
+}
+
+/// What `arg` stands for, if the shell would pass it on as written but for
+/// its quotes and a leading `~`, the home directory in `env` or this
+/// process's
+pub(crate) fn literal_word(
+    arg: &ParsedArg,
+    env: Option<&HashMap<String, String>>,
+) -> Option<String> {
+    let word = &arg.value;
+    let literal = match arg.quote_char {
+        None => {
+            !word.is_empty()
+                && !word.starts_with('#')
+                && !word.contains(['\'', '"', '\\', '$', '`'])
+        }
+        Some('\'') => !word.contains('\''),
+        Some(_) => !word.contains(['"', '\\', '$', '`']),
+    };
+    literal.then(
+        || match (arg.quote_char, crate::expand::tilde_prefix(word, env)) {
+            (None, Some((home, len))) => home + &word[len..],
+            _ => word.clone(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shell_parser::parse_shell_command;
+
+    #[test]
+    fn test_literal_argv() {
+        let argv = |cmd: &str| literal_argv(&parse_shell_command(cmd).unwrap());
+
+        assert_eq!(
+            argv("git commit -m 'hello world'"),
+            Some(vec![
+                "git".to_string(),
+                "commit".to_string(),
+                "-m".to_string(),
+                "hello world".to_string()
+            ])
+        );
+        assert_eq!(
+            argv("echo \"a b\""),
+            Some(vec!["echo".into(), "a b".into()])
+        );
+        assert_eq!(argv("echo $HOME"), None);
+        assert_eq!(argv("echo \"$HOME\""), None);
+        assert_eq!(argv("echo a\\ b"), None);
+        assert_eq!(argv("FOO=bar env"), None);
+        assert_eq!(argv("echo hi > out.txt"), None);
+        assert_eq!(argv("echo a && echo b"), None);
+    }
+}
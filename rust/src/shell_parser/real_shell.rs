@@ -0,0 +1,93 @@
+//! Commands that need a real shell: the features the parser leaves to it
+
+use super::{tokenize, TokenType};
+
+/// The length of the `$(...)` at the start of `chars`, to the end of
+/// `chars` if it isn't closed
+pub(crate) fn substitution_len(chars: &[char]) -> usize {
+    let (mut depth, mut quote, mut i) = (0, None, 1);
+    while i < chars.len() {
+        let c = chars[i];
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => i += 1,
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(_), '"') => quote = None,
+            (Some(_), _) => {}
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            (None, _) => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// Check if a command needs shell features we don't handle
+///
+/// Glob characters only count outside quotes: `echo '*'` needs no shell.
+pub fn needs_real_shell(command: &str) -> bool {
+    crate::expand::has_glob(command)
+        || command.contains("${")
+        || command.contains("$(")
+        || needs_real_shell_except_expansions(command)
+}
+
+/// [`needs_real_shell`] for callers that expand globs and variables
+/// themselves, with [`expand`](crate::expand)
+pub fn needs_real_shell_except_expansions(command: &str) -> bool {
+    // Check for features we don't handle yet
+    let unsupported = [
+        "`",   // Command substitution
+        "$((", // Arithmetic expansion
+        "|&",  // Piping stderr too
+        "<<",  // Here documents
+        "<<<", // Here strings
+        "<&",  // Input descriptor duplication
+        "<>",  // Read-write redirection
+        ">|",  // Clobbering redirection
+    ];
+
+    for feature in &unsupported {
+        if command.contains(feature) {
+            return true;
+        }
+    }
+
+    let tokens = tokenize(command);
+    tokens.windows(2).any(|pair| {
+        let adjacent = pair[0].span.end == pair[1].span.start;
+        match (&pair[0].token_type, &pair[1].token_type) {
+            // Duplications of other descriptors (`>&3`)
+            (previous, TokenType::Background) if previous.is_redirect() => adjacent,
+            // Redirects of other descriptors (`3> file`, `2>&12`)
+            (TokenType::Word(word), next) if next.is_redirect() => {
+                adjacent && word.bytes().all(|b| b.is_ascii_digit())
+            }
+            (previous, TokenType::Word(_)) if previous.is_duplication() => adjacent,
+            _ => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_real_shell() {
+        assert!(needs_real_shell("echo $(date)"));
+        assert!(needs_real_shell("ls *.txt"));
+        assert!(needs_real_shell("echo ${HOME}"));
+        assert!(!needs_real_shell("echo hello"));
+        assert!(!needs_real_shell("ls | grep foo"));
+        assert!(!needs_real_shell("sleep 1 &"));
+        assert!(!needs_real_shell("true && echo ok"));
+    }
+}
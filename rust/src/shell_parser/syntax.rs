@@ -0,0 +1,118 @@
+//! Syntax errors in commands: what is wrong, and where
+
+use std::fmt;
+
+use super::{needs_real_shell_except_expansions, parse_shell_command, Span};
+use crate::Error;
+
+/// Why a command couldn't be parsed, and where in it
+///
+/// ```
+/// use command_stream::parse_shell_command;
+///
+/// let error = parse_shell_command("make &&").unwrap_err();
+/// assert_eq!(error.span.start, 5);
+/// assert_eq!(error.to_string(), "expected a command after `&&` at byte 5");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    /// The token or quote the error is about, as a byte range of the command
+    pub span: Span,
+}
+
+impl ParseError {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        ParseError {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.span.start)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::ParseError(error.to_string())
+    }
+}
+
+/// Check `command` for syntax errors a shell would reject it for, such as
+/// an unterminated quote or a `&&` with nothing after it
+///
+/// Empty commands pass, and so do commands using syntax the parser leaves
+/// to the shell, like here documents.
+pub fn check_syntax(command: &str) -> Result<(), ParseError> {
+    if command.trim().is_empty() || needs_real_shell_except_expansions(command) {
+        return Ok(());
+    }
+    parse_shell_command(command).map(|_| ())
+}
+
+/// The innermost quote or `$(` of `command` that is never closed
+///
+/// Comments are skipped, so an apostrophe in one doesn't count.
+pub(super) fn unclosed(command: &str) -> Option<ParseError> {
+    // What is open, with where: a quote character, `$` for a command
+    // substitution, or `(` for parentheses inside one
+    let mut open: Vec<(char, usize)> = Vec::new();
+    let mut word_start = true;
+    let mut chars = command.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let inside = open.last().map(|&(opened, _)| opened);
+        match (inside, c) {
+            (Some('\''), '\'') | (Some('"' | '`'), '"' | '`') if inside == Some(c) => {
+                open.pop();
+            }
+            (Some('\''), _) => {}
+            (_, '\\') => {
+                chars.next();
+            }
+            (_, '$') if chars.next_if(|&(_, next)| next == '(').is_some() => {
+                open.push(('$', i));
+            }
+            (Some('"' | '`'), _) => {}
+            (_, '\'' | '"' | '`') => open.push((c, i)),
+            (Some('$' | '('), '(') => open.push(('(', i)),
+            (Some('$' | '('), ')') => {
+                open.pop();
+            }
+            (_, '#') if word_start => while chars.next_if(|&(_, next)| next != '\n').is_some() {},
+            _ => {}
+        }
+        word_start = c.is_whitespace() || ";&|()<>".contains(c);
+    }
+    let (opened, offset) = open.pop()?;
+    let (message, len) = match opened {
+        '$' => ("unclosed `$(`".to_string(), 2),
+        '(' => ("unclosed `(`".to_string(), 1),
+        quote => (format!("unterminated `{}` quote", quote), 1),
+    };
+    Some(ParseError::new(Span::new(offset, offset + len), message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unclosed_finds_the_innermost_opening() {
+        let start = |command: &str| unclosed(command).map(|error| error.span.start);
+        assert_eq!(start("echo 'it''s'"), None);
+        assert_eq!(start("echo \"a \\\" b\" $(ls (x)) # don't"), None);
+        assert_eq!(start("echo 'oops"), Some(5));
+        assert_eq!(start("echo \"$(date"), Some(6));
+        assert_eq!(start("echo $(printf ')"), Some(14));
+        assert_eq!(
+            unclosed("echo \"a").unwrap().message,
+            "unterminated `\"` quote"
+        );
+    }
+}
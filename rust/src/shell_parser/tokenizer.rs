@@ -0,0 +1,154 @@
+//! Splitting a command string into words and operators
+
+use super::{substitution_len, Span, Token, TokenType};
+
+/// Tokenize a shell command string
+pub fn tokenize(command: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = command.chars().collect();
+    // Byte offset of each char, plus the end of the string
+    let offsets: Vec<usize> = command
+        .char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(command.len()))
+        .collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        // Skip whitespace
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+
+        if i >= chars.len() {
+            break;
+        }
+
+        let start = i;
+        let next = chars.get(i + 1).copied();
+        // Whether `text` comes right after the current character
+        let follows = |text: &str| {
+            text.chars()
+                .enumerate()
+                .all(|(k, c)| chars.get(i + 1 + k) == Some(&c))
+        };
+        let operator = match (chars[i], next) {
+            // A digit starting a word and followed by `>` names the
+            // descriptor to redirect
+            ('2', Some('>')) if follows(">&1") => Some((TokenType::RedirectErrToOut, 4)),
+            ('2', Some('>')) if follows(">>") => Some((TokenType::RedirectErrAppend, 3)),
+            ('2', Some('>')) => Some((TokenType::RedirectErr, 2)),
+            ('1', Some('>')) if follows(">&2") => Some((TokenType::RedirectOutToErr, 4)),
+            ('1', Some('>')) if follows(">>") => Some((TokenType::RedirectAppend, 3)),
+            ('1', Some('>')) => Some((TokenType::RedirectOut, 2)),
+            ('>', Some('&')) if follows("&2") => Some((TokenType::RedirectOutToErr, 3)),
+            ('&', Some('>')) if follows(">>") => Some((TokenType::RedirectBothAppend, 3)),
+            ('&', Some('>')) => Some((TokenType::RedirectBoth, 2)),
+            ('&', Some('&')) => Some((TokenType::And, 2)),
+            ('&', _) => Some((TokenType::Background, 1)),
+            ('|', Some('|')) => Some((TokenType::Or, 2)),
+            ('|', _) => Some((TokenType::Pipe, 1)),
+            (';', _) => Some((TokenType::Semicolon, 1)),
+            ('(', _) => Some((TokenType::LParen, 1)),
+            (')', _) => Some((TokenType::RParen, 1)),
+            ('>', Some('>')) => Some((TokenType::RedirectAppend, 2)),
+            ('>', _) => Some((TokenType::RedirectOut, 1)),
+            ('<', _) => Some((TokenType::RedirectIn, 1)),
+            _ => None,
+        };
+
+        if let Some((token_type, len)) = operator {
+            i += len;
+            tokens.push(Token {
+                token_type,
+                value: chars[start..i].iter().collect(),
+                span: Span::new(offsets[start], offsets[i]),
+            });
+        } else {
+            // Parse word (respecting quotes)
+            let mut word = String::new();
+            let mut in_quote = false;
+            let mut quote_char = ' ';
+
+            while i < chars.len() {
+                let c = chars[i];
+
+                // A command substitution is part of the word, whatever it
+                // contains
+                if c == '$' && chars.get(i + 1) == Some(&'(') && (!in_quote || quote_char == '"') {
+                    let len = substitution_len(&chars[i..]);
+                    word.extend(&chars[i..i + len]);
+                    i += len;
+                    continue;
+                }
+
+                if !in_quote {
+                    if c == '"' || c == '\'' {
+                        in_quote = true;
+                        quote_char = c;
+                        word.push(c);
+                        i += 1;
+                    } else if c.is_whitespace() || "&|;()<>".contains(c) {
+                        break;
+                    } else if c == '\\' && i + 1 < chars.len() {
+                        // Handle escape sequences
+                        word.push(c);
+                        i += 1;
+                        if i < chars.len() {
+                            word.push(chars[i]);
+                            i += 1;
+                        }
+                    } else {
+                        word.push(c);
+                        i += 1;
+                    }
+                } else {
+                    let prev_char = if i > 0 { Some(chars[i - 1]) } else { None };
+                    if c == quote_char && prev_char != Some('\\') {
+                        in_quote = false;
+                        word.push(c);
+                        i += 1;
+                    } else if c == '\\' && i + 1 < chars.len() {
+                        let next_char = chars[i + 1];
+                        if next_char == quote_char || next_char == '\\' {
+                            // Handle escaped quotes and backslashes inside quotes
+                            word.push(c);
+                            i += 1;
+                            if i < chars.len() {
+                                word.push(chars[i]);
+                                i += 1;
+                            }
+                        } else {
+                            word.push(c);
+                            i += 1;
+                        }
+                    } else {
+                        word.push(c);
+                        i += 1;
+                    }
+                }
+            }
+
+            if word.is_empty() {
+                // Every character that ends a word starts an operator, but
+                // make sure the scan always advances
+                word.push(chars[i]);
+                i += 1;
+            }
+
+            tokens.push(Token {
+                token_type: TokenType::Word(word.clone()),
+                value: word,
+                span: Span::new(offsets[start], offsets[i]),
+            });
+        }
+    }
+
+    tokens.push(Token {
+        token_type: TokenType::Eof,
+        value: String::new(),
+        span: Span::new(command.len(), command.len()),
+    });
+
+    tokens
+}
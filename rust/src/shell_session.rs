@@ -0,0 +1,500 @@
+//! Persistent shell sessions
+//!
+//! A [`ShellSession`] keeps one long-lived shell process (bash/sh or
+//! PowerShell) and runs commands over its stdin, instead of spawning a new
+//! shell per command. This removes the per-command spawn cost for tools that
+//! issue many small commands, and shell state (working directory, exported
+//! variables, functions) carries over from one command to the next.
+//!
+//! Each command is followed by sentinel lines on stdout and stderr; the
+//! session reads output up to those sentinels and takes the exit code from
+//! the stdout sentinel.
+//!
+//! `source file` and `. file` are the session's own builtin: the file runs
+//! in the shell itself, relative to its current directory, and a missing
+//! file fails the command without ending the session.
+//!
+//! Traps set with the shell's own `trap` builtin behave as in a script:
+//! `trap '...' EXIT` runs when the session is [closed](ShellSession::close),
+//! and traps for other signals run when [`signal`](ShellSession::signal)
+//! forwards that signal to the shell. Rust code can register its own hooks
+//! with [`on_cleanup`](ShellSession::on_cleanup).
+//!
+//! ## Usage
+//!
+//! ```rust,no_run
+//! use command_stream::ShellSession;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let session = ShellSession::start().await?;
+//!
+//!     session.run("cd /tmp && export GREETING=hello").await?;
+//!     let result = session.run("echo $GREETING from $(pwd)").await?;
+//!     assert_eq!(result.stdout, "hello from /tmp\n");
+//!
+//!     session.close().await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+use crate::state::TrackedChild;
+use crate::trace::trace_lazy;
+use crate::{literal_argv, parse_shell_command, CommandResult, Error, Result};
+
+/// Prefix of the sentinel lines that terminate each command's output.
+const SENTINEL_PREFIX: &str = "__COMMAND_STREAM_END_";
+
+/// Counter used to make every command's sentinel unique.
+static NEXT_SENTINEL: AtomicU64 = AtomicU64::new(1);
+
+/// The shell dialect a session speaks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionShell {
+    /// A POSIX shell (bash, sh, ...) given by program name or path
+    Posix(String),
+    /// PowerShell (`pwsh` or `powershell.exe`) given by program name or path
+    PowerShell(String),
+}
+
+impl SessionShell {
+    /// Pick the session shell for this platform
+    ///
+    /// Prefers bash (commands that fail to parse don't end a bash session),
+    /// then `sh`. On Windows, prefers `pwsh`, then `powershell.exe`.
+    pub fn detect() -> Self {
+        if cfg!(windows) {
+            if which::which("pwsh").is_ok() {
+                return SessionShell::PowerShell("pwsh".to_string());
+            }
+            return SessionShell::PowerShell("powershell.exe".to_string());
+        }
+
+        if which::which("bash").is_ok() {
+            SessionShell::Posix("bash".to_string())
+        } else {
+            SessionShell::Posix("/bin/sh".to_string())
+        }
+    }
+
+    fn program(&self) -> &str {
+        match self {
+            SessionShell::Posix(program) | SessionShell::PowerShell(program) => program,
+        }
+    }
+
+    fn args(&self) -> Vec<&'static str> {
+        match self {
+            SessionShell::Posix(program) if program.ends_with("bash") => {
+                vec!["--noprofile", "--norc"]
+            }
+            SessionShell::Posix(_) => vec![],
+            SessionShell::PowerShell(_) => vec!["-NoLogo", "-NoProfile", "-Command", "-"],
+        }
+    }
+
+    /// Build the script text that runs `command` and then prints the
+    /// sentinels for `token`.
+    fn frame(&self, command: &str, token: u64) -> String {
+        match self {
+            SessionShell::Posix(_) => format!(
+                "eval {} </dev/null\n\
+                 __cs_status=$?\n\
+                 printf '\\n{prefix}{token}:%d\\n' \"$__cs_status\"\n\
+                 printf '\\n{prefix}{token}:\\n' >&2\n",
+                crate::quote::quote(command),
+                prefix = SENTINEL_PREFIX,
+            ),
+            SessionShell::PowerShell(_) => format!(
+                "$global:LASTEXITCODE = 0; \
+                 Invoke-Expression {}; \
+                 $__cs_status = if ($?) {{ $global:LASTEXITCODE }} \
+                 elseif ($global:LASTEXITCODE) {{ $global:LASTEXITCODE }} else {{ 1 }}; \
+                 [Console]::Out.Write(\"`n{prefix}{token}:$__cs_status`n\"); \
+                 [Console]::Error.Write(\"`n{prefix}{token}:`n\")\n",
+                powershell_quote(command),
+                prefix = SENTINEL_PREFIX,
+            ),
+        }
+    }
+
+    /// Build the command that runs `command` as the session's own builtin,
+    /// if it is `source` or `.` with one file and nothing else.
+    fn builtin(&self, command: &str) -> Option<String> {
+        let argv = literal_argv(&parse_shell_command(command).ok()?)?;
+        match &argv[..] {
+            [name, path] if name == "source" || name == "." => {
+                Some(self.source_command(Path::new(path)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Build the command that sources `path` into the session.
+    fn source_command(&self, path: &Path) -> String {
+        let mut path = path.to_string_lossy().into_owned();
+        if Path::new(&path).is_relative() {
+            path = format!("./{}", path);
+        }
+        match self {
+            // `.` on a missing file ends a POSIX shell, so check first.
+            SessionShell::Posix(_) => {
+                let quoted = crate::quote::quote(&path);
+                format!(
+                    "if [ -r {quoted} ]; then . {quoted}; else \
+                     printf 'source: %s: No such file or directory\\n' {quoted} >&2; false; fi"
+                )
+            }
+            SessionShell::PowerShell(_) => format!(". {}", powershell_quote(&path)),
+        }
+    }
+}
+
+/// Quote a value as a single-quoted PowerShell string literal.
+fn powershell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// The pipes of the running shell process
+struct SessionIo {
+    stdin: ChildStdin,
+    stdout: OutputPipe<ChildStdout>,
+    stderr: OutputPipe<ChildStderr>,
+    /// Whether a command's script was being written when the run was
+    /// dropped, leaving the shell with part of it
+    writing: bool,
+}
+
+/// One of the shell's output pipes, with what a dropped run left unread
+struct OutputPipe<R> {
+    reader: BufReader<R>,
+    /// The start of a line that was being read
+    line: Vec<u8>,
+    /// The sentinel of the command whose output is read up to next
+    pending: Option<String>,
+}
+
+impl<R: tokio::io::AsyncRead + Unpin> OutputPipe<R> {
+    fn new(reader: R) -> Self {
+        OutputPipe {
+            reader: BufReader::new(reader),
+            line: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// Read up to the pending sentinel; see [`read_until_sentinel`]
+    ///
+    /// Lines read so far are kept if the future is dropped, so that the
+    /// next read still finds the sentinel.
+    async fn read_pending(&mut self) -> std::io::Result<(String, String)> {
+        let Some(marker) = self.pending.clone() else {
+            return Ok(Default::default());
+        };
+        let output = read_until_sentinel(&mut self.reader, &mut self.line, &marker).await?;
+        self.pending = None;
+        Ok(output)
+    }
+}
+
+/// A hook registered with [`ShellSession::on_cleanup`]
+type CleanupHook = Box<dyn FnOnce() + Send + 'static>;
+
+/// A long-lived shell process that runs commands one after another
+///
+/// Commands are serialized: concurrent calls to [`run`](Self::run) on a shared
+/// session wait for each other. Each command's stdin is empty (`/dev/null`),
+/// so a command cannot consume the session's own command stream.
+///
+/// Dropping a session kills the shell without running its `EXIT` trap; call
+/// [`close`](Self::close) for an orderly shutdown.
+pub struct ShellSession {
+    shell: SessionShell,
+    pid: Option<u32>,
+    _tracked: Option<TrackedChild>,
+    child: Mutex<Child>,
+    io: Mutex<Option<SessionIo>>,
+    cleanup: std::sync::Mutex<Vec<CleanupHook>>,
+}
+
+impl ShellSession {
+    /// Start a session using the platform's default shell
+    pub async fn start() -> Result<Self> {
+        Self::start_with(SessionShell::detect(), None).await
+    }
+
+    /// Start a session with an explicit shell and optional initial directory
+    pub async fn start_with(shell: SessionShell, cwd: Option<&Path>) -> Result<Self> {
+        let mut cmd = Command::new(shell.program());
+        cmd.args(shell.args())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        if let Some(cwd) = crate::resolve_spawn_cwd(cwd.map(Path::to_path_buf).as_ref()) {
+            cmd.current_dir(cwd);
+        }
+
+        let mut child = cmd.spawn()?;
+        trace_lazy("ShellSession", || {
+            format!("Started {:?} session (pid {:?})", shell, child.id())
+        });
+
+        let io = SessionIo {
+            stdin: child.stdin.take().ok_or_else(session_closed)?,
+            stdout: OutputPipe::new(child.stdout.take().ok_or_else(session_closed)?),
+            stderr: OutputPipe::new(child.stderr.take().ok_or_else(session_closed)?),
+            writing: false,
+        };
+
+        Ok(ShellSession {
+            shell,
+            pid: child.id(),
+            _tracked: crate::state::track_spawned(&child),
+            child: Mutex::new(child),
+            io: Mutex::new(Some(io)),
+            cleanup: std::sync::Mutex::new(Vec::new()),
+        })
+    }
+
+    /// The shell this session runs
+    pub fn shell(&self) -> &SessionShell {
+        &self.shell
+    }
+
+    /// Run a command in the session and wait for its result
+    ///
+    /// `source file` and `. file` run as [`source`](Self::source) does, in
+    /// any shell the session speaks. Returns an error if the shell process
+    /// has gone away (for example because the command ran `exit`); the
+    /// session is unusable afterwards.
+    ///
+    /// Dropping the returned future, as a timeout does, leaves the command
+    /// running in the shell. The next command waits for it to finish, and
+    /// its output is discarded rather than returned with the next
+    /// command's. A run dropped while its command was still being sent
+    /// leaves the shell with part of it, and the session is closed.
+    pub async fn run(&self, command: &str) -> Result<CommandResult> {
+        let mut guard = self.io.lock().await;
+        let io = guard.as_mut().ok_or_else(session_closed)?;
+        if io.writing {
+            trace_lazy("ShellSession", || "a command was sent in part".to_string());
+            *guard = None;
+            return Err(session_closed());
+        }
+        if let Err(e) = read_pending(io).await {
+            trace_lazy("ShellSession", || format!("session ended: {}", e));
+            *guard = None;
+            return Err(session_closed());
+        }
+
+        let token = NEXT_SENTINEL.fetch_add(1, Ordering::SeqCst);
+        trace_lazy("ShellSession", || format!("run #{}: {}", token, command));
+
+        let script = match self.shell.builtin(command) {
+            Some(builtin) => self.shell.frame(&builtin, token),
+            None => self.shell.frame(command, token),
+        };
+        io.writing = true;
+        if let Err(e) = write_script(&mut io.stdin, &script).await {
+            *guard = None;
+            return Err(e);
+        }
+        io.writing = false;
+
+        let marker = format!("{}{}:", SENTINEL_PREFIX, token);
+        io.stdout.pending = Some(marker.clone());
+        io.stderr.pending = Some(marker);
+        let ((stdout, status), (stderr, _)) = match read_pending(io).await {
+            Ok(output) => output,
+            Err(e) => {
+                trace_lazy("ShellSession", || format!("session ended: {}", e));
+                *guard = None;
+                return Err(session_closed());
+            }
+        };
+
+        Ok(CommandResult {
+            stdout,
+            stderr,
+            code: status.trim().parse().unwrap_or(-1),
+            ..Default::default()
+        })
+    }
+
+    /// Run the script at `path` in the session itself, like `source` or `.`
+    ///
+    /// Assignments, exports, function definitions and `cd`s made by the
+    /// script stay in effect for later commands, as configuration files and
+    /// activation scripts expect. A relative path is resolved against the
+    /// session's current directory rather than looked up on `PATH`. A missing
+    /// or unreadable file gives exit code 1 and leaves the session running.
+    pub async fn source(&self, path: impl AsRef<Path>) -> Result<CommandResult> {
+        let command = self.shell.source_command(path.as_ref());
+        self.run(&command).await
+    }
+
+    /// Check whether the shell process is still accepting commands
+    pub async fn is_alive(&self) -> bool {
+        if self.io.lock().await.is_none() {
+            return false;
+        }
+        matches!(self.child.lock().await.try_wait(), Ok(None))
+    }
+
+    /// Register a hook to run when the session ends
+    ///
+    /// Hooks run once, in reverse registration order, after the shell has
+    /// exited in [`close`](Self::close), or when the session is dropped.
+    pub fn on_cleanup<F>(&self, hook: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.cleanup
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Box::new(hook));
+    }
+
+    /// Forward a signal (for example `"SIGTERM"` or `"SIGUSR1"`) to the shell
+    ///
+    /// A `trap` the session set for the signal runs right away if the shell is
+    /// idle, or once the current command finishes; its output is returned with
+    /// the next command's output. Without a trap, the signal's default action
+    /// applies, which usually ends the session. Does nothing on non-Unix
+    /// platforms.
+    pub async fn signal(&self, signal: &str) -> Result<()> {
+        if self.io.lock().await.is_none() {
+            return Err(session_closed());
+        }
+        let pid = self.pid.ok_or_else(session_closed)?;
+        trace_lazy("ShellSession", || {
+            format!("forwarding {} to pid {}", signal, pid)
+        });
+        crate::stream::send_signal_to_process(pid, signal);
+        Ok(())
+    }
+
+    /// End the session, letting the shell exit and waiting for it
+    ///
+    /// The shell runs its `EXIT` trap, if any, before exiting. The returned
+    /// result holds the output produced during shutdown and the shell's exit
+    /// code. Cleanup hooks run after the shell has exited.
+    pub async fn close(self) -> Result<CommandResult> {
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        if let Some(mut io) = self.io.lock().await.take() {
+            // Closing stdin makes the shell exit at end of input.
+            let _ = io.stdin.shutdown().await;
+            drop(io.stdin);
+            let _ = tokio::join!(
+                io.stdout.reader.read_to_end(&mut stdout),
+                io.stderr.reader.read_to_end(&mut stderr),
+            );
+        }
+
+        let status = self.child.lock().await.wait().await;
+        self.run_cleanup_hooks();
+        let status = status?;
+        trace_lazy("ShellSession", || format!("closed with {}", status));
+
+        Ok(CommandResult::from_exit_status(
+            String::from_utf8_lossy(&stdout),
+            String::from_utf8_lossy(&stderr),
+            status,
+        ))
+    }
+
+    fn run_cleanup_hooks(&self) {
+        let hooks = std::mem::take(&mut *self.cleanup.lock().unwrap_or_else(|e| e.into_inner()));
+        for hook in hooks.into_iter().rev() {
+            hook();
+        }
+    }
+}
+
+impl Drop for ShellSession {
+    fn drop(&mut self) {
+        if self.io.try_lock().is_ok_and(|io| io.is_some()) {
+            crate::trace::warn_not_closed(
+                "ShellSession",
+                "shell session dropped without close(); the shell is killed without running its EXIT trap",
+            );
+        }
+        self.run_cleanup_hooks();
+    }
+}
+
+impl std::fmt::Debug for ShellSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShellSession")
+            .field("shell", &self.shell)
+            .finish()
+    }
+}
+
+fn session_closed() -> Error {
+    Error::Io(std::io::Error::new(
+        std::io::ErrorKind::BrokenPipe,
+        "shell session is closed",
+    ))
+}
+
+async fn write_script(stdin: &mut ChildStdin, script: &str) -> Result<()> {
+    stdin.write_all(script.as_bytes()).await?;
+    stdin.flush().await?;
+    Ok(())
+}
+
+/// Read stdout and stderr up to their pending sentinels, returning the
+/// output and sentinel remainder of each
+async fn read_pending(io: &mut SessionIo) -> std::io::Result<((String, String), (String, String))> {
+    let (stdout, stderr) = tokio::join!(io.stdout.read_pending(), io.stderr.read_pending());
+    Ok((stdout?, stderr?))
+}
+
+/// Read lines until one starts with `marker`, returning the output before it
+/// and the remainder of the sentinel line.
+///
+/// `line` holds the start of a line read before, and is left holding the
+/// start of one if the future is dropped. The sentinel is always printed
+/// after a newline, so that output without a trailing newline still ends
+/// before the sentinel line; that extra newline is removed from the
+/// returned output.
+async fn read_until_sentinel<R>(
+    reader: &mut R,
+    line: &mut Vec<u8>,
+    marker: &str,
+) -> std::io::Result<(String, String)>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut output = Vec::new();
+    loop {
+        if reader.read_until(b'\n', line).await? == 0 && line.is_empty() {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let complete = std::mem::take(line);
+        let text = String::from_utf8_lossy(&complete);
+        if let Some(rest) = text.strip_prefix(marker) {
+            if output.ends_with(b"\r\n") {
+                output.truncate(output.len() - 2);
+            } else if output.ends_with(b"\n") {
+                output.pop();
+            }
+            return Ok((
+                String::from_utf8_lossy(&output).into_owned(),
+                rest.to_string(),
+            ));
+        }
+        output.extend_from_slice(&complete);
+    }
+}
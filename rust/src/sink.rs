@@ -0,0 +1,120 @@
+//! Forwarding command output line by line
+//!
+//! A [`LineSink`] receives each line of a command's stdout or stderr as it
+//! arrives, without its line ending. Attach one with
+//! [`ProcessRunner::pipe_stdout_to`](crate::ProcessRunner::pipe_stdout_to)
+//! or [`pipe_stderr_to`](crate::ProcessRunner::pipe_stderr_to):
+//!
+//! ```rust,no_run
+//! use command_stream::{ProcessRunner, RunOptions};
+//! use tokio::sync::mpsc;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let (tx, mut rx) = mpsc::channel(100);
+//! tokio::spawn(async move {
+//!     while let Some(line) = rx.recv().await {
+//!         println!("build: {}", line);
+//!     }
+//! });
+//! ProcessRunner::new("cargo build", RunOptions::default().quiet())
+//!     .pipe_stderr_to(tx)
+//!     .run()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Where forwarded lines go
+#[derive(Clone)]
+pub enum LineSink {
+    /// Send each line on a channel, waiting while it is full. Lines are
+    /// dropped once the receiver is gone.
+    Channel(mpsc::Sender<String>),
+    /// Call a function with each line
+    Callback(Arc<dyn Fn(String) + Send + Sync>),
+}
+
+impl LineSink {
+    /// A sink that calls `callback` with each line
+    pub fn callback(callback: impl Fn(String) + Send + Sync + 'static) -> Self {
+        LineSink::Callback(Arc::new(callback))
+    }
+
+    /// Forward one line
+    pub(crate) async fn send(&self, line: String) {
+        match self {
+            LineSink::Channel(tx) => {
+                let _ = tx.send(line).await;
+            }
+            LineSink::Callback(callback) => callback(line),
+        }
+    }
+}
+
+impl From<mpsc::Sender<String>> for LineSink {
+    fn from(tx: mpsc::Sender<String>) -> Self {
+        LineSink::Channel(tx)
+    }
+}
+
+impl fmt::Debug for LineSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineSink::Channel(tx) => f.debug_tuple("Channel").field(tx).finish(),
+            LineSink::Callback(_) => f.write_str("Callback(..)"),
+        }
+    }
+}
+
+/// Cuts output that arrives in arbitrary chunks into lines
+#[derive(Default)]
+pub(crate) struct LineSplitter {
+    partial: String,
+}
+
+impl LineSplitter {
+    /// The complete lines in `text`, without line endings; a trailing
+    /// partial line is kept for the next chunk
+    pub(crate) fn push(&mut self, text: &str) -> Vec<String> {
+        self.partial.push_str(text);
+        let mut lines = Vec::new();
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            lines.push(line.trim_end_matches(['\n', '\r']).to_string());
+        }
+        lines
+    }
+
+    /// What is left of an unterminated last line
+    pub(crate) fn finish(&mut self) -> Option<String> {
+        Some(std::mem::take(&mut self.partial)).filter(|line| !line.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splitter_yields_whole_lines() {
+        let mut splitter = LineSplitter::default();
+        assert_eq!(splitter.push("one\r\ntw"), ["one"]);
+        assert_eq!(splitter.push("o\n\nthr"), ["two", ""]);
+        assert!(splitter.push("ee").is_empty());
+        assert_eq!(splitter.finish().as_deref(), Some("three"));
+        assert_eq!(splitter.finish(), None);
+    }
+
+    #[tokio::test]
+    async fn test_channel_sink_ignores_closed_receiver() {
+        let (tx, rx) = mpsc::channel(1);
+        let sink = LineSink::from(tx);
+        sink.send("kept".to_string()).await;
+        drop(rx);
+        sink.send("dropped".to_string()).await;
+    }
+}
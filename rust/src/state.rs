@@ -3,9 +3,10 @@
 //! This module handles signal handlers, process tracking, and cleanup,
 //! similar to the JavaScript $.state.mjs module.
 
-use std::collections::HashSet;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Once};
+use std::time::Duration;
 use tokio::sync::RwLock;
 
 use crate::trace::trace_lazy;
@@ -25,6 +26,8 @@ pub struct ShellSettings {
     pub nounset: bool,
     /// Disable filename globbing (set -f)
     pub noglob: bool,
+    /// Let `**` in a glob match across directories (shopt -s globstar)
+    pub globstar: bool,
     /// Export all variables (set -a)
     pub allexport: bool,
 }
@@ -50,6 +53,7 @@ impl ShellSettings {
             "x" | "xtrace" => self.xtrace = value,
             "u" | "nounset" => self.nounset = value,
             "f" | "noglob" => self.noglob = value,
+            "globstar" => self.globstar = value,
             "a" | "allexport" => self.allexport = value,
             "o pipefail" | "pipefail" => self.pipefail = value,
             _ => {
@@ -71,12 +75,16 @@ impl ShellSettings {
     }
 }
 
+/// How long terminated children get to exit on `SIGTERM` before `SIGKILL`
+pub const CHILD_TERMINATE_GRACE: Duration = Duration::from_millis(200);
+
 /// Global state for the command-stream library
 pub struct GlobalState {
     /// Current shell settings
     shell_settings: RwLock<ShellSettings>,
-    /// Set of active process runner IDs
-    active_runners: RwLock<HashSet<u64>>,
+    /// Active process runners by ID. A plain mutex so a runner can
+    /// unregister itself when dropped.
+    active_runners: std::sync::Mutex<HashMap<u64, RunnerInfo>>,
     /// Counter for generating runner IDs
     next_runner_id: std::sync::atomic::AtomicU64,
     /// Whether signal handlers are installed
@@ -85,6 +93,17 @@ pub struct GlobalState {
     virtual_commands_enabled: AtomicBool,
     /// Initial working directory
     initial_cwd: RwLock<Option<std::path::PathBuf>>,
+    /// PIDs of spawned child processes that are still running, keyed by
+    /// tracking ID. A plain mutex so exit, panic and signal handlers can use it.
+    tracked_children: std::sync::Mutex<HashMap<u64, u32>>,
+    /// Counter for generating child tracking IDs
+    next_child_id: AtomicU64,
+    /// Whether the process exits after a termination signal has been handled
+    exit_on_signal: AtomicBool,
+    /// Recorded command history (disabled unless given a capacity)
+    history: std::sync::Mutex<crate::history::History>,
+    /// Background jobs started with `&` or [`spawn_job`](crate::jobs::spawn_job)
+    jobs: std::sync::Mutex<crate::jobs::JobTable>,
 }
 
 impl Default for GlobalState {
@@ -100,11 +119,16 @@ impl GlobalState {
 
         GlobalState {
             shell_settings: RwLock::new(ShellSettings::new()),
-            active_runners: RwLock::new(HashSet::new()),
+            active_runners: std::sync::Mutex::new(HashMap::new()),
             next_runner_id: std::sync::atomic::AtomicU64::new(1),
             signal_handlers_installed: AtomicBool::new(false),
             virtual_commands_enabled: AtomicBool::new(true),
             initial_cwd: RwLock::new(initial_cwd),
+            tracked_children: std::sync::Mutex::new(HashMap::new()),
+            next_child_id: AtomicU64::new(1),
+            exit_on_signal: AtomicBool::new(true),
+            history: std::sync::Mutex::new(Default::default()),
+            jobs: std::sync::Mutex::new(Default::default()),
         }
     }
 
@@ -139,10 +163,21 @@ impl GlobalState {
 
     /// Register a new active runner and return its ID
     pub async fn register_runner(&self) -> u64 {
+        self.register_runner_info(String::new(), None)
+    }
+
+    /// Register a runner for `command` and return its ID
+    fn register_runner_info(&self, command: String, pid: Option<u32>) -> u64 {
         let id = self
             .next_runner_id
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-        self.active_runners.write().await.insert(id);
+        let info = RunnerInfo {
+            id,
+            pid,
+            command,
+            started_at: chrono::Utc::now(),
+        };
+        self.lock_active_runners().insert(id, info);
 
         trace_lazy("GlobalState", || format!("Registered runner {}", id));
 
@@ -151,14 +186,31 @@ impl GlobalState {
 
     /// Unregister an active runner
     pub async fn unregister_runner(&self, id: u64) {
-        self.active_runners.write().await.remove(&id);
+        self.unregister_runner_now(id);
+    }
+
+    fn unregister_runner_now(&self, id: u64) {
+        self.lock_active_runners().remove(&id);
 
         trace_lazy("GlobalState", || format!("Unregistered runner {}", id));
     }
 
     /// Get the count of active runners
     pub async fn active_runner_count(&self) -> usize {
-        self.active_runners.read().await.len()
+        self.lock_active_runners().len()
+    }
+
+    /// Describe the active runners, oldest first
+    pub async fn list_active(&self) -> Vec<RunnerInfo> {
+        let mut runners: Vec<RunnerInfo> = self.lock_active_runners().values().cloned().collect();
+        runners.sort_by_key(|info| info.id);
+        runners
+    }
+
+    fn lock_active_runners(&self) -> std::sync::MutexGuard<'_, HashMap<u64, RunnerInfo>> {
+        self.active_runners
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
     }
 
     /// Check if signal handlers are installed
@@ -172,6 +224,87 @@ impl GlobalState {
             .store(installed, Ordering::SeqCst);
     }
 
+    /// Track a spawned child process until the returned guard is dropped
+    ///
+    /// Once [`install_cleanup_handlers`] has run, tracked children are
+    /// terminated when the host process exits, panics on the main thread, or
+    /// receives `SIGINT`/`SIGTERM`/`SIGHUP`. Only children of [`global_state`]
+    /// are covered by those handlers.
+    pub fn track_child(self: &Arc<Self>, pid: u32) -> TrackedChild {
+        let id = self.next_child_id.fetch_add(1, Ordering::SeqCst);
+        self.lock_tracked_children().insert(id, pid);
+        crate::instrument::active_children(1);
+        trace_lazy("GlobalState", || {
+            format!("Tracking child {} (pid {})", id, pid)
+        });
+        TrackedChild {
+            state: Arc::clone(self),
+            id,
+        }
+    }
+
+    /// PIDs of the tracked children that are still running
+    pub fn tracked_child_pids(&self) -> Vec<u32> {
+        self.lock_tracked_children().values().copied().collect()
+    }
+
+    /// Terminate every tracked child: `SIGTERM` first, then `SIGKILL` for
+    /// those still alive after `grace`. Returns how many children were
+    /// signalled.
+    ///
+    /// This blocks the calling thread for up to `grace`. Signals are only
+    /// delivered on Unix.
+    pub fn terminate_tracked_children(&self, grace: Duration) -> usize {
+        let pids: Vec<u32> = self
+            .lock_tracked_children()
+            .drain()
+            .map(|(_, pid)| pid)
+            .collect();
+        if pids.is_empty() {
+            return 0;
+        }
+        trace_lazy("GlobalState", || {
+            format!("Terminating tracked children: {:?}", pids)
+        });
+
+        for &pid in &pids {
+            crate::stream::send_signal_to_process(pid, "SIGTERM");
+        }
+        let deadline = std::time::Instant::now() + grace;
+        while pids.iter().any(|&pid| process_exists(pid)) && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        for &pid in pids.iter().filter(|&&pid| process_exists(pid)) {
+            crate::stream::send_signal_to_process(pid, "SIGKILL");
+        }
+        pids.len()
+    }
+
+    /// Choose whether the process exits (with `128 + signal`) after a
+    /// termination signal has been handled; on by default. Turn it off when
+    /// the host application handles `SIGINT`/`SIGTERM` itself and only wants
+    /// the children cleaned up.
+    pub fn set_exit_on_signal(&self, exit: bool) {
+        self.exit_on_signal.store(exit, Ordering::SeqCst);
+    }
+
+    /// Run `f` with exclusive access to the command history
+    pub(crate) fn with_history<R>(&self, f: impl FnOnce(&mut crate::history::History) -> R) -> R {
+        f(&mut self.history.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Run `f` with exclusive access to the job table
+    pub(crate) fn with_jobs<R>(&self, f: impl FnOnce(&mut crate::jobs::JobTable) -> R) -> R {
+        f(&mut self.jobs.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn lock_tracked_children(&self) -> std::sync::MutexGuard<'_, HashMap<u64, u32>> {
+        // A panic while holding the lock must not disable cleanup.
+        self.tracked_children
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
     /// Check if virtual commands are enabled
     pub fn are_virtual_commands_enabled(&self) -> bool {
         self.virtual_commands_enabled.load(Ordering::SeqCst)
@@ -200,7 +333,13 @@ impl GlobalState {
         *self.shell_settings.write().await = ShellSettings::new();
 
         // Clear active runners
-        self.active_runners.write().await.clear();
+        self.lock_active_runners().clear();
+
+        // Stop recording history
+        self.with_history(|history| *history = Default::default());
+
+        // Kill and forget background jobs
+        self.with_jobs(|jobs| jobs.clear());
 
         // Reset virtual commands flag
         self.virtual_commands_enabled.store(true, Ordering::SeqCst);
@@ -221,6 +360,178 @@ impl GlobalState {
     }
 }
 
+/// A running command as listed by [`GlobalState::list_active`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunnerInfo {
+    /// Runner ID, unique within the process
+    pub id: u64,
+    /// OS process ID, once the command has spawned a process
+    pub pid: Option<u32>,
+    /// The command being run
+    pub command: String,
+    /// When the runner started
+    pub started_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Registration of an active runner that is removed when dropped
+pub(crate) struct RunnerRegistration {
+    state: Arc<GlobalState>,
+    id: u64,
+}
+
+impl RunnerRegistration {
+    /// Register a runner for `command` in the global state
+    pub(crate) fn new(command: &str) -> Self {
+        let state = global_state();
+        let id = state.register_runner_info(command.to_string(), None);
+        RunnerRegistration { state, id }
+    }
+
+    /// The id the runner is registered under
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Record the PID of the process the runner spawned
+    pub(crate) fn set_pid(&self, pid: Option<u32>) {
+        if let Some(info) = self.state.lock_active_runners().get_mut(&self.id) {
+            info.pid = pid;
+        }
+    }
+}
+
+impl Drop for RunnerRegistration {
+    fn drop(&mut self) {
+        self.state.unregister_runner_now(self.id);
+    }
+}
+
+/// Registration of a running child in [`GlobalState::track_child`]
+///
+/// Dropping it stops tracking the child; drop it once the child has exited.
+pub struct TrackedChild {
+    state: Arc<GlobalState>,
+    id: u64,
+}
+
+impl std::fmt::Debug for TrackedChild {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrackedChild")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl Drop for TrackedChild {
+    fn drop(&mut self) {
+        self.state.lock_tracked_children().remove(&self.id);
+        crate::instrument::active_children(-1);
+    }
+}
+
+/// Check whether a process with this PID still exists
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+    nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid as i32), None).is_ok()
+}
+
+#[cfg(not(unix))]
+fn process_exists(_pid: u32) -> bool {
+    false
+}
+
+static CLEANUP_HANDLERS: Once = Once::new();
+
+/// Install the handlers that terminate tracked children when the process
+/// ends
+///
+/// Runs once per process, and only when called: the library spawns and
+/// tracks children without taking over how the host application shuts
+/// down, and an application that wants its children cleaned up calls this
+/// at startup, as the `command-stream` CLI does. It registers an `atexit`
+/// handler (Unix, which also removes `mktemp` files when
+/// [`crate::temp::set_auto_cleanup`] is on), a panic hook that runs the
+/// hook installed before it and then cleans up when the main thread
+/// panics, and, when called inside a Tokio runtime, a task that cleans up
+/// on `SIGINT`/`SIGTERM`/`SIGHUP` (Ctrl+C on other platforms) and then
+/// exits unless [`GlobalState::set_exit_on_signal`] turned that off.
+pub fn install_cleanup_handlers() {
+    CLEANUP_HANDLERS.call_once(|| {
+        global_state().set_signal_handlers_installed(true);
+
+        #[cfg(unix)]
+        {
+            extern "C" fn cleanup_at_exit() {
+                global_state().terminate_tracked_children(CHILD_TERMINATE_GRACE);
+                crate::temp::cleanup_at_exit();
+            }
+            // SAFETY: `cleanup_at_exit` is a plain function that stays valid
+            // for the life of the process.
+            unsafe {
+                libc::atexit(cleanup_at_exit);
+            }
+        }
+
+        let previous_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            previous_hook(info);
+            // Panics on other threads are often caught (e.g. by Tokio), so
+            // only a main-thread panic means the process is going down.
+            if std::thread::current().name() == Some("main") {
+                global_state().terminate_tracked_children(CHILD_TERMINATE_GRACE);
+            }
+        }));
+
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async {
+                let Some(signal) = wait_for_termination_signal().await else {
+                    return;
+                };
+                let state = global_state();
+                trace_lazy("GlobalState", || {
+                    format!("Received signal {}, cleaning up children", signal)
+                });
+                let cleanup = Arc::clone(&state);
+                let _ = tokio::task::spawn_blocking(move || {
+                    cleanup.terminate_tracked_children(CHILD_TERMINATE_GRACE)
+                })
+                .await;
+                if state.exit_on_signal.load(Ordering::SeqCst) {
+                    std::process::exit(128 + signal);
+                }
+            });
+        }
+
+        trace_lazy("GlobalState", || "Cleanup handlers installed".to_string());
+    });
+}
+
+/// Track a child the library just spawned
+pub(crate) fn track_spawned(child: &tokio::process::Child) -> Option<TrackedChild> {
+    child.id().map(|pid| global_state().track_child(pid))
+}
+
+/// Wait for the first termination signal and return its number
+#[cfg(unix)]
+async fn wait_for_termination_signal() -> Option<i32> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt()).ok()?;
+    let mut terminate = signal(SignalKind::terminate()).ok()?;
+    let mut hangup = signal(SignalKind::hangup()).ok()?;
+    tokio::select! {
+        _ = interrupt.recv() => Some(libc::SIGINT),
+        _ = terminate.recv() => Some(libc::SIGTERM),
+        _ = hangup.recv() => Some(libc::SIGHUP),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_termination_signal() -> Option<i32> {
+    tokio::signal::ctrl_c().await.ok()?;
+    Some(2)
+}
+
 /// Global state singleton
 static GLOBAL_STATE: std::sync::OnceLock<Arc<GlobalState>> = std::sync::OnceLock::new();
 
@@ -241,6 +552,11 @@ pub async fn get_shell_settings() -> ShellSettings {
     global_state().get_shell_settings().await
 }
 
+/// Describe the commands currently running through the library
+pub async fn list_active() -> Vec<RunnerInfo> {
+    global_state().list_active().await
+}
+
 /// Enable a shell option globally
 pub async fn set_shell_option(option: &str) {
     global_state().enable_shell_option(option).await;
@@ -296,6 +612,35 @@ mod tests {
         assert_eq!(state.active_runner_count().await, 0);
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_terminate_tracked_children() {
+        let state = Arc::new(GlobalState::new());
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+
+        let tracked = state.track_child(child.id());
+        assert_eq!(state.tracked_child_pids(), vec![child.id()]);
+
+        assert_eq!(state.terminate_tracked_children(CHILD_TERMINATE_GRACE), 1);
+        assert!(state.tracked_child_pids().is_empty());
+
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGTERM));
+        drop(tracked);
+    }
+
+    #[test]
+    fn test_tracked_child_guard_untracks_on_drop() {
+        let state = Arc::new(GlobalState::new());
+        let tracked = state.track_child(u32::MAX);
+        assert_eq!(state.tracked_child_pids().len(), 1);
+        drop(tracked);
+        assert!(state.tracked_child_pids().is_empty());
+    }
+
     #[tokio::test]
     async fn test_global_state_virtual_commands() {
         let state = GlobalState::new();
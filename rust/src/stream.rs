@@ -54,16 +54,20 @@
 //! }
 //! ```
 
+use bytes::{Bytes, BytesMut};
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::process::Stdio;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::BufReader;
-use tokio::process::Command;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::sync::mpsc;
 
-use crate::trace::trace_lazy;
-use crate::{CommandResult, Result};
+use crate::confirm::{confirm, ConfirmationGate};
+use crate::policy;
+use crate::shell::{find_shell, ShellChoice};
+use crate::trace::{trace_lazy, trace_runner};
+use crate::{CancellationToken, CommandResult, ExecutionPolicy, Result};
 
 /// Default grace period (in milliseconds) to keep draining the stdio pipes
 /// after the process has exited before aborting any lingering readers. Mirrors
@@ -73,13 +77,24 @@ const DEFAULT_EXIT_PUMP_GRACE_MS: u64 = 100;
 /// Default signal used to stop a process when no explicit signal is given.
 const DEFAULT_KILL_SIGNAL: &str = "SIGTERM";
 
+/// Exit code reported for a command an execution policy or confirmation gate
+/// rejected, the code a shell uses for a command it can't execute
+const POLICY_EXIT_CODE: i32 = 126;
+
+/// Capacity of the reusable read buffer used by the stdio readers.
+const READ_BUFFER_CAPACITY: usize = 8192;
+
 /// A chunk of output from a streaming process
+///
+/// Data chunks are reference-counted [`Bytes`], so cloning a chunk to fan it
+/// out to several consumers (mirroring, capture, events) does not copy the
+/// underlying buffer.
 #[derive(Debug, Clone)]
 pub enum OutputChunk {
     /// Stdout data
-    Stdout(Vec<u8>),
+    Stdout(Bytes),
     /// Stderr data
-    Stderr(Vec<u8>),
+    Stderr(Bytes),
     /// Process exit code
     Exit(i32),
 }
@@ -92,8 +107,17 @@ pub struct StreamingRunner {
     stdin_content: Option<String>,
     kill_signal: String,
     exit_pump_grace_ms: u64,
+    cancel: Option<CancellationToken>,
+    mirror_stdout: Option<MirrorSink>,
+    mirror_stderr: Option<MirrorSink>,
+    line_buffered: bool,
+    policy: Option<Arc<ExecutionPolicy>>,
+    confirm: Option<Arc<ConfirmationGate>>,
 }
 
+/// A writer that receives a copy of streamed output
+pub type MirrorSink = Box<dyn AsyncWrite + Send + Unpin>;
+
 impl StreamingRunner {
     /// Create a new streaming runner
     pub fn new(command: impl Into<String>) -> Self {
@@ -104,6 +128,12 @@ impl StreamingRunner {
             stdin_content: None,
             kill_signal: DEFAULT_KILL_SIGNAL.to_string(),
             exit_pump_grace_ms: DEFAULT_EXIT_PUMP_GRACE_MS,
+            cancel: None,
+            mirror_stdout: None,
+            mirror_stderr: None,
+            line_buffered: false,
+            policy: None,
+            confirm: None,
         }
     }
 
@@ -119,6 +149,73 @@ impl StreamingRunner {
         self
     }
 
+    /// Set one environment variable, keeping any set before
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Take the working directory, environment, string stdin, mirroring,
+    /// cancellation token, policy and confirmation gate from `options`
+    ///
+    /// Lets one [`RunOptions`](crate::RunOptions) value configure both kinds
+    /// of runner. Options that only apply to
+    /// [`ProcessRunner`](crate::ProcessRunner) are ignored.
+    pub fn with_options(mut self, options: &crate::RunOptions) -> Self {
+        if let Some(cwd) = &options.cwd {
+            self.cwd = Some(cwd.clone());
+        }
+        if let Some(env) = &options.env {
+            self.env
+                .get_or_insert_with(HashMap::new)
+                .extend(env.clone());
+        }
+        if let crate::StdinOption::Content(content) = &options.stdin {
+            self.stdin_content = Some(content.clone());
+        }
+        if let Some(token) = &options.cancel {
+            self.cancel = Some(token.clone());
+        }
+        self.line_buffered = options.line_buffered;
+        if let Some(policy) = &options.policy {
+            self.policy = Some(policy.clone());
+        }
+        if let Some(gate) = &options.confirm {
+            self.confirm = Some(gate.clone());
+        }
+        self.mirror(options.mirror)
+    }
+
+    /// Ask `gate` before starting the command if it looks destructive
+    ///
+    /// A declined command is reported like one rejected by a
+    /// [`policy`](Self::policy).
+    pub fn confirm(mut self, gate: impl Into<Arc<ConfirmationGate>>) -> Self {
+        self.confirm = Some(gate.into());
+        self
+    }
+
+    /// Check the command against `policy` before it starts
+    ///
+    /// A rejected command isn't spawned: [`collect`](Self::collect) returns
+    /// [`Error::PolicyViolation`](crate::Error::PolicyViolation), and
+    /// [`stream`](Self::stream) yields the reason on stderr and exit code
+    /// 126.
+    pub fn policy(mut self, policy: ExecutionPolicy) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Ask the command to flush its output per line, so chunks arrive as
+    /// lines are written (see
+    /// [`RunOptions::line_buffered`](crate::RunOptions::line_buffered))
+    pub fn line_buffered(mut self, enabled: bool) -> Self {
+        self.line_buffered = enabled;
+        self
+    }
+
     /// Set stdin content
     pub fn stdin(mut self, content: impl Into<String>) -> Self {
         self.stdin_content = Some(content.into());
@@ -144,25 +241,101 @@ impl StreamingRunner {
         self
     }
 
+    /// Stop the process with the configured kill signal when `token` is
+    /// cancelled; [`collect`](Self::collect) then returns
+    /// [`Error::Cancelled`](crate::Error::Cancelled).
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Echo the output to this process's stdout and stderr as it streams,
+    /// like [`RunOptions::mirror`](crate::RunOptions::mirror) does for
+    /// [`ProcessRunner`](crate::ProcessRunner). Off by default.
+    pub fn mirror(mut self, enabled: bool) -> Self {
+        if enabled {
+            return self.mirror_to(tokio::io::stdout(), tokio::io::stderr());
+        }
+        self.mirror_stdout = None;
+        self.mirror_stderr = None;
+        self
+    }
+
+    /// Copy stdout and stderr to the given writers as the output streams,
+    /// while still delivering every chunk to the consumer
+    pub fn mirror_to(
+        mut self,
+        stdout: impl AsyncWrite + Send + Unpin + 'static,
+        stderr: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Self {
+        self.mirror_stdout = Some(Box::new(stdout));
+        self.mirror_stderr = Some(Box::new(stderr));
+        self
+    }
+
     /// Start the process and return a stream of output chunks
-    pub fn stream(mut self) -> OutputStream {
+    pub fn stream(self) -> OutputStream {
+        self.start(true)
+    }
+
+    /// Spawn the process, asking the confirmation gate first if `ask`
+    fn start(mut self, ask: bool) -> OutputStream {
         let (tx, rx) = mpsc::channel(1024);
         // Unbounded so a synchronous Drop can request a kill without awaiting.
         let (kill_tx, kill_rx) = mpsc::unbounded_channel::<String>();
 
+        if let Err(e) = policy::enforce(self.policy.as_ref(), &self.command) {
+            trace_lazy("StreamingRunner", || format!("Rejected by policy: {}", e));
+            let _ = tx.try_send(OutputChunk::Stderr(Bytes::from(format!("{}\n", e))));
+            let _ = tx.try_send(OutputChunk::Exit(POLICY_EXIT_CODE));
+            return OutputStream {
+                rx,
+                kill_tx,
+                kill_signal: self.kill_signal,
+                killed: false,
+            };
+        }
+
         // Spawn the process handling task
-        let command = self.command.clone();
-        let cwd = self.cwd.take();
-        let env = self.env.take();
-        let stdin_content = self.stdin_content.take();
-        let grace = self.exit_pump_grace_ms;
+        let spec = ProcessSpec {
+            command: self.command.clone(),
+            cwd: self.cwd.take(),
+            env: self.env.take(),
+            stdin_content: self.stdin_content.take(),
+            exit_pump_grace_ms: self.exit_pump_grace_ms,
+            mirror_stdout: self.mirror_stdout.take(),
+            mirror_stderr: self.mirror_stderr.take(),
+            line_buffered: self.line_buffered,
+        };
         let kill_signal = self.kill_signal.clone();
 
+        if let Some(token) = self.cancel.clone() {
+            let kill_tx = kill_tx.clone();
+            let signal = kill_signal.clone();
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = token.cancelled() => {
+                        let _ = kill_tx.send(signal);
+                    }
+                    _ = kill_tx.closed() => {}
+                }
+            });
+        }
+
+        let gate = self.confirm.take();
         tokio::spawn(async move {
-            if let Err(e) =
-                run_streaming_process(command, cwd, env, stdin_content, grace, tx.clone(), kill_rx)
-                    .await
-            {
+            let confirmed = match ask {
+                true => confirm(gate.as_ref(), &spec.command).await,
+                false => Ok(()),
+            };
+            if let Err(e) = confirmed {
+                let _ = tx
+                    .send(OutputChunk::Stderr(Bytes::from(format!("{}\n", e))))
+                    .await;
+                let _ = tx.send(OutputChunk::Exit(POLICY_EXIT_CODE)).await;
+                return;
+            }
+            if let Err(e) = run_streaming_process(spec, tx.clone(), kill_rx).await {
                 trace_lazy("StreamingRunner", || format!("Error: {}", e));
             }
         });
@@ -177,23 +350,30 @@ impl StreamingRunner {
 
     /// Run to completion and collect all output
     pub async fn collect(self) -> Result<CommandResult> {
+        policy::enforce(self.policy.as_ref(), &self.command)?;
+        confirm(self.confirm.as_ref(), &self.command).await?;
+        let cancel = self.cancel.clone();
         let mut stdout = Vec::new();
         let mut stderr = Vec::new();
         let mut exit_code = 0;
 
-        let mut stream = self.stream();
+        let mut stream = self.start(false);
         while let Some(chunk) = stream.rx.recv().await {
             match chunk {
-                OutputChunk::Stdout(data) => stdout.extend(data),
-                OutputChunk::Stderr(data) => stderr.extend(data),
+                OutputChunk::Stdout(data) => stdout.extend_from_slice(&data),
+                OutputChunk::Stderr(data) => stderr.extend_from_slice(&data),
                 OutputChunk::Exit(code) => exit_code = code,
             }
         }
+        if cancel.is_some_and(|token| token.is_cancelled()) {
+            return Err(crate::Error::Cancelled);
+        }
 
         Ok(CommandResult {
             stdout: String::from_utf8_lossy(&stdout).to_string(),
             stderr: String::from_utf8_lossy(&stderr).to_string(),
             code: exit_code,
+            ..Default::default()
         })
     }
 }
@@ -243,8 +423,8 @@ impl OutputStream {
 
         while let Some(chunk) = self.rx.recv().await {
             match chunk {
-                OutputChunk::Stdout(data) => stdout.extend(data),
-                OutputChunk::Stderr(data) => stderr.extend(data),
+                OutputChunk::Stdout(data) => stdout.extend_from_slice(&data),
+                OutputChunk::Stderr(data) => stderr.extend_from_slice(&data),
                 OutputChunk::Exit(code) => exit_code = code,
             }
         }
@@ -258,7 +438,7 @@ impl OutputStream {
 
         while let Some(chunk) = self.rx.recv().await {
             if let OutputChunk::Stdout(data) = chunk {
-                stdout.extend(data);
+                stdout.extend_from_slice(&data);
             }
         }
 
@@ -277,24 +457,40 @@ impl Drop for OutputStream {
     }
 }
 
-/// Run a streaming process and send output to the channel
-async fn run_streaming_process(
+/// What to spawn for a streaming run
+struct ProcessSpec {
     command: String,
     cwd: Option<PathBuf>,
     env: Option<HashMap<String, String>>,
     stdin_content: Option<String>,
     exit_pump_grace_ms: u64,
+    mirror_stdout: Option<MirrorSink>,
+    mirror_stderr: Option<MirrorSink>,
+    line_buffered: bool,
+}
+
+/// Run a streaming process and send output to the channel
+async fn run_streaming_process(
+    spec: ProcessSpec,
     tx: mpsc::Sender<OutputChunk>,
     mut kill_rx: mpsc::UnboundedReceiver<String>,
 ) -> Result<()> {
-    trace_lazy("StreamingRunner", || format!("Starting: {}", command));
-
-    let shell = find_available_shell();
-    let mut cmd = Command::new(&shell.cmd);
-    for arg in &shell.args {
-        cmd.arg(arg);
-    }
-    cmd.arg(&command);
+    let ProcessSpec {
+        command,
+        cwd,
+        env,
+        stdin_content,
+        exit_pump_grace_ms,
+        mirror_stdout,
+        mirror_stderr,
+        line_buffered,
+    } = spec;
+    let registration = crate::state::RunnerRegistration::new(&command);
+    let id = Some(registration.id());
+    trace_runner("StreamingRunner", id, || format!("Starting: {}", command));
+
+    let argv = find_shell(ShellChoice::Auto).argv(&command);
+    let mut cmd = crate::runner::program_command(argv, line_buffered);
 
     // Configure stdio
     if stdin_content.is_some() {
@@ -324,66 +520,33 @@ async fn run_streaming_process(
 
     // Spawn the process
     let mut child = cmd.spawn()?;
+    let _tracked = crate::state::track_spawned(&child);
+    registration.set_pid(child.id());
 
     // Write stdin if needed
     if let Some(content) = stdin_content {
         if let Some(mut stdin) = child.stdin.take() {
-            use tokio::io::AsyncWriteExt;
             let _ = stdin.write_all(content.as_bytes()).await;
             let _ = stdin.shutdown().await;
         }
     }
 
-    // Spawn stdout reader
-    let stdout = child.stdout.take();
-    let tx_stdout = tx.clone();
-    let stdout_handle = stdout.map(|stdout| {
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(stdout);
-            let mut buf = vec![0u8; 8192];
-            loop {
-                use tokio::io::AsyncReadExt;
-                match reader.read(&mut buf).await {
-                    Ok(0) => break,
-                    Ok(n) => {
-                        if tx_stdout
-                            .send(OutputChunk::Stdout(buf[..n].to_vec()))
-                            .await
-                            .is_err()
-                        {
-                            break;
-                        }
-                    }
-                    Err(_) => break,
-                }
-            }
-        })
+    // Spawn stdout/stderr readers
+    let stdout_handle = child.stdout.take().map(|stdout| {
+        tokio::spawn(pump_output(
+            stdout,
+            tx.clone(),
+            OutputChunk::Stdout,
+            mirror_stdout,
+        ))
     });
-
-    // Spawn stderr reader
-    let stderr = child.stderr.take();
-    let tx_stderr = tx.clone();
-    let stderr_handle = stderr.map(|stderr| {
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(stderr);
-            let mut buf = vec![0u8; 8192];
-            loop {
-                use tokio::io::AsyncReadExt;
-                match reader.read(&mut buf).await {
-                    Ok(0) => break,
-                    Ok(n) => {
-                        if tx_stderr
-                            .send(OutputChunk::Stderr(buf[..n].to_vec()))
-                            .await
-                            .is_err()
-                        {
-                            break;
-                        }
-                    }
-                    Err(_) => break,
-                }
-            }
-        })
+    let stderr_handle = child.stderr.take().map(|stderr| {
+        tokio::spawn(pump_output(
+            stderr,
+            tx.clone(),
+            OutputChunk::Stderr,
+            mirror_stderr,
+        ))
     });
 
     // Wait for the process to exit OR for a kill request — crucially we do NOT
@@ -400,7 +563,9 @@ async fn run_streaming_process(
             // A kill was requested (explicit kill()/kill_with() or the stream
             // being dropped). Stop the process group with the requested signal.
             let signal = maybe_signal.unwrap_or_else(|| DEFAULT_KILL_SIGNAL.to_string());
-            trace_lazy("StreamingRunner", || format!("Kill requested | signal={}", signal));
+            trace_runner("StreamingRunner", id, || {
+                format!("Kill requested | signal={}", signal)
+            });
             if let Some(pid) = pid {
                 send_signal_to_process(pid, &signal);
             }
@@ -449,11 +614,49 @@ async fn run_streaming_process(
     // Send exit code (always — even if a reader was aborted).
     let _ = tx.send(OutputChunk::Exit(code)).await;
 
-    trace_lazy("StreamingRunner", || format!("Exited with code: {}", code));
+    trace_runner("StreamingRunner", id, || {
+        format!("Exited with code: {}", code)
+    });
 
     Ok(())
 }
 
+/// Read `reader` to EOF, forwarding each read as a chunk built by `wrap`.
+///
+/// A single `BytesMut` buffer is reused across reads: each chunk is split off
+/// and frozen, so the data is handed to the channel without an extra copy.
+/// When a `mirror` sink is given, each chunk is written to it first.
+async fn pump_output<R>(
+    mut reader: R,
+    tx: mpsc::Sender<OutputChunk>,
+    wrap: fn(Bytes) -> OutputChunk,
+    mut mirror: Option<MirrorSink>,
+) where
+    R: AsyncRead + Unpin,
+{
+    let mut buf = BytesMut::with_capacity(READ_BUFFER_CAPACITY);
+    loop {
+        buf.reserve(READ_BUFFER_CAPACITY);
+        match reader.read_buf(&mut buf).await {
+            Ok(0) => break,
+            Ok(_) => {
+                let data = buf.split().freeze();
+                if let Some(sink) = mirror.as_mut() {
+                    // Mirroring is best effort; a failing sink must not stop
+                    // delivery to the consumer.
+                    if sink.write_all(&data).await.is_err() || sink.flush().await.is_err() {
+                        mirror = None;
+                    }
+                }
+                if tx.send(wrap(data)).await.is_err() {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+}
+
 /// Convert an exit status into a numeric exit code, using the conventional
 /// `128 + signal` mapping when the process was terminated by a signal.
 fn status_to_code(status: std::process::ExitStatus) -> i32 {
@@ -487,7 +690,7 @@ fn signal_number(signal: &str) -> i32 {
 
 /// Send a signal to a process and its process group (best effort).
 #[cfg(unix)]
-fn send_signal_to_process(pid: u32, signal: &str) {
+pub(crate) fn send_signal_to_process(pid: u32, signal: &str) {
     use nix::sys::signal::{kill, Signal};
     use nix::unistd::Pid;
 
@@ -511,46 +714,7 @@ fn send_signal_to_process(pid: u32, signal: &str) {
 /// On non-Unix platforms there is no signal delivery; the forceful
 /// `start_kill()` escalation in the caller handles termination.
 #[cfg(not(unix))]
-fn send_signal_to_process(_pid: u32, _signal: &str) {}
-
-/// Shell configuration
-#[derive(Debug, Clone)]
-struct ShellConfig {
-    cmd: String,
-    args: Vec<String>,
-}
-
-/// Find an available shell
-fn find_available_shell() -> ShellConfig {
-    let is_windows = cfg!(windows);
-
-    if is_windows {
-        ShellConfig {
-            cmd: "cmd.exe".to_string(),
-            args: vec!["/c".to_string()],
-        }
-    } else {
-        let shells = [
-            ("/bin/sh", "-c"),
-            ("/usr/bin/sh", "-c"),
-            ("/bin/bash", "-c"),
-        ];
-
-        for (cmd, arg) in shells {
-            if std::path::Path::new(cmd).exists() {
-                return ShellConfig {
-                    cmd: cmd.to_string(),
-                    args: vec![arg.to_string()],
-                };
-            }
-        }
-
-        ShellConfig {
-            cmd: "/bin/sh".to_string(),
-            args: vec!["-c".to_string()],
-        }
-    }
-}
+pub(crate) fn send_signal_to_process(_pid: u32, _signal: &str) {}
 
 /// Async iterator trait for output streams
 #[async_trait::async_trait]
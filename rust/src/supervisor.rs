@@ -0,0 +1,423 @@
+//! Keeping a process running
+//!
+//! [`Supervised::spawn`] runs a command in the background and starts it
+//! again when it exits, as its [`RestartPolicy`] allows. Lifecycle changes
+//! are broadcast as [`SupervisorEvent`]s, and the handle can
+//! [`restart`](Supervised::restart) or [`stop`](Supervised::stop) the
+//! process:
+//!
+//! ```rust,no_run
+//! use command_stream::supervisor::{Backoff, ProcessSpec, RestartPolicy, Supervised};
+//! use std::time::Duration;
+//!
+//! # async fn example() {
+//! let policy = RestartPolicy::OnFailure {
+//!     max: 5,
+//!     backoff: Backoff::exponential(Duration::from_millis(500), Duration::from_secs(30)),
+//! };
+//! let server = Supervised::spawn(ProcessSpec::new("python3 -m http.server 8000"), policy);
+//! let mut events = server.subscribe();
+//! tokio::spawn(async move {
+//!     while let Ok(event) = events.recv().await {
+//!         eprintln!("server: {:?}", event);
+//!     }
+//! });
+//!
+//! // ... use the server ...
+//! server.stop();
+//! let _ = server.wait().await;
+//! # }
+//! ```
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::trace::trace_lazy;
+use crate::{
+    CancellationToken, CommandResult, Error, ProcessRunner, Result, RunOptions, StreamEmitter,
+};
+
+/// Lifecycle events kept for subscribers that fall behind
+const EVENT_CAPACITY: usize = 64;
+
+/// The command to supervise and how to run it
+#[derive(Debug, Clone)]
+pub struct ProcessSpec {
+    command: String,
+    options: RunOptions,
+    emitter: Option<Arc<StreamEmitter>>,
+}
+
+impl ProcessSpec {
+    /// Supervise `command`, run with [`RunOptions::default`]
+    pub fn new(command: impl Into<String>) -> Self {
+        ProcessSpec {
+            command: command.into(),
+            options: RunOptions::default(),
+            emitter: None,
+        }
+    }
+
+    /// Run each attempt with `options`
+    ///
+    /// The supervisor cancels attempts through its own token, linked to
+    /// [`RunOptions::cancel`] if one is set.
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Send the output events of every attempt to `emitter`
+    pub fn emitter(mut self, emitter: Arc<StreamEmitter>) -> Self {
+        self.emitter = Some(emitter);
+        self
+    }
+}
+
+impl<T: Into<String>> From<T> for ProcessSpec {
+    fn from(command: T) -> Self {
+        ProcessSpec::new(command)
+    }
+}
+
+/// Delay before each restart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    /// Wait `delay` before every restart
+    pub fn fixed(delay: Duration) -> Self {
+        Backoff {
+            initial: delay,
+            max: delay,
+        }
+    }
+
+    /// Wait `initial` before the first restart and double the delay each
+    /// time after, up to `max`
+    pub fn exponential(initial: Duration, max: Duration) -> Self {
+        Backoff { initial, max }
+    }
+
+    /// Restart right away
+    pub fn none() -> Self {
+        Backoff::fixed(Duration::ZERO)
+    }
+
+    /// The delay before restart number `restart` (counting from 0)
+    pub fn delay(&self, restart: usize) -> Duration {
+        let factor = 1u32.checked_shl(restart.min(31) as u32).unwrap_or(u32::MAX);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// When a supervised process is started again after it exits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Run it once
+    Never,
+    /// Restart it when it fails (a non-zero exit code or an error), at most
+    /// `max` times
+    OnFailure { max: usize, backoff: Backoff },
+    /// Restart it whenever it exits, at most `max` times
+    Always { max: usize, backoff: Backoff },
+}
+
+impl RestartPolicy {
+    /// Whether restart number `restart` should happen after `outcome`, and
+    /// after what delay
+    fn next(&self, restart: usize, outcome: &Result<CommandResult>) -> Option<Duration> {
+        let (max, backoff, failed_only) = match *self {
+            RestartPolicy::Never => return None,
+            RestartPolicy::OnFailure { max, backoff } => (max, backoff, true),
+            RestartPolicy::Always { max, backoff } => (max, backoff, false),
+        };
+        let failed = !matches!(outcome, Ok(result) if result.is_success());
+        (restart < max && (failed || !failed_only)).then(|| backoff.delay(restart))
+    }
+}
+
+/// A change in a supervised process's life
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SupervisorEvent {
+    /// Attempt number `attempt` (counting from 0) started
+    Started { attempt: usize },
+    /// The attempt exited with `code`
+    Exited { attempt: usize, code: i32 },
+    /// The attempt could not run or was stopped, e.g. it timed out
+    Failed { attempt: usize, error: String },
+    /// The process will be started again after `delay`
+    Restarting { attempt: usize, delay: Duration },
+    /// The process was stopped with [`Supervised::stop`]
+    Stopped,
+    /// The restart policy allows no more restarts
+    Finished { restarts: usize },
+}
+
+/// State shared between the handle and the supervising task
+#[derive(Debug)]
+struct Shared {
+    stop: CancellationToken,
+    /// Cancels the attempt that is running
+    current: Mutex<CancellationToken>,
+    restart_requested: AtomicBool,
+    restarts: AtomicUsize,
+    events: broadcast::Sender<SupervisorEvent>,
+    /// Subscribed before the task started, for the first subscriber
+    first_receiver: Mutex<Option<broadcast::Receiver<SupervisorEvent>>>,
+}
+
+impl Shared {
+    /// A fresh token for the next attempt, which [`Supervised::restart`]
+    /// cancels
+    fn next_token(&self) -> CancellationToken {
+        let token = self.stop.child_token();
+        *self.current.lock().unwrap_or_else(|e| e.into_inner()) = token.clone();
+        token
+    }
+
+    fn send(&self, event: SupervisorEvent) {
+        trace_lazy("Supervisor", || format!("{:?}", event));
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+}
+
+/// Handle to a supervised process; see the [module docs](self)
+///
+/// Dropping the handle leaves the process supervised; call
+/// [`stop`](Supervised::stop) to end it.
+#[derive(Debug)]
+pub struct Supervised {
+    shared: Arc<Shared>,
+    task: JoinHandle<Result<CommandResult>>,
+}
+
+impl Supervised {
+    /// Start supervising `spec` on the current Tokio runtime
+    pub fn spawn(spec: impl Into<ProcessSpec>, policy: RestartPolicy) -> Self {
+        let spec = spec.into();
+        let stop = match &spec.options.cancel {
+            Some(token) => token.child_token(),
+            None => CancellationToken::new(),
+        };
+        let (events, first_receiver) = broadcast::channel(EVENT_CAPACITY);
+        let shared = Arc::new(Shared {
+            current: Mutex::new(stop.child_token()),
+            stop,
+            restart_requested: AtomicBool::new(false),
+            restarts: AtomicUsize::new(0),
+            events,
+            first_receiver: Mutex::new(Some(first_receiver)),
+        });
+        let task = tokio::spawn(supervise(spec, policy, Arc::clone(&shared)));
+        Supervised { shared, task }
+    }
+
+    /// Receive the lifecycle events
+    ///
+    /// The first subscriber gets every event since the spawn (up to the
+    /// last 64); later ones get events from the time they subscribe.
+    pub fn subscribe(&self) -> broadcast::Receiver<SupervisorEvent> {
+        let first = self
+            .shared
+            .first_receiver
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        first.unwrap_or_else(|| self.shared.events.subscribe())
+    }
+
+    /// Kill the running attempt and start a new one right away
+    ///
+    /// Manual restarts don't count against the policy's `max`.
+    pub fn restart(&self) {
+        self.shared.restart_requested.store(true, Ordering::SeqCst);
+        self.shared
+            .current
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .cancel();
+    }
+
+    /// Kill the process and stop supervising it
+    pub fn stop(&self) {
+        self.shared.stop.cancel();
+    }
+
+    /// Whether the supervisor is still running or restarting the process
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// How many times the policy has restarted the process
+    pub fn restarts(&self) -> usize {
+        self.shared.restarts.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the supervised service answers `url` with a 2xx status;
+    /// see [`wait_for_http_ok`](crate::wait::wait_for_http_ok)
+    ///
+    /// If it doesn't within `timeout`, or supervision ends first, the
+    /// process is [stopped](Supervised::stop) and the error says how the
+    /// last attempt ended. Needs the `http` feature.
+    #[cfg(feature = "http")]
+    pub async fn wait_for_http_ok(&self, url: &str, timeout: Duration) -> Result<u16> {
+        let mut events = self.shared.events.subscribe();
+        let mut last_attempt = None;
+        let ended = async {
+            while self.is_running() {
+                match events.recv().await {
+                    Ok(SupervisorEvent::Exited { code, .. }) => {
+                        last_attempt = Some(format!("exited with code {}", code));
+                    }
+                    Ok(SupervisorEvent::Failed { error, .. }) => last_attempt = Some(error),
+                    Ok(SupervisorEvent::Finished { .. } | SupervisorEvent::Stopped)
+                    | Err(broadcast::error::RecvError::Closed) => break,
+                    _ => {}
+                }
+            }
+        };
+        let (kind, failure) = tokio::select! {
+            ready = crate::wait::wait_for_http_ok(url, timeout) => match ready {
+                Ok(status) => return Ok(status),
+                Err(Error::Io(e)) => (e.kind(), e.to_string()),
+                Err(e) => return Err(e),
+            },
+            _ = ended => (
+                std::io::ErrorKind::Other,
+                format!("the process ended before {} responded", url),
+            ),
+        };
+
+        self.stop();
+        let process = match last_attempt {
+            Some(last_attempt) => format!("its last run {}", last_attempt),
+            None if self.is_running() => "it was still running".to_string(),
+            None => "it was not running".to_string(),
+        };
+        Err(Error::Io(std::io::Error::new(
+            kind,
+            format!("{}; {}", failure, process),
+        )))
+    }
+
+    /// Wait until supervision ends and return the last attempt's outcome
+    ///
+    /// After [`stop`](Supervised::stop) that is usually [`Error::Cancelled`].
+    pub async fn wait(self) -> Result<CommandResult> {
+        self.task
+            .await
+            .unwrap_or_else(|e| Err(Error::Io(std::io::Error::other(e))))
+    }
+}
+
+/// Run attempts until the policy or a stop ends supervision
+async fn supervise(
+    spec: ProcessSpec,
+    policy: RestartPolicy,
+    shared: Arc<Shared>,
+) -> Result<CommandResult> {
+    let mut attempt = 0;
+    let mut token = shared.next_token();
+    loop {
+        let mut options = spec.options.clone();
+        options.cancel = Some(token.clone());
+        let mut runner = ProcessRunner::new(spec.command.clone(), options);
+        if let Some(emitter) = &spec.emitter {
+            runner = runner.with_emitter(Arc::clone(emitter));
+        }
+
+        shared.send(SupervisorEvent::Started { attempt });
+        let outcome = runner.run().await;
+        shared.send(match &outcome {
+            Ok(result) => SupervisorEvent::Exited {
+                attempt,
+                code: result.code,
+            },
+            Err(e) => SupervisorEvent::Failed {
+                attempt,
+                error: e.to_string(),
+            },
+        });
+        attempt += 1;
+
+        if shared.stop.is_cancelled() {
+            shared.send(SupervisorEvent::Stopped);
+            return outcome;
+        }
+        if shared.restart_requested.swap(false, Ordering::SeqCst) {
+            token = shared.next_token();
+            continue;
+        }
+        let restarts = shared.restarts.load(Ordering::SeqCst);
+        let Some(delay) = policy.next(restarts, &outcome) else {
+            shared.send(SupervisorEvent::Finished { restarts });
+            return outcome;
+        };
+        shared.restarts.fetch_add(1, Ordering::SeqCst);
+        shared.send(SupervisorEvent::Restarting { attempt, delay });
+
+        // A stop or a manual restart cuts the wait short
+        token = shared.next_token();
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = token.cancelled() => {
+                if shared.stop.is_cancelled() {
+                    shared.send(SupervisorEvent::Stopped);
+                    return outcome;
+                }
+                shared.restart_requested.store(false, Ordering::SeqCst);
+                token = shared.next_token();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delays() {
+        let backoff = Backoff::exponential(Duration::from_millis(100), Duration::from_secs(1));
+        let delays: Vec<_> = (0..6).map(|restart| backoff.delay(restart)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+        );
+        assert_eq!(backoff.delay(usize::MAX), Duration::from_secs(1));
+        assert_eq!(
+            Backoff::fixed(Duration::from_secs(2)).delay(7),
+            Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn test_policy_decisions() {
+        let ok = Ok(CommandResult::success_empty());
+        let failed = Ok(CommandResult::error(""));
+        let backoff = Backoff::none();
+
+        let on_failure = RestartPolicy::OnFailure { max: 2, backoff };
+        assert_eq!(on_failure.next(0, &ok), None);
+        assert_eq!(on_failure.next(0, &failed), Some(Duration::ZERO));
+        assert_eq!(
+            on_failure.next(1, &Err(Error::Cancelled)),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(on_failure.next(2, &failed), None);
+
+        let always = RestartPolicy::Always { max: 1, backoff };
+        assert_eq!(always.next(0, &ok), Some(Duration::ZERO));
+        assert_eq!(always.next(1, &ok), None);
+        assert_eq!(RestartPolicy::Never.next(0, &failed), None);
+    }
+}
@@ -0,0 +1,167 @@
+//! Keeping only the end of a command's output
+//!
+//! A long build or test run can write far more output than is worth holding
+//! when only its last screenful matters, usually to show why it failed.
+//! With [`RunOptions::capture_tail`](crate::RunOptions::capture_tail) set,
+//! the runner keeps output in a buffer bounded by a [`TailLimit`] and the
+//! result's `stdout` and `stderr` hold only their tails.
+//!
+//! ```rust,no_run
+//! use command_stream::{ProcessRunner, RunOptions, TailLimit};
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let options = RunOptions::builder()
+//!     .mirror(false)
+//!     .capture_tail(TailLimit::Lines(50))
+//!     .build();
+//! let result = ProcessRunner::new("cargo build", options).run().await?;
+//! if !result.is_success() {
+//!     eprint!("{}", result.stderr);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+/// How much of the end of each output stream to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TailLimit {
+    /// The last this many lines
+    Lines(usize),
+    /// At most this many bytes, starting at a line boundary unless the last
+    /// line alone is longer
+    Bytes(usize),
+}
+
+impl TailLimit {
+    /// Where the tail of `text` this limit keeps starts
+    pub fn start(self, text: &str) -> usize {
+        match self {
+            TailLimit::Lines(0) => text.len(),
+            TailLimit::Lines(lines) => text
+                .strip_suffix('\n')
+                .unwrap_or(text)
+                .rmatch_indices('\n')
+                .nth(lines - 1)
+                .map_or(0, |(i, _)| i + 1),
+            TailLimit::Bytes(bytes) => {
+                let mut start = text.len().saturating_sub(bytes);
+                if start == 0 || text.as_bytes()[start - 1] == b'\n' {
+                    return start;
+                }
+                match text[start..].find('\n') {
+                    Some(i) if start + i + 1 < text.len() => start + i + 1,
+                    _ => {
+                        while !text.is_char_boundary(start) {
+                            start += 1;
+                        }
+                        start
+                    }
+                }
+            }
+        }
+    }
+
+    /// Cut `text` down to the tail this limit keeps
+    pub fn apply(self, text: &mut String) {
+        let start = self.start(text);
+        text.drain(..start);
+    }
+
+    /// How far past the limit a buffer may grow before it is trimmed, so
+    /// that trimming costs amortized constant time per line
+    fn slack(self) -> usize {
+        match self {
+            TailLimit::Lines(lines) => lines.max(64),
+            TailLimit::Bytes(bytes) => bytes.max(4096),
+        }
+    }
+}
+
+/// Append `piece` to `text`, trimming it to `limit` once it has grown past
+/// the limit by the slack; `lines` counts the newlines in `text`
+pub(crate) fn push(text: &mut String, lines: &mut usize, piece: &str, limit: Option<TailLimit>) {
+    text.push_str(piece);
+    let Some(limit) = limit else {
+        return;
+    };
+    let over = match limit {
+        TailLimit::Lines(max) => {
+            *lines += piece.bytes().filter(|&b| b == b'\n').count();
+            *lines > max + limit.slack()
+        }
+        TailLimit::Bytes(max) => text.len() > max + limit.slack(),
+    };
+    if over {
+        limit.apply(text);
+        *lines = text.bytes().filter(|&b| b == b'\n').count();
+    }
+}
+
+/// Output collected under an optional [`TailLimit`]
+#[derive(Debug, Default)]
+pub(crate) struct TailBuffer {
+    limit: Option<TailLimit>,
+    text: String,
+    lines: usize,
+}
+
+impl TailBuffer {
+    pub(crate) fn new(limit: Option<TailLimit>) -> Self {
+        TailBuffer {
+            limit,
+            ..TailBuffer::default()
+        }
+    }
+
+    pub(crate) fn push_str(&mut self, piece: &str) {
+        push(&mut self.text, &mut self.lines, piece, self.limit);
+    }
+
+    /// The collected output, cut to the limit
+    pub(crate) fn into_string(mut self) -> String {
+        if let Some(limit) = self.limit {
+            limit.apply(&mut self.text);
+        }
+        self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines_keep_the_last_lines() {
+        let text = "a\nb\nc\n";
+        assert_eq!(&text[TailLimit::Lines(2).start(text)..], "b\nc\n");
+        assert_eq!(&text[TailLimit::Lines(5).start(text)..], text);
+        assert_eq!(&text[TailLimit::Lines(0).start(text)..], "");
+        assert_eq!(&"a\nb"[TailLimit::Lines(1).start("a\nb")..], "b");
+    }
+
+    #[test]
+    fn test_bytes_start_at_a_line_boundary() {
+        let text = "first\nsecond\nthird\n";
+        assert_eq!(&text[TailLimit::Bytes(8).start(text)..], "third\n");
+        assert_eq!(&text[TailLimit::Bytes(13).start(text)..], "second\nthird\n");
+        assert_eq!(&text[TailLimit::Bytes(100).start(text)..], text);
+        // A last line longer than the limit is cut inside, on a character
+        let long = "xé\n";
+        assert_eq!(&long[TailLimit::Bytes(3).start(long)..], "é\n");
+    }
+
+    #[test]
+    fn test_buffer_stays_bounded() {
+        let mut buffer = TailBuffer::new(Some(TailLimit::Lines(3)));
+        for i in 0..10_000 {
+            buffer.push_str(&format!("line {}\n", i));
+            assert!(buffer.lines <= 3 + 64 + 1);
+        }
+        assert_eq!(buffer.into_string(), "line 9997\nline 9998\nline 9999\n");
+
+        let mut unlimited = TailBuffer::new(None);
+        unlimited.push_str("a\n");
+        unlimited.push_str("b\n");
+        assert_eq!(unlimited.into_string(), "a\nb\n");
+    }
+}
@@ -0,0 +1,173 @@
+//! Temporary files and directories
+//!
+//! [`temp_file`] and [`temp_dir`] create scratch space that is removed when
+//! the returned guard is dropped:
+//!
+//! ```rust,no_run
+//! use command_stream::temp::temp_dir;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let scratch = temp_dir()?;
+//! let cmd = format!("tar -xzf release.tar.gz -C {}", scratch.path().display());
+//! command_stream::run(cmd).await?;
+//! // The directory and everything in it is gone once `scratch` drops
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The virtual `mktemp` command creates files and directories for scripts,
+//! which only know them by path, so they outlive the command. Those are
+//! tracked here: [`cleanup_temp`] removes them, and with
+//! [`set_auto_cleanup`] they are also removed when the process exits.
+
+use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::Result;
+
+/// Paths created by the `mktemp` builtin that have not been cleaned up
+static TRACKED: Lazy<Mutex<Vec<PathBuf>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+static AUTO_CLEANUP: AtomicBool = AtomicBool::new(false);
+
+/// A temporary file, deleted when dropped
+#[derive(Debug)]
+pub struct TempFile {
+    path: tempfile::TempPath,
+}
+
+impl TempFile {
+    /// Where the file is
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keep the file after the guard is gone and return its path
+    pub fn keep(self) -> Result<PathBuf> {
+        self.path.keep().map_err(|e| e.error.into())
+    }
+}
+
+impl AsRef<Path> for TempFile {
+    fn as_ref(&self) -> &Path {
+        self.path()
+    }
+}
+
+/// A temporary directory, deleted with its contents when dropped
+#[derive(Debug)]
+pub struct TempDir {
+    dir: tempfile::TempDir,
+}
+
+impl TempDir {
+    /// Where the directory is
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Keep the directory after the guard is gone and return its path
+    pub fn keep(self) -> PathBuf {
+        self.dir.keep()
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        self.path()
+    }
+}
+
+/// Create an empty file in the system temporary directory (`$TMPDIR` or
+/// `/tmp` on Unix)
+pub fn temp_file() -> Result<TempFile> {
+    let file = tempfile::Builder::new().prefix("tmp.").tempfile()?;
+    Ok(TempFile {
+        path: file.into_temp_path(),
+    })
+}
+
+/// Create an empty directory in the system temporary directory
+pub fn temp_dir() -> Result<TempDir> {
+    let dir = tempfile::Builder::new().prefix("tmp.").tempdir()?;
+    Ok(TempDir { dir })
+}
+
+/// Remove what `mktemp` created when the process exits; off by default
+///
+/// Only takes effect on Unix, where the removal runs as an `atexit` handler
+/// (which also runs after a termination signal is handled); turning it on
+/// installs that handler with the others of
+/// [`install_cleanup_handlers`](crate::install_cleanup_handlers).
+pub fn set_auto_cleanup(enabled: bool) {
+    AUTO_CLEANUP.store(enabled, Ordering::SeqCst);
+    if enabled {
+        crate::state::install_cleanup_handlers();
+    }
+}
+
+/// Whether [`set_auto_cleanup`] is on
+pub fn auto_cleanup() -> bool {
+    AUTO_CLEANUP.load(Ordering::SeqCst)
+}
+
+/// Remove every file and directory `mktemp` has created so far and return
+/// how many were still there
+pub fn cleanup_temp() -> usize {
+    let paths = std::mem::take(&mut *TRACKED.lock().unwrap_or_else(|e| e.into_inner()));
+    paths
+        .iter()
+        .filter(|path| {
+            if path.is_dir() {
+                std::fs::remove_dir_all(path).is_ok()
+            } else {
+                std::fs::remove_file(path).is_ok()
+            }
+        })
+        .count()
+}
+
+/// Remember a path `mktemp` created
+pub(crate) fn track(path: PathBuf) {
+    TRACKED.lock().unwrap_or_else(|e| e.into_inner()).push(path);
+}
+
+/// Called on process exit
+pub(crate) fn cleanup_at_exit() {
+    if auto_cleanup() {
+        cleanup_temp();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guards_remove_on_drop() {
+        let file = temp_file().unwrap();
+        let path = file.path().to_path_buf();
+        assert!(path.is_file());
+        drop(file);
+        assert!(!path.exists());
+
+        let dir = temp_dir().unwrap();
+        let path = dir.path().to_path_buf();
+        std::fs::write(path.join("inner"), "x").unwrap();
+        drop(dir);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_keep() {
+        let kept = temp_file().unwrap().keep().unwrap();
+        assert!(kept.is_file());
+        std::fs::remove_file(kept).unwrap();
+
+        let kept = temp_dir().unwrap().keep();
+        assert!(kept.is_dir());
+        std::fs::remove_dir(kept).unwrap();
+    }
+}
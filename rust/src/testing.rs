@@ -0,0 +1,284 @@
+//! Assertions for testing command flows
+//!
+//! [`expect`] runs a command and checks its result, in the style of
+//! `assert_cmd`. Awaiting the expectation panics with every failed check and
+//! the command's output, so it reads like an `assert!` in a test:
+//!
+//! ```rust,no_run
+//! use command_stream::testing::expect;
+//!
+//! # async fn example() {
+//! expect("cargo --version")
+//!     .code(0)
+//!     .stdout_contains("cargo")
+//!     .stderr_empty()
+//!     .await;
+//! # }
+//! ```
+//!
+//! Output can also be compared with a snapshot file. It is normalized first:
+//! ANSI escapes are stripped, `\r\n` becomes `\n`, and redacted values such as
+//! temporary directories are replaced with placeholders. A missing snapshot
+//! is written, and setting `COMMAND_STREAM_UPDATE_SNAPSHOTS=1` rewrites them
+//! all.
+//!
+//! ```rust,no_run
+//! use command_stream::testing::expect;
+//!
+//! # async fn example(tmp: &std::path::Path) {
+//! expect("ls -l")
+//!     .cwd(tmp)
+//!     .redact(tmp.display().to_string(), "[TMP]")
+//!     .stdout_snapshot("tests/snapshots/ls.txt")
+//!     .await;
+//! # }
+//! ```
+
+use std::future::{Future, IntoFuture};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+use crate::{AnsiUtils, CommandResult, ProcessRunner, RunOptions, StdinOption};
+
+/// Environment variable that makes snapshot checks rewrite their files
+pub const UPDATE_SNAPSHOTS_ENV: &str = "COMMAND_STREAM_UPDATE_SNAPSHOTS";
+
+/// A check against a command's result
+#[derive(Debug, Clone)]
+enum Check {
+    Code(i32),
+    Success,
+    Failure,
+    StdoutEq(String),
+    StdoutContains(String),
+    StderrContains(String),
+    StderrEmpty,
+    StdoutSnapshot(PathBuf),
+}
+
+/// Normalizes output before it is compared
+#[derive(Debug, Clone, Default)]
+pub struct Normalizer {
+    redactions: Vec<(String, String)>,
+}
+
+impl Normalizer {
+    /// A normalizer with no redactions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace every occurrence of `value` with `placeholder`
+    ///
+    /// For values that name an existing path, its canonical form (e.g.
+    /// `/private/var/...` for a macOS temp dir) is redacted too.
+    pub fn redact(mut self, value: impl Into<String>, placeholder: impl Into<String>) -> Self {
+        let value = value.into();
+        let placeholder = placeholder.into();
+        if let Ok(canonical) = std::fs::canonicalize(&value) {
+            let canonical = canonical.to_string_lossy().into_owned();
+            if canonical != value {
+                self.redactions.push((canonical, placeholder.clone()));
+            }
+        }
+        if !value.is_empty() {
+            self.redactions.push((value, placeholder));
+        }
+        // Longest first, so a path isn't partly replaced by its parent
+        self.redactions
+            .sort_by_key(|(value, _)| std::cmp::Reverse(value.len()));
+        self
+    }
+
+    /// `text` without ANSI escapes or `\r\n` line endings, and with the
+    /// redactions applied
+    pub fn normalize(&self, text: &str) -> String {
+        let mut text = AnsiUtils::strip_ansi(text).replace("\r\n", "\n");
+        for (value, placeholder) in &self.redactions {
+            text = text.replace(value.as_str(), placeholder);
+        }
+        text
+    }
+}
+
+/// Run `command` and check its result; see the [module docs](self)
+pub fn expect(command: impl Into<String>) -> Expectation {
+    Expectation {
+        command: command.into(),
+        options: RunOptions::from_env().quiet(),
+        checks: Vec::new(),
+        normalizer: Normalizer::new(),
+    }
+}
+
+/// A command together with the checks its result must pass
+#[derive(Debug, Clone)]
+pub struct Expectation {
+    command: String,
+    options: RunOptions,
+    checks: Vec<Check>,
+    normalizer: Normalizer,
+}
+
+impl Expectation {
+    /// Run the command with `options` (mirroring is still turned off)
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options.quiet();
+        self
+    }
+
+    /// Run the command in `dir`
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.cwd = Some(dir.into());
+        self
+    }
+
+    /// Give the command `input` on stdin
+    pub fn stdin(mut self, input: impl Into<String>) -> Self {
+        self.options.stdin = StdinOption::Content(input.into());
+        self
+    }
+
+    /// Replace `value` with `placeholder` before comparing output (see
+    /// [`Normalizer::redact`])
+    pub fn redact(mut self, value: impl Into<String>, placeholder: impl Into<String>) -> Self {
+        self.normalizer = self.normalizer.redact(value, placeholder);
+        self
+    }
+
+    /// The exit code must be `code`
+    pub fn code(self, code: i32) -> Self {
+        self.check(Check::Code(code))
+    }
+
+    /// The exit code must be 0
+    pub fn success(self) -> Self {
+        self.check(Check::Success)
+    }
+
+    /// The exit code must not be 0
+    pub fn failure(self) -> Self {
+        self.check(Check::Failure)
+    }
+
+    /// Normalized stdout must be exactly `expected`
+    pub fn stdout_eq(self, expected: impl Into<String>) -> Self {
+        self.check(Check::StdoutEq(expected.into()))
+    }
+
+    /// Normalized stdout must contain `text`
+    pub fn stdout_contains(self, text: impl Into<String>) -> Self {
+        self.check(Check::StdoutContains(text.into()))
+    }
+
+    /// Normalized stderr must contain `text`
+    pub fn stderr_contains(self, text: impl Into<String>) -> Self {
+        self.check(Check::StderrContains(text.into()))
+    }
+
+    /// Nothing may be written to stderr
+    pub fn stderr_empty(self) -> Self {
+        self.check(Check::StderrEmpty)
+    }
+
+    /// Normalized stdout must match the snapshot file at `path`
+    pub fn stdout_snapshot(self, path: impl Into<PathBuf>) -> Self {
+        self.check(Check::StdoutSnapshot(path.into()))
+    }
+
+    fn check(mut self, check: Check) -> Self {
+        self.checks.push(check);
+        self
+    }
+
+    /// Run the command and report every failed check instead of panicking
+    pub async fn verify(self) -> Result<CommandResult, String> {
+        let result = ProcessRunner::new(&self.command, self.options.clone())
+            .run()
+            .await
+            .map_err(|e| format!("`{}` could not run: {}", self.command, e))?;
+        let stdout = self.normalizer.normalize(&result.stdout);
+        let stderr = self.normalizer.normalize(&result.stderr);
+
+        let mut failures = Vec::new();
+        for check in &self.checks {
+            let failure = match check {
+                Check::Code(code) if result.code != *code => {
+                    Some(format!("expected exit code {}, got {}", code, result.code))
+                }
+                Check::Success if !result.is_success() => {
+                    Some(format!("expected success, got exit code {}", result.code))
+                }
+                Check::Failure if result.is_success() => {
+                    Some("expected failure, got exit code 0".to_string())
+                }
+                Check::StdoutEq(expected) if stdout != *expected => {
+                    Some(format!("expected stdout {:?}", expected))
+                }
+                Check::StdoutContains(text) if !stdout.contains(text.as_str()) => {
+                    Some(format!("expected stdout to contain {:?}", text))
+                }
+                Check::StderrContains(text) if !stderr.contains(text.as_str()) => {
+                    Some(format!("expected stderr to contain {:?}", text))
+                }
+                Check::StderrEmpty if !stderr.is_empty() => {
+                    Some("expected empty stderr".to_string())
+                }
+                Check::StdoutSnapshot(path) => compare_snapshot(path, &stdout),
+                _ => None,
+            };
+            failures.extend(failure);
+        }
+
+        if failures.is_empty() {
+            return Ok(result);
+        }
+        Err(format!(
+            "`{}` failed {} check(s):\n  - {}\n--- stdout ---\n{}\n--- stderr ---\n{}",
+            self.command,
+            failures.len(),
+            failures.join("\n  - "),
+            stdout,
+            stderr
+        ))
+    }
+}
+
+impl IntoFuture for Expectation {
+    type Output = CommandResult;
+    type IntoFuture = Pin<Box<dyn Future<Output = CommandResult> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            match self.verify().await {
+                Ok(result) => result,
+                Err(report) => panic!("{}", report),
+            }
+        })
+    }
+}
+
+/// Compare `actual` with the snapshot at `path`, writing the snapshot when
+/// it is missing or updates are requested
+fn compare_snapshot(path: &Path, actual: &str) -> Option<String> {
+    let update = std::env::var(UPDATE_SNAPSHOTS_ENV).is_ok_and(|value| value == "1");
+    match std::fs::read_to_string(path) {
+        Ok(expected) if !update => (expected != actual).then(|| {
+            format!(
+                "stdout differs from snapshot {} (set {}=1 to update it)\n--- snapshot ---\n{}",
+                path.display(),
+                UPDATE_SNAPSHOTS_ENV,
+                expected
+            )
+        }),
+        _ => {
+            let written = path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(path, actual));
+            written
+                .err()
+                .map(|e| format!("could not write snapshot {}: {}", path.display(), e))
+        }
+    }
+}
@@ -1,32 +1,178 @@
 //! Trace/logging utilities for command-stream
 //!
 //! This module provides verbose logging functionality that can be controlled
-//! via environment variables for debugging and development purposes.
+//! via environment variables for debugging and development purposes. The
+//! settings are cached and events can be filtered by category and rate
+//! limited, so tracing is cheap enough to leave on in production.
+//!
+//! Events are written as text to stderr by default. With
+//! [`TraceFormat::Json`] each event is one JSON object per line, and
+//! [`set_trace_writer`] sends them elsewhere, e.g. to a file a log pipeline
+//! ingests:
+//!
+//! ```text
+//! {"ts":1760617200.123456,"category":"ProcessRunner","runner":3,"pid":4242,"message":"Starting command: ls"}
+//! ```
 
 use std::env;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// How trace events are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// `[TRACE +1.250000s] [ProcessRunner #3] message` lines for people
+    #[default]
+    Text,
+    /// One JSON object per line with `ts` (Unix seconds), `category`,
+    /// `runner` (the runner id, or `null`), `pid` and `message`
+    Json,
+}
 
-/// Check if tracing is enabled via environment variables
+/// Trace settings
 ///
-/// Tracing can be controlled via:
-/// - COMMAND_STREAM_TRACE=true/false (explicit control)
-/// - COMMAND_STREAM_VERBOSE=true (enables tracing unless TRACE=false)
-pub fn is_trace_enabled() -> bool {
-    let trace_env = env::var("COMMAND_STREAM_TRACE").ok();
-    let verbose_env = env::var("COMMAND_STREAM_VERBOSE")
-        .map(|v| v == "true")
-        .unwrap_or(false);
+/// Read from the environment on first use and cached, so a disabled trace
+/// costs one atomic load:
+/// - `COMMAND_STREAM_TRACE=true/false` turns tracing on or off
+/// - `COMMAND_STREAM_TRACE=ProcessRunner,Pipeline` traces only those
+///   categories
+/// - `COMMAND_STREAM_VERBOSE=true` enables tracing unless `TRACE=false`
+/// - `COMMAND_STREAM_TRACE_RATE=100` writes at most 100 events per second
+/// - `COMMAND_STREAM_TRACE_FORMAT=json` writes [`TraceFormat::Json`] events
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceConfig {
+    /// Whether anything is traced
+    pub enabled: bool,
+    /// Categories to trace; empty traces every category
+    pub categories: Vec<String>,
+    /// Most events written per second, `None` for no limit
+    pub max_per_second: Option<u32>,
+    /// How events are written
+    pub format: TraceFormat,
+}
+
+impl TraceConfig {
+    /// Settings from the environment variables above
+    pub fn from_env() -> Self {
+        Self::from_vars(
+            env::var("COMMAND_STREAM_TRACE").ok().as_deref(),
+            env::var("COMMAND_STREAM_VERBOSE").ok().as_deref(),
+            env::var("COMMAND_STREAM_TRACE_RATE").ok().as_deref(),
+        )
+        .format(env::var("COMMAND_STREAM_TRACE_FORMAT").ok().as_deref())
+    }
+
+    fn from_vars(trace: Option<&str>, verbose: Option<&str>, rate: Option<&str>) -> Self {
+        let mut config = TraceConfig {
+            max_per_second: rate.and_then(|rate| rate.trim().parse().ok()),
+            ..Default::default()
+        };
+        match trace.map(str::trim) {
+            Some("false") | Some("0") => {}
+            Some("true") | Some("1") => config.enabled = true,
+            Some(list) if !list.is_empty() => {
+                config.enabled = true;
+                config.categories = list
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|category| !category.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+            _ => config.enabled = verbose == Some("true"),
+        }
+        config
+    }
+
+    fn format(mut self, format: Option<&str>) -> Self {
+        if format.is_some_and(|format| format.trim().eq_ignore_ascii_case("json")) {
+            self.format = TraceFormat::Json;
+        }
+        self
+    }
 
-    match trace_env.as_deref() {
-        Some("false") => false,
-        Some("true") => true,
-        _ => verbose_env,
+    /// Whether events in `category` are traced
+    pub fn allows(&self, category: &str) -> bool {
+        self.enabled
+            && (self.categories.is_empty() || self.categories.iter().any(|c| c == category))
     }
 }
 
+const UNKNOWN: u8 = 0;
+const DISABLED: u8 = 1;
+const ENABLED: u8 = 2;
+
+/// Mirrors `CONFIG.enabled` so the disabled path never takes the lock
+static STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+static CONFIG: RwLock<Option<TraceConfig>> = RwLock::new(None);
+static LIMITER: RateLimiter = RateLimiter::new();
+static WRITER: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Replace the trace settings, e.g. to enable tracing from code
+pub fn set_trace_config(config: TraceConfig) {
+    let enabled = config.enabled;
+    *CONFIG.write().unwrap_or_else(|e| e.into_inner()) = Some(config);
+    STATE.store(if enabled { ENABLED } else { DISABLED }, Ordering::Release);
+}
+
+/// Write trace events to `writer` instead of stderr
+pub fn set_trace_writer(writer: impl Write + Send + 'static) {
+    *WRITER.lock().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(writer));
+}
+
+/// Write trace events to stderr again
+pub fn reset_trace_writer() {
+    *WRITER.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Re-read the trace settings from the environment
+pub fn reload_trace_config() {
+    set_trace_config(TraceConfig::from_env());
+}
+
+/// The current trace settings
+pub fn trace_config() -> TraceConfig {
+    is_trace_enabled();
+    CONFIG
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_default()
+}
+
+/// Check if tracing is enabled for any category
+///
+/// The environment is read once; see [`TraceConfig`] for the variables and
+/// [`reload_trace_config`] to pick up later changes.
+pub fn is_trace_enabled() -> bool {
+    match STATE.load(Ordering::Acquire) {
+        ENABLED => true,
+        DISABLED => false,
+        _ => {
+            reload_trace_config();
+            STATE.load(Ordering::Acquire) == ENABLED
+        }
+    }
+}
+
+/// Check if events in `category` are traced
+pub fn is_category_enabled(category: &str) -> bool {
+    is_trace_enabled()
+        && CONFIG
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .is_some_and(|config| config.allows(category))
+}
+
 /// Trace function for verbose logging
 ///
-/// Outputs trace messages to stderr when tracing is enabled.
-/// Messages are prefixed with timestamp and category.
+/// Outputs trace messages to stderr when tracing is enabled for `category`.
+/// Messages are prefixed with the seconds since tracing started and the
+/// category.
 ///
 /// # Examples
 ///
@@ -36,17 +182,15 @@ pub fn is_trace_enabled() -> bool {
 /// trace("ProcessRunner", "Starting command execution");
 /// ```
 pub fn trace(category: &str, message: &str) {
-    if !is_trace_enabled() {
+    if !is_category_enabled(category) {
         return;
     }
-
-    let timestamp = chrono::Utc::now().to_rfc3339();
-    eprintln!("[TRACE {}] [{}] {}", timestamp, category, message);
+    write_event(category, None, message);
 }
 
 /// Trace function with lazy message evaluation
 ///
-/// Only evaluates the message function if tracing is enabled.
+/// Only evaluates the message function if `category` is traced.
 /// This is useful for expensive message formatting that should
 /// be avoided when tracing is disabled.
 ///
@@ -63,11 +207,181 @@ pub fn trace_lazy<F>(category: &str, message_fn: F)
 where
     F: FnOnce() -> String,
 {
-    if !is_trace_enabled() {
+    if !is_category_enabled(category) {
         return;
     }
+    write_event(category, None, &message_fn());
+}
+
+/// Report a handle dropped with work left that its `close()` would have
+/// finished
+///
+/// Always traced under `category`; debug builds also print it to stderr,
+/// since the leak is otherwise silent.
+pub(crate) fn warn_not_closed(category: &str, message: &str) {
+    trace(category, message);
+    if cfg!(debug_assertions) {
+        eprintln!("[command-stream] Warning: {}", message);
+    }
+}
+
+/// Like [`trace_lazy`], for an event of the runner with id `runner`
+///
+/// The id is the one the runner is registered under in the global state,
+/// so events of concurrent commands can be told apart.
+pub fn trace_runner<F>(category: &str, runner: Option<u64>, message_fn: F)
+where
+    F: FnOnce() -> String,
+{
+    if !is_category_enabled(category) {
+        return;
+    }
+    write_event(category, runner, &message_fn());
+}
+
+fn write_event(category: &str, runner: Option<u64>, message: &str) {
+    let elapsed = started().elapsed();
+    let (limit, format) = CONFIG
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map_or((None, TraceFormat::Text), |config| {
+            (config.max_per_second, config.format)
+        });
+    let dropped = match limit {
+        Some(limit) => match LIMITER.admit(elapsed.as_secs(), limit) {
+            Some(dropped) => dropped,
+            None => return,
+        },
+        None => 0,
+    };
+
+    // Messages often quote the command being run
+    let message = crate::redact::redact(message);
+    let mut out = String::new();
+    if dropped > 0 {
+        let notice = format!("{} events dropped by the rate limit", dropped);
+        format_event(&mut out, format, elapsed, "trace", None, &notice);
+    }
+    format_event(&mut out, format, elapsed, category, runner, &message);
+
+    match WRITER.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        Some(writer) => {
+            let _ = writer.write_all(out.as_bytes());
+            let _ = writer.flush();
+        }
+        None => {
+            let _ = std::io::stderr().lock().write_all(out.as_bytes());
+        }
+    }
+}
+
+/// Append one event line to `out`
+fn format_event(
+    out: &mut String,
+    format: TraceFormat,
+    elapsed: std::time::Duration,
+    category: &str,
+    runner: Option<u64>,
+    message: &str,
+) {
+    match format {
+        TraceFormat::Text => {
+            let _ = write!(
+                out,
+                "[TRACE +{}.{:06}s] [{}",
+                elapsed.as_secs(),
+                elapsed.subsec_micros(),
+                category
+            );
+            if let Some(runner) = runner {
+                let _ = write!(out, " #{}", runner);
+            }
+            let _ = writeln!(out, "] {}", message);
+        }
+        TraceFormat::Json => {
+            let ts = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let _ = write!(
+                out,
+                "{{\"ts\":{}.{:06},\"category\":",
+                ts.as_secs(),
+                ts.subsec_micros()
+            );
+            push_json_string(out, category);
+            match runner {
+                Some(runner) => {
+                    let _ = write!(out, ",\"runner\":{}", runner);
+                }
+                None => out.push_str(",\"runner\":null"),
+            }
+            let _ = write!(out, ",\"pid\":{},\"message\":", std::process::id());
+            push_json_string(out, message);
+            out.push_str("}\n");
+        }
+    }
+}
+
+/// Append `value` to `out` as a quoted JSON string
+pub(crate) fn push_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// When the first event was traced; timestamps count from here
+fn started() -> Instant {
+    static STARTED: OnceLock<Instant> = OnceLock::new();
+    *STARTED.get_or_init(Instant::now)
+}
 
-    trace(category, &message_fn());
+/// Admits a fixed number of events per one-second window
+struct RateLimiter {
+    window: AtomicU64,
+    count: AtomicU32,
+    dropped: AtomicU64,
+}
+
+impl RateLimiter {
+    const fn new() -> Self {
+        RateLimiter {
+            window: AtomicU64::new(0),
+            count: AtomicU32::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// `None` if the event in second `now` is over `limit`, otherwise how
+    /// many events were dropped since the last admitted one
+    fn admit(&self, now: u64, limit: u32) -> Option<u64> {
+        let window = self.window.load(Ordering::Relaxed);
+        if now != window
+            && self
+                .window
+                .compare_exchange(window, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.count.store(0, Ordering::Relaxed);
+        }
+        if self.count.fetch_add(1, Ordering::Relaxed) >= limit {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(self.dropped.swap(0, Ordering::Relaxed))
+    }
 }
 
 #[cfg(test)]
@@ -106,6 +420,7 @@ mod tests {
                 Some(v) => env::set_var("COMMAND_STREAM_VERBOSE", v),
                 None => env::remove_var("COMMAND_STREAM_VERBOSE"),
             }
+            reload_trace_config();
         }
     }
 
@@ -117,6 +432,7 @@ mod tests {
         // Clear env vars to test default behavior
         env::remove_var("COMMAND_STREAM_TRACE");
         env::remove_var("COMMAND_STREAM_VERBOSE");
+        reload_trace_config();
         assert!(!is_trace_enabled());
     }
 
@@ -127,6 +443,7 @@ mod tests {
 
         env::remove_var("COMMAND_STREAM_TRACE");
         env::set_var("COMMAND_STREAM_VERBOSE", "true");
+        reload_trace_config();
         assert!(is_trace_enabled());
     }
 
@@ -137,6 +454,7 @@ mod tests {
 
         env::remove_var("COMMAND_STREAM_VERBOSE");
         env::set_var("COMMAND_STREAM_TRACE", "true");
+        reload_trace_config();
         assert!(is_trace_enabled());
     }
 
@@ -147,6 +465,84 @@ mod tests {
 
         env::set_var("COMMAND_STREAM_TRACE", "false");
         env::set_var("COMMAND_STREAM_VERBOSE", "true");
+        reload_trace_config();
         assert!(!is_trace_enabled());
     }
+
+    #[test]
+    fn test_trace_categories() {
+        let config = TraceConfig::from_vars(Some("ProcessRunner, Pipeline"), None, None);
+        assert!(config.allows("ProcessRunner") && config.allows("Pipeline"));
+        assert!(!config.allows("GlobalState"));
+
+        let config = TraceConfig::from_vars(Some("true"), None, Some("50"));
+        assert!(config.allows("GlobalState"));
+        assert_eq!(config.max_per_second, Some(50));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new();
+        assert_eq!(limiter.admit(7, 2), Some(0));
+        assert_eq!(limiter.admit(7, 2), Some(0));
+        assert_eq!(limiter.admit(7, 2), None);
+        assert_eq!(limiter.admit(7, 2), None);
+        // A new second admits events again and reports the dropped ones
+        assert_eq!(limiter.admit(8, 2), Some(2));
+        assert_eq!(limiter.admit(8, 2), Some(0));
+    }
+
+    /// A writer tests can read back
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_events_go_to_the_writer() {
+        let _lock = ENV_MUTEX.lock().unwrap();
+        let _guard = EnvGuard::new();
+
+        let buffer = SharedBuffer::default();
+        set_trace_writer(buffer.clone());
+        set_trace_config(TraceConfig {
+            enabled: true,
+            categories: vec!["JsonTest".to_string()],
+            format: TraceFormat::Json,
+            ..Default::default()
+        });
+        trace_runner("JsonTest", Some(7), || "said \"hi\"\n".to_string());
+        trace("JsonTest", "tab\there");
+        trace("OtherCategory", "filtered out");
+        reset_trace_writer();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", output);
+        assert!(lines[0].starts_with("{\"ts\":"));
+        let pid = format!("\"pid\":{}", std::process::id());
+        assert!(lines[0].ends_with(&format!(
+            "\"category\":\"JsonTest\",\"runner\":7,{},\"message\":\"said \\\"hi\\\"\\n\"}}",
+            pid
+        )));
+        assert!(lines[1].contains("\"runner\":null,"));
+        assert!(lines[1].ends_with("\"message\":\"tab\\there\"}"));
+    }
+
+    #[test]
+    fn test_trace_format_from_env() {
+        let config = TraceConfig::from_vars(Some("true"), None, None).format(Some("JSON"));
+        assert_eq!(config.format, TraceFormat::Json);
+        let config = TraceConfig::from_vars(Some("true"), None, None).format(Some("text"));
+        assert_eq!(config.format, TraceFormat::Text);
+    }
 }
@@ -0,0 +1,159 @@
+//! Parsing of human-friendly durations and sizes
+//!
+//! Options, builtins and environment variables that take a duration or a
+//! byte count accept the same spellings, so `30s`, `1h30m` or `10MB` mean the
+//! same thing wherever they appear.
+//!
+//! ```rust
+//! use command_stream::{parse_duration, parse_size};
+//! use std::time::Duration;
+//!
+//! assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+//! assert_eq!(parse_duration("1.5").unwrap(), Duration::from_millis(1500));
+//! assert_eq!(parse_size("10MB").unwrap(), 10_000_000);
+//! assert_eq!(parse_size("4KiB").unwrap(), 4096);
+//! ```
+
+use std::time::Duration;
+
+use crate::{Error, Result};
+
+/// Parse a duration such as `250ms`, `30s`, `1.5h` or `1h30m`
+///
+/// A duration is one or more numbers, each followed by a unit: `ns`, `us`
+/// (or `µs`), `ms`, `s`, `m`, `h` or `d`. A number without a unit is seconds,
+/// as with `sleep`. Whitespace between parts is allowed; negative values are
+/// not.
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let invalid = || Error::ParseError(format!("invalid duration '{}'", text));
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut total = 0.0_f64;
+    while !rest.is_empty() {
+        let (value, after_number) = split_number(rest).ok_or_else(invalid)?;
+        let unit_len = after_number
+            .find(|c: char| c.is_ascii_digit() || c == '.' || c.is_whitespace())
+            .unwrap_or(after_number.len());
+        let seconds_per_unit = match &after_number[..unit_len] {
+            "" | "s" | "sec" | "secs" => 1.0,
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "m" | "min" | "mins" => 60.0,
+            "h" | "hr" | "hrs" => 3600.0,
+            "d" => 86400.0,
+            _ => return Err(invalid()),
+        };
+        total += value * seconds_per_unit;
+        rest = after_number[unit_len..].trim_start();
+    }
+
+    Duration::try_from_secs_f64(total).map_err(|_| invalid())
+}
+
+/// Parse a byte count such as `512`, `64K`, `10MB` or `1.5GiB`
+///
+/// Suffixes follow the coreutils: `K`, `M`, `G`, `T` and their `KiB`-style
+/// spellings are powers of 1024, while `KB`, `MB`, `GB` and `TB` are powers
+/// of 1000. `B` and a bare number are bytes. Suffixes are case-insensitive.
+pub fn parse_size(text: &str) -> Result<u64> {
+    let invalid = || Error::ParseError(format!("invalid size '{}'", text));
+    let (value, suffix) = split_number(text.trim()).ok_or_else(invalid)?;
+
+    let multiplier: u64 = match suffix.trim_start().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        _ => return Err(invalid()),
+    };
+
+    let bytes = value * multiplier as f64;
+    if bytes.fract() != 0.0 || bytes > u64::MAX as f64 {
+        return Err(invalid());
+    }
+    Ok(bytes as u64)
+}
+
+/// Split the leading unsigned decimal number off `text`
+fn split_number(text: &str) -> Option<(f64, &str)> {
+    let end = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let number = &text[..end];
+    if !number.contains(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((number.parse().ok()?, &text[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        for (text, expected) in [
+            ("5", Duration::from_secs(5)),
+            ("0.25", Duration::from_millis(250)),
+            ("250ms", Duration::from_millis(250)),
+            ("30s", Duration::from_secs(30)),
+            ("2m", Duration::from_secs(120)),
+            ("1.5h", Duration::from_secs(5400)),
+            ("1h30m", Duration::from_secs(5400)),
+            ("1h 30m 15s", Duration::from_secs(5415)),
+            ("1d", Duration::from_secs(86400)),
+            ("10us", Duration::from_micros(10)),
+        ] {
+            assert_eq!(parse_duration(text).unwrap(), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration_rejects_invalid() {
+        for text in ["", "abc", "-5", "5x", "h", "1..5s", "1e400"] {
+            assert!(
+                matches!(parse_duration(text), Err(Error::ParseError(_))),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_size() {
+        for (text, expected) in [
+            ("512", 512),
+            ("512B", 512),
+            ("64K", 65_536),
+            ("64k", 65_536),
+            ("4KiB", 4_096),
+            ("10KB", 10_000),
+            ("10MB", 10_000_000),
+            ("2M", 2 * 1024 * 1024),
+            ("1.5GiB", 3 * 512 * 1024 * 1024),
+            ("1 TB", 1_000_000_000_000),
+        ] {
+            assert_eq!(parse_size(text).unwrap(), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_parse_size_rejects_invalid() {
+        for text in ["", "MB", "-1", "10XB", "1.5", "0.1B"] {
+            assert!(
+                matches!(parse_size(text), Err(Error::ParseError(_))),
+                "{}",
+                text
+            );
+        }
+    }
+}
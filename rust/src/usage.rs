@@ -0,0 +1,123 @@
+//! CPU time and peak memory, from `getrusage`
+//!
+//! [`ResourceUsage`] reads what the operating system counted for this
+//! process, for the child processes it has waited for, or for both, as the
+//! `time` builtin and the CLI's `--stats` report them:
+//!
+//! ```rust
+//! use command_stream::usage::ResourceUsage;
+//!
+//! let before = ResourceUsage::children();
+//! // ... run commands ...
+//! let spent = ResourceUsage::children().since(&before);
+//! println!("{:?} user, {:?} system", spent.user, spent.system);
+//! ```
+//!
+//! Only Unix counts these; elsewhere they are all zero.
+
+use std::time::Duration;
+
+/// CPU time used and the largest resident set size reached
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// CPU time spent running the program's own code
+    pub user: Duration,
+    /// CPU time the kernel spent working for it
+    pub system: Duration,
+    /// Peak resident set size, in bytes; for children, that of the largest
+    /// child rather than a sum
+    pub max_rss: u64,
+}
+
+impl ResourceUsage {
+    /// Usage of this process so far, all its threads included
+    pub fn process() -> Self {
+        get(Who::Process)
+    }
+
+    /// Usage of the child processes that have been waited for so far
+    ///
+    /// Children started by anything else in this process count too, so
+    /// usage measured while other commands run may include some of theirs.
+    pub fn children() -> Self {
+        get(Who::Children)
+    }
+
+    /// Usage of this process and its children together, as `time` reports
+    /// for a program
+    pub fn total() -> Self {
+        let (process, children) = (Self::process(), Self::children());
+        ResourceUsage {
+            user: process.user + children.user,
+            system: process.system + children.system,
+            max_rss: process.max_rss.max(children.max_rss),
+        }
+    }
+
+    /// The CPU time used since `earlier` was read; the peak is kept as it is
+    pub fn since(&self, earlier: &ResourceUsage) -> Self {
+        ResourceUsage {
+            user: self.user.saturating_sub(earlier.user),
+            system: self.system.saturating_sub(earlier.system),
+            max_rss: self.max_rss,
+        }
+    }
+
+    /// User and system time together
+    pub fn cpu(&self) -> Duration {
+        self.user + self.system
+    }
+}
+
+enum Who {
+    Process,
+    Children,
+}
+
+#[cfg(unix)]
+fn get(who: Who) -> ResourceUsage {
+    let who = match who {
+        Who::Process => libc::RUSAGE_SELF,
+        Who::Children => libc::RUSAGE_CHILDREN,
+    };
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: getrusage fills in the rusage it is given
+    let usage = unsafe {
+        libc::getrusage(who, usage.as_mut_ptr());
+        usage.assume_init()
+    };
+    let duration = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    // macOS counts bytes, the others kilobytes
+    let unit = if cfg!(target_vendor = "apple") {
+        1
+    } else {
+        1024
+    };
+    ResourceUsage {
+        user: duration(usage.ru_utime),
+        system: duration(usage.ru_stime),
+        max_rss: (usage.ru_maxrss.max(0) as u64) * unit,
+    }
+}
+
+#[cfg(not(unix))]
+fn get(_who: Who) -> ResourceUsage {
+    ResourceUsage::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_usage_counts_this_process() {
+        let usage = ResourceUsage::process();
+        assert!(usage.max_rss > 0);
+        let total = ResourceUsage::total();
+        assert!(total.cpu() >= usage.cpu());
+        assert_eq!(total.since(&total).cpu(), Duration::ZERO);
+    }
+}
@@ -13,29 +13,86 @@
 //! - `quote` - Shell quoting utilities
 //! - `utils` (this module) - Command results and virtual command helpers
 
+use std::collections::HashMap;
 use std::env;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 // Re-export from specialized modules for backwards compatibility
 pub use crate::ansi::{AnsiConfig, AnsiUtils};
 pub use crate::quote::quote;
 pub use crate::trace::{is_trace_enabled, trace, trace_lazy};
 
+/// The longest prefix of `text` that fits in `max_bytes` without splitting a
+/// character
+pub(crate) fn truncate_at_char_boundary(text: &str, max_bytes: usize) -> String {
+    let mut end = max_bytes.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].to_string()
+}
+
 /// Result type for virtual command operations
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct CommandResult {
     pub stdout: String,
     pub stderr: String,
     pub code: i32,
+    /// Signal that terminated the process, if any (Unix only). `code` is then
+    /// the conventional `128 + signal`.
+    pub signal: Option<i32>,
+    /// Whether the process dumped core when the signal terminated it (Unix
+    /// only)
+    pub core_dumped: bool,
+    /// The content written to the command's stdin, when
+    /// [`RunOptions::capture_stdin`](crate::RunOptions::capture_stdin) is set.
+    /// Cut to that many bytes.
+    pub stdin: Option<String>,
+    /// Every output line with when it arrived, in arrival order, when
+    /// [`RunOptions::timed_lines`](crate::RunOptions::timed_lines) is set
+    pub timed_lines: Vec<TimedLine>,
 }
 
 impl CommandResult {
+    /// Create a result from a finished process's output and exit status
+    ///
+    /// A process killed by a signal has no exit code; it gets `128 + signal`,
+    /// like a shell reports it, and `signal`/`core_dumped` are filled in.
+    pub fn from_exit_status(
+        stdout: impl Into<String>,
+        stderr: impl Into<String>,
+        status: std::process::ExitStatus,
+    ) -> Self {
+        #[cfg(unix)]
+        let (signal, core_dumped) = {
+            use std::os::unix::process::ExitStatusExt;
+            (status.signal(), status.core_dumped())
+        };
+        #[cfg(not(unix))]
+        let (signal, core_dumped) = (None, false);
+
+        CommandResult {
+            stdout: stdout.into(),
+            stderr: stderr.into(),
+            code: status
+                .code()
+                .or(signal.map(|signal| 128 + signal))
+                .unwrap_or(-1),
+            signal,
+            core_dumped,
+            stdin: None,
+            timed_lines: Vec::new(),
+        }
+    }
+
     /// Create a success result with stdout output
     pub fn success(stdout: impl Into<String>) -> Self {
         CommandResult {
             stdout: stdout.into(),
             stderr: String::new(),
             code: 0,
+            ..Default::default()
         }
     }
 
@@ -45,6 +102,7 @@ impl CommandResult {
             stdout: String::new(),
             stderr: String::new(),
             code: 0,
+            ..Default::default()
         }
     }
 
@@ -54,6 +112,7 @@ impl CommandResult {
             stdout: String::new(),
             stderr: stderr.into(),
             code: 1,
+            ..Default::default()
         }
     }
 
@@ -63,6 +122,7 @@ impl CommandResult {
             stdout: String::new(),
             stderr: stderr.into(),
             code,
+            ..Default::default()
         }
     }
 
@@ -78,6 +138,177 @@ impl CommandResult {
     pub fn exit_code(&self) -> i32 {
         self.code
     }
+
+    /// What the exit status means; see [`ExitKind`]
+    pub fn exit_kind(&self) -> ExitKind {
+        match self.signal {
+            Some(signal) => ExitKind::SignalTerminated(signal),
+            None => ExitKind::from_code(self.code),
+        }
+    }
+
+    /// The first non-blank line of stdout, without its line ending
+    pub fn first_line(&self) -> Option<&str> {
+        self.stdout.lines().find(|line| !line.trim().is_empty())
+    }
+
+    /// The last non-blank line of stdout, without its line ending
+    ///
+    /// Handy for commands that print progress and then the value, like
+    /// `git rev-parse HEAD` after hook output.
+    pub fn last_line(&self) -> Option<&str> {
+        self.stdout.lines().rfind(|line| !line.trim().is_empty())
+    }
+
+    /// The `n`th whitespace-separated field (counting from 0) of each line of
+    /// stdout, like `awk '{print $(n+1)}'`
+    ///
+    /// Lines with fewer fields are skipped.
+    pub fn column(&self, n: usize) -> Vec<&str> {
+        self.stdout
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(n))
+            .collect()
+    }
+
+    /// `KEY=VALUE` lines of stdout, as printed by `env` or found in
+    /// `/etc/os-release`
+    ///
+    /// Keys and values are trimmed and one pair of matching quotes around a
+    /// value is removed. Blank lines, `#` comments and lines without `=` are
+    /// skipped; a repeated key keeps its last value.
+    pub fn key_values(&self) -> HashMap<String, String> {
+        self.stdout
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), unquote(value.trim()).to_string()))
+            .filter(|(key, _)| !key.is_empty())
+            .collect()
+    }
+}
+
+/// Which output stream a line came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamKind {
+    Stdout,
+    Stderr,
+}
+
+/// A line of output and when it arrived
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedLine {
+    /// The stream it was written to
+    pub stream: StreamKind,
+    /// Time since the command started, from a monotonic clock
+    pub at: Duration,
+    /// The line, without its line ending
+    pub text: String,
+}
+
+/// The output a still-running command has produced so far
+///
+/// See [`ProcessRunner::partial_output`](crate::ProcessRunner::partial_output).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputSnapshot {
+    /// Complete stdout lines, each with its line ending
+    pub stdout: String,
+    /// Complete stderr lines, each with its line ending
+    pub stderr: String,
+    /// Newlines in `stdout` and `stderr`, for keeping them to a
+    /// [`TailLimit`](crate::TailLimit)
+    pub(crate) lines: (usize, usize),
+}
+
+impl OutputSnapshot {
+    /// The last `lines` lines of `stream`, for showing where a command is up
+    /// to or where it got stuck
+    pub fn tail(&self, stream: StreamKind, lines: usize) -> &str {
+        let text = match stream {
+            StreamKind::Stdout => &self.stdout,
+            StreamKind::Stderr => &self.stderr,
+        };
+        if lines == 0 {
+            return "";
+        }
+        let start = text
+            .trim_end_matches('\n')
+            .rmatch_indices('\n')
+            .nth(lines - 1)
+            .map_or(0, |(i, _)| i + 1);
+        &text[start..]
+    }
+}
+
+/// How a command ended, classified from its exit code or signal
+///
+/// Lets error handling branch on what happened instead of comparing exit
+/// codes against the shell conventions by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ExitKind {
+    /// Exit code 0
+    Success,
+    /// Any other non-zero exit code
+    Failure(i32),
+    /// Exit code 127: the shell could not find the command
+    NotFound,
+    /// Exit code 126: the command was found but could not be executed
+    NotExecutable,
+    /// Killed by this signal, or an exit code of `128 + signal` as a shell
+    /// reports a child killed by a signal
+    SignalTerminated(i32),
+    /// Ran out of time: exit code 124 as `timeout(1)` reports it, an
+    /// [`Error::Timeout`](crate::Error::Timeout), or an
+    /// [`Error::Stalled`](crate::Error::Stalled)
+    Timeout,
+}
+
+impl ExitKind {
+    /// Classify a raw exit code
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            0 => ExitKind::Success,
+            124 => ExitKind::Timeout,
+            126 => ExitKind::NotExecutable,
+            127 => ExitKind::NotFound,
+            // Signal numbers stop at 64 on Linux
+            129..=192 => ExitKind::SignalTerminated(code - 128),
+            code => ExitKind::Failure(code),
+        }
+    }
+
+    /// Whether the command succeeded
+    pub fn is_success(self) -> bool {
+        self == ExitKind::Success
+    }
+}
+
+impl std::fmt::Display for ExitKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitKind::Success => write!(f, "success"),
+            ExitKind::Failure(code) => write!(f, "exit code {}", code),
+            ExitKind::NotFound => write!(f, "command not found"),
+            ExitKind::NotExecutable => write!(f, "command not executable"),
+            ExitKind::SignalTerminated(signal) => write!(f, "killed by signal {}", signal),
+            ExitKind::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+/// `value` without one pair of matching surrounding quotes
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|rest| rest.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
 }
 
 /// Utility functions for virtual commands
@@ -146,6 +377,19 @@ impl VirtualUtils {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_output_snapshot_tail() {
+        let snapshot = OutputSnapshot {
+            stdout: "a\nb\nc\n".to_string(),
+            stderr: String::new(),
+            ..OutputSnapshot::default()
+        };
+        assert_eq!(snapshot.tail(StreamKind::Stdout, 2), "b\nc\n");
+        assert_eq!(snapshot.tail(StreamKind::Stdout, 5), "a\nb\nc\n");
+        assert_eq!(snapshot.tail(StreamKind::Stdout, 0), "");
+        assert_eq!(snapshot.tail(StreamKind::Stderr, 1), "");
+    }
+
     #[test]
     fn test_command_result_success() {
         let result = CommandResult::success("hello");
@@ -0,0 +1,213 @@
+//! Walking and rewriting parsed commands
+//!
+//! [`Visit`] inspects a [`ParsedCommand`] and [`VisitMut`] changes it in
+//! place. Both call `visit_simple` for every simple command, however deeply
+//! it is nested in sequences, pipelines and subshells, so a policy only has
+//! to say what happens to one command. Returning an error stops the walk,
+//! which makes the same traits usable for rejecting commands.
+//!
+//! [`rewrite_command`] parses a command string, applies a visitor and prints
+//! the result back, ready to run:
+//!
+//! ```rust
+//! use command_stream::shell_parser::{ParsedArg, Redirect};
+//! use command_stream::visit::{rewrite_command, VisitMut};
+//! use command_stream::Result;
+//!
+//! /// Run every stage at low priority
+//! struct Nice;
+//!
+//! impl VisitMut for Nice {
+//!     fn visit_simple(
+//!         &mut self,
+//!         cmd: &mut String,
+//!         args: &mut Vec<ParsedArg>,
+//!         _redirects: &mut Vec<Redirect>,
+//!     ) -> Result<()> {
+//!         let program = std::mem::replace(cmd, "nice".to_string());
+//!         args.insert(0, ParsedArg::literal(program));
+//!         Ok(())
+//!     }
+//! }
+//!
+//! assert_eq!(
+//!     rewrite_command("make -j8 | tee 'build log.txt'", &mut Nice).unwrap(),
+//!     "nice make -j8 | nice tee 'build log.txt'"
+//! );
+//! ```
+
+use crate::shell_parser::{parse_shell_command, ParsedArg, ParsedCommand, Redirect};
+use crate::Result;
+
+/// Inspects a parsed command; see the [module docs](self)
+pub trait Visit {
+    /// Called for every command node; the default visits its children
+    fn visit_command(&mut self, command: &ParsedCommand) -> Result<()> {
+        walk_command(self, command)
+    }
+
+    /// Called for every simple command
+    fn visit_simple(
+        &mut self,
+        cmd: &str,
+        args: &[ParsedArg],
+        redirects: &[Redirect],
+    ) -> Result<()> {
+        let _ = (cmd, args, redirects);
+        Ok(())
+    }
+
+    /// Called for every pipeline before its stages are visited
+    fn visit_pipeline(&mut self, commands: &[ParsedCommand]) -> Result<()> {
+        let _ = commands;
+        Ok(())
+    }
+}
+
+/// Changes a parsed command in place; see the [module docs](self)
+pub trait VisitMut {
+    /// Called for every command node; the default visits its children
+    fn visit_command(&mut self, command: &mut ParsedCommand) -> Result<()> {
+        walk_command_mut(self, command)
+    }
+
+    /// Called for every simple command
+    fn visit_simple(
+        &mut self,
+        cmd: &mut String,
+        args: &mut Vec<ParsedArg>,
+        redirects: &mut Vec<Redirect>,
+    ) -> Result<()> {
+        let _ = (cmd, args, redirects);
+        Ok(())
+    }
+
+    /// Called for every pipeline before its stages are visited, so stages
+    /// can be added or removed
+    fn visit_pipeline(&mut self, commands: &mut Vec<ParsedCommand>) -> Result<()> {
+        let _ = commands;
+        Ok(())
+    }
+}
+
+/// Visit the children of `command`, or the command itself if it is simple
+pub fn walk_command<V: Visit + ?Sized>(visitor: &mut V, command: &ParsedCommand) -> Result<()> {
+    match command {
+        ParsedCommand::Simple {
+            cmd,
+            args,
+            redirects,
+            ..
+        } => visitor.visit_simple(cmd, args, redirects),
+        ParsedCommand::Sequence { commands, .. } => commands
+            .iter()
+            .try_for_each(|command| visitor.visit_command(command)),
+        ParsedCommand::Pipeline { commands } => {
+            visitor.visit_pipeline(commands)?;
+            commands
+                .iter()
+                .try_for_each(|command| visitor.visit_command(command))
+        }
+        ParsedCommand::Subshell { command }
+        | ParsedCommand::Background { command }
+        | ParsedCommand::Not { command }
+        | ParsedCommand::Time { command, .. } => visitor.visit_command(command),
+    }
+}
+
+/// Visit the children of `command` mutably, or the command itself if it is
+/// simple
+pub fn walk_command_mut<V: VisitMut + ?Sized>(
+    visitor: &mut V,
+    command: &mut ParsedCommand,
+) -> Result<()> {
+    match command {
+        ParsedCommand::Simple {
+            cmd,
+            args,
+            redirects,
+            ..
+        } => visitor.visit_simple(cmd, args, redirects),
+        ParsedCommand::Sequence { commands, .. } => commands
+            .iter_mut()
+            .try_for_each(|command| visitor.visit_command(command)),
+        ParsedCommand::Pipeline { commands } => {
+            visitor.visit_pipeline(commands)?;
+            commands
+                .iter_mut()
+                .try_for_each(|command| visitor.visit_command(command))
+        }
+        ParsedCommand::Subshell { command }
+        | ParsedCommand::Background { command }
+        | ParsedCommand::Not { command }
+        | ParsedCommand::Time { command, .. } => visitor.visit_command(command),
+    }
+}
+
+/// Parse `command`, apply `visitor` and print the result as a shell string
+pub fn rewrite_command<V: VisitMut + ?Sized>(command: &str, visitor: &mut V) -> Result<String> {
+    let mut parsed = parse_shell_command(command)?;
+    visitor.visit_command(&mut parsed)?;
+    Ok(parsed.to_shell_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    /// Collects program names and rejects `rm`
+    #[derive(Default)]
+    struct Programs(Vec<String>);
+
+    impl Visit for Programs {
+        fn visit_simple(&mut self, cmd: &str, _: &[ParsedArg], _: &[Redirect]) -> Result<()> {
+            if cmd == "rm" {
+                return Err(Error::ParseError("rm is not allowed".to_string()));
+            }
+            self.0.push(cmd.to_string());
+            Ok(())
+        }
+    }
+
+    struct NoColor;
+
+    impl VisitMut for NoColor {
+        fn visit_simple(
+            &mut self,
+            cmd: &mut String,
+            args: &mut Vec<ParsedArg>,
+            _: &mut Vec<Redirect>,
+        ) -> Result<()> {
+            if cmd == "ls" || cmd == "grep" {
+                args.insert(0, ParsedArg::literal("--color=never"));
+            }
+            Ok(())
+        }
+
+        fn visit_pipeline(&mut self, commands: &mut Vec<ParsedCommand>) -> Result<()> {
+            commands.retain(|command| command.to_shell_string() != "cat");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_visit_reaches_nested_commands() {
+        let parsed = parse_shell_command("(cd src && ls) | wc -l; echo done").unwrap();
+        let mut programs = Programs::default();
+        programs.visit_command(&parsed).unwrap();
+        assert_eq!(programs.0, ["cd", "ls", "wc", "echo"]);
+
+        let parsed = parse_shell_command("echo a && rm -rf b").unwrap();
+        assert!(Programs::default().visit_command(&parsed).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_command() {
+        assert_eq!(
+            rewrite_command("ls src | cat | grep -n x && echo ok", &mut NoColor).unwrap(),
+            "ls --color=never src | grep --color=never -n x && echo ok"
+        );
+        assert!(rewrite_command("", &mut NoColor).is_err());
+    }
+}
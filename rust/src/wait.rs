@@ -0,0 +1,368 @@
+//! Waiting for services started by a command to become ready
+//!
+//! Orchestration flows often start a server and then run something against
+//! it. Instead of sleeping in a loop, wait for the sign that it is ready:
+//!
+//! ```rust,no_run
+//! use command_stream::supervisor::{ProcessSpec, RestartPolicy, Supervised};
+//! use command_stream::wait::{wait_for_output, wait_for_port};
+//! use command_stream::StreamEmitter;
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let output = Arc::new(StreamEmitter::new());
+//! let spec = ProcessSpec::new("npm run dev").emitter(output.clone());
+//! let server = Supervised::spawn(spec, RestartPolicy::Never);
+//!
+//! wait_for_output(&output, r"ready in \d+ ms", Duration::from_secs(60)).await?;
+//! wait_for_port("127.0.0.1", 5173, Duration::from_secs(5)).await?;
+//! command_stream::run("npm run e2e").await?;
+//! server.stop();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each helper gives up after its timeout with an [`Error::Io`] of kind
+//! [`TimedOut`](std::io::ErrorKind::TimedOut) that says what it was waiting
+//! for.
+
+use regex::Regex;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+use crate::{Error, EventData, EventType, Result, StreamEmitter};
+
+/// First pause between checks
+const FIRST_POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Longest pause between checks
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Wait until `host:port` accepts TCP connections
+pub async fn wait_for_port(host: &str, port: u16, timeout: Duration) -> Result<()> {
+    poll_until(
+        timeout,
+        || format!("{}:{} to accept connections", host, port),
+        || async {
+            TcpStream::connect((host, port))
+                .await
+                .map(drop)
+                .map_err(|e| e.to_string())
+        },
+    )
+    .await
+}
+
+/// Wait until a file or directory exists at `path`
+pub async fn wait_for_file(path: impl AsRef<Path>, timeout: Duration) -> Result<()> {
+    let path = path.as_ref();
+    poll_until(
+        timeout,
+        || format!("{} to exist", path.display()),
+        || async {
+            match tokio::fs::try_exists(path).await {
+                Ok(true) => Ok(()),
+                Ok(false) => Err("it does not exist yet".to_string()),
+                Err(e) => Err(e.to_string()),
+            }
+        },
+    )
+    .await
+}
+
+/// Wait until a line of output reported by `emitter` matches `pattern` (a
+/// regular expression) and return that line
+///
+/// `emitter` is the one given to
+/// [`ProcessRunner::with_emitter`](crate::ProcessRunner::with_emitter) or
+/// [`ProcessSpec::emitter`](crate::supervisor::ProcessSpec::emitter); both
+/// stdout and stderr are searched. Only output reported once this is
+/// waiting is seen, so a line printed right at startup can be missed; wait
+/// for something that comes later, like a "listening" message.
+pub async fn wait_for_output(
+    emitter: &StreamEmitter,
+    pattern: &str,
+    timeout: Duration,
+) -> Result<String> {
+    let regex = Regex::new(pattern)
+        .map_err(|e| Error::ParseError(format!("invalid output pattern: {}", e)))?;
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    for event in [EventType::Stdout, EventType::Stderr] {
+        let tx = tx.clone();
+        // The listener stays registered but does nothing once `rx` is gone
+        emitter
+            .on(event, move |data| {
+                if let EventData::String(chunk) = data {
+                    let _ = tx.send(chunk);
+                }
+            })
+            .await;
+    }
+    drop(tx);
+
+    let found = async {
+        while let Some(chunk) = rx.recv().await {
+            if let Some(line) = chunk.lines().find(|line| regex.is_match(line)) {
+                return Some(line.to_string());
+            }
+        }
+        None
+    };
+    match tokio::time::timeout(timeout, found).await {
+        Ok(Some(line)) => Ok(line),
+        _ => Err(timed_out(
+            timeout,
+            &format!("output matching '{}'", pattern),
+        )),
+    }
+}
+
+/// Wait until `url` answers a `GET` request with a 2xx status and return
+/// that status
+///
+/// Needs the `http` feature. Only plain `http://` URLs are supported, which
+/// covers the local services this is meant for.
+#[cfg(feature = "http")]
+pub async fn wait_for_http_ok(url: &str, timeout: Duration) -> Result<u16> {
+    let target = http::Target::parse(url)?;
+    poll_until(
+        timeout,
+        || format!("{} to respond with a 2xx status", url),
+        || async {
+            match target.get_status().await {
+                Ok(status) if (200..300).contains(&status) => Ok(status),
+                Ok(status) => Err(format!("status {}", status)),
+                Err(e) => Err(e.to_string()),
+            }
+        },
+    )
+    .await
+}
+
+#[cfg(feature = "http")]
+mod http {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use crate::{Error, Result};
+
+    /// Longest status line read
+    const MAX_STATUS_LINE: usize = 1024;
+
+    /// Where a plain `http://` URL points
+    #[derive(Debug, PartialEq)]
+    pub(super) struct Target {
+        host: String,
+        port: u16,
+        /// `host[:port]` as written, for the `Host` header
+        authority: String,
+        path: String,
+    }
+
+    impl Target {
+        pub(super) fn parse(url: &str) -> Result<Target> {
+            let invalid = |reason: &str| Error::ParseError(format!("{}: {}", reason, url));
+            let rest = match url.split_once("://") {
+                Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => rest,
+                Some(_) => return Err(invalid("only http:// URLs are supported")),
+                None => return Err(invalid("not an http:// URL")),
+            };
+            let (authority, path) = match rest.find(['/', '?']) {
+                Some(index) => (&rest[..index], &rest[index..]),
+                None => (rest, "/"),
+            };
+            let hostport = authority
+                .rsplit_once('@')
+                .map_or(authority, |(_, host)| host);
+            let (host, port) = match hostport.rsplit_once(':') {
+                Some((host, port)) if !port.contains(']') => {
+                    (host, port.parse().map_err(|_| invalid("invalid port"))?)
+                }
+                _ => (hostport, 80),
+            };
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            if host.is_empty() {
+                return Err(invalid("missing host"));
+            }
+            Ok(Target {
+                host: host.to_string(),
+                port,
+                authority: hostport.to_string(),
+                path: if path.starts_with('?') {
+                    format!("/{}", path)
+                } else {
+                    path.to_string()
+                },
+            })
+        }
+
+        /// Send a `GET` and read the response status
+        pub(super) async fn get_status(&self) -> std::io::Result<u16> {
+            let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+            let request = format!(
+                "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: command-stream\r\nConnection: close\r\n\r\n",
+                self.path, self.authority
+            );
+            stream.write_all(request.as_bytes()).await?;
+
+            let mut response = Vec::new();
+            let mut buffer = [0; 256];
+            while !response.windows(2).any(|pair| pair == b"\r\n") {
+                let read = stream.read(&mut buffer).await?;
+                if read == 0 || response.len() > MAX_STATUS_LINE {
+                    break;
+                }
+                response.extend_from_slice(&buffer[..read]);
+            }
+            let response = String::from_utf8_lossy(&response);
+            let status = response
+                .strip_prefix("HTTP/")
+                .and_then(|rest| rest.split_whitespace().nth(1))
+                .and_then(|status| status.parse().ok());
+            status.ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "not an HTTP response".to_string(),
+                )
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_parse_target() {
+            let target = Target::parse("http://localhost:8080/health?full=1").unwrap();
+            assert_eq!(
+                target,
+                Target {
+                    host: "localhost".to_string(),
+                    port: 8080,
+                    authority: "localhost:8080".to_string(),
+                    path: "/health?full=1".to_string(),
+                }
+            );
+            let target = Target::parse("HTTP://[::1]").unwrap();
+            assert_eq!((target.host.as_str(), target.port), ("::1", 80));
+            assert_eq!(target.path, "/");
+            assert!(Target::parse("https://example.com").is_err());
+            assert!(Target::parse("localhost:80").is_err());
+            assert!(Target::parse("http://host:port/").is_err());
+        }
+    }
+}
+
+/// Run `check` until it succeeds, pausing a little longer each time
+///
+/// On timeout the error names `what` was awaited and the last failure.
+async fn poll_until<T, F, Fut>(
+    timeout: Duration,
+    what: impl FnOnce() -> String,
+    mut check: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<T, String>>,
+{
+    let deadline = Instant::now() + timeout;
+    let mut interval = FIRST_POLL_INTERVAL;
+    let mut last_failure = None;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match tokio::time::timeout(left, check()).await {
+            Ok(Ok(value)) => return Ok(value),
+            Ok(Err(failure)) => last_failure = Some(failure),
+            Err(_) => {}
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            let mut what = what();
+            if let Some(failure) = last_failure {
+                what = format!("{} (last attempt: {})", what, failure);
+            }
+            return Err(timed_out(timeout, &what));
+        }
+        tokio::time::sleep(interval.min(left)).await;
+        interval = (interval * 2).min(MAX_POLL_INTERVAL);
+    }
+}
+
+fn timed_out(timeout: Duration, what: &str) -> Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("timed out after {:?} waiting for {}", timeout, what),
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_for_port() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        wait_for_port("127.0.0.1", port, Duration::from_secs(2))
+            .await
+            .unwrap();
+
+        drop(listener);
+        let err = wait_for_port("127.0.0.1", port, Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("to accept connections"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ready");
+        let created = path.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            std::fs::write(created, "").unwrap();
+        });
+        wait_for_file(&path, Duration::from_secs(2)).await.unwrap();
+
+        let missing = dir.path().join("never");
+        assert!(wait_for_file(&missing, Duration::from_millis(50))
+            .await
+            .is_err());
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn test_wait_for_http_ok() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            for status in ["503 Service Unavailable", "200 OK"] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let url = format!("http://127.0.0.1:{}/health", port);
+        let status = wait_for_http_ok(&url, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(status, 200);
+
+        let err = wait_for_http_ok(&url, Duration::from_millis(100))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("last attempt"), "{}", err);
+    }
+}
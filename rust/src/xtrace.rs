@@ -0,0 +1,142 @@
+//! Where `set -x` traces go and how they look
+//!
+//! With `xtrace` on, each command is written before it runs, like a shell
+//! writes it: after the expanded `PS4` prefix, `+ ` unless the command's
+//! environment sets `PS4`, on stderr. An [`XtraceConfig`] set with
+//! [`set_xtrace_config`] changes the prefix, sends the traces to a file or
+//! any writer so they can be collected apart from the commands' own
+//! stderr, and can start each line with the time:
+//!
+//! ```rust,no_run
+//! use command_stream::xtrace::{set_xtrace_config, XtraceConfig};
+//! use command_stream::set_shell_option;
+//!
+//! # async fn example() -> command_stream::Result<()> {
+//! let config = XtraceConfig::new()
+//!     .prefix("[$?] > ")
+//!     .file("trace.log")?
+//!     .timestamps(true);
+//! set_xtrace_config(Some(config.into()));
+//! set_shell_option("xtrace").await;
+//!
+//! command_stream::run("make build").await?;
+//! // trace.log: 2026-10-16T09:30:00.000Z [0] > make build
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The prefix is expanded like a double-quoted word of the command: its
+//! variables and its `$?`, `$0` and the like. Command substitutions in it
+//! are written as they are.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
+use chrono::Utc;
+use once_cell::sync::Lazy;
+
+use crate::expand::{self, Context};
+use crate::Result;
+
+/// The prefix when the command's environment has no `PS4`
+pub const DEFAULT_PS4: &str = "+ ";
+
+/// How `xtrace` output is written
+#[derive(Clone, Default)]
+pub struct XtraceConfig {
+    prefix: Option<String>,
+    output: Option<Arc<Mutex<dyn Write + Send>>>,
+    timestamps: bool,
+}
+
+impl fmt::Debug for XtraceConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("XtraceConfig")
+            .field("prefix", &self.prefix)
+            .field("to_writer", &self.output.is_some())
+            .field("timestamps", &self.timestamps)
+            .finish()
+    }
+}
+
+impl XtraceConfig {
+    /// Traces on stderr, after the command's `PS4` or `+ `, without times
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `ps4` as the prefix, whatever the command's `PS4` is
+    pub fn prefix(mut self, ps4: impl Into<String>) -> Self {
+        self.prefix = Some(ps4.into());
+        self
+    }
+
+    /// Write the traces to `writer` instead of stderr
+    pub fn writer(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.output = Some(Arc::new(Mutex::new(writer)));
+        self
+    }
+
+    /// Append the traces to the file at `path`, creating it if needed
+    pub fn file(self, path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(self.writer(file))
+    }
+
+    /// Start each trace with the UTC time, to the millisecond
+    pub fn timestamps(mut self, enabled: bool) -> Self {
+        self.timestamps = enabled;
+        self
+    }
+
+    /// The line tracing `command`, without its newline
+    fn line(&self, command: &str, context: &Context) -> String {
+        let ps4 = match &self.prefix {
+            Some(prefix) => prefix.clone(),
+            None => context
+                .variable("PS4")
+                .unwrap_or_else(|| DEFAULT_PS4.to_string()),
+        };
+        let prefix = expand::expand_prompt(&ps4, context);
+        match self.timestamps {
+            true => format!(
+                "{} {}{}",
+                Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+                prefix,
+                command
+            ),
+            false => format!("{}{}", prefix, command),
+        }
+    }
+}
+
+static CONFIG: Lazy<RwLock<Option<Arc<XtraceConfig>>>> = Lazy::new(|| RwLock::new(None));
+
+/// Write `xtrace` output as `config` says; `None` restores the default
+pub fn set_xtrace_config(config: Option<Arc<XtraceConfig>>) {
+    *CONFIG.write().unwrap_or_else(|e| e.into_inner()) = config;
+}
+
+/// The configuration set with [`set_xtrace_config`]
+pub fn xtrace_config() -> Option<Arc<XtraceConfig>> {
+    CONFIG.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Trace `command`, already redacted, which is about to run in `context`
+pub(crate) fn trace_command(command: &str, context: &Context) {
+    let config = xtrace_config().unwrap_or_default();
+    let line = format!("{}\n", config.line(command, context));
+    // Like the shell's, a trace that can't be written is dropped
+    let _ = match &config.output {
+        Some(output) => {
+            let mut output = output.lock().unwrap_or_else(|e| e.into_inner());
+            output
+                .write_all(line.as_bytes())
+                .and_then(|()| output.flush())
+        }
+        None => io::stderr().lock().write_all(line.as_bytes()),
+    };
+}
@@ -0,0 +1,50 @@
+//! Tests for aliases expanded where a command name goes
+
+use command_stream::aliases::{alias, alias_of, aliases, clear_aliases};
+use command_stream::{ProcessRunner, RunOptions, Session};
+
+async fn run(command: &str) -> command_stream::CommandResult {
+    let options = RunOptions::builder().mirror(false).build();
+    ProcessRunner::new(command, options).run().await.unwrap()
+}
+
+// A single test, since the global aliases are process-wide state.
+#[cfg(unix)]
+#[tokio::test]
+async fn test_aliases_expand_at_each_command_name() {
+    alias("greet", "echo hello");
+    alias("echo", "echo said:");
+    let result = run("greet you && (greet) | cat; echo greet").await;
+    assert_eq!(result.stdout, "said: hello you\nsaid: hello\nsaid: greet\n");
+    // Quoted, or not where a command name goes, they stay as they are
+    assert_eq!(run("'greet' 2>/dev/null || true greet").await.code, 0);
+
+    // The builtins define, show and remove them
+    run("alias ll='ls -la' shout='echo HEY'").await;
+    assert_eq!(alias_of("ll").as_deref(), Some("ls -la"));
+    assert_eq!(run("shout").await.stdout, "said: HEY\n");
+    assert_eq!(run("alias shout").await.stdout, "alias shout='echo HEY'\n");
+    let listed = run("alias").await.stdout;
+    assert!(listed.contains("alias greet='echo hello'\n"), "{}", listed);
+    let result = run("unalias echo missing").await;
+    assert_eq!(result.code, 1);
+    assert_eq!(result.stderr, "unalias: missing: not found\n");
+    assert_eq!(run("shout").await.stdout, "HEY\n");
+    assert_eq!(run("alias 'a b=c'").await.code, 1);
+
+    // A session's own aliases take precedence, and its builtins change
+    // only those
+    let session = Session::with_options(RunOptions::builder().mirror(false).build());
+    session.run("alias greet='echo hi'").await.unwrap();
+    assert_eq!(
+        session.run("greet && shout").await.unwrap().stdout,
+        "hi\nHEY\n"
+    );
+    assert_eq!(alias_of("greet").as_deref(), Some("echo hello"));
+
+    run("unalias -a").await;
+    assert!(aliases().is_empty());
+    alias("x", "true");
+    clear_aliases();
+    assert!(aliases().is_empty());
+}
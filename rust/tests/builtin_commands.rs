@@ -3,12 +3,10 @@
 //! These tests mirror the JavaScript tests in js/tests/builtin-commands.test.mjs
 
 use command_stream::commands::{
-    basename, cat, cd, cp, dirname, echo, env, exit, ls, mkdir, mv, pwd, rm, seq, sleep, test,
-    touch, which, yes, CommandContext,
+    basename, cat, cp, dirname, echo, env, exit, ls, mkdir, mv, pwd, rm, seq, sleep, test, touch,
+    which, yes, CommandContext,
 };
-use command_stream::utils::CommandResult;
 use std::fs;
-use std::path::PathBuf;
 use tempfile::TempDir;
 
 /// Helper to create a command context with args
@@ -20,18 +18,9 @@ fn ctx(args: Vec<&str>) -> CommandContext {
         env: None,
         output_tx: None,
         is_cancelled: None,
-    }
-}
-
-/// Helper to create a command context with args and cwd
-fn ctx_with_cwd(args: Vec<&str>, cwd: PathBuf) -> CommandContext {
-    CommandContext {
-        args: args.into_iter().map(String::from).collect(),
-        stdin: None,
-        cwd: Some(cwd),
-        env: None,
-        output_tx: None,
-        is_cancelled: None,
+        cancel_token: None,
+        shell_settings: None,
+        executor: None,
     }
 }
 
@@ -44,6 +33,9 @@ fn ctx_with_stdin(args: Vec<&str>, stdin: &str) -> CommandContext {
         env: None,
         output_tx: None,
         is_cancelled: None,
+        cancel_token: None,
+        shell_settings: None,
+        executor: None,
     }
 }
 
@@ -130,6 +122,22 @@ async fn test_cat_from_stdin() {
     assert_eq!(result.stdout, "stdin input");
 }
 
+#[tokio::test]
+async fn test_cat_standard_stream_operands() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("middle.txt");
+    fs::write(&file_path, "middle\n").unwrap();
+
+    let args = vec!["-", file_path.to_str().unwrap(), "/dev/stdin", "/dev/null"];
+    let result = cat(ctx_with_stdin(args, "first\n")).await;
+    assert!(result.is_success(), "{:?}", result);
+    assert_eq!(result.stdout, "first\nmiddle\n");
+
+    let result = cat(ctx(vec!["/dev/null"])).await;
+    assert!(result.is_success());
+    assert_eq!(result.stdout, "");
+}
+
 #[tokio::test]
 async fn test_cat_nonexistent_file() {
     let result = cat(ctx(vec!["nonexistent.txt"])).await;
@@ -137,6 +145,21 @@ async fn test_cat_nonexistent_file() {
     assert!(result.stderr.contains("No such file or directory"));
 }
 
+#[tokio::test]
+async fn test_cat_continues_after_missing_file() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("present.txt");
+    fs::write(&file_path, "present\n").unwrap();
+
+    let result = cat(ctx(vec!["missing.txt", file_path.to_str().unwrap()])).await;
+    assert_eq!(result.code, 1);
+    assert_eq!(result.stdout, "present\n");
+    assert_eq!(
+        result.stderr,
+        "cat: missing.txt: No such file or directory\n"
+    );
+}
+
 // ============================================================================
 // Ls Command Tests
 // ============================================================================
@@ -471,6 +494,9 @@ async fn test_yes_with_cancel() {
         env: None,
         output_tx: None,
         is_cancelled: Some(Box::new(move || cancelled.load(Ordering::SeqCst))),
+        cancel_token: None,
+        shell_settings: None,
+        executor: None,
     };
 
     let result = yes(ctx).await;
@@ -551,3 +577,49 @@ async fn test_env_list() {
     // Should contain some environment variables
     assert!(!result.stdout.is_empty());
 }
+
+// ============================================================================
+// Argument Parsing Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_combined_flags_in_any_order() {
+    let dir = TempDir::new().unwrap();
+    let source = dir.path().join("src");
+    fs::create_dir_all(source.join("inner")).unwrap();
+    fs::write(source.join("inner/file.txt"), "x").unwrap();
+    let copy = dir.path().join("copy");
+
+    let result = cp(ctx(vec![
+        "-fR",
+        source.to_str().unwrap(),
+        copy.to_str().unwrap(),
+    ]))
+    .await;
+    assert!(result.is_success(), "{}", result.stderr);
+    assert!(copy.join("inner/file.txt").exists());
+
+    let result = rm(ctx(vec![copy.to_str().unwrap(), "-Rf"])).await;
+    assert!(result.is_success(), "{}", result.stderr);
+    assert!(!copy.exists());
+}
+
+#[tokio::test]
+async fn test_unknown_option_is_rejected() {
+    let result = rm(ctx(vec!["-z", "file.txt"])).await;
+    assert_eq!(result.code, 1);
+    assert_eq!(result.stderr, "rm: invalid option -- 'z'\n");
+
+    let result = ls(ctx(vec!["--bogus"])).await;
+    assert_eq!(result.stderr, "ls: unrecognized option '--bogus'\n");
+}
+
+#[tokio::test]
+async fn test_touch_no_create() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("absent.txt");
+
+    let result = touch(ctx(vec!["-c", file_path.to_str().unwrap()])).await;
+    assert!(result.is_success());
+    assert!(!file_path.exists());
+}
@@ -0,0 +1,98 @@
+//! Integration tests for cached command results
+
+use command_stream::cache::{invalidate, run_cached_with};
+use command_stream::{CachePolicy, RunOptions};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// A command that counts its runs in `dir/count`
+#[cfg(unix)]
+fn counting_command(dir: &TempDir, tag: &str) -> String {
+    format!(
+        "echo {} >> {} && wc -l < {}",
+        tag,
+        dir.path().join("count").display(),
+        dir.path().join("count").display()
+    )
+}
+
+#[cfg(unix)]
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_cached_reuses_result_until_ttl() {
+    let dir = TempDir::new().unwrap();
+    let command = counting_command(&dir, "ttl");
+    let policy = CachePolicy::ttl(Duration::from_millis(300));
+
+    let first = run_cached_with(&command, RunOptions::default().quiet(), policy.clone())
+        .await
+        .unwrap();
+    let second = run_cached_with(&command, RunOptions::default().quiet(), policy.clone())
+        .await
+        .unwrap();
+    assert_eq!(first.stdout.trim(), "1");
+    assert_eq!(second.stdout.trim(), "1");
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    let third = run_cached_with(&command, RunOptions::default().quiet(), policy)
+        .await
+        .unwrap();
+    assert_eq!(third.stdout.trim(), "2");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_cached_reruns_when_input_changes() {
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("input.txt");
+    std::fs::write(&input, "a").unwrap();
+    let command = counting_command(&dir, "input");
+    let policy = CachePolicy::forever().input(&input);
+
+    let first = run_cached_with(&command, RunOptions::default().quiet(), policy.clone())
+        .await
+        .unwrap();
+    let cached = run_cached_with(&command, RunOptions::default().quiet(), policy.clone())
+        .await
+        .unwrap();
+    assert_eq!((first.stdout.trim(), cached.stdout.trim()), ("1", "1"));
+
+    std::fs::write(&input, "changed").unwrap();
+    let rerun = run_cached_with(&command, RunOptions::default().quiet(), policy.clone())
+        .await
+        .unwrap();
+    assert_eq!(rerun.stdout.trim(), "2");
+
+    invalidate(&command);
+    let after_invalidate = run_cached_with(&command, RunOptions::default().quiet(), policy)
+        .await
+        .unwrap();
+    assert_eq!(after_invalidate.stdout.trim(), "3");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_cached_skips_failures() {
+    let dir = TempDir::new().unwrap();
+    let command = format!("{} && false", counting_command(&dir, "fail"));
+
+    for expected in [1, 2] {
+        let result = run_cached_with(
+            &command,
+            RunOptions::default().quiet(),
+            CachePolicy::forever(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.code, 1);
+        assert_eq!(result.stdout.trim(), expected.to_string());
+    }
+    let policy = CachePolicy::forever().cache_failures(true);
+    run_cached_with(&command, RunOptions::default().quiet(), policy.clone())
+        .await
+        .unwrap();
+    let cached = run_cached_with(&command, RunOptions::default().quiet(), policy)
+        .await
+        .unwrap();
+    assert_eq!(cached.stdout.trim(), "3");
+}
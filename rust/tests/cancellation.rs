@@ -0,0 +1,109 @@
+//! Tests for cancelling runners, pipelines and virtual commands with a
+//! shared `CancellationToken`
+
+use command_stream::{
+    CancellationToken, Error, Pipeline, ProcessRunner, RunOptions, StreamingRunner,
+};
+use std::time::{Duration, Instant};
+
+fn cancel_after(token: &CancellationToken, delay: Duration) {
+    let token = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        token.cancel();
+    });
+}
+
+fn options_with(token: &CancellationToken) -> RunOptions {
+    RunOptions {
+        mirror: false,
+        cancel: Some(token.clone()),
+        ..Default::default()
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_cancel_kills_running_process() {
+    let token = CancellationToken::new();
+    cancel_after(&token, Duration::from_millis(100));
+
+    let started = Instant::now();
+    let mut runner = ProcessRunner::new("sh -c 'sleep 5'", options_with(&token));
+    let err = runner.run().await.unwrap_err();
+
+    assert!(matches!(err, Error::Cancelled));
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[tokio::test]
+async fn test_cancel_stops_virtual_command() {
+    let token = CancellationToken::new();
+    cancel_after(&token, Duration::from_millis(100));
+
+    let started = Instant::now();
+    let mut runner = ProcessRunner::new("sleep 5", options_with(&token));
+    let err = runner.run().await.unwrap_err();
+
+    assert!(matches!(err, Error::Cancelled));
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[tokio::test]
+async fn test_already_cancelled_token_prevents_start() {
+    let token = CancellationToken::new();
+    token.cancel();
+
+    let mut runner = ProcessRunner::new("echo never", options_with(&token));
+    assert!(matches!(runner.run().await, Err(Error::Cancelled)));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_runner_token_cancels_from_another_task() {
+    let mut runner = ProcessRunner::new(
+        "sh -c 'sleep 5'",
+        RunOptions {
+            mirror: false,
+            ..Default::default()
+        },
+    );
+    cancel_after(&runner.cancel_token(), Duration::from_millis(100));
+
+    assert!(matches!(runner.run().await, Err(Error::Cancelled)));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_cancel_stops_pipeline() {
+    let token = CancellationToken::new();
+    cancel_after(&token, Duration::from_millis(100));
+
+    let started = Instant::now();
+    let result = Pipeline::new()
+        .add("sh -c 'sleep 5; echo late'")
+        .add("cat")
+        .mirror_output(false)
+        .cancel_token(token)
+        .run()
+        .await;
+
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_cancel_stops_streaming_runner() {
+    let token = CancellationToken::new();
+    cancel_after(&token, Duration::from_millis(100));
+
+    let started = Instant::now();
+    let result = StreamingRunner::new("sleep 5")
+        .cancel_token(token)
+        .collect()
+        .await;
+
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
@@ -0,0 +1,263 @@
+//! Tests for the command-stream CLI binary
+
+use std::process::{Command, Stdio};
+
+#[test]
+fn test_cli_prints_virtual_command_output_once() {
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["echo", "-n", "hello"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_prints_process_stderr_once() {
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .arg("sh -c 'echo oops >&2; exit 3'")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "oops\n");
+}
+
+#[test]
+fn test_cli_respects_mirror_environment_default() {
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["echo", "hidden"])
+        .env("COMMAND_STREAM_MIRROR", "false")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_runs_script_files() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("greet.csh");
+    std::fs::write(
+        &script,
+        format!(
+            "#!{}\necho \"hello $1\"\nset -e\nsh -c 'exit 4'\necho unreachable\n",
+            env!("CARGO_BIN_EXE_command-stream")
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // Run through the shebang, as an interpreter
+    let output = Command::new(&script).arg("world").output().unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello world\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("greet.csh:4:1: "), "{}", stderr);
+
+    // And with --file
+    std::fs::write(&script, "echo 'unterminated\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["--file", &script.to_string_lossy()])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_runs_files_for_other_interpreters_as_commands() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Not a script of ours, so it runs with the interpreter its #! names
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("show");
+    std::fs::write(&file, "#!/bin/cat\nnot ( a script\n").unwrap();
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "#!/bin/cat\nnot ( a script\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_runs_destructive_commands_without_a_terminal() {
+    use std::os::unix::process::CommandExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let build = dir.path().join("build");
+    std::fs::create_dir(&build).unwrap();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_command-stream"));
+    command
+        .arg(format!("rm -rf {}", build.display()))
+        .stdin(Stdio::null());
+    // A new session has no controlling terminal, as under CI
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!build.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_runs_commands_on_a_backend() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // A stand-in for ssh that shows what it was asked to run
+    let dir = tempfile::tempdir().unwrap();
+    let ssh = dir.path().join("ssh");
+    std::fs::write(&ssh, "#!/bin/sh\necho \"ssh $*\"\n").unwrap();
+    std::fs::set_permissions(&ssh, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        dir.path().display(),
+        std::env::var("PATH").unwrap()
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["--shell", "posix", "--backend", "ssh://deploy@web1:2222"])
+        .arg("echo hi && uptime")
+        .env("PATH", &path)
+        .env("COMMAND_STREAM_SSH_IDENTITY", "/keys/deploy")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "ssh -p 2222 -i /keys/deploy deploy@web1 -- echo hi && uptime\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["echo", "hi"])
+        .env("COMMAND_STREAM_BACKEND", "ftp://web1")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("invalid backend `ftp://web1`"),
+        "{}",
+        stderr
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_runs_scripts_on_a_backend() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // A stand-in for docker that shows what it was asked to run
+    let dir = tempfile::tempdir().unwrap();
+    let docker = dir.path().join("docker");
+    std::fs::write(&docker, "#!/bin/sh\nprintf '%s\\n' \"$@\"\n").unwrap();
+    std::fs::set_permissions(&docker, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        dir.path().display(),
+        std::env::var("PATH").unwrap()
+    );
+    let script = dir.path().join("deploy.csh");
+    std::fs::write(&script, "cd /only-on-remote\nexport STAGE=$1\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["--backend", "docker://nosuch"])
+        .arg(&script)
+        .arg("prod")
+        .env("PATH", &path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "exec\n-i\nnosuch\nsh\n-c\nset -- prod\ncd /only-on-remote\nexport STAGE=$1\n\n"
+    );
+}
+
+#[test]
+fn test_cli_reports_time_and_stats() {
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["--time", "echo", "hello"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let names: Vec<&str> = stderr
+        .lines()
+        .filter_map(|line| line.split('\t').next())
+        .filter(|name| !name.is_empty())
+        .collect();
+    assert_eq!(names, ["real", "user", "sys"], "{}", stderr);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["--stats=json", "echo", "hello"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("{\"real\":"), "{}", stderr);
+    assert!(stderr.contains("\"max_rss_bytes\":"), "{}", stderr);
+    assert!(
+        stderr.ends_with(",\"stdout_bytes\":6,\"stderr_bytes\":0}\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_cli_prints_completion_scripts() {
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["--completions", "fish"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let script = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        script.contains("complete -c command-stream -l backend -x"),
+        "{}",
+        script
+    );
+    assert!(script.contains("'alias basename cat"), "{}", script);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["--completions", "tcsh"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_cli_completions_to_a_closed_pipe_succeed() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .args(["--completions", "bash"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Stop reading before anything is written, as `| head -0` would
+    drop(child.stdout.take());
+    let output = child.wait_with_output().unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
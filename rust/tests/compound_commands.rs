@@ -0,0 +1,231 @@
+//! Tests for compound commands executed in-process
+//!
+//! `cd` changes this process's directory, so these live in their own test
+//! binary.
+
+use command_stream::{Error, ProcessRunner, RunOptions, ShellSettings};
+
+async fn run(command: &str) -> command_stream::Result<command_stream::CommandResult> {
+    ProcessRunner::new(command, RunOptions::default().quiet())
+        .run()
+        .await
+}
+
+#[tokio::test]
+async fn test_cd_applies_to_later_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let before = std::env::current_dir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+
+    let result = run(&format!(
+        "(mkdir -p '{0}/x' && cd '{0}/x' && pwd && ls ..)",
+        root.display()
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(result.stdout, format!("{}/x\nx\n", root.display()));
+    assert_eq!(std::env::current_dir().unwrap(), before);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_cd_leaves_the_process_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    let before = std::env::current_dir().unwrap();
+    let pwd = std::env::var_os("PWD");
+
+    let result = run(&format!(
+        "cd '{}' && pwd && echo $PWD && cd - > /dev/null && pwd",
+        root.display()
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(
+        result.stdout,
+        format!("{0}\n{0}\n{1}\n", root.display(), before.display())
+    );
+    assert_eq!(std::env::current_dir().unwrap(), before);
+    assert_eq!(std::env::var_os("PWD"), pwd);
+}
+
+#[tokio::test]
+async fn test_and_or_lists() {
+    let result = run("true && echo a; false && echo skipped || echo b")
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "a\nb\n");
+    assert!(result.is_success());
+
+    let result = run("echo a && false").await.unwrap();
+    assert_eq!(result.stdout, "a\n");
+    assert_eq!(result.code, 1);
+}
+
+#[tokio::test]
+async fn test_exit_ends_the_list() {
+    let result = run("echo a; exit 3; echo b").await.unwrap();
+    assert_eq!(result.stdout, "a\n");
+    assert_eq!(result.code, 3);
+
+    let result = run("(exit 4); echo after").await.unwrap();
+    assert_eq!(result.stdout, "after\n");
+}
+
+#[tokio::test]
+async fn test_errexit_stops_the_list() {
+    let options = RunOptions {
+        shell_settings: Some(ShellSettings {
+            errexit: true,
+            ..Default::default()
+        }),
+        ..RunOptions::default().quiet()
+    };
+    let result = ProcessRunner::new("false || echo tested; false; echo unreachable", options)
+        .run()
+        .await;
+    assert!(matches!(result, Err(Error::CommandFailed { .. })));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pipeline_with_virtual_stage() {
+    let result = run("echo hello | tr a-z A-Z && echo done").await.unwrap();
+    assert_eq!(result.stdout, "HELLO\ndone\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdin_goes_to_first_reader() {
+    let options = RunOptions {
+        stdin: command_stream::StdinOption::Content("input\n".to_string()),
+        ..RunOptions::default().quiet()
+    };
+    let result = ProcessRunner::new("cat; echo next; cat", options)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "input\nnext\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pipeline_mixes_virtual_and_system_stages() {
+    let result = run("seq 100 | grep 5 | wc -l").await.unwrap();
+    assert_eq!(result.stdout.trim(), "19");
+
+    let result = run("printf 'b\\na\\n' | cat | sort").await.unwrap();
+    assert_eq!(result.stdout, "a\nb\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pipeline_stops_virtual_stage_when_reader_exits() {
+    let result = run("yes | head -n 3").await.unwrap();
+    assert_eq!(result.stdout, "y\ny\ny\n");
+    assert!(result.is_success());
+
+    let within = std::time::Duration::from_secs(10);
+    let result = tokio::time::timeout(within, run("cat /dev/zero | head -c 1 | wc -c"))
+        .await
+        .expect("cat kept reading after head exited")
+        .unwrap();
+    assert_eq!(result.stdout.trim(), "1");
+
+    let result = tokio::time::timeout(within, run("seq 1 50000000 | head -1"))
+        .await
+        .expect("seq kept writing after head exited")
+        .unwrap();
+    assert_eq!(result.stdout, "1\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pipeline_streams_system_output_through_virtual_stage() {
+    let result = run("sh -c 'exec yes' | head -n 5000 | cat | wc -l")
+        .await
+        .unwrap();
+    assert_eq!(result.stdout.trim(), "5000");
+}
+
+#[tokio::test]
+async fn test_globs_expand_after_cd() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("found.txt"), "").unwrap();
+
+    let result = run(&format!(
+        "(cd '{}' && echo *.txt | cat)",
+        dir.path().display()
+    ))
+    .await
+    .unwrap();
+    assert_eq!(result.stdout, "found.txt\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_lists_setting_variables_run_in_the_shell() {
+    let result = run("NAME=shell; echo $NAME").await.unwrap();
+    assert_eq!(result.stdout, "shell\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_lists_with_shell_keywords_run_in_the_shell() {
+    let result = run("echo start; if true; then echo y; fi").await.unwrap();
+    assert_eq!(result.stdout, "start\ny\n");
+
+    let result = run("for i in 1 2; do echo $i; done; echo end")
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "1\n2\nend\n");
+
+    let result = run("echo start; while false; do echo x; done; echo end")
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "start\nend\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_negation_inverts_the_status() {
+    let result = run("! grep -q x /dev/null && echo not found")
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "not found\n");
+    assert!(result.is_success());
+    assert_eq!(run("! true | cat").await.unwrap().code, 1);
+
+    // A negated failure doesn't stop a list under `set -e`
+    let options = RunOptions::builder()
+        .mirror(false)
+        .shell_settings(ShellSettings {
+            errexit: true,
+            ..ShellSettings::new()
+        })
+        .build();
+    let result = ProcessRunner::new("! echo a; echo b", options)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "a\nb\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_time_reports_on_stderr() {
+    let result = run("time sleep 0.1 && echo done").await.unwrap();
+    assert_eq!(result.stdout, "done\n");
+    let lines: Vec<_> = result.stderr.lines().collect();
+    assert_eq!(lines[0], "");
+    assert!(lines[1].starts_with("real\t0m0.1"), "{:?}", lines);
+    assert!(lines[2].starts_with("user\t0m"));
+    assert!(lines[3].starts_with("sys\t0m"));
+
+    let result = run("time -p ! echo hi | cat").await.unwrap();
+    assert_eq!((result.stdout.as_str(), result.code), ("hi\n", 1));
+    assert!(result.stderr.starts_with("real 0."), "{}", result.stderr);
+    assert!(result.stderr.contains("\nuser ") && result.stderr.contains("\nsys "));
+}
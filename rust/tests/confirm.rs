@@ -0,0 +1,85 @@
+//! Integration tests for confirmation of destructive commands
+
+use command_stream::confirm::{Classifier, ConfirmationGate};
+use command_stream::{Error, Pipeline, ProcessRunner, RunOptions, StreamingRunner};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_declined_command_does_not_run() {
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir(dir.path().join("build")).unwrap();
+    let command = format!("rm -rf {}", dir.path().join("build").display());
+
+    let gate = Arc::new(ConfirmationGate::new(|_, _| false));
+    let options = RunOptions::builder()
+        .mirror(false)
+        .confirm(gate.clone())
+        .build();
+    let err = ProcessRunner::new(&command, options)
+        .run()
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, Error::NotConfirmed { rule, .. } if rule == "recursive delete"),
+        "{:?}",
+        err
+    );
+    assert!(dir.path().join("build").exists());
+
+    let log = gate.audit_log();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].command, command);
+    assert!(!log[0].approved);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_approved_command_runs() {
+    let dir = TempDir::new().unwrap();
+    let target = dir.path().join("build");
+    std::fs::create_dir(&target).unwrap();
+
+    let gate = Arc::new(ConfirmationGate::new(|command, risk| {
+        command.starts_with("rm ") && risk.matched.starts_with("rm -r")
+    }));
+    let options = RunOptions::builder()
+        .mirror(false)
+        .confirm(gate.clone())
+        .build();
+    let result = ProcessRunner::new(format!("rm -r {}", target.display()), options)
+        .run()
+        .await
+        .unwrap();
+    assert!(result.is_success());
+    assert!(!target.exists());
+    assert!(gate.audit_log()[0].approved);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_gate_applies_to_pipelines_and_streams() {
+    let gate = || {
+        ConfirmationGate::new(|_, _| false)
+            .classifier(Classifier::empty().rule("wipe", r"\bshred\b").unwrap())
+    };
+
+    let err = Pipeline::new()
+        .add("echo data")
+        .add("shred -")
+        .mirror_output(false)
+        .confirm(gate())
+        .run()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::NotConfirmed { .. }));
+
+    let err = StreamingRunner::new("shred -u secrets.txt")
+        .mirror(false)
+        .confirm(gate())
+        .collect()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::NotConfirmed { .. }));
+}
@@ -0,0 +1,128 @@
+//! Tests for running plain commands without a shell, and for PowerShell mode
+
+use command_stream::{exec, Error, ProcessRunner, RunOptions, ShellChoice};
+use std::collections::HashMap;
+
+// ============================================================================
+// Direct Exec Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_direct_exec_preserves_quoted_arguments() {
+    let result = exec(
+        "printf '%s|' 'a  b' \"c d\" e",
+        RunOptions {
+            mirror: false,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(result.is_success());
+    assert_eq!(result.stdout.trim_end(), "a  b|c d|e|");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_direct_exec_opt_out_uses_shell() {
+    let result = exec(
+        "printf '%s|' 'a  b' c",
+        RunOptions {
+            mirror: false,
+            direct_exec: false,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(result.is_success());
+    assert_eq!(result.stdout.trim_end(), "a  b|c|");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_shell_features_still_use_shell() {
+    let result = exec(
+        "printf '%s' \"$COMMAND_STREAM_DIRECT_TEST\"",
+        RunOptions {
+            mirror: false,
+            env: Some(HashMap::from([(
+                "COMMAND_STREAM_DIRECT_TEST".to_string(),
+                "expanded".to_string(),
+            )])),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.stdout.trim_end(), "expanded");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_direct_exec_looks_programs_up_on_the_commands_path() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let tool = dir.path().join("cs-path-tool");
+    std::fs::write(&tool, "#!/bin/sh\necho \"tool $1\"\n").unwrap();
+    std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let options = RunOptions {
+        mirror: false,
+        env: Some(HashMap::from([(
+            "PATH".to_string(),
+            dir.path().display().to_string(),
+        )])),
+        ..Default::default()
+    };
+
+    let result = exec("cs-path-tool ran", options.clone()).await.unwrap();
+    assert_eq!(result.stdout, "tool ran\n");
+
+    // `printf` is only on this process's PATH, so the shell's builtin runs
+    let result = exec("printf hi", options).await.unwrap();
+    assert_eq!(result.stdout.trim_end(), "hi");
+}
+
+// ============================================================================
+// PowerShell Mode Tests
+// ============================================================================
+
+fn powershell_options() -> RunOptions {
+    RunOptions::builder()
+        .mirror(false)
+        .shell(ShellChoice::PowerShell)
+        .build()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_powershell_mode_runs_plain_commands_directly() {
+    // PowerShell quoting: '' is a quote and backslashes are ordinary
+    let mut runner = ProcessRunner::new(
+        r"printf '%s|%s' 'it''s' C:\Tools\app.exe",
+        powershell_options(),
+    );
+    let result = runner.run().await.unwrap();
+    assert_eq!(result.stdout.trim_end(), r"it's|C:\Tools\app.exe");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_powershell_mode_delegates_to_pwsh() {
+    if which::which("pwsh").is_ok() {
+        let mut runner =
+            ProcessRunner::new("Write-Output ($env:CS_PS + '!')", powershell_options())
+                .with_env("CS_PS", "hi");
+        let result = runner.run().await.unwrap();
+        assert_eq!(result.stdout.trim_end(), "hi!");
+    } else {
+        // Without PowerShell installed the command must not fall back to sh
+        let mut runner = ProcessRunner::new("Write-Output $env:HOME", powershell_options());
+        assert!(matches!(runner.run().await, Err(Error::Io(_))));
+    }
+}
@@ -1,8 +1,10 @@
 //! Integration tests for the events module
 
-use command_stream::{EventData, EventType, StreamEmitter};
+use command_stream::{
+    exec_with, run_events, run_with_events, EventData, EventType, RunOptions, StreamEmitter,
+};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 #[tokio::test]
 async fn test_stream_emitter_creation() {
@@ -211,3 +213,140 @@ async fn test_event_data_variants() {
     );
     assert_eq!(*code_received.lock().unwrap(), Some(42));
 }
+
+// ============================================================================
+// Runner Event Tests
+// ============================================================================
+
+/// Log the name and payload of every lifecycle and stdout event
+async fn record(emitter: &StreamEmitter, log: &Arc<Mutex<Vec<String>>>) {
+    for event in [
+        EventType::Spawn,
+        EventType::Stdout,
+        EventType::Data,
+        EventType::Exit,
+        EventType::End,
+    ] {
+        let log = log.clone();
+        let name = event.to_string();
+        emitter
+            .on(event, move |data| {
+                let entry = match data {
+                    EventData::String(s) => format!("{}:{}", name, s),
+                    EventData::ExitCode(code) => format!("{}:{}", name, code),
+                    EventData::TypedData { data_type, .. } => format!("{}:{}", name, data_type),
+                    _ => name.clone(),
+                };
+                log.lock().unwrap().push(entry);
+            })
+            .await;
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_events_reports_process_output() {
+    let (emitter, result) = run_events("sh -c 'echo one; echo two'");
+    let log = Arc::new(Mutex::new(Vec::new()));
+    record(&emitter, &log).await;
+
+    let result = result.await.unwrap();
+    assert_eq!(result.stdout, "one\ntwo\n");
+
+    let log = log.lock().unwrap();
+    assert_eq!(log.first().map(String::as_str), Some("spawn"));
+    assert!(log.contains(&"stdout:one\n".to_string()));
+    assert!(log.contains(&"data:stdout".to_string()));
+    let tail: Vec<_> = log.iter().rev().take(2).rev().cloned().collect();
+    assert_eq!(tail, ["exit:0", "end"]);
+}
+
+#[tokio::test]
+async fn test_run_with_events_reports_virtual_command() {
+    let emitter = Arc::new(StreamEmitter::new());
+    let log = Arc::new(Mutex::new(Vec::new()));
+    record(&emitter, &log).await;
+
+    let options = RunOptions {
+        mirror: false,
+        ..Default::default()
+    };
+    let result = run_with_events("echo hi", options, emitter).await.unwrap();
+    assert_eq!(result.stdout, "hi\n");
+
+    let log = log.lock().unwrap();
+    assert_eq!(*log, ["stdout:hi\n", "data:stdout", "exit:0", "end"]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_with_events_reports_each_command_of_a_list() {
+    let emitter = Arc::new(StreamEmitter::new());
+    let log = Arc::new(Mutex::new(Vec::new()));
+    for event in [
+        EventType::CommandStart,
+        EventType::Stdout,
+        EventType::CommandFinish,
+    ] {
+        let log = log.clone();
+        emitter
+            .on(event.clone(), move |data| {
+                let entry = match data {
+                    EventData::Command {
+                        index,
+                        command,
+                        code,
+                    } => format!("{} {} {} {:?}", event, index, command, code),
+                    EventData::String(s) => format!("{} {}", event, s.trim_end()),
+                    _ => event.to_string(),
+                };
+                log.lock().unwrap().push(entry);
+            })
+            .await;
+    }
+
+    let options = RunOptions {
+        mirror: false,
+        ..Default::default()
+    };
+    let command = "printf 'a\\n' && false && echo skipped || sh -c 'echo b'";
+    let result = run_with_events(command, options, emitter).await.unwrap();
+    assert_eq!(result.stdout, "a\nb\n");
+
+    let log = log.lock().unwrap();
+    assert_eq!(
+        *log,
+        [
+            "command_start 0 printf 'a\\n' None",
+            "stdout a",
+            "command_finish 0 printf 'a\\n' Some(0)",
+            "command_start 1 false None",
+            "command_finish 1 false Some(1)",
+            "command_start 3 sh -c 'echo b' None",
+            "stdout b",
+            "command_finish 3 sh -c 'echo b' Some(0)",
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_exec_with_calls_line_callbacks() {
+    let (mut out, mut err) = (Vec::new(), Vec::new());
+    let options = RunOptions {
+        mirror: false,
+        ..Default::default()
+    };
+    let result = exec_with(
+        "sh -c 'echo one; echo oops >&2; echo two'",
+        options,
+        |line| out.push(line),
+        |line| err.push(line),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(out, ["one", "two"]);
+    assert_eq!(err, ["oops"]);
+    assert_eq!(result.stdout, "one\ntwo\n");
+}
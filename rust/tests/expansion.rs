@@ -0,0 +1,279 @@
+//! Tests for the expansions done without a shell: globs, `~`, parameters,
+//! variables, command substitution and assignments
+
+use command_stream::{Error, ProcessRunner, RunOptions};
+
+// ============================================================================
+// Glob Expansion Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_virtual_commands_expand_globs() {
+    let dir = tempfile::tempdir().unwrap();
+    for name in ["b.txt", "a.txt", "c.md"] {
+        std::fs::write(dir.path().join(name), "").unwrap();
+    }
+    let echo = |command: &'static str, noglob| {
+        let options = RunOptions {
+            cwd: Some(dir.path().to_path_buf()),
+            mirror: false,
+            shell_settings: Some(command_stream::ShellSettings {
+                noglob,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        async move {
+            ProcessRunner::new(command, options)
+                .run()
+                .await
+                .unwrap()
+                .stdout
+        }
+    };
+
+    assert_eq!(echo("echo *.txt", false).await, "a.txt b.txt\n");
+    assert_eq!(echo("echo '*.txt' *.rs", false).await, "*.txt *.rs\n");
+    assert_eq!(echo("echo *.txt", true).await, "*.txt\n");
+}
+
+#[tokio::test]
+async fn test_virtual_commands_expand_globstar() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("src/a/b")).unwrap();
+    std::fs::create_dir(dir.path().join(".git")).unwrap();
+    for name in [
+        "top.rs",
+        "src/x.rs",
+        "src/a/y.rs",
+        "src/a/b/z.rs",
+        ".git/h.rs",
+    ] {
+        std::fs::write(dir.path().join(name), "").unwrap();
+    }
+    let echo = |command: &'static str, globstar| {
+        let options = RunOptions {
+            cwd: Some(dir.path().to_path_buf()),
+            mirror: false,
+            shell_settings: Some(command_stream::ShellSettings {
+                globstar,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        async move {
+            ProcessRunner::new(command, options)
+                .run()
+                .await
+                .unwrap()
+                .stdout
+        }
+    };
+
+    // Off, as in bash, `**` is `*`
+    assert_eq!(echo("echo src/**/*.rs", false).await, "src/a/y.rs\n");
+    assert_eq!(
+        echo("echo src/**/*.rs", true).await,
+        "src/a/b/z.rs src/a/y.rs src/x.rs\n"
+    );
+    assert_eq!(
+        echo("echo **/*.rs", true).await,
+        "src/a/b/z.rs src/a/y.rs src/x.rs top.rs\n"
+    );
+    assert_eq!(
+        echo("echo src/**", true).await,
+        "src/ src/a src/a/b src/a/b/z.rs src/a/y.rs src/x.rs\n"
+    );
+    assert_eq!(echo("echo src/a**", true).await, "src/a\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_malformed_commands_fail_with_where() {
+    let options = RunOptions::builder().mirror(false).build();
+    let error = ProcessRunner::new("echo 'oops", options.clone())
+        .run()
+        .await
+        .unwrap_err();
+    assert!(matches!(&error, Error::ParseError(message)
+            if message == "unterminated `'` quote at byte 5"));
+    let error = ProcessRunner::new("true &&", options.clone())
+        .run()
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("`&&` at byte 5"), "{}", error);
+
+    // A raw shell command is the shell's to reject
+    let raw = RunOptions::builder().mirror(false).raw_shell(true).build();
+    let result = ProcessRunner::new("echo 'oops", raw).run().await.unwrap();
+    assert!(!result.is_success());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_tilde_expands_without_a_shell() {
+    let home = tempfile::tempdir().unwrap();
+    std::fs::write(home.path().join("notes.txt"), "remember\n").unwrap();
+    let options = RunOptions::builder()
+        .mirror(false)
+        .env("HOME", home.path().to_string_lossy())
+        .build();
+    let result = ProcessRunner::new("cat ~/notes.txt '~/notes.txt'", options.clone())
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "remember\n");
+    assert!(result.stderr.contains("~/notes.txt"));
+
+    // Run directly, and written to through a redirect
+    let result = ProcessRunner::new("printf %s ~/x > ~/out.txt", options)
+        .run()
+        .await
+        .unwrap();
+    assert!(result.is_success());
+    let written = std::fs::read_to_string(home.path().join("out.txt")).unwrap();
+    assert_eq!(written, format!("{}/x", home.path().display()));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_special_and_positional_parameters() {
+    let run = |command: &'static str| {
+        let options = RunOptions {
+            mirror: false,
+            parameters: command_stream::Parameters::new("deploy.sh", ["staging", "two words"]),
+            ..Default::default()
+        };
+        async move {
+            ProcessRunner::new(command, options)
+                .run()
+                .await
+                .unwrap()
+                .stdout
+        }
+    };
+    assert_eq!(run("false; echo $?").await, "1\n");
+    assert_eq!(run("false || echo $? && echo $?").await, "1\n0\n");
+    assert_eq!(run("echo $0 $# \"$1\"").await, "deploy.sh 2 staging\n");
+    // Commands the shell runs get the same parameters
+    assert_eq!(
+        run("(exit 3); printf '[%s]' \"$@\" $?").await,
+        "[staging][two words][3]\n"
+    );
+    let pid = run("echo $$").await;
+    assert_eq!(pid.trim(), std::process::id().to_string());
+}
+
+// ============================================================================
+// Variable Expansion Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_virtual_commands_expand_variables() {
+    let options = RunOptions::builder()
+        .mirror(false)
+        .env("GREETING", "hi  there")
+        .build();
+    let result = ProcessRunner::new(r#"echo $GREETING "${GREETING}!" '$GREETING'"#, options)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "hi there hi  there! $GREETING\n");
+}
+
+#[tokio::test]
+async fn test_nounset_rejects_unset_variable() {
+    let options = RunOptions {
+        mirror: false,
+        shell_settings: Some(command_stream::ShellSettings {
+            nounset: true,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let result = ProcessRunner::new("echo ${COMMAND_STREAM_TEST_UNSET}", options)
+        .run()
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::UnsetVariable { name, .. }) if name == "COMMAND_STREAM_TEST_UNSET"
+    ));
+}
+
+// ============================================================================
+// Command Substitution Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_command_substitution_runs_in_process() {
+    let options = RunOptions::builder()
+        .mirror(false)
+        .env("NAME", "world")
+        .build();
+    let stdout = |command: &'static str| {
+        let options = options.clone();
+        async move {
+            ProcessRunner::new(command, options)
+                .run()
+                .await
+                .unwrap()
+                .stdout
+        }
+    };
+
+    assert_eq!(
+        stdout(r#"echo "hello $(echo $NAME)!""#).await,
+        "hello world!\n"
+    );
+    assert_eq!(stdout("echo $(echo a $(echo b))").await, "a b\n");
+    assert_eq!(stdout("echo x$(seq 3)").await, "x1 2 3\n");
+    assert_eq!(stdout(r#"echo "x$(seq 2)""#).await, "x1\n2\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_command_substitution_of_system_command() {
+    let options = RunOptions::builder().mirror(false).build();
+    let result = ProcessRunner::new("echo [$(printf 'a\\n\\n' | tr a b)] && echo done", options)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "[b]\ndone\n");
+}
+
+// ============================================================================
+// Assignment Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_assignments_set_the_environment_of_one_command() {
+    let options = RunOptions::builder()
+        .mirror(false)
+        .env("GREETING", "from-options")
+        .build();
+    let run = |command: &'static str| {
+        let options = options.clone();
+        async move { ProcessRunner::new(command, options).run().await.unwrap() }
+    };
+
+    // Spawned directly, over the options' environment
+    let result = run("GREETING=hello LEVEL='a b' printenv GREETING LEVEL").await;
+    assert_eq!(result.stdout, "hello\na b\n");
+    assert_eq!(run("printenv GREETING").await.stdout, "from-options\n");
+
+    // Virtual commands see them, but the words of the command don't
+    let result = run("GREETING=\"$GREETING!\" env").await;
+    assert!(
+        result.stdout.contains("GREETING=from-options!\n"),
+        "{:?}",
+        result
+    );
+    let result = run("GREETING=hi echo $GREETING").await;
+    assert_eq!(result.stdout, "from-options\n");
+
+    // In a list run in-process, each command has its own
+    let result = run("GREETING=one printenv GREETING && cd /tmp && printenv GREETING").await;
+    assert_eq!(result.stdout, "one\nfrom-options\n");
+}
@@ -0,0 +1,87 @@
+//! Tests for the git helpers, against throwaway local repositories
+
+#![cfg(unix)]
+
+use command_stream::git::{self, Git};
+use command_stream::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+/// A repository in `dir` with one commit of `tracked.txt`
+async fn init_repo(dir: &Path) -> Git {
+    let repo = Git::in_dir(dir);
+    repo.run(["init", "--quiet", "--initial-branch=main"])
+        .await
+        .unwrap();
+    std::fs::write(dir.join("tracked.txt"), "v1\n").unwrap();
+    repo.run(["add", "tracked.txt"]).await.unwrap();
+    repo.run([
+        "-c",
+        "user.name=Test",
+        "-c",
+        "user.email=test@example.com",
+        "commit",
+        "--quiet",
+        "-m",
+        "first commit",
+    ])
+    .await
+    .unwrap();
+    repo
+}
+
+#[tokio::test]
+async fn test_git_status_and_rev_parse() {
+    let dir = TempDir::new().unwrap();
+    let repo = init_repo(dir.path()).await;
+
+    let status = git::status(dir.path()).await.unwrap();
+    assert!(status.is_clean());
+    assert_eq!(status.branch.as_deref(), Some("main"));
+
+    std::fs::write(dir.path().join("tracked.txt"), "v2\n").unwrap();
+    std::fs::write(dir.path().join("new file.txt"), "").unwrap();
+    let status = repo.status().await.unwrap();
+    let paths: Vec<_> = status
+        .entries
+        .iter()
+        .map(|entry| (entry.path.as_str(), entry.is_untracked()))
+        .collect();
+    assert_eq!(paths, [("tracked.txt", false), ("new file.txt", true)]);
+
+    let head = repo.rev_parse("HEAD").await.unwrap();
+    assert_eq!(head.len(), 40);
+    assert!(head.chars().all(|c| c.is_ascii_hexdigit()));
+
+    // Revisions can't smuggle in options
+    let err = repo.rev_parse("--all").await.unwrap_err();
+    assert!(matches!(err, Error::CommandFailed { code: 128, .. }));
+}
+
+#[tokio::test]
+async fn test_git_clone_with_depth_and_progress() {
+    let source = TempDir::new().unwrap();
+    init_repo(source.path()).await;
+    let target = TempDir::new().unwrap();
+    let dest = target.path().join("clone");
+
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let seen = lines.clone();
+    git::clone(format!("file://{}", source.path().display()), &dest)
+        .depth(1)
+        .on_progress(move |line| seen.lock().unwrap().push(line.to_string()))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(dest.join("tracked.txt")).unwrap(),
+        "v1\n"
+    );
+    assert!(!lines.lock().unwrap().is_empty());
+
+    let err = git::clone("file:///nonexistent/repo", target.path().join("missing"))
+        .await
+        .unwrap_err();
+    assert!(err.stderr_tail().unwrap().contains("nonexistent"));
+}
@@ -0,0 +1,102 @@
+//! Integration tests for the heartbeat watchdog
+
+use command_stream::heartbeat::{Heartbeat, StallAction};
+use command_stream::{Error, EventData, EventType, ProcessRunner, RunOptions, StreamEmitter};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn options(heartbeat: Heartbeat) -> RunOptions {
+    RunOptions::builder()
+        .mirror(false)
+        .heartbeat(heartbeat)
+        .build()
+}
+
+/// An emitter that records the idle time of every `Stalled` event
+async fn stall_recorder() -> (Arc<StreamEmitter>, Arc<Mutex<Vec<Duration>>>) {
+    let emitter = Arc::new(StreamEmitter::new());
+    let stalls = Arc::new(Mutex::new(Vec::new()));
+    let recorded = stalls.clone();
+    emitter
+        .on(EventType::Stalled, move |data| {
+            if let EventData::Idle(idle) = data {
+                recorded.lock().unwrap().push(idle);
+            }
+        })
+        .await;
+    (emitter, stalls)
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_quiet_command_is_killed() {
+    let heartbeat = Heartbeat::new(Duration::from_millis(150)).on_stall(StallAction::Kill);
+    let (emitter, stalls) = stall_recorder().await;
+    let started = Instant::now();
+
+    // A path, so the virtual `sleep` (which is not watched) isn't used
+    let err = ProcessRunner::new("/bin/sleep 5", options(heartbeat))
+        .with_emitter(emitter)
+        .run()
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, Error::Stalled { .. }), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(3));
+    assert_eq!(stalls.lock().unwrap().len(), 1);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_output_keeps_command_alive() {
+    let heartbeat = Heartbeat::new(Duration::from_millis(300)).on_stall(StallAction::Kill);
+    let result = ProcessRunner::new(
+        "for i in 1 2 3 4 5 6; do echo $i >&2; sleep 0.1; done",
+        options(heartbeat),
+    )
+    .run()
+    .await
+    .unwrap();
+    assert!(result.is_success());
+    assert_eq!(result.stderr.lines().count(), 6);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stalled_command_is_restarted() {
+    let dir = tempfile::tempdir().unwrap();
+    let heartbeat = Heartbeat::new(Duration::from_millis(150)).on_stall(StallAction::Restart(2));
+    let options = RunOptions {
+        cwd: Some(dir.path().to_path_buf()),
+        ..options(heartbeat)
+    };
+
+    let err = ProcessRunner::new("echo start >> attempts; sleep 5", options)
+        .run()
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, Error::Stalled { .. }), "{:?}", err);
+    let attempts = std::fs::read_to_string(dir.path().join("attempts")).unwrap();
+    assert_eq!(attempts.lines().count(), 3);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_notify_keeps_running() {
+    let heartbeat = Heartbeat::new(Duration::from_millis(100));
+    let (emitter, stalls) = stall_recorder().await;
+
+    let result = ProcessRunner::new("/bin/sleep 0.45", options(heartbeat))
+        .with_emitter(emitter)
+        .run()
+        .await
+        .unwrap();
+
+    assert!(result.is_success());
+    let stalls = stalls.lock().unwrap();
+    assert!(stalls.len() >= 2, "{:?}", stalls);
+    assert!(stalls
+        .iter()
+        .all(|idle| *idle >= Duration::from_millis(100)));
+}
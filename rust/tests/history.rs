@@ -0,0 +1,54 @@
+//! Tests for the command execution history
+
+use command_stream::history::{clear_history, disable_history, enable_history, history};
+use command_stream::{exec, history::history_filtered, HistoryFilter, RunOptions};
+
+// A single test, since the history is process-wide state.
+#[tokio::test]
+async fn test_history_records_and_filters_commands() {
+    exec("echo before enabling", RunOptions::default().quiet())
+        .await
+        .unwrap();
+    assert!(history().is_empty());
+
+    enable_history(10);
+    exec("echo first", RunOptions::default().quiet())
+        .await
+        .unwrap();
+    exec("false", RunOptions::default().quiet()).await.unwrap();
+    exec("echo second", RunOptions::default().quiet())
+        .await
+        .unwrap();
+
+    let entries = history();
+    let commands: Vec<_> = entries.iter().map(|e| e.command.as_str()).collect();
+    assert_eq!(commands, ["echo first", "false", "echo second"]);
+    assert_eq!(entries[0].code, Some(0));
+    assert_eq!(entries[0].stdout_len, "first\n".len());
+    assert!(entries[0].finished_at >= entries[0].started_at);
+
+    let failed = history_filtered(&HistoryFilter::new().failed());
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].command, "false");
+
+    // A command is recorded once it finishes, so `history` doesn't list itself.
+    let listing = exec("history 2", RunOptions::default().quiet())
+        .await
+        .unwrap();
+    assert_eq!(
+        listing.stdout,
+        format!(
+            "{:>5}  false\n{:>5}  echo second\n",
+            entries[1].id, entries[2].id
+        )
+    );
+
+    clear_history();
+    assert!(history().is_empty());
+
+    disable_history();
+    exec("echo after disabling", RunOptions::default().quiet())
+        .await
+        .unwrap();
+    assert!(history().is_empty());
+}
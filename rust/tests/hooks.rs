@@ -0,0 +1,77 @@
+//! Tests for hooks run before and after commands
+
+use command_stream::hooks::{after_exec, before_exec, clear_exec_hooks};
+use command_stream::{exec, Error, RunOptions, Session};
+use std::sync::{Arc, Mutex};
+
+// A single test, since the global hooks are process-wide state.
+#[cfg(unix)]
+#[tokio::test]
+async fn test_hooks_change_commands_and_see_outcomes() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    before_exec(|mut spec| async move {
+        if spec.command == "forbidden" {
+            return Err(Error::ParseError("not today".to_string()));
+        }
+        let env = spec.options.env.get_or_insert_with(Default::default);
+        env.insert("TOKEN".to_string(), "secret".to_string());
+        spec.command = spec.command.replace("$TOKEN", "\"$TOKEN\"");
+        Ok(spec)
+    });
+    let seen = log.clone();
+    after_exec(move |spec, outcome| {
+        let entry = format!(
+            "{} -> {:?}",
+            spec.command,
+            outcome.as_ref().map(|result| result.code).ok()
+        );
+        seen.lock().unwrap().push(entry);
+        async {}
+    });
+
+    let options = RunOptions::builder().mirror(false).build();
+    let result = exec("sh -c 'echo $TOKEN; exit 3'", options.clone())
+        .await
+        .unwrap();
+    assert_eq!((result.stdout.as_str(), result.code), ("secret\n", 3));
+    assert!(exec("forbidden", options).await.is_err());
+
+    // Session hooks run around the global ones
+    let session = Session::with_options(RunOptions::builder().mirror(false).build());
+    let order = log.clone();
+    session.before_exec(move |mut spec| {
+        order
+            .lock()
+            .unwrap()
+            .push(format!("session saw {}", spec.command));
+        spec.command = "echo $TOKEN".to_string();
+        async { Ok(spec) }
+    });
+    let order = log.clone();
+    session.after_exec(move |spec, _| {
+        order
+            .lock()
+            .unwrap()
+            .push(format!("session done {}", spec.command));
+        async {}
+    });
+    let result = session.run("echo hi").await.unwrap();
+    assert_eq!(result.stdout, "secret\n");
+
+    // Without the global hook, nothing sets the variable
+    clear_exec_hooks();
+    let result = session.run("true").await.unwrap();
+    assert_eq!(result.stdout, "\n");
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            "sh -c 'echo \"$TOKEN\"; exit 3' -> Some(3)",
+            "session saw echo hi",
+            "echo \"$TOKEN\" -> Some(0)",
+            "session done echo $TOKEN",
+            "session saw true",
+            "session done echo $TOKEN",
+        ]
+    );
+}
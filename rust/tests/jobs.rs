@@ -0,0 +1,62 @@
+//! Tests for background jobs and the `jobs`/`wait` builtins
+
+use command_stream::jobs::{kill_job, list_jobs, spawn_job, wait_job};
+use command_stream::{exec, Error, JobStatus, ProcessRunner, RunOptions};
+use std::time::{Duration, Instant};
+
+// A single test, since the job table is process-wide state.
+#[tokio::test]
+async fn test_background_jobs() {
+    let started = Instant::now();
+    let result = exec("sleep 10 & echo started", RunOptions::default().quiet())
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "started\n");
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let listing = exec("jobs", RunOptions::default().quiet()).await.unwrap();
+    assert_eq!(listing.stdout, "[1]+  Running                 sleep 10 &\n");
+    assert_eq!(list_jobs()[0].status, JobStatus::Running);
+
+    // The job's output is its own, but its effects are there once waited for
+    let dir = tempfile::tempdir().unwrap();
+    let in_dir = RunOptions {
+        cwd: Some(dir.path().to_path_buf()),
+        ..RunOptions::default().quiet()
+    };
+    let result = exec("echo one > out.txt & wait %2 && cat out.txt", in_dir)
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "one\n");
+    assert_eq!(list_jobs().len(), 1);
+
+    let result = exec("exit 3 & wait %%", RunOptions::default().quiet())
+        .await
+        .unwrap();
+    assert_eq!(result.code, 3);
+    let result = exec("wait %9", RunOptions::default().quiet())
+        .await
+        .unwrap();
+    assert_eq!(result.code, 127);
+    assert!(result.stderr.contains("%9: no such job"));
+
+    assert!(kill_job(1));
+    assert!(matches!(wait_job(1).await, Some(Err(Error::Cancelled))));
+    assert!(wait_job(1).await.is_none());
+
+    let id = spawn_job(ProcessRunner::new(
+        "echo from rust",
+        RunOptions::default().quiet(),
+    ));
+    assert_eq!(id, 1);
+    let result = wait_job(id).await.unwrap().unwrap();
+    assert_eq!(result.stdout, "from rust\n");
+
+    exec("exit 2 &", RunOptions::default().quiet())
+        .await
+        .unwrap();
+    assert!(exec("wait", RunOptions::default().quiet())
+        .await
+        .unwrap()
+        .is_success());
+    assert!(list_jobs().is_empty());
+}
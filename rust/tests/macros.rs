@@ -75,3 +75,35 @@ async fn test_cmd_macro_with_numbers() {
     assert!(result.is_success());
     assert!(result.stdout.contains("42"));
 }
+
+#[tokio::test]
+async fn test_cmd_macro_splats_named_list() {
+    let words = vec!["one".to_string(), "two words".to_string()];
+    let result = cmd!("printf '%s|' {} {words...}", "first", words = &words)
+        .await
+        .unwrap();
+    assert_eq!(result.stdout.trim_end(), "first|one|two words|");
+}
+
+#[tokio::test]
+async fn test_cmd_macro_named_list_as_single_argument() {
+    let parts = ["a", "b"];
+    let result = s!("printf '%s|' {parts}", parts = parts).await.unwrap();
+    assert_eq!(result.stdout.trim_end(), "a b|");
+}
+
+#[test]
+fn test_build_template_keeps_unrelated_braces() {
+    use command_stream::macros::build_template;
+
+    let command = build_template(
+        "echo {a,b} {} {}",
+        &["x y".to_string()],
+        &[("files", vec![])],
+    );
+    assert_eq!(command, "echo {a,b} 'x y' {}");
+    assert_eq!(
+        build_template("ls {files...}", &[], &[("files", vec![])]),
+        "ls "
+    );
+}
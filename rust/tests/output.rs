@@ -0,0 +1,233 @@
+//! Tests for what happens to a command's output: line sinks, timestamps,
+//! filters, color and tail capture
+
+use command_stream::{ProcessRunner, RunOptions, TailLimit};
+
+// ============================================================================
+// Line Sink Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pipe_output_to_channel_and_callback() {
+    use command_stream::sink::LineSink;
+    use std::sync::{Arc, Mutex};
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let seen = errors.clone();
+    let options = RunOptions::builder().mirror(false).build();
+    let result = ProcessRunner::new("sh -c 'echo one; echo oops >&2; echo two'", options)
+        .pipe_stdout_to(tx)
+        .pipe_stderr_to(LineSink::callback(move |line| {
+            seen.lock().unwrap().push(line)
+        }))
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(result.stdout, "one\ntwo\n");
+    let mut lines = Vec::new();
+    while let Some(line) = rx.recv().await {
+        lines.push(line);
+    }
+    assert_eq!(lines, ["one", "two"]);
+    assert_eq!(*errors.lock().unwrap(), ["oops"]);
+}
+
+#[tokio::test]
+async fn test_pipe_virtual_command_output() {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+    let options = RunOptions::builder().mirror(false).build();
+    ProcessRunner::new("echo virtual", options)
+        .pipe_stdout_to(tx)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(rx.recv().await.as_deref(), Some("virtual"));
+    assert_eq!(rx.recv().await, None);
+}
+
+// ============================================================================
+// Timed Line Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_timed_lines_record_stream_and_arrival() {
+    use command_stream::StreamKind;
+    use std::time::Duration;
+
+    let options = RunOptions::builder()
+        .mirror(false)
+        .timed_lines(true)
+        .build();
+    let result = ProcessRunner::new("sh -c 'echo first; sleep 0.2; echo second >&2'", options)
+        .run()
+        .await
+        .unwrap();
+
+    let lines: Vec<_> = result
+        .timed_lines
+        .iter()
+        .map(|line| (line.stream, line.text.as_str()))
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            (StreamKind::Stdout, "first"),
+            (StreamKind::Stderr, "second")
+        ]
+    );
+    let gap = result.timed_lines[1].at - result.timed_lines[0].at;
+    assert!(gap >= Duration::from_millis(150), "{:?}", gap);
+}
+
+#[tokio::test]
+async fn test_timed_lines_are_off_by_default() {
+    let options = RunOptions::builder().mirror(false).build();
+    let result = ProcessRunner::new("echo quiet", options)
+        .run()
+        .await
+        .unwrap();
+    assert!(result.timed_lines.is_empty());
+
+    let options = RunOptions::builder()
+        .mirror(false)
+        .timed_lines(true)
+        .build();
+    let result = ProcessRunner::new("echo virtual", options)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(result.timed_lines.len(), 1);
+    assert_eq!(result.timed_lines[0].text, "virtual");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_close_waits_for_started_process() {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+    let options = RunOptions::builder().mirror(false).build();
+    let mut runner =
+        ProcessRunner::new("sh -c 'echo first; sleep 0.1; echo last'", options).pipe_stdout_to(tx);
+    runner.start().await.unwrap();
+    runner.close().await.unwrap();
+
+    let mut lines = Vec::new();
+    while let Some(line) = rx.recv().await {
+        lines.push(line);
+    }
+    assert_eq!(lines, ["first", "last"]);
+}
+
+// ============================================================================
+// Output Filter Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_output_filters_run_before_capture_and_sinks() {
+    use command_stream::filter::StripAnsi;
+    use command_stream::StreamKind;
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+    let options = RunOptions::builder()
+        .mirror(false)
+        .output_filter(StripAnsi)
+        .output_filter(|_: StreamKind, line: String| (line != "noise").then_some(line))
+        .output_filter(|stream: StreamKind, line: String| match stream {
+            StreamKind::Stdout => Some(line),
+            StreamKind::Stderr => Some(format!("[err] {}", line)),
+        })
+        .build();
+    let result = ProcessRunner::new(
+        "sh -c 'printf \"\\033[32mok\\033[0m\\nnoise\\n\"; echo bad >&2'",
+        options,
+    )
+    .pipe_stdout_to(tx)
+    .run()
+    .await
+    .unwrap();
+
+    assert_eq!(result.stdout, "ok\n");
+    assert_eq!(result.stderr, "[err] bad\n");
+    assert_eq!(rx.recv().await.as_deref(), Some("ok"));
+    assert_eq!(rx.recv().await, None);
+}
+
+#[tokio::test]
+async fn test_output_filters_apply_to_virtual_commands() {
+    use command_stream::StreamKind;
+
+    let options = RunOptions::builder()
+        .mirror(false)
+        .output_filter(|_: StreamKind, line: String| Some(line.replace("hunter2", "***")))
+        .build();
+    let result = ProcessRunner::new("echo password hunter2", options)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "password ***\n");
+}
+
+// ============================================================================
+// Color Policy Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_color_policy_sets_child_variables() {
+    use command_stream::ColorPolicy;
+
+    let colors = |policy| async move {
+        let options = RunOptions::builder()
+            .mirror(false)
+            .env("NO_COLOR", "from-options")
+            .color(policy)
+            .build();
+        ProcessRunner::new(
+            "sh -c 'echo \"${NO_COLOR-unset} ${FORCE_COLOR-unset} ${CLICOLOR_FORCE-unset}\"'",
+            options,
+        )
+        .run()
+        .await
+        .unwrap()
+        .stdout
+    };
+
+    assert_eq!(colors(ColorPolicy::Always).await, "unset 1 1\n");
+    assert_eq!(colors(ColorPolicy::Never).await, "1 unset unset\n");
+}
+
+// ============================================================================
+// Tail Capture Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_capture_tail_keeps_the_last_lines() {
+    let options = RunOptions::builder()
+        .mirror(false)
+        .capture_tail(TailLimit::Lines(3))
+        .build();
+    let mut runner = ProcessRunner::new("seq 10000", options);
+    let result = runner.run().await.unwrap();
+    assert_eq!(result.stdout, "9998\n9999\n10000\n");
+    assert!(runner.partial_output().stdout.lines().count() < 100);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_capture_tail_of_system_command() {
+    let options = RunOptions::builder()
+        .mirror(false)
+        .raw_shell(true)
+        .capture_tail(TailLimit::Bytes(12))
+        .build();
+    let result = ProcessRunner::new("seq 5000; seq 3 >&2", options)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "4999\n5000\n");
+    assert_eq!(result.stderr, "1\n2\n3\n");
+}
@@ -87,3 +87,103 @@ async fn test_pipeline_builder_pattern() {
     let result = pipeline.run().await.unwrap();
     assert!(result.is_success());
 }
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pipeline_streams_between_stages() {
+    // The real `yes` never ends on its own: it only stops once `head` has
+    // what it needs and closes the pipe
+    let result = Pipeline::new()
+        .add("sh -c 'exec yes'")
+        .add("head -n 100000")
+        .add("wc -l")
+        .mirror_output(false)
+        .run()
+        .await
+        .unwrap();
+
+    assert!(result.is_success());
+    assert_eq!(result.stdout.trim(), "100000");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pipeline_stages_run_concurrently() {
+    let started = std::time::Instant::now();
+    let result = Pipeline::new()
+        .add("sh -c 'sleep 0.5; echo done'")
+        .add("sh -c 'sleep 0.5; cat'")
+        .mirror_output(false)
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(result.stdout, "done\n");
+    assert!(started.elapsed() < std::time::Duration::from_millis(900));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_pipeline_reports_first_failed_stage() {
+    let result = Pipeline::new()
+        .add("sh -c 'echo oops >&2; exit 3'")
+        .add("cat")
+        .add("sh -c 'echo fine; echo last >&2'")
+        .mirror_output(false)
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(result.code, 3);
+    assert_eq!(result.stdout, "fine\n");
+    assert_eq!(result.stderr, "oops\nlast\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_missing_stage_program_is_named() {
+    use command_stream::{Error, ExitKind};
+
+    let error = Pipeline::new()
+        .add("echo '{}'")
+        .add("cat")
+        .add("no-such-program-jq .name")
+        .mirror_output(false)
+        .run()
+        .await
+        .unwrap_err();
+    let Error::PipelineStage {
+        index,
+        command,
+        source,
+        ..
+    } = &error
+    else {
+        panic!("expected a stage error, got {:?}", error);
+    };
+    assert_eq!((*index, command.as_str()), (2, "no-such-program-jq .name"));
+    assert!(matches!(&**source, Error::CommandNotFound(name) if name == "no-such-program-jq"));
+    assert_eq!(error.exit_kind(), Some(ExitKind::NotFound));
+
+    let options = RunOptions::builder().mirror(false).build();
+    let error = ProcessRunner::new("echo '{}'", options)
+        .pipe("cat")
+        .pipe("no-such-program-jq .name")
+        .run()
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Pipeline stage 3 (no-such-program-jq .name): Command not found: no-such-program-jq"
+    );
+
+    // A program that exits with 127 itself is just a failure
+    let result = Pipeline::new()
+        .add("echo hi")
+        .add("sh -c 'echo not found >&2; exit 127'")
+        .mirror_output(false)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(result.code, 127);
+}
@@ -0,0 +1,114 @@
+//! Integration tests for execution policies
+
+use command_stream::policy::set_execution_policy;
+use command_stream::{
+    Error, ExecutionPolicy, OutputChunk, Pipeline, ProcessRunner, RunOptions, StreamingRunner,
+    Violation,
+};
+use tempfile::TempDir;
+
+fn options(policy: ExecutionPolicy) -> RunOptions {
+    RunOptions::builder().mirror(false).policy(policy).build()
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_policy_rejects_before_running() {
+    let dir = TempDir::new().unwrap();
+    let marker = dir.path().join("ran");
+    let command = format!("echo start && touch {}", marker.display());
+
+    let policy = ExecutionPolicy::new().allow(["echo"]);
+    let err = ProcessRunner::new(&command, options(policy))
+        .run()
+        .await
+        .unwrap_err();
+    match &err {
+        Error::PolicyViolation { violation, .. } => assert_eq!(
+            *violation,
+            Violation::NotAllowed {
+                program: "touch".to_string()
+            }
+        ),
+        other => panic!("expected PolicyViolation, got {:?}", other),
+    }
+    assert_eq!(err.command(), Some(command.as_str()));
+    assert!(!marker.exists(), "no part of the command may run");
+
+    let policy = ExecutionPolicy::new().allow(["echo", "touch"]);
+    let result = ProcessRunner::new(&command, options(policy))
+        .run()
+        .await
+        .unwrap();
+    assert!(result.is_success());
+    assert!(marker.exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_policy_denied_pattern() {
+    let policy = ExecutionPolicy::new().deny_pattern(r"rm\s+-rf").unwrap();
+    let err = ProcessRunner::new("rm -rf /nonexistent-dir", options(policy))
+        .run()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("denied pattern"), "{}", err);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_policy_applies_to_pipelines_and_streams() {
+    let err = Pipeline::new()
+        .add("echo hello")
+        .add("cat")
+        .add("cat")
+        .mirror_output(false)
+        .policy(ExecutionPolicy::new().max_pipeline_len(2))
+        .run()
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::PolicyViolation {
+            violation: Violation::PipelineTooLong { len: 3, max: 2 },
+            ..
+        }
+    ));
+
+    let runner = || StreamingRunner::new("curl http://example.com").mirror(false);
+    let err = runner()
+        .policy(ExecutionPolicy::new().forbid_network())
+        .collect()
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::PolicyViolation { .. }));
+
+    let mut stream = runner()
+        .policy(ExecutionPolicy::new().forbid_network())
+        .stream();
+    let mut chunks = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        chunks.push(chunk);
+    }
+    assert!(matches!(
+        chunks.as_slice(),
+        [OutputChunk::Stderr(_), OutputChunk::Exit(126)]
+    ));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_global_policy() {
+    set_execution_policy(Some(ExecutionPolicy::new().allow(["true"])));
+    let quiet = || RunOptions::builder().mirror(false).build();
+    let rejected = ProcessRunner::new("false", quiet()).run().await;
+    let allowed = ProcessRunner::new("true", quiet()).run().await;
+    let overridden = ProcessRunner::new("false", options(ExecutionPolicy::new()))
+        .run()
+        .await;
+    set_execution_policy(None);
+
+    assert!(matches!(rejected, Err(Error::PolicyViolation { .. })));
+    assert!(allowed.unwrap().is_success());
+    assert_eq!(overridden.unwrap().code, 1);
+}
@@ -0,0 +1,50 @@
+//! Integration tests for the preflight checks
+
+use command_stream::preflight::{preflight, Requirement};
+use command_stream::Error;
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_preflight_reports_missing_commands() {
+    let report = preflight(["sh", "command-stream-no-such-tool"]).await;
+    assert!(!report.is_ok());
+    assert!(report.checks[0].is_ok() && report.checks[0].path.is_some());
+    assert_eq!(
+        report.checks[1].problem.as_deref(),
+        Some("not found in PATH")
+    );
+
+    match report.into_result() {
+        Err(Error::CommandNotFound(message)) => {
+            assert_eq!(message, "command-stream-no-such-tool: not found in PATH")
+        }
+        other => panic!("expected CommandNotFound, got {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_preflight_checks_minimum_versions() {
+    let fake = |version: &str| {
+        Requirement::new("sh").version_args(["-c", &format!("echo tool {}", version)])
+    };
+    let report = preflight([
+        fake("3.10.1").min_version("3.9"),
+        fake("3.2.1").min_version("3.10"),
+        fake("v7").min_version("1.0").parse_version(|_| None),
+    ])
+    .await;
+
+    assert!(report.checks[0].is_ok());
+    assert_eq!(report.checks[0].version.as_deref(), Some("3.10.1"));
+    assert_eq!(
+        report.checks[1].problem.as_deref(),
+        Some("version 3.2.1 is older than the required 3.10")
+    );
+    assert!(report.checks[2]
+        .problem
+        .as_deref()
+        .unwrap()
+        .starts_with("could not read a version"));
+    assert_eq!(report.failures().count(), 2);
+}
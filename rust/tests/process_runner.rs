@@ -2,9 +2,10 @@
 //!
 //! These tests mirror the JavaScript $.test.mjs tests
 
-use command_stream::{create, exec, run, ProcessRunner, RunOptions, StdinOption};
+use command_stream::{
+    create, exec, list_active, run, Error, ProcessRunner, RunOptions, StdinOption,
+};
 use std::collections::HashMap;
-use std::path::PathBuf;
 use tempfile::TempDir;
 
 // ============================================================================
@@ -144,6 +145,20 @@ async fn test_custom_environment_variable() {
     assert!(result.stdout.contains("test_value"));
 }
 
+#[tokio::test]
+async fn test_runner_with_methods() {
+    let dir = TempDir::new().unwrap();
+    let mut runner = ProcessRunner::new("printenv BUILT_VAR", RunOptions::builder().build())
+        .with_mirror(false)
+        .with_env("BUILT_VAR", "from_builder")
+        .with_cwd(dir.path());
+
+    let result = runner.run().await.unwrap();
+    assert_eq!(result.stdout, "from_builder\n");
+    assert!(!runner.options().mirror);
+    assert_eq!(runner.options().cwd.as_deref(), Some(dir.path()));
+}
+
 // ============================================================================
 // Stdin Tests
 // ============================================================================
@@ -162,6 +177,29 @@ async fn test_stdin_content() {
     let result = runner.run().await.unwrap();
     assert!(result.is_success());
     assert!(result.stdout.contains("hello from stdin"));
+    assert_eq!(result.stdin, None);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_capture_stdin_is_recorded_and_limited() {
+    let options = |input: &str, limit| {
+        RunOptions::builder()
+            .mirror(false)
+            .stdin(StdinOption::Content(input.to_string()))
+            .capture_stdin(limit)
+            .build()
+    };
+
+    let result = exec("sh -c 'cat >/dev/null'", options("full input", 1024))
+        .await
+        .unwrap();
+    assert_eq!(result.stdin.as_deref(), Some("full input"));
+
+    // Virtual commands record it too; the cut never splits a character
+    let result = exec("cat", options("héllo", 2)).await.unwrap();
+    assert_eq!(result.stdout, "héllo");
+    assert_eq!(result.stdin.as_deref(), Some("h"));
 }
 
 // ============================================================================
@@ -284,3 +322,183 @@ async fn test_kill_process() {
     let kill_result = runner.kill();
     assert!(kill_result.is_ok());
 }
+
+// ============================================================================
+// Active Runner Registry Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_running_command_is_listed_as_active() {
+    let command = "sh -c 'sleep 0.3; : registry-test'";
+    let mut runner = ProcessRunner::new(command, RunOptions::default());
+    runner.start().await.unwrap();
+
+    let info = list_active()
+        .await
+        .into_iter()
+        .find(|info| info.command == command)
+        .expect("runner should be registered while running");
+    assert!(info.pid.is_some());
+    assert!(info.started_at <= chrono::Utc::now());
+
+    runner.run().await.unwrap();
+    assert!(!list_active()
+        .await
+        .iter()
+        .any(|info| info.command == command));
+}
+
+// ============================================================================
+// Timeout Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_timeout_kills_command() {
+    let started = std::time::Instant::now();
+    let err = exec(
+        "sh -c 'sleep 5'",
+        RunOptions {
+            mirror: false,
+            timeout: Some(std::time::Duration::from_millis(200)),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap_err();
+
+    assert!(matches!(err, Error::Timeout { .. }));
+    assert_eq!(err.command(), Some("sh -c 'sleep 5'"));
+    assert!(started.elapsed() < std::time::Duration::from_secs(3));
+}
+
+#[tokio::test]
+async fn test_timeout_not_reached() {
+    let result = exec(
+        "echo quick",
+        RunOptions {
+            mirror: false,
+            timeout: Some(std::time::Duration::from_secs(10)),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.stdout.trim(), "quick");
+}
+
+// ============================================================================
+// Signal Termination Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_signal_termination_is_reported() {
+    let result = exec(
+        "sh -c 'kill -9 $$'",
+        RunOptions {
+            mirror: false,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.code, 137);
+    assert_eq!(result.signal, Some(9));
+    assert!(!result.core_dumped);
+}
+
+// ============================================================================
+// Stdin File Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdin_from_file() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("input.txt"), "b\na\n").unwrap();
+    let options = || {
+        RunOptions::builder()
+            .mirror(false)
+            .cwd(dir.path())
+            .stdin(StdinOption::File("input.txt".into()))
+            .build()
+    };
+
+    // Real process
+    let result = exec("sort", options()).await.unwrap();
+    assert_eq!(result.stdout, "a\nb\n");
+
+    // Virtual command
+    let result = exec("cat", options()).await.unwrap();
+    assert_eq!(result.stdout, "b\na\n");
+}
+
+#[tokio::test]
+async fn test_stdin_from_missing_file() {
+    let options = RunOptions::builder()
+        .mirror(false)
+        .stdin(StdinOption::File("/nonexistent/input.txt".into()))
+        .build();
+    let err = exec("cat", options).await.unwrap_err();
+    assert!(matches!(err, Error::Io(_)));
+}
+
+// ============================================================================
+// Raw Shell Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_run_raw_shell_uses_the_shell() {
+    let result = command_stream::run_raw_shell("f() { echo $((6 * 7)); }; f | tr 4 X")
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "X2\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_raw_shell_skips_virtual_commands() {
+    // The virtual `cd` would change this process's directory; the shell's doesn't
+    let before = std::env::current_dir().unwrap();
+    let options = RunOptions::builder().mirror(false).raw_shell(true).build();
+    let result = exec("cd /", options).await.unwrap();
+    assert!(result.is_success());
+    assert_eq!(std::env::current_dir().unwrap(), before);
+}
+
+// ============================================================================
+// Partial Output Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_partial_output_while_running() {
+    let options = RunOptions {
+        mirror: false,
+        timeout: Some(std::time::Duration::from_secs(2)),
+        ..Default::default()
+    };
+    let mut runner = ProcessRunner::new("sh -c 'echo first; echo warn >&2; sleep 5'", options);
+    let mut output = runner.watch_output();
+    let watcher = async {
+        let seen = output
+            .wait_for(|seen| !seen.stdout.is_empty() && !seen.stderr.is_empty())
+            .await
+            .unwrap()
+            .clone();
+        // Seen while the command is still running
+        assert_eq!(seen.stdout, "first\n");
+        assert_eq!(seen.stderr, "warn\n");
+    };
+    let (result, ()) = tokio::join!(runner.run(), watcher);
+
+    assert!(matches!(result, Err(Error::Timeout { .. })));
+    let partial = runner.partial_output();
+    assert_eq!(
+        partial.tail(command_stream::StreamKind::Stdout, 1),
+        "first\n"
+    );
+}
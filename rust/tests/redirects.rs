@@ -0,0 +1,124 @@
+//! Tests for redirects done without a shell
+
+use command_stream::{ProcessRunner, RunOptions};
+use tempfile::TempDir;
+
+// ============================================================================
+// Stderr Redirect Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_virtual_command_stderr_redirects() {
+    let dir = TempDir::new().unwrap();
+    let options = RunOptions::builder().mirror(false).cwd(dir.path()).build();
+    let run = |command: &'static str| {
+        let options = options.clone();
+        async move { ProcessRunner::new(command, options).run().await.unwrap() }
+    };
+
+    let result = run("cat missing.txt 2>/dev/null").await;
+    assert_eq!((result.code, result.stderr.as_str()), (1, ""));
+
+    let result = run("cat missing.txt 2>&1").await;
+    assert!(result.stdout.contains("missing.txt"), "{:?}", result);
+    assert_eq!(result.stderr, "");
+
+    let result = run("echo moved >&2").await;
+    assert_eq!(
+        (result.stdout.as_str(), result.stderr.as_str()),
+        ("", "moved\n")
+    );
+
+    run("cat missing.txt 2> err.txt").await;
+    run("echo more &>> err.txt").await;
+    let logged = std::fs::read_to_string(dir.path().join("err.txt")).unwrap();
+    assert!(
+        logged.contains("missing.txt") && logged.ends_with("more\n"),
+        "{}",
+        logged
+    );
+
+    let result = run("cat missing.txt 2>/dev/null || echo fallback").await;
+    assert_eq!(
+        (result.stdout.as_str(), result.stderr.as_str()),
+        ("fallback\n", "")
+    );
+}
+
+#[tokio::test]
+async fn test_stderr_redirect_to_unwritable_target_fails() {
+    let options = RunOptions::builder().mirror(false).build();
+    let result = ProcessRunner::new("echo hi 2> missing-dir/err.txt", options)
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(result.code, 1);
+    assert!(
+        result.stderr.contains("missing-dir/err.txt"),
+        "{:?}",
+        result
+    );
+}
+
+// ============================================================================
+// Redirect Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_virtual_command_file_redirects() {
+    let dir = TempDir::new().unwrap();
+    let options = RunOptions::builder().mirror(false).cwd(dir.path()).build();
+    let run = |command: &'static str| {
+        let options = options.clone();
+        async move { ProcessRunner::new(command, options).run().await.unwrap() }
+    };
+
+    assert_eq!(run("echo one > out.txt").await.stdout, "");
+    run("echo two >> out.txt").await;
+    let read = std::fs::read_to_string(dir.path().join("out.txt")).unwrap();
+    assert_eq!(read, "one\ntwo\n");
+    assert_eq!(run("cat < out.txt").await.stdout, "one\ntwo\n");
+    assert_eq!(run("cat < /dev/null").await.stdout, "");
+
+    let result = run("cat < missing.txt").await;
+    assert_eq!(result.code, 1);
+    assert!(result.stderr.contains("missing.txt"), "{:?}", result);
+
+    // Stages of a pipeline, and commands of a list, apply their own
+    assert_eq!(run("echo hi > piped.txt | cat").await.stdout, "");
+    assert_eq!(run("echo hi 2>&1 | cat").await.stdout, "hi\n");
+    assert_eq!(run("cat < out.txt | cat").await.stdout, "one\ntwo\n");
+    let result = run("echo three > out.txt && cat out.txt").await;
+    assert_eq!(result.stdout, "three\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_program_redirects_without_shell() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("in.txt"), "b\na\n").unwrap();
+    let options = RunOptions::builder().mirror(false).cwd(dir.path()).build();
+    let run = |command: &'static str| {
+        let options = options.clone();
+        async move { ProcessRunner::new(command, options).run().await.unwrap() }
+    };
+
+    run("sort < in.txt > sorted.txt").await;
+    let sorted = std::fs::read_to_string(dir.path().join("sorted.txt")).unwrap();
+    assert_eq!(sorted, "a\nb\n");
+
+    let result = run("sh -c 'echo out; echo err >&2' 2>/dev/null").await;
+    assert_eq!(
+        (result.stdout.as_str(), result.stderr.as_str()),
+        ("out\n", "")
+    );
+    run("sh -c 'echo out; echo err >&2' > both.txt 2>&1").await;
+    let both = std::fs::read_to_string(dir.path().join("both.txt")).unwrap();
+    assert_eq!(both, "out\nerr\n");
+    // Joining the captured streams is left to the shell
+    let result = run("sh -c 'echo err >&2' 2>&1").await;
+    assert_eq!(
+        (result.stdout.as_str(), result.stderr.as_str()),
+        ("err\n", "")
+    );
+}
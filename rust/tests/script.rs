@@ -0,0 +1,118 @@
+//! Tests for running script files
+
+use command_stream::{run_script, run_script_in, Error, RunOptions, Session};
+
+fn script(dir: &tempfile::TempDir, text: &str) -> std::path::PathBuf {
+    let path = dir.path().join("deploy.sh");
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_script_runs_statement_by_statement() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = script(
+        &dir,
+        "#!/usr/bin/env command-stream\n\
+         echo one &&\n  echo two\n\
+         if true; then\n  echo three\nfi\n\
+         false\n",
+    );
+    let result = run_script(&path).await.unwrap();
+    assert_eq!(result.stdout, "one\ntwo\nthree\n");
+    assert_eq!(result.code, 1);
+}
+
+#[tokio::test]
+async fn test_script_errors_name_the_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = script(&dir, "set -e\necho start\n\n  false\necho unreachable\n");
+    let error = run_script(&path).await.unwrap_err();
+    let Error::Script {
+        line,
+        column,
+        snippet,
+        ..
+    } = &error
+    else {
+        panic!("not a script error: {:?}", error);
+    };
+    assert_eq!((*line, *column), (4, 3));
+    assert_eq!(snippet, "  4 |   false\n    |   ^^^^^");
+    assert_eq!(error.exit_code(), Some(1));
+    assert!(error
+        .to_string()
+        .starts_with(&format!("{}:4:3: ", path.display())));
+
+    let path = script(&dir, "echo first\necho \"never\nclosed\n");
+    let error = run_script(&path).await.unwrap_err();
+    assert!(matches!(
+        error,
+        Error::Script {
+            line: 2,
+            column: 6,
+            ..
+        }
+    ));
+    assert!(error.to_string().contains("unterminated `\"` quote"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_script_heredocs_exit_and_source() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = script(
+        &dir,
+        "set -e\ncat <<EOF\nfi 'quoted\nEOF\nexit 3\necho unreachable\n",
+    );
+    let result = run_script(&path).await.unwrap();
+    assert_eq!(result.stdout, "fi 'quoted\n");
+    assert_eq!(result.code, 3);
+
+    let path = script(
+        &dir,
+        "echo start\ntest -f missing || exit 4\necho unreachable\n",
+    );
+    let result = run_script(&path).await.unwrap();
+    assert_eq!((result.stdout.as_str(), result.code), ("start\n", 4));
+
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("env.sh"), "export STAGE=prod\ncd sub\n").unwrap();
+    let session = Session::with_options(RunOptions::builder().mirror(false).build());
+    session.set_cwd(dir.path()).unwrap();
+    let result = session.source("env.sh").await.unwrap();
+    assert!(result.is_success(), "{:?}", result);
+    assert_eq!(session.cwd(), dir.path().join("sub"));
+    assert_eq!(session.var("STAGE").as_deref(), Some("prod"));
+
+    let path = script(&dir, "source ./env.sh\necho $STAGE\n");
+    let session = Session::with_options(RunOptions::builder().mirror(false).build());
+    session.set_cwd(dir.path()).unwrap();
+    let result = run_script_in(&session, &path).await.unwrap();
+    assert_eq!(result.stdout, "prod\n");
+    assert_eq!(session.cwd(), dir.path().join("sub"));
+}
+
+#[tokio::test]
+async fn test_script_exit_trap_runs_at_the_end() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = script(&dir, "trap 'echo bye' EXIT\necho hi\nexit 2\n");
+    let result = run_script(&path).await.unwrap();
+    assert_eq!(result.stdout, "hi\nbye\n");
+    assert_eq!(result.code, 2);
+}
+
+#[tokio::test]
+async fn test_script_variables_and_functions_carry_over() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = script(
+        &dir,
+        "NAME=world\n\
+         echo \"hello $NAME\"\n\
+         greet() {\n  echo \"hi $1 from $NAME\"\n}\n\
+         greet you\n",
+    );
+    let result = run_script(&path).await.unwrap();
+    assert_eq!(result.stdout, "hello world\nhi you from world\n");
+    assert_eq!(result.code, 0);
+}
@@ -0,0 +1,397 @@
+//! Integration tests for sessions
+
+use command_stream::commands::CommandContext;
+use command_stream::{CommandResult, Error, RunOptions, Session};
+use std::future::Future;
+use std::pin::Pin;
+
+fn new_session() -> Session {
+    Session::with_options(RunOptions::builder().mirror(false).build())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_commands_run_in_session_cwd_and_env() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    let session = new_session();
+    session.set_cwd(dir.path()).unwrap();
+    session.set_cwd("sub").unwrap();
+    assert!(session.set_cwd("missing").is_err());
+
+    session.export("GREETING", "hello");
+    session.set_var("LOCAL", "unexported");
+    let result = session
+        .run("sh -c 'pwd; echo $GREETING; echo ${LOCAL:-none}'")
+        .await
+        .unwrap();
+    let lines: Vec<_> = result.stdout.lines().collect();
+    assert!(lines[0].ends_with("/sub"), "{:?}", lines);
+    assert_eq!(&lines[1..], ["hello", "none"]);
+
+    assert_eq!(session.unset("GREETING").as_deref(), Some("hello"));
+    assert!(session.env().is_empty());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_cd_export_and_unset_change_only_the_session() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("a/b")).unwrap();
+    let process_dir = std::env::current_dir().unwrap();
+    let session = new_session();
+    session.set_cwd(dir.path()).unwrap();
+    let a = dir.path().join("a");
+
+    session.run("cd a").await.unwrap();
+    let pwd = session.run("pwd").await.unwrap().stdout;
+    assert_eq!(pwd, format!("{}\n", a.display()));
+    session.run("cd b && cd .. && cd b").await.unwrap();
+    assert_eq!(session.cwd(), a.join("b"));
+    let back = session.run("cd -").await.unwrap().stdout;
+    assert_eq!(back, format!("{}\n", a.display()));
+    // A subshell's cd doesn't outlive it
+    let pwd = session.run("(cd b); pwd").await.unwrap().stdout;
+    assert_eq!(pwd, format!("{}\n", a.display()));
+    assert!(session.run("cd missing").await.unwrap().code != 0);
+    assert_eq!(session.cwd(), a);
+
+    session.run("export GREETING=hi LATER").await.unwrap();
+    session.run("export MESSAGE=$GREETING-there").await.unwrap();
+    let result = session.run("sh -c 'echo $MESSAGE'").await.unwrap();
+    assert_eq!(result.stdout, "hi-there\n");
+    assert!(session
+        .run("export -p")
+        .await
+        .unwrap()
+        .stdout
+        .contains("export MESSAGE=hi-there\n"));
+    session.run("unset GREETING MESSAGE").await.unwrap();
+    assert_eq!(session.var("GREETING"), None);
+    assert!(!session.env().contains_key("MESSAGE"));
+    let result = session.run("export 1x=2").await.unwrap();
+    assert_eq!(result.code, 1);
+    assert!(result.stderr.contains("not a valid identifier"));
+
+    assert_eq!(std::env::current_dir().unwrap(), process_dir);
+    assert!(std::env::var("GREETING").is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_aliases_and_functions() {
+    let session = new_session();
+    session.alias("greet", "echo hello");
+    session.alias("echo", "echo said:");
+    session.define_function("twice", "echo \"$1\"; echo \"$1\"");
+
+    assert_eq!(
+        session.run("greet world").await.unwrap().stdout,
+        "said: hello world\n"
+    );
+    assert_eq!(session.run("twice hi").await.unwrap().stdout, "hi\nhi\n");
+
+    assert!(session.unalias("echo"));
+    assert!(session.undefine_function("twice"));
+    assert_eq!(session.run("greet").await.unwrap().stdout, "hello\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_assignments_and_definitions_stay_in_the_session() {
+    let session = new_session();
+    session.run("X=2").await.unwrap();
+    assert_eq!(session.var("X").as_deref(), Some("2"));
+    assert_eq!(session.run("echo $X").await.unwrap().stdout, "2\n");
+    // Not exported, so commands don't get it in their environment
+    assert!(!session.env().contains_key("X"));
+    assert_eq!(
+        session.run("sh -c 'echo \"[$X]\"'").await.unwrap().stdout,
+        "[]\n"
+    );
+
+    session.run("shout() { echo \"$1!\"; }").await.unwrap();
+    assert_eq!(session.run("shout hey").await.unwrap().stdout, "hey!\n");
+
+    let result = session
+        .run("export A=1; echo $A; Y=3 && echo $X$Y")
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "1\n23\n");
+    assert_eq!(session.env().get("A").map(String::as_str), Some("1"));
+    assert_eq!(session.var("Y").as_deref(), Some("3"));
+
+    let result = session
+        .run("cd / && pwd; false || shout done")
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "/\ndone!\n");
+    assert_eq!(session.cwd(), std::path::Path::new("/"));
+}
+
+#[tokio::test]
+async fn test_set_changes_only_session_settings() {
+    let session = new_session();
+    session.run("set -e").await.unwrap();
+    assert!(session.shell_settings().await.errexit);
+    assert!(!command_stream::get_shell_settings().await.errexit);
+
+    let outcome = session.run("false").await;
+    assert!(
+        matches!(outcome, Err(Error::CommandFailed { .. })),
+        "{:?}",
+        outcome
+    );
+    assert!(new_session().run("false").await.is_ok());
+}
+
+#[tokio::test]
+async fn test_set_applies_to_the_rest_of_the_list() {
+    let session = new_session();
+    let outcome = session.run("set -e; false; echo notreached").await;
+    assert!(
+        matches!(outcome, Err(Error::CommandFailed { code: 1, .. })),
+        "{:?}",
+        outcome
+    );
+
+    let session = new_session();
+    let outcome = session
+        .run("set -u; echo $COMMAND_STREAM_SURELY_UNSET_VAR")
+        .await;
+    assert!(
+        matches!(outcome, Err(Error::UnsetVariable { .. })),
+        "{:?}",
+        outcome
+    );
+}
+
+#[tokio::test]
+async fn test_shopt_enables_globstar_for_later_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+    std::fs::write(dir.path().join("src/nested/lib.rs"), "").unwrap();
+    let session = new_session();
+    session.set_cwd(dir.path()).unwrap();
+
+    assert_eq!(
+        session.run("echo src/**/*.rs").await.unwrap().stdout,
+        "src/nested/lib.rs\n"
+    );
+    session.run("shopt -s globstar").await.unwrap();
+    assert!(session.shell_settings().await.globstar);
+    std::fs::write(dir.path().join("src/main.rs"), "").unwrap();
+    assert_eq!(
+        session.run("echo src/**/*.rs").await.unwrap().stdout,
+        "src/main.rs src/nested/lib.rs\n"
+    );
+}
+
+#[tokio::test]
+async fn test_last_status_is_kept_between_commands() {
+    let session = new_session();
+    assert_eq!(session.last_status(), 0);
+    session.run("exit 3").await.unwrap();
+    assert_eq!(session.last_status(), 3);
+    assert_eq!(session.run("echo $?").await.unwrap().stdout, "3\n");
+    assert_eq!(session.last_status(), 0);
+}
+
+fn hello(ctx: CommandContext) -> Pin<Box<dyn Future<Output = CommandResult> + Send>> {
+    Box::pin(async move { CommandResult::success(format!("hello {}\n", ctx.args.join(" "))) })
+}
+
+#[tokio::test]
+async fn test_registered_commands_are_per_session() {
+    let session = new_session();
+    session.register("hello", hello);
+    assert_eq!(
+        session.run("hello there").await.unwrap().stdout,
+        "hello there\n"
+    );
+    let elsewhere = new_session().run("hello").await;
+    assert!(elsewhere.map_or(true, |result| !result.is_success()));
+}
+
+fn twice(ctx: CommandContext) -> Pin<Box<dyn Future<Output = CommandResult> + Send>> {
+    Box::pin(async move {
+        let command = ctx.args.join(" ");
+        let executor = ctx.executor();
+        let mut stdout = String::new();
+        for _ in 0..2 {
+            match executor.run(&command).await {
+                Ok(result) => stdout.push_str(&result.stdout),
+                Err(e) => return CommandResult::error(format!("twice: {}\n", e)),
+            }
+        }
+        CommandResult::success(stdout)
+    })
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_registered_commands_run_subcommands_in_the_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let session = new_session();
+    session.register("twice", twice);
+    session.set_cwd(dir.path()).unwrap();
+    session.export("GREETING", "hello");
+    session.run("set -e").await.unwrap();
+
+    std::fs::write(dir.path().join("only.txt"), "").unwrap();
+    let listed = session.run("twice ls").await.unwrap().stdout;
+    assert_eq!(listed, "only.txt\nonly.txt\n");
+    let result = session.run("twice printenv GREETING").await.unwrap();
+    assert_eq!(result.stdout, "hello\nhello\n");
+    // A failing sub-command is the registered command's to handle
+    assert!(session.run("twice false").await.unwrap().is_success());
+}
+
+#[tokio::test]
+async fn test_save_and_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("session");
+    let session = new_session();
+    session.set_cwd(dir.path()).unwrap();
+    session.export("TOKEN", "a\tb\nc");
+    session.set_var("LOCAL", "1");
+    session.alias("ll", "ls -la");
+    session.define_function("greet", "echo hi\necho \"$1\"");
+    session.run("set -o pipefail").await.unwrap();
+    session.save(&file).await.unwrap();
+
+    let restored = new_session();
+    restored.load(&file).await.unwrap();
+    assert_eq!(restored.cwd(), dir.path());
+    assert_eq!(restored.env(), session.env());
+    assert_eq!(restored.var("LOCAL").as_deref(), Some("1"));
+    assert_eq!(restored.alias_of("ll").as_deref(), Some("ls -la"));
+    assert_eq!(restored.history(), ["set -o pipefail"]);
+    assert!(restored.shell_settings().await.pipefail);
+    #[cfg(unix)]
+    assert_eq!(
+        restored.run("greet there").await.unwrap().stdout,
+        "hi\nthere\n"
+    );
+
+    std::fs::write(&file, "something else\n").unwrap();
+    assert!(matches!(
+        restored.load(&file).await,
+        Err(Error::ParseError(_))
+    ));
+    assert_eq!(restored.alias_of("ll").as_deref(), Some("ls -la"));
+}
+
+#[tokio::test]
+async fn test_close_runs_cleanup_hooks_in_reverse() {
+    use std::sync::{Arc, Mutex};
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let session = new_session();
+    for hook in ["first", "second"] {
+        let order = order.clone();
+        session.on_cleanup(move || order.lock().unwrap().push(hook));
+    }
+    session.close().await;
+    assert_eq!(*order.lock().unwrap(), ["second", "first"]);
+}
+
+#[tokio::test]
+async fn test_exit_trap_runs_when_the_session_closes() {
+    let dir = tempfile::tempdir().unwrap();
+    let session = new_session();
+    session.set_cwd(dir.path()).unwrap();
+
+    session.run("trap 'touch closed' EXIT").await.unwrap();
+    assert!(!dir.path().join("closed").exists());
+    assert_eq!(
+        session.run("trap -p").await.unwrap().stdout,
+        "trap -- 'touch closed' EXIT\n"
+    );
+    session.close().await;
+    assert!(dir.path().join("closed").exists());
+
+    let session = new_session();
+    session.set_cwd(dir.path()).unwrap();
+    session.run("trap 'touch dropped' EXIT").await.unwrap();
+    // Dropping can't wait for the trap; only close() runs it
+    drop(session);
+    assert!(!dir.path().join("dropped").exists());
+
+    let session = new_session();
+    session.set_cwd(dir.path()).unwrap();
+    session.run("trap 'touch reset' EXIT").await.unwrap();
+    session.run("trap - EXIT").await.unwrap();
+    session.close().await;
+    assert!(!dir.path().join("reset").exists());
+}
+
+#[tokio::test]
+async fn test_trap_refuses_other_signals() {
+    let session = new_session();
+    let result = session.run("trap 'echo caught' HUP").await.unwrap();
+    assert_eq!(result.code, 1);
+    assert!(
+        result.stderr.contains("only EXIT, INT and TERM"),
+        "{:?}",
+        result
+    );
+    assert_eq!(session.run("trap").await.unwrap().stdout, "");
+    assert!(session.signal("HUP").await.is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_int_and_term_traps_run_on_signals_and_cancellation() {
+    use command_stream::CancellationToken;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    let session = Arc::new(new_session());
+    session
+        .run("trap 'echo interrupted' INT; trap 'echo terminated' SIGTERM")
+        .await
+        .unwrap();
+    assert_eq!(
+        session.run("trap").await.unwrap().stdout,
+        "trap -- 'echo interrupted' INT\ntrap -- 'echo terminated' TERM\n"
+    );
+
+    // A signal cancels the command running, then its trap runs
+    let running = tokio::spawn({
+        let session = session.clone();
+        async move { session.run("sleep 5").await }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let started = Instant::now();
+    let trapped = session.signal("TERM").await.unwrap().unwrap();
+    assert_eq!(trapped.stdout, "terminated\n");
+    assert!(matches!(running.await.unwrap(), Err(Error::Cancelled)));
+    assert!(started.elapsed() < Duration::from_secs(3));
+    assert_eq!(session.run("echo on").await.unwrap().stdout, "on\n");
+
+    // An empty action ignores the signal
+    session.run("trap '' INT").await.unwrap();
+    assert!(session.signal("INT").await.unwrap().is_none());
+
+    // Cancelling the session's token interrupts it
+    let dir = tempfile::tempdir().unwrap();
+    let token = CancellationToken::new();
+    let session = Session::with_options(
+        RunOptions::builder()
+            .mirror(false)
+            .cwd(dir.path())
+            .cancel(token.clone())
+            .build(),
+    );
+    session.run("trap 'touch interrupted' INT").await.unwrap();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        token.cancel();
+    });
+    assert!(matches!(
+        session.run("sleep 5").await,
+        Err(Error::Cancelled)
+    ));
+    assert!(dir.path().join("interrupted").exists());
+}
@@ -0,0 +1,50 @@
+//! Tests for the reusable `Sh` handle
+
+use command_stream::Sh;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_sh_commands_inherit_cwd_and_env() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("marker.txt"), "here\n").unwrap();
+    let sh = Sh::new()
+        .quiet()
+        .cwd(dir.path())
+        .env("SH_TEST_VAR", "inherited");
+
+    let result = sh.run("cat marker.txt").await.unwrap();
+    assert_eq!(result.stdout, "here\n");
+
+    let result = sh.run("printenv SH_TEST_VAR").await.unwrap();
+    assert_eq!(result.stdout, "inherited\n");
+
+    // A derived handle adds to the parent's settings without changing them
+    let child = sh.clone().env("SH_CHILD_VAR", "only-child");
+    let result = child.run("printenv SH_CHILD_VAR").await.unwrap();
+    assert_eq!(result.stdout, "only-child\n");
+    assert!(!sh
+        .options()
+        .env
+        .as_ref()
+        .unwrap()
+        .contains_key("SH_CHILD_VAR"));
+}
+
+#[tokio::test]
+async fn test_sh_stdin_and_stream() {
+    let sh = Sh::new().quiet();
+
+    let result = sh.run_with_stdin("cat", "piped in").await.unwrap();
+    assert_eq!(result.stdout, "piped in");
+
+    let result = sh.stream("echo streamed").collect().await.unwrap();
+    assert_eq!(result.stdout, "streamed\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_sh_locale_reaches_commands() {
+    let sh = Sh::new().quiet().locale(command_stream::STABLE_LOCALE);
+    let result = sh.run("sh -c 'echo \"$LC_ALL $LANG\"'").await.unwrap();
+    assert_eq!(result.stdout, "C.UTF-8 C.UTF-8\n");
+}
@@ -3,7 +3,8 @@
 //! These tests mirror the JavaScript shell parser tests
 
 use command_stream::shell_parser::{
-    needs_real_shell, parse_shell_command, tokenize, ParsedCommand, TokenType,
+    check_syntax, needs_real_shell, parse_shell_command, tokenize, ParsedArg, ParsedCommand,
+    TokenType,
 };
 
 // ============================================================================
@@ -122,6 +123,23 @@ fn test_tokenize_mixed_operators() {
     assert_eq!(ops.len(), 3);
 }
 
+#[test]
+fn test_tokenize_command_substitution_stays_in_word() {
+    let tokens = tokenize(r#"echo $(ls | wc -l)x "n: $(echo "a b")" && true"#);
+    let words: Vec<_> = tokens.iter().map(|t| t.value.as_str()).collect();
+    assert_eq!(
+        words,
+        [
+            "echo",
+            "$(ls | wc -l)x",
+            r#""n: $(echo "a b")""#,
+            "&&",
+            "true",
+            ""
+        ]
+    );
+}
+
 // ============================================================================
 // Parser Tests
 // ============================================================================
@@ -222,6 +240,7 @@ fn test_parse_with_redirect_out() {
             cmd,
             args,
             redirects,
+            ..
         } => {
             assert_eq!(cmd, "echo");
             assert_eq!(args.len(), 1);
@@ -313,14 +332,160 @@ fn test_needs_real_shell_glob_patterns() {
     assert!(needs_real_shell("ls [abc].txt"));
 }
 
+#[test]
+fn test_needs_real_shell_quoted_glob_characters() {
+    assert!(!needs_real_shell("echo '*.txt'"));
+    assert!(!needs_real_shell("echo \"what?\" \\[x]"));
+    assert!(needs_real_shell("ls 'my dir'/*"));
+}
+
 #[test]
 fn test_needs_real_shell_stderr_redirect() {
-    assert!(needs_real_shell("cmd 2>/dev/null"));
+    assert!(!needs_real_shell("cmd 2>/dev/null"));
+    assert!(!needs_real_shell("cmd > out.txt 2>&1"));
+    assert!(!needs_real_shell("cmd >&2"));
+    // Descriptors other than stdout and stderr are left to the shell
+    assert!(needs_real_shell("cmd 3>/dev/null"));
+    assert!(needs_real_shell("cmd 2>&3"));
+    assert!(needs_real_shell("cmd >&3"));
+    assert!(needs_real_shell("cmd 2>&12"));
 }
 
 #[test]
 fn test_needs_real_shell_combined_redirect() {
-    assert!(needs_real_shell("cmd &>/dev/null"));
+    assert!(!needs_real_shell("cmd &>/dev/null"));
+    let kinds: Vec<_> = tokenize("cmd & >/dev/null")
+        .into_iter()
+        .map(|token| token.token_type)
+        .collect();
+    assert_eq!(kinds[1..3], [TokenType::Background, TokenType::RedirectOut]);
+}
+
+#[test]
+fn test_parse_stderr_redirects() {
+    let parsed = parse_shell_command("cmd 2>err.txt arg 2>>log &>all >&2 1>&2 2>&1").unwrap();
+    let ParsedCommand::Simple {
+        args, redirects, ..
+    } = &parsed
+    else {
+        panic!("expected a simple command, got {:?}", parsed);
+    };
+    assert_eq!(args.len(), 1);
+    let kinds: Vec<_> = redirects.iter().map(|r| r.redirect_type.clone()).collect();
+    assert_eq!(
+        kinds,
+        [
+            TokenType::RedirectErr,
+            TokenType::RedirectErrAppend,
+            TokenType::RedirectBoth,
+            TokenType::RedirectOutToErr,
+            TokenType::RedirectOutToErr,
+            TokenType::RedirectErrToOut,
+        ]
+    );
+    assert_eq!(redirects[0].target, "err.txt");
+    assert_eq!(redirects[3].target, "");
+    assert_eq!(
+        parsed.to_string(),
+        "cmd arg 2> err.txt 2>> log &> all >&2 >&2 2>&1"
+    );
+    // A 2 inside a word is just part of it
+    let tokens = tokenize("seq 12>out");
+    assert_eq!(tokens[1].token_type, TokenType::Word("12".to_string()));
+}
+
+#[test]
+fn test_parse_background() {
+    let parsed = parse_shell_command("a; b && c & d").unwrap();
+    let ParsedCommand::Sequence {
+        commands,
+        operators,
+    } = &parsed
+    else {
+        panic!("expected a sequence, got {:?}", parsed);
+    };
+    assert_eq!(operators, &[TokenType::Semicolon, TokenType::Semicolon]);
+    // `&` puts the whole `&&` list in the background, not just `c`
+    let ParsedCommand::Background { command } = &commands[1] else {
+        panic!("expected a background command, got {:?}", commands[1]);
+    };
+    assert_eq!(command.to_string(), "b && c");
+    assert!(matches!(
+        parse_shell_command("sleep 1 &"),
+        Ok(ParsedCommand::Background { .. })
+    ));
+}
+
+#[test]
+fn test_parse_negation_and_time() {
+    let parsed = parse_shell_command("! time a | b && c").unwrap();
+    let ParsedCommand::Sequence { commands, .. } = &parsed else {
+        panic!("expected a sequence, got {:?}", parsed);
+    };
+    // Both apply to the whole pipeline, not its first command
+    let ParsedCommand::Not { command } = &commands[0] else {
+        panic!("expected a negation, got {:?}", commands[0]);
+    };
+    let ParsedCommand::Time { command, posix } = &**command else {
+        panic!("expected a timed command, got {:?}", command);
+    };
+    assert!(!posix);
+    assert!(matches!(**command, ParsedCommand::Pipeline { .. }));
+    // Anywhere else they are ordinary words
+    let parsed = parse_shell_command("echo ! time").unwrap();
+    assert!(matches!(parsed, ParsedCommand::Simple { ref args, .. } if args.len() == 2));
+    assert_eq!(
+        parse_shell_command("a; !").unwrap_err().message,
+        "expected a command after `!`"
+    );
+}
+
+#[test]
+fn test_parse_errors_point_at_the_problem() {
+    let error = |command: &str| {
+        let error = parse_shell_command(command).unwrap_err();
+        (error.span.start, error.message)
+    };
+    assert_eq!(
+        error("echo 'oops"),
+        (5, "unterminated `'` quote".to_string())
+    );
+    assert_eq!(
+        error("make && "),
+        (5, "expected a command after `&&`".to_string())
+    );
+    assert_eq!(
+        error("ls | "),
+        (3, "expected a command after `|`".to_string())
+    );
+    assert_eq!(error("|| ls"), (0, "unexpected `||`".to_string()));
+    assert_eq!(error("a; ; b"), (3, "unexpected `;`".to_string()));
+    assert_eq!(
+        error("echo hi >"),
+        (8, "expected a file after `>`".to_string())
+    );
+    assert_eq!(error("(cd src && ls"), (0, "unclosed `(`".to_string()));
+    assert_eq!(error("  "), (2, "expected a command".to_string()));
+    // The first error in the command is the one reported
+    assert_eq!(error("&& echo 'oops").0, 0);
+}
+
+#[test]
+fn test_parse_leaves_what_it_skips_alone() {
+    // Comments, a trailing `;` and the parts of compound commands the
+    // parser drops aren't errors
+    for command in [
+        "echo hi # it's fine &&",
+        "make;",
+        "case $x in a) echo a ;; esac",
+        "deploy() { echo go; }",
+        "diff <(ls a) <(ls b)",
+    ] {
+        assert!(parse_shell_command(command).is_ok(), "{}", command);
+    }
+    assert!(check_syntax("cat <<EOF").is_ok());
+    assert!(check_syntax("").is_ok());
+    assert!(check_syntax("echo \"oops").is_err());
 }
 
 #[test]
@@ -334,3 +499,80 @@ fn test_needs_real_shell_simple_commands() {
     assert!(!needs_real_shell("ls | grep foo"));
     assert!(!needs_real_shell("cmd1 && cmd2"));
 }
+
+// ============================================================================
+// Pretty-printer Tests
+// ============================================================================
+
+#[test]
+fn test_to_shell_string_round_trips() {
+    for command in [
+        "echo hello world",
+        "echo 'hello world' \"a $HOME\" a\\ b",
+        "cmd1 && cmd2 || cmd3; cmd4",
+        "ls -la | grep foo | wc -l",
+        "(cd /tmp && ls) && echo done > out.txt",
+        "sort < in.txt >> out.txt",
+        "sleep 10 & echo started",
+        "a; b && c & (d &) | e",
+        "! grep -q x f && time -p make | tee log",
+        "RUST_LOG=debug A='x y' cargo run",
+    ] {
+        let printed = parse_shell_command(command).unwrap().to_shell_string();
+        assert_eq!(printed, command);
+        let reparsed = parse_shell_command(&printed).unwrap().to_shell_string();
+        assert_eq!(reparsed, printed);
+    }
+}
+
+#[test]
+fn test_to_shell_string_normalizes_spacing() {
+    let parsed = parse_shell_command("a&&b|c;(d)&&e>f").unwrap();
+    assert_eq!(parsed.to_shell_string(), "a && b | c; (d) && e > f");
+}
+
+#[test]
+fn test_to_shell_string_after_rewrite() {
+    let mut parsed = parse_shell_command("ls -l").unwrap();
+    if let ParsedCommand::Simple { args, .. } = &mut parsed {
+        args.push(ParsedArg::literal("--color=never"));
+        args.push(ParsedArg::literal("my file's"));
+    }
+    assert_eq!(
+        parsed.to_shell_string(),
+        "ls -l --color=never 'my file'\\''s'"
+    );
+}
+
+#[test]
+fn test_parse_assignments_before_a_command() {
+    let parsed = parse_shell_command("RUST_LOG=debug NAME=\"a b\" mycmd X=1").unwrap();
+    let ParsedCommand::Simple {
+        assignments,
+        cmd,
+        args,
+        ..
+    } = &parsed
+    else {
+        panic!("expected a simple command, got {:?}", parsed);
+    };
+    assert_eq!(cmd, "mycmd");
+    let names: Vec<_> = assignments.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(names, ["RUST_LOG", "NAME"]);
+    assert_eq!(assignments[1].value.unquoted(), "a b");
+    // After the command name, it is an argument
+    assert_eq!(args[0].value, "X=1");
+
+    // Without a command, the assignments are the shell's, and left as is
+    let parsed = parse_shell_command("A=1 B=2").unwrap();
+    let ParsedCommand::Simple {
+        assignments, cmd, ..
+    } = &parsed
+    else {
+        panic!("expected a simple command, got {:?}", parsed);
+    };
+    assert!(assignments.is_empty());
+    assert_eq!(cmd, "A=1");
+    let parsed = parse_shell_command("1A=x cmd --a=b").unwrap();
+    assert!(matches!(&parsed, ParsedCommand::Simple { assignments, .. } if assignments.is_empty()));
+}
@@ -0,0 +1,203 @@
+//! Tests for persistent shell sessions
+
+#![cfg(unix)]
+
+use command_stream::{SessionShell, ShellSession};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_session_runs_commands() {
+    let session = ShellSession::start().await.unwrap();
+
+    let result = session.run("echo hello").await.unwrap();
+    assert!(result.is_success());
+    assert_eq!(result.stdout, "hello\n");
+    assert_eq!(result.stderr, "");
+
+    session.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_session_captures_exit_code_and_stderr() {
+    let session = ShellSession::start().await.unwrap();
+
+    let result = session
+        .run("echo oops >&2; exit_code() { return 3; }; exit_code")
+        .await
+        .unwrap();
+    assert_eq!(result.code, 3);
+    assert_eq!(result.stderr, "oops\n");
+
+    // The session keeps working after a failing command.
+    let result = session.run("true").await.unwrap();
+    assert_eq!(result.code, 0);
+}
+
+#[tokio::test]
+async fn test_session_preserves_cwd_and_env() {
+    let dir = TempDir::new().unwrap();
+    let session = ShellSession::start().await.unwrap();
+
+    session
+        .run(&format!(
+            "cd '{}' && export CS_SESSION_VAR=kept",
+            dir.path().display()
+        ))
+        .await
+        .unwrap();
+
+    let result = session
+        .run("basename \"$(pwd)\"; echo $CS_SESSION_VAR")
+        .await
+        .unwrap();
+    let expected_dir = dir.path().file_name().unwrap().to_string_lossy();
+    assert_eq!(result.stdout, format!("{}\nkept\n", expected_dir));
+}
+
+#[tokio::test]
+async fn test_session_output_without_trailing_newline() {
+    let session = ShellSession::start().await.unwrap();
+
+    let result = session.run("printf 'no newline'").await.unwrap();
+    assert_eq!(result.stdout, "no newline");
+
+    let result = session.run("printf 'two\\n\\n'").await.unwrap();
+    assert_eq!(result.stdout, "two\n\n");
+}
+
+#[tokio::test]
+async fn test_session_command_with_quotes() {
+    let session = ShellSession::start().await.unwrap();
+
+    let result = session.run("echo \"it's\" 'a \"test\"'").await.unwrap();
+    assert_eq!(result.stdout, "it's a \"test\"\n");
+}
+
+#[tokio::test]
+async fn test_session_exit_closes_session() {
+    let session = ShellSession::start_with(SessionShell::Posix("/bin/sh".to_string()), None)
+        .await
+        .unwrap();
+
+    assert!(session.run("exit 0").await.is_err());
+    assert!(!session.is_alive().await);
+    assert!(session.run("echo again").await.is_err());
+}
+
+#[tokio::test]
+async fn test_session_close_runs_exit_trap() {
+    let session = ShellSession::start().await.unwrap();
+
+    session.run("trap 'echo goodbye' EXIT").await.unwrap();
+    let result = session.close().await.unwrap();
+    assert_eq!(result.stdout, "goodbye\n");
+    assert_eq!(result.code, 0);
+}
+
+#[tokio::test]
+async fn test_session_signal_runs_trap() {
+    let session = ShellSession::start().await.unwrap();
+
+    session.run("trap 'echo caught' USR1").await.unwrap();
+    session.signal("SIGUSR1").await.unwrap();
+    let result = session.run("echo next").await.unwrap();
+    assert!(result.stdout.contains("caught\n"));
+    assert!(result.stdout.ends_with("next\n"));
+    assert!(session.is_alive().await);
+}
+
+#[tokio::test]
+async fn test_session_cleanup_hooks_run_in_reverse_order() {
+    let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+    let session = ShellSession::start().await.unwrap();
+    for name in ["first", "second"] {
+        let order = order.clone();
+        session.on_cleanup(move || order.lock().unwrap().push(name));
+    }
+    session.close().await.unwrap();
+    assert_eq!(*order.lock().unwrap(), ["second", "first"]);
+
+    let session = ShellSession::start().await.unwrap();
+    let order_on_drop = order.clone();
+    session.on_cleanup(move || order_on_drop.lock().unwrap().push("dropped"));
+    drop(session);
+    assert_eq!(order.lock().unwrap().last(), Some(&"dropped"));
+}
+
+#[tokio::test]
+async fn test_session_source_keeps_script_state() {
+    let dir = TempDir::new().unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(
+        dir.path().join("env.sh"),
+        "export CS_SOURCED=yes\nCS_LOCAL=local\ngreet() { echo \"hi $1\"; }\ncd sub\n",
+    )
+    .unwrap();
+
+    let session = ShellSession::start_with(SessionShell::detect(), Some(dir.path()))
+        .await
+        .unwrap();
+    let result = session.source("env.sh").await.unwrap();
+    assert!(result.is_success(), "{:?}", result);
+
+    let result = session
+        .run("echo $CS_SOURCED $CS_LOCAL; greet there; basename \"$(pwd)\"")
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "yes local\nhi there\nsub\n");
+}
+
+#[tokio::test]
+async fn test_dropped_run_leaves_no_output_for_the_next() {
+    let session = ShellSession::start().await.unwrap();
+
+    let dropped = tokio::time::timeout(
+        std::time::Duration::from_millis(100),
+        session.run("printf 'sta'; sleep 0.5; echo le; echo err >&2"),
+    )
+    .await;
+    assert!(dropped.is_err());
+
+    let result = session.run("echo fresh").await.unwrap();
+    assert_eq!(result.stdout, "fresh\n");
+    assert_eq!(result.stderr, "");
+    assert!(result.is_success());
+}
+
+#[tokio::test]
+async fn test_session_source_builtin() {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(
+        dir.path().join("env.sh"),
+        "export CS_SOURCED=yes\ngreet() { echo \"hi $1\"; }\n",
+    )
+    .unwrap();
+
+    // `sh` may have no `source` of its own, and `.` ends it on a missing file
+    let session = ShellSession::start_with(SessionShell::Posix("/bin/sh".into()), Some(dir.path()))
+        .await
+        .unwrap();
+    let result = session.run("source env.sh").await.unwrap();
+    assert!(result.is_success(), "{:?}", result);
+    let result = session.run("echo $CS_SOURCED; greet there").await.unwrap();
+    assert_eq!(result.stdout, "yes\nhi there\n");
+
+    let result = session.run(". missing.sh").await.unwrap();
+    assert_eq!(result.code, 1);
+    assert!(result.stderr.contains("No such file or directory"));
+    assert!(session.is_alive().await);
+}
+
+#[tokio::test]
+async fn test_session_source_missing_file() {
+    let session = ShellSession::start_with(SessionShell::Posix("/bin/sh".into()), None)
+        .await
+        .unwrap();
+
+    let result = session.source("/nonexistent/env.sh").await.unwrap();
+    assert_eq!(result.code, 1);
+    assert!(result.stderr.contains("No such file or directory"));
+    assert!(session.is_alive().await);
+}
@@ -0,0 +1,161 @@
+//! Tests for applying shell settings (errexit, pipefail, ...) during execution
+
+use command_stream::{
+    exec, get_shell_settings, set_shell_option, unset_shell_option, Error, RunOptions,
+    ShellSettings,
+};
+use tokio::sync::Mutex;
+
+// Tests that touch the global settings are serialized against each other.
+static GLOBAL_SETTINGS_LOCK: Mutex<()> = Mutex::const_new(());
+
+fn options_with(settings: ShellSettings) -> RunOptions {
+    RunOptions {
+        mirror: false,
+        shell_settings: Some(settings),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_errexit_override_returns_error() {
+    let mut settings = ShellSettings::new();
+    settings.enable("errexit");
+
+    let err = exec("false", options_with(settings)).await.unwrap_err();
+    assert!(matches!(err, Error::CommandFailed { code: 1, .. }));
+}
+
+#[tokio::test]
+async fn test_default_settings_return_failed_result() {
+    let result = exec("false", options_with(ShellSettings::new()))
+        .await
+        .unwrap();
+    assert_eq!(result.code, 1);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_errexit_stops_multi_command_string() {
+    let mut settings = ShellSettings::new();
+    settings.enable("errexit");
+
+    let err = exec("sh -c 'exit 4'; echo unreachable", options_with(settings))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::CommandFailed { code: 4, .. }));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_nounset_applies_to_shell_commands() {
+    let mut settings = ShellSettings::new();
+    settings.enable("nounset");
+
+    let result = exec(
+        "printf %s \"$COMMAND_STREAM_SURELY_UNSET_VAR\"",
+        options_with(settings),
+    )
+    .await
+    .unwrap();
+    assert_ne!(result.code, 0);
+}
+
+#[tokio::test]
+async fn test_global_errexit_is_the_default() {
+    let _guard = GLOBAL_SETTINGS_LOCK.lock().await;
+    set_shell_option("errexit").await;
+    assert!(get_shell_settings().await.errexit);
+
+    let global = exec(
+        "false",
+        RunOptions {
+            mirror: false,
+            ..Default::default()
+        },
+    )
+    .await;
+    // A per-run override wins over the global setting.
+    let scoped = exec("false", options_with(ShellSettings::new())).await;
+
+    unset_shell_option("errexit").await;
+
+    assert!(matches!(global, Err(Error::CommandFailed { code: 1, .. })));
+    assert_eq!(scoped.unwrap().code, 1);
+}
+
+#[tokio::test]
+async fn test_set_builtin_leaves_global_settings_alone() {
+    let _guard = GLOBAL_SETTINGS_LOCK.lock().await;
+
+    exec("set -eu -o pipefail", RunOptions::default().quiet())
+        .await
+        .unwrap();
+    exec("shopt -s globstar", RunOptions::default().quiet())
+        .await
+        .unwrap();
+    let settings = get_shell_settings().await;
+    let result = exec("false", RunOptions::default().quiet()).await;
+
+    assert!(!settings.errexit && !settings.nounset && !settings.pipefail);
+    assert!(!settings.globstar);
+    assert_eq!(result.unwrap().code, 1);
+}
+
+#[tokio::test]
+async fn test_set_applies_to_the_rest_of_the_list() {
+    let outcome = exec(
+        "set -e; false; echo notreached",
+        options_with(ShellSettings::new()),
+    )
+    .await;
+    assert!(
+        matches!(outcome, Err(Error::CommandFailed { code: 1, .. })),
+        "{:?}",
+        outcome
+    );
+
+    let outcome = exec(
+        "set -u; echo $COMMAND_STREAM_SURELY_UNSET_VAR",
+        options_with(ShellSettings::new()),
+    )
+    .await;
+    assert!(
+        matches!(outcome, Err(Error::UnsetVariable { .. })),
+        "{:?}",
+        outcome
+    );
+
+    let result = exec(
+        "set -e; set +e; false; echo reached",
+        options_with(ShellSettings::new()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.stdout, "reached\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_errexit_error_carries_command_and_stderr() {
+    let mut settings = ShellSettings::new();
+    settings.enable("errexit");
+
+    let command = "sh -c 'echo first >&2; echo last >&2; exit 3'";
+    let err = exec(command, options_with(settings)).await.unwrap_err();
+    assert_eq!(err.command(), Some(command));
+    assert_eq!(err.exit_code(), Some(3));
+    assert_eq!(err.stderr_tail(), Some("first\nlast\n"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_errexit_reports_killed_by_signal() {
+    let mut settings = ShellSettings::new();
+    settings.enable("errexit");
+
+    let err = exec("sh -c 'kill -9 $$'", options_with(settings))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::KilledBySignal { signal: 9, .. }));
+}
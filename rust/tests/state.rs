@@ -205,3 +205,64 @@ fn test_global_state_default() {
     assert!(state.are_virtual_commands_enabled());
     assert!(!state.are_signal_handlers_installed());
 }
+
+// ============================================================================
+// Child Cleanup Tests
+// ============================================================================
+
+/// Whether `pid` is still a live (non-zombie) process
+#[cfg(target_os = "linux")]
+fn is_running(pid: i32) -> bool {
+    match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => !stat
+            .rsplit(')')
+            .next()
+            .is_some_and(|rest| rest.trim_start().starts_with('Z')),
+        Err(_) => false,
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_running_commands_leaves_signal_handling_to_the_host() {
+    let result = command_stream::exec("sh -c true", command_stream::RunOptions::default())
+        .await
+        .unwrap();
+    assert!(result.is_success());
+    assert!(!command_stream::global_state().are_signal_handlers_installed());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_children_are_terminated_when_host_gets_sigterm() {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+
+    let mut host = Command::new(env!("CARGO_BIN_EXE_command-stream"))
+        .arg("sh -c 'echo $$; exec sleep 30'")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut line = String::new();
+    BufReader::new(host.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let child_pid: i32 = line.trim().parse().unwrap();
+    assert!(is_running(child_pid));
+
+    nix::sys::signal::kill(
+        nix::unistd::Pid::from_raw(host.id() as i32),
+        nix::sys::signal::Signal::SIGTERM,
+    )
+    .unwrap();
+    // Either the signal handler exits with 143 or the host sees its command
+    // fail first; in both cases it must not succeed.
+    assert!(!host.wait().unwrap().success());
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while is_running(child_pid) && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    assert!(!is_running(child_pid));
+}
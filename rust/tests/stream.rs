@@ -1,6 +1,6 @@
 //! Tests for the streaming module
 
-use command_stream::{AsyncIterator, OutputChunk, StreamingRunner};
+use command_stream::{OutputChunk, RunOptions, StreamingRunner};
 
 #[tokio::test]
 async fn test_streaming_runner_basic() {
@@ -257,3 +257,84 @@ async fn test_streaming_runner_env() {
     assert!(result.is_success());
     assert!(result.stdout.contains("test_value"));
 }
+
+/// Output larger than the read buffer arrives intact across many chunks, and
+/// cloning a chunk shares its buffer instead of copying it.
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stream_large_output_chunks_are_shared() {
+    let runner = StreamingRunner::new("yes 0123456789 | head -n 5000");
+    let mut stream = runner.stream();
+
+    let mut total = 0;
+    while let Some(chunk) = stream.next().await {
+        if let OutputChunk::Stdout(data) = chunk {
+            let copy = data.clone();
+            assert_eq!(copy.as_ptr(), data.as_ptr());
+            total += data.len();
+        }
+    }
+
+    assert_eq!(total, 5000 * "0123456789\n".len());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stream_mirror_to_sinks() {
+    use tokio::io::AsyncReadExt;
+
+    let (stdout_sink, mut stdout_mirror) = tokio::io::duplex(64 * 1024);
+    let (stderr_sink, mut stderr_mirror) = tokio::io::duplex(64 * 1024);
+
+    let result = StreamingRunner::new("echo out; echo err >&2")
+        .mirror_to(stdout_sink, stderr_sink)
+        .collect()
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "out\n");
+    assert_eq!(result.stderr, "err\n");
+
+    let mut mirrored = String::new();
+    stdout_mirror.read_to_string(&mut mirrored).await.unwrap();
+    assert_eq!(mirrored, "out\n");
+    mirrored.clear();
+    stderr_mirror.read_to_string(&mut mirrored).await.unwrap();
+    assert_eq!(mirrored, "err\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_streaming_runner_with_options() {
+    let options = RunOptions::builder()
+        .mirror(false)
+        .env("STREAM_VAR", "from_options")
+        .build();
+    let result = StreamingRunner::new("sh -c 'echo $STREAM_VAR $EXTRA'")
+        .with_options(&options)
+        .with_env("EXTRA", "more")
+        .collect()
+        .await
+        .unwrap();
+
+    assert_eq!(result.stdout, "from_options more\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_line_buffered_output_arrives_per_line() {
+    if which::which("stdbuf").is_err() {
+        return;
+    }
+    // sed block-buffers when writing to a pipe, so without line buffering
+    // both lines would arrive together when it exits
+    let mut stream = StreamingRunner::new("(echo first; sleep 1; echo second) | sed s/^/x/")
+        .line_buffered(true)
+        .stream();
+    let mut chunks = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        if let OutputChunk::Stdout(data) = chunk {
+            chunks.push(String::from_utf8_lossy(&data).into_owned());
+        }
+    }
+    assert_eq!(chunks, ["xfirst\n", "xsecond\n"]);
+}
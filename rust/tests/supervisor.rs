@@ -0,0 +1,159 @@
+//! Integration tests for the process supervisor
+
+use command_stream::supervisor::{
+    Backoff, ProcessSpec, RestartPolicy, Supervised, SupervisorEvent,
+};
+use command_stream::{Error, RunOptions};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::Receiver;
+
+fn spec(command: &str) -> ProcessSpec {
+    ProcessSpec::new(command).options(RunOptions::default().quiet())
+}
+
+/// Wait for the next event matching `wanted`, returning the ones before it
+async fn until(
+    events: &mut Receiver<SupervisorEvent>,
+    wanted: impl Fn(&SupervisorEvent) -> bool,
+) -> Vec<SupervisorEvent> {
+    let mut seen = Vec::new();
+    loop {
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("event in time")
+            .unwrap();
+        if wanted(&event) {
+            return seen;
+        }
+        seen.push(event);
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_failing_process_is_restarted_up_to_max() {
+    let policy = RestartPolicy::OnFailure {
+        max: 2,
+        backoff: Backoff::fixed(Duration::from_millis(10)),
+    };
+    let supervised = Supervised::spawn(spec("sh -c 'exit 3'"), policy);
+    let mut events = supervised.subscribe();
+
+    let seen = until(&mut events, |event| {
+        matches!(event, SupervisorEvent::Finished { restarts: 2 })
+    })
+    .await;
+    assert_eq!(
+        seen,
+        vec![
+            SupervisorEvent::Started { attempt: 0 },
+            SupervisorEvent::Exited {
+                attempt: 0,
+                code: 3
+            },
+            SupervisorEvent::Restarting {
+                attempt: 1,
+                delay: Duration::from_millis(10)
+            },
+            SupervisorEvent::Started { attempt: 1 },
+            SupervisorEvent::Exited {
+                attempt: 1,
+                code: 3
+            },
+            SupervisorEvent::Restarting {
+                attempt: 2,
+                delay: Duration::from_millis(10)
+            },
+            SupervisorEvent::Started { attempt: 2 },
+            SupervisorEvent::Exited {
+                attempt: 2,
+                code: 3
+            },
+        ]
+    );
+    assert_eq!(supervised.restarts(), 2);
+    assert_eq!(supervised.wait().await.unwrap().code, 3);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_success_is_not_restarted_on_failure_policy() {
+    let policy = RestartPolicy::OnFailure {
+        max: 5,
+        backoff: Backoff::none(),
+    };
+    let supervised = Supervised::spawn(spec("echo ok"), policy);
+    let result = supervised.wait().await.unwrap();
+    assert_eq!(result.stdout, "ok\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stop_and_restart() {
+    let policy = RestartPolicy::Always {
+        max: 10,
+        backoff: Backoff::none(),
+    };
+    let supervised = Supervised::spawn(spec("/bin/sleep 10"), policy);
+    let mut events = supervised.subscribe();
+    let started = Instant::now();
+
+    until(&mut events, |event| {
+        *event == SupervisorEvent::Started { attempt: 0 }
+    })
+    .await;
+    supervised.restart();
+    until(&mut events, |event| {
+        *event == SupervisorEvent::Started { attempt: 1 }
+    })
+    .await;
+    assert_eq!(supervised.restarts(), 0);
+
+    supervised.stop();
+    until(&mut events, |event| *event == SupervisorEvent::Stopped).await;
+    let outcome = supervised.wait().await;
+    assert!(matches!(outcome, Err(Error::Cancelled)), "{:?}", outcome);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_wait_for_supervised_output() {
+    use command_stream::wait::wait_for_output;
+    use command_stream::StreamEmitter;
+    use std::sync::Arc;
+
+    let output = Arc::new(StreamEmitter::new());
+    let spec =
+        spec("sh -c 'sleep 0.1; echo listening on port 8080; sleep 10'").emitter(output.clone());
+    let waiting = wait_for_output(&output, r"listening on port \d+", Duration::from_secs(5));
+    let supervised = Supervised::spawn(spec, RestartPolicy::Never);
+
+    assert_eq!(waiting.await.unwrap(), "listening on port 8080");
+    supervised.stop();
+    assert!(supervised.wait().await.is_err());
+}
+
+#[cfg(all(unix, feature = "http"))]
+#[tokio::test]
+async fn test_wait_for_http_ok_reports_a_dead_service() {
+    let free_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let supervised = Supervised::spawn(
+        spec("sh -c 'sleep 0.2; echo bind failed >&2; exit 4'"),
+        RestartPolicy::Never,
+    );
+
+    let url = format!("http://127.0.0.1:{}/", free_port);
+    let err = supervised
+        .wait_for_http_ok(&url, Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("ended before"), "{}", message);
+    assert!(message.contains("exited with code 4"), "{}", message);
+    assert_eq!(supervised.wait().await.unwrap().code, 4);
+}
@@ -0,0 +1,70 @@
+//! Tests for the `expect` assertions and snapshot comparison
+
+use command_stream::testing::{expect, Normalizer};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_expect_passes_and_returns_result() {
+    let result = expect("echo ok")
+        .code(0)
+        .success()
+        .stdout_contains("ok")
+        .stdout_eq("ok\n")
+        .stderr_empty()
+        .await;
+    assert_eq!(result.stdout, "ok\n");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_expect_reports_every_failed_check() {
+    let report = expect("sh -c 'echo out; echo err >&2; exit 3'")
+        .code(0)
+        .stdout_contains("out")
+        .stderr_empty()
+        .verify()
+        .await
+        .unwrap_err();
+    assert!(report.contains("failed 2 check(s)"), "{}", report);
+    assert!(report.contains("expected exit code 0, got 3"));
+    assert!(report.contains("expected empty stderr"));
+    assert!(report.contains("--- stderr ---\nerr\n"));
+}
+
+#[tokio::test]
+#[should_panic(expected = "expected failure")]
+async fn test_expect_panics_when_awaited() {
+    expect("echo fine").failure().await;
+}
+
+#[test]
+fn test_normalizer_strips_ansi_and_redacts() {
+    let normalizer = Normalizer::new()
+        .redact("/tmp/build-123", "[TMP]")
+        .redact("/tmp/build-123/out", "[OUT]");
+    assert_eq!(
+        normalizer.normalize("\x1b[32mok\x1b[0m /tmp/build-123/out/a /tmp/build-123/b\r\n"),
+        "ok [OUT]/a [TMP]/b\n"
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stdout_snapshot_is_written_then_compared() {
+    let dir = TempDir::new().unwrap();
+    let snapshot = dir.path().join("snapshots").join("echo.txt");
+    let path = dir.path().display().to_string();
+
+    expect(format!("echo {}", path))
+        .redact(&path, "[DIR]")
+        .stdout_snapshot(&snapshot)
+        .await;
+    assert_eq!(std::fs::read_to_string(&snapshot).unwrap(), "[DIR]\n");
+
+    let report = expect("echo changed")
+        .stdout_snapshot(&snapshot)
+        .verify()
+        .await
+        .unwrap_err();
+    assert!(report.contains("differs from snapshot"), "{}", report);
+}
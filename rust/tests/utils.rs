@@ -2,7 +2,7 @@
 //!
 //! These tests mirror the JavaScript utility tests
 
-use command_stream::utils::{quote, AnsiConfig, AnsiUtils, CommandResult, VirtualUtils};
+use command_stream::utils::{quote, AnsiConfig, AnsiUtils, CommandResult, ExitKind, VirtualUtils};
 use std::path::PathBuf;
 
 // ============================================================================
@@ -55,6 +55,82 @@ fn test_command_result_exit_code_alias() {
     assert_eq!(failure.exit_code(), failure.code);
 }
 
+#[cfg(unix)]
+#[test]
+fn test_command_result_from_exit_status() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    // Raw wait statuses: exit code in the high byte, signal in the low bits,
+    // 0x80 for a core dump.
+    let exited = CommandResult::from_exit_status("out", "", ExitStatus::from_raw(3 << 8));
+    assert_eq!(exited.code, 3);
+    assert_eq!(exited.signal, None);
+
+    let killed = CommandResult::from_exit_status("", "", ExitStatus::from_raw(9));
+    assert_eq!(killed.code, 137);
+    assert_eq!(killed.signal, Some(9));
+    assert!(!killed.core_dumped);
+
+    let dumped = CommandResult::from_exit_status("", "", ExitStatus::from_raw(6 | 0x80));
+    assert_eq!(dumped.code, 134);
+    assert!(dumped.core_dumped);
+}
+
+#[test]
+fn test_command_result_exit_kind() {
+    let kind = |code| CommandResult::error_with_code("", code).exit_kind();
+    assert_eq!(
+        CommandResult::success_empty().exit_kind(),
+        ExitKind::Success
+    );
+    assert_eq!(kind(1), ExitKind::Failure(1));
+    assert_eq!(kind(124), ExitKind::Timeout);
+    assert_eq!(kind(126), ExitKind::NotExecutable);
+    assert_eq!(kind(127), ExitKind::NotFound);
+    assert_eq!(kind(130), ExitKind::SignalTerminated(2));
+    assert_eq!(kind(255), ExitKind::Failure(255));
+    assert!(!kind(130).is_success());
+    assert_eq!(kind(126).to_string(), "command not executable");
+
+    let killed = CommandResult {
+        code: 137,
+        signal: Some(9),
+        ..Default::default()
+    };
+    assert_eq!(killed.exit_kind(), ExitKind::SignalTerminated(9));
+}
+
+#[test]
+fn test_command_result_lines() {
+    let result = CommandResult::success("\nbuilding...\r\nabc123\n\n");
+    assert_eq!(result.first_line(), Some("building..."));
+    assert_eq!(result.last_line(), Some("abc123"));
+    assert_eq!(CommandResult::success("").last_line(), None);
+    assert_eq!(CommandResult::success(" \n").first_line(), None);
+}
+
+#[test]
+fn test_command_result_column() {
+    let result = CommandResult::success("PID TTY  CMD\n 1 ?  init\n42 pts/0 bash\n\n");
+    assert_eq!(result.column(0), vec!["PID", "1", "42"]);
+    assert_eq!(result.column(2), vec!["CMD", "init", "bash"]);
+    assert!(result.column(3).is_empty());
+}
+
+#[test]
+fn test_command_result_key_values() {
+    let result = CommandResult::success(
+        "# os-release\nNAME=\"Ubuntu\"\nVERSION_ID='24.04'\nEMPTY=\nURL=https://x.org/?a=b\nnoise\n",
+    );
+    let values = result.key_values();
+    assert_eq!(values.len(), 4);
+    assert_eq!(values["NAME"], "Ubuntu");
+    assert_eq!(values["VERSION_ID"], "24.04");
+    assert_eq!(values["EMPTY"], "");
+    assert_eq!(values["URL"], "https://x.org/?a=b");
+}
+
 // ============================================================================
 // VirtualUtils Tests
 // ============================================================================
@@ -297,3 +373,31 @@ fn test_quote_newline() {
     assert!(result.contains("hello"));
     assert!(result.contains("world"));
 }
+
+// ============================================================================
+// Output Parser Tests
+// ============================================================================
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_parse_ls_output_of_real_command() {
+    use command_stream::parsers::LsEntries;
+    use command_stream::RunOptions;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    std::fs::create_dir(dir.path().join("sub dir")).unwrap();
+    std::fs::write(dir.path().join("file.txt"), "12345").unwrap();
+
+    let options = RunOptions::builder()
+        .mirror(false)
+        .cwd(dir.path())
+        .stable_locale()
+        .build();
+    let result = command_stream::exec("ls -l", options).await.unwrap();
+    let entries: LsEntries = result.parse().unwrap();
+
+    let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["file.txt", "sub dir"]);
+    assert_eq!(entries[0].size, 5);
+    assert!(entries[1].is_dir());
+}
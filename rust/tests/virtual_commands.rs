@@ -4,9 +4,12 @@
 
 use command_stream::commands::{
     are_virtual_commands_enabled, disable_virtual_commands, enable_virtual_commands,
-    CommandContext, VirtualCommandRegistry,
+    execute_builtin, CommandContext, VirtualCommandRegistry,
 };
-use command_stream::{run, ProcessRunner, RunOptions};
+use command_stream::{
+    run, EventData, EventType, ProcessRunner, RunOptions, StreamChunk, StreamEmitter,
+};
+use std::sync::Arc;
 use tokio::sync::{Mutex, MutexGuard};
 
 static VIRTUAL_COMMANDS_TEST_LOCK: Mutex<()> = Mutex::const_new(());
@@ -86,6 +89,9 @@ fn test_command_context_with_cwd() {
         env: None,
         output_tx: None,
         is_cancelled: None,
+        cancel_token: None,
+        shell_settings: None,
+        executor: None,
     };
     assert_eq!(ctx.get_cwd(), std::path::PathBuf::from("/tmp"));
 }
@@ -105,6 +111,9 @@ fn test_command_context_is_cancelled_with_fn() {
         env: None,
         output_tx: None,
         is_cancelled: Some(Box::new(|| true)),
+        cancel_token: None,
+        shell_settings: None,
+        executor: None,
     };
     assert!(ctx.is_cancelled());
 }
@@ -217,3 +226,134 @@ async fn test_process_runner_virtual_pwd() {
     assert!(result.is_success());
     assert!(!result.stdout.is_empty());
 }
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_compound_command_is_not_sent_to_builtin() {
+    let _guard = lock_virtual_commands().await;
+    enable_virtual_commands();
+    let result = run("echo first && echo second").await.unwrap();
+    assert!(result.is_success());
+    assert_eq!(result.stdout, "first\nsecond\n");
+}
+
+// ============================================================================
+// Virtual Stderr Streaming Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_execute_builtin_streams_result_stderr() {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+    let mut ctx = CommandContext::new(vec!["/nonexistent/file/12345".to_string()]);
+    ctx.output_tx = Some(tx);
+
+    let result = execute_builtin("cat", ctx).await.unwrap();
+    assert_eq!(result.code, 1);
+    assert!(result.stderr.is_empty());
+
+    match rx.recv().await {
+        Some(StreamChunk::Stderr(text)) => assert!(text.contains("No such file or directory")),
+        other => panic!("expected a stderr chunk, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_process_runner_emits_virtual_stderr() {
+    let _guard = lock_virtual_commands().await;
+    enable_virtual_commands();
+
+    let emitter = Arc::new(StreamEmitter::new());
+    let seen = Arc::new(std::sync::Mutex::new(String::new()));
+    let seen_clone = seen.clone();
+    emitter
+        .on(EventType::Stderr, move |data| {
+            if let EventData::String(text) = data {
+                seen_clone.lock().unwrap().push_str(&text);
+            }
+        })
+        .await;
+
+    let options = RunOptions {
+        mirror: false,
+        ..Default::default()
+    };
+    let mut runner =
+        ProcessRunner::new("cat /nonexistent/file/12345", options).with_emitter(emitter);
+    let result = runner.run().await.unwrap();
+
+    assert_eq!(result.code, 1);
+    assert!(result.stderr.contains("No such file or directory"));
+    assert_eq!(*seen.lock().unwrap(), result.stderr);
+}
+
+// ============================================================================
+// End of Options Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_double_dash_allows_dash_file_names() {
+    let _guard = lock_virtual_commands().await;
+    enable_virtual_commands();
+    let dir = tempfile::TempDir::new().unwrap();
+    let options = || RunOptions {
+        mirror: false,
+        cwd: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    };
+
+    let result = command_stream::exec("touch -- --flag-looking-name -weirdfile", options())
+        .await
+        .unwrap();
+    assert!(result.is_success(), "{}", result.stderr);
+    assert!(dir.path().join("--flag-looking-name").exists());
+    assert!(dir.path().join("-weirdfile").exists());
+
+    let result = command_stream::exec("rm -- -weirdfile", options())
+        .await
+        .unwrap();
+    assert!(result.is_success(), "{}", result.stderr);
+    assert!(!dir.path().join("-weirdfile").exists());
+    assert!(dir.path().join("--flag-looking-name").exists());
+}
+
+#[tokio::test]
+async fn test_basename_after_double_dash() {
+    let _guard = lock_virtual_commands().await;
+    enable_virtual_commands();
+    let options = RunOptions {
+        mirror: false,
+        ..Default::default()
+    };
+    let result = command_stream::exec("basename -- -dir/-name", options)
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "-name\n");
+}
+
+// ============================================================================
+// Quoted Argument Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_virtual_commands_receive_unquoted_arguments() {
+    let _guard = lock_virtual_commands().await;
+    enable_virtual_commands();
+    let dir = tempfile::TempDir::new().unwrap();
+    std::fs::write(dir.path().join("my file.txt"), "spaced\n").unwrap();
+    let options = || RunOptions {
+        mirror: false,
+        cwd: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    };
+
+    let result = command_stream::exec(r#"echo "hello   world" a\ b --name="x y""#, options())
+        .await
+        .unwrap();
+    assert_eq!(result.stdout, "hello   world a b --name=x y\n");
+
+    let result = command_stream::exec(r#"cat "my file.txt""#, options())
+        .await
+        .unwrap();
+    assert!(result.is_success(), "{}", result.stderr);
+    assert_eq!(result.stdout, "spaced\n");
+}
@@ -0,0 +1,80 @@
+//! Tests for configurable `set -x` output
+
+use command_stream::xtrace::{set_xtrace_config, XtraceConfig};
+use command_stream::{exec, Parameters, RunOptions, ShellSettings};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// A writer whose output the test can read
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Buffer {
+    fn take(&self) -> String {
+        String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap()
+    }
+}
+
+fn traced() -> RunOptions {
+    RunOptions {
+        mirror: false,
+        shell_settings: Some(ShellSettings {
+            xtrace: true,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+// A single test, since the xtrace configuration is process-wide state.
+#[tokio::test]
+async fn test_xtrace_prefix_and_destination() {
+    let buffer = Buffer::default();
+    set_xtrace_config(Some(XtraceConfig::new().writer(buffer.clone()).into()));
+    let result = exec("echo hi", traced()).await.unwrap();
+    assert_eq!(result.stderr, "");
+    assert_eq!(buffer.take(), "+ echo hi\n");
+
+    // The command's PS4, expanded
+    let env = [("PS4", "[$STAGE:$?] "), ("STAGE", "build")]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+    let options = RunOptions {
+        env: Some(env.into()),
+        parameters: Parameters {
+            status: 2,
+            ..Parameters::default()
+        },
+        ..traced()
+    };
+    exec("echo hi", options).await.unwrap();
+    assert_eq!(buffer.take(), "[build:2] echo hi\n");
+
+    let config = XtraceConfig::new()
+        .prefix("$(date) ")
+        .writer(buffer.clone())
+        .timestamps(true);
+    set_xtrace_config(Some(config.into()));
+    exec("echo hi", traced()).await.unwrap();
+    let line = buffer.take();
+    let (time, rest) = line.split_once(' ').unwrap();
+    assert!(time.ends_with('Z') && time.contains('T'), "{}", line);
+    assert_eq!(rest, "$(date) echo hi\n");
+
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("trace.log");
+    set_xtrace_config(Some(XtraceConfig::new().file(&log).unwrap().into()));
+    exec("echo hi", traced()).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "+ echo hi\n");
+    set_xtrace_config(None);
+}